@@ -0,0 +1,674 @@
+//! # Aggregate queries
+//!
+//!  The rows of an aggregate query are split into groups, and each group is
+//! folded into a single row by the aggregate functions of the query. Rows
+//! reach the aggregation sorted by their group, so that each group is done
+//! with before the next one starts, whatever the number of groups.
+//!
+//! *Reference:* https://www.sqlite.org/lang_aggfunc.html
+
+use super::expr::Expr;
+use super::json::JsonGroup;
+use super::operator::Operator;
+use super::query_plan::QueryPlan;
+use super::sorter::{compare_rows, SortKey};
+use super::user_function::{UserFunction, UserState};
+use super::value::{is_true, KeySet};
+use super::vdbe::{Builder, Codegen, Consumer, Opcode, Source, P4};
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  compare_values, parse_number, to_real, to_text, Collation, Value,
+};
+use core::cmp::Ordering;
+use core::slice;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AggregateFunction {
+  Count,
+  Sum,
+  Total,
+  Avg,
+  Min,
+  Max,
+  /// `group_concat()`, also named `string_agg()`.
+  GroupConcat,
+  /// `json_group_array()` or, with `object`, `json_group_object()`, and
+  /// whether it returns JSONB.
+  JsonGroup {
+    object: bool,
+    binary: bool,
+  },
+  /// An aggregate or window function registered on the connection.
+  User(UserFunction),
+}
+
+impl AggregateFunction {
+  /// The aggregate function named `name`, and the numbers of arguments it
+  /// takes.
+  pub(crate) fn from_name(name: &str) -> Option<(Self, &'static [usize])> {
+    let name = name.to_ascii_lowercase();
+    let binary = name.starts_with("jsonb");
+    Some(match name.as_str() {
+      "count" => (Self::Count, &[0, 1]),
+      "sum" => (Self::Sum, &[1]),
+      "total" => (Self::Total, &[1]),
+      "avg" => (Self::Avg, &[1]),
+      "min" => (Self::Min, &[1]),
+      "max" => (Self::Max, &[1]),
+      "group_concat" => (Self::GroupConcat, &[1, 2]),
+      "string_agg" => (Self::GroupConcat, &[2]),
+      "json_group_array" | "jsonb_group_array" => (
+        Self::JsonGroup {
+          object: false,
+          binary,
+        },
+        &[1],
+      ),
+      "json_group_object" | "jsonb_group_object" => (
+        Self::JsonGroup {
+          object: true,
+          binary,
+        },
+        &[2],
+      ),
+      _ => return None,
+    })
+  }
+
+  pub(crate) fn name(&self) -> &str {
+    match self {
+      Self::Count => "count",
+      Self::Sum => "sum",
+      Self::Total => "total",
+      Self::Avg => "avg",
+      Self::Min => "min",
+      Self::Max => "max",
+      Self::GroupConcat => "group_concat",
+      Self::JsonGroup { object, binary } => match (object, binary) {
+        (false, false) => "json_group_array",
+        (false, true) => "jsonb_group_array",
+        (true, false) => "json_group_object",
+        (true, true) => "jsonb_group_object",
+      },
+      Self::User(function) => function.name(),
+    }
+  }
+}
+
+/// A call to an aggregate function.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AggregateCall {
+  pub(crate) function: AggregateFunction,
+  pub(crate) arguments: Vec<Expr>,
+  /// Collating sequence of the first argument, used by `min()` and `max()`
+  /// and to tell values apart with `DISTINCT`.
+  pub(crate) collation: Collation,
+  pub(crate) distinct: bool,
+  /// Rows for which the condition of the `FILTER` clause is not true are
+  /// left out.
+  pub(crate) filter: Option<Expr>,
+  /// The `ORDER BY` within the arguments: the values of each row sort by
+  /// the keys, evaluated after the arguments.
+  pub(crate) order_by: Vec<Expr>,
+  pub(crate) order: Vec<SortKey>,
+}
+
+impl AggregateCall {
+  /// Calls `f` with each expression of the call.
+  pub(crate) fn visit_exprs(&self, f: &mut impl FnMut(&Expr)) {
+    let exprs = self.arguments.iter().chain(&self.filter);
+    exprs.chain(&self.order_by).for_each(f);
+  }
+}
+
+/// The state of an aggregate function over the rows of a group, or of the
+/// frame of a window.
+#[derive(Debug)]
+pub(crate) struct Accumulator {
+  state: State,
+  /// Values already seen, with `DISTINCT`.
+  seen: KeySet,
+  /// Values held back until the end of the group, with `ORDER BY`.
+  ordered: Vec<Vec<Value>>,
+}
+
+#[derive(Debug)]
+enum State {
+  Count(i64),
+  Sum(Sum),
+  Best(Option<Value>),
+  Concat(Option<String>),
+  Json(JsonGroup),
+  User(UserState),
+}
+
+/// The sum of the values of `sum()`, `total()` and `avg()`. Integers add up
+/// exactly until a value that is not an integer comes along, after which
+/// the sum is a floating point value with Kahan-Babuska-Neumaier
+/// compensation.
+#[derive(Debug, Default)]
+struct Sum {
+  count: i64,
+  int: i64,
+  real: f64,
+  error: f64,
+  approximate: bool,
+  overflow: bool,
+}
+
+impl Sum {
+  /// Integers beyond 2^52 are added in two parts, so that no precision is
+  /// lost converting them.
+  const EXACT: i64 = 1 << 52;
+
+  fn add(&mut self, value: &Value) {
+    let value = match value {
+      Value::Text(text) => match parse_number(text) {
+        Some((number, true)) => number,
+        _ => Value::Real(to_real(value)),
+      },
+      Value::Blob(_) => Value::Real(to_real(value)),
+      value => value.clone(),
+    };
+    self.count += 1;
+    match value {
+      Value::Integer(int) if !self.approximate => {
+        match self.int.checked_add(int) {
+          Some(sum) => self.int = sum,
+          None => {
+            self.overflow = true;
+            self.approximate = true;
+            self.start_real();
+            self.add_int(int);
+          }
+        }
+      }
+      Value::Integer(int) => self.add_int(int),
+      value => {
+        if !self.approximate {
+          self.approximate = true;
+          self.start_real();
+        }
+        self.add_real(to_real(&value));
+      }
+    }
+  }
+
+  /// Carries the integer sum over to the floating point one.
+  fn start_real(&mut self) {
+    if !(-Self::EXACT..=Self::EXACT).contains(&self.int) {
+      let small = self.int % 16384;
+      self.real = (self.int - small) as f64;
+      self.error = small as f64;
+    } else {
+      self.real = self.int as f64;
+      self.error = 0.0;
+    }
+  }
+
+  fn add_int(&mut self, int: i64) {
+    if !(-Self::EXACT..=Self::EXACT).contains(&int) {
+      let small = int % 16384;
+      self.add_real((int - small) as f64);
+      self.add_real(small as f64);
+    } else {
+      self.add_real(int as f64);
+    }
+  }
+
+  fn add_real(&mut self, real: f64) {
+    let sum = self.real + real;
+    if self.real.abs() > real.abs() {
+      self.error += (self.real - sum) + real;
+    } else {
+      self.error += (real - sum) + self.real;
+    }
+    self.real = sum;
+  }
+
+  fn real_sum(&self) -> f64 {
+    match self.approximate {
+      true if (self.error * 1.0).is_finite() => self.real + self.error,
+      true => self.real,
+      false => self.int as f64,
+    }
+  }
+}
+
+impl Accumulator {
+  pub(crate) fn new(call: &AggregateCall) -> Self {
+    let state = match &call.function {
+      AggregateFunction::Count => State::Count(0),
+      AggregateFunction::Sum
+      | AggregateFunction::Total
+      | AggregateFunction::Avg => State::Sum(Sum::default()),
+      AggregateFunction::Min | AggregateFunction::Max => State::Best(None),
+      AggregateFunction::GroupConcat => State::Concat(None),
+      AggregateFunction::JsonGroup { object, .. } => {
+        State::Json(JsonGroup::new(*object))
+      }
+      AggregateFunction::User(function) => State::User(function.start()),
+    };
+    Self {
+      state,
+      seen: KeySet::new(slice::from_ref(&call.collation)),
+      ordered: vec![],
+    }
+  }
+
+  /// Feeds the row of `row` to the aggregate. Returns whether the row is
+  /// the new minimum or maximum, for `min()` and `max()`.
+  pub(crate) fn step(
+    &mut self,
+    call: &AggregateCall,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<bool> {
+    if let Some(filter) = &call.filter {
+      if is_true(&filter.eval(ctx, row)?) != Some(true) {
+        return Ok(false);
+      }
+    }
+    let mut values = call
+      .arguments
+      .iter()
+      .map(|expr| expr.eval(ctx, row))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    if call.distinct && !self.seen.insert(&values, ctx.sort_memory_limit())? {
+      return Ok(false);
+    }
+    if !call.order.is_empty() {
+      for expr in call.order_by.iter() {
+        values.push(expr.eval(ctx, row)?);
+      }
+      self.ordered.push(values);
+      return Ok(false);
+    }
+    self.state.step(call, &values)
+  }
+
+  /// Takes the row of `row`, fed to the aggregate earlier, back out of it.
+  /// Only window functions registered on the connection can.
+  pub(crate) fn inverse(
+    &mut self,
+    call: &AggregateCall,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    if let Some(filter) = &call.filter {
+      if is_true(&filter.eval(ctx, row)?) != Some(true) {
+        return Ok(());
+      }
+    }
+    let values = call
+      .arguments
+      .iter()
+      .map(|expr| expr.eval(ctx, row))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    match (&call.function, &mut self.state) {
+      (AggregateFunction::User(function), State::User(state)) => {
+        function.inverse(state, &values)
+      }
+      _ => Err(SqliteError::Custom(
+        "Aggregate cannot take rows back out".into(),
+      )),
+    }
+  }
+
+  pub(crate) fn finish(mut self, call: &AggregateCall) -> SqliteResult<Value> {
+    let keys = &call.order;
+    self
+      .ordered
+      .sort_by(|left, right| compare_rows(keys, left, right));
+    for values in self.ordered.iter() {
+      self.state.step(call, &values[..call.arguments.len()])?;
+    }
+    match (&call.function, self.state) {
+      (AggregateFunction::User(function), State::User(state)) => {
+        function.finalize(state)
+      }
+      (_, state) => {
+        self.state = state;
+        self.value(call)
+      }
+    }
+  }
+
+  /// The result of the aggregate over the rows fed to it so far, which
+  /// must not be held back by an `ORDER BY`.
+  pub(crate) fn value(&self, call: &AggregateCall) -> SqliteResult<Value> {
+    Ok(match &self.state {
+      State::Count(count) => Value::Integer(*count),
+      State::Sum(sum) if sum.count == 0 => match call.function {
+        AggregateFunction::Total => Value::Real(0.0),
+        _ => Value::Null,
+      },
+      State::Sum(sum) => match call.function {
+        AggregateFunction::Total => Value::Real(sum.real_sum()),
+        AggregateFunction::Avg => {
+          Value::Real(sum.real_sum() / sum.count as f64)
+        }
+        _ if sum.overflow => {
+          return Err(SqliteError::Custom("integer overflow".into()))
+        }
+        _ if sum.approximate => Value::Real(sum.real_sum()),
+        _ => Value::Integer(sum.int),
+      },
+      State::Best(best) => best.clone().unwrap_or(Value::Null),
+      State::Concat(text) => text.clone().map_or(Value::Null, Value::Text),
+      State::Json(group) => match call.function {
+        AggregateFunction::JsonGroup { binary, .. } => group.value(binary)?,
+        _ => Value::Null,
+      },
+      State::User(state) => match &call.function {
+        AggregateFunction::User(function) => function.value(state)?,
+        _ => Value::Null,
+      },
+    })
+  }
+}
+
+impl State {
+  fn step(
+    &mut self,
+    call: &AggregateCall,
+    values: &[Value],
+  ) -> SqliteResult<bool> {
+    let value = values.first().unwrap_or(&Value::Null);
+    Ok(match self {
+      Self::Count(count) => {
+        if values.is_empty() || !value.is_null() {
+          *count += 1;
+        }
+        false
+      }
+      Self::Sum(sum) => {
+        if !value.is_null() {
+          sum.add(value);
+        }
+        false
+      }
+      // A NULL is the best value only as long as there is no other.
+      Self::Best(best) if value.is_null() => best.is_none(),
+      Self::Best(best) => {
+        let wanted = match call.function {
+          AggregateFunction::Max => Ordering::Greater,
+          _ => Ordering::Less,
+        };
+        let is_best = best.as_ref().map_or(true, |best| {
+          compare_values(value, best, &call.collation) == wanted
+        });
+        if is_best {
+          *best = Some(value.clone());
+        }
+        is_best
+      }
+      Self::Concat(text) => {
+        let Some(value) = to_text(value) else {
+          return Ok(false);
+        };
+        match text {
+          Some(text) => {
+            match values.get(1) {
+              Some(separator) => {
+                text.push_str(&to_text(separator).unwrap_or_default())
+              }
+              None => text.push(','),
+            }
+            text.push_str(&value);
+          }
+          None => *text = Some(value),
+        }
+        false
+      }
+      Self::Json(group) => {
+        group.step(values);
+        false
+      }
+      Self::User(state) => {
+        if let AggregateFunction::User(function) = &call.function {
+          function.step(state, values)?;
+        }
+        false
+      }
+    })
+  }
+}
+
+/// Folds each group of rows into one. The rows of a group must follow one
+/// another: each input row holds `width` values, followed by the values of
+/// its group.
+///
+///  The output rows hold the values of the first row of their group, for
+/// the columns of the query that are not aggregated, followed by the result
+/// of each aggregate. With `min()` or `max()`, the values are instead those
+/// of the row the minimum or maximum comes from.
+#[derive(Debug)]
+pub(crate) struct Aggregate {
+  input: Box<dyn Operator>,
+  width: usize,
+  /// Collating sequence of each value of the groups.
+  groups: Vec<Collation>,
+  calls: Vec<AggregateCall>,
+  /// The `min()` or `max()` call the values of bare columns follow, the
+  /// last one when there are several.
+  bare_from: Option<usize>,
+  /// The first row of the next group.
+  pending: Option<Vec<Value>>,
+  started: bool,
+  done: bool,
+}
+
+impl Aggregate {
+  pub(crate) fn new(
+    input: Box<dyn Operator>,
+    width: usize,
+    groups: Vec<Collation>,
+    calls: Vec<AggregateCall>,
+  ) -> Self {
+    let bare_from = calls.iter().rposition(|call| {
+      matches!(
+        call.function,
+        AggregateFunction::Min | AggregateFunction::Max
+      )
+    });
+    Self {
+      input,
+      width,
+      groups,
+      calls,
+      bare_from,
+      pending: None,
+      started: false,
+      done: false,
+    }
+  }
+
+  fn same_group(&self, left: &[Value], right: &[Value]) -> bool {
+    self.groups.iter().enumerate().all(|(idx, collation)| {
+      let (left, right) = (&left[self.width + idx], &right[self.width + idx]);
+      match (left.is_null(), right.is_null()) {
+        (true, true) => true,
+        (false, false) => compare_values(left, right, collation).is_eq(),
+        _ => false,
+      }
+    })
+  }
+
+  fn step(
+    &self,
+    accumulators: &mut [Accumulator],
+    ctx: &mut Context<'_>,
+    row: &[Value],
+    bare: &mut Vec<Value>,
+  ) -> SqliteResult<()> {
+    for (idx, (call, accumulator)) in
+      self.calls.iter().zip(accumulators.iter_mut()).enumerate()
+    {
+      if accumulator.step(call, ctx, row)? && self.bare_from == Some(idx) {
+        *bare = row.to_vec();
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Operator for Aggregate {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if self.done {
+      return Ok(None);
+    }
+    let mut accumulators =
+      self.calls.iter().map(Accumulator::new).collect::<Vec<_>>();
+    let first = match self.pending.take() {
+      Some(row) => Some(row),
+      None => self.input.next(ctx)?,
+    };
+    let mut bare = match first {
+      Some(first) => {
+        self.started = true;
+        let mut bare = first.clone();
+        self.step(&mut accumulators, ctx, &first, &mut bare)?;
+        loop {
+          match self.input.next(ctx)? {
+            Some(row) if self.same_group(&first, &row) => {
+              self.step(&mut accumulators, ctx, &row, &mut bare)?;
+            }
+            Some(row) => {
+              self.pending = Some(row);
+              break;
+            }
+            None => {
+              self.done = true;
+              break;
+            }
+          }
+        }
+        bare
+      }
+      // Without GROUP BY, there is a row even when there are no rows to
+      // aggregate.
+      None if !self.started && self.groups.is_empty() => {
+        self.done = true;
+        vec![Value::Null; self.width]
+      }
+      None => {
+        self.done = true;
+        return Ok(None);
+      }
+    };
+    bare.truncate(self.width);
+    for (call, accumulator) in self.calls.iter().zip(accumulators) {
+      bare.push(accumulator.finish(call)?);
+    }
+    Ok(Some(bare))
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+    self.pending = None;
+    self.started = false;
+    self.done = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+  }
+
+  /// Each row of the input is fed to the accumulators of the calls, after
+  /// the row of the previous group, if it is done with, is returned by a
+  /// subroutine.
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let width = self.width as i32;
+    let accumulators = builder.accumulators(self.calls.len());
+    // The values of the columns, then the results of the calls.
+    let output = builder.registers(self.width + self.calls.len());
+    let groups = builder.registers(self.groups.len());
+    let started = builder.register();
+    let is_best = builder.register();
+    let return_address = builder.register();
+    let (subroutine, done) = (builder.label(), builder.label());
+    builder.add(Opcode::Integer, 0, started, 0);
+    // The input is already sorted by the sort of the groups, not by the
+    // LIMIT above.
+    let sort_limit = builder.sort_limit.take();
+    let result = self.input.compile(builder, &mut |builder, row, _| {
+      let first = builder.row_registers(row);
+      let (new_group, same_group) = (builder.label(), builder.label());
+      builder.jump(Opcode::IfNot, started, new_group, 0);
+      let changed = builder.label();
+      for (idx, collation) in (0..).zip(&self.groups) {
+        let value = first + width + idx;
+        builder.if_distinct(groups + idx, value, collation, changed);
+      }
+      builder.jump(Opcode::Goto, 0, same_group, 0);
+      if !self.groups.is_empty() {
+        builder.resolve(changed);
+        builder.jump(Opcode::Gosub, return_address, subroutine, 0);
+      }
+      builder.resolve(new_group);
+      for idx in 0..self.groups.len() as i32 {
+        builder.add(Opcode::SCopy, first + width + idx, groups + idx, 0);
+      }
+      for idx in 0..width {
+        builder.add(Opcode::SCopy, first + idx, output + idx, 0);
+      }
+      builder.add(Opcode::Integer, 1, started, 0);
+      builder.resolve(same_group);
+      for (idx, call) in (0..).zip(&self.calls) {
+        let bare = self.bare_from == Some(idx as usize) && width > 0;
+        let step = builder.add(
+          Opcode::AggStep,
+          if bare { is_best } else { 0 },
+          first,
+          accumulators + idx,
+        );
+        step.p4 = P4::Aggregate(call.clone());
+        step.p5 = row.len() as u16;
+        if bare {
+          let skip = builder.label();
+          builder.jump(Opcode::IfNot, is_best, skip, 0);
+          for idx in 0..width {
+            builder.add(Opcode::SCopy, first + idx, output + idx, 0);
+          }
+          builder.resolve(skip);
+        }
+      }
+      Ok(())
+    });
+    builder.sort_limit = sort_limit;
+    result?;
+    // Without GROUP BY, there is a row even when there are no rows to
+    // aggregate.
+    if !self.groups.is_empty() {
+      builder.jump(Opcode::IfNot, started, done, 0);
+    }
+    builder.jump(Opcode::Gosub, return_address, subroutine, 0);
+    builder.jump(Opcode::Goto, 0, done, 0);
+    builder.resolve(subroutine);
+    for (idx, call) in (0..).zip(&self.calls) {
+      let result = output + width + idx;
+      builder
+        .add(Opcode::AggFinal, accumulators + idx, 0, result)
+        .p4 = P4::Aggregate(call.clone());
+    }
+    let row = (output..)
+      .take(self.width + self.calls.len())
+      .map(Source::Register)
+      .collect::<Vec<_>>();
+    let end = builder.label();
+    consume(builder, &row, end)?;
+    builder.resolve(end);
+    builder.add(Opcode::Return, return_address, 0, 0);
+    builder.resolve(done);
+    Ok(())
+  }
+}
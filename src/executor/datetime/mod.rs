@@ -0,0 +1,1029 @@
+//! # Date and time functions
+//!
+//!  `date()`, `time()`, `datetime()`, `julianday()`, `unixepoch()`,
+//! `strftime()` and `timediff()` read a time value, change it with each of
+//! their modifiers in turn, and render it. A time value is either text, in
+//! one of the ISO-8601 formats or `'now'`, or a number: a julian day number,
+//! or with the `'unixepoch'` or `'auto'` modifier a unix timestamp.
+//!
+//!  Times are held as julian day numbers in milliseconds, from which the
+//! calendar date and the time of day are computed when they are needed, and
+//! the other way around. All dates are in the proleptic Gregorian calendar,
+//! from `-4713-11-24 12:00:00` to `9999-12-31 23:59:59.999`; a time value or
+//! a modifier taking the time out of that range makes the result NULL, as
+//! does any malformed time value or modifier.
+//!
+//! *Reference:* https://www.sqlite.org/lang_datefunc.html
+
+mod zone;
+
+use super::printf;
+use super::Context;
+use crate::runtime::{parse_number, to_real, to_text, Value};
+
+/// Milliseconds in a day.
+const DAY: i64 = 86_400_000;
+/// The julian day of the unix epoch, `1970-01-01 00:00:00`, in milliseconds.
+const UNIX_EPOCH: i64 = 210_866_760_000_000;
+/// The last julian day of the supported range, in milliseconds.
+const MAX_JULIAN_DAY: i64 = 464_269_060_799_999;
+
+/// The units of the `'NNN units'` modifiers, with the largest amount of each
+/// that may be added and its length in seconds. Months and years are added to
+/// the calendar date, and only their fractional part counts as 30 and 365
+/// days.
+const UNITS: [(&str, f32, f64); 6] = [
+  ("second", 4.6427e14, 1.0),
+  ("minute", 7.7379e12, 60.0),
+  ("hour", 1.2897e11, 3_600.0),
+  ("day", 5_373_485.0, 86_400.0),
+  ("month", 176_546.0, 2_592_000.0),
+  ("year", 14_713.0, 31_536_000.0),
+];
+
+/// A point in time, as a julian day number, as a calendar date and a time of
+/// day, or both.
+#[derive(Debug, Clone, Copy, Default)]
+struct DateTime {
+  /// The julian day number, in milliseconds.
+  jd: i64,
+  year: i32,
+  month: i32,
+  day: i32,
+  hour: i32,
+  minute: i32,
+  seconds: f64,
+  /// The offset of the time zone of the time of day from UTC, in minutes.
+  tz: i32,
+  valid_jd: bool,
+  valid_ymd: bool,
+  valid_hms: bool,
+  /// Days past the end of its month the date was given, which the `'floor'`
+  /// modifier takes back.
+  overflow: i32,
+  /// Whether `seconds` holds the number the time value was, still to be
+  /// interpreted as a julian day number or a unix timestamp.
+  raw: bool,
+  error: bool,
+  /// Whether the seconds are rendered with milliseconds.
+  subsec: bool,
+  /// Whether the time is known to be in UTC.
+  utc: bool,
+  /// Whether the time is known to be local time.
+  local: bool,
+}
+
+/// `date(time-value, modifier, ...)`: the date, as `YYYY-MM-DD`.
+pub(crate) fn date(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  match DateTime::read(ctx, arguments) {
+    Some(mut dt) => {
+      dt.compute_ymd();
+      Value::Text(dt.date())
+    }
+    None => Value::Null,
+  }
+}
+
+/// `time(time-value, modifier, ...)`: the time of day, as `HH:MM:SS`, or
+/// `HH:MM:SS.SSS` with the `'subsec'` modifier.
+pub(crate) fn time(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  match DateTime::read(ctx, arguments) {
+    Some(mut dt) => {
+      dt.compute_hms();
+      Value::Text(dt.time())
+    }
+    None => Value::Null,
+  }
+}
+
+/// `datetime(time-value, modifier, ...)`: the date and the time of day, as
+/// `YYYY-MM-DD HH:MM:SS`.
+pub(crate) fn datetime(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  match DateTime::read(ctx, arguments) {
+    Some(mut dt) => {
+      dt.compute_ymd_hms();
+      Value::Text(format!("{} {}", dt.date(), dt.time()))
+    }
+    None => Value::Null,
+  }
+}
+
+/// `julianday(time-value, modifier, ...)`: the fractional number of days
+/// since noon in Greenwich on November 24, 4714 B.C.
+pub(crate) fn julianday(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  match DateTime::read(ctx, arguments) {
+    Some(dt) => Value::Real(dt.jd as f64 / DAY as f64),
+    None => Value::Null,
+  }
+}
+
+/// `unixepoch(time-value, modifier, ...)`: the seconds since
+/// `1970-01-01 00:00:00`, an integer unless the `'subsec'` modifier is given.
+pub(crate) fn unixepoch(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  match DateTime::read(ctx, arguments) {
+    Some(dt) => dt.unix_seconds(),
+    None => Value::Null,
+  }
+}
+
+/// `strftime(format, time-value, modifier, ...)`: the time rendered by
+/// `format`, whose `%` substitutions are those of the C library function,
+/// plus `%f` for seconds with milliseconds and `%J` for the julian day
+/// number. An unknown substitution makes the result NULL.
+pub(crate) fn strftime(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  let Some(format) = arguments.first().and_then(to_text) else {
+    return Value::Null;
+  };
+  let Some(mut dt) = DateTime::read(ctx, &arguments[1..]) else {
+    return Value::Null;
+  };
+  dt.compute_ymd_hms();
+  let mut result = String::new();
+  let mut rest = format.as_str();
+  while let Some(idx) = rest.find('%') {
+    result.push_str(&rest[..idx]);
+    let mut chars = rest[idx + 1..].chars();
+    let conversion = chars.next();
+    rest = chars.as_str();
+    let text = match conversion {
+      Some('d') => format!("{:02}", dt.day),
+      Some('e') => format!("{:2}", dt.day),
+      Some('f') => real("%06.3f", dt.seconds.min(59.999)),
+      Some('F') => format!("{:04}-{:02}-{:02}", dt.year, dt.month, dt.day),
+      Some('G') => format!("{:04}", dt.thursday_of_week().year),
+      Some('g') => format!("{:02}", dt.thursday_of_week().year % 100),
+      Some('H') => format!("{:02}", dt.hour),
+      Some('k') => format!("{:2}", dt.hour),
+      Some(conversion @ ('I' | 'l')) => {
+        let hour = match dt.hour {
+          0 => 12,
+          hour if hour > 12 => hour - 12,
+          hour => hour,
+        };
+        match conversion {
+          'I' => format!("{hour:02}"),
+          _ => format!("{hour:2}"),
+        }
+      }
+      Some('j') => format!("{:03}", dt.days_after_jan01() + 1),
+      Some('J') => real("%.16g", dt.jd as f64 / DAY as f64),
+      Some('m') => format!("{:02}", dt.month),
+      Some('M') => format!("{:02}", dt.minute),
+      Some('p') => (if dt.hour >= 12 { "PM" } else { "AM" }).into(),
+      Some('P') => (if dt.hour >= 12 { "pm" } else { "am" }).into(),
+      Some('R') => format!("{:02}:{:02}", dt.hour, dt.minute),
+      Some('s') => match dt.unix_seconds() {
+        Value::Real(seconds) => real("%.3f", seconds),
+        seconds => seconds.to_string(),
+      },
+      Some('S') => format!("{:02}", dt.seconds as i32),
+      Some('T') => {
+        format!("{:02}:{:02}:{:02}", dt.hour, dt.minute, dt.seconds as i32)
+      }
+      Some('u') => match dt.days_after_sunday() {
+        0 => "7".into(),
+        day => day.to_string(),
+      },
+      Some('w') => dt.days_after_sunday().to_string(),
+      Some('U') => format!(
+        "{:02}",
+        (dt.days_after_jan01() - dt.days_after_sunday() + 7) / 7
+      ),
+      Some('V') => {
+        format!("{:02}", dt.thursday_of_week().days_after_jan01() / 7 + 1)
+      }
+      Some('W') => format!(
+        "{:02}",
+        (dt.days_after_jan01() - dt.days_after_monday() + 7) / 7
+      ),
+      Some('Y') => format!("{:04}", dt.year),
+      Some('%') => "%".into(),
+      _ => return Value::Null,
+    };
+    result.push_str(&text);
+  }
+  result.push_str(rest);
+  Value::Text(result)
+}
+
+/// `timediff(time-value, time-value)`: the time to add to the second time
+/// to reach the first, as `(+|-)YYYY-MM-DD HH:MM:SS.SSS`.
+pub(crate) fn timediff(ctx: &Context<'_>, arguments: &[Value]) -> Value {
+  let (Some(mut first), Some(mut second)) = (
+    DateTime::read(ctx, &arguments[..1]),
+    DateTime::read(ctx, &arguments[1..]),
+  ) else {
+    return Value::Null;
+  };
+  first.compute_ymd_hms();
+  second.compute_ymd_hms();
+  // `second` is moved by whole years and months toward `first`, and the
+  // rest of the difference is rendered as a time past `0000-01-01`.
+  let sign = if first.jd >= second.jd { '+' } else { '-' };
+  let (mut years, mut months) = match sign {
+    '+' => (first.year - second.year, first.month - second.month),
+    _ => (second.year - first.year, second.month - first.month),
+  };
+  if years != 0 {
+    second.year = first.year;
+    second.valid_jd = false;
+    second.compute_jd();
+  }
+  if months < 0 {
+    years -= 1;
+    months += 12;
+  }
+  if months != 0 {
+    second.month = first.month;
+    second.valid_jd = false;
+    second.compute_jd();
+  }
+  while (sign == '+' && first.jd < second.jd)
+    || (sign == '-' && first.jd > second.jd)
+  {
+    months -= 1;
+    if months < 0 {
+      months = 11;
+      years -= 1;
+    }
+    match sign {
+      '+' => second.month -= 1,
+      _ => second.month += 1,
+    }
+    if second.month < 1 {
+      second.month = 12;
+      second.year -= 1;
+    } else if second.month > 12 {
+      second.month = 1;
+      second.year += 1;
+    }
+    second.valid_jd = false;
+    second.compute_jd();
+  }
+  let difference = match sign {
+    '+' => first.jd - second.jd,
+    _ => second.jd - first.jd,
+  };
+  let mut rest = DateTime {
+    jd: difference + 148_699_540_800_000,
+    valid_jd: true,
+    ..DateTime::default()
+  };
+  rest.compute_ymd_hms();
+  Value::Text(format!(
+    "{sign}{years:04}-{months:02}-{:02} {:02}:{:02}:{}",
+    rest.day - 1,
+    rest.hour,
+    rest.minute,
+    real("%06.3f", rest.seconds)
+  ))
+}
+
+/// Seconds rounded to milliseconds. The product is rounded before the sum,
+/// as SQLite computes it.
+fn milliseconds(seconds: f64) -> i64 {
+  let milliseconds = seconds * 1000.0;
+  (milliseconds + 0.5) as i64
+}
+
+/// The julian day, in milliseconds, of a unix timestamp in seconds.
+fn unix_milliseconds(seconds: f64) -> f64 {
+  let milliseconds = seconds * 1000.0;
+  milliseconds + UNIX_EPOCH as f64
+}
+
+/// `value` rendered by the `printf()` format `format`.
+fn real(format: &str, value: f64) -> String {
+  printf::format(format, &[Value::Real(value)])
+}
+
+impl DateTime {
+  /// The time value of the first of `arguments` changed by the modifiers
+  /// that follow it, or the current time if there are no arguments. `None`
+  /// if any of them is NULL or malformed, or the time is out of range.
+  fn read(ctx: &Context<'_>, arguments: &[Value]) -> Option<Self> {
+    let mut dt = Self::default();
+    match arguments.first() {
+      None => dt.set_now(ctx),
+      Some(value @ (Value::Integer(_) | Value::Real(_))) => {
+        dt.set_raw_number(to_real(value));
+      }
+      Some(value) => dt.parse(ctx, &to_text(value)?)?,
+    }
+    for (position, modifier) in arguments.iter().enumerate().skip(1) {
+      dt.modify(to_text(modifier)?.as_bytes(), position)?;
+    }
+    dt.compute_jd();
+    if dt.error || !(0..=MAX_JULIAN_DAY).contains(&dt.jd) {
+      return None;
+    }
+    // A date past the end of its month, as `2023-02-31`, is rendered as the
+    // date it overflows to.
+    if arguments.len() == 1 && dt.valid_ymd && dt.day > 28 {
+      dt.valid_ymd = false;
+    }
+    Some(dt)
+  }
+
+  /// Reads a time value given as text.
+  fn parse(&mut self, ctx: &Context<'_>, text: &str) -> Option<()> {
+    if self.parse_date(text.as_bytes()).is_some()
+      || self.parse_time(text.as_bytes()).is_some()
+    {
+      return Some(());
+    }
+    if text.eq_ignore_ascii_case("now") {
+      self.set_now(ctx);
+    } else if let Some((number, true)) = parse_number(text) {
+      self.set_raw_number(to_real(&number));
+    } else if text.eq_ignore_ascii_case("subsec")
+      || text.eq_ignore_ascii_case("subsecond")
+    {
+      self.subsec = true;
+      self.set_now(ctx);
+    } else {
+      return None;
+    }
+    Some(())
+  }
+
+  /// Reads `YYYY-MM-DD`, optionally followed by a time of day after spaces
+  /// or a `T`.
+  fn parse_date(&mut self, text: &[u8]) -> Option<()> {
+    let (negative, text) = match text.first() {
+      Some(b'-') => (true, &text[1..]),
+      _ => (false, text),
+    };
+    let [year, month, day] = digits(
+      text,
+      [(4, 0, 14_712, b'-'), (2, 1, 12, b'-'), (2, 1, 31, 0)],
+    )?;
+    let mut idx = 10;
+    while is_space(at(text, idx)) || at(text, idx) == b'T' {
+      idx += 1;
+    }
+    if self.parse_time(rest(text, idx)).is_none() {
+      if at(text, idx) != 0 {
+        return None;
+      }
+      self.valid_hms = false;
+    }
+    self.valid_jd = false;
+    self.valid_ymd = true;
+    self.year = if negative { -year } else { year };
+    self.month = month;
+    self.day = day;
+    self.compute_overflow();
+    if self.tz != 0 {
+      self.compute_jd();
+    }
+    Some(())
+  }
+
+  /// Reads `HH:MM`, `HH:MM:SS` or `HH:MM:SS.SSS`, optionally followed by a
+  /// time zone.
+  fn parse_time(&mut self, text: &[u8]) -> Option<()> {
+    let [hour, minute] = digits(text, [(2, 0, 24, b':'), (2, 0, 59, 0)])?;
+    let mut idx = 5;
+    let mut seconds = 0.0;
+    if at(text, idx) == b':' {
+      let [whole] = digits(rest(text, idx + 1), [(2, 0, 59, 0)])?;
+      seconds = f64::from(whole);
+      idx += 3;
+      if at(text, idx) == b'.' && at(text, idx + 1).is_ascii_digit() {
+        let (mut fraction, mut scale) = (0.0, 1.0);
+        idx += 1;
+        while at(text, idx).is_ascii_digit() {
+          let shifted = fraction * 10.0;
+          fraction = shifted + f64::from(at(text, idx) - b'0');
+          scale *= 10.0;
+          idx += 1;
+        }
+        // Truncated, for the milliseconds not to round up to a second.
+        seconds += (fraction / scale).min(0.999);
+      }
+    }
+    self.valid_jd = false;
+    self.raw = false;
+    self.valid_hms = true;
+    self.hour = hour;
+    self.minute = minute;
+    self.seconds = seconds;
+    self.parse_timezone(rest(text, idx))
+  }
+
+  /// Reads the optional time zone after a time of day: `Z`, or `+HH:MM` or
+  /// `-HH:MM` from UTC.
+  fn parse_timezone(&mut self, text: &[u8]) -> Option<()> {
+    let idx = skip_spaces(text, 0);
+    self.tz = 0;
+    let sign = match at(text, idx) {
+      b'-' => -1,
+      b'+' => 1,
+      b'Z' | b'z' => {
+        self.local = false;
+        self.utc = true;
+        return (at(text, skip_spaces(text, idx + 1)) == 0).then_some(());
+      }
+      next => return (next == 0).then_some(()),
+    };
+    let [hours, minutes] =
+      digits(rest(text, idx + 1), [(2, 0, 14, b':'), (2, 0, 59, 0)])?;
+    self.tz = sign * (minutes + hours * 60);
+    (at(text, skip_spaces(text, idx + 6)) == 0).then_some(())
+  }
+
+  /// The time value `'now'`: the time the statement started, in UTC.
+  fn set_now(&mut self, ctx: &Context<'_>) {
+    self.jd = ctx.now() + UNIX_EPOCH;
+    self.valid_jd = true;
+    self.utc = true;
+    self.local = false;
+    self.clear_ymd_hms();
+  }
+
+  /// A number given as the time value: a julian day number, unless a
+  /// modifier says otherwise.
+  fn set_raw_number(&mut self, number: f64) {
+    self.seconds = number;
+    self.raw = true;
+    if (0.0..5_373_484.5).contains(&number) {
+      let jd = number * DAY as f64;
+      self.jd = (jd + 0.5) as i64;
+      self.valid_jd = true;
+    }
+  }
+
+  /// Applies the modifier at `position` among the arguments.
+  fn modify(&mut self, modifier: &[u8], position: usize) -> Option<()> {
+    let name = String::from_utf8_lossy(modifier).to_ascii_lowercase();
+    match name.as_str() {
+      // These apply to a number time value, and come first.
+      "auto" | "julianday" | "unixepoch" if position > 1 => return None,
+      "auto" => self.auto_adjust(),
+      "julianday" => match self.valid_jd && self.raw {
+        true => self.raw = false,
+        false => return None,
+      },
+      "unixepoch" => {
+        if !self.raw {
+          return None;
+        }
+        let jd = unix_milliseconds(self.seconds);
+        if !(0.0..(MAX_JULIAN_DAY + 1) as f64).contains(&jd) {
+          return None;
+        }
+        self.clear_ymd_hms();
+        self.jd = (jd + 0.5) as i64;
+        self.valid_jd = true;
+        self.raw = false;
+      }
+      "ceiling" => {
+        self.compute_jd();
+        self.clear_ymd_hms();
+        self.overflow = 0;
+      }
+      "floor" => {
+        self.compute_jd();
+        self.jd -= i64::from(self.overflow) * DAY;
+        self.clear_ymd_hms();
+      }
+      "localtime" => {
+        if !self.local {
+          self.switch_to_local_time();
+        }
+        self.utc = false;
+        self.local = true;
+      }
+      "utc" => {
+        if !self.utc {
+          self.switch_to_utc();
+        }
+      }
+      "subsec" | "subsecond" => self.subsec = true,
+      _ => match modifier.first()? {
+        b'+' | b'-' | b'0'..=b'9' => self.shift(modifier)?,
+        _ => {
+          if let Some(unit) = name.strip_prefix("start of ") {
+            self.start_of(unit)?;
+          } else if let Some(day) = name.strip_prefix("weekday ") {
+            self.weekday(day)?;
+          } else {
+            return None;
+          }
+        }
+      },
+    }
+    Some(())
+  }
+
+  /// `'auto'`: a number time value is a julian day number if it is in the
+  /// range of those, or else a unix timestamp.
+  fn auto_adjust(&mut self) {
+    if !self.raw || self.valid_jd {
+      self.raw = false;
+    } else if (-210_866_760_000.0..=253_402_300_799.0).contains(&self.seconds) {
+      let jd = unix_milliseconds(self.seconds);
+      self.clear_ymd_hms();
+      self.jd = (jd + 0.5) as i64;
+      self.valid_jd = true;
+      self.raw = false;
+    }
+  }
+
+  /// `'start of day'`, `'start of month'` and `'start of year'`.
+  fn start_of(&mut self, unit: &str) -> Option<()> {
+    if !self.valid_jd && !self.valid_ymd && !self.valid_hms {
+      return None;
+    }
+    self.compute_ymd();
+    self.valid_hms = true;
+    self.hour = 0;
+    self.minute = 0;
+    self.seconds = 0.0;
+    self.raw = false;
+    self.tz = 0;
+    self.valid_jd = false;
+    match unit {
+      "day" => {}
+      "month" => self.day = 1,
+      "year" => {
+        self.month = 1;
+        self.day = 1;
+      }
+      _ => return None,
+    }
+    Some(())
+  }
+
+  /// `'weekday N'`: moves the date forward to the next day that is the
+  /// `N`th of the week, Sunday being 0, unless it already is.
+  fn weekday(&mut self, day: &str) -> Option<()> {
+    let day = match parse_number(day)? {
+      (number, true) => to_real(&number),
+      _ => return None,
+    };
+    if !(0.0..7.0).contains(&day) || day.fract() != 0.0 {
+      return None;
+    }
+    let day = day as i64;
+    self.compute_ymd_hms();
+    self.tz = 0;
+    self.valid_jd = false;
+    self.compute_jd();
+    let mut current = (self.jd + 129_600_000) / DAY % 7;
+    if current > day {
+      current -= 7;
+    }
+    self.jd += (day - current) * DAY;
+    self.clear_ymd_hms();
+    Some(())
+  }
+
+  /// The modifiers that start with a sign or a digit: `'NNN units'`,
+  /// `'(+|-)HH:MM:SS.SSS'` and `'(+|-)YYYY-MM-DD HH:MM:SS.SSS'`.
+  fn shift(&mut self, modifier: &[u8]) -> Option<()> {
+    let sign = modifier[0];
+    // The number ends at a colon, a space, or the dash after a year.
+    let mut n = 1;
+    while n < modifier.len() {
+      match modifier[n] {
+        b':' => break,
+        next if is_space(next) => break,
+        b'-'
+          if (n == 5 || n == 6)
+            && digits(&modifier[1..], [(n - 1, 0, 14_712, 0)]).is_some() =>
+        {
+          break
+        }
+        _ => n += 1,
+      }
+    }
+    let mut amount =
+      match parse_number(&String::from_utf8_lossy(&modifier[..n]))? {
+        (number, true) => to_real(&number),
+        _ => return None,
+      };
+    let (mut time, mut time_end) = (modifier, n);
+    if at(modifier, n) == b'-' {
+      // Years, months and days, with months less than 12 and days less than
+      // 31, optionally followed by a time of day.
+      if sign != b'+' && sign != b'-' {
+        return None;
+      }
+      let (date, [years, months, days]) = match n {
+        5 => (
+          modifier,
+          digits(
+            &modifier[1..],
+            [(4, 0, 14_712, b'-'), (2, 0, 12, b'-'), (2, 0, 31, 0)],
+          )?,
+        ),
+        _ => (
+          &modifier[1..],
+          digits(
+            &modifier[1..],
+            [(5, 0, 14_712, b'-'), (2, 0, 12, b'-'), (2, 0, 31, 0)],
+          )?,
+        ),
+      };
+      if months >= 12 || days >= 31 {
+        return None;
+      }
+      self.compute_ymd_hms();
+      self.valid_jd = false;
+      let days = match sign {
+        b'-' => {
+          self.year -= years;
+          self.month -= months;
+          -days
+        }
+        _ => {
+          self.year += years;
+          self.month += months;
+          days
+        }
+      };
+      self.normalize_month();
+      self.compute_overflow();
+      self.compute_jd();
+      self.valid_hms = false;
+      self.valid_ymd = false;
+      self.jd += i64::from(days) * DAY;
+      match at(date, 11) {
+        0 => return Some(()),
+        next
+          if is_space(next)
+            && digits(rest(date, 12), [(2, 0, 24, b':'), (2, 0, 59, 0)])
+              .is_some() =>
+        {
+          (time, time_end) = (rest(date, 12), 2);
+        }
+        _ => return None,
+      }
+    }
+    if at(time, time_end) == b':' {
+      let time = match time[0].is_ascii_digit() {
+        true => time,
+        false => &time[1..],
+      };
+      let mut shift = Self::default();
+      shift.parse_time(time)?;
+      shift.compute_jd();
+      shift.jd -= 43_200_000;
+      shift.jd -= shift.jd / DAY * DAY;
+      if sign == b'-' {
+        shift.jd = -shift.jd;
+      }
+      self.compute_jd();
+      self.clear_ymd_hms();
+      self.jd += shift.jd;
+      return Some(());
+    }
+
+    let unit = &modifier[skip_spaces(modifier, n)..];
+    if !(3..=10).contains(&unit.len()) {
+      return None;
+    }
+    let unit = match unit.last() {
+      Some(b's' | b'S') => &unit[..unit.len() - 1],
+      _ => unit,
+    };
+    self.compute_jd();
+    self.overflow = 0;
+    let (name, _, length) = UNITS.into_iter().find(|(name, limit, _)| {
+      let limit = f64::from(*limit);
+      unit.eq_ignore_ascii_case(name.as_bytes())
+        && amount > -limit
+        && amount < limit
+    })?;
+    match name {
+      "month" => {
+        self.compute_ymd_hms();
+        self.month += amount as i32;
+        self.normalize_month();
+        self.compute_overflow();
+        self.valid_jd = false;
+        amount = amount.fract();
+      }
+      "year" => {
+        self.compute_ymd_hms();
+        self.year += amount as i32;
+        self.compute_overflow();
+        self.valid_jd = false;
+        amount = amount.fract();
+      }
+      _ => {}
+    }
+    self.compute_jd();
+    let rounder = if amount < 0.0 { -0.5 } else { 0.5 };
+    let shift = amount * 1000.0 * length;
+    self.jd += (shift + rounder) as i64;
+    self.clear_ymd_hms();
+    Some(())
+  }
+
+  /// `'localtime'`: the time, taken to be in UTC, in the local time zone.
+  fn switch_to_local_time(&mut self) {
+    self.compute_jd();
+    // Outside of the years the C library reliably handles, the year is
+    // mapped to one between 2000 and 2003 with the same leap year pattern.
+    let (seconds, year_difference) =
+      if self.jd < UNIX_EPOCH || self.jd > 213_014_145_600_000 {
+        let mut mapped = *self;
+        mapped.compute_ymd_hms();
+        let difference = 2000 + mapped.year % 4 - mapped.year;
+        mapped.year += difference;
+        mapped.valid_jd = false;
+        mapped.compute_jd();
+        (mapped.jd / 1000 - UNIX_EPOCH / 1000, difference)
+      } else {
+        (self.jd / 1000 - UNIX_EPOCH / 1000, 0)
+      };
+    let offset = zone::local().offset(seconds);
+    let mut local = Self {
+      jd: (seconds + offset) * 1000 + UNIX_EPOCH,
+      valid_jd: true,
+      ..Self::default()
+    };
+    local.compute_ymd_hms();
+    self.year = local.year - year_difference;
+    self.month = local.month;
+    self.day = local.day;
+    self.hour = local.hour;
+    self.minute = local.minute;
+    let milliseconds = (self.jd % 1000) as f64 * 0.001;
+    self.seconds = local.seconds + milliseconds;
+    self.valid_ymd = true;
+    self.valid_hms = true;
+    self.valid_jd = false;
+    self.raw = false;
+    self.tz = 0;
+    self.error = false;
+  }
+
+  /// `'utc'`: the time, taken to be local time, in UTC. The UTC time is
+  /// guessed, and the guess corrected by how far off its local time is.
+  fn switch_to_utc(&mut self) {
+    self.compute_jd();
+    let original = self.jd;
+    let mut guess = original;
+    let mut error = 0;
+    for _ in 0..4 {
+      guess -= error;
+      let mut local = Self {
+        jd: guess,
+        valid_jd: true,
+        ..Self::default()
+      };
+      local.switch_to_local_time();
+      local.compute_jd();
+      error = local.jd - original;
+      if error == 0 {
+        break;
+      }
+    }
+    *self = Self {
+      jd: guess,
+      valid_jd: true,
+      utc: true,
+      ..Self::default()
+    };
+  }
+
+  /// Brings the month back between 1 and 12, carrying into the year.
+  fn normalize_month(&mut self) {
+    let years = match self.month > 0 {
+      true => (self.month - 1) / 12,
+      false => (self.month - 12) / 12,
+    };
+    self.year += years;
+    self.month -= years * 12;
+  }
+
+  /// Counts the days the date is past the end of its month.
+  fn compute_overflow(&mut self) {
+    let is_leap =
+      self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0);
+    self.overflow = match self.month {
+      _ if self.day <= 28 => 0,
+      1 | 3 | 5 | 7 | 8 | 10 | 12 => 0,
+      2 if is_leap => self.day - 29,
+      2 => self.day - 28,
+      _ => i32::from(self.day == 31),
+    };
+  }
+
+  /// Computes the julian day number from the date and the time of day,
+  /// shifted to UTC when the time of day has a time zone.
+  fn compute_jd(&mut self) {
+    if self.valid_jd {
+      return;
+    }
+    let (mut year, mut month, day) = match self.valid_ymd {
+      true => (self.year, self.month, self.day),
+      false => (2000, 1, 1),
+    };
+    if !(-4713..=9999).contains(&year) || self.raw {
+      self.set_error();
+      return;
+    }
+    if month <= 2 {
+      year -= 1;
+      month += 12;
+    }
+    let a = (year + 4800) / 100;
+    let b = 38 - a + a / 4;
+    let x1 = 36525 * (year + 4716) / 100;
+    let x2 = 306_001 * (month + 1) / 10_000;
+    self.jd = ((f64::from(x1 + x2 + day + b) - 1524.5) * DAY as f64) as i64;
+    self.valid_jd = true;
+    if self.valid_hms {
+      self.jd += i64::from(self.hour * 3_600_000 + self.minute * 60_000)
+        + milliseconds(self.seconds);
+      if self.tz != 0 {
+        self.jd -= i64::from(self.tz) * 60_000;
+        self.valid_ymd = false;
+        self.valid_hms = false;
+        self.tz = 0;
+        self.utc = true;
+        self.local = false;
+      }
+    }
+  }
+
+  /// Computes the date from the julian day number.
+  fn compute_ymd(&mut self) {
+    if self.valid_ymd {
+      return;
+    }
+    if !self.valid_jd {
+      self.year = 2000;
+      self.month = 1;
+      self.day = 1;
+    } else if !(0..=MAX_JULIAN_DAY).contains(&self.jd) {
+      self.set_error();
+      return;
+    } else {
+      let z = ((self.jd + 43_200_000) / DAY) as i32;
+      let alpha = ((f64::from(z) + 32_044.75) / 36_524.25) as i32 - 52;
+      let a = z + 1 + alpha - (alpha + 100) / 4 + 25;
+      let b = a + 1524;
+      let c = ((f64::from(b) - 122.1) / 365.25) as i32;
+      let d = (36525 * (c & 32767)) / 100;
+      let e = (f64::from(b - d) / 30.6001) as i32;
+      let x1 = (30.6001 * f64::from(e)) as i32;
+      self.day = b - d - x1;
+      self.month = if e < 14 { e - 1 } else { e - 13 };
+      self.year = if self.month > 2 { c - 4716 } else { c - 4715 };
+    }
+    self.valid_ymd = true;
+  }
+
+  /// Computes the time of day from the julian day number.
+  fn compute_hms(&mut self) {
+    if self.valid_hms {
+      return;
+    }
+    self.compute_jd();
+    let milliseconds = ((self.jd + 43_200_000) % DAY) as i32;
+    self.seconds = f64::from(milliseconds % 60_000) / 1000.0;
+    let minutes = milliseconds / 60_000;
+    self.minute = minutes % 60;
+    self.hour = minutes / 60;
+    self.raw = false;
+    self.valid_hms = true;
+  }
+
+  fn compute_ymd_hms(&mut self) {
+    self.compute_ymd();
+    self.compute_hms();
+  }
+
+  /// Forgets the date and the time of day, after the julian day number
+  /// changed.
+  fn clear_ymd_hms(&mut self) {
+    self.valid_ymd = false;
+    self.valid_hms = false;
+    self.tz = 0;
+  }
+
+  fn set_error(&mut self) {
+    *self = Self {
+      error: true,
+      ..Self::default()
+    };
+  }
+
+  /// The date, as `YYYY-MM-DD`.
+  fn date(&self) -> String {
+    let sign = if self.year < 0 { "-" } else { "" };
+    format!(
+      "{sign}{:04}-{:02}-{:02}",
+      self.year.abs() % 10_000,
+      self.month,
+      self.day
+    )
+  }
+
+  /// The time of day, as `HH:MM:SS`, or `HH:MM:SS.SSS` with `'subsec'`.
+  fn time(&self) -> String {
+    let time = format!("{:02}:{:02}", self.hour, self.minute);
+    match self.subsec {
+      true => {
+        let milliseconds = milliseconds(self.seconds);
+        format!(
+          "{time}:{:02}.{:03}",
+          milliseconds / 1000 % 100,
+          milliseconds % 1000
+        )
+      }
+      false => format!("{time}:{:02}", self.seconds as i32 % 100),
+    }
+  }
+
+  /// The seconds since the unix epoch, with milliseconds with `'subsec'`.
+  fn unix_seconds(&self) -> Value {
+    match self.subsec {
+      true => Value::Real((self.jd - UNIX_EPOCH) as f64 / 1000.0),
+      false => Value::Integer(self.jd / 1000 - UNIX_EPOCH / 1000),
+    }
+  }
+
+  /// The Thursday of the ISO-8601 week of the date, whose year is the year
+  /// of the week.
+  fn thursday_of_week(&self) -> Self {
+    let mut thursday = *self;
+    thursday.jd += i64::from(3 - self.days_after_monday()) * DAY;
+    thursday.valid_ymd = false;
+    thursday.compute_ymd();
+    thursday
+  }
+
+  /// The day of the year of the date, January 1 being 0.
+  fn days_after_jan01(&self) -> i32 {
+    let mut jan01 = *self;
+    jan01.valid_jd = false;
+    jan01.month = 1;
+    jan01.day = 1;
+    jan01.compute_jd();
+    ((self.jd - jan01.jd + 43_200_000) / DAY) as i32
+  }
+
+  /// The day of the week of the date, Monday being 0.
+  fn days_after_monday(&self) -> i32 {
+    ((self.jd + 43_200_000) / DAY % 7) as i32
+  }
+
+  /// The day of the week of the date, Sunday being 0.
+  fn days_after_sunday(&self) -> i32 {
+    ((self.jd + 129_600_000) / DAY % 7) as i32
+  }
+}
+
+/// Reads fields of digits from the start of `text`, each given as its number
+/// of digits, its smallest and largest values, and the character that must
+/// follow it, if any.
+fn digits<const N: usize>(
+  text: &[u8],
+  fields: [(usize, i32, i32, u8); N],
+) -> Option<[i32; N]> {
+  let mut values = [0; N];
+  let mut idx = 0;
+  for ((width, min, max, next), value) in fields.into_iter().zip(&mut values) {
+    for _ in 0..width {
+      let digit = at(text, idx);
+      if !digit.is_ascii_digit() {
+        return None;
+      }
+      *value = *value * 10 + i32::from(digit - b'0');
+      idx += 1;
+    }
+    if *value < min || *value > max || (next != 0 && at(text, idx) != next) {
+      return None;
+    }
+    idx += 1;
+  }
+  Some(values)
+}
+
+/// The byte at `idx`, or NUL past the end, as C strings read.
+fn at(text: &[u8], idx: usize) -> u8 {
+  text.get(idx).copied().unwrap_or(0)
+}
+
+fn rest(text: &[u8], idx: usize) -> &[u8] {
+  text.get(idx..).unwrap_or_default()
+}
+
+fn skip_spaces(text: &[u8], mut idx: usize) -> usize {
+  while is_space(at(text, idx)) {
+    idx += 1;
+  }
+  idx
+}
+
+/// Whether `byte` is a space to C's `isspace()`.
+fn is_space(byte: u8) -> bool {
+  matches!(byte, b' ' | b'\t'..=b'\r')
+}
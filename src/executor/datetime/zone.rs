@@ -0,0 +1,417 @@
+//! # Local time zone
+//!
+//!  The offset of local time from UTC, found the way the C library finds it:
+//! the `TZ` environment variable names a file of the time zone database, or
+//! holds a POSIX time zone rule, and without it `/etc/localtime` is read.
+//! Without any of those, local time is UTC.
+//!
+//!  The files list the times at which the offset changed, and end with a
+//! rule for the times after the last of those.
+//!
+//! *Reference:* https://man7.org/linux/man-pages/man5/tzfile.5.html
+
+use std::sync::OnceLock;
+
+/// Seconds in a day.
+const DAY: i64 = 86_400;
+
+#[derive(Debug, Default)]
+pub(crate) struct Zone {
+  /// The times, in seconds since the unix epoch, at which the offset
+  /// changed, with the index of the offset in effect from then.
+  transitions: Vec<(i64, usize)>,
+  /// The offsets from UTC, in seconds, with whether they are daylight
+  /// saving time.
+  offsets: Vec<(i64, bool)>,
+  /// The rule for times after the last transition.
+  rule: Option<Rule>,
+}
+
+/// A POSIX time zone rule, as
+/// `std offset [dst [offset] [,start[/time],end[/time]]]`.
+#[derive(Debug)]
+struct Rule {
+  /// The offset of standard time from UTC, in seconds.
+  standard: i64,
+  /// The offset of daylight saving time from UTC, and when it starts and
+  /// ends each year.
+  daylight: Option<(i64, Change, Change)>,
+}
+
+/// The day, and the local time of day in seconds, at which daylight saving
+/// time starts or ends.
+#[derive(Debug, Clone, Copy)]
+struct Change {
+  day: Day,
+  time: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Day {
+  /// `Jn`: the day of the year from 1 to 365, February 29 never counted.
+  Julian(i64),
+  /// `n`: the day of the year from 0 to 365.
+  Ordinal(i64),
+  /// `Mm.w.d`: the day `d` of the week, Sunday being 0, in week `w` from 1
+  /// to 5 of month `m`, 5 being the last.
+  Week { month: i64, week: i64, weekday: i64 },
+}
+
+/// The local time zone, read once.
+pub(crate) fn local() -> &'static Zone {
+  static LOCAL: OnceLock<Zone> = OnceLock::new();
+  LOCAL.get_or_init(|| Zone::named(std::env::var("TZ").ok().as_deref()))
+}
+
+impl Zone {
+  /// The time zone the value of `TZ` names.
+  fn named(tz: Option<&str>) -> Self {
+    let Some(tz) = tz else {
+      return Self::read("/etc/localtime").unwrap_or_default();
+    };
+    let name = tz.strip_prefix(':').unwrap_or(tz);
+    if name.is_empty() {
+      return Self::default();
+    }
+    let path = match name.starts_with('/') {
+      true => name.into(),
+      false => {
+        let directory = std::env::var("TZDIR")
+          .unwrap_or_else(|_| "/usr/share/zoneinfo".into());
+        format!("{directory}/{name}")
+      }
+    };
+    Self::read(&path)
+      .or_else(|| {
+        Some(Self {
+          rule: Some(Rule::parse(name)?),
+          ..Self::default()
+        })
+      })
+      .unwrap_or_default()
+  }
+
+  /// Reads a file of the time zone database.
+  fn read(path: &str) -> Option<Self> {
+    Self::parse(&std::fs::read(path).ok()?)
+  }
+
+  /// Reads the TZif format: a header of counts, then the transitions, their
+  /// offsets and abbreviations. From version 2 on, they follow again with
+  /// 64-bit times, then the rule.
+  fn parse(data: &[u8]) -> Option<Self> {
+    let (mut counts, mut data) = header(data)?;
+    let mut time_size = 4;
+    if data[4] >= b'2' {
+      let skipped = body_length(&counts, 4);
+      (counts, data) = header(data.get(44 + skipped..)?)?;
+      time_size = 8;
+    }
+    let [_, _, _, transitions, offsets, _] = counts;
+    let body = data.get(44..44 + body_length(&counts, time_size))?;
+    let indices = &body[transitions * time_size..];
+    let mut zone = Self::default();
+    for idx in 0..transitions {
+      let bytes = &body[idx * time_size..(idx + 1) * time_size];
+      let time = match time_size {
+        4 => i64::from(i32::from_be_bytes(bytes.try_into().ok()?)),
+        _ => i64::from_be_bytes(bytes.try_into().ok()?),
+      };
+      zone.transitions.push((time, usize::from(indices[idx])));
+    }
+    for offset in indices[transitions..].chunks(6).take(offsets) {
+      let seconds = i32::from_be_bytes(offset[..4].try_into().ok()?);
+      zone.offsets.push((i64::from(seconds), offset[4] != 0));
+    }
+    if zone
+      .transitions
+      .iter()
+      .any(|(_, idx)| *idx >= zone.offsets.len())
+    {
+      return None;
+    }
+    if time_size == 8 {
+      let footer = &data[44 + body.len()..];
+      let footer = footer.strip_prefix(b"\n").unwrap_or_default();
+      let end = footer.iter().position(|&byte| byte == b'\n');
+      zone.rule = end
+        .and_then(|end| core::str::from_utf8(&footer[..end]).ok())
+        .and_then(Rule::parse);
+    }
+    Some(zone)
+  }
+
+  /// The offset of local time from UTC, in seconds, at `time` seconds since
+  /// the unix epoch.
+  pub(crate) fn offset(&self, time: i64) -> i64 {
+    let passed = self.transitions.partition_point(|(at, _)| *at <= time);
+    match (passed, &self.rule) {
+      (_, Some(rule)) if self.transitions.is_empty() => rule.offset(time),
+      // Before the first transition, or without any, the first offset of
+      // standard time applies.
+      (0, _) => self
+        .offsets
+        .iter()
+        .find(|(_, is_dst)| !is_dst)
+        .or(self.offsets.first())
+        .map_or(0, |(offset, _)| *offset),
+      (passed, Some(rule)) if passed == self.transitions.len() => {
+        rule.offset(time)
+      }
+      (passed, _) => self.offsets[self.transitions[passed - 1].1].0,
+    }
+  }
+}
+
+/// The counts of a TZif header: of UT/local indicators, of standard/wall
+/// indicators, of leap seconds, of transitions, of offsets and of bytes of
+/// abbreviations.
+fn header(data: &[u8]) -> Option<([usize; 6], &[u8])> {
+  if data.get(..4)? != b"TZif" {
+    return None;
+  }
+  let mut counts = [0; 6];
+  for (idx, count) in counts.iter_mut().enumerate() {
+    let bytes = data.get(20 + idx * 4..24 + idx * 4)?;
+    *count = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+  }
+  Some((counts, data))
+}
+
+/// The length of the data after a TZif header with `counts`.
+fn body_length(counts: &[usize; 6], time_size: usize) -> usize {
+  let [utc, standard, leaps, transitions, offsets, abbreviations] = *counts;
+  transitions * (time_size + 1)
+    + offsets * 6
+    + abbreviations
+    + leaps * (time_size + 4)
+    + standard
+    + utc
+}
+
+impl Rule {
+  /// Reads a rule such as `EST5EDT,M3.2.0,M11.1.0`, whose offsets are west
+  /// of UTC. Daylight saving time is an hour ahead of standard time unless
+  /// said otherwise, and without dates it follows the rules of the USA.
+  fn parse(text: &str) -> Option<Self> {
+    let mut rest = text.as_bytes();
+    name(&mut rest)?;
+    let standard = -duration(&mut rest)?;
+    if rest.is_empty() {
+      return Some(Self {
+        standard,
+        daylight: None,
+      });
+    }
+    name(&mut rest)?;
+    let daylight = match rest.first() {
+      Some(b',') | None => standard + 3_600,
+      Some(_) => -duration(&mut rest)?,
+    };
+    let (start, end) = match rest {
+      [] => (
+        Change {
+          day: Day::Week {
+            month: 3,
+            week: 2,
+            weekday: 0,
+          },
+          time: 7_200,
+        },
+        Change {
+          day: Day::Week {
+            month: 11,
+            week: 1,
+            weekday: 0,
+          },
+          time: 7_200,
+        },
+      ),
+      [b',', dates @ ..] => {
+        rest = dates;
+        let start = change(&mut rest)?;
+        rest = rest.strip_prefix(b",")?;
+        (start, change(&mut rest)?)
+      }
+      _ => return None,
+    };
+    rest.is_empty().then_some(Self {
+      standard,
+      daylight: Some((daylight, start, end)),
+    })
+  }
+
+  /// The offset the rule gives at `time` seconds since the unix epoch.
+  fn offset(&self, time: i64) -> i64 {
+    let Some((daylight, start, end)) = self.daylight else {
+      return self.standard;
+    };
+    let year = civil_from_days(time.div_euclid(DAY)).0;
+    // The changes happen at local times: the start in standard time and the
+    // end in daylight saving time.
+    let start = start.local_time(year) - self.standard;
+    let end = end.local_time(year) - daylight;
+    let is_daylight = match start < end {
+      true => start <= time && time < end,
+      false => time < end || time >= start,
+    };
+    match is_daylight {
+      true => daylight,
+      false => self.standard,
+    }
+  }
+}
+
+impl Change {
+  /// The time of the change in `year`, in seconds since the unix epoch as if
+  /// local time were UTC.
+  fn local_time(self, year: i64) -> i64 {
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let jan01 = days_from_civil(year, 1, 1);
+    let day = match self.day {
+      Day::Julian(day) if day >= 60 && is_leap => jan01 + day,
+      Day::Julian(day) => jan01 + day - 1,
+      Day::Ordinal(day) => jan01 + day,
+      Day::Week {
+        month,
+        week,
+        weekday,
+      } => {
+        let first = days_from_civil(year, month, 1);
+        let length = match month {
+          12 => days_from_civil(year + 1, 1, 1),
+          _ => days_from_civil(year, month + 1, 1),
+        } - first;
+        // The first such weekday of the month, then as many weeks later as
+        // fit in it.
+        let mut day = (weekday - (first + 4).rem_euclid(7)).rem_euclid(7);
+        for _ in 1..week {
+          if day + 7 >= length {
+            break;
+          }
+          day += 7;
+        }
+        first + day
+      }
+    };
+    day * DAY + self.time
+  }
+}
+
+/// Reads the name of a time zone: letters, or anything between `<` and `>`.
+fn name(text: &mut &[u8]) -> Option<()> {
+  let length = match text.first()? {
+    b'<' => text.iter().position(|&byte| byte == b'>')? + 1,
+    _ => text
+      .iter()
+      .take_while(|byte| byte.is_ascii_alphabetic())
+      .count(),
+  };
+  if length < 3 {
+    return None;
+  }
+  *text = &text[length..];
+  Some(())
+}
+
+/// Reads `[+-]hh[:mm[:ss]]`, as seconds.
+fn duration(text: &mut &[u8]) -> Option<i64> {
+  let sign = match text.first() {
+    Some(b'-') => -1,
+    Some(b'+') => 1,
+    _ => 0,
+  };
+  if sign != 0 {
+    *text = &text[1..];
+  }
+  let mut seconds = 0;
+  for (idx, scale) in [3_600, 60, 1].into_iter().enumerate() {
+    if idx > 0 {
+      match text.strip_prefix(b":") {
+        Some(rest) => *text = rest,
+        None => break,
+      }
+    }
+    let digits = text.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    if digits == 0 {
+      return None;
+    }
+    let value = core::str::from_utf8(&text[..digits])
+      .ok()?
+      .parse::<i64>()
+      .ok()?;
+    seconds += value * scale;
+    *text = &text[digits..];
+  }
+  Some(if sign < 0 { -seconds } else { seconds })
+}
+
+/// Reads a date `Jn`, `n` or `Mm.w.d`, optionally followed by `/time`.
+fn change(text: &mut &[u8]) -> Option<Change> {
+  let number = |text: &mut &[u8]| {
+    let digits = text.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    let value = core::str::from_utf8(&text[..digits]).ok()?.parse().ok();
+    *text = &text[digits..];
+    value
+  };
+  let day = match text.first()? {
+    b'J' => {
+      *text = &text[1..];
+      Day::Julian(number(text).filter(|day| (1..=365).contains(day))?)
+    }
+    b'M' => {
+      *text = &text[1..];
+      let month = number(text).filter(|month| (1..=12).contains(month))?;
+      *text = text.strip_prefix(b".")?;
+      let week = number(text).filter(|week| (1..=5).contains(week))?;
+      *text = text.strip_prefix(b".")?;
+      let weekday = number(text).filter(|day| (0..=6).contains(day))?;
+      Day::Week {
+        month,
+        week,
+        weekday,
+      }
+    }
+    _ => Day::Ordinal(number(text).filter(|day| (0..=365).contains(day))?),
+  };
+  let time = match text.strip_prefix(b"/") {
+    Some(rest) => {
+      *text = rest;
+      duration(text)?
+    }
+    None => 7_200,
+  };
+  Some(Change { day, time })
+}
+
+/// The days since the unix epoch of a date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era =
+    year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day of a day since the unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days - era * 146_097;
+  let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+    - day_of_era / 146_096)
+    / 365;
+  let day_of_year =
+    day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 {
+    shifted_month + 3
+  } else {
+    shifted_month - 9
+  };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
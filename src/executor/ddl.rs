@@ -0,0 +1,1503 @@
+//! # Schema changes
+//!
+//!  Each table, index, view and trigger of a database is described in the
+//! schema table by the SQL text of the statement that created it. `CREATE`
+//! and `DROP` statements add and remove these descriptions, along with the
+//! b-trees of tables and indexes, and `ALTER TABLE` edits their text in
+//! place. Once committed, a schema change bumps the schema cookie of the
+//! database, telling other connections to read the schema again.
+//!
+//!  Renaming a table or a column leaves the bodies of views and triggers
+//! alone, as SQLite does under its `legacy_alter_table` setting.
+//!
+//! *Reference:* https://www.sqlite.org/lang_altertable.html
+
+use super::planner::{unsupported, Planner};
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  Affinity, Record, SqliteRuntime, SqliteSchema, TableCursor, Value,
+};
+use crate::sql::ast::{
+  AlterTable, AlterTableAction, ColumnConstraintKind, ColumnDefinition,
+  CreateIndex, CreateTable, CreateTableBody, CreateTrigger, CreateView, Drop,
+  Expr, ExprKind, ForeignKeyClause, InTarget, Name, ObjectKind, QualifiedName,
+  Select, SortOrder, Span, StatementKind, TableConstraint, TableConstraintKind,
+  TableOptions, TriggerEvent, TriggerTime, TypeName,
+};
+use crate::sql::{is_keyword, tokenize, Parser, TokenKind};
+use crate::SqliteConnection;
+use core::mem;
+
+/// The column types `STRICT` tables accept.
+///
+/// *Reference:* https://www.sqlite.org/stricttables.html
+const STRICT_TYPES: [&str; 6] =
+  ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+
+/// Runs a `CREATE TABLE` statement. The indexes of its `PRIMARY KEY` and
+/// `UNIQUE` constraints are created along with the table, and named
+/// `sqlite_autoindex_TABLE_N`. The query of `CREATE TABLE ... AS SELECT`
+/// reads `parameters`.
+///
+/// *Reference:* https://www.sqlite.org/lang_createtable.html
+pub(super) fn create_table(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  create: &CreateTable,
+  parameters: &[Value],
+) -> SqliteResult<()> {
+  if create.temporary {
+    return Err(unsupported("TEMP table"));
+  }
+  check_schema(&create.name)?;
+  let name = &create.name.name;
+  check_object_name(&name.value)?;
+  let schema = conn.runtime_mut().schema()?;
+  if let Some(entry) = find(&schema, &["table", "view"], &name.value) {
+    if create.if_not_exists {
+      return Ok(());
+    }
+    return Err(SqliteError::Custom(format!(
+      "{} {} already exists",
+      entry.kind(),
+      name.span.text(sql)
+    )));
+  }
+  if find(&schema, &["index"], &name.value).is_some() {
+    return Err(SqliteError::Custom(format!(
+      "there is already an index named {}",
+      name.value
+    )));
+  }
+  let (columns, constraints, options) = match &create.body {
+    CreateTableBody::Columns {
+      columns,
+      constraints,
+      options,
+    } => (columns, constraints, options),
+    CreateTableBody::AsSelect(select) => {
+      return create_table_as(conn, sql, create, select, parameters)
+    }
+  };
+  check_definition(
+    conn.runtime(),
+    sql,
+    &name.value,
+    columns,
+    constraints,
+    *options,
+  )?;
+  let text = format!("CREATE TABLE {}", &sql[name.span.start..]);
+  let runtime = &mut conn.runtime;
+  let root = match options.without_rowid {
+    true => runtime.btree().create_index()?,
+    false => runtime.btree().create_table()?,
+  };
+  runtime.add_schema_entry(&SqliteSchema::new(
+    "table",
+    &name.value,
+    &name.value,
+    root,
+    Some(text.clone()),
+  ))?;
+  let numbers = Planner::new(runtime, &conn.functions, &text)
+    .constraint_indexes(&create.name)?;
+  for number in numbers {
+    let root = runtime.btree().create_index()?;
+    runtime.add_schema_entry(&SqliteSchema::new(
+      "index",
+      format!("sqlite_autoindex_{}_{number}", name.value),
+      &name.value,
+      root,
+      None,
+    ))?;
+  }
+  if is_autoincrement(columns, constraints) {
+    runtime.create_sequence_table()?;
+  }
+  Ok(())
+}
+
+/// Runs a `CREATE TABLE ... AS SELECT` statement: the table has a column
+/// for each result column of the query, with its affinity, and is filled
+/// with its rows. Its SQL text is made up from these columns.
+///
+/// *Reference:* https://www.sqlite.org/lang_createtable.html#create_table_as_select_statements
+fn create_table_as(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  create: &CreateTable,
+  select: &Select,
+  parameters: &[Value],
+) -> SqliteResult<()> {
+  let name = &create.name.name.value;
+  let (plan, affinities) =
+    Planner::new(&mut conn.runtime, &conn.functions, sql)
+      .table_select(select)?;
+  let columns = unique_names(&plan.columns);
+  // Short definitions fit on one line, longer ones have a line per column.
+  let length = columns
+    .iter()
+    .map(|column| identifier_length(column) + 5)
+    .sum::<usize>()
+    + identifier_length(name);
+  let (first, separator, end) = match length < 50 {
+    true => ("", ",", ")"),
+    false => ("\n  ", ",\n  ", "\n)"),
+  };
+  let mut text = format!("CREATE TABLE {}(", identifier(name));
+  for (idx, (column, affinity)) in columns.iter().zip(affinities).enumerate() {
+    text.push_str(if idx == 0 { first } else { separator });
+    text.push_str(&identifier(column));
+    text.push_str(match affinity {
+      Some(Affinity::Text) => " TEXT",
+      Some(Affinity::Numeric) => " NUM",
+      Some(Affinity::Integer) => " INT",
+      Some(Affinity::Real) => " REAL",
+      Some(Affinity::Blob) | None => "",
+    });
+  }
+  text.push_str(end);
+  let runtime = conn.runtime_mut();
+  let root = runtime.btree().create_table()?;
+  runtime.add_schema_entry(&SqliteSchema::new(
+    "table",
+    name,
+    name,
+    root,
+    Some(text),
+  ))?;
+  let mut change = Planner::new(&mut conn.runtime, &conn.functions, sql)
+    .insert_rows(&create.name, plan)?;
+  let subqueries = mem::take(&mut change.subqueries);
+  change.run(&mut Context::new(conn, subqueries, parameters))
+}
+
+/// Runs a `CREATE INDEX` statement, adding the rows the table already has
+/// to the new index.
+///
+/// *Reference:* https://www.sqlite.org/lang_createindex.html
+pub(super) fn create_index(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  create: &CreateIndex,
+) -> SqliteResult<()> {
+  check_schema(&create.name)?;
+  let name = &create.name.name.value;
+  let table_name = &create.table.value;
+  let schema = conn.runtime_mut().schema()?;
+  if is_schema_table(table_name) {
+    return Err(SqliteError::Custom(
+      "table sqlite_master may not be indexed".into(),
+    ));
+  }
+  let Some(table) = find(&schema, &["table", "view"], table_name) else {
+    return Err(SqliteError::Custom(format!(
+      "no such table: main.{table_name}"
+    )));
+  };
+  if is_internal(table.name()) {
+    return Err(SqliteError::Custom(format!(
+      "table {} may not be indexed",
+      table.name()
+    )));
+  }
+  if table.kind() == "view" {
+    return Err(SqliteError::Custom("views may not be indexed".into()));
+  }
+  check_object_name(name)?;
+  if find(&schema, &["table", "view"], name).is_some() {
+    return Err(SqliteError::Custom(format!(
+      "there is already a table named {name}"
+    )));
+  }
+  if find(&schema, &["index"], name).is_some() {
+    if create.if_not_exists {
+      return Ok(());
+    }
+    return Err(SqliteError::Custom(format!("index {name} already exists")));
+  }
+  let text = match create.unique {
+    true => format!(
+      "CREATE UNIQUE INDEX {}",
+      &sql[create.name.name.span.start..]
+    ),
+    false => format!("CREATE INDEX {}", &sql[create.name.name.span.start..]),
+  };
+  let runtime = conn.runtime_mut();
+  let root = runtime.btree().create_index()?;
+  runtime.add_schema_entry(&SqliteSchema::new(
+    "index",
+    name,
+    table.name(),
+    root,
+    Some(text),
+  ))?;
+  let table = QualifiedName {
+    schema: None,
+    name: create.table.clone(),
+  };
+  let mut change = Planner::new(&mut conn.runtime, &conn.functions, sql)
+    .fill_index(&table, root)?;
+  let subqueries = mem::take(&mut change.subqueries);
+  change.run(&mut Context::new(conn, subqueries, &[]))
+}
+
+/// Runs a `CREATE VIEW` statement. As in SQLite, the tables the view reads
+/// are only looked up when it is read.
+///
+/// *Reference:* https://www.sqlite.org/lang_createview.html
+pub(super) fn create_view(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  create: &CreateView,
+) -> SqliteResult<()> {
+  if create.temporary {
+    return Err(unsupported("TEMP view"));
+  }
+  check_schema(&create.name)?;
+  let name = &create.name.name;
+  check_object_name(&name.value)?;
+  let schema = conn.runtime_mut().schema()?;
+  if let Some(entry) = find(&schema, &["table", "view"], &name.value) {
+    if create.if_not_exists {
+      return Ok(());
+    }
+    return Err(SqliteError::Custom(format!(
+      "{} {} already exists",
+      entry.kind(),
+      name.span.text(sql)
+    )));
+  }
+  if find(&schema, &["index"], &name.value).is_some() {
+    return Err(SqliteError::Custom(format!(
+      "there is already an index named {}",
+      name.value
+    )));
+  }
+  let text = format!("CREATE VIEW {}", &sql[name.span.start..]);
+  conn.runtime_mut().add_schema_entry(&SqliteSchema::new(
+    "view",
+    &name.value,
+    &name.value,
+    0,
+    Some(text),
+  ))
+}
+
+/// Runs a `CREATE TRIGGER` statement. Its program is only planned when it
+/// fires.
+///
+/// *Reference:* https://www.sqlite.org/lang_createtrigger.html
+pub(super) fn create_trigger(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  create: &CreateTrigger,
+) -> SqliteResult<()> {
+  if create.temporary {
+    return Err(unsupported("TEMP trigger"));
+  }
+  check_schema(&create.name)?;
+  check_schema(&create.table)?;
+  let name = &create.name.name.value;
+  let table_name = &create.table.name.value;
+  let schema = conn.runtime_mut().schema()?;
+  let table = find(&schema, &["table", "view"], table_name);
+  if table.is_none() && !is_schema_table(table_name) {
+    return Err(SqliteError::Custom(format!(
+      "no such table: main.{table_name}"
+    )));
+  }
+  let Some(table) = table.filter(|table| !is_internal(table.name())) else {
+    return Err(SqliteError::Custom(
+      "cannot create trigger on system table".into(),
+    ));
+  };
+  check_object_name(name)?;
+  if find(&schema, &["trigger"], name).is_some() {
+    if create.if_not_exists {
+      return Ok(());
+    }
+    return Err(SqliteError::Custom(format!(
+      "trigger {name} already exists"
+    )));
+  }
+  let time = match create.time {
+    TriggerTime::Before => "BEFORE",
+    TriggerTime::After => "AFTER",
+    TriggerTime::InsteadOf => "INSTEAD OF",
+  };
+  // Views only have `INSTEAD OF` triggers, which tables may not have.
+  match (table.kind(), create.time) {
+    ("view", TriggerTime::InsteadOf)
+    | ("table", TriggerTime::Before | TriggerTime::After) => {}
+    (kind, _) => {
+      return Err(SqliteError::Custom(format!(
+        "cannot create {time} trigger on {kind}: {}",
+        table.name()
+      )))
+    }
+  }
+  let text = format!("CREATE TRIGGER {}", &sql[create.name.name.span.start..]);
+  let runtime = conn.runtime_mut();
+  runtime.add_schema_entry(&SqliteSchema::new(
+    "trigger",
+    name,
+    table.name(),
+    0,
+    Some(text),
+  ))
+}
+
+/// Runs a `DROP` statement. Dropping a table drops its indexes and triggers
+/// along with it, and frees the pages of its b-trees.
+///
+/// *Reference:* https://www.sqlite.org/lang_droptable.html
+pub(super) fn drop(
+  conn: &mut SqliteConnection,
+  drop: &Drop,
+) -> SqliteResult<()> {
+  check_schema(&drop.name)?;
+  let name = &drop.name.name.value;
+  let full_name = match &drop.name.schema {
+    Some(schema) => format!("{}.{name}", schema.value),
+    None => name.clone(),
+  };
+  let (kinds, what) = match drop.object {
+    ObjectKind::Table => (&["table", "view"][..], "table"),
+    ObjectKind::View => (&["table", "view"][..], "view"),
+    ObjectKind::Index => (&["index"][..], "index"),
+    ObjectKind::Trigger => (&["trigger"][..], "trigger"),
+  };
+  if kinds.contains(&"table") && is_schema_table(name) {
+    return Err(SqliteError::Custom(
+      "table sqlite_master may not be dropped".into(),
+    ));
+  }
+  let runtime = conn.runtime_mut();
+  let schema = runtime.schema()?;
+  let Some(entry) = find(&schema, kinds, name).cloned() else {
+    if drop.if_exists {
+      return Ok(());
+    }
+    return Err(SqliteError::Custom(format!("no such {what}: {full_name}")));
+  };
+  match (drop.object, entry.kind()) {
+    (ObjectKind::Table, "view") => {
+      return Err(SqliteError::Custom(format!(
+        "use DROP VIEW to delete view {}",
+        entry.name()
+      )))
+    }
+    (ObjectKind::View, "table") => {
+      return Err(SqliteError::Custom(format!(
+        "use DROP TABLE to delete table {}",
+        entry.name()
+      )))
+    }
+    (ObjectKind::Table, _)
+      if is_internal(entry.name()) && !is_droppable(entry.name()) =>
+    {
+      return Err(SqliteError::Custom(format!(
+        "table {} may not be dropped",
+        entry.name()
+      )))
+    }
+    (ObjectKind::Index, _) if entry.sql().is_none() => {
+      return Err(SqliteError::Custom(
+        "index associated with UNIQUE or PRIMARY KEY constraint cannot be \
+         dropped"
+          .into(),
+      ))
+    }
+    _ => {}
+  }
+  // The objects of a table or view go with it.
+  let is_dropped = |other: &SqliteSchema| {
+    (other.kind() == entry.kind() && other.name() == entry.name())
+      || (matches!(entry.kind(), "table" | "view")
+        && matches!(other.kind(), "index" | "trigger")
+        && other.tbl_name().eq_ignore_ascii_case(entry.name()))
+  };
+  let mut roots = schema
+    .iter()
+    .filter(|other| is_dropped(other) && other.rootpage() > 0)
+    .map(SqliteSchema::rootpage)
+    .collect::<Vec<_>>();
+  runtime.update_schema(|other| match is_dropped(other) {
+    true => None,
+    false => Some(other.clone()),
+  })?;
+  // Auto-vacuum moves the largest root page in place of a dropped one, so
+  // the largest are dropped first, lest one be moved before its turn.
+  roots.sort_unstable_by(|left, right| right.cmp(left));
+  for root in roots {
+    runtime.drop_btree(root)?;
+  }
+  let sql = entry.sql().unwrap_or_default();
+  if entry.kind() == "table" && is_autoincrement_sql(sql)? {
+    runtime.remove_autoincrement_sequence(entry.name())?;
+  }
+  Ok(())
+}
+
+/// Runs an `ALTER TABLE` statement.
+///
+/// *Reference:* https://www.sqlite.org/lang_altertable.html
+pub(super) fn alter_table(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  alter: &AlterTable,
+) -> SqliteResult<()> {
+  check_schema(&alter.name)?;
+  let name = &alter.name.name.value;
+  let schema = conn.runtime_mut().schema()?;
+  let entry = match find(&schema, &["table", "view"], name) {
+    Some(entry) => entry.clone(),
+    None if is_schema_table(name) => {
+      SqliteSchema::new("table", "sqlite_master", "sqlite_master", 1, None)
+    }
+    None => return Err(SqliteError::Custom(format!("no such table: {name}"))),
+  };
+  let is_view = entry.kind() == "view";
+  if let AlterTableAction::RenameTo(new) = &alter.action {
+    if find(&schema, &["table", "view", "index"], &new.value).is_some() {
+      return Err(SqliteError::Custom(format!(
+        "there is already another table or index with this name: {}",
+        new.value
+      )));
+    }
+  }
+  if let (AlterTableAction::AddColumn(_), true) = (&alter.action, is_view) {
+    return Err(SqliteError::Custom("Cannot add a column to a view".into()));
+  }
+  if is_internal(entry.name()) {
+    return Err(SqliteError::Custom(format!(
+      "table {} may not be altered",
+      entry.name()
+    )));
+  }
+  match &alter.action {
+    AlterTableAction::RenameTo(new) => {
+      check_object_name(&new.value)?;
+      if is_view {
+        return Err(SqliteError::Custom(format!(
+          "view {} may not be altered",
+          entry.name()
+        )));
+      }
+      rename_table(conn, &schema, entry.name(), &new.value)
+    }
+    AlterTableAction::RenameColumn { old, new } => {
+      if is_view {
+        return Err(SqliteError::Custom(format!(
+          "cannot rename columns of view \"{}\"",
+          entry.name()
+        )));
+      }
+      rename_column(conn, sql, &schema, &entry, old, new)
+    }
+    AlterTableAction::AddColumn(column) => {
+      add_column(conn, sql, &entry, column)
+    }
+    AlterTableAction::DropColumn(column) => {
+      if is_view {
+        return Err(SqliteError::Custom(format!(
+          "cannot drop column from view \"{}\"",
+          entry.name()
+        )));
+      }
+      drop_column(conn, sql, &schema, &entry, column)
+    }
+  }
+}
+
+/// Renames the table `table` to `new`. The indexes and triggers of the table
+/// move over to it, and the foreign keys of the tables referring to it are
+/// made to refer to the new name.
+fn rename_table(
+  conn: &mut SqliteConnection,
+  schema: &[SqliteSchema],
+  table: &str,
+  new: &str,
+) -> SqliteResult<()> {
+  let replacement = quoted(new);
+  let autoindex = format!("sqlite_autoindex_{table}_");
+  let mut renamed = vec![];
+  for entry in schema {
+    let is_own = entry.tbl_name().eq_ignore_ascii_case(table);
+    if entry.kind() != "table" && !(is_own && entry.kind() != "view") {
+      continue;
+    }
+    let mut new_entry = entry.clone();
+    if let Some(sql) = entry.sql() {
+      let spans = table_references(&parse_object(sql)?, table);
+      let edits = spans.into_iter().map(|span| (span, replacement.clone()));
+      new_entry.set_sql(edit(sql, edits.collect()));
+    }
+    if is_own {
+      new_entry.set_tbl_name(new);
+      if entry.kind() == "table" {
+        new_entry.set_name(new);
+      }
+      if let Some(number) = entry.name().strip_prefix(&autoindex) {
+        new_entry.set_name(format!("sqlite_autoindex_{new}_{number}"));
+      }
+    }
+    if new_entry != *entry {
+      renamed.push((entry.clone(), new_entry));
+    }
+  }
+  let runtime = conn.runtime_mut();
+  replace_entries(runtime, renamed)?;
+  runtime.rename_autoincrement_sequence(table, new)
+}
+
+/// Renames the column `old` of the table of `entry` to `new`, wherever the
+/// schema refers to it: in the table definition, in the foreign keys of
+/// other tables, in the indexes of the table and in the `UPDATE OF` clauses
+/// of its triggers.
+fn rename_column(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  schema: &[SqliteSchema],
+  entry: &SqliteSchema,
+  old: &Name,
+  new: &Name,
+) -> SqliteResult<()> {
+  let table = entry.name();
+  let definition = conn.runtime_mut().table(table)?.definition().clone();
+  let Some(idx) = definition.column_index(&old.value) else {
+    return Err(SqliteError::Custom(format!(
+      "no such column: \"{}\"",
+      old.span.text(sql)
+    )));
+  };
+  let column = definition.columns()[idx].name().to_owned();
+  let mut renamed = vec![];
+  for other in schema {
+    let is_own = other.tbl_name().eq_ignore_ascii_case(table);
+    let Some(other_sql) = other.sql() else {
+      continue;
+    };
+    if other.kind() != "table" && !(is_own && other.kind() != "view") {
+      continue;
+    }
+    let spans = column_references(&parse_object(other_sql)?, table, &column);
+    if spans.is_empty() {
+      continue;
+    }
+    // The new name is quoted when it was, or when the name it replaces was.
+    let edits = spans
+      .into_iter()
+      .map(|span| {
+        let is_quoted = !other_sql
+          .as_bytes()
+          .get(span.start)
+          .copied()
+          .is_some_and(is_id_char);
+        let text = match new.quoted || is_quoted {
+          true => quoted(&new.value),
+          false => new.value.clone(),
+        };
+        (span, text)
+      })
+      .collect();
+    let mut new_entry = other.clone();
+    new_entry.set_sql(edit(other_sql, edits));
+    renamed.push((other.clone(), new_entry));
+  }
+  let new_sql = renamed
+    .iter()
+    .find(|(old, _)| old == entry)
+    .map_or(entry.sql(), |(_, new)| new.sql())
+    .unwrap_or_default()
+    .to_owned();
+  replace_entries(conn.runtime_mut(), renamed)?;
+  let (_, columns, constraints, options) = parse_table(&new_sql)?;
+  check_definition(
+    conn.runtime(),
+    &new_sql,
+    table,
+    &columns,
+    &constraints,
+    options,
+  )
+  .and_then(|()| check_table(conn, table, &new_sql))
+  .map_err(|error| in_object("table", table, "rename", error))
+}
+
+/// Adds the column `column` to the table of `entry`. Its definition is
+/// appended to that of the table, and the rows of the table are left as
+/// they are: the rows without a value for the column read as its default.
+fn add_column(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  entry: &SqliteSchema,
+  column: &ColumnDefinition,
+) -> SqliteResult<()> {
+  let table = entry.name();
+  let table_sql = entry.sql().unwrap_or_default();
+  let offset = column_list(table_sql)?.end;
+  let new_sql = format!(
+    "{}, {}{}",
+    &table_sql[..offset],
+    &sql[column.name.span.start..],
+    &table_sql[offset..]
+  );
+  let (_, columns, constraints, options) = parse_table(&new_sql)?;
+  check_definition(
+    conn.runtime(),
+    &new_sql,
+    table,
+    &columns,
+    &constraints,
+    options,
+  )?;
+  let kinds = column.constraints.iter().map(|c| &c.kind);
+  if kinds
+    .clone()
+    .any(|c| matches!(c, ColumnConstraintKind::PrimaryKey { .. }))
+  {
+    return Err(SqliteError::Custom(
+      "Cannot add a PRIMARY KEY column".into(),
+    ));
+  }
+  if kinds
+    .clone()
+    .any(|c| matches!(c, ColumnConstraintKind::Unique(_)))
+  {
+    return Err(SqliteError::Custom("Cannot add a UNIQUE column".into()));
+  }
+  let has_default = kinds
+    .clone()
+    .any(|c| matches!(c, ColumnConstraintKind::Default(_)));
+  let generated = kinds.clone().find_map(|c| match c {
+    ColumnConstraintKind::Generated { stored, .. } => Some(*stored),
+    _ => None,
+  });
+  let mut new_entry = entry.clone();
+  new_entry.set_sql(new_sql.clone());
+  let runtime = conn.runtime_mut();
+  replace_entries(runtime, vec![(entry.clone(), new_entry)])?;
+  let mut cursor = runtime.table(table)?;
+  let added = cursor.definition().columns()[columns.len() - 1].clone();
+  match generated {
+    Some(true) => {
+      return Err(SqliteError::Custom("cannot add a STORED column".into()))
+    }
+    Some(false) => {}
+    // The rows read the default value of the column from its definition,
+    // so it has to be a literal.
+    None if has_default && added.default().is_none() => {
+      return Err(SqliteError::Custom(
+        "Cannot add a column with non-constant default".into(),
+      ))
+    }
+    None => {
+      let is_null = added.default().map_or(true, Value::is_null);
+      if added.is_not_null() && is_null && cursor.first(&mut runtime.btree())? {
+        return Err(SqliteError::Custom(
+          "Cannot add a NOT NULL column with default value NULL".into(),
+        ));
+      }
+    }
+  }
+  check_table(conn, table, &new_sql)
+    .map_err(|error| in_object("table", table, "add column", error))
+}
+
+/// Drops the column `column` from the table of `entry`, rewriting every row
+/// of the table without it. Columns that are part of a key, or that other
+/// parts of the schema refer to, may not be dropped.
+fn drop_column(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  schema: &[SqliteSchema],
+  entry: &SqliteSchema,
+  column: &Name,
+) -> SqliteResult<()> {
+  let table = entry.name();
+  let table_sql = entry.sql().unwrap_or_default();
+  let (_, columns, constraints, _) = parse_table(table_sql)?;
+  let Some(idx) = columns.iter().position(|c| c.name.is(&column.value)) else {
+    return Err(SqliteError::Custom(format!(
+      "no such column: \"{}\"",
+      column.span.text(sql)
+    )));
+  };
+  let name = &columns[idx].name.value;
+  let kinds = columns[idx].constraints.iter().map(|c| &c.kind);
+  let is_primary_key = kinds
+    .clone()
+    .any(|c| matches!(c, ColumnConstraintKind::PrimaryKey { .. }))
+    || constraints.iter().any(|constraint| match &constraint.kind {
+      TableConstraintKind::PrimaryKey { columns, .. } => columns
+        .iter()
+        .any(|key| column_name(&key.expr).is_some_and(|key| key.is(name))),
+      _ => false,
+    });
+  if is_primary_key {
+    return Err(SqliteError::Custom(format!(
+      "cannot drop PRIMARY KEY column: \"{name}\""
+    )));
+  }
+  if kinds
+    .clone()
+    .any(|c| matches!(c, ColumnConstraintKind::Unique(_)))
+  {
+    return Err(SqliteError::Custom(format!(
+      "cannot drop UNIQUE column: \"{name}\""
+    )));
+  }
+  if columns.len() == 1 {
+    return Err(SqliteError::Custom(format!(
+      "cannot drop column \"{name}\": no other columns exist"
+    )));
+  }
+  // A column is removed up to the next one, the last one from the comma
+  // before it.
+  let list = column_list(table_sql)?;
+  let removed = match columns.get(idx + 1) {
+    Some(next) => Span::new(columns[idx].name.span.start, next.name.span.start),
+    None => Span::new(list.commas[idx - 1], list.end),
+  };
+  let new_sql = edit(table_sql, vec![(removed, String::new())]);
+  let runtime = conn.runtime_mut();
+  let old = runtime.table(table)?;
+  let mut new_entry = entry.clone();
+  new_entry.set_sql(new_sql.clone());
+  replace_entries(runtime, vec![(entry.clone(), new_entry)])?;
+  let (_, columns_after, constraints_after, options) = parse_table(&new_sql)?;
+  check_definition(
+    conn.runtime(),
+    &new_sql,
+    table,
+    &columns_after,
+    &constraints_after,
+    options,
+  )
+  .and_then(|()| check_table(conn, table, &new_sql))
+  .map_err(|error| in_object("table", table, "drop column", error))?;
+  for index in schema {
+    if index.kind() != "index" || !index.tbl_name().eq_ignore_ascii_case(table)
+    {
+      continue;
+    }
+    let Some(index_sql) = index.sql() else {
+      continue;
+    };
+    let StatementKind::CreateIndex(create) = parse_object(index_sql)? else {
+      continue;
+    };
+    let mut exprs = create.columns.iter().map(|c| &c.expr).collect::<Vec<_>>();
+    exprs.extend(create.where_clause.as_ref());
+    let mut missing = None;
+    for expr in exprs {
+      expr.walk(&mut |expr| {
+        if let ExprKind::Column {
+          table: t,
+          column: c,
+          ..
+        } = &expr.kind
+        {
+          if missing.is_none()
+            && c.is(name)
+            && t.as_ref().map_or(true, |t| t.is(table))
+          {
+            missing = Some(match t {
+              Some(t) => format!("{}.{}", t.value, c.value),
+              None => c.value.clone(),
+            });
+          }
+        }
+      });
+    }
+    if let Some(missing) = missing {
+      return Err(SqliteError::Custom(format!(
+        "error in index {} after drop column: no such column: {missing}",
+        index.name()
+      )));
+    }
+  }
+  drop_column_values(conn, old, idx)
+}
+
+/// Writes the rows of the table of `old` again, without the column at `idx`
+/// which has been dropped from the table.
+fn drop_column_values(
+  conn: &mut SqliteConnection,
+  mut old: TableCursor,
+  idx: usize,
+) -> SqliteResult<()> {
+  let runtime = conn.runtime_mut();
+  let new = runtime.table(old.definition().name())?;
+  let definition = new.definition();
+  let mut btree = runtime.btree();
+  let mut rows = vec![];
+  let mut has_row = old.first(&mut btree)?;
+  while has_row {
+    let mut row = old.row(&mut btree)?;
+    row.remove(idx);
+    rows.push((old.rowid(&btree)?, row));
+    has_row = old.next(&mut btree)?;
+  }
+  let alias = definition.rowid_alias();
+  let key_info = definition.key_info();
+  let key_len = definition.primary_key().len();
+  for (rowid, row) in rows {
+    let record = definition
+      .storage_order()
+      .into_iter()
+      .map(|column| match Some(column) == alias {
+        true => Value::Null,
+        false => row[column].clone(),
+      })
+      .collect::<Vec<_>>();
+    match rowid {
+      Some(rowid) => {
+        btree.insert(new.root(), rowid, &Record::encode(&record))?
+      }
+      None => {
+        btree.delete_index(new.root(), &record[..key_len], &key_info)?;
+        btree.insert_index(new.root(), &record, &key_info)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Checks the columns and constraints of the table `name` declared in `sql`,
+/// as SQLite does while it parses a `CREATE TABLE` statement. Its
+/// expressions are checked once it is in the schema.
+fn check_definition(
+  runtime: &SqliteRuntime,
+  sql: &str,
+  name: &str,
+  columns: &[ColumnDefinition],
+  constraints: &[TableConstraint],
+  options: TableOptions,
+) -> SqliteResult<()> {
+  let mut has_primary_key = false;
+  let mut primary_key = |autoincrement: bool, alias: bool| {
+    if mem::replace(&mut has_primary_key, true) {
+      return Err(SqliteError::Custom(format!(
+        "table \"{name}\" has more than one primary key"
+      )));
+    }
+    if autoincrement && !alias {
+      return Err(SqliteError::Custom(
+        "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY".into(),
+      ));
+    }
+    Ok(())
+  };
+  for (idx, column) in columns.iter().enumerate() {
+    let column_name = &column.name.value;
+    if columns[..idx]
+      .iter()
+      .any(|other| other.name.is(column_name))
+    {
+      return Err(SqliteError::Custom(format!(
+        "duplicate column name: {column_name}"
+      )));
+    }
+    for constraint in column.constraints.iter() {
+      match &constraint.kind {
+        ColumnConstraintKind::PrimaryKey {
+          order,
+          autoincrement,
+          ..
+        } => primary_key(
+          *autoincrement,
+          is_integer(column.type_name.as_ref())
+            && *order != Some(SortOrder::Desc),
+        )?,
+        ColumnConstraintKind::Collate(collation)
+          if runtime.collation(&collation.value).is_none() =>
+        {
+          return Err(SqliteError::Custom(format!(
+            "no such collation sequence: {}",
+            collation.value
+          )))
+        }
+        ColumnConstraintKind::Default(expr) if !is_constant(expr) => {
+          return Err(SqliteError::Custom(format!(
+            "default value of column [{column_name}] is not constant"
+          )))
+        }
+        ColumnConstraintKind::ForeignKey(clause)
+          if clause.columns.len() > 1 =>
+        {
+          return Err(SqliteError::Custom(format!(
+            "foreign key on {column_name} should reference only one column of \
+             table {}",
+            clause.table.span.text(sql)
+          )))
+        }
+        _ => {}
+      }
+    }
+  }
+  let mut autoincrement = is_autoincrement(columns, constraints);
+  for constraint in constraints.iter() {
+    match &constraint.kind {
+      TableConstraintKind::PrimaryKey {
+        columns: key,
+        autoincrement: auto,
+        ..
+      } => {
+        let alias = match key.as_slice() {
+          [key] => {
+            column_name(&key.expr)
+              .and_then(|name| columns.iter().find(|c| c.name.is(&name.value)))
+              .is_some_and(|column| is_integer(column.type_name.as_ref()))
+              && key.order != Some(SortOrder::Desc)
+          }
+          _ => false,
+        };
+        primary_key(*auto, alias)?;
+        autoincrement |= *auto;
+      }
+      TableConstraintKind::ForeignKey {
+        columns: local,
+        clause,
+      } => {
+        if let Some(unknown) = local
+          .iter()
+          .find(|local| !columns.iter().any(|c| c.name.is(&local.value)))
+        {
+          return Err(SqliteError::Custom(format!(
+            "unknown column \"{}\" in foreign key definition",
+            unknown.value
+          )));
+        }
+        if !clause.columns.is_empty() && clause.columns.len() != local.len() {
+          return Err(SqliteError::Custom(
+            "number of columns in foreign key does not match the number of \
+             columns in the referenced table"
+              .into(),
+          ));
+        }
+      }
+      _ => {}
+    }
+  }
+  if options.strict {
+    for column in columns.iter() {
+      let Some(type_name) = &column.type_name else {
+        return Err(SqliteError::Custom(format!(
+          "missing datatype for {name}.{}",
+          column.name.value
+        )));
+      };
+      let declared = declared_type(type_name);
+      if !STRICT_TYPES
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(&declared))
+      {
+        return Err(SqliteError::Custom(format!(
+          "unknown datatype for {name}.{}: \"{declared}\"",
+          column.name.value
+        )));
+      }
+    }
+  }
+  if options.without_rowid {
+    if autoincrement {
+      return Err(SqliteError::Custom(
+        "AUTOINCREMENT not allowed on WITHOUT ROWID tables".into(),
+      ));
+    }
+    if !has_primary_key {
+      return Err(SqliteError::Custom(format!(
+        "PRIMARY KEY missing on table {name}"
+      )));
+    }
+  }
+  let is_generated = |column: &ColumnDefinition| {
+    column
+      .constraints
+      .iter()
+      .any(|c| matches!(c.kind, ColumnConstraintKind::Generated { .. }))
+  };
+  if columns.iter().all(is_generated) {
+    return Err(SqliteError::Custom(
+      "must have at least one non-generated column".into(),
+    ));
+  }
+  Ok(())
+}
+
+/// Checks the expressions of the constraints of the table `table`, now
+/// that its definition `sql` is in the schema.
+fn check_table(
+  conn: &mut SqliteConnection,
+  table: &str,
+  sql: &str,
+) -> SqliteResult<()> {
+  let name = QualifiedName {
+    schema: None,
+    name: Name {
+      value: table.to_owned(),
+      quoted: false,
+      span: Span::default(),
+    },
+  };
+  Planner::new(&mut conn.runtime, &conn.functions, sql)
+    .constraint_indexes(&name)?;
+  Ok(())
+}
+
+/// Replaces each schema entry by its new version.
+fn replace_entries(
+  runtime: &mut crate::runtime::SqliteRuntime,
+  replaced: Vec<(SqliteSchema, SqliteSchema)>,
+) -> SqliteResult<()> {
+  runtime.update_schema(|entry| {
+    let new = replaced
+      .iter()
+      .find(|(old, _)| old == entry)
+      .map_or(entry, |(_, new)| new);
+    Some(new.clone())
+  })
+}
+
+/// The byte offsets of the column list of a `CREATE TABLE` statement: the
+/// commas between its items, and where its columns end, which is the comma
+/// before the first table constraint or else the closing parenthesis.
+struct ColumnList {
+  commas: Vec<usize>,
+  end: usize,
+}
+
+fn column_list(sql: &str) -> SqliteResult<ColumnList> {
+  let (_, columns, _, _) = parse_table(sql)?;
+  let start = columns.first().map_or(0, |column| column.name.span.start);
+  let mut depth = 0;
+  let mut commas = vec![];
+  for token in tokenize(sql)? {
+    if token.span.start < start {
+      continue;
+    }
+    match token.kind {
+      TokenKind::LeftParen => depth += 1,
+      TokenKind::RightParen if depth == 0 => {
+        let end = commas.get(columns.len() - 1).copied();
+        return Ok(ColumnList {
+          end: end.unwrap_or(token.span.start),
+          commas,
+        });
+      }
+      TokenKind::RightParen => depth -= 1,
+      TokenKind::Comma if depth == 0 => commas.push(token.span.start),
+      _ => {}
+    }
+  }
+  Err(malformed(sql))
+}
+
+/// The spans of the names of the table `table` in the definition of a
+/// schema object: those of the table itself, of the table an index or
+/// trigger is on, and of the table a foreign key refers to.
+fn table_references(statement: &StatementKind, table: &str) -> Vec<Span> {
+  let mut spans = vec![];
+  match statement {
+    StatementKind::CreateTable(create) => {
+      if create.name.name.is(table) {
+        spans.push(create.name.name.span);
+      }
+      for clause in foreign_keys(create) {
+        if clause.table.is(table) {
+          spans.push(clause.table.span);
+        }
+      }
+    }
+    StatementKind::CreateIndex(index) if index.table.is(table) => {
+      spans.push(index.table.span)
+    }
+    StatementKind::CreateTrigger(trigger) if trigger.table.name.is(table) => {
+      spans.push(trigger.table.name.span)
+    }
+    _ => {}
+  }
+  spans
+}
+
+/// The spans of the names of the column `column` of the table `table` in
+/// the definition of a schema object.
+fn column_references(
+  statement: &StatementKind,
+  table: &str,
+  column: &str,
+) -> Vec<Span> {
+  let mut spans = vec![];
+  let in_expr = |expr: &Expr, spans: &mut Vec<Span>| {
+    expr.walk(&mut |expr| {
+      if let ExprKind::Column {
+        table: qualifier,
+        column: name,
+        ..
+      } = &expr.kind
+      {
+        if name.is(column) && qualifier.as_ref().map_or(true, |t| t.is(table)) {
+          spans.push(name.span);
+        }
+      }
+    })
+  };
+  let in_names = |names: &[Name], spans: &mut Vec<Span>| {
+    spans.extend(names.iter().filter(|name| name.is(column)).map(|n| n.span))
+  };
+  match statement {
+    StatementKind::CreateTable(create) => {
+      let is_own = create.name.name.is(table);
+      for clause in foreign_keys(create) {
+        if clause.table.is(table) {
+          in_names(&clause.columns, &mut spans);
+        }
+      }
+      let CreateTableBody::Columns {
+        columns,
+        constraints,
+        ..
+      } = &create.body
+      else {
+        return spans;
+      };
+      if !is_own {
+        return spans;
+      }
+      for definition in columns.iter() {
+        if definition.name.is(column) {
+          spans.push(definition.name.span);
+        }
+        for constraint in definition.constraints.iter() {
+          match &constraint.kind {
+            ColumnConstraintKind::Check(expr)
+            | ColumnConstraintKind::Generated { expr, .. } => {
+              in_expr(expr, &mut spans)
+            }
+            _ => {}
+          }
+        }
+      }
+      for constraint in constraints.iter() {
+        match &constraint.kind {
+          TableConstraintKind::PrimaryKey { columns, .. }
+          | TableConstraintKind::Unique { columns, .. } => {
+            for key in columns.iter() {
+              in_expr(&key.expr, &mut spans);
+            }
+          }
+          TableConstraintKind::Check(expr) => in_expr(expr, &mut spans),
+          TableConstraintKind::ForeignKey { columns, .. } => {
+            in_names(columns, &mut spans)
+          }
+        }
+      }
+    }
+    StatementKind::CreateIndex(index) if index.table.is(table) => {
+      for key in index.columns.iter() {
+        in_expr(&key.expr, &mut spans);
+      }
+      if let Some(condition) = &index.where_clause {
+        in_expr(condition, &mut spans);
+      }
+    }
+    StatementKind::CreateTrigger(trigger) if trigger.table.name.is(table) => {
+      if let TriggerEvent::Update(columns) = &trigger.event {
+        in_names(columns, &mut spans);
+      }
+    }
+    _ => {}
+  }
+  spans
+}
+
+/// The foreign key clauses of a `CREATE TABLE` statement.
+fn foreign_keys(create: &CreateTable) -> Vec<&ForeignKeyClause> {
+  let CreateTableBody::Columns {
+    columns,
+    constraints,
+    ..
+  } = &create.body
+  else {
+    return vec![];
+  };
+  let column_keys = columns
+    .iter()
+    .flat_map(|column| column.constraints.iter())
+    .filter_map(|constraint| match &constraint.kind {
+      ColumnConstraintKind::ForeignKey(clause) => Some(clause),
+      _ => None,
+    });
+  let table_keys =
+    constraints
+      .iter()
+      .filter_map(|constraint| match &constraint.kind {
+        TableConstraintKind::ForeignKey { clause, .. } => Some(clause),
+        _ => None,
+      });
+  column_keys.chain(table_keys).collect()
+}
+
+/// `sql` with the text of each span replaced. Spans may not overlap.
+fn edit(sql: &str, mut edits: Vec<(Span, String)>) -> String {
+  edits.sort_by_key(|(span, _)| span.start);
+  edits.dedup_by_key(|(span, _)| span.start);
+  let mut edited = String::with_capacity(sql.len());
+  let mut position = 0;
+  for (span, text) in edits {
+    edited.push_str(&sql[position..span.start]);
+    edited.push_str(&text);
+    position = span.end;
+  }
+  edited.push_str(&sql[position..]);
+  edited
+}
+
+/// Parses the SQL text of a schema object.
+pub(super) fn parse_object(sql: &str) -> SqliteResult<StatementKind> {
+  Parser::new(sql)?
+    .next_statement()?
+    .map(|statement| statement.kind)
+    .ok_or_else(|| malformed(sql))
+}
+
+/// Parses the SQL text of a table.
+pub(super) fn parse_table(
+  sql: &str,
+) -> SqliteResult<(
+  Name,
+  Vec<ColumnDefinition>,
+  Vec<TableConstraint>,
+  TableOptions,
+)> {
+  let StatementKind::CreateTable(create) = parse_object(sql)? else {
+    return Err(malformed(sql));
+  };
+  match create.body {
+    CreateTableBody::Columns {
+      columns,
+      constraints,
+      options,
+    } => Ok((create.name.name, columns, constraints, options)),
+    CreateTableBody::AsSelect(_) => Err(malformed(sql)),
+  }
+}
+
+fn malformed(sql: &str) -> SqliteError {
+  SqliteError::Corrupt(format!("Malformed schema statement: {sql}"))
+}
+
+/// Turns an error in the schema object `name`, which a change made invalid,
+/// into one that says so.
+fn in_object(
+  kind: &str,
+  name: &str,
+  change: &str,
+  error: SqliteError,
+) -> SqliteError {
+  match error {
+    SqliteError::Custom(message) => SqliteError::Custom(format!(
+      "error in {kind} {name} after {change}: {message}"
+    )),
+    error => error,
+  }
+}
+
+/// Objects of the schema of the given `kinds` named `name`.
+pub(super) fn find<'a>(
+  schema: &'a [SqliteSchema],
+  kinds: &[&str],
+  name: &str,
+) -> Option<&'a SqliteSchema> {
+  schema.iter().find(|entry| {
+    kinds.contains(&entry.kind()) && entry.name().eq_ignore_ascii_case(name)
+  })
+}
+
+pub(super) fn check_schema(name: &QualifiedName) -> SqliteResult<()> {
+  match name.schema.as_ref().filter(|schema| !schema.is("main")) {
+    Some(schema) => Err(SqliteError::Custom(format!(
+      "unknown database {}",
+      schema.value
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// Fails when `name` is reserved for the objects SQLite creates itself.
+fn check_object_name(name: &str) -> SqliteResult<()> {
+  match is_internal(name) {
+    true => Err(SqliteError::Custom(format!(
+      "object name reserved for internal use: {name}"
+    ))),
+    false => Ok(()),
+  }
+}
+
+/// Whether `name` starts with `sqlite_`, like the objects SQLite creates.
+fn is_internal(name: &str) -> bool {
+  name
+    .get(..7)
+    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("sqlite_"))
+}
+
+/// Whether `name` is one of the internal tables that may be dropped.
+fn is_droppable(name: &str) -> bool {
+  let name = name.to_ascii_lowercase();
+  name.starts_with("sqlite_stat") || name == "sqlite_parameters"
+}
+
+fn is_schema_table(name: &str) -> bool {
+  name.eq_ignore_ascii_case("sqlite_master")
+    || name.eq_ignore_ascii_case("sqlite_schema")
+}
+
+/// Whether the declared type is `INTEGER`, which makes a primary key an
+/// alias of the rowid.
+fn is_integer(type_name: Option<&TypeName>) -> bool {
+  type_name.is_some_and(|type_name| {
+    type_name.name.eq_ignore_ascii_case("INTEGER")
+      && type_name.arguments.is_empty()
+  })
+}
+
+fn declared_type(type_name: &TypeName) -> String {
+  match type_name.arguments.is_empty() {
+    true => type_name.name.clone(),
+    false => format!("{}({})", type_name.name, type_name.arguments.join(",")),
+  }
+}
+
+fn is_autoincrement(
+  columns: &[ColumnDefinition],
+  constraints: &[TableConstraint],
+) -> bool {
+  let column_keys = columns
+    .iter()
+    .flat_map(|column| column.constraints.iter())
+    .any(|constraint| {
+      matches!(
+        constraint.kind,
+        ColumnConstraintKind::PrimaryKey {
+          autoincrement: true,
+          ..
+        }
+      )
+    });
+  column_keys
+    || constraints.iter().any(|constraint| {
+      matches!(
+        constraint.kind,
+        TableConstraintKind::PrimaryKey {
+          autoincrement: true,
+          ..
+        }
+      )
+    })
+}
+
+/// Whether the table of `sql` has an `AUTOINCREMENT` primary key. The word
+/// is a keyword, so it can only appear as such.
+fn is_autoincrement_sql(sql: &str) -> SqliteResult<bool> {
+  Ok(
+    tokenize(sql)?
+      .iter()
+      .any(|token| token.is_keyword("AUTOINCREMENT")),
+  )
+}
+
+/// The column `expr` names, with its `COLLATE` clause.
+fn column_name(expr: &Expr) -> Option<&Name> {
+  match &expr.kind {
+    ExprKind::Column {
+      table: None,
+      column,
+      ..
+    } => Some(column),
+    ExprKind::Collate { expr, .. } => column_name(expr),
+    _ => None,
+  }
+}
+
+/// Whether `expr` reads no column, parameter or subquery, as the `DEFAULT`
+/// of a column may not. Bare `TRUE` and `FALSE` are literals there.
+fn is_constant(expr: &Expr) -> bool {
+  let mut constant = true;
+  expr.walk(&mut |expr| match &expr.kind {
+    ExprKind::Column {
+      table: None,
+      column,
+      ..
+    } if !column.quoted && (column.is("true") || column.is("false")) => {}
+    ExprKind::Column { .. }
+    | ExprKind::Variable(_)
+    | ExprKind::Exists(_)
+    | ExprKind::Subquery(_)
+    | ExprKind::In {
+      target: InTarget::Select(_),
+      ..
+    } => constant = false,
+    _ => {}
+  });
+  constant
+}
+
+/// The names of the columns of a table made from a query with these result
+/// column names. Names taken by an earlier column get a `:N` suffix, in
+/// place of any they had.
+fn unique_names(names: &[String]) -> Vec<String> {
+  let mut unique: Vec<String> = vec![];
+  for name in names {
+    let mut candidate = name.clone();
+    let mut count = 0;
+    while unique
+      .iter()
+      .any(|other| other.eq_ignore_ascii_case(&candidate))
+    {
+      let base = match name.rfind(':') {
+        Some(idx)
+          if idx > 0 && name[idx + 1..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+          &name[..idx]
+        }
+        _ => name.as_str(),
+      };
+      count += 1;
+      candidate = format!("{base}:{count}");
+    }
+    unique.push(candidate);
+  }
+  unique
+}
+
+/// `name` as an identifier in double quotes.
+fn quoted(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `name` as an identifier, in double quotes unless it reads as one
+/// without them.
+fn identifier(name: &str) -> String {
+  let is_bare = name.bytes().next().is_some_and(|b| !b.is_ascii_digit())
+    && name.bytes().all(is_id_char)
+    && !is_keyword(name);
+  match is_bare {
+    true => name.to_owned(),
+    false => quoted(name),
+  }
+}
+
+/// The length of `name` once quoted, which SQLite sizes the text of the
+/// tables made from queries by.
+fn identifier_length(name: &str) -> usize {
+  name.len() + name.bytes().filter(|&b| b == b'"').count() + 2
+}
+
+fn is_id_char(byte: u8) -> bool {
+  byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || byte >= 0x80
+}
@@ -0,0 +1,2237 @@
+//! # Data changes
+//!
+//!  `INSERT`, `UPDATE` and `DELETE` statements first read every row they
+//! change through the operators of their plan, and only then write them, so
+//! the changes never disturb the scans finding them. Each row written goes
+//! to the b-tree of its table and to every index of the table, after the
+//! constraints of the table are checked: `NOT NULL`, the types of `STRICT`
+//! tables, `CHECK`, then uniqueness.
+//!
+//!  The `BEFORE` triggers of the table fire for each row before it is
+//! written, and its `AFTER` triggers once it is. A statement on a view
+//! runs its `INSTEAD OF` triggers instead.
+//!
+//!  A failed constraint is resolved as the `OR` clause of the statement
+//! says, else as the `ON CONFLICT` clause of the constraint, else by
+//! aborting the statement.
+//!
+//! *Reference:* https://www.sqlite.org/lang_insert.html,
+//! https://www.sqlite.org/lang_conflict.html
+
+use super::ddl::parse_table;
+use super::expr::Expr;
+use super::foreign_key::{ForeignKey, ForeignKeys};
+use super::join::JoinType;
+use super::operator::{Operator, Project, Values};
+use super::planner::{
+  malformed_table, no_such_table, prohibit_subqueries, table_source,
+  uncollated, unsupported, FromTable, Input, Plan, Planner, Query, Source,
+  ROWID_NAMES,
+};
+use super::sorter::SortKey;
+use super::subquery::Subquery;
+use super::trigger::{Event, Triggers, ViewChange};
+use super::value::is_true;
+use super::vdbe::{self, Builder, Codegen, Opcode, Unsupported, Vdbe};
+use super::Context;
+use crate::debug;
+use crate::result::{ConstraintKind, SqliteError, SqliteResult};
+use crate::runtime::{
+  Affinity, BtreeCursor, ColumnDefinition, KeyColumn, KeyInfo, Record,
+  SqliteBtree, TableCursor, TableDefinition, Value,
+};
+use crate::sql::ast::{
+  self, ColumnConstraintKind, ConflictResolution, CreateTableBody, Delete,
+  ExprKind, ForeignKeyAction, IndexedColumn, Insert, InsertSource, Name,
+  NullsOrder, OrderingTerm, QualifiedName, RaiseAction, SortOrder, Span,
+  StatementKind, TableConstraintKind, TriggerTime, Update,
+};
+use crate::sql::Parser;
+use core::{iter, mem};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+
+/// A planned `INSERT`, `UPDATE` or `DELETE` statement: a change of a table,
+/// or of a view through its `INSTEAD OF` triggers.
+#[derive(Debug)]
+pub(crate) enum Write {
+  Table(Box<Change>),
+  View(ViewChange),
+}
+
+/// A planned `INSERT`, `UPDATE` or `DELETE` statement on a table, or the
+/// building of a new index.
+#[derive(Debug)]
+pub(crate) struct Change {
+  target: Target,
+  kind: ChangeKind,
+  /// The rows to insert, or those to update or delete.
+  rows: Box<dyn Operator>,
+  /// The `OR` clause of the statement, which overrides the conflict
+  /// resolution of the constraints.
+  conflict: Option<ConflictResolution>,
+  /// The foreign keys of the tables the statement changes, when enforced.
+  foreign_keys: Option<ForeignKeys>,
+  /// The triggers the statement fires, if any.
+  triggers: Option<Triggers>,
+  pub(crate) subqueries: Vec<Subquery>,
+  /// The program running the statement, when the virtual machine can.
+  program: Option<Vdbe>,
+  changes: u64,
+  last_rowid: Option<i64>,
+  /// Set when a constraint failed under `OR FAIL`, or a trigger program
+  /// raised `FAIL`, which keeps the rows changed before.
+  failed: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum ChangeKind {
+  /// The rows hold the values supplied for some of the columns. For each
+  /// column of the table, and for the rowid after them, the expression
+  /// computing its value from those: the value supplied, or else its
+  /// default value.
+  Insert(Vec<Expr>),
+  /// The rows hold those of the table, rowid included, followed by the new
+  /// values of the columns at these positions, the rowid being past the
+  /// last column.
+  Update(Vec<usize>),
+  /// The rows hold those of the table, rowid included.
+  Delete,
+  /// Builds the new index rooted at this page: the rows hold those of the
+  /// table, rowid included, whose entries are added to it.
+  Index(u32),
+}
+
+/// The table a statement writes to. Its rows are handled as the values of
+/// its columns followed by the rowid, NULL in `WITHOUT ROWID` tables.
+#[derive(Debug)]
+pub(crate) struct Target {
+  cursor: TableCursor,
+  /// The indexes, in the order their uniqueness is checked.
+  indexes: Vec<TargetIndex>,
+  /// The number of indexes whose uniqueness is checked before that of the
+  /// primary key.
+  key_position: usize,
+  /// Set by `AUTOINCREMENT`: rowids are never reused.
+  autoincrement: bool,
+  /// How rows are told apart: by their rowid, or by their primary key in
+  /// `WITHOUT ROWID` tables.
+  key_info: KeyInfo,
+  constraints: Constraints,
+}
+
+/// The constraints of a table on the values of each row, besides
+/// uniqueness.
+#[derive(Debug, Default)]
+pub(crate) struct Constraints {
+  /// The columns that may not be NULL, in order, with the conflict
+  /// resolution of their constraint.
+  pub(crate) not_null: Vec<(usize, Option<ConflictResolution>)>,
+  /// The default value of each column, which a NULL is replaced with under
+  /// `REPLACE` when the column may not be NULL.
+  pub(crate) defaults: Vec<Expr>,
+  /// The `CHECK` constraints, with how their failures name them.
+  pub(crate) checks: Vec<(String, Expr)>,
+  /// The conflict resolution of the primary key constraint, when the key
+  /// is the rowid or that of a `WITHOUT ROWID` table.
+  pub(crate) primary_key: Option<ConflictResolution>,
+}
+
+/// An index b-tree kept up to date with the rows of its table.
+#[derive(Debug)]
+pub(crate) struct TargetIndex {
+  pub(crate) root: u32,
+  /// The indexed values, computed from the rows of the table.
+  pub(crate) values: Vec<Expr>,
+  pub(crate) key_info: KeyInfo,
+  /// The `WHERE` clause of a partial index, which only holds the rows that
+  /// satisfy it.
+  pub(crate) condition: Option<Expr>,
+  /// Whether no two rows may have the same values, unless one is NULL.
+  pub(crate) unique: bool,
+  /// How failures of the uniqueness constraint name it.
+  pub(crate) constraint: String,
+  /// Whether the constraint is the primary key.
+  pub(crate) primary: bool,
+  /// The conflict resolution of the constraint.
+  pub(crate) conflict: Option<ConflictResolution>,
+  /// In `WITHOUT ROWID` tables, the primary key columns that follow the
+  /// indexed values in the entries, and the position in the entries of
+  /// each primary key column.
+  pub(crate) primary_key: (Vec<usize>, Vec<usize>),
+}
+
+/// The values of the entries of an index, and how they are ordered.
+type IndexKey = (Vec<Expr>, KeyInfo);
+
+/// An index SQLite creates for the `PRIMARY KEY` and `UNIQUE` constraints
+/// of a table on the same columns.
+#[derive(Debug)]
+struct ConstraintIndex {
+  key: IndexKey,
+  /// The columns of the first of the constraints.
+  columns: Vec<IndexedColumn>,
+  /// The conflict resolution of the first of the constraints.
+  conflict: Option<ConflictResolution>,
+}
+
+impl Write {
+  pub(crate) fn run(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    match self {
+      Self::Table(change) => change.run(ctx),
+      Self::View(change) => change.run(ctx),
+    }
+  }
+
+  /// Rows inserted, updated or deleted: none in views.
+  pub(crate) fn changes(&self) -> u64 {
+    match self {
+      Self::Table(change) => change.changes(),
+      Self::View(_) => 0,
+    }
+  }
+
+  pub(crate) fn last_rowid(&self) -> Option<i64> {
+    match self {
+      Self::Table(change) => change.last_rowid(),
+      Self::View(_) => None,
+    }
+  }
+
+  pub(crate) fn keeps_changes(&self) -> bool {
+    match self {
+      Self::Table(change) => change.keeps_changes(),
+      Self::View(change) => change.keeps_changes(),
+    }
+  }
+
+  pub(crate) fn take_subqueries(&mut self) -> Vec<Subquery> {
+    match self {
+      Self::Table(change) => mem::take(&mut change.subqueries),
+      Self::View(change) => mem::take(&mut change.subqueries),
+    }
+  }
+
+  /// Resolves failed constraints as `conflict` says, when given, whatever
+  /// the `OR` clause of the statement.
+  pub(crate) fn with_conflict(
+    mut self,
+    conflict: Option<ConflictResolution>,
+  ) -> Self {
+    if let Self::Table(change) = &mut self {
+      change.conflict = conflict.or(change.conflict);
+    }
+    self
+  }
+}
+
+impl Change {
+  pub(crate) fn new(
+    target: Target,
+    kind: ChangeKind,
+    rows: Box<dyn Operator>,
+    conflict: Option<ConflictResolution>,
+    subqueries: Vec<Subquery>,
+  ) -> Self {
+    Self {
+      target,
+      kind,
+      rows,
+      conflict,
+      foreign_keys: None,
+      triggers: None,
+      subqueries,
+      program: None,
+      changes: 0,
+      last_rowid: None,
+      failed: false,
+    }
+  }
+
+  /// Enforces `foreign_keys` on the rows changed.
+  pub(crate) fn with_foreign_keys(
+    mut self,
+    foreign_keys: Option<ForeignKeys>,
+  ) -> Self {
+    self.foreign_keys = foreign_keys;
+    self
+  }
+
+  /// Fires `triggers` for the rows changed.
+  pub(crate) fn with_triggers(mut self, triggers: Option<Triggers>) -> Self {
+    self.triggers = triggers;
+    self
+  }
+
+  /// Rows inserted, updated or deleted.
+  pub(crate) fn changes(&self) -> u64 {
+    self.changes
+  }
+
+  /// Rowid of the last row inserted, in a rowid table.
+  pub(crate) fn last_rowid(&self) -> Option<i64> {
+    self.last_rowid
+  }
+
+  /// Whether the rows changed before the statement failed are kept.
+  pub(crate) fn keeps_changes(&self) -> bool {
+    self.failed
+  }
+
+  pub(crate) fn run(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    if let Some(mut program) = self.program.take() {
+      let result = program.change(ctx, self);
+      self.program = Some(program);
+      return result;
+    }
+    let mut rows = vec![];
+    while let Some(row) = self.rows.next(ctx)? {
+      rows.push(row);
+    }
+    self.write(ctx, rows)?;
+    self.check_foreign_keys(ctx)
+  }
+
+  /// Writes `rows`, which are those of the statement read, as it says.
+  pub(crate) fn write(
+    &mut self,
+    ctx: &mut Context<'_>,
+    rows: Vec<Vec<Value>>,
+  ) -> SqliteResult<()> {
+    match &self.kind {
+      ChangeKind::Insert(columns) => {
+        let columns = columns.clone();
+        self.insert(ctx, rows, &columns)
+      }
+      ChangeKind::Update(columns) => {
+        let columns = columns.clone();
+        self.update(ctx, rows, &columns)
+      }
+      ChangeKind::Delete => {
+        for row in rows {
+          // Rows deleted by the actions of foreign keys or by trigger
+          // programs are gone.
+          let mut row = row;
+          if self.foreign_keys.is_some() || self.triggers.is_some() {
+            match self.target.find(ctx, &self.target.key(&row))? {
+              Some(found) => row = found,
+              None => continue,
+            }
+          }
+          if self.triggers.is_some() {
+            if !self.fire(ctx, TriggerTime::Before, Some(&row), None)? {
+              continue;
+            }
+            match self.target.find(ctx, &self.target.key(&row))? {
+              Some(found) => row = found,
+              None => continue,
+            }
+          }
+          self.delete(ctx, &row)?;
+          self.changes += 1;
+          self.fire(ctx, TriggerTime::After, Some(&row), None)?;
+        }
+        Ok(())
+      }
+      ChangeKind::Index(root) => {
+        let root = *root;
+        for mut row in rows {
+          let key = self.target.key(&row);
+          self.check(ctx, &mut row, Some(&key))?;
+          self.target.insert_entry(ctx, root, &row)?;
+        }
+        Ok(())
+      }
+    }
+  }
+
+  /// Generates the program of the statement, which reads the rows it
+  /// changes, then writes them once all are read.
+  pub(crate) fn compile(&self, builder: &mut Builder) -> Codegen {
+    let opcode = match self.kind {
+      ChangeKind::Insert(_) | ChangeKind::Update(_) => Opcode::Insert,
+      ChangeKind::Delete => Opcode::Delete,
+      ChangeKind::Index(_) => return Err(Unsupported("CREATE INDEX")),
+    };
+    let cursor =
+      builder.open_write(self.target.definition(), self.target.root());
+    self.rows.compile(builder, &mut |builder, row, _| {
+      let first = builder.row_registers(row);
+      builder.add(opcode, cursor, first, row.len() as i32);
+      Ok(())
+    })?;
+    builder.add(Opcode::Close, cursor, 0, 0);
+    if self.foreign_keys.is_some() {
+      builder.add(Opcode::FkCheck, 0, 0, 0);
+    }
+    Ok(())
+  }
+
+  /// Fails when foreign key constraints are left unsatisfied by the rows
+  /// written.
+  pub(crate) fn check_foreign_keys(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<()> {
+    match &mut self.foreign_keys {
+      Some(foreign_keys) => foreign_keys.check(ctx),
+      None => Ok(()),
+    }
+  }
+
+  fn insert(
+    &mut self,
+    ctx: &mut Context<'_>,
+    rows: Vec<Vec<Value>>,
+    columns: &[Expr],
+  ) -> SqliteResult<()> {
+    let name = self.target.name().to_owned();
+    let mut seq = match self.target.autoincrement {
+      true => ctx.conn.runtime_mut().autoincrement_sequence(&name)?,
+      false => None,
+    };
+    let width = self.target.width();
+    for values in rows {
+      let mut row = columns
+        .iter()
+        .map(|column| column.eval(ctx, &values))
+        .collect::<SqliteResult<Vec<_>>>()?;
+      let is_rowid_missing = self.target.apply_affinity(&mut row)?;
+      // The rowid to be chosen reads -1 in `BEFORE` triggers.
+      if self.triggers.is_some() {
+        let mut new = row.clone();
+        if is_rowid_missing {
+          self.target.set_rowid(&mut new, -1);
+        }
+        if !self.fire(ctx, TriggerTime::Before, None, Some(&new))? {
+          continue;
+        }
+      }
+      if is_rowid_missing {
+        let rowid = self.target.new_rowid(ctx, seq.unwrap_or_default())?;
+        self.target.set_rowid(&mut row, rowid);
+      }
+      if !self.check(ctx, &mut row, None)? {
+        continue;
+      }
+      self.target.insert(ctx, &row)?;
+      if let Some(foreign_keys) = &mut self.foreign_keys {
+        foreign_keys.inserted(ctx, 0, &row)?;
+      }
+      self.changes += 1;
+      if let Value::Integer(rowid) = row[width] {
+        self.last_rowid = Some(rowid);
+        if self.target.autoincrement {
+          seq = Some(seq.map_or(rowid, |seq| seq.max(rowid)));
+        }
+      }
+      self.fire(ctx, TriggerTime::After, None, Some(&row))?;
+    }
+    match seq {
+      Some(seq) if self.target.autoincrement => ctx
+        .conn
+        .runtime_mut()
+        .set_autoincrement_sequence(&name, seq),
+      _ => Ok(()),
+    }
+  }
+
+  fn update(
+    &mut self,
+    ctx: &mut Context<'_>,
+    rows: Vec<Vec<Value>>,
+    columns: &[usize],
+  ) -> SqliteResult<()> {
+    let width = self.target.width();
+    // A row joined with several rows of the FROM clause is updated once,
+    // with the values of its last match, like SQLite does.
+    let keys = rows
+      .iter()
+      .map(|values| Record::encode(&self.target.key(values)))
+      .collect::<Vec<_>>();
+    let last = keys
+      .iter()
+      .enumerate()
+      .map(|(idx, key)| (key, idx))
+      .collect::<HashMap<_, _>>();
+    for (idx, values) in rows.iter().enumerate() {
+      if last[&keys[idx]] != idx {
+        continue;
+      }
+      let (old, new_values) = values.split_at(width + 1);
+      let key = self.target.key(old);
+      // Rows deleted by REPLACE conflicts are gone.
+      let Some(mut old) = self.target.find(ctx, &key)? else {
+        continue;
+      };
+      let mut row = self.updated_row(&old, columns, new_values)?;
+      // The programs of `BEFORE` triggers may change the row, or delete it.
+      if self.triggers.is_some() {
+        if !self.fire(ctx, TriggerTime::Before, Some(&old), Some(&row))? {
+          continue;
+        }
+        let Some(current) = self.target.find(ctx, &key)? else {
+          continue;
+        };
+        row = self.updated_row(&current, columns, new_values)?;
+        old = current;
+      }
+      if !self.check(ctx, &mut row, Some(&key))? {
+        continue;
+      }
+      self.target.delete(ctx, &old)?;
+      self.target.insert(ctx, &row)?;
+      if let Some(foreign_keys) = &mut self.foreign_keys {
+        foreign_keys.updated(ctx, 0, &old, &row)?;
+      }
+      self.changes += 1;
+      self.fire(ctx, TriggerTime::After, Some(&old), Some(&row))?;
+    }
+    Ok(())
+  }
+
+  /// The row `old` with the new `values` of the `columns` updated.
+  fn updated_row(
+    &self,
+    old: &[Value],
+    columns: &[usize],
+    values: &[Value],
+  ) -> SqliteResult<Vec<Value>> {
+    let mut row = old.to_vec();
+    for (&column, value) in columns.iter().zip(values) {
+      row[column] = value.clone();
+    }
+    if self.target.apply_affinity(&mut row)? {
+      return Err(SqliteError::Custom("datatype mismatch".into()));
+    }
+    Ok(row)
+  }
+
+  /// Fires the triggers at `time` for a row whose values are `old` before
+  /// the change and `new` after it. Returns false when the change of the
+  /// row is abandoned.
+  fn fire(
+    &mut self,
+    ctx: &mut Context<'_>,
+    time: TriggerTime,
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+  ) -> SqliteResult<bool> {
+    let Some(triggers) = &self.triggers else {
+      return Ok(true);
+    };
+    let result = triggers.fire(ctx, time, old, new);
+    if result.is_err() && ctx.raised == Some(RaiseAction::Fail) {
+      self.failed = true;
+    }
+    result
+  }
+
+  /// Removes `row` from the table, along with the rows the actions of
+  /// foreign keys delete.
+  fn delete(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    self.target.delete(ctx, row)?;
+    match &mut self.foreign_keys {
+      Some(foreign_keys) => foreign_keys.deleted(ctx, 0, row),
+      None => Ok(()),
+    }
+  }
+
+  /// Checks the constraints of the table for `row`, which is the row with
+  /// the given `key` when it is updated. Returns false when the row is to
+  /// be skipped.
+  fn check(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &mut [Value],
+    key: Option<&[Value]>,
+  ) -> SqliteResult<bool> {
+    if let Some((error, resolution)) =
+      self.target.check_values(ctx, row, self.conflict)?
+    {
+      return self.resolve(ctx, error, resolution);
+    }
+    for (kind, constraint, conflict, conflicts) in
+      self.target.conflicts(ctx, row, key)?
+    {
+      if conflicts.is_empty() {
+        continue;
+      }
+      match self
+        .conflict
+        .or(conflict)
+        .unwrap_or(ConflictResolution::Abort)
+      {
+        ConflictResolution::Replace => {
+          for key in conflicts {
+            if let Some(other) = self.target.find(ctx, &key)? {
+              self.delete(ctx, &other)?;
+            }
+          }
+        }
+        resolution => {
+          let error = unique_failed(kind, &constraint);
+          return self.resolve(ctx, error, resolution);
+        }
+      }
+    }
+    Ok(true)
+  }
+
+  /// Resolves the failure of a constraint: the row is skipped under
+  /// `IGNORE`, and the statement fails otherwise, rolling back the
+  /// transaction under `ROLLBACK`.
+  fn resolve(
+    &mut self,
+    ctx: &mut Context<'_>,
+    error: SqliteError,
+    resolution: ConflictResolution,
+  ) -> SqliteResult<bool> {
+    match resolution {
+      ConflictResolution::Ignore => Ok(false),
+      resolution => {
+        self.failed = resolution == ConflictResolution::Fail;
+        if resolution == ConflictResolution::Rollback {
+          ctx.raised = Some(RaiseAction::Rollback);
+        }
+        Err(error)
+      }
+    }
+  }
+}
+
+impl TargetIndex {
+  /// The columns of the index, unless it has expressions.
+  pub(crate) fn columns(&self) -> Option<Vec<usize>> {
+    let columns = self.values.iter().map(|value| match uncollated(value) {
+      Expr::Column { index, .. } => Some(*index),
+      _ => None,
+    });
+    columns.collect()
+  }
+}
+
+impl Target {
+  pub(crate) fn new(
+    cursor: TableCursor,
+    indexes: Vec<TargetIndex>,
+    key_position: usize,
+    autoincrement: bool,
+    constraints: Constraints,
+  ) -> Self {
+    let definition = cursor.definition();
+    let key_info = match definition.is_without_rowid() {
+      true => definition.key_info(),
+      false => KeyInfo::default(),
+    };
+    Self {
+      cursor,
+      indexes,
+      key_position,
+      autoincrement,
+      key_info,
+      constraints,
+    }
+  }
+
+  pub(crate) fn definition(&self) -> &TableDefinition {
+    self.cursor.definition()
+  }
+
+  pub(crate) fn name(&self) -> &str {
+    self.cursor.definition().name()
+  }
+
+  /// Root page of the b-tree holding the rows.
+  fn root(&self) -> u32 {
+    self.cursor.root()
+  }
+
+  pub(crate) fn indexes(&self) -> &[TargetIndex] {
+    &self.indexes
+  }
+
+  /// Number of columns of the table.
+  fn width(&self) -> usize {
+    self.cursor.definition().columns().len()
+  }
+
+  fn is_without_rowid(&self) -> bool {
+    self.cursor.definition().is_without_rowid()
+  }
+
+  /// Converts the values of `row` to the affinity of their column, but for
+  /// the `ANY` columns of `STRICT` tables, which keep them as they are. The
+  /// rowid, which the alias of the rowid stands for, must be an integer.
+  /// Returns whether the rowid is missing.
+  pub(crate) fn apply_affinity(&self, row: &mut [Value]) -> SqliteResult<bool> {
+    let definition = self.cursor.definition();
+    let width = self.width();
+    for (value, column) in row.iter_mut().zip(definition.columns()) {
+      if definition.is_strict() && declared_type(column) == "ANY" {
+        continue;
+      }
+      *value = column.affinity().apply(mem::replace(value, Value::Null));
+    }
+    if definition.is_without_rowid() {
+      return Ok(false);
+    }
+    let alias = definition.rowid_alias();
+    let rowid = match alias {
+      Some(alias) => row[alias].clone(),
+      None => {
+        Affinity::Integer.apply(mem::replace(&mut row[width], Value::Null))
+      }
+    };
+    match rowid {
+      Value::Null => Ok(true),
+      Value::Integer(rowid) => {
+        self.set_rowid(row, rowid);
+        Ok(false)
+      }
+      _ => Err(SqliteError::Custom("datatype mismatch".into())),
+    }
+  }
+
+  fn set_rowid(&self, row: &mut [Value], rowid: i64) {
+    if let Some(alias) = self.cursor.definition().rowid_alias() {
+      row[alias] = Value::Integer(rowid);
+    }
+    row[self.width()] = Value::Integer(rowid);
+  }
+
+  /// The rowid of a new row: one more than the largest rowid of the table,
+  /// and of `seq` in `AUTOINCREMENT` tables. Once the largest possible
+  /// rowid is used, unused rowids are picked at random, except in
+  /// `AUTOINCREMENT` tables.
+  ///
+  /// *Reference:* https://www.sqlite.org/autoinc.html
+  fn new_rowid(
+    &mut self,
+    ctx: &mut Context<'_>,
+    seq: i64,
+  ) -> SqliteResult<i64> {
+    let mut btree = ctx.btree();
+    let mut cursor = BtreeCursor::new(self.cursor.root());
+    let largest = match cursor.last(&mut btree)? {
+      true => cursor.rowid(&btree)?,
+      false => 0,
+    };
+    if self.autoincrement {
+      return largest.max(seq).checked_add(1).ok_or_else(full);
+    }
+    if let Some(rowid) = largest.checked_add(1) {
+      return Ok(rowid);
+    }
+    let state = RandomState::new();
+    for attempt in 0..100u64 {
+      let mut hasher = state.build_hasher();
+      hasher.write_u64(attempt);
+      let rowid = (hasher.finish() >> 1) as i64 + 1;
+      if !cursor.seek_rowid(&mut btree, rowid)? {
+        return Ok(rowid);
+      }
+    }
+    Err(full())
+  }
+
+  /// What tells `row` apart from the other rows of the table.
+  pub(crate) fn key(&self, row: &[Value]) -> Vec<Value> {
+    let definition = self.cursor.definition();
+    match definition.is_without_rowid() {
+      true => definition
+        .primary_key()
+        .iter()
+        .map(|key| row[key.column()].clone())
+        .collect(),
+      false => vec![row[self.width()].clone()],
+    }
+  }
+
+  /// The row with the given `key`, if any.
+  pub(crate) fn find(
+    &mut self,
+    ctx: &mut Context<'_>,
+    key: &[Value],
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let mut btree = ctx.btree();
+    let found = match key {
+      _ if self.is_without_rowid() => {
+        self.cursor.seek_primary_key(&mut btree, key)?
+      }
+      [Value::Integer(rowid)] => self.cursor.seek_rowid(&mut btree, *rowid)?,
+      _ => false,
+    };
+    if !found {
+      return Ok(None);
+    }
+    self.current_row(&mut btree).map(Some)
+  }
+
+  /// The default value of `column`.
+  pub(crate) fn default(
+    &self,
+    ctx: &mut Context<'_>,
+    column: usize,
+  ) -> SqliteResult<Value> {
+    let default = self.constraints.defaults[column].eval(ctx, &[])?;
+    let affinity = self.cursor.definition().columns()[column].affinity();
+    Ok(affinity.apply(default))
+  }
+
+  /// Every row of the table.
+  pub(crate) fn rows(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Vec<Vec<Value>>> {
+    let mut btree = ctx.btree();
+    let mut rows = vec![];
+    let mut found = self.cursor.first(&mut btree)?;
+    while found {
+      rows.push(self.current_row(&mut btree)?);
+      found = self.cursor.next(&mut btree)?;
+    }
+    Ok(rows)
+  }
+
+  /// The row the cursor is at, followed by its rowid.
+  fn current_row(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+  ) -> SqliteResult<Vec<Value>> {
+    let mut row = self.cursor.row(btree)?;
+    for (value, column) in
+      row.iter_mut().zip(self.cursor.definition().columns())
+    {
+      if let (Value::Integer(int), Affinity::Real) = (&value, column.affinity())
+      {
+        *value = Value::Real(*int as f64);
+      }
+    }
+    let rowid = self.cursor.rowid(btree)?;
+    row.push(rowid.map_or(Value::Null, Value::Integer));
+    Ok(row)
+  }
+
+  /// Whether the index at position `index` has an entry starting with
+  /// `values`.
+  pub(crate) fn has_entry(
+    &self,
+    ctx: &mut Context<'_>,
+    index: usize,
+    values: &[Value],
+  ) -> SqliteResult<bool> {
+    let index = &self.indexes[index];
+    let mut btree = ctx.btree();
+    let mut cursor = BtreeCursor::new(index.root);
+    if !cursor.seek_first_key(&mut btree, values, &index.key_info)? {
+      return Ok(false);
+    }
+    let record = cursor.record(&mut btree)?;
+    Ok(index.key_info.compare(&record, values).is_eq())
+  }
+
+  /// Checks the `NOT NULL` constraints, the types of a `STRICT` table and
+  /// the `CHECK` constraints of the table for `row`, in that order, given
+  /// the `OR` clause of the statement. Under `REPLACE`, a NULL in a column
+  /// that may not be NULL is replaced with the default value of the column.
+  /// Returns the failure of the first constraint `row` fails, and how it is
+  /// resolved: type errors always abort the statement, as do `CHECK`
+  /// failures under `REPLACE`.
+  pub(crate) fn check_values(
+    &self,
+    ctx: &mut Context<'_>,
+    row: &mut [Value],
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<Option<(SqliteError, ConflictResolution)>> {
+    let definition = self.cursor.definition();
+    for &(column, resolution) in self.constraints.not_null.iter() {
+      if !row[column].is_null() {
+        continue;
+      }
+      let resolution = match conflict.or(resolution) {
+        Some(ConflictResolution::Replace) => {
+          let default = self.constraints.defaults[column].eval(ctx, row)?;
+          let affinity = definition.columns()[column].affinity();
+          row[column] = affinity.apply(default);
+          if !row[column].is_null() {
+            continue;
+          }
+          ConflictResolution::Abort
+        }
+        resolution => resolution.unwrap_or(ConflictResolution::Abort),
+      };
+      let error = SqliteError::Constraint {
+        kind: ConstraintKind::NotNull,
+        message: format!(
+          "NOT NULL constraint failed: {}.{}",
+          definition.name(),
+          definition.columns()[column].name()
+        ),
+      };
+      return Ok(Some((error, resolution)));
+    }
+    if definition.is_strict() {
+      for (value, column) in row.iter().zip(definition.columns()) {
+        if let Some(error) = type_error(definition, column, value) {
+          return Ok(Some((error, ConflictResolution::Abort)));
+        }
+      }
+    }
+    for (name, check) in self.constraints.checks.iter() {
+      if is_true(&check.eval(ctx, row)?) == Some(false) {
+        let error = SqliteError::Constraint {
+          kind: ConstraintKind::Check,
+          message: format!("CHECK constraint failed: {name}"),
+        };
+        let resolution = match conflict {
+          Some(ConflictResolution::Replace) | None => ConflictResolution::Abort,
+          Some(resolution) => resolution,
+        };
+        return Ok(Some((error, resolution)));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Checks every constraint of the table for `row`, which is the row with
+  /// the given `key` when it is updated, failing on the first it fails.
+  pub(crate) fn check_all(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &mut [Value],
+    key: Option<&[Value]>,
+  ) -> SqliteResult<()> {
+    let abort = Some(ConflictResolution::Abort);
+    if let Some((error, _)) = self.check_values(ctx, row, abort)? {
+      return Err(error);
+    }
+    for (kind, constraint, _, conflicts) in self.conflicts(ctx, row, key)? {
+      if !conflicts.is_empty() {
+        return Err(unique_failed(kind, &constraint));
+      }
+    }
+    Ok(())
+  }
+
+  /// The entry of `index` for `row`, unless the index leaves the row out.
+  fn entry(
+    &self,
+    ctx: &mut Context<'_>,
+    index: &TargetIndex,
+    row: &[Value],
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if let Some(condition) = &index.condition {
+      if is_true(&condition.eval(ctx, row)?) != Some(true) {
+        return Ok(None);
+      }
+    }
+    let mut entry = index
+      .values
+      .iter()
+      .map(|value| value.eval(ctx, row))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    match self.is_without_rowid() {
+      true => entry.extend(index.primary_key.0.iter().map(|&c| row[c].clone())),
+      false => entry.push(row[self.width()].clone()),
+    }
+    Ok(Some(entry))
+  }
+
+  /// The uniqueness constraints of the table, in the order they are
+  /// checked, each with its conflict resolution and the keys of the rows
+  /// other than the one with `key` that have the same values as `row` for
+  /// it.
+  fn conflicts(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+    key: Option<&[Value]>,
+  ) -> SqliteResult<Vec<Conflicts>> {
+    let primary_key = self.primary_key_constraint();
+    let primary_conflict = self.constraints.primary_key;
+    let own = self.key(row);
+    let key_info = self.key_info.clone();
+    let is_other = |other: &[Value]| {
+      key.map_or(true, |key| !key_info.compare(key, other).is_eq())
+    };
+    let mut primary_key =
+      match is_other(&own) && self.find(ctx, &own)?.is_some() {
+        true => Some((
+          ConstraintKind::PrimaryKey,
+          primary_key,
+          primary_conflict,
+          vec![own],
+        )),
+        false => None,
+      };
+    let mut conflicts = vec![];
+    for (idx, index) in self.indexes.iter().enumerate() {
+      if idx == self.key_position {
+        conflicts.extend(primary_key.take());
+      }
+      if !index.unique {
+        continue;
+      }
+      let Some(mut entry) = self.entry(ctx, index, row)? else {
+        continue;
+      };
+      entry.truncate(index.values.len());
+      if entry.iter().any(Value::is_null) {
+        continue;
+      }
+      let mut btree = ctx.btree();
+      let mut cursor = BtreeCursor::new(index.root);
+      let mut others = vec![];
+      let mut found =
+        cursor.seek_first_key(&mut btree, &entry, &index.key_info)?;
+      while found {
+        let record = cursor.record(&mut btree)?;
+        if !index.key_info.compare(&record, &entry).is_eq() {
+          break;
+        }
+        let other = match self.cursor.definition().is_without_rowid() {
+          true => index
+            .primary_key
+            .1
+            .iter()
+            .map(|&idx| record.get(idx).cloned().unwrap_or(Value::Null))
+            .collect(),
+          false => record.last().cloned().into_iter().collect::<Vec<_>>(),
+        };
+        if is_other(&other) {
+          others.push(other);
+        }
+        found = cursor.next(&mut btree)?;
+      }
+      let kind = match index.primary {
+        true => ConstraintKind::PrimaryKey,
+        false => ConstraintKind::Unique,
+      };
+      conflicts.push((kind, index.constraint.clone(), index.conflict, others));
+    }
+    conflicts.extend(primary_key);
+    Ok(conflicts)
+  }
+
+  /// How failures of the uniqueness of the primary key name it.
+  fn primary_key_constraint(&self) -> String {
+    let definition = self.cursor.definition();
+    let columns = match definition.rowid_alias() {
+      Some(alias) => vec![definition.columns()[alias].name()],
+      None if !definition.is_without_rowid() => vec!["rowid"],
+      None => definition
+        .primary_key()
+        .iter()
+        .map(|key| definition.columns()[key.column()].name())
+        .collect(),
+    };
+    columns
+      .iter()
+      .map(|column| format!("{}.{column}", definition.name()))
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+
+  /// Writes `row` to the table and its indexes.
+  pub(crate) fn insert(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    for index in self.indexes.iter() {
+      if let Some(entry) = self.entry(ctx, index, row)? {
+        ctx
+          .btree()
+          .insert_index(index.root, &entry, &index.key_info)?;
+      }
+    }
+    let definition = self.cursor.definition();
+    let alias = definition.rowid_alias();
+    let record = definition
+      .storage_order()
+      .into_iter()
+      .map(|column| match Some(column) == alias {
+        true => Value::Null,
+        false => row[column].clone(),
+      })
+      .collect::<Vec<_>>();
+    let root = self.cursor.root();
+    match row[self.width()] {
+      Value::Integer(rowid) => {
+        ctx.btree().insert(root, rowid, &Record::encode(&record))
+      }
+      _ => ctx.btree().insert_index(root, &record, &self.key_info),
+    }
+  }
+
+  /// Adds the entry of `row` to the index rooted at `root` alone.
+  fn insert_entry(
+    &mut self,
+    ctx: &mut Context<'_>,
+    root: u32,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    let Some(index) = self.indexes.iter().find(|index| index.root == root)
+    else {
+      return Ok(());
+    };
+    if let Some(entry) = self.entry(ctx, index, row)? {
+      ctx
+        .btree()
+        .insert_index(index.root, &entry, &index.key_info)?;
+    }
+    Ok(())
+  }
+
+  /// Removes `row` from the table and its indexes.
+  pub(crate) fn delete(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    for index in self.indexes.iter() {
+      if let Some(entry) = self.entry(ctx, index, row)? {
+        ctx
+          .btree()
+          .delete_index(index.root, &entry, &index.key_info)?;
+      }
+    }
+    let root = self.cursor.root();
+    match row[self.width()] {
+      Value::Integer(rowid) => ctx.btree().delete(root, rowid)?,
+      _ => {
+        let key = self.key(row);
+        ctx.btree().delete_index(root, &key, &self.key_info)?
+      }
+    };
+    Ok(())
+  }
+}
+
+impl Planner<'_> {
+  /// The change of a table `change` plans, which the virtual machine runs
+  /// when it can run every operator of it.
+  fn table_write(&self, mut change: Change) -> Write {
+    match vdbe::compile_change(&change, self.schema_cookie()) {
+      Ok(program) => change.program = Some(Vdbe::new(program)),
+      Err(Unsupported(feature)) => {
+        debug!("Running on operators, the machine does not run {feature}.");
+      }
+    }
+    Write::Table(Box::new(change))
+  }
+
+  /// Plans an `INSERT` statement. Columns without a value take their
+  /// default value, and the rowid is chosen when it is not given.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_insert.html
+  pub(crate) fn insert(&mut self, insert: &Insert) -> SqliteResult<Write> {
+    if !insert.upserts.is_empty() {
+      return Err(unsupported("Upsert"));
+    }
+    if !insert.returning.is_empty() {
+      return Err(unsupported("RETURNING clause"));
+    }
+    if let Some((_, view)) = self.view(&insert.table, None, 0)? {
+      return self.insert_view(insert, view);
+    }
+    let (target, declared) = self.target(&insert.table)?;
+    let foreign_keys = self.foreign_keys(&insert.table)?;
+    let definition = target.definition();
+    let width = definition.columns().len();
+    let rowid = match definition.is_without_rowid() {
+      true => None,
+      false => Some(definition.rowid_alias().unwrap_or(width)),
+    };
+    let named = match insert.columns.is_empty() {
+      true => (0..width).collect(),
+      false => insert
+        .columns
+        .iter()
+        .map(|name| {
+          definition
+            .column_index(&name.value)
+            .or(rowid.filter(|_| ROWID_NAMES.iter().any(|r| name.is(r))))
+            .ok_or(SqliteError::Custom(format!(
+              "table {} has no column named {}",
+              definition.name(),
+              name.value
+            )))
+        })
+        .collect::<SqliteResult<Vec<_>>>()?,
+    };
+    let triggers = self.triggers(
+      definition.name(),
+      &Event::Insert,
+      width + 1,
+      insert.conflict,
+    )?;
+    let (rows, named) =
+      self.inserted_rows(insert, definition.name(), width, named)?;
+    let mut columns = self.in_scope(vec![], |planner| {
+      declared
+        .iter()
+        .map(|column| match default_value(column) {
+          Some(default) => planner.expr(default),
+          None => Ok(Expr::Literal(Value::Null)),
+        })
+        .collect::<SqliteResult<Vec<_>>>()
+    })?;
+    columns.push(Expr::Literal(Value::Null));
+    for (position, column) in named.into_iter().enumerate() {
+      columns[column] = Expr::Slot(position);
+    }
+    let change = Change::new(
+      target,
+      ChangeKind::Insert(columns),
+      rows,
+      insert.conflict,
+      mem::take(&mut self.subqueries),
+    );
+    let change = change.with_foreign_keys(foreign_keys);
+    Ok(self.table_write(change.with_triggers(triggers)))
+  }
+
+  /// The rows of values an `INSERT` statement supplies for the `named`
+  /// columns of `table`, which has `width` columns, and the columns they
+  /// are for.
+  pub(super) fn inserted_rows(
+    &mut self,
+    insert: &Insert,
+    table: &str,
+    width: usize,
+    named: Vec<usize>,
+  ) -> SqliteResult<(Box<dyn Operator>, Vec<usize>)> {
+    match &insert.source {
+      InsertSource::DefaultValues => {
+        Ok((Box::new(Values::new(vec![vec![]])), vec![]))
+      }
+      InsertSource::Select(select) => {
+        let query = self.in_with(insert.with.as_ref(), |planner| {
+          planner.query(select, None)
+        })?;
+        let count = query.columns.len();
+        if insert.columns.is_empty() && count != width {
+          return Err(SqliteError::Custom(format!(
+            "table {table} has {width} columns but {count} values were \
+             supplied"
+          )));
+        }
+        if count != named.len() {
+          return Err(SqliteError::Custom(format!(
+            "{count} values for {} columns",
+            named.len()
+          )));
+        }
+        Ok((query.root, named))
+      }
+    }
+  }
+
+  /// Plans an `UPDATE` statement. The rows of its table are joined with
+  /// those of its `FROM` clause, as the last table of the join, and each row
+  /// is updated once, with the values computed for its first match.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_update.html
+  pub(crate) fn update(&mut self, update: &Update) -> SqliteResult<Write> {
+    if !update.returning.is_empty() {
+      return Err(unsupported("RETURNING clause"));
+    }
+    if let Some((_, view)) = self.view(&update.table, None, 0)? {
+      return self.update_view(update, view);
+    }
+    let (target, _) = self.target(&update.table)?;
+    let foreign_keys = self.foreign_keys(&update.table)?;
+    let definition = target.definition();
+    let width = definition.columns().len();
+    let rowid = match definition.is_without_rowid() {
+      true => None,
+      false => Some(definition.rowid_alias().unwrap_or(width)),
+    };
+    let (columns, values) = assignments(update, |name| {
+      definition
+        .column_index(&name.value)
+        .or(rowid.filter(|_| ROWID_NAMES.iter().any(|r| name.is(r))))
+    })?;
+    let updated = columns
+      .iter()
+      .map(|&column| match definition.columns().get(column) {
+        Some(column) => column.name().to_owned(),
+        None => "rowid".into(),
+      })
+      .collect();
+    let triggers = self.triggers(
+      definition.name(),
+      &Event::Update(updated),
+      width + 1,
+      update.conflict,
+    )?;
+    let target_rows = |planner: &mut Self, offset| {
+      let (table, source) =
+        planner.table(&update.table, update.alias.as_ref(), offset)?;
+      Ok((Input::Table(table), source))
+    };
+    let rows = self.update_rows(update, target_rows, |planner, offset| {
+      let mut exprs = (offset..offset + width + 1)
+        .map(Expr::Slot)
+        .collect::<Vec<_>>();
+      for value in values {
+        exprs.push(planner.expr(value)?);
+      }
+      Ok(exprs)
+    })?;
+    let change = Change::new(
+      target,
+      ChangeKind::Update(columns),
+      rows,
+      update.conflict,
+      mem::take(&mut self.subqueries),
+    );
+    let change = change.with_foreign_keys(foreign_keys);
+    Ok(self.table_write(change.with_triggers(triggers)))
+  }
+
+  /// The rows an `UPDATE` statement changes, joined with those of its `FROM`
+  /// clause. `target` plans the rows of the table it changes, given the
+  /// position of their values in the joined rows, and `exprs` the values
+  /// of the rows changed.
+  pub(super) fn update_rows(
+    &mut self,
+    update: &Update,
+    target: impl FnOnce(&mut Self, usize) -> SqliteResult<(Input, Source)>,
+    exprs: impl FnOnce(&mut Self, usize) -> SqliteResult<Vec<Expr>>,
+  ) -> SqliteResult<Box<dyn Operator>> {
+    self.in_with(update.with.as_ref(), |planner| {
+      let (mut tables, mut sources) = match &update.from {
+        Some(from) => planner.from(from)?,
+        None => (vec![], vec![]),
+      };
+      let offset = sources.iter().map(|source| source.width).sum();
+      let (input, source) = target(planner, offset)?;
+      tables.push(FromTable {
+        input,
+        join_type: JoinType::Inner,
+        on: None,
+        using: vec![],
+      });
+      sources.push(source);
+      planner.in_scope(sources, |planner| {
+        let exprs = exprs(planner, offset)?;
+        let on = tables
+          .iter()
+          .map(|table| table.on.map(|on| planner.expr(on)).transpose())
+          .collect::<SqliteResult<Vec<_>>>()?;
+        let condition = update
+          .where_clause
+          .as_ref()
+          .map(|condition| planner.expr(condition))
+          .transpose()?;
+        let root = planner.join(tables, on, condition, None)?;
+        planner.changed_rows(root, exprs, &update.order_by, &update.limit)
+      })
+    })
+  }
+
+  /// Plans a `DELETE` statement.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_delete.html
+  pub(crate) fn delete(&mut self, delete: &Delete) -> SqliteResult<Write> {
+    if !delete.returning.is_empty() {
+      return Err(unsupported("RETURNING clause"));
+    }
+    if let Some((_, view)) = self.view(&delete.table, None, 0)? {
+      // The `INSTEAD OF` triggers of a view see the rows it selects.
+      let width = view.width;
+      let triggers =
+        self.view_triggers(&view.name, Event::Delete, width, None)?;
+      let view_rows = |planner: &mut Self| {
+        planner
+          .view(&delete.table, delete.alias.as_ref(), 0)?
+          .ok_or_else(|| no_such_table(&delete.table))
+      };
+      let exprs = (0..width)
+        .map(Expr::Slot)
+        .chain(iter::repeat(Expr::Literal(Value::Null)).take(width))
+        .collect();
+      let rows = self.delete_rows(delete, view_rows, exprs)?;
+      let subqueries = mem::take(&mut self.subqueries);
+      return Ok(Write::View(ViewChange::new(rows, triggers, subqueries)));
+    }
+    let (target, _) = self.target(&delete.table)?;
+    let foreign_keys = self.foreign_keys(&delete.table)?;
+    let width = target.definition().columns().len();
+    let triggers =
+      self.triggers(target.name(), &Event::Delete, width + 1, None)?;
+    let table_rows = |planner: &mut Self| {
+      let (table, source) =
+        planner.table(&delete.table, delete.alias.as_ref(), 0)?;
+      Ok((Input::Table(table), source))
+    };
+    let exprs = (0..width + 1).map(Expr::Slot).collect();
+    let rows = self.delete_rows(delete, table_rows, exprs)?;
+    let change = Change::new(
+      target,
+      ChangeKind::Delete,
+      rows,
+      None,
+      mem::take(&mut self.subqueries),
+    );
+    let change = change.with_foreign_keys(foreign_keys);
+    Ok(self.table_write(change.with_triggers(triggers)))
+  }
+
+  /// The values of `exprs` for the rows a `DELETE` statement deletes, of
+  /// the table whose rows `target` plans.
+  fn delete_rows(
+    &mut self,
+    delete: &Delete,
+    target: impl FnOnce(&mut Self) -> SqliteResult<(Input, Source)>,
+    exprs: Vec<Expr>,
+  ) -> SqliteResult<Box<dyn Operator>> {
+    self.in_with(delete.with.as_ref(), |planner| {
+      let (input, source) = target(planner)?;
+      let tables = vec![FromTable {
+        input,
+        join_type: JoinType::Inner,
+        on: None,
+        using: vec![],
+      }];
+      planner.in_scope(vec![source], |planner| {
+        let condition = delete
+          .where_clause
+          .as_ref()
+          .map(|condition| planner.expr(condition))
+          .transpose()?;
+        let root = planner.join(tables, vec![None], condition, None)?;
+        planner.changed_rows(root, exprs, &delete.order_by, &delete.limit)
+      })
+    })
+  }
+
+  /// Plans inserting the rows of `plan`, which hold a value for every column,
+  /// into the table `name`.
+  pub(crate) fn insert_rows(
+    &mut self,
+    name: &QualifiedName,
+    plan: Plan,
+  ) -> SqliteResult<Change> {
+    let (target, _) = self.target(name)?;
+    let width = target.definition().columns().len();
+    let mut columns = (0..width).map(Expr::Slot).collect::<Vec<_>>();
+    columns.push(Expr::Literal(Value::Null));
+    Ok(Change::new(
+      target,
+      ChangeKind::Insert(columns),
+      plan.root,
+      None,
+      plan.subqueries,
+    ))
+  }
+
+  /// Checks the expressions of the constraints and generated columns of the
+  /// table `name`, and returns the numbers of the indexes SQLite creates for
+  /// its `PRIMARY KEY` and `UNIQUE` constraints. The primary key of a
+  /// `WITHOUT ROWID` table has none: the table is that index.
+  pub(crate) fn constraint_indexes(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<Vec<usize>> {
+    let (cursor, columns, constraints) = self.declaration(name)?;
+    let definition = cursor.definition().clone();
+    let source = table_source(&definition, definition.name(), 0);
+    self.in_scope(vec![source], |planner| {
+      let (automatic, primary_number) =
+        planner.constraint_keys(&definition, &columns, &constraints)?;
+      let column_checks = columns
+        .iter()
+        .flat_map(|column| column.constraints.iter())
+        .filter_map(|constraint| match &constraint.kind {
+          ColumnConstraintKind::Check(expr) => Some(expr),
+          _ => None,
+        });
+      let table_checks =
+        constraints
+          .iter()
+          .filter_map(|constraint| match &constraint.kind {
+            TableConstraintKind::Check(expr) => Some(expr),
+            _ => None,
+          });
+      for check in column_checks.chain(table_checks) {
+        prohibit_subqueries(check, "CHECK constraints")?;
+        planner.expr(check)?;
+      }
+      let generated = columns
+        .iter()
+        .flat_map(|column| column.constraints.iter())
+        .filter_map(|constraint| match &constraint.kind {
+          ColumnConstraintKind::Generated { expr, .. } => Some(expr),
+          _ => None,
+        });
+      for expr in generated {
+        prohibit_subqueries(expr, "generated columns")?;
+        planner.expr(expr)?;
+      }
+      Ok(
+        (1..=automatic.len())
+          .filter(|&number| {
+            !definition.is_without_rowid() || primary_number != Some(number)
+          })
+          .collect(),
+      )
+    })
+  }
+
+  /// Plans adding the rows of the table `name` to its new index rooted at
+  /// `root`.
+  pub(crate) fn fill_index(
+    &mut self,
+    name: &QualifiedName,
+    root: u32,
+  ) -> SqliteResult<Change> {
+    let (target, _) = self.target(name)?;
+    let width = target.definition().columns().len();
+    let (table, source) = self.table(name, None, 0)?;
+    let tables = vec![FromTable {
+      input: Input::Table(table),
+      join_type: JoinType::Inner,
+      on: None,
+      using: vec![],
+    }];
+    let rows = self.in_scope(vec![source], |planner| {
+      let root = planner.join(tables, vec![None], None, None)?;
+      let exprs = (0..width + 1).map(Expr::Slot).collect();
+      Ok(Box::new(Project::new(root, exprs)) as Box<dyn Operator>)
+    })?;
+    Ok(Change::new(
+      target,
+      ChangeKind::Index(root),
+      rows,
+      Some(ConflictResolution::Abort),
+      mem::take(&mut self.subqueries),
+    ))
+  }
+
+  /// The rows an `UPDATE` or `DELETE` statement changes: the values of
+  /// `exprs` for the rows of `root`, sorted and limited as the optional
+  /// `ORDER BY` and `LIMIT` clauses of the statement say.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_delete.html#optional_limit_and_order_by_clauses
+  fn changed_rows(
+    &mut self,
+    root: Box<dyn Operator>,
+    mut exprs: Vec<Expr>,
+    order_by: &[OrderingTerm],
+    limit: &Option<ast::Limit>,
+  ) -> SqliteResult<Box<dyn Operator>> {
+    let width = exprs.len();
+    let mut keys = vec![];
+    for term in order_by {
+      let expr = self.expr(&term.expr)?;
+      let collation = expr.collation().map(|(c, _)| c).unwrap_or_default();
+      keys.push(SortKey::new(
+        exprs.len(),
+        term.order == SortOrder::Desc,
+        term.nulls.map(|nulls| nulls == NullsOrder::First),
+        collation,
+      ));
+      exprs.push(expr);
+    }
+    let query = Query {
+      root: Box::new(Project::new(root, exprs)),
+      columns: vec![String::new(); width],
+      exprs: vec![],
+      keys,
+      aggregate: false,
+    };
+    Ok(self.limit(query, limit.as_ref())?.root)
+  }
+
+  /// The table `name` a statement writes to, with the indexes to keep up to
+  /// date with its rows, and its columns as declared.
+  fn target(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<(Target, Vec<ast::ColumnDefinition>)> {
+    let (cursor, columns, constraints) = self.declaration(name)?;
+    if cursor.root() == 1 {
+      return Err(SqliteError::Custom(
+        "table sqlite_master may not be modified".into(),
+      ));
+    }
+    let definition = cursor.definition().clone();
+    let autoincrement = columns
+      .iter()
+      .flat_map(|column| column.constraints.iter().map(|c| &c.kind))
+      .any(|kind| {
+        matches!(
+          kind,
+          ColumnConstraintKind::PrimaryKey {
+            autoincrement: true,
+            ..
+          }
+        )
+      })
+      || constraints.iter().any(|constraint| {
+        matches!(
+          constraint.kind,
+          TableConstraintKind::PrimaryKey {
+            autoincrement: true,
+            ..
+          }
+        )
+      });
+    let schema = self.runtime.schema()?;
+    let source = table_source(&definition, definition.name(), 0);
+    let entries = schema.into_iter().filter(|entry| {
+      entry.kind() == "index"
+        && entry.tbl_name().eq_ignore_ascii_case(definition.name())
+    });
+    let (indexes, key_position) = self.in_scope(vec![source], |planner| {
+      let (automatic, primary_number) =
+        planner.constraint_keys(&definition, &columns, &constraints)?;
+      // Each index is checked before those created earlier: those of the
+      // constraints, in order, then those of `CREATE INDEX` statements.
+      let mut indexes = vec![];
+      for entry in entries {
+        let (rank, unique, (values, key_info), condition, conflict) =
+          match entry.sql() {
+            Some(sql) => {
+              let statement = Parser::new(sql)?.next_statement()?;
+              let Some(StatementKind::CreateIndex(index)) =
+                statement.map(|statement| statement.kind)
+              else {
+                return Err(SqliteError::Corrupt(format!(
+                  "Malformed CREATE INDEX statement: {sql}"
+                )));
+              };
+              let key = planner.index_key(&index.columns)?;
+              let condition = match &index.where_clause {
+                Some(condition) => {
+                  let place = "partial index WHERE clauses";
+                  prohibit_subqueries(condition, place)?;
+                  Some(planner.deterministic_expr(condition, place)?)
+                }
+                None => None,
+              };
+              (usize::MAX, index.unique, key, condition, None)
+            }
+            None => {
+              let number = entry
+                .name()
+                .rsplit('_')
+                .next()
+                .and_then(|number| number.parse::<usize>().ok());
+              let Some((number, index)) = number
+                .and_then(|n| Some((n, automatic.get(n.checked_sub(1)?)?)))
+              else {
+                return Err(SqliteError::Corrupt(format!(
+                  "No constraint of table [{}] for index [{}]",
+                  definition.name(),
+                  entry.name()
+                )));
+              };
+              (number, true, index.key.clone(), None, index.conflict)
+            }
+          };
+        let index = target_index(
+          &definition,
+          entry.name(),
+          entry.rootpage(),
+          values,
+          key_info,
+          condition,
+          unique,
+        );
+        let primary = Some(rank) == primary_number;
+        let index = TargetIndex {
+          conflict,
+          primary,
+          ..index
+        };
+        indexes.push((rank, index));
+      }
+      indexes.sort_by_key(|(rank, _)| *rank);
+      indexes.reverse();
+      // The primary key of a `WITHOUT ROWID` table is checked as an index,
+      // while the rowid is checked first.
+      let key_position = match primary_number {
+        Some(number) if definition.is_without_rowid() => {
+          indexes.iter().filter(|(rank, _)| *rank > number).count()
+        }
+        _ => 0,
+      };
+      let indexes = indexes.into_iter().map(|(_, index)| index).collect();
+      Ok((indexes, key_position))
+    })?;
+    let sql = self.table_sql(definition.name())?;
+    let source = table_source(&definition, definition.name(), 0);
+    let constraints = self.in_scope(vec![source], |planner| {
+      planner.row_constraints(&definition, &columns, &constraints, &sql)
+    })?;
+    let target =
+      Target::new(cursor, indexes, key_position, autoincrement, constraints);
+    Ok((target, columns))
+  }
+
+  /// The foreign keys a statement changing the table `name` enforces, if
+  /// any: those with the table as child or parent, then those of the tables
+  /// their actions change, in turn.
+  ///
+  /// *Reference:* https://www.sqlite.org/foreignkeys.html#fk_actions
+  pub(crate) fn foreign_keys(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<Option<ForeignKeys>> {
+    if !self.foreign_keys {
+      return Ok(None);
+    }
+    let mut clauses = vec![];
+    let entries = self.runtime.tables()?;
+    for entry in entries.iter() {
+      let Some(sql) = entry.sql() else {
+        continue;
+      };
+      let (_, columns, constraints, _) = parse_table(sql)?;
+      for column in columns.iter() {
+        for constraint in column.constraints.iter() {
+          if let ColumnConstraintKind::ForeignKey(clause) = &constraint.kind {
+            let columns = vec![column.name.clone()];
+            clauses.push((entry.name().to_owned(), columns, clause.clone()));
+          }
+        }
+      }
+      for constraint in constraints {
+        if let TableConstraintKind::ForeignKey { columns, clause } =
+          constraint.kind
+        {
+          clauses.push((entry.name().to_owned(), columns, clause));
+        }
+      }
+    }
+    let mut tables = vec![self.target(name)?.0];
+    let mut changed = vec![0];
+    let mut used = vec![false; clauses.len()];
+    let mut keys = vec![];
+    let position = |planner: &mut Self, tables: &mut Vec<Target>, name| {
+      let position = tables
+        .iter()
+        .position(|table| table.name().eq_ignore_ascii_case(name));
+      if let Some(position) = position {
+        return Ok(position);
+      }
+      if !entries.iter().any(|e| e.name().eq_ignore_ascii_case(name)) {
+        return Err(SqliteError::Custom(format!("no such table: main.{name}")));
+      }
+      let table = QualifiedName {
+        schema: None,
+        name: Name {
+          value: name.to_owned(),
+          quoted: false,
+          span: Span::default(),
+        },
+      };
+      tables.push(planner.target(&table)?.0);
+      Ok(tables.len() - 1)
+    };
+    let mut next = 0;
+    while let Some(&table) = changed.get(next) {
+      next += 1;
+      for (idx, (child_name, columns, clause)) in clauses.iter().enumerate() {
+        let table_name = tables[table].name();
+        if used[idx]
+          || !(child_name.eq_ignore_ascii_case(table_name)
+            || clause.table.value.eq_ignore_ascii_case(table_name))
+        {
+          continue;
+        }
+        used[idx] = true;
+        let child = position(self, &mut tables, child_name)?;
+        let parent = position(self, &mut tables, &clause.table.value)?;
+        let mismatch = || {
+          SqliteError::Custom(format!(
+            "foreign key mismatch - \"{child_name}\" referencing \"{}\"",
+            clause.table.value
+          ))
+        };
+        let child_definition = tables[child].definition();
+        let child_columns = columns
+          .iter()
+          .map(|column| child_definition.column_index(&column.value))
+          .collect::<Option<Vec<_>>>()
+          .ok_or_else(mismatch)?;
+        let parent_definition = tables[parent].definition();
+        let parent_columns = match clause.columns.is_empty() {
+          true => parent_definition
+            .primary_key()
+            .iter()
+            .map(|key| Some(key.column()))
+            .collect::<Option<Vec<_>>>(),
+          false => clause
+            .columns
+            .iter()
+            .map(|column| parent_definition.column_index(&column.value))
+            .collect(),
+        };
+        let parent_columns = parent_columns
+          .filter(|columns| columns.len() == child_columns.len())
+          .ok_or_else(mismatch)?;
+        let lookup =
+          ForeignKey::lookup(&tables[child], &tables[parent], &parent_columns)?;
+        let on_delete = clause.on_delete.unwrap_or(ForeignKeyAction::NoAction);
+        let on_update = clause.on_update.unwrap_or(ForeignKeyAction::NoAction);
+        let changes_child = [on_delete, on_update].iter().any(|action| {
+          !matches!(
+            action,
+            ForeignKeyAction::NoAction | ForeignKeyAction::Restrict
+          )
+        });
+        if changes_child && !changed.contains(&child) {
+          changed.push(child);
+        }
+        keys.push(ForeignKey {
+          child,
+          parent,
+          child_columns,
+          parent_columns,
+          lookup,
+          on_delete,
+          on_update,
+          deferred: clause.deferred,
+        });
+      }
+    }
+    Ok(Some(ForeignKeys::new(tables, keys)))
+  }
+
+  /// The constraints of a table on the values of each row, besides
+  /// uniqueness. The table is to be in scope, and `sql` is its `CREATE
+  /// TABLE` statement, which unnamed `CHECK` constraints are named from.
+  fn row_constraints(
+    &mut self,
+    definition: &TableDefinition,
+    columns: &[ast::ColumnDefinition],
+    constraints: &[ast::TableConstraint],
+    sql: &str,
+  ) -> SqliteResult<Constraints> {
+    let mut row = Constraints::default();
+    let check_name = |name: &Option<Name>, expr: &ast::Expr| match name {
+      Some(name) => name.value.clone(),
+      None => expr.span.text(sql).to_owned(),
+    };
+    // The columns of the primary key of a `WITHOUT ROWID` table may not be
+    // NULL either.
+    let mut not_null = vec![];
+    for (idx, column) in columns.iter().enumerate() {
+      let generated = column.constraints.iter().any(|constraint| {
+        matches!(constraint.kind, ColumnConstraintKind::Generated { .. })
+      });
+      for constraint in column.constraints.iter() {
+        match &constraint.kind {
+          ColumnConstraintKind::NotNull(conflict) if !generated => {
+            not_null.push((idx, *conflict))
+          }
+          ColumnConstraintKind::PrimaryKey { conflict, .. } => {
+            row.primary_key = *conflict
+          }
+          ColumnConstraintKind::Check(expr) => {
+            let name = check_name(&constraint.name, expr);
+            row.checks.push((name, self.expr(expr)?));
+          }
+          _ => {}
+        }
+      }
+    }
+    for constraint in constraints.iter() {
+      match &constraint.kind {
+        TableConstraintKind::PrimaryKey { conflict, .. } => {
+          row.primary_key = *conflict
+        }
+        TableConstraintKind::Check(expr) => {
+          let name = check_name(&constraint.name, expr);
+          row.checks.push((name, self.expr(expr)?));
+        }
+        _ => {}
+      }
+    }
+    if definition.is_without_rowid() {
+      for key in definition.primary_key() {
+        not_null.push((key.column(), row.primary_key));
+      }
+    }
+    not_null.sort_by_key(|(column, _)| *column);
+    not_null.dedup_by_key(|(column, _)| *column);
+    row.not_null = not_null;
+    row.defaults = self.in_scope(vec![], |planner| {
+      columns
+        .iter()
+        .map(|column| match default_value(column) {
+          Some(default) => planner.expr(default),
+          None => Ok(Expr::Literal(Value::Null)),
+        })
+        .collect::<SqliteResult<Vec<_>>>()
+    })?;
+    Ok(row)
+  }
+
+  /// The table `name` with its columns and constraints, as declared in its
+  /// `CREATE TABLE` statement.
+  fn declaration(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<(
+    TableCursor,
+    Vec<ast::ColumnDefinition>,
+    Vec<ast::TableConstraint>,
+  )> {
+    if let Some(schema) = name.schema.as_ref().filter(|s| !s.is("main")) {
+      return Err(SqliteError::Custom(format!(
+        "unknown database {}",
+        schema.value
+      )));
+    }
+    let cursor = self.runtime.table(&name.name.value)?;
+    if cursor.root() == 1 {
+      return Ok((cursor, vec![], vec![]));
+    }
+    let sql = self.table_sql(&name.name.value)?;
+    let statement = Parser::new(&sql)?.next_statement()?;
+    let Some(StatementKind::CreateTable(create)) =
+      statement.map(|statement| statement.kind)
+    else {
+      return Err(malformed_table(&sql));
+    };
+    let CreateTableBody::Columns {
+      columns,
+      constraints,
+      ..
+    } = create.body
+    else {
+      return Err(malformed_table(&sql));
+    };
+    Ok((cursor, columns, constraints))
+  }
+
+  /// The `CREATE TABLE` statement of the table `name`.
+  fn table_sql(&mut self, name: &str) -> SqliteResult<String> {
+    let sql = self
+      .runtime
+      .tables()?
+      .into_iter()
+      .find(|entry| entry.name().eq_ignore_ascii_case(name))
+      .and_then(|entry| entry.sql().map(str::to_owned));
+    Ok(sql.unwrap_or_default())
+  }
+
+  /// The columns of the indexes SQLite creates for the `PRIMARY KEY` and
+  /// `UNIQUE` constraints of the table `name`, in the order of their
+  /// numbers, and the number of that of the primary key.
+  pub(crate) fn constraint_index_columns(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<(Vec<Vec<IndexedColumn>>, Option<usize>)> {
+    let (cursor, columns, constraints) = self.declaration(name)?;
+    let definition = cursor.definition().clone();
+    let source = table_source(&definition, definition.name(), 0);
+    self.in_scope(vec![source], |planner| {
+      let (automatic, primary_number) =
+        planner.constraint_keys(&definition, &columns, &constraints)?;
+      let columns = automatic.into_iter().map(|index| index.columns);
+      Ok((columns.collect(), primary_number))
+    })
+  }
+
+  /// The indexes SQLite creates for the `PRIMARY KEY` and `UNIQUE`
+  /// constraints of a table, in the order of their numbers, and the number
+  /// of that of the primary key. The table is to be in scope.
+  fn constraint_keys(
+    &mut self,
+    definition: &TableDefinition,
+    columns: &[ast::ColumnDefinition],
+    constraints: &[ast::TableConstraint],
+  ) -> SqliteResult<(Vec<ConstraintIndex>, Option<usize>)> {
+    let alias = definition.rowid_alias();
+    // The indexes are named after the position of their constraint among
+    // the constraints, left to right. A constraint on the same columns as
+    // an earlier one has none, and neither has the primary key when it is
+    // an alias of the rowid.
+    let mut keys: Vec<(Vec<IndexedColumn>, bool, Option<_>)> = vec![];
+    for (idx, column) in columns.iter().enumerate() {
+      for constraint in column.constraints.iter() {
+        let (order, primary, conflict) = match &constraint.kind {
+          ColumnConstraintKind::PrimaryKey {
+            order, conflict, ..
+          } if alias != Some(idx) => (*order, true, *conflict),
+          ColumnConstraintKind::Unique(conflict) => (None, false, *conflict),
+          _ => continue,
+        };
+        let expr = ast::Expr::new(
+          ExprKind::Column {
+            schema: None,
+            table: None,
+            column: column.name.clone(),
+          },
+          column.name.span,
+        );
+        keys.push((vec![IndexedColumn { expr, order }], primary, conflict));
+      }
+    }
+    for constraint in constraints.iter() {
+      match &constraint.kind {
+        TableConstraintKind::PrimaryKey {
+          columns, conflict, ..
+        } if alias.is_none() => keys.push((columns.clone(), true, *conflict)),
+        TableConstraintKind::Unique { columns, conflict } => {
+          keys.push((columns.clone(), false, *conflict))
+        }
+        _ => {}
+      }
+    }
+    let mut automatic: Vec<ConstraintIndex> = vec![];
+    let mut primary_number = None;
+    for (columns, primary, conflict) in keys.into_iter() {
+      let (values, key_info) = self.index_key(&columns)?;
+      let is_same = |index: &ConstraintIndex| {
+        let (other, other_info) = &index.key;
+        other.len() == values.len()
+          && other
+            .iter()
+            .zip(&values)
+            .all(|(left, right)| uncollated(left) == uncollated(right))
+          && other_info
+            .columns()
+            .iter()
+            .zip(key_info.columns())
+            .all(|(left, right)| left.collation == right.collation)
+      };
+      let number = match automatic.iter().position(is_same) {
+        Some(idx) => idx + 1,
+        None => {
+          automatic.push(ConstraintIndex {
+            key: (values, key_info),
+            columns,
+            conflict,
+          });
+          automatic.len()
+        }
+      };
+      if primary {
+        primary_number = Some(number);
+      }
+    }
+    Ok((automatic, primary_number))
+  }
+
+  /// The values of the entries of an index on `columns`, and how they are
+  /// ordered.
+  fn index_key(
+    &mut self,
+    columns: &[IndexedColumn],
+  ) -> SqliteResult<(Vec<Expr>, KeyInfo)> {
+    let mut values = vec![];
+    let mut key_columns = vec![];
+    for column in columns {
+      prohibit_subqueries(&column.expr, "index expressions")?;
+      let value = self.deterministic_expr(&column.expr, "index expressions")?;
+      key_columns.push(KeyColumn {
+        descending: column.order == Some(SortOrder::Desc),
+        collation: value.collation().map(|(c, _)| c).unwrap_or_default(),
+      });
+      values.push(value);
+    }
+    Ok((values, KeyInfo::new(key_columns)))
+  }
+
+  /// Compiles `expr`, part of a schema object, which may only call
+  /// deterministic functions.
+  fn deterministic_expr(
+    &mut self,
+    expr: &ast::Expr,
+    place: &'static str,
+  ) -> SqliteResult<Expr> {
+    let outer = self.deterministic_in.replace(place);
+    let compiled = self.expr(expr);
+    self.deterministic_in = outer;
+    compiled
+  }
+}
+
+/// A uniqueness constraint, how its failures name it, its conflict
+/// resolution, and the keys of the rows a row conflicts with for it.
+type Conflicts = (
+  ConstraintKind,
+  String,
+  Option<ConflictResolution>,
+  Vec<Vec<Value>>,
+);
+
+fn full() -> SqliteError {
+  SqliteError::Custom("database or disk is full".into())
+}
+
+fn unique_failed(kind: ConstraintKind, constraint: &str) -> SqliteError {
+  SqliteError::Constraint {
+    kind,
+    message: format!("UNIQUE constraint failed: {constraint}"),
+  }
+}
+
+/// The declared type of `column`, in uppercase.
+fn declared_type(column: &ColumnDefinition) -> String {
+  column
+    .declared_type()
+    .unwrap_or_default()
+    .to_ascii_uppercase()
+}
+
+/// The failure to store `value` in `column` of the `STRICT` table
+/// `definition`, unless it has the type of the column. Integers fit in
+/// `REAL` columns.
+///
+/// *Reference:* https://www.sqlite.org/stricttables.html
+fn type_error(
+  definition: &TableDefinition,
+  column: &ColumnDefinition,
+  value: &Value,
+) -> Option<SqliteError> {
+  let declared = declared_type(column);
+  let fits = matches!(
+    (declared.as_str(), value),
+    (_, Value::Null)
+      | ("ANY", _)
+      | ("INT" | "INTEGER", Value::Integer(_))
+      | ("REAL", Value::Integer(_) | Value::Real(_))
+      | ("TEXT", Value::Text(_))
+      | ("BLOB", Value::Blob(_))
+  );
+  if fits {
+    return None;
+  }
+  let type_name = match value {
+    Value::Integer(_) => "INTEGER",
+    Value::Real(_) => "REAL",
+    Value::Text(_) => "TEXT",
+    _ => "BLOB",
+  };
+  Some(SqliteError::Constraint {
+    kind: ConstraintKind::Datatype,
+    message: format!(
+      "cannot store {type_name} value in {declared} column {}.{}",
+      definition.name(),
+      column.name()
+    ),
+  })
+}
+
+/// The index named `name` of the table of `definition`, whose entries hold
+/// `values`: a row's values for its indexed columns and expressions. In a
+/// `WITHOUT ROWID` table, the primary key columns not among them follow
+/// them in the entries, in place of the rowid.
+fn target_index(
+  definition: &TableDefinition,
+  name: &str,
+  root: u32,
+  values: Vec<Expr>,
+  key_info: KeyInfo,
+  condition: Option<Expr>,
+  unique: bool,
+) -> TargetIndex {
+  let columns = values
+    .iter()
+    .map(|value| match uncollated(value) {
+      Expr::Column { index, .. } => Some(*index),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  let constraint = match columns.iter().copied().collect::<Option<Vec<_>>>() {
+    Some(columns) => columns
+      .iter()
+      .map(|&c| {
+        format!("{}.{}", definition.name(), definition.columns()[c].name())
+      })
+      .collect::<Vec<_>>()
+      .join(", "),
+    None => format!("index '{name}'"),
+  };
+  let mut key_columns = key_info.columns().to_vec();
+  let mut appended = vec![];
+  let mut positions = vec![];
+  if definition.is_without_rowid() {
+    let primary_key = definition.primary_key().iter();
+    for (key, key_column) in primary_key.zip(definition.key_info().columns()) {
+      let position = columns.iter().zip(&key_columns).position(|(c, k)| {
+        *c == Some(key.column()) && k.collation == key_column.collation
+      });
+      let position = position.unwrap_or_else(|| {
+        appended.push(key.column());
+        key_columns.push(key_column.clone());
+        key_columns.len() - 1
+      });
+      positions.push(position);
+    }
+  }
+  TargetIndex {
+    root,
+    values,
+    key_info: KeyInfo::new(key_columns),
+    condition,
+    unique,
+    constraint,
+    primary: false,
+    primary_key: (appended, positions),
+    conflict: None,
+  }
+}
+
+/// The `DEFAULT` clause of a column, if any.
+fn default_value(column: &ast::ColumnDefinition) -> Option<&ast::Expr> {
+  column
+    .constraints
+    .iter()
+    .find_map(|constraint| match &constraint.kind {
+      ColumnConstraintKind::Default(expr) => Some(expr),
+      _ => None,
+    })
+}
+
+/// The columns the assignments of an `UPDATE` statement set, as `column`
+/// finds them by name, and the value assigned to each.
+pub(super) fn assignments(
+  update: &Update,
+  column: impl Fn(&Name) -> Option<usize>,
+) -> SqliteResult<(Vec<usize>, Vec<&ast::Expr>)> {
+  let mut columns = vec![];
+  let mut values = vec![];
+  for assignment in update.assignments.iter() {
+    for name in assignment.columns.iter() {
+      let index = column(name).ok_or(SqliteError::Custom(format!(
+        "no such column: {}",
+        name.value
+      )))?;
+      columns.push(index);
+    }
+    let count = assignment.columns.len();
+    match &assignment.value.kind {
+      _ if count == 1 => values.push(&assignment.value),
+      ExprKind::Vector(list) if list.len() == count => values.extend(list),
+      ExprKind::Subquery(_) => {
+        return Err(unsupported("Assigning a subquery to several columns"))
+      }
+      kind => {
+        let assigned = match kind {
+          ExprKind::Vector(list) => list.len(),
+          _ => 1,
+        };
+        return Err(SqliteError::Custom(format!(
+          "{count} columns assigned {assigned} values"
+        )));
+      }
+    }
+  }
+  Ok((columns, values))
+}
@@ -0,0 +1,628 @@
+//! Expressions compiled for evaluation: column references are resolved to
+//! positions within rows, and the affinity and collating sequence of every
+//! comparison are known in advance.
+
+use super::function::ScalarFunction;
+use super::pattern::pattern_match;
+use super::subquery::SubqueryRef;
+use super::value::{
+  binary, bit_not, compare, comparison_affinity, from_bool, is_true, negate,
+};
+use super::Context;
+use crate::result::{ConstraintKind, SqliteError, SqliteResult};
+use crate::runtime::{to_text, Affinity, Collation, Value};
+use crate::sql::ast::{BinaryOperator, RaiseAction, UnaryOperator};
+use core::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+  Literal(Value),
+  /// The value bound to the parameter numbered `index`, NULL when none is.
+  Parameter(usize),
+  /// The value at `index` in the current row, which unlike a column has no
+  /// affinity or collating sequence: the result of an aggregate, say.
+  Slot(usize),
+  /// The column at `index` in the current row when `depth` is zero, or in
+  /// the row of the query `depth` levels above.
+  Column {
+    depth: usize,
+    index: usize,
+    affinity: Affinity,
+    collation: Collation,
+  },
+  Unary {
+    operator: UnaryOperator,
+    expr: Box<Expr>,
+  },
+  /// Arithmetic and bitwise operators, and `||`.
+  Binary {
+    operator: BinaryOperator,
+    left: Box<Expr>,
+    right: Box<Expr>,
+  },
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Compare {
+    operator: Comparison,
+    left: Box<Expr>,
+    right: Box<Expr>,
+    comparator: Comparator,
+  },
+  IsNull {
+    expr: Box<Expr>,
+    not: bool,
+  },
+  Like {
+    expr: Box<Expr>,
+    not: bool,
+    glob: bool,
+    pattern: Box<Expr>,
+    escape: Option<Box<Expr>>,
+  },
+  /// `expr BETWEEN low AND high`, the same as `expr >= low AND expr <= high`
+  /// with `expr` evaluated once.
+  Between {
+    expr: Box<Expr>,
+    not: bool,
+    low: Box<Expr>,
+    high: Box<Expr>,
+    low_comparator: Comparator,
+    high_comparator: Comparator,
+  },
+  InList {
+    expr: Box<Expr>,
+    not: bool,
+    list: Vec<Expr>,
+    comparator: Comparator,
+  },
+  /// `expr IN (SELECT ...)`, which compares as the subquery says.
+  InSubquery {
+    expr: Box<Expr>,
+    not: bool,
+    subquery: SubqueryRef,
+  },
+  /// The first value of the first row of a subquery, NULL without rows.
+  Subquery(SubqueryRef),
+  Exists(SubqueryRef),
+  /// The value of the window function call at `index` among those of its
+  /// query, until the position its window puts it at in the rows is known.
+  Window(usize),
+  /// Only changes how the expression compares.
+  Collate {
+    expr: Box<Expr>,
+    collation: Collation,
+  },
+  Cast {
+    expr: Box<Expr>,
+    affinity: Affinity,
+  },
+  Case {
+    /// The operand of `CASE operand WHEN ...`, and how it compares to each
+    /// `WHEN` value.
+    operand: Option<(Box<Expr>, Vec<Comparator>)>,
+    when_then: Vec<(Expr, Expr)>,
+    else_expr: Option<Box<Expr>>,
+  },
+  /// A call to a scalar function, which compares values with `collation`
+  /// when it compares them.
+  Function {
+    function: ScalarFunction,
+    arguments: Vec<Expr>,
+    collation: Collation,
+  },
+  /// `RAISE()` in a trigger program, which fails with its message, the
+  /// action saying what becomes of the statement.
+  Raise {
+    action: RaiseAction,
+    message: Option<Box<Expr>>,
+  },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Comparison {
+  Eq,
+  NotEq,
+  Lt,
+  LtEq,
+  Gt,
+  GtEq,
+  /// `IS`, for which two NULLs are equal.
+  Is,
+  IsNot,
+}
+
+/// How the two sides of a comparison compare: the affinity applied to both,
+/// and the collating sequence of text.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Comparator {
+  affinity: Option<Affinity>,
+  collation: Collation,
+}
+
+impl Comparator {
+  /// The comparator of `left` and `right`. An explicit `COLLATE` on the left
+  /// wins over one on the right, which wins over the collating sequence of
+  /// a column on the left, then on the right.
+  ///
+  /// *Reference:* https://www.sqlite.org/datatype3.html#collating_sequences
+  pub(crate) fn new(left: &Expr, right: &Expr) -> Self {
+    let collation = match (left.collation(), right.collation()) {
+      (Some((collation, true)), _) | (_, Some((collation, true))) => collation,
+      (Some((collation, _)), _) | (_, Some((collation, _))) => collation,
+      (None, None) => Collation::Binary,
+    };
+    Self {
+      affinity: comparison_affinity(left.affinity(), right.affinity()),
+      collation,
+    }
+  }
+
+  /// The comparator of `expr IN (...)`, where the values of the list are
+  /// considered to have no affinity.
+  pub(crate) fn for_in(expr: &Expr) -> Self {
+    Self {
+      affinity: expr.affinity(),
+      collation: expr.collation().map(|(c, _)| c).unwrap_or_default(),
+    }
+  }
+
+  pub(crate) fn affinity(&self) -> Option<Affinity> {
+    self.affinity
+  }
+
+  pub(crate) fn collation(&self) -> &Collation {
+    &self.collation
+  }
+
+  /// `None` when either value is NULL.
+  pub(crate) fn compare(
+    &self,
+    left: &Value,
+    right: &Value,
+  ) -> Option<Ordering> {
+    compare(left, right, self.affinity, &self.collation)
+  }
+}
+
+impl Expr {
+  /// The affinity of the expression: columns have the affinity of their
+  /// declared type, `CAST` expressions that of their target type, and other
+  /// expressions have none.
+  pub(crate) fn affinity(&self) -> Option<Affinity> {
+    match self {
+      Self::Column { affinity, .. } | Self::Cast { affinity, .. } => {
+        Some(*affinity)
+      }
+      Self::Collate { expr, .. } => expr.affinity(),
+      _ => None,
+    }
+  }
+
+  /// The collating sequence of the expression, and whether it was given with
+  /// an explicit `COLLATE`.
+  pub(crate) fn collation(&self) -> Option<(Collation, bool)> {
+    match self {
+      Self::Collate { collation, .. } => Some((collation.clone(), true)),
+      Self::Column { collation, .. } => Some((collation.clone(), false)),
+      Self::Cast { expr, .. }
+      | Self::Unary {
+        operator: UnaryOperator::Plus,
+        expr,
+      } => expr.collation(),
+      Self::Binary { left, right, .. } => {
+        let explicit = |expr: &Expr| expr.collation().filter(|(_, is)| *is);
+        explicit(left).or_else(|| explicit(right))
+      }
+      _ => None,
+    }
+  }
+
+  /// Calls `f` with the position of every value of the current row that the
+  /// expression reads.
+  pub(crate) fn visit_columns(&self, f: &mut impl FnMut(usize)) {
+    match self {
+      Self::Slot(index)
+      | Self::Column {
+        depth: 0, index, ..
+      } => f(*index),
+      Self::Literal(_)
+      | Self::Parameter(_)
+      | Self::Column { .. }
+      | Self::Window(_) => {}
+      Self::Unary { expr, .. }
+      | Self::IsNull { expr, .. }
+      | Self::Collate { expr, .. }
+      | Self::Cast { expr, .. } => expr.visit_columns(f),
+      Self::Binary { left, right, .. }
+      | Self::And(left, right)
+      | Self::Or(left, right)
+      | Self::Compare { left, right, .. } => {
+        left.visit_columns(f);
+        right.visit_columns(f);
+      }
+      Self::Like {
+        expr,
+        pattern,
+        escape,
+        ..
+      } => {
+        expr.visit_columns(f);
+        pattern.visit_columns(f);
+        if let Some(escape) = escape {
+          escape.visit_columns(f);
+        }
+      }
+      Self::Between {
+        expr, low, high, ..
+      } => {
+        expr.visit_columns(f);
+        low.visit_columns(f);
+        high.visit_columns(f);
+      }
+      Self::InList { expr, list, .. } => {
+        expr.visit_columns(f);
+        list.iter().for_each(|item| item.visit_columns(f));
+      }
+      Self::InSubquery { expr, subquery, .. } => {
+        expr.visit_columns(f);
+        subquery.columns.iter().for_each(|&index| f(index));
+      }
+      Self::Subquery(subquery) | Self::Exists(subquery) => {
+        subquery.columns.iter().for_each(|&index| f(index));
+      }
+      Self::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        if let Some((operand, _)) = operand {
+          operand.visit_columns(f);
+        }
+        for (when, then) in when_then {
+          when.visit_columns(f);
+          then.visit_columns(f);
+        }
+        if let Some(else_expr) = else_expr {
+          else_expr.visit_columns(f);
+        }
+      }
+      Self::Function { arguments, .. } => {
+        arguments
+          .iter()
+          .for_each(|argument| argument.visit_columns(f));
+      }
+      Self::Raise { message, .. } => {
+        if let Some(message) = message {
+          message.visit_columns(f);
+        }
+      }
+    }
+  }
+
+  /// Calls `f` with every expression within this one, inner ones first,
+  /// for it to rewrite them.
+  pub(crate) fn visit_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+    match self {
+      Self::Literal(_)
+      | Self::Parameter(_)
+      | Self::Slot(_)
+      | Self::Column { .. }
+      | Self::Subquery(_)
+      | Self::Exists(_)
+      | Self::Window(_) => {}
+      Self::Unary { expr, .. }
+      | Self::IsNull { expr, .. }
+      | Self::InSubquery { expr, .. }
+      | Self::Collate { expr, .. }
+      | Self::Cast { expr, .. } => expr.visit_mut(f),
+      Self::Binary { left, right, .. }
+      | Self::And(left, right)
+      | Self::Or(left, right)
+      | Self::Compare { left, right, .. } => {
+        left.visit_mut(f);
+        right.visit_mut(f);
+      }
+      Self::Like {
+        expr,
+        pattern,
+        escape,
+        ..
+      } => {
+        expr.visit_mut(f);
+        pattern.visit_mut(f);
+        if let Some(escape) = escape {
+          escape.visit_mut(f);
+        }
+      }
+      Self::Between {
+        expr, low, high, ..
+      } => {
+        expr.visit_mut(f);
+        low.visit_mut(f);
+        high.visit_mut(f);
+      }
+      Self::InList { expr, list, .. } => {
+        expr.visit_mut(f);
+        list.iter_mut().for_each(|item| item.visit_mut(f));
+      }
+      Self::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        if let Some((operand, _)) = operand {
+          operand.visit_mut(f);
+        }
+        for (when, then) in when_then {
+          when.visit_mut(f);
+          then.visit_mut(f);
+        }
+        if let Some(else_expr) = else_expr {
+          else_expr.visit_mut(f);
+        }
+      }
+      Self::Function { arguments, .. } => {
+        arguments
+          .iter_mut()
+          .for_each(|argument| argument.visit_mut(f));
+      }
+      Self::Raise { message, .. } => {
+        if let Some(message) = message {
+          message.visit_mut(f);
+        }
+      }
+    }
+    f(self);
+  }
+
+  pub(crate) fn eval(
+    &self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<Value> {
+    Ok(match self {
+      Self::Literal(value) => value.clone(),
+      Self::Parameter(index) => ctx.parameter(*index),
+      Self::Slot(index) => column(row, *index)?,
+      Self::Column {
+        depth: 0, index, ..
+      } => column(row, *index)?,
+      Self::Column { depth, index, .. } => {
+        column(ctx.outer_row(*depth)?, *index)?
+      }
+      Self::Unary { operator, expr } => {
+        let value = expr.eval(ctx, row)?;
+        match operator {
+          UnaryOperator::Negate => negate(&value),
+          UnaryOperator::Plus => value,
+          UnaryOperator::Not => from_bool(is_true(&value).map(|is| !is)),
+          UnaryOperator::BitNot => bit_not(&value),
+        }
+      }
+      Self::Binary {
+        operator,
+        left,
+        right,
+      } => {
+        let left = left.eval(ctx, row)?;
+        binary(*operator, &left, &right.eval(ctx, row)?)
+      }
+      Self::And(left, right) => {
+        let left = is_true(&left.eval(ctx, row)?);
+        if left == Some(false) {
+          return Ok(from_bool(Some(false)));
+        }
+        match (left, is_true(&right.eval(ctx, row)?)) {
+          (_, Some(false)) => from_bool(Some(false)),
+          (Some(true), Some(true)) => from_bool(Some(true)),
+          _ => Value::Null,
+        }
+      }
+      Self::Or(left, right) => {
+        let left = is_true(&left.eval(ctx, row)?);
+        if left == Some(true) {
+          return Ok(from_bool(Some(true)));
+        }
+        match (left, is_true(&right.eval(ctx, row)?)) {
+          (_, Some(true)) => from_bool(Some(true)),
+          (Some(false), Some(false)) => from_bool(Some(false)),
+          _ => Value::Null,
+        }
+      }
+      Self::Compare {
+        operator,
+        left,
+        right,
+        comparator,
+      } => {
+        let left = left.eval(ctx, row)?;
+        let right = right.eval(ctx, row)?;
+        from_bool(compare_with(*operator, &left, &right, comparator))
+      }
+      Self::IsNull { expr, not } => {
+        from_bool(Some(expr.eval(ctx, row)?.is_null() != *not))
+      }
+      Self::Like {
+        expr,
+        not,
+        glob: is_glob,
+        pattern,
+        escape,
+      } => {
+        let text = expr.eval(ctx, row)?;
+        let pattern = pattern.eval(ctx, row)?;
+        let escape = match escape {
+          Some(escape) => Some(escape.eval(ctx, row)?),
+          None => None,
+        };
+        let is_match =
+          pattern_match(*is_glob, &text, &pattern, escape.as_ref())?;
+        from_bool(is_match.map(|is_match| is_match != *not))
+      }
+      Self::Between {
+        expr,
+        not,
+        low,
+        high,
+        low_comparator,
+        high_comparator,
+      } => {
+        let value = expr.eval(ctx, row)?;
+        let low = low.eval(ctx, row)?;
+        let high = high.eval(ctx, row)?;
+        let above =
+          compare_with(Comparison::GtEq, &value, &low, low_comparator);
+        let below =
+          compare_with(Comparison::LtEq, &value, &high, high_comparator);
+        let between = match (above, below) {
+          (Some(false), _) | (_, Some(false)) => Some(false),
+          (Some(true), Some(true)) => Some(true),
+          _ => None,
+        };
+        from_bool(between.map(|between| between != *not))
+      }
+      Self::InList {
+        expr,
+        not,
+        list,
+        comparator,
+      } => {
+        if list.is_empty() {
+          return Ok(from_bool(Some(*not)));
+        }
+        let value = expr.eval(ctx, row)?;
+        if value.is_null() {
+          return Ok(Value::Null);
+        }
+        let mut found = Some(false);
+        for item in list {
+          match comparator.compare(&value, &item.eval(ctx, row)?) {
+            Some(Ordering::Equal) => {
+              found = Some(true);
+              break;
+            }
+            None => found = None,
+            Some(_) => {}
+          }
+        }
+        from_bool(found.map(|found| found != *not))
+      }
+      Self::InSubquery {
+        expr,
+        not,
+        subquery,
+      } => {
+        let value = expr.eval(ctx, row)?;
+        let found = ctx.subquery_contains(subquery.id, row, &value)?;
+        from_bool(found.map(|found| found != *not))
+      }
+      Self::Subquery(subquery) | Self::Exists(subquery) => {
+        ctx.subquery_value(subquery.id, row)?
+      }
+      Self::Window(index) => {
+        return Err(SqliteError::Custom(format!(
+          "Window function {index} was not computed"
+        )))
+      }
+      Self::Collate { expr, .. } => expr.eval(ctx, row)?,
+      Self::Cast { expr, affinity } => affinity.cast(expr.eval(ctx, row)?),
+      Self::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        let operand = match operand {
+          Some((operand, comparators)) => {
+            Some((operand.eval(ctx, row)?, comparators))
+          }
+          None => None,
+        };
+        for (idx, (when, then)) in when_then.iter().enumerate() {
+          let when = when.eval(ctx, row)?;
+          let is_match = match &operand {
+            Some((operand, comparators)) => {
+              comparators[idx].compare(operand, &when) == Some(Ordering::Equal)
+            }
+            None => is_true(&when) == Some(true),
+          };
+          if is_match {
+            return then.eval(ctx, row);
+          }
+        }
+        match else_expr {
+          Some(else_expr) => else_expr.eval(ctx, row)?,
+          None => Value::Null,
+        }
+      }
+      // The arguments after the first that is not NULL are not evaluated.
+      Self::Function {
+        function: ScalarFunction::Coalesce,
+        arguments,
+        ..
+      } => {
+        for argument in arguments {
+          let value = argument.eval(ctx, row)?;
+          if !value.is_null() {
+            return Ok(value);
+          }
+        }
+        Value::Null
+      }
+      Self::Function {
+        function,
+        arguments,
+        collation,
+      } => {
+        let arguments = arguments
+          .iter()
+          .map(|argument| argument.eval(ctx, row))
+          .collect::<SqliteResult<Vec<_>>>()?;
+        function.call(ctx, &arguments, collation)?
+      }
+      Self::Raise { action, message } => {
+        let message = match message {
+          Some(message) => to_text(&message.eval(ctx, row)?),
+          None => None,
+        };
+        ctx.raised = Some(*action);
+        return Err(SqliteError::Constraint {
+          kind: ConstraintKind::Trigger,
+          message: message.unwrap_or_default(),
+        });
+      }
+    })
+  }
+}
+
+fn column(row: &[Value], index: usize) -> SqliteResult<Value> {
+  row.get(index).cloned().ok_or(SqliteError::Custom(format!(
+    "Column {index} is out of the bounds of a row of {} values",
+    row.len()
+  )))
+}
+
+/// Compares two values, `None` standing for NULL.
+pub(crate) fn compare_with(
+  operator: Comparison,
+  left: &Value,
+  right: &Value,
+  comparator: &Comparator,
+) -> Option<bool> {
+  let ordering = comparator.compare(left, right);
+  match operator {
+    Comparison::Is | Comparison::IsNot => {
+      let same = match (left.is_null(), right.is_null()) {
+        (true, true) => true,
+        (false, false) => ordering == Some(Ordering::Equal),
+        _ => false,
+      };
+      Some(same == (operator == Comparison::Is))
+    }
+    Comparison::Eq => ordering.map(Ordering::is_eq),
+    Comparison::NotEq => ordering.map(Ordering::is_ne),
+    Comparison::Lt => ordering.map(Ordering::is_lt),
+    Comparison::LtEq => ordering.map(Ordering::is_le),
+    Comparison::Gt => ordering.map(Ordering::is_gt),
+    Comparison::GtEq => ordering.map(Ordering::is_ge),
+  }
+}
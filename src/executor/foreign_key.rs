@@ -0,0 +1,421 @@
+//! # Foreign keys
+//!
+//!  When `PRAGMA foreign_keys` is on, a statement changing a table checks
+//! that each row of a child table it writes references a row of the parent
+//! table, and runs the `ON DELETE` and `ON UPDATE` actions of the foreign
+//! keys referencing the rows it changes. The tables the actions change are
+//! followed in turn, along with their own foreign keys.
+//!
+//!  A row whose parent is missing once written is only an error if it still
+//! is when the statement completes, so that a statement may insert rows
+//! referencing each other. Within a transaction opened by `BEGIN`, the rows
+//! of deferred foreign keys are only checked by `COMMIT`.
+//!
+//! *Reference:* https://www.sqlite.org/foreignkeys.html
+
+use std::cmp::Ordering;
+
+use super::dml::Target;
+use super::transaction::DeferredViolation;
+use super::Context;
+use crate::result::{ConstraintKind, SqliteError, SqliteResult};
+use crate::runtime::{compare_values, Value};
+use crate::sql::ast::ForeignKeyAction;
+
+/// The foreign keys a statement enforces, between the tables it may change
+/// or read.
+#[derive(Debug)]
+pub(crate) struct ForeignKeys {
+  /// The tables, the one the statement changes first.
+  tables: Vec<Target>,
+  keys: Vec<ForeignKey>,
+  /// The foreign keys found missing their parent, with the values of the
+  /// child columns, to check again once the statement completes.
+  pending: Vec<(usize, Vec<Value>)>,
+}
+
+/// A foreign key of the table `child` referencing the table `parent`, both
+/// positions in [ForeignKeys::tables].
+#[derive(Debug)]
+pub(crate) struct ForeignKey {
+  pub(crate) child: usize,
+  pub(crate) parent: usize,
+  pub(crate) child_columns: Vec<usize>,
+  pub(crate) parent_columns: Vec<usize>,
+  pub(crate) lookup: Lookup,
+  pub(crate) on_delete: ForeignKeyAction,
+  pub(crate) on_update: ForeignKeyAction,
+  /// `DEFERRABLE INITIALLY DEFERRED`: checked by `COMMIT` in transactions.
+  pub(crate) deferred: bool,
+}
+
+/// How the parent row of a foreign key is found from the values of its
+/// parent columns.
+#[derive(Debug)]
+pub(crate) enum Lookup {
+  /// The parent column is the alias of the rowid.
+  Rowid,
+  /// The parent columns are the primary key of a `WITHOUT ROWID` table,
+  /// given the position of each key column among them.
+  PrimaryKey(Vec<usize>),
+  /// The parent columns are those of the unique index at the given position
+  /// among the indexes of the parent table, given the position of each
+  /// indexed column among them.
+  Index(usize, Vec<usize>),
+}
+
+impl ForeignKey {
+  /// How the parent row of a foreign key referencing `parent_columns` of
+  /// `parent` is found. They must be the columns of its primary key or of a
+  /// unique index, with the same collations.
+  pub(crate) fn lookup(
+    child: &Target,
+    parent: &Target,
+    parent_columns: &[usize],
+  ) -> SqliteResult<Lookup> {
+    let definition = parent.definition();
+    let order = |columns: &[usize]| {
+      let mut sorted = columns.to_vec();
+      sorted.sort_unstable();
+      let mut expected = parent_columns.to_vec();
+      expected.sort_unstable();
+      (sorted.len() == columns.len() && sorted == expected).then(|| {
+        columns
+          .iter()
+          .filter_map(|c| parent_columns.iter().position(|p| p == c))
+          .collect::<Vec<_>>()
+      })
+    };
+    let collations = definition.columns().iter().map(|c| c.collation());
+    let collations = collations.collect::<Vec<_>>();
+    if definition.is_without_rowid() {
+      let key = definition.primary_key();
+      let columns = key.iter().map(|key| key.column()).collect::<Vec<_>>();
+      let same = key.iter().all(|k| collations[k.column()] == k.collation());
+      if let Some(order) = order(&columns).filter(|_| same) {
+        return Ok(Lookup::PrimaryKey(order));
+      }
+    } else if parent_columns.len() == 1
+      && definition.rowid_alias() == Some(parent_columns[0])
+    {
+      return Ok(Lookup::Rowid);
+    }
+    for (idx, index) in parent.indexes().iter().enumerate() {
+      if !index.unique || index.condition.is_some() {
+        continue;
+      }
+      let Some(columns) = index.columns() else {
+        continue;
+      };
+      let same = columns
+        .iter()
+        .zip(index.key_info.columns())
+        .all(|(&c, key)| *collations[c] == key.collation);
+      if let Some(order) = order(&columns).filter(|_| same) {
+        return Ok(Lookup::Index(idx, order));
+      }
+    }
+    Err(SqliteError::Custom(format!(
+      "foreign key mismatch - \"{}\" referencing \"{}\"",
+      child.name(),
+      parent.name()
+    )))
+  }
+}
+
+impl ForeignKeys {
+  pub(crate) fn new(tables: Vec<Target>, keys: Vec<ForeignKey>) -> Self {
+    Self {
+      tables,
+      keys,
+      pending: vec![],
+    }
+  }
+
+  /// Checks the parent of `row`, written to the table at position `table`.
+  pub(crate) fn inserted(
+    &mut self,
+    ctx: &mut Context<'_>,
+    table: usize,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    for key in 0..self.keys.len() {
+      if self.keys[key].child == table {
+        self.check_parent(ctx, key, row)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Runs the `ON DELETE` actions of the foreign keys referencing `row`,
+  /// removed from the table at position `table`.
+  pub(crate) fn deleted(
+    &mut self,
+    ctx: &mut Context<'_>,
+    table: usize,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    for key in 0..self.keys.len() {
+      if self.keys[key].parent != table {
+        continue;
+      }
+      let values = select(row, &self.keys[key].parent_columns);
+      let action = self.keys[key].on_delete;
+      self.act(ctx, key, action, values, None)?;
+    }
+    Ok(())
+  }
+
+  /// Checks the parent of `new`, the row `old` of the table at position
+  /// `table` is updated to, and runs the `ON UPDATE` actions of the foreign
+  /// keys referencing `old`.
+  pub(crate) fn updated(
+    &mut self,
+    ctx: &mut Context<'_>,
+    table: usize,
+    old: &[Value],
+    new: &[Value],
+  ) -> SqliteResult<()> {
+    for key in 0..self.keys.len() {
+      let child_columns = &self.keys[key].child_columns;
+      if self.keys[key].child == table
+        && select(old, child_columns) != select(new, child_columns)
+      {
+        self.check_parent(ctx, key, new)?;
+      }
+    }
+    for key in 0..self.keys.len() {
+      if self.keys[key].parent != table {
+        continue;
+      }
+      let old_values = select(old, &self.keys[key].parent_columns);
+      let new_values = select(new, &self.keys[key].parent_columns);
+      if old_values != new_values {
+        let action = self.keys[key].on_update;
+        self.act(ctx, key, action, old_values, Some(new_values))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks the foreign keys found missing their parent once the statement
+  /// completes: their parent must have been written since, or the rows
+  /// referencing it changed. Deferred ones are left for `COMMIT` to check
+  /// within a transaction.
+  pub(crate) fn check(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    for (key, values) in std::mem::take(&mut self.pending) {
+      if self.parent_exists(ctx, key, &values)?
+        || self.children(ctx, key, &values)?.is_empty()
+      {
+        continue;
+      }
+      let foreign_key = &self.keys[key];
+      if !(foreign_key.deferred && ctx.conn.in_transaction) {
+        return Err(failed());
+      }
+      ctx.conn.deferred_violations.push(DeferredViolation {
+        child: self.tables[foreign_key.child].name().to_owned(),
+        parent: self.tables[foreign_key.parent].name().to_owned(),
+        child_columns: foreign_key.child_columns.clone(),
+        values,
+      });
+    }
+    Ok(())
+  }
+
+  /// Whether the row found missing its parent by `violation` still does,
+  /// and is still there.
+  pub(crate) fn is_violated(
+    &mut self,
+    ctx: &mut Context<'_>,
+    violation: &DeferredViolation,
+  ) -> SqliteResult<bool> {
+    let key = self.keys.iter().position(|key| {
+      self.tables[key.child]
+        .name()
+        .eq_ignore_ascii_case(&violation.child)
+        && self.tables[key.parent]
+          .name()
+          .eq_ignore_ascii_case(&violation.parent)
+        && key.child_columns == violation.child_columns
+    });
+    let Some(key) = key else {
+      return Ok(false);
+    };
+    Ok(
+      !self.parent_exists(ctx, key, &violation.values)?
+        && !self.children(ctx, key, &violation.values)?.is_empty(),
+    )
+  }
+
+  /// Checks that the parent of `row`, a row of the child table of `key`,
+  /// exists, unless one of its child columns is NULL.
+  fn check_parent(
+    &mut self,
+    ctx: &mut Context<'_>,
+    key: usize,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    let values = select(row, &self.keys[key].child_columns);
+    if !values.iter().any(Value::is_null)
+      && !self.parent_exists(ctx, key, &values)?
+    {
+      self.pending.push((key, values));
+    }
+    Ok(())
+  }
+
+  /// Runs `action` on the rows of the child table of `key` referencing the
+  /// parent row whose parent columns held `values`, and now hold
+  /// `new_values` when it is updated.
+  fn act(
+    &mut self,
+    ctx: &mut Context<'_>,
+    key: usize,
+    action: ForeignKeyAction,
+    values: Vec<Value>,
+    new_values: Option<Vec<Value>>,
+  ) -> SqliteResult<()> {
+    if values.iter().any(Value::is_null) {
+      return Ok(());
+    }
+    let children = self.children(ctx, key, &values)?;
+    if children.is_empty() {
+      return Ok(());
+    }
+    let child = self.keys[key].child;
+    let columns = self.keys[key].child_columns.clone();
+    match (action, new_values) {
+      (ForeignKeyAction::Restrict, _) => return Err(failed()),
+      (ForeignKeyAction::NoAction, _) => self.pending.push((key, values)),
+      (ForeignKeyAction::Cascade, None) => {
+        for row in children {
+          let key = self.tables[child].key(&row);
+          // Rows may be deleted by the actions of earlier ones.
+          if let Some(row) = self.tables[child].find(ctx, &key)? {
+            self.tables[child].delete(ctx, &row)?;
+            self.deleted(ctx, child, &row)?;
+          }
+        }
+      }
+      (action, new_values) => {
+        for row in children {
+          let mut new = row.clone();
+          for (idx, &column) in columns.iter().enumerate() {
+            new[column] = match (action, &new_values) {
+              (ForeignKeyAction::Cascade, Some(values)) => values[idx].clone(),
+              (ForeignKeyAction::SetDefault, _) => {
+                self.tables[child].default(ctx, column)?
+              }
+              _ => Value::Null,
+            };
+          }
+          // The default may reference the parent row gone.
+          if action == ForeignKeyAction::SetDefault {
+            self.check_parent(ctx, key, &new)?;
+          }
+          self.update(ctx, child, &row, new)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Updates `old`, a row of the table at position `table`, to `new`.
+  fn update(
+    &mut self,
+    ctx: &mut Context<'_>,
+    table: usize,
+    old: &[Value],
+    mut new: Vec<Value>,
+  ) -> SqliteResult<()> {
+    let target = &mut self.tables[table];
+    if target.apply_affinity(&mut new)? {
+      return Err(SqliteError::Custom("datatype mismatch".into()));
+    }
+    let key = target.key(old);
+    target.check_all(ctx, &mut new, Some(&key))?;
+    target.delete(ctx, old)?;
+    target.insert(ctx, &new)?;
+    self.updated(ctx, table, old, &new)
+  }
+
+  /// Whether the parent table of `key` has a row whose parent columns hold
+  /// `values`, given in the order of the child columns.
+  fn parent_exists(
+    &mut self,
+    ctx: &mut Context<'_>,
+    key: usize,
+    values: &[Value],
+  ) -> SqliteResult<bool> {
+    let foreign_key = &self.keys[key];
+    let parent = &mut self.tables[foreign_key.parent];
+    let columns = parent.definition().columns();
+    let values = values
+      .iter()
+      .zip(&foreign_key.parent_columns)
+      .map(|(value, &c)| columns[c].affinity().apply(value.clone()))
+      .collect::<Vec<_>>();
+    let ordered = |order: &[usize]| {
+      order
+        .iter()
+        .map(|&idx| values[idx].clone())
+        .collect::<Vec<_>>()
+    };
+    match &foreign_key.lookup {
+      Lookup::Rowid => match values[0] {
+        Value::Integer(rowid) => {
+          Ok(parent.find(ctx, &[Value::Integer(rowid)])?.is_some())
+        }
+        _ => Ok(false),
+      },
+      Lookup::PrimaryKey(order) => {
+        Ok(parent.find(ctx, &ordered(order))?.is_some())
+      }
+      Lookup::Index(index, order) => {
+        parent.has_entry(ctx, *index, &ordered(order))
+      }
+    }
+  }
+
+  /// The rows of the child table of `key` referencing the parent row whose
+  /// parent columns hold `values`. They are compared as the parent columns
+  /// are, with their affinities and collations.
+  fn children(
+    &mut self,
+    ctx: &mut Context<'_>,
+    key: usize,
+    values: &[Value],
+  ) -> SqliteResult<Vec<Vec<Value>>> {
+    let foreign_key = &self.keys[key];
+    let parent = self.tables[foreign_key.parent].definition().clone();
+    let columns = foreign_key
+      .child_columns
+      .iter()
+      .zip(&foreign_key.parent_columns)
+      .map(|(&child, &p)| (child, &parent.columns()[p]))
+      .collect::<Vec<_>>();
+    let rows = self.tables[foreign_key.child].rows(ctx)?;
+    let references = |row: &Vec<Value>| {
+      columns.iter().zip(values).all(|(&(child, column), value)| {
+        let own = column.affinity().apply(row[child].clone());
+        let value = column.affinity().apply(value.clone());
+        !own.is_null()
+          && compare_values(&own, &value, column.collation()) == Ordering::Equal
+      })
+    };
+    Ok(rows.into_iter().filter(references).collect())
+  }
+}
+
+/// The values of `row` for `columns`.
+fn select(row: &[Value], columns: &[usize]) -> Vec<Value> {
+  columns.iter().map(|&column| row[column].clone()).collect()
+}
+
+fn failed() -> SqliteError {
+  SqliteError::Constraint {
+    kind: ConstraintKind::ForeignKey,
+    message: "FOREIGN KEY constraint failed".into(),
+  }
+}
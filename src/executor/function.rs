@@ -0,0 +1,529 @@
+//! # Core functions
+//!
+//!  The scalar functions SQLite always provides. They compute one value from
+//! the values of their arguments, which most of them convert to text or to
+//! numbers first, and most return NULL when an argument is NULL.
+//!
+//! *Reference:* https://www.sqlite.org/lang_corefunc.html
+
+use super::datetime;
+use super::json::JsonFunction;
+use super::pattern::pattern_match;
+use super::printf;
+use super::user_function::UserFunction;
+use super::value::from_bool;
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  compare_values, to_integer, to_numeric, to_real, to_text, Collation, Value,
+};
+use crate::VERSION_NUMBER;
+use core::cmp::Ordering;
+use core::hash::{BuildHasher, Hasher};
+use core::ops::RangeInclusive;
+use core::sync::atomic::{self, AtomicU64};
+use std::collections::hash_map::RandomState;
+
+/// The largest string or blob a function returns.
+const MAX_LENGTH: i64 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ScalarFunction {
+  Abs,
+  Changes,
+  Char,
+  /// `coalesce()`, and `ifnull()` with two arguments.
+  Coalesce,
+  Date,
+  Datetime,
+  Glob,
+  Hex,
+  Instr,
+  /// A JSON function, and whether it returns JSONB.
+  Json {
+    function: JsonFunction,
+    binary: bool,
+  },
+  Julianday,
+  LastInsertRowid,
+  Length,
+  Like,
+  Lower,
+  Ltrim,
+  Max,
+  Min,
+  Nullif,
+  OctetLength,
+  /// `printf()`, also named `format()`.
+  Printf,
+  Quote,
+  Random,
+  Randomblob,
+  Replace,
+  Round,
+  Rtrim,
+  SqliteVersion,
+  Strftime,
+  /// `substr()`, also named `substring()`.
+  Substr,
+  Time,
+  Timediff,
+  TotalChanges,
+  Trim,
+  Typeof,
+  Unhex,
+  Unicode,
+  Unixepoch,
+  Upper,
+  /// A function registered on the connection.
+  User(UserFunction),
+  Zeroblob,
+}
+
+impl ScalarFunction {
+  /// The scalar function named `name`, and the numbers of arguments it
+  /// takes. `iif()` is not one: it is compiled as a `CASE` expression.
+  pub(crate) fn from_name(name: &str) -> Option<(Self, RangeInclusive<usize>)> {
+    const ANY: usize = usize::MAX;
+    let name = name.to_ascii_lowercase();
+    if let Some((function, binary, counts)) = JsonFunction::from_name(&name) {
+      return Some((Self::Json { function, binary }, counts));
+    }
+    Some(match name.as_str() {
+      "abs" => (Self::Abs, 1..=1),
+      "changes" => (Self::Changes, 0..=0),
+      "char" => (Self::Char, 0..=ANY),
+      "coalesce" => (Self::Coalesce, 2..=ANY),
+      "date" => (Self::Date, 0..=ANY),
+      "datetime" => (Self::Datetime, 0..=ANY),
+      "format" | "printf" => (Self::Printf, 0..=ANY),
+      "glob" => (Self::Glob, 2..=2),
+      "hex" => (Self::Hex, 1..=1),
+      "ifnull" => (Self::Coalesce, 2..=2),
+      "instr" => (Self::Instr, 2..=2),
+      "julianday" => (Self::Julianday, 0..=ANY),
+      "last_insert_rowid" => (Self::LastInsertRowid, 0..=0),
+      "length" => (Self::Length, 1..=1),
+      "like" => (Self::Like, 2..=3),
+      "lower" => (Self::Lower, 1..=1),
+      "ltrim" => (Self::Ltrim, 1..=2),
+      "max" => (Self::Max, 2..=ANY),
+      "min" => (Self::Min, 2..=ANY),
+      "nullif" => (Self::Nullif, 2..=2),
+      "octet_length" => (Self::OctetLength, 1..=1),
+      "quote" => (Self::Quote, 1..=1),
+      "random" => (Self::Random, 0..=0),
+      "randomblob" => (Self::Randomblob, 1..=1),
+      "replace" => (Self::Replace, 3..=3),
+      "round" => (Self::Round, 1..=2),
+      "rtrim" => (Self::Rtrim, 1..=2),
+      "sqlite_version" => (Self::SqliteVersion, 0..=0),
+      "strftime" => (Self::Strftime, 0..=ANY),
+      "substr" | "substring" => (Self::Substr, 2..=3),
+      "time" => (Self::Time, 0..=ANY),
+      "timediff" => (Self::Timediff, 2..=2),
+      "total_changes" => (Self::TotalChanges, 0..=0),
+      "trim" => (Self::Trim, 1..=2),
+      "typeof" => (Self::Typeof, 1..=1),
+      "unhex" => (Self::Unhex, 1..=2),
+      "unicode" => (Self::Unicode, 1..=1),
+      "unixepoch" => (Self::Unixepoch, 0..=ANY),
+      "upper" => (Self::Upper, 1..=1),
+      "zeroblob" => (Self::Zeroblob, 1..=1),
+      _ => return None,
+    })
+  }
+
+  pub(crate) fn name(&self) -> &str {
+    match self {
+      Self::Abs => "abs",
+      Self::Changes => "changes",
+      Self::Char => "char",
+      Self::Coalesce => "coalesce",
+      Self::Date => "date",
+      Self::Datetime => "datetime",
+      Self::Glob => "glob",
+      Self::Hex => "hex",
+      Self::Instr => "instr",
+      Self::Json { function, binary } => function.name(*binary),
+      Self::Julianday => "julianday",
+      Self::LastInsertRowid => "last_insert_rowid",
+      Self::Length => "length",
+      Self::Like => "like",
+      Self::Lower => "lower",
+      Self::Ltrim => "ltrim",
+      Self::Max => "max",
+      Self::Min => "min",
+      Self::Nullif => "nullif",
+      Self::OctetLength => "octet_length",
+      Self::Printf => "printf",
+      Self::Quote => "quote",
+      Self::Random => "random",
+      Self::Randomblob => "randomblob",
+      Self::Replace => "replace",
+      Self::Round => "round",
+      Self::Rtrim => "rtrim",
+      Self::SqliteVersion => "sqlite_version",
+      Self::Strftime => "strftime",
+      Self::Substr => "substr",
+      Self::Time => "time",
+      Self::Timediff => "timediff",
+      Self::TotalChanges => "total_changes",
+      Self::Trim => "trim",
+      Self::Typeof => "typeof",
+      Self::Unhex => "unhex",
+      Self::Unicode => "unicode",
+      Self::Unixepoch => "unixepoch",
+      Self::Upper => "upper",
+      Self::User(function) => function.name(),
+      Self::Zeroblob => "zeroblob",
+    }
+  }
+
+  /// Whether the function compares its arguments, with the collating
+  /// sequence of the first of them that has one.
+  pub(crate) fn needs_collation(&self) -> bool {
+    matches!(self, Self::Max | Self::Min | Self::Nullif)
+  }
+
+  /// Whether the function always returns the same result for the same
+  /// arguments.
+  pub(crate) fn is_deterministic(&self) -> bool {
+    match self {
+      Self::Changes
+      | Self::LastInsertRowid
+      | Self::Random
+      | Self::Randomblob
+      | Self::TotalChanges => false,
+      Self::User(function) => function.is_deterministic(),
+      _ => true,
+    }
+  }
+
+  /// Calls the function with the values of its `arguments`.
+  pub(crate) fn call(
+    &self,
+    ctx: &Context<'_>,
+    arguments: &[Value],
+    collation: &Collation,
+  ) -> SqliteResult<Value> {
+    let argument = |idx: usize| arguments.get(idx).unwrap_or(&Value::Null);
+    let (first, second) = (argument(0), argument(1));
+    Ok(match self {
+      Self::Changes => Value::Integer(ctx.changes() as i64),
+      Self::LastInsertRowid => Value::Integer(ctx.last_insert_rowid()),
+      Self::TotalChanges => Value::Integer(ctx.total_changes() as i64),
+      Self::SqliteVersion => Value::Text(version()),
+      Self::Random => Value::Integer(random() as i64),
+      Self::Json { function, binary } => {
+        return function.call(*binary, arguments)
+      }
+      Self::User(function) => return function.call(arguments),
+      Self::Char => Value::Text(
+        arguments
+          .iter()
+          .map(|value| {
+            u32::try_from(to_integer(value))
+              .ok()
+              .and_then(char::from_u32)
+              .unwrap_or(char::REPLACEMENT_CHARACTER)
+          })
+          .collect(),
+      ),
+      Self::Coalesce => arguments
+        .iter()
+        .find(|value| !value.is_null())
+        .cloned()
+        .unwrap_or(Value::Null),
+      Self::Printf => match to_text(first) {
+        Some(format) => Value::Text(printf::format(&format, &arguments[1..])),
+        None => Value::Null,
+      },
+      Self::Hex => {
+        let bytes = match first {
+          Value::Blob(blob) => blob.clone(),
+          value => to_text(value).unwrap_or_default().into_bytes(),
+        };
+        Value::Text(bytes.iter().map(|byte| format!("{byte:02X}")).collect())
+      }
+      Self::Like | Self::Glob => from_bool(pattern_match(
+        *self == Self::Glob,
+        second,
+        first,
+        arguments.get(2),
+      )?),
+      Self::Max | Self::Min => {
+        if arguments.iter().any(Value::is_null) {
+          return Ok(Value::Null);
+        }
+        // Of equal values, `max()` keeps the first and `min()` the last.
+        let mut best = first;
+        for value in &arguments[1..] {
+          let ordering = compare_values(value, best, collation);
+          let is_better = match self {
+            Self::Max => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Greater,
+          };
+          if is_better {
+            best = value;
+          }
+        }
+        best.clone()
+      }
+      Self::Nullif => match compare_values(first, second, collation) {
+        Ordering::Equal if !second.is_null() => Value::Null,
+        _ => first.clone(),
+      },
+      Self::Quote => Value::Text(quote(first)),
+      Self::Typeof => Value::Text(
+        match first {
+          Value::Null => "null",
+          Value::Integer(_) => "integer",
+          Value::Real(_) => "real",
+          Value::Text(_) => "text",
+          Value::Blob(_) => "blob",
+        }
+        .into(),
+      ),
+      Self::Zeroblob => Value::Blob(vec![0; blob_length(first)?]),
+      Self::Randomblob => {
+        let length = blob_length(first)?.max(1);
+        Value::Blob((0..length).map(|_| random() as u8).collect())
+      }
+      _ if arguments.iter().any(Value::is_null) => Value::Null,
+      Self::Date => datetime::date(ctx, arguments),
+      Self::Datetime => datetime::datetime(ctx, arguments),
+      Self::Julianday => datetime::julianday(ctx, arguments),
+      Self::Strftime => datetime::strftime(ctx, arguments),
+      Self::Time => datetime::time(ctx, arguments),
+      Self::Timediff => datetime::timediff(ctx, arguments),
+      Self::Unixepoch => datetime::unixepoch(ctx, arguments),
+      Self::Abs => match to_numeric_argument(first) {
+        Value::Integer(int) => Value::Integer(
+          int
+            .checked_abs()
+            .ok_or_else(|| SqliteError::Custom("integer overflow".into()))?,
+        ),
+        value => Value::Real(to_real(&value).abs()),
+      },
+      Self::Instr => instr(first, second),
+      Self::Length => match first {
+        Value::Blob(blob) => Value::Integer(blob.len() as i64),
+        value => {
+          let text = to_text(value).unwrap_or_default();
+          Value::Integer(text.chars().take_while(|&c| c != '\0').count() as i64)
+        }
+      },
+      Self::OctetLength => match first {
+        Value::Blob(blob) => Value::Integer(blob.len() as i64),
+        value => {
+          Value::Integer(to_text(value).unwrap_or_default().len() as i64)
+        }
+      },
+      Self::Lower => Value::Text(text(first).to_ascii_lowercase()),
+      Self::Upper => Value::Text(text(first).to_ascii_uppercase()),
+      Self::Ltrim | Self::Rtrim | Self::Trim => {
+        let characters = match arguments.len() {
+          1 => vec![' '],
+          _ => text(second).chars().collect(),
+        };
+        let text = text(first);
+        let trimmed = match self {
+          Self::Ltrim => text.trim_start_matches(characters.as_slice()),
+          Self::Rtrim => text.trim_end_matches(characters.as_slice()),
+          _ => text.trim_matches(characters.as_slice()),
+        };
+        Value::Text(trimmed.into())
+      }
+      Self::Replace => match text(second).as_str() {
+        "" => Value::Text(text(first)),
+        pattern => {
+          Value::Text(text(first).replace(pattern, &text(argument(2))))
+        }
+      },
+      Self::Round => round(first, arguments.get(1)),
+      Self::Substr => substr(first, second, arguments.get(2)),
+      Self::Unhex => match arguments.get(1) {
+        Some(ignored) => unhex(&text(first), &text(ignored)),
+        None => unhex(&text(first), ""),
+      },
+      Self::Unicode => match text(first).chars().next() {
+        Some(c) => Value::Integer(i64::from(u32::from(c))),
+        None => Value::Null,
+      },
+    })
+  }
+}
+
+/// The text of a value that is not NULL.
+fn text(value: &Value) -> String {
+  to_text(value).unwrap_or_default()
+}
+
+/// Numbers stay as they are, other values are read as REAL.
+fn to_numeric_argument(value: &Value) -> Value {
+  match value {
+    Value::Integer(_) | Value::Real(_) => value.clone(),
+    value => Value::Real(to_real(&to_numeric(value))),
+  }
+}
+
+/// The length of the blob `zeroblob()` and `randomblob()` return.
+fn blob_length(value: &Value) -> SqliteResult<usize> {
+  match to_integer(value) {
+    length if length > MAX_LENGTH => {
+      Err(SqliteError::Custom("string or blob too big".into()))
+    }
+    length => Ok(usize::try_from(length).unwrap_or(0)),
+  }
+}
+
+/// The version of the library, as `X.Y.Z`.
+fn version() -> String {
+  let number = VERSION_NUMBER.get().copied().unwrap_or(0);
+  format!(
+    "{}.{}.{}",
+    number / 10_000,
+    number / 100 % 100,
+    number % 100
+  )
+}
+
+/// A pseudo-random number, from a generator seeded once per process.
+fn random() -> u64 {
+  static STATE: AtomicU64 = AtomicU64::new(0);
+  let mut state = STATE.load(atomic::Ordering::Relaxed);
+  if state == 0 {
+    state = RandomState::new().build_hasher().finish() | 1;
+  }
+  state ^= state >> 12;
+  state ^= state << 25;
+  state ^= state >> 27;
+  STATE.store(state, atomic::Ordering::Relaxed);
+  state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+/// `quote(X)`: the text of an SQL literal for the value.
+fn quote(value: &Value) -> String {
+  match value {
+    Value::Null => "NULL".into(),
+    Value::Integer(int) => int.to_string(),
+    Value::Real(real) => {
+      // Enough digits to read the same value back.
+      let short = printf::format("%!.15g", &[Value::Real(*real)]);
+      match short.parse::<f64>() {
+        Ok(parsed) if parsed == *real => short,
+        _ => printf::format("%!.20e", &[Value::Real(*real)]),
+      }
+    }
+    Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+    Value::Blob(blob) => {
+      let hex = blob.iter().map(|byte| format!("{byte:02X}"));
+      format!("X'{}'", hex.collect::<String>())
+    }
+  }
+}
+
+/// `instr(X, Y)`: the position of the first `Y` within `X`, counted in
+/// bytes when both are blobs and in characters otherwise, 0 when there is
+/// none.
+fn instr(haystack: &Value, needle: &Value) -> Value {
+  let position = match (haystack, needle) {
+    (Value::Blob(haystack), Value::Blob(needle)) => match needle.len() {
+      0 => Some(0),
+      length => haystack.windows(length).position(|window| window == needle),
+    },
+    _ => {
+      let haystack = text(haystack);
+      haystack
+        .find(&text(needle))
+        .map(|at| haystack[..at].chars().count())
+    }
+  };
+  Value::Integer(position.map_or(0, |position| position as i64 + 1))
+}
+
+/// `round(X, Y)`: `X` rounded half away from zero to `Y` digits after the
+/// decimal point, between 0 and 30. NULL when `Y` is.
+fn round(value: &Value, digits: Option<&Value>) -> Value {
+  let digits = match digits {
+    Some(Value::Null) => return Value::Null,
+    Some(digits) => to_integer(digits).clamp(0, 30),
+    None => 0,
+  };
+  let real = to_real(value);
+  // Beyond 2^52, reals have no fractional part to round.
+  const INTEGRAL: f64 = 4_503_599_627_370_496.0;
+  if !(-INTEGRAL..=INTEGRAL).contains(&real) {
+    return Value::Real(real);
+  }
+  if digits == 0 {
+    return Value::Real((real + 0.5_f64.copysign(real)).trunc());
+  }
+  let text = printf::format("%!.*f", &[Value::Integer(digits), value.clone()]);
+  Value::Real(text.parse().unwrap_or(real))
+}
+
+/// `substr(X, Y, Z)`: the characters of `X`, or the bytes of a blob, from
+/// the `Y`th one, counted from the end when negative, and `Z` of them, or
+/// those before when `Z` is negative.
+fn substr(value: &Value, start: &Value, length: Option<&Value>) -> Value {
+  let mut start = to_integer(start);
+  let (mut length, before) = match length.map(to_integer) {
+    Some(length) if length < 0 => (length.saturating_neg(), true),
+    Some(length) => (length, false),
+    None => (i64::MAX, false),
+  };
+  let total = match value {
+    Value::Blob(blob) => blob.len(),
+    value => text(value).chars().count(),
+  } as i64;
+  if start < 0 {
+    start += total;
+    if start < 0 {
+      length = (length + start).max(0);
+      start = 0;
+    }
+  } else if start > 0 {
+    start -= 1;
+  } else if length > 0 {
+    length -= 1;
+  }
+  if before {
+    start -= length;
+    if start < 0 {
+      length += start;
+      start = 0;
+    }
+  }
+  let start = usize::try_from(start).unwrap_or(usize::MAX);
+  let length = usize::try_from(length).unwrap_or(0);
+  match value {
+    Value::Blob(blob) => {
+      Value::Blob(blob.iter().skip(start).take(length).copied().collect())
+    }
+    value => {
+      Value::Text(text(value).chars().skip(start).take(length).collect())
+    }
+  }
+}
+
+/// `unhex(X, Y)`: the blob of the hexadecimal digits of `X`, where the
+/// characters of `Y` may appear between pairs of digits. NULL when `X`
+/// holds anything else.
+fn unhex(hex: &str, ignored: &str) -> Value {
+  let mut blob = vec![];
+  let mut chars = hex.chars();
+  while let Some(c) = chars.next() {
+    if ignored.contains(c) {
+      continue;
+    }
+    let high = c.to_digit(16);
+    let low = chars.next().and_then(|c| c.to_digit(16));
+    match (high, low) {
+      (Some(high), Some(low)) => blob.push((high * 16 + low) as u8),
+      _ => return Value::Null,
+    }
+  }
+  Value::Blob(blob)
+}
@@ -0,0 +1,229 @@
+//! # Joins
+//!
+//!  Tables are joined in the order of the `FROM` clause, by nested loops:
+//! for each row of the tables on its left, the rows of the next table are
+//! looked up again, through an index when one covers the join key. Outer
+//! joins extend the rows that match nothing with NULLs for the columns of
+//! the other side.
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html#determination_of_input_data_from_clause_processing_
+
+use super::expr::Expr;
+use super::operator::{Operator, Scan};
+use super::query_plan::QueryPlan;
+use super::value::is_true;
+use super::vdbe::{Builder, Codegen, Consumer, Opcode, Unsupported};
+use super::Context;
+use crate::result::SqliteResult;
+use crate::runtime::Value;
+
+/// Which rows without a match a join keeps, extended with NULLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JoinType {
+  Inner,
+  /// The rows of the left.
+  Left,
+  /// The rows of the right.
+  Right,
+  /// The rows of both sides.
+  Full,
+}
+
+impl JoinType {
+  fn keeps_left(self) -> bool {
+    matches!(self, Self::Left | Self::Full)
+  }
+
+  fn keeps_right(self) -> bool {
+    matches!(self, Self::Right | Self::Full)
+  }
+}
+
+/// Joins the rows of the tables on the left, `left_width` values each, to
+/// the rows of one more table for which the condition is true.
+#[derive(Debug)]
+pub(crate) struct Join {
+  left: Box<dyn Operator>,
+  right: Scan,
+  join_type: JoinType,
+  condition: Option<Expr>,
+  left_width: usize,
+  right_width: usize,
+  /// The current row of the left, and whether it matched a row yet.
+  current: Option<(Vec<Value>, bool)>,
+  /// Which rows of the right, in the order they are scanned, matched a row
+  /// of the left. Only kept when the unmatched ones are returned.
+  matched: Vec<bool>,
+  position: usize,
+  /// Set once the left has no more rows, while the rows of the right that
+  /// matched none are returned.
+  finishing: bool,
+}
+
+impl Join {
+  /// A right or full join must scan every row of `right` for each row of
+  /// the left, so that the positions of its rows are the same every time.
+  pub(crate) fn new(
+    left: Box<dyn Operator>,
+    right: Scan,
+    join_type: JoinType,
+    condition: Option<Expr>,
+    left_width: usize,
+    right_width: usize,
+  ) -> Self {
+    Self {
+      left,
+      right,
+      join_type,
+      condition,
+      left_width,
+      right_width,
+      current: None,
+      matched: vec![],
+      position: 0,
+      finishing: false,
+    }
+  }
+
+  /// The rows of the right that matched no row of the left.
+  fn unmatched_right(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    while let Some(right) = self.right.next_row(ctx)? {
+      let is_matched = self.matched.get(self.position).copied();
+      self.position += 1;
+      if is_matched != Some(true) {
+        let mut row = vec![Value::Null; self.left_width];
+        row.extend(right);
+        return Ok(Some(row));
+      }
+    }
+    Ok(None)
+  }
+}
+
+impl Operator for Join {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if self.finishing {
+      return self.unmatched_right(ctx);
+    }
+    loop {
+      let Some((left, matched)) = &mut self.current else {
+        let Some(left) = self.left.next(ctx)? else {
+          if !self.join_type.keeps_right() {
+            return Ok(None);
+          }
+          self.finishing = true;
+          self.position = 0;
+          self.right.rewind(ctx, &[])?;
+          return self.unmatched_right(ctx);
+        };
+        self.right.rewind(ctx, &left)?;
+        self.position = 0;
+        self.current = Some((left, false));
+        continue;
+      };
+      let Some(right) = self.right.next_row(ctx)? else {
+        let keeps_left = !*matched && self.join_type.keeps_left();
+        let (mut row, _) = self.current.take().unwrap_or_default();
+        if keeps_left {
+          row.resize(self.left_width + self.right_width, Value::Null);
+          return Ok(Some(row));
+        }
+        continue;
+      };
+      let mut row = left.clone();
+      row.extend(right);
+      self.position += 1;
+      let is_match = match &self.condition {
+        Some(condition) => is_true(&condition.eval(ctx, &row)?) == Some(true),
+        None => true,
+      };
+      if is_match {
+        *matched = true;
+        if self.join_type.keeps_right() {
+          if self.matched.len() < self.position {
+            self.matched.resize(self.position, false);
+          }
+          self.matched[self.position - 1] = true;
+        }
+        return Ok(Some(row));
+      }
+    }
+  }
+
+  fn reset(&mut self) {
+    self.left.reset();
+    self.current = None;
+    self.matched.clear();
+    self.position = 0;
+    self.finishing = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.left.describe(plan);
+    match (&self.right, self.join_type) {
+      (Scan::Table(right), JoinType::Left) => {
+        plan.add(format!("{} LEFT-JOIN", right.detail()));
+      }
+      (right, _) => right.describe(plan),
+    }
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let Scan::Table(right) = &self.right else {
+      // Which reports the source it cannot read.
+      return self.right.compile(builder, consume);
+    };
+    let keeps_left = match self.join_type {
+      JoinType::Inner => false,
+      JoinType::Left => true,
+      JoinType::Right | JoinType::Full => {
+        return Err(Unsupported("RIGHT and FULL joins"))
+      }
+    };
+    let cursors = right.open(builder)?;
+    let matched = keeps_left.then(|| builder.register());
+    self.left.compile(builder, &mut |builder, left, left_next| {
+      let body = builder.label();
+      if let Some(matched) = matched {
+        builder.add(Opcode::Integer, 0, matched, 0);
+        builder.comment("init LEFT JOIN match flag");
+      }
+      right.compile_loop(
+        builder,
+        cursors,
+        left,
+        &mut |builder, right, next| {
+          let mut row = left.to_vec();
+          row.extend_from_slice(right);
+          if let Some(condition) = &self.condition {
+            builder.if_false(condition, &row, next, true)?;
+          }
+          builder.resolve(body);
+          if let Some(matched) = matched {
+            builder.add(Opcode::Integer, 1, matched, 0);
+            builder.comment("record LEFT JOIN hit");
+          }
+          consume(builder, &row, next)
+        },
+      )?;
+      if let Some(matched) = matched {
+        builder.jump(Opcode::IfPos, matched, left_next, 0);
+        for cursor in cursors.iter() {
+          builder.add(Opcode::NullRow, cursor, 0, 0);
+        }
+        builder.jump(Opcode::Goto, 0, body, 0);
+      }
+      Ok(())
+    })
+  }
+}
@@ -0,0 +1,284 @@
+//! `json_each()` and `json_tree()`: the table-valued functions walking a
+//! JSON value. `json_each(X)` has a row for each element of the array or
+//! object `X`, or for `X` itself when it is neither, and `json_tree(X)` a
+//! row for `X` and for every element within it, depth first.
+//! `json_each(X, P)` and `json_tree(X, P)` walk the element the path `P`
+//! leads to instead.
+//!
+//!  Elements are identified by their position in the JSONB of `X`; that of
+//! an object member is the position of its label.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html#jeach
+
+use super::super::expr::Expr;
+use super::super::operator::Operator;
+use super::super::query_plan::QueryPlan;
+use super::super::Context;
+use super::node::Node;
+use super::{bad_path, document, path, path_steps, sql_value};
+use crate::result::SqliteResult;
+use crate::runtime::{to_text, Value};
+
+/// The columns of the rows, followed by the hidden columns of the
+/// arguments.
+pub(crate) const COLUMNS: [&str; 10] = [
+  "key", "value", "type", "atom", "id", "parent", "fullkey", "path", "json",
+  "root",
+];
+/// The position of the `value` column, whose values are the JSON text of
+/// arrays and objects.
+pub(crate) const VALUE: usize = 1;
+/// The position of the `atom` column, NULL for arrays and objects.
+pub(crate) const ATOM: usize = 3;
+/// The number of columns that are not hidden.
+pub(crate) const VISIBLE: usize = 8;
+
+/// The rows of a call to `json_each()` or `json_tree()`, walked again for
+/// each row of the tables on its left, which its arguments may read.
+#[derive(Debug)]
+pub(crate) struct JsonEach {
+  /// The name the query gives the function.
+  name: String,
+  /// Set for `json_tree()`.
+  recursive: bool,
+  arguments: Vec<Expr>,
+  rows: Vec<Vec<Value>>,
+  position: usize,
+  started: bool,
+}
+
+impl JsonEach {
+  pub(crate) fn new(
+    name: String,
+    recursive: bool,
+    arguments: Vec<Expr>,
+  ) -> Self {
+    Self {
+      name,
+      recursive,
+      arguments,
+      rows: vec![],
+      position: 0,
+      started: false,
+    }
+  }
+
+  /// Starts over, walking the value the arguments evaluate to given `row`.
+  pub(crate) fn rewind(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    let arguments = self
+      .arguments
+      .iter()
+      .map(|expr| expr.eval(ctx, row))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    self.rows = walk(self.recursive, &arguments)?;
+    self.position = 0;
+    self.started = true;
+    Ok(())
+  }
+
+  /// The next row, once rewound.
+  pub(crate) fn next_row(&mut self) -> Option<Vec<Value>> {
+    let row = self.rows.get(self.position).cloned();
+    self.position += 1;
+    row
+  }
+}
+
+impl Operator for JsonEach {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.started {
+      self.rewind(ctx, &[])?;
+    }
+    Ok(self.next_row())
+  }
+
+  fn reset(&mut self) {
+    self.started = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    let index = match self.arguments.len() {
+      1 => 1,
+      _ => 3,
+    };
+    plan.add(format!("SCAN {} VIRTUAL TABLE INDEX {index}:", self.name));
+  }
+}
+
+/// The rows for the JSON value and the path of `arguments`.
+fn walk(recursive: bool, arguments: &[Value]) -> SqliteResult<Vec<Vec<Value>>> {
+  let json = arguments.first().cloned().unwrap_or(Value::Null);
+  let Some(root) = document(&json)? else {
+    return Ok(vec![]);
+  };
+  let root_path = match arguments.get(1) {
+    Some(path) => match to_text(path) {
+      Some(text) => Some(text),
+      None => return Ok(vec![]),
+    },
+    None => None,
+  };
+  let steps = match &root_path {
+    Some(text) => path_steps(text)?,
+    None => "",
+  };
+  let Some(trail) = path::find(&root, steps)
+    .map_err(|_| bad_path(root_path.as_deref().unwrap_or_default()))?
+  else {
+    return Ok(vec![]);
+  };
+  // The element the path leads to, and its path.
+  let mut element = Element {
+    node: &root,
+    key: Value::Null,
+    id: 0,
+    at: 0,
+  };
+  let mut path = String::from("$");
+  let mut fullkey = path.clone();
+  for &idx in &trail {
+    let Some(child) = children(&element).nth(idx) else {
+      return Ok(vec![]);
+    };
+    path = fullkey.clone();
+    append_step(&mut fullkey, &child.key);
+    element = child;
+  }
+  let mut walker = Walker {
+    recursive,
+    rows: vec![],
+    json,
+    root: Value::Text(root_path.unwrap_or_else(|| "$".into())),
+  };
+  match recursive || !element.node.is_container() {
+    true => walker.visit(element, None, &fullkey, &path)?,
+    false => {
+      for child in children(&element) {
+        let mut child_path = fullkey.clone();
+        append_step(&mut child_path, &child.key);
+        walker.row(child, None, &child_path, &fullkey)?;
+      }
+    }
+  }
+  Ok(walker.rows)
+}
+
+/// An element of the JSON value walked.
+struct Element<'a> {
+  node: &'a Node,
+  /// Its label in an object, or its position in an array.
+  key: Value,
+  id: usize,
+  /// Its position in the JSONB, past its label in an object.
+  at: usize,
+}
+
+struct Walker {
+  recursive: bool,
+  rows: Vec<Vec<Value>>,
+  json: Value,
+  root: Value,
+}
+
+impl Walker {
+  /// Adds the row of `element`, then for `json_tree()` those of the
+  /// elements within it.
+  fn visit(
+    &mut self,
+    element: Element<'_>,
+    parent: Option<usize>,
+    fullkey: &str,
+    path: &str,
+  ) -> SqliteResult<()> {
+    let children = match self.recursive {
+      true => children(&element).collect(),
+      false => vec![],
+    };
+    let id = element.id;
+    self.row(element, parent, fullkey, path)?;
+    for child in children {
+      let mut child_path = fullkey.to_owned();
+      append_step(&mut child_path, &child.key);
+      self.visit(child, Some(id), &child_path, fullkey)?;
+    }
+    Ok(())
+  }
+
+  fn row(
+    &mut self,
+    element: Element<'_>,
+    parent: Option<usize>,
+    fullkey: &str,
+    path: &str,
+  ) -> SqliteResult<()> {
+    let value = sql_value(element.node)?;
+    let atom = match element.node.is_container() {
+      true => Value::Null,
+      false => value.clone(),
+    };
+    self.rows.push(vec![
+      element.key,
+      value,
+      Value::Text(element.node.type_name().into()),
+      atom,
+      Value::Integer(element.id as i64),
+      parent.map_or(Value::Null, |parent| Value::Integer(parent as i64)),
+      Value::Text(fullkey.into()),
+      Value::Text(path.into()),
+      self.json.clone(),
+      self.root.clone(),
+    ]);
+    Ok(())
+  }
+}
+
+/// The elements of the array or object of `element`.
+fn children<'a>(element: &Element<'a>) -> impl Iterator<Item = Element<'a>> {
+  let node = element.node;
+  let mut at = element.at + node.header_size();
+  let (elements, members): (&[Node], &[(Node, Node)]) = match node {
+    Node::Array(elements) => (elements, &[]),
+    Node::Object(members) => (&[], members),
+    _ => (&[], &[]),
+  };
+  let elements = elements
+    .iter()
+    .enumerate()
+    .map(|(idx, element)| (element, Value::Integer(idx as i64), 0));
+  let members = members
+    .iter()
+    .map(|(label, value)| (value, Value::Text(label.text()), label.size()));
+  elements.chain(members).map(move |(node, key, label)| {
+    let id = at;
+    at += label + node.size();
+    Element {
+      node,
+      key,
+      id,
+      at: id + label,
+    }
+  })
+}
+
+/// Appends the step to the element of `key` to a path.
+fn append_step(path: &mut String, key: &Value) {
+  match key {
+    Value::Integer(idx) => path.push_str(&format!("[{idx}]")),
+    key => {
+      let label = to_text(key).unwrap_or_default();
+      let is_plain = label.starts_with(|c: char| c.is_ascii_alphabetic())
+        && label.bytes().all(|c| c.is_ascii_alphanumeric());
+      match is_plain {
+        true => path.push_str(&format!(".{label}")),
+        false => path.push_str(&format!(".\"{label}\"")),
+      }
+    }
+  }
+}
@@ -0,0 +1,559 @@
+//! # JSON functions
+//!
+//!  The functions and operators that read, build and change JSON values, held
+//! either as JSON text or as JSONB, SQLite's binary encoding of JSON. The
+//! `json_` functions return JSON text, and their `jsonb_` twins JSONB; both
+//! read either. JSON5 is accepted as input, and turned into standard JSON.
+//!
+//!  SQLite marks the JSON text these functions return, so that another JSON
+//! function given it as a value takes it for JSON rather than for a string.
+//! Values here carry no such mark: instead, calls in that position are
+//! compiled into their `jsonb_` twins, whose JSONB blobs are taken for JSON.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html
+
+mod each;
+mod node;
+mod path;
+
+pub(crate) use each::{JsonEach, ATOM, COLUMNS, VALUE, VISIBLE};
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{to_integer, to_text, Value};
+use core::ops::RangeInclusive;
+use node::{is_jsonb, might_be_jsonb, Node};
+use path::{BadPath, Edit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JsonFunction {
+  Json,
+  Array,
+  ArrayLength,
+  ErrorPosition,
+  Extract,
+  /// The `->` operator.
+  Arrow,
+  /// The `->>` operator.
+  LongArrow,
+  Insert,
+  Object,
+  Patch,
+  Pretty,
+  Quote,
+  Remove,
+  Replace,
+  Set,
+  Type,
+  Valid,
+}
+
+impl JsonFunction {
+  /// The JSON function named `name`, in lowercase, whether it returns
+  /// JSONB, and the numbers of arguments it takes.
+  pub(crate) fn from_name(
+    name: &str,
+  ) -> Option<(Self, bool, RangeInclusive<usize>)> {
+    const ANY: usize = usize::MAX;
+    let (binary, name) = match name.strip_prefix("jsonb") {
+      Some(rest) => (true, rest),
+      None => (false, name.strip_prefix("json")?),
+    };
+    let (function, counts) = match name {
+      "" => (Self::Json, 1..=1),
+      "_array" => (Self::Array, 0..=ANY),
+      "_extract" => (Self::Extract, 0..=ANY),
+      "_insert" => (Self::Insert, 0..=ANY),
+      "_object" => (Self::Object, 0..=ANY),
+      "_patch" => (Self::Patch, 2..=2),
+      "_remove" => (Self::Remove, 0..=ANY),
+      "_replace" => (Self::Replace, 0..=ANY),
+      "_set" => (Self::Set, 0..=ANY),
+      _ if binary => return None,
+      "_array_length" => (Self::ArrayLength, 1..=2),
+      "_error_position" => (Self::ErrorPosition, 1..=1),
+      "_pretty" => (Self::Pretty, 1..=2),
+      "_quote" => (Self::Quote, 1..=1),
+      "_type" => (Self::Type, 1..=2),
+      "_valid" => (Self::Valid, 1..=2),
+      _ => return None,
+    };
+    Some((function, binary, counts))
+  }
+
+  pub(crate) fn name(self, binary: bool) -> &'static str {
+    match (self, binary) {
+      (Self::Json, false) => "json",
+      (Self::Json, true) => "jsonb",
+      (Self::Array, false) => "json_array",
+      (Self::Array, true) => "jsonb_array",
+      (Self::ArrayLength, _) => "json_array_length",
+      (Self::ErrorPosition, _) => "json_error_position",
+      (Self::Extract, false) => "json_extract",
+      (Self::Extract, true) => "jsonb_extract",
+      (Self::Arrow, _) => "->",
+      (Self::LongArrow, _) => "->>",
+      (Self::Insert, false) => "json_insert",
+      (Self::Insert, true) => "jsonb_insert",
+      (Self::Object, false) => "json_object",
+      (Self::Object, true) => "jsonb_object",
+      (Self::Patch, false) => "json_patch",
+      (Self::Patch, true) => "jsonb_patch",
+      (Self::Pretty, _) => "json_pretty",
+      (Self::Quote, _) => "json_quote",
+      (Self::Remove, false) => "json_remove",
+      (Self::Remove, true) => "jsonb_remove",
+      (Self::Replace, false) => "json_replace",
+      (Self::Replace, true) => "jsonb_replace",
+      (Self::Set, false) => "json_set",
+      (Self::Set, true) => "jsonb_set",
+      (Self::Type, _) => "json_type",
+      (Self::Valid, _) => "json_valid",
+    }
+  }
+
+  /// Whether the function returns JSON, which another JSON function takes
+  /// for JSON rather than for a string. `json_extract()` only does so for
+  /// arrays and objects.
+  pub(crate) fn returns_json(self) -> bool {
+    !matches!(
+      self,
+      Self::ArrayLength
+        | Self::ErrorPosition
+        | Self::LongArrow
+        | Self::Pretty
+        | Self::Type
+        | Self::Valid
+    )
+  }
+
+  /// Whether the argument at `idx` is a value the function puts in the JSON
+  /// it returns.
+  pub(crate) fn takes_value(self, idx: usize) -> bool {
+    match self {
+      Self::Array | Self::Quote => true,
+      Self::Object => idx % 2 == 1,
+      Self::Insert | Self::Replace | Self::Set => idx > 0 && idx % 2 == 0,
+      _ => false,
+    }
+  }
+
+  /// Calls the function with the values of its `arguments`, returning its
+  /// JSON as JSONB when `binary`.
+  pub(crate) fn call(
+    self,
+    binary: bool,
+    arguments: &[Value],
+  ) -> SqliteResult<Value> {
+    let argument = |idx: usize| arguments.get(idx).unwrap_or(&Value::Null);
+    let first = argument(0);
+    match self {
+      Self::Array => {
+        let elements =
+          arguments.iter().map(|value| value_node(value, INFINITY));
+        Ok(result(
+          Node::Array(elements.collect::<SqliteResult<_>>()?),
+          binary,
+        ))
+      }
+      Self::Object => {
+        if arguments.len() % 2 == 1 {
+          return Err(SqliteError::Custom(
+            "json_object() requires an even number of arguments".into(),
+          ));
+        }
+        let mut members = vec![];
+        for pair in arguments.chunks(2) {
+          let Value::Text(label) = &pair[0] else {
+            return Err(SqliteError::Custom(
+              "json_object() labels must be TEXT".into(),
+            ));
+          };
+          let value = value_node(&pair[1], INFINITY)?;
+          members.push((Node::TextRaw(label.clone()), value));
+        }
+        Ok(result(Node::Object(members), binary))
+      }
+      Self::Quote => Ok(result(value_node(first, INFINITY)?, binary)),
+      Self::Valid => valid(first, arguments.get(1)),
+      Self::ErrorPosition => Ok(error_position(first)),
+      Self::Extract | Self::Arrow | Self::LongArrow => {
+        if arguments.len() < 2 {
+          return Ok(Value::Null);
+        }
+        let Some(root) = document(first)? else {
+          return Ok(Value::Null);
+        };
+        self.extract(binary, &root, &arguments[1..])
+      }
+      Self::Insert | Self::Replace | Self::Set => {
+        if arguments.is_empty() {
+          return Ok(Value::Null);
+        }
+        if arguments.len() % 2 == 0 {
+          return Err(SqliteError::Custom(format!(
+            "json_{}() needs an odd number of arguments",
+            match self {
+              Self::Insert => "insert",
+              Self::Replace => "replace",
+              _ => "set",
+            }
+          )));
+        }
+        let Some(mut root) = document(first)? else {
+          return Ok(Value::Null);
+        };
+        for pair in arguments[1..].chunks(2) {
+          let Some(text) = to_text(&pair[0]) else {
+            continue;
+          };
+          let path = path_steps(&text)?;
+          let value = value_node(&pair[1], "9e999")?;
+          let change = match self {
+            Self::Insert => Edit::Insert(value),
+            Self::Replace => Edit::Replace(value),
+            _ => Edit::Set(value),
+          };
+          match (path, change) {
+            ("", Edit::Replace(value) | Edit::Set(value)) => root = value,
+            ("", _) => {}
+            (path, change) => {
+              path::edit(&mut root, path, &change)
+                .map_err(|_| bad_path(&text))?;
+            }
+          }
+        }
+        Ok(result(root, binary))
+      }
+      Self::Remove => {
+        if arguments.is_empty() {
+          return Ok(Value::Null);
+        }
+        let Some(mut root) = document(first)? else {
+          return Ok(Value::Null);
+        };
+        for path in &arguments[1..] {
+          let Some(text) = to_text(path) else {
+            return Ok(Value::Null);
+          };
+          match path_steps(&text)? {
+            "" => return Ok(Value::Null),
+            path => {
+              path::edit(&mut root, path, &Edit::Delete)
+                .map_err(|_| bad_path(&text))?;
+            }
+          }
+        }
+        Ok(result(root, binary))
+      }
+      _ => {
+        let Some(root) = document(first)? else {
+          return Ok(Value::Null);
+        };
+        // The node the path of the second argument leads to, if any.
+        let target = |path: Option<&Value>| match path {
+          None => Ok(Some(&root)),
+          Some(path) => match to_text(path) {
+            Some(text) => lookup(&root, &text),
+            None => Ok(None),
+          },
+        };
+        Ok(match self {
+          Self::Json => result(root.clone(), binary),
+          Self::ArrayLength => match target(arguments.get(1))? {
+            Some(Node::Array(elements)) => {
+              Value::Integer(elements.len() as i64)
+            }
+            Some(_) => Value::Integer(0),
+            None => Value::Null,
+          },
+          Self::Type => match target(arguments.get(1))? {
+            Some(node) => Value::Text(node.type_name().into()),
+            None => Value::Null,
+          },
+          Self::Patch => match document(argument(1))? {
+            Some(patch) => result(merge_patch(root, patch), binary),
+            None => Value::Null,
+          },
+          _ => {
+            let indent = match arguments.get(1) {
+              Some(indent) => to_text(indent),
+              None => None,
+            };
+            Value::Text(root.pretty(indent.as_deref().unwrap_or("    ")))
+          }
+        })
+      }
+    }
+  }
+
+  /// `json_extract()`, `->` and `->>`: the values the `paths` lead to within
+  /// `root`, or the array of them when there are several.
+  fn extract(
+    self,
+    binary: bool,
+    root: &Node,
+    paths: &[Value],
+  ) -> SqliteResult<Value> {
+    let mut found = vec![];
+    for path in paths {
+      let Some(text) = to_text(path) else {
+        return Ok(Value::Null);
+      };
+      let node = match text.strip_prefix('$') {
+        Some(steps) => path::lookup(root, steps),
+        // The operators take a label, or an array index, for a path.
+        None if self != Self::Extract => {
+          let steps = match path {
+            Value::Integer(int) if *int < 0 => format!("[#{int}]"),
+            Value::Integer(int) => format!("[{int}]"),
+            _ if text
+              .bytes()
+              .all(|c| c.is_ascii_alphanumeric() || c == b'_') =>
+            {
+              format!(".{text}")
+            }
+            _ if text.len() >= 3
+              && text.starts_with('[')
+              && text.ends_with(']') =>
+            {
+              text.clone()
+            }
+            _ => format!(".\"{text}\""),
+          };
+          path::lookup(root, &steps)
+        }
+        None => Err(BadPath),
+      };
+      match node.map_err(|_| bad_path(&text))? {
+        Some(node) => found.push(node.clone()),
+        None if paths.len() == 1 => return Ok(Value::Null),
+        None => found.push(Node::Null),
+      }
+    }
+    if found.len() > 1 {
+      return Ok(result(Node::Array(found), binary));
+    }
+    let node = found.remove(0);
+    match self {
+      Self::Arrow => Ok(result(node, binary)),
+      Self::Extract if node.is_container() => Ok(result(node, binary)),
+      _ => sql_value(&node),
+    }
+  }
+}
+
+/// How `json_array()`, `json_object()`, `json_quote()` and the aggregates
+/// write infinite reals.
+const INFINITY: &str = "9.0e+999";
+
+/// The JSON text, or the JSONB, of `node`.
+fn result(node: Node, binary: bool) -> Value {
+  match binary {
+    true => Value::Blob(node.to_jsonb()),
+    false => Value::Text(node.render()),
+  }
+}
+
+fn malformed() -> SqliteError {
+  SqliteError::Custom("malformed JSON".into())
+}
+
+fn bad_path(path: &str) -> SqliteError {
+  SqliteError::Custom(format!("bad JSON path: '{path}'"))
+}
+
+/// The JSON value `value` holds, as JSON text or as JSONB. `None` for NULL.
+/// A blob that is not JSONB is read as text.
+fn document(value: &Value) -> SqliteResult<Option<Node>> {
+  let text = match value {
+    Value::Null => return Ok(None),
+    Value::Blob(blob) if is_jsonb(blob) => {
+      return Node::from_jsonb(blob).map(Some).map_err(|_| malformed())
+    }
+    Value::Blob(blob) => {
+      String::from_utf8(blob.clone()).map_err(|_| malformed())?
+    }
+    value => to_text(value).unwrap_or_default(),
+  };
+  match Node::parse(&text) {
+    Ok(parsed) => Ok(Some(parsed.node)),
+    Err(_) => Err(malformed()),
+  }
+}
+
+/// The steps of a path, after its `$`.
+fn path_steps(path: &str) -> SqliteResult<&str> {
+  path.strip_prefix('$').ok_or_else(|| bad_path(path))
+}
+
+/// The node `path` leads to within `root`.
+fn lookup<'a>(root: &'a Node, path: &str) -> SqliteResult<Option<&'a Node>> {
+  path::lookup(root, path_steps(path)?).map_err(|_| bad_path(path))
+}
+
+/// The JSON value of an SQL value. Text is a string, except for the JSON
+/// another JSON function returns, which arrives as JSONB. Infinite reals
+/// are written as `infinity`.
+fn value_node(value: &Value, infinity: &str) -> SqliteResult<Node> {
+  Ok(match value {
+    Value::Null => Node::Null,
+    Value::Integer(int) => Node::Int(int.to_string()),
+    Value::Real(real) if real.is_nan() => Node::Null,
+    Value::Real(real) if real.is_infinite() => match *real > 0.0 {
+      true => Node::Float(infinity.into()),
+      false => Node::Float(format!("-{infinity}")),
+    },
+    Value::Real(_) => Node::Float(to_text(value).unwrap_or_default()),
+    Value::Text(text) => Node::TextRaw(text.clone()),
+    Value::Blob(blob) if might_be_jsonb(blob) => {
+      Node::from_jsonb(blob).map_err(|_| malformed())?
+    }
+    Value::Blob(_) => {
+      return Err(SqliteError::Custom("JSON cannot hold BLOB values".into()))
+    }
+  })
+}
+
+/// The SQL value of a JSON value.
+fn sql_value(node: &Node) -> SqliteResult<Value> {
+  node.to_value().ok_or_else(malformed)
+}
+
+/// `json_valid(X, Y)`: whether `X` is well-formed JSON, of the kinds the
+/// bits of `Y` allow: 1 for standard JSON text, 2 for JSON5 text, 4 for
+/// what looks like JSONB and 8 for JSONB checked in full.
+fn valid(value: &Value, flags: Option<&Value>) -> SqliteResult<Value> {
+  let flags = match flags.map(to_integer) {
+    Some(flags @ 1..=15) => flags,
+    Some(_) => {
+      return Err(SqliteError::Custom(
+        "FLAGS parameter to json_valid() must be between 1 and 15".into(),
+      ))
+    }
+    None => 1,
+  };
+  let is_valid = match value {
+    Value::Null => return Ok(Value::Null),
+    Value::Blob(blob) if might_be_jsonb(blob) => {
+      flags & 4 != 0 || (flags & 8 != 0 && Node::from_jsonb(blob).is_ok())
+    }
+    _ if flags & 3 == 0 => false,
+    value => {
+      let text = match value {
+        Value::Blob(blob) => String::from_utf8(blob.clone()).ok(),
+        value => to_text(value),
+      };
+      let parsed = text.map(|text| Node::parse(&text));
+      parsed.is_some_and(|parsed| {
+        parsed.is_ok_and(|parsed| flags & 2 != 0 || !parsed.nonstandard)
+      })
+    }
+  };
+  Ok(Value::Integer(i64::from(is_valid)))
+}
+
+/// `json_error_position(X)`: 0 when `X` is well-formed JSON, else the
+/// position of the first error, in characters of JSON text or in bytes of
+/// JSONB, counting from 1.
+fn error_position(value: &Value) -> Value {
+  let position = match value {
+    Value::Null => return Value::Null,
+    Value::Blob(blob) if is_jsonb(blob) => match Node::from_jsonb(blob) {
+      Ok(_) => 0,
+      Err(at) => at + 1,
+    },
+    value => {
+      let text = match value {
+        Value::Blob(blob) => String::from_utf8_lossy(blob).into_owned(),
+        value => to_text(value).unwrap_or_default(),
+      };
+      match Node::parse(&text) {
+        Ok(_) => 0,
+        Err(at) => {
+          let bytes = text.as_bytes().get(..at).unwrap_or(text.as_bytes());
+          bytes.iter().filter(|&&byte| byte & 0xc0 != 0x80).count() + 1
+        }
+      }
+    }
+  };
+  Value::Integer(position as i64)
+}
+
+/// `json_patch(T, P)`: `T` with the changes of the merge patch `P`, as RFC
+/// 7396 has it: a patch that is not an object replaces the value it
+/// applies to, and the members of one that is replace those of the same
+/// labels, or remove them when null.
+///
+/// *Reference:* https://www.rfc-editor.org/rfc/rfc7396
+fn merge_patch(target: Node, patch: Node) -> Node {
+  let Node::Object(changes) = patch else {
+    return patch;
+  };
+  let mut members = match target {
+    Node::Object(members) => members,
+    _ => vec![],
+  };
+  for (label, value) in changes {
+    let text = label.text();
+    let found = members.iter().position(|(other, _)| other.text() == text);
+    match (found, value) {
+      (Some(idx), Node::Null) => {
+        members.remove(idx);
+      }
+      (Some(idx), value) => {
+        let old = core::mem::replace(&mut members[idx].1, Node::Null);
+        members[idx].1 = merge_patch(old, value);
+      }
+      (None, Node::Null) => {}
+      (None, value) => {
+        members.push((label, merge_patch(Node::Object(vec![]), value)))
+      }
+    }
+  }
+  Node::Object(members)
+}
+
+/// The state of `json_group_array()` or `json_group_object()` over the
+/// values of a group: the array or object so far, or the error a value
+/// caused.
+#[derive(Debug)]
+pub(crate) struct JsonGroup(Result<Node, String>);
+
+impl JsonGroup {
+  pub(crate) fn new(object: bool) -> Self {
+    Self(Ok(match object {
+      true => Node::Object(vec![]),
+      false => Node::Array(vec![]),
+    }))
+  }
+
+  /// Adds the value of `values`, after its label for an object. Members
+  /// labelled NULL are left out.
+  pub(crate) fn step(&mut self, values: &[Value]) {
+    let Ok(node) = &mut self.0 else {
+      return;
+    };
+    let value = |idx: usize| values.get(idx).unwrap_or(&Value::Null);
+    let added = match node {
+      Node::Object(members) => match to_text(value(0)) {
+        Some(label) => value_node(value(1), INFINITY)
+          .map(|value| members.push((Node::TextRaw(label), value))),
+        None => Ok(()),
+      },
+      Node::Array(elements) => {
+        value_node(value(0), INFINITY).map(|value| elements.push(value))
+      }
+      _ => Ok(()),
+    };
+    if let Err(SqliteError::Custom(error)) = added {
+      self.0 = Err(error);
+    }
+  }
+
+  pub(crate) fn value(&self, binary: bool) -> SqliteResult<Value> {
+    match &self.0 {
+      Ok(node) => Ok(result(node.clone(), binary)),
+      Err(error) => Err(SqliteError::Custom(error.clone())),
+    }
+  }
+}
@@ -0,0 +1,1196 @@
+//! JSON values as trees of nodes, read from JSON text or from JSONB, and
+//! rendered back to either.
+//!
+//!  Nodes are shaped after the elements of JSONB: numbers and strings keep
+//! the text of their payload, along with the element type telling how that
+//! text is to be read. So rendering a value reproduces the numbers as they
+//! were written, and the strings with their escapes, once the JSON5
+//! extensions are turned into standard JSON.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html#jsonb
+
+use crate::runtime::{to_real, Value};
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// The deepest arrays and objects may nest.
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+  Null,
+  True,
+  False,
+  /// An integer in decimal.
+  Int(String),
+  /// A JSON5 integer in hexadecimal, with its `0x` prefix and sign.
+  Int5(String),
+  Float(String),
+  /// A JSON5 number without digits on one side of its decimal point.
+  Float5(String),
+  /// A string that needs no escaping.
+  Text(String),
+  /// A string with standard JSON escapes.
+  TextJ(String),
+  /// A string with JSON5 escapes or raw control characters.
+  Text5(String),
+  /// A string whose characters are all literal, escaped when rendered.
+  TextRaw(String),
+  Array(Vec<Node>),
+  /// The labels and values of an object. Labels are strings.
+  Object(Vec<(Node, Node)>),
+}
+
+/// A value parsed from JSON text.
+#[derive(Debug)]
+pub(crate) struct Parsed {
+  pub(crate) node: Node,
+  /// Whether the text uses JSON5 extensions.
+  pub(crate) nonstandard: bool,
+}
+
+impl Node {
+  /// Parses JSON text, or JSON5. On error, returns the position in bytes
+  /// of the error, the end of the text for trailing garbage.
+  pub(crate) fn parse(text: &str) -> Result<Parsed, usize> {
+    let mut parser = Parser {
+      text: text.as_bytes(),
+      error: 0,
+      nonstandard: false,
+      depth: 0,
+    };
+    let Step::Value(node, mut idx) = parser.value(0) else {
+      return Err(parser.error);
+    };
+    while is_space(parser.at(idx)) {
+      idx += 1;
+    }
+    if parser.at(idx) != 0 {
+      idx += json5_space(&parser.text[idx..]);
+      if parser.at(idx) != 0 {
+        return Err(parser.error);
+      }
+      parser.nonstandard = true;
+    }
+    Ok(Parsed {
+      node,
+      nonstandard: parser.nonstandard,
+    })
+  }
+
+  /// The JSONB type code of the element.
+  fn code(&self) -> u8 {
+    match self {
+      Self::Null => 0,
+      Self::True => 1,
+      Self::False => 2,
+      Self::Int(_) => 3,
+      Self::Int5(_) => 4,
+      Self::Float(_) => 5,
+      Self::Float5(_) => 6,
+      Self::Text(_) => 7,
+      Self::TextJ(_) => 8,
+      Self::Text5(_) => 9,
+      Self::TextRaw(_) => 10,
+      Self::Array(_) => 11,
+      Self::Object(_) => 12,
+    }
+  }
+
+  /// The text of a number or a string.
+  pub(crate) fn payload(&self) -> Option<&str> {
+    match self {
+      Self::Int(text)
+      | Self::Int5(text)
+      | Self::Float(text)
+      | Self::Float5(text)
+      | Self::Text(text)
+      | Self::TextJ(text)
+      | Self::Text5(text)
+      | Self::TextRaw(text) => Some(text),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn is_text(&self) -> bool {
+    (7..=10).contains(&self.code())
+  }
+
+  pub(crate) fn is_container(&self) -> bool {
+    matches!(self, Self::Array(_) | Self::Object(_))
+  }
+
+  /// The name `json_type()` gives the type of the value.
+  pub(crate) fn type_name(&self) -> &'static str {
+    match self {
+      Self::Null => "null",
+      Self::True => "true",
+      Self::False => "false",
+      Self::Int(_) | Self::Int5(_) => "integer",
+      Self::Float(_) | Self::Float5(_) => "real",
+      Self::Array(_) => "array",
+      Self::Object(_) => "object",
+      _ => "text",
+    }
+  }
+
+  /// The text of a string, escapes decoded.
+  pub(crate) fn text(&self) -> String {
+    match self {
+      Self::TextJ(text) | Self::Text5(text) => unescape(text),
+      node => node.payload().unwrap_or_default().into(),
+    }
+  }
+
+  /// The SQL value of the JSON value: true and false are 1 and 0, numbers
+  /// are INTEGER or REAL, strings are TEXT and arrays and objects are their
+  /// JSON text. `None` for a malformed number.
+  pub(crate) fn to_value(&self) -> Option<Value> {
+    Some(match self {
+      Self::Null => Value::Null,
+      Self::True => Value::Integer(1),
+      Self::False => Value::Integer(0),
+      Self::Int(text) | Self::Int5(text) => {
+        let (negative, digits) = match text.strip_prefix('-') {
+          Some(digits) => (true, digits),
+          None => (false, text.as_str()),
+        };
+        let int = match digits.strip_prefix("0x").or(digits.strip_prefix("0X"))
+        {
+          // Hexadecimal integers wrap around to negative ones.
+          Some(hex) => {
+            let hex = hex.trim_start_matches('0');
+            if hex.len() > 16 {
+              return None;
+            }
+            u64::from_str_radix(hex, 16).map_or(Some(0), |int| Some(int as i64))
+          }
+          None => digits.parse::<i64>().ok(),
+        };
+        match int {
+          Some(int) if negative => Value::Integer(int.wrapping_neg()),
+          Some(int) => Value::Integer(int),
+          None if negative && digits == "9223372036854775808" => {
+            Value::Integer(i64::MIN)
+          }
+          None => Value::Real(to_real(&Value::Text(text.clone()))),
+        }
+      }
+      Self::Float(text) | Self::Float5(text) => {
+        Value::Real(to_real(&Value::Text(text.clone())))
+      }
+      Self::Array(_) | Self::Object(_) => Value::Text(self.render()),
+      node => Value::Text(node.text()),
+    })
+  }
+
+  /// The JSON text of the value, without any JSON5 extension or
+  /// whitespace.
+  pub(crate) fn render(&self) -> String {
+    let mut out = String::new();
+    self.render_into(&mut out, None, 0);
+    out
+  }
+
+  /// The JSON text of the value, with each element of arrays and objects
+  /// on its own line, indented by `indent` for each level.
+  pub(crate) fn pretty(&self, indent: &str) -> String {
+    let mut out = String::new();
+    self.render_into(&mut out, Some(indent), 0);
+    out
+  }
+
+  fn render_into(&self, out: &mut String, indent: Option<&str>, level: usize) {
+    let new_line = |out: &mut String, level: usize| {
+      if let Some(indent) = indent {
+        out.push('\n');
+        for _ in 0..level {
+          out.push_str(indent);
+        }
+      }
+    };
+    match self {
+      Self::Null => out.push_str("null"),
+      Self::True => out.push_str("true"),
+      Self::False => out.push_str("false"),
+      Self::Int(text) | Self::Float(text) => out.push_str(text),
+      Self::Int5(text) => {
+        let (sign, digits) = match text.strip_prefix('-') {
+          Some(digits) => ("-", digits),
+          None => ("", text.as_str()),
+        };
+        let digits =
+          digits.get(2..).unwrap_or_default().trim_start_matches('0');
+        out.push_str(sign);
+        match digits.len() > 16 {
+          true => out.push_str("9.0e999"),
+          false => {
+            let int = u64::from_str_radix(digits, 16).unwrap_or_default();
+            out.push_str(&int.to_string());
+          }
+        }
+      }
+      Self::Float5(text) => {
+        let bytes = text.as_bytes();
+        for (idx, c) in text.char_indices() {
+          if c == '.'
+            && !bytes
+              .get(idx.wrapping_sub(1))
+              .is_some_and(u8::is_ascii_digit)
+          {
+            out.push('0');
+          }
+          out.push(c);
+          if c == '.' && !bytes.get(idx + 1).is_some_and(u8::is_ascii_digit) {
+            out.push('0');
+          }
+        }
+      }
+      Self::Text(text) | Self::TextJ(text) => {
+        out.push('"');
+        out.push_str(text);
+        out.push('"');
+      }
+      Self::Text5(text) => render_text5(out, text),
+      Self::TextRaw(text) => append_string(out, text),
+      Self::Array(elements) if elements.is_empty() => out.push_str("[]"),
+      Self::Array(elements) => {
+        out.push('[');
+        for (idx, element) in elements.iter().enumerate() {
+          if idx > 0 {
+            out.push(',');
+          }
+          new_line(out, level + 1);
+          element.render_into(out, indent, level + 1);
+        }
+        new_line(out, level);
+        out.push(']');
+      }
+      Self::Object(members) if members.is_empty() => out.push_str("{}"),
+      Self::Object(members) => {
+        out.push('{');
+        for (idx, (label, value)) in members.iter().enumerate() {
+          if idx > 0 {
+            out.push(',');
+          }
+          new_line(out, level + 1);
+          label.render_into(out, indent, level + 1);
+          out.push(':');
+          if indent.is_some() {
+            out.push(' ');
+          }
+          value.render_into(out, indent, level + 1);
+        }
+        new_line(out, level);
+        out.push('}');
+      }
+    }
+  }
+
+  /// The JSONB encoding of the value.
+  pub(crate) fn to_jsonb(&self) -> Vec<u8> {
+    let mut out = vec![];
+    self.encode(&mut out);
+    out
+  }
+
+  fn encode(&self, out: &mut Vec<u8>) {
+    let payload = match self {
+      Self::Array(elements) => {
+        let mut payload = vec![];
+        elements
+          .iter()
+          .for_each(|element| element.encode(&mut payload));
+        payload
+      }
+      Self::Object(members) => {
+        let mut payload = vec![];
+        for (label, value) in members {
+          label.encode(&mut payload);
+          value.encode(&mut payload);
+        }
+        payload
+      }
+      node => node.payload().unwrap_or_default().as_bytes().to_vec(),
+    };
+    encode_header(out, self.code(), payload.len());
+    out.extend_from_slice(&payload);
+  }
+
+  /// The size of the JSONB encoding of the value.
+  pub(crate) fn size(&self) -> usize {
+    let payload = self.payload_size();
+    header_size(payload) + payload
+  }
+
+  fn payload_size(&self) -> usize {
+    match self {
+      Self::Array(elements) => elements.iter().map(Node::size).sum(),
+      Self::Object(members) => members
+        .iter()
+        .map(|(label, value)| label.size() + value.size())
+        .sum(),
+      node => node.payload().unwrap_or_default().len(),
+    }
+  }
+
+  /// The size of the header of the JSONB encoding of the value, before its
+  /// first element for an array or an object.
+  pub(crate) fn header_size(&self) -> usize {
+    header_size(self.payload_size())
+  }
+
+  /// Decodes JSONB. On error, returns the position in bytes of the element
+  /// found malformed.
+  pub(crate) fn from_jsonb(blob: &[u8]) -> Result<Node, usize> {
+    let (node, end) = decode(blob, 0, 0)?;
+    match end == blob.len() {
+      true => Ok(node),
+      false => Err(end),
+    }
+  }
+}
+
+/// Whether the blob looks like JSONB: a single element, whose header says
+/// it spans the whole blob.
+pub(crate) fn might_be_jsonb(blob: &[u8]) -> bool {
+  match decode_header(blob, 0) {
+    Some((code, header, size)) => {
+      code <= 12 && header + size == blob.len() && (code > 2 || size == 0)
+    }
+    None => false,
+  }
+}
+
+/// Whether the blob holds JSONB rather than JSON text. Short blobs that
+/// might be either are checked in full.
+pub(crate) fn is_jsonb(blob: &[u8]) -> bool {
+  let Some(&first) = blob.first() else {
+    return false;
+  };
+  might_be_jsonb(blob)
+    && (blob.len() > 8
+      || (first != b'{' && first != b'[' && !first.is_ascii_digit())
+      || Node::from_jsonb(blob).is_ok())
+}
+
+/// The type code, the size of the header and that of the payload of the
+/// JSONB element at `idx`.
+fn decode_header(blob: &[u8], idx: usize) -> Option<(u8, usize, usize)> {
+  let first = *blob.get(idx)?;
+  let bytes = |count: usize| -> Option<usize> {
+    let bytes = blob.get(idx + 1..idx + 1 + count)?;
+    Some(
+      bytes
+        .iter()
+        .fold(0, |size, &byte| size << 8 | usize::from(byte)),
+    )
+  };
+  let (header, size) = match first >> 4 {
+    size @ 0..=11 => (1, usize::from(size)),
+    12 => (2, bytes(1)?),
+    13 => (3, bytes(2)?),
+    14 => (5, bytes(4)?),
+    _ => {
+      let size = bytes(8)?;
+      if size >> 32 != 0 {
+        return None;
+      }
+      (9, size)
+    }
+  };
+  match idx + header + size <= blob.len() {
+    true => Some((first & 0x0f, header, size)),
+    false => None,
+  }
+}
+
+fn header_size(payload: usize) -> usize {
+  match payload {
+    0..=11 => 1,
+    12..=0xff => 2,
+    0x100..=0xffff => 3,
+    _ => 5,
+  }
+}
+
+fn encode_header(out: &mut Vec<u8>, code: u8, payload: usize) {
+  match payload {
+    0..=11 => out.push((payload as u8) << 4 | code),
+    12..=0xff => out.extend_from_slice(&[0xc0 | code, payload as u8]),
+    0x100..=0xffff => {
+      out.push(0xd0 | code);
+      out.extend_from_slice(&(payload as u16).to_be_bytes());
+    }
+    _ => {
+      out.push(0xe0 | code);
+      out.extend_from_slice(&(payload as u32).to_be_bytes());
+    }
+  }
+}
+
+/// Decodes the JSONB element at `idx`, and returns it with the position
+/// after it.
+fn decode(
+  blob: &[u8],
+  idx: usize,
+  depth: usize,
+) -> Result<(Node, usize), usize> {
+  let (code, header, size) = decode_header(blob, idx).ok_or(idx)?;
+  let start = idx + header;
+  let end = start + size;
+  let text = || {
+    core::str::from_utf8(&blob[start..end])
+      .map(String::from)
+      .map_err(|_| idx)
+  };
+  let node = match code {
+    0..=2 if size > 0 => return Err(idx),
+    0 => Node::Null,
+    1 => Node::True,
+    2 => Node::False,
+    3..=6 if size == 0 => return Err(idx),
+    3 => Node::Int(text()?),
+    4 => Node::Int5(text()?),
+    5 => Node::Float(text()?),
+    6 => Node::Float5(text()?),
+    7 => Node::Text(text()?),
+    8 => Node::TextJ(text()?),
+    9 => Node::Text5(text()?),
+    10 => Node::TextRaw(text()?),
+    11 | 12 if depth >= MAX_DEPTH => return Err(idx),
+    11 => {
+      let mut elements = vec![];
+      let mut at = start;
+      while at < end {
+        let (element, next) = decode(&blob[..end], at, depth + 1)?;
+        elements.push(element);
+        at = next;
+      }
+      Node::Array(elements)
+    }
+    12 => {
+      let mut members = vec![];
+      let mut at = start;
+      while at < end {
+        let (label, next) = decode(&blob[..end], at, depth + 1)?;
+        if !label.is_text() || next >= end {
+          return Err(at);
+        }
+        let (value, next) = decode(&blob[..end], next, depth + 1)?;
+        members.push((label, value));
+        at = next;
+      }
+      Node::Object(members)
+    }
+    _ => return Err(idx),
+  };
+  Ok((node, end))
+}
+
+/// The escapes a string of JSON text holds, which tell the kind of its
+/// node.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Escapes {
+  None,
+  Json,
+  /// JSON5 escapes, or raw control characters.
+  Json5,
+}
+
+impl Escapes {
+  fn node(self, text: String) -> Node {
+    match self {
+      Self::None => Node::Text(text),
+      Self::Json => Node::TextJ(text),
+      Self::Json5 => Node::Text5(text),
+    }
+  }
+}
+
+/// What parsing finds at some position of JSON text.
+enum Step {
+  /// A value, and the position after it.
+  Value(Node, usize),
+  /// The end of the text.
+  End,
+  /// A syntax error, at `Parser::error`.
+  Error,
+  /// `}`, `]`, `,` or `:`, at `Parser::error`.
+  CloseObject,
+  CloseArray,
+  Comma,
+  Colon,
+}
+
+struct Parser<'a> {
+  text: &'a [u8],
+  /// The position of the last error, or punctuation.
+  error: usize,
+  nonstandard: bool,
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn at(&self, idx: usize) -> u8 {
+    self.text.get(idx).copied().unwrap_or(0)
+  }
+
+  fn fail(&mut self, idx: usize) -> Step {
+    self.error = idx;
+    Step::Error
+  }
+
+  /// Parses the value at `idx`, after any whitespace.
+  fn value(&mut self, mut idx: usize) -> Step {
+    loop {
+      return match self.at(idx) {
+        b'{' => self.object(idx),
+        b'[' => self.array(idx),
+        b'\'' => {
+          self.nonstandard = true;
+          self.string(idx)
+        }
+        b'"' => self.string(idx),
+        b't' if self.keyword(idx, "true") => Step::Value(Node::True, idx + 4),
+        b'f' if self.keyword(idx, "false") => Step::Value(Node::False, idx + 5),
+        b'n' if self.keyword(idx, "null") => Step::Value(Node::Null, idx + 4),
+        b't' | b'f' => self.fail(idx),
+        b'+' => {
+          self.nonstandard = true;
+          self.number(idx)
+        }
+        b'.' if self.at(idx + 1).is_ascii_digit() => {
+          self.nonstandard = true;
+          self.number(idx)
+        }
+        b'-' | b'0'..=b'9' => self.number(idx),
+        b'}' => {
+          self.error = idx;
+          Step::CloseObject
+        }
+        b']' => {
+          self.error = idx;
+          Step::CloseArray
+        }
+        b',' => {
+          self.error = idx;
+          Step::Comma
+        }
+        b':' => {
+          self.error = idx;
+          Step::Colon
+        }
+        0 if idx >= self.text.len() => Step::End,
+        b'\t' | b'\n' | b'\r' | b' ' => {
+          idx += 1;
+          while is_space(self.at(idx)) {
+            idx += 1;
+          }
+          continue;
+        }
+        0x0b | 0x0c | b'/' | 0xc2 | 0xe1 | 0xe2 | 0xe3 | 0xef => {
+          match json5_space(&self.text[idx..]) {
+            0 => self.fail(idx),
+            skipped => {
+              idx += skipped;
+              self.nonstandard = true;
+              continue;
+            }
+          }
+        }
+        _ => self.special_number(idx),
+      };
+    }
+  }
+
+  /// Whether `keyword` is at `idx`, not followed by more of a word.
+  fn keyword(&self, idx: usize, keyword: &str) -> bool {
+    self.text[idx..].starts_with(keyword.as_bytes())
+      && !self.at(idx + keyword.len()).is_ascii_alphanumeric()
+  }
+
+  /// The names JSON5 gives infinity and NaN, the latter read as null.
+  fn special_number(&mut self, idx: usize) -> Step {
+    const NAMES: [(&str, bool); 5] = [
+      ("inf", true),
+      ("infinity", true),
+      ("nan", false),
+      ("qnan", false),
+      ("snan", false),
+    ];
+    for (name, infinite) in NAMES {
+      if self.starts_with(idx, name)
+        && !self.at(idx + name.len()).is_ascii_alphanumeric()
+      {
+        self.nonstandard = true;
+        let node = match infinite {
+          true => Node::Float("9e999".into()),
+          false => Node::Null,
+        };
+        return Step::Value(node, idx + name.len());
+      }
+    }
+    self.fail(idx)
+  }
+
+  fn object(&mut self, start: usize) -> Step {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return self.fail(start);
+    }
+    let mut members = vec![];
+    let mut idx = start + 1;
+    loop {
+      let (label, mut after) = match self.value(idx) {
+        Step::Value(label, after) => (label, after),
+        Step::CloseObject => {
+          idx = self.error;
+          if !members.is_empty() {
+            self.nonstandard = true;
+          }
+          break;
+        }
+        step => {
+          // A JSON5 label may be an identifier.
+          idx += json5_space(&self.text[idx..]);
+          let mut kind = Escapes::None;
+          let c = self.at(idx);
+          if is_identifier_start(c)
+            || (c == b'\\' && self.hex_escape(idx + 1, &mut kind))
+          {
+            let mut end = idx + 1;
+            loop {
+              let c = self.at(end);
+              if (is_identifier(c) && json5_space(&self.text[end..]) == 0)
+                || (c == b'\\' && self.hex_escape(end + 1, &mut kind))
+              {
+                end += 1;
+              } else {
+                break;
+              }
+            }
+            self.nonstandard = true;
+            let label = self.slice(idx, end);
+            (kind.node(label), end)
+          } else {
+            if !matches!(step, Step::Error) {
+              self.error = idx;
+            }
+            return Step::Error;
+          }
+        }
+      };
+      if !label.is_text() {
+        return self.fail(idx);
+      }
+      if self.at(after) == b':' {
+        after += 1;
+      } else {
+        if is_space(self.at(after)) {
+          while is_space(self.at(after)) {
+            after += 1;
+          }
+        }
+        if self.at(after) == b':' {
+          after += 1;
+        } else {
+          match self.value(after) {
+            Step::Colon => after = self.error + 1,
+            Step::Error => return Step::Error,
+            _ => return self.fail(after),
+          }
+        }
+      }
+      match self.value(after) {
+        Step::Value(value, next) => {
+          members.push((label, value));
+          idx = next;
+        }
+        Step::Error => return Step::Error,
+        _ => return self.fail(after),
+      }
+      match self.at(idx) {
+        b',' => {
+          idx += 1;
+          continue;
+        }
+        b'}' => break,
+        _ => {}
+      }
+      while is_space(self.at(idx)) {
+        idx += 1;
+      }
+      match self.at(idx) {
+        b',' => {
+          idx += 1;
+          continue;
+        }
+        b'}' => break,
+        _ => {}
+      }
+      match self.value(idx) {
+        Step::Comma => idx = self.error + 1,
+        Step::CloseObject => {
+          idx = self.error;
+          break;
+        }
+        _ => return self.fail(idx),
+      }
+    }
+    self.depth -= 1;
+    Step::Value(Node::Object(members), idx + 1)
+  }
+
+  fn array(&mut self, start: usize) -> Step {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return self.fail(start);
+    }
+    let mut elements = vec![];
+    let mut idx = start + 1;
+    loop {
+      match self.value(idx) {
+        Step::Value(element, next) => {
+          elements.push(element);
+          idx = next;
+        }
+        Step::CloseArray => {
+          idx = self.error;
+          if !elements.is_empty() {
+            self.nonstandard = true;
+          }
+          break;
+        }
+        Step::Error => return Step::Error,
+        _ => return self.fail(idx),
+      }
+      match self.at(idx) {
+        b',' => {
+          idx += 1;
+          continue;
+        }
+        b']' => break,
+        _ => {}
+      }
+      while is_space(self.at(idx)) {
+        idx += 1;
+      }
+      match self.at(idx) {
+        b',' => {
+          idx += 1;
+          continue;
+        }
+        b']' => break,
+        _ => {}
+      }
+      match self.value(idx) {
+        Step::Comma => idx = self.error + 1,
+        Step::CloseArray => {
+          idx = self.error;
+          break;
+        }
+        _ => return self.fail(idx),
+      }
+    }
+    self.depth -= 1;
+    Step::Value(Node::Array(elements), idx + 1)
+  }
+
+  /// Whether four hexadecimal digits follow the `u` at `idx`, as in the
+  /// `\uXXXX` escapes labels may hold. Sets `kind` to that of a string with
+  /// escapes.
+  fn hex_escape(&self, idx: usize, kind: &mut Escapes) -> bool {
+    if self.at(idx) != b'u' || !self.is_hex(idx + 1, 4) {
+      return false;
+    }
+    *kind = Escapes::Json5;
+    true
+  }
+
+  fn is_hex(&self, idx: usize, count: usize) -> bool {
+    (idx..idx + count).all(|idx| self.at(idx).is_ascii_hexdigit())
+  }
+
+  fn slice(&self, start: usize, end: usize) -> String {
+    String::from_utf8_lossy(&self.text[start..end]).into_owned()
+  }
+
+  fn string(&mut self, start: usize) -> Step {
+    let quote = self.at(start);
+    let mut kind = Escapes::None;
+    let mut idx = start + 1;
+    loop {
+      let c = self.at(idx);
+      if is_plain(c) {
+        idx += 1;
+        continue;
+      }
+      if c == quote {
+        break;
+      }
+      if c == b'\\' {
+        idx += 1;
+        let c = self.at(idx);
+        let next = self.at(idx + 1);
+        if matches!(c, b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't')
+          || (c == b'u' && self.is_hex(idx + 1, 4))
+        {
+          if kind == Escapes::None {
+            kind = Escapes::Json;
+          }
+        } else if matches!(c, b'\'' | b'v' | b'\n')
+          || (c == b'0' && !next.is_ascii_digit())
+          || (c == 0xe2
+            && next == 0x80
+            && matches!(self.at(idx + 2), 0xa8 | 0xa9))
+          || (c == b'x' && self.is_hex(idx + 1, 2))
+        {
+          kind = Escapes::Json5;
+          self.nonstandard = true;
+        } else if c == b'\r' {
+          if next == b'\n' {
+            idx += 1;
+          }
+          kind = Escapes::Json5;
+          self.nonstandard = true;
+        } else {
+          return self.fail(idx);
+        }
+      } else if c == 0 {
+        return self.fail(idx);
+      } else if c <= 0x1f {
+        kind = Escapes::Json5;
+        self.nonstandard = true;
+      } else if c == b'"' {
+        kind = Escapes::Json5;
+      }
+      idx += 1;
+    }
+    Step::Value(kind.node(self.slice(start + 1, idx)), idx + 1)
+  }
+
+  fn number(&mut self, start: usize) -> Step {
+    // Bit 0 of `kind` is set for JSON5 numbers, bit 1 for reals.
+    let mut kind = 0;
+    let c = self.at(start);
+    if c == b'.' {
+      kind = 3;
+    } else if c == b'0' {
+      if matches!(self.at(start + 1), b'x' | b'X')
+        && self.at(start + 2).is_ascii_hexdigit()
+      {
+        return self.hexadecimal(start, start + 3);
+      } else if self.at(start + 1).is_ascii_digit() {
+        return self.fail(start + 1);
+      }
+    } else if c == b'-' || c == b'+' {
+      let next = self.at(start + 1);
+      if !next.is_ascii_digit() {
+        if self.starts_with(start + 1, "inf") {
+          self.nonstandard = true;
+          let node = match c {
+            b'-' => Node::Float("-9e999".into()),
+            _ => Node::Float("9e999".into()),
+          };
+          let end = match self.starts_with(start + 4, "inity") {
+            true => start + 9,
+            false => start + 4,
+          };
+          return Step::Value(node, end);
+        }
+        if next != b'.' {
+          return self.fail(start);
+        }
+        self.nonstandard = true;
+        kind |= 1;
+      } else if next == b'0' {
+        if self.at(start + 2).is_ascii_digit() {
+          return self.fail(start + 1);
+        }
+        if matches!(self.at(start + 2), b'x' | b'X')
+          && self.at(start + 3).is_ascii_hexdigit()
+        {
+          return self.hexadecimal(start, start + 4);
+        }
+      }
+    }
+    let mut exponent = false;
+    let mut idx = start + 1;
+    loop {
+      let c = self.at(idx);
+      if c.is_ascii_digit() {
+        idx += 1;
+      } else if c == b'.' {
+        if kind & 2 != 0 {
+          return self.fail(idx);
+        }
+        kind |= 2;
+        idx += 1;
+      } else if c == b'e' || c == b'E' {
+        if self.at(idx - 1) < b'0' {
+          if !self.ends_with_point(start, idx) {
+            return self.fail(idx);
+          }
+          kind |= 1;
+        }
+        if exponent {
+          return self.fail(idx);
+        }
+        kind |= 2;
+        exponent = true;
+        if matches!(self.at(idx + 1), b'+' | b'-') {
+          idx += 1;
+        }
+        if !self.at(idx + 1).is_ascii_digit() {
+          return self.fail(idx);
+        }
+        idx += 2;
+      } else {
+        break;
+      }
+    }
+    if self.at(idx - 1) < b'0' {
+      if !self.ends_with_point(start, idx) {
+        return self.fail(idx);
+      }
+      kind |= 1;
+    }
+    let text = self.number_text(start, idx);
+    let node = match kind {
+      0 => Node::Int(text),
+      1 => Node::Int5(text),
+      2 => Node::Float(text),
+      _ => Node::Float5(text),
+    };
+    Step::Value(node, idx)
+  }
+
+  /// Whether the digits of the number at `start` end with a decimal point
+  /// at `end`, as JSON5 allows.
+  fn ends_with_point(&mut self, start: usize, end: usize) -> bool {
+    let is_json5 = self.at(end - 1) == b'.'
+      && end >= start + 2
+      && self.at(end - 2).is_ascii_digit();
+    self.nonstandard |= is_json5;
+    is_json5
+  }
+
+  /// The hexadecimal integer at `start`, whose digits go on from `idx`.
+  fn hexadecimal(&mut self, start: usize, mut idx: usize) -> Step {
+    self.nonstandard = true;
+    while self.at(idx).is_ascii_hexdigit() {
+      idx += 1;
+    }
+    Step::Value(Node::Int5(self.number_text(start, idx)), idx)
+  }
+
+  /// The text of a number, without the `+` JSON5 allows before it.
+  fn number_text(&self, start: usize, end: usize) -> String {
+    match self.at(start) {
+      b'+' => self.slice(start + 1, end),
+      _ => self.slice(start, end),
+    }
+  }
+
+  fn starts_with(&self, idx: usize, word: &str) -> bool {
+    self
+      .text
+      .get(idx..idx + word.len())
+      .is_some_and(|text| text.eq_ignore_ascii_case(word.as_bytes()))
+  }
+}
+
+fn is_space(c: u8) -> bool {
+  matches!(c, b'\t' | b'\n' | b'\r' | b' ')
+}
+
+/// Whether the character stands for itself in a JSON string.
+fn is_plain(c: u8) -> bool {
+  c > 0x1f && c != b'"' && c != b'\'' && c != b'\\'
+}
+
+fn is_identifier_start(c: u8) -> bool {
+  c.is_ascii_alphabetic() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+fn is_identifier(c: u8) -> bool {
+  is_identifier_start(c) || c.is_ascii_digit()
+}
+
+/// The length of the whitespace and comments JSON5 allows at the start of
+/// `text`.
+fn json5_space(text: &[u8]) -> usize {
+  let at = |idx: usize| text.get(idx).copied().unwrap_or(0);
+  let mut idx = 0;
+  loop {
+    match at(idx) {
+      b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' => idx += 1,
+      b'/' if at(idx + 1) == b'*' => {
+        let Some(end) = text[idx + 2..].windows(2).position(|w| w == b"*/")
+        else {
+          return idx;
+        };
+        idx += end + 4;
+      }
+      b'/' if at(idx + 1) == b'/' => {
+        idx += 2;
+        while idx < text.len() {
+          match at(idx) {
+            b'\n' | b'\r' => {
+              idx += 1;
+              break;
+            }
+            0xe2
+              if at(idx + 1) == 0x80 && matches!(at(idx + 2), 0xa8 | 0xa9) =>
+            {
+              idx += 3;
+              break;
+            }
+            _ => idx += 1,
+          }
+        }
+      }
+      // U+00A0
+      0xc2 if at(idx + 1) == 0xa0 => idx += 2,
+      // U+1680
+      0xe1 if at(idx + 1) == 0x9a && at(idx + 2) == 0x80 => idx += 3,
+      // U+2000 to U+200A, U+2028, U+2029, U+202F and U+205F
+      0xe2
+        if at(idx + 1) == 0x80
+          && (at(idx + 2) <= 0x8a
+            || matches!(at(idx + 2), 0xa8 | 0xa9 | 0xaf)) =>
+      {
+        idx += 3
+      }
+      0xe2 if at(idx + 1) == 0x81 && at(idx + 2) == 0x9f => idx += 3,
+      // U+3000
+      0xe3 if at(idx + 1) == 0x80 && at(idx + 2) == 0x80 => idx += 3,
+      // U+FEFF
+      0xef if at(idx + 1) == 0xbb && at(idx + 2) == 0xbf => idx += 3,
+      _ => return idx,
+    }
+  }
+}
+
+/// Decodes the escapes of the text of a string.
+pub(crate) fn unescape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    let decoded = match chars.next() {
+      Some('u') => {
+        let mut code = hex_digits(&mut chars, 4);
+        // A surrogate pair, as two escapes.
+        if (0xd800..0xdc00).contains(&code) {
+          let mut ahead = chars.clone();
+          if ahead.next() == Some('\\') && ahead.next() == Some('u') {
+            let low = hex_digits(&mut ahead, 4);
+            if (0xdc00..0xe000).contains(&low) {
+              code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+              chars = ahead;
+            }
+          }
+        }
+        char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+      }
+      Some('b') => '\u{8}',
+      Some('f') => '\u{c}',
+      Some('n') => '\n',
+      Some('r') => '\r',
+      Some('t') => '\t',
+      Some('v') => '\u{b}',
+      Some('0') => '\0',
+      Some('x') => {
+        char::from_u32(hex_digits(&mut chars, 2)).unwrap_or_default()
+      }
+      // Line continuations.
+      Some('\r') => {
+        chars.next_if_eq(&'\n');
+        continue;
+      }
+      Some('\n' | '\u{2028}' | '\u{2029}') => continue,
+      Some(c) => c,
+      None => break,
+    };
+    out.push(decoded);
+  }
+  out
+}
+
+/// The value of up to `count` hexadecimal digits.
+fn hex_digits(chars: &mut Peekable<Chars<'_>>, count: usize) -> u32 {
+  let mut value = 0;
+  for _ in 0..count {
+    match chars.peek().and_then(|c| c.to_digit(16)) {
+      Some(digit) => {
+        value = value * 16 + digit;
+        chars.next();
+      }
+      None => break,
+    }
+  }
+  value
+}
+
+/// Appends `text` as a JSON string.
+pub(crate) fn append_string(out: &mut String, text: &str) {
+  out.push('"');
+  for c in text.chars() {
+    match c {
+      '"' | '\\' => {
+        out.push('\\');
+        out.push(c);
+      }
+      '\0'..='\u{1f}' => append_control(out, c as u8),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+fn append_control(out: &mut String, c: u8) {
+  match c {
+    0x08 => out.push_str("\\b"),
+    0x09 => out.push_str("\\t"),
+    0x0a => out.push_str("\\n"),
+    0x0c => out.push_str("\\f"),
+    0x0d => out.push_str("\\r"),
+    c => out.push_str(&format!("\\u{c:04x}")),
+  }
+}
+
+/// Renders a string with JSON5 escapes as a standard JSON string.
+fn render_text5(out: &mut String, text: &str) {
+  out.push('"');
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\0'..='\u{1f}' => append_control(out, c as u8),
+      '\\' => match chars.next() {
+        Some('\'') => out.push('\''),
+        Some('v') => out.push_str("\\u000b"),
+        Some('x') => {
+          out.push_str("\\u00");
+          out.extend(chars.by_ref().take(2));
+        }
+        Some('0') => out.push_str("\\u0000"),
+        Some('\r') => {
+          chars.next_if_eq(&'\n');
+        }
+        Some('\n' | '\u{2028}' | '\u{2029}') | None => {}
+        Some(c) => {
+          out.push('\\');
+          out.push(c);
+        }
+      },
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
@@ -0,0 +1,233 @@
+//! JSON paths: `$` for the whole value, followed by steps into it, `.label`
+//! or `."label"` for a member of an object, `[N]` for an element of an
+//! array, `[#]` past its last element, and `[#-N]` counting from its end.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html#path_arguments
+
+use super::node::{unescape, Node};
+
+/// A malformed path.
+#[derive(Debug)]
+pub(crate) struct BadPath;
+
+/// A change to the element a path leads to.
+#[derive(Debug)]
+pub(crate) enum Edit {
+  /// Removes it, when there is one.
+  Delete,
+  /// Adds it, when there is none.
+  Insert(Node),
+  /// Replaces it, when there is one.
+  Replace(Node),
+  /// Adds it, or replaces it.
+  Set(Node),
+}
+
+/// The first step of a path from a node.
+enum Step<'p> {
+  /// The step leads to the element, or the member, at this position,
+  /// whence the rest of the path goes on.
+  Child(usize, &'p str),
+  /// The step leads to a member the object does not have, with this label,
+  /// or just past the end of the array.
+  Missing(Option<Node>, &'p str),
+  NotFound,
+}
+
+/// The positions of the elements the steps of `path` lead to from `node`,
+/// one for each step, or `None` when there is no such element. A path
+/// that does not fit the value is only found malformed up to the step
+/// that does not fit.
+pub(crate) fn find(
+  mut node: &Node,
+  mut path: &str,
+) -> Result<Option<Vec<usize>>, BadPath> {
+  let mut trail = vec![];
+  while !path.is_empty() {
+    let Step::Child(idx, rest) = step(node, path)? else {
+      return Ok(None);
+    };
+    trail.push(idx);
+    node = child(node, idx);
+    path = rest;
+  }
+  Ok(Some(trail))
+}
+
+/// The element the steps of `path` lead to from `node`.
+pub(crate) fn lookup<'a>(
+  node: &'a Node,
+  path: &str,
+) -> Result<Option<&'a Node>, BadPath> {
+  Ok(find(node, path)?.map(|trail| descend(node, &trail)))
+}
+
+/// The element at the positions of `trail` within `node`.
+pub(crate) fn descend<'a>(node: &'a Node, trail: &[usize]) -> &'a Node {
+  trail.iter().fold(node, |node, &idx| child(node, idx))
+}
+
+/// Applies `change` to the element the steps of `path`, of which there is
+/// at least one, lead to from `node`. The arrays and objects missing on the
+/// way to an element added are created. Returns whether the path led
+/// anywhere.
+pub(crate) fn edit(
+  node: &mut Node,
+  path: &str,
+  change: &Edit,
+) -> Result<bool, BadPath> {
+  match step(node, path)? {
+    Step::NotFound => Ok(false),
+    Step::Child(idx, "") => {
+      match (node, change) {
+        (_, Edit::Insert(_)) => {}
+        (Node::Array(elements), Edit::Delete) => {
+          elements.remove(idx);
+        }
+        (Node::Object(members), Edit::Delete) => {
+          members.remove(idx);
+        }
+        (node, Edit::Replace(value) | Edit::Set(value)) => {
+          *child_mut(node, idx) = value.clone();
+        }
+        _ => {}
+      }
+      Ok(true)
+    }
+    Step::Child(idx, rest) => edit(child_mut(node, idx), rest, change),
+    Step::Missing(label, rest) => {
+      let (Edit::Insert(value) | Edit::Set(value)) = change else {
+        return Ok(false);
+      };
+      let value = match rest.starts_with('.') {
+        _ if rest.is_empty() => Some(value.clone()),
+        true => created(Node::Object(vec![]), rest, change)?,
+        false => created(Node::Array(vec![]), rest, change)?,
+      };
+      let Some(value) = value else {
+        return Ok(false);
+      };
+      match node {
+        Node::Object(members) => members.extend(label.map(|l| (l, value))),
+        Node::Array(elements) => elements.push(value),
+        _ => {}
+      }
+      Ok(true)
+    }
+  }
+}
+
+/// The array or object `empty` once the rest of a path is added to it,
+/// if it can be.
+fn created(
+  mut empty: Node,
+  path: &str,
+  change: &Edit,
+) -> Result<Option<Node>, BadPath> {
+  Ok(edit(&mut empty, path, change)?.then_some(empty))
+}
+
+fn step<'p>(node: &Node, path: &'p str) -> Result<Step<'p>, BadPath> {
+  let bytes = path.as_bytes();
+  match bytes.first() {
+    Some(b'.') => {
+      let path = &path[1..];
+      let (key, raw, rest) = match path.strip_prefix('"') {
+        Some(quoted) => {
+          let bytes = quoted.as_bytes();
+          let mut end = 0;
+          loop {
+            match bytes.get(end) {
+              None => return Err(BadPath),
+              Some(b'"') => break,
+              Some(b'\\') if end + 1 < bytes.len() => end += 2,
+              Some(_) => end += 1,
+            }
+          }
+          let key = &quoted[..end];
+          (key, !key.contains('\\'), &quoted[end + 1..])
+        }
+        None => {
+          let end = path.find(['.', '[']).unwrap_or(path.len());
+          if end == 0 {
+            return Err(BadPath);
+          }
+          (&path[..end], true, &path[end..])
+        }
+      };
+      let Node::Object(members) = node else {
+        return Ok(Step::NotFound);
+      };
+      let text = match raw {
+        true => key.into(),
+        false => unescape(key),
+      };
+      let found = members.iter().position(|(label, _)| label.text() == text);
+      Ok(match found {
+        Some(idx) => Step::Child(idx, rest),
+        None => {
+          let label = match raw {
+            true => Node::TextRaw(key.into()),
+            false => Node::Text5(key.into()),
+          };
+          Step::Missing(Some(label), rest)
+        }
+      })
+    }
+    Some(b'[') => {
+      let Node::Array(elements) = node else {
+        return Ok(Step::NotFound);
+      };
+      let count = elements.len();
+      let number = |start: usize| {
+        let digits = bytes[start..].iter().take_while(|c| c.is_ascii_digit());
+        digits.fold((0usize, start), |(value, end), c| {
+          let value = value.saturating_mul(10);
+          (value.saturating_add(usize::from(c - b'0')), end + 1)
+        })
+      };
+      let (mut index, mut end) = number(1);
+      if end < 2 || bytes.get(end) != Some(&b']') {
+        if bytes.get(1) != Some(&b'#') {
+          return Err(BadPath);
+        }
+        (index, end) = (count, 2);
+        if bytes.get(2) == Some(&b'-')
+          && bytes.get(3).is_some_and(u8::is_ascii_digit)
+        {
+          let (back, after) = number(3);
+          if back > count {
+            return Ok(Step::NotFound);
+          }
+          (index, end) = (count - back, after);
+        }
+        if bytes.get(end) != Some(&b']') {
+          return Err(BadPath);
+        }
+      }
+      let rest = &path[end + 1..];
+      Ok(match index {
+        index if index < count => Step::Child(index, rest),
+        index if index == count => Step::Missing(None, rest),
+        _ => Step::NotFound,
+      })
+    }
+    _ => Err(BadPath),
+  }
+}
+
+fn child(node: &Node, idx: usize) -> &Node {
+  match node {
+    Node::Array(elements) => &elements[idx],
+    Node::Object(members) => &members[idx].1,
+    node => node,
+  }
+}
+
+fn child_mut(node: &mut Node, idx: usize) -> &mut Node {
+  match node {
+    Node::Array(elements) => &mut elements[idx],
+    Node::Object(members) => &mut members[idx].1,
+    node => node,
+  }
+}
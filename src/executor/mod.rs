@@ -0,0 +1,452 @@
+//! # Query execution
+//!
+//!  A statement is compiled into a tree of operators, each pulling rows from
+//! the ones below it: table scans read the b-trees of the database through
+//! cursors, and the operators above them filter, compute and sort the rows.
+//! Rows are produced one at a time, as the caller asks for them.
+//!
+//! ```
+//! use sqlite_rs::{runtime::Value, SqliteConnection};
+//!
+//! let mut conn = SqliteConnection::open(":memory:").unwrap();
+//! let mut rows = conn.query("SELECT 1 + 1 AS two, 'a' || 'b'").unwrap();
+//! assert_eq!(rows.column_names(), ["two", "'a' || 'b'"]);
+//! let row = rows.next().unwrap().unwrap();
+//! assert_eq!(row.get_by_name("two"), Some(&Value::Integer(2)));
+//! assert!(rows.next().is_none());
+//! ```
+//!
+//! *Reference:* https://www.sqlite.org/arch.html
+
+mod aggregate;
+mod datetime;
+mod ddl;
+mod dml;
+mod expr;
+mod foreign_key;
+mod function;
+mod join;
+mod json;
+mod operator;
+mod pattern;
+mod planner;
+mod pragma;
+mod printf;
+mod query_plan;
+mod sorter;
+mod statement;
+mod subquery;
+mod transaction;
+mod trigger;
+mod user_function;
+mod value;
+mod vdbe;
+mod window;
+
+use self::operator::Operator;
+use self::planner::{unsupported, Plan, Planner};
+use self::statement::Prepared;
+use self::subquery::Subquery;
+use self::transaction::Undo;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{SqliteBtree, Value};
+use crate::sql::ast::{RaiseAction, StatementKind};
+use crate::SqliteConnection;
+use core::cell::OnceCell;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub use self::statement::Statement;
+pub(crate) use self::statement::StatementCache;
+pub(crate) use self::transaction::DeferredViolation;
+pub(crate) use self::user_function::{Functions, UserFunction};
+
+/// Bytes of rows a sort holds in memory by default, before it spills them
+/// to temporary files.
+pub const DEFAULT_SORT_MEMORY_LIMIT: usize = 64 << 20;
+
+/// Parsed statements a connection keeps by default, for the same SQL text
+/// to be run again without parsing it.
+pub const DEFAULT_STATEMENT_CACHE_CAPACITY: usize = 16;
+
+/// The `cache_size` pragma of a new connection: pages when positive, else
+/// KiB.
+pub const DEFAULT_CACHE_SIZE: i64 = -2000;
+
+/// The rows of a query, read as the iterator advances.
+#[derive(Debug)]
+pub struct Rows<'a> {
+  context: Context<'a>,
+  root: Box<dyn Operator>,
+  columns: Arc<[String]>,
+  done: bool,
+}
+
+impl<'a> Rows<'a> {
+  fn new(
+    conn: &'a mut SqliteConnection,
+    plan: Plan,
+    parameters: &'a [Value],
+  ) -> Self {
+    Self {
+      context: Context::new(conn, plan.subqueries, parameters),
+      root: plan.root,
+      columns: plan.columns.into(),
+      done: false,
+    }
+  }
+
+  /// Names of the result columns: their alias, the name of the column they
+  /// read, or else the text of their expression.
+  pub fn column_names(&self) -> &[String] {
+    &self.columns
+  }
+}
+
+impl Iterator for Rows<'_> {
+  type Item = SqliteResult<Row>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    match self.root.next(&mut self.context) {
+      Ok(Some(values)) => Some(Ok(Row {
+        columns: self.columns.clone(),
+        values,
+      })),
+      Ok(None) => {
+        self.done = true;
+        None
+      }
+      Err(error) => {
+        self.done = true;
+        Some(Err(error))
+      }
+    }
+  }
+}
+
+/// A row of a query result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+  columns: Arc<[String]>,
+  values: Vec<Value>,
+}
+
+impl Row {
+  pub fn column_names(&self) -> &[String] {
+    &self.columns
+  }
+
+  pub fn values(&self) -> &[Value] {
+    &self.values
+  }
+
+  pub fn into_values(self) -> Vec<Value> {
+    self.values
+  }
+
+  /// The value of the column at `idx`.
+  pub fn get(&self, idx: usize) -> Option<&Value> {
+    self.values.get(idx)
+  }
+
+  /// The value of the first column named `name`, ignoring ASCII case.
+  pub fn get_by_name(&self, name: &str) -> Option<&Value> {
+    let idx = self
+      .columns
+      .iter()
+      .position(|column| column.eq_ignore_ascii_case(name))?;
+    self.values.get(idx)
+  }
+}
+
+/// State shared by the operators of a running statement.
+#[derive(Debug)]
+pub(crate) struct Context<'a> {
+  conn: &'a mut SqliteConnection,
+  /// Rows of the enclosing queries, innermost last, read by correlated
+  /// subqueries.
+  outer: Vec<Vec<Value>>,
+  /// The subqueries of the statement, which expressions and table scans
+  /// refer to by their position.
+  subqueries: Vec<Subquery>,
+  /// Values bound to the parameters of the statement, by index minus one.
+  parameters: &'a [Value],
+  /// The time of the first `'now'` of the statement, which all of them
+  /// read.
+  now: OnceCell<i64>,
+  /// The names of the triggers whose programs are running, the innermost
+  /// last.
+  triggers: Vec<String>,
+  /// The action of the `RAISE()` failing the statement, or `FAIL` when a
+  /// constraint failed under `OR FAIL` within a trigger program.
+  raised: Option<RaiseAction>,
+}
+
+impl<'a> Context<'a> {
+  fn new(
+    conn: &'a mut SqliteConnection,
+    subqueries: Vec<Subquery>,
+    parameters: &'a [Value],
+  ) -> Self {
+    Self {
+      conn,
+      outer: vec![],
+      subqueries,
+      parameters,
+      now: OnceCell::new(),
+      triggers: vec![],
+      raised: None,
+    }
+  }
+
+  pub(crate) fn btree(&mut self) -> SqliteBtree<'_> {
+    self.conn.runtime_mut().btree()
+  }
+
+  /// The schema cookie of the database, which changes with its schema.
+  pub(crate) fn schema_cookie(&self) -> u32 {
+    **self.conn.runtime().header().schema_cookie()
+  }
+
+  pub(crate) fn sort_memory_limit(&self) -> usize {
+    self.conn.sort_memory_limit()
+  }
+
+  /// The row of the query `depth` levels above the current one.
+  pub(crate) fn outer_row(&self, depth: usize) -> SqliteResult<&[Value]> {
+    self
+      .outer
+      .len()
+      .checked_sub(depth)
+      .and_then(|idx| self.outer.get(idx))
+      .map(Vec::as_slice)
+      .ok_or(SqliteError::Custom(format!(
+        "No enclosing query {depth} levels up"
+      )))
+  }
+
+  /// The value bound to the parameter numbered `index`, NULL when none is.
+  pub(crate) fn parameter(&self, index: usize) -> Value {
+    index
+      .checked_sub(1)
+      .and_then(|idx| self.parameters.get(idx))
+      .cloned()
+      .unwrap_or(Value::Null)
+  }
+
+  /// Rows changed by the last completed `INSERT`, `UPDATE` or `DELETE`.
+  pub(crate) fn changes(&self) -> u64 {
+    self.conn.changes()
+  }
+
+  /// Rows changed since the connection was opened.
+  pub(crate) fn total_changes(&self) -> u64 {
+    self.conn.total_changes()
+  }
+
+  /// Rowid of the last row inserted in a rowid table.
+  pub(crate) fn last_insert_rowid(&self) -> i64 {
+    self.conn.last_insert_rowid()
+  }
+
+  /// The current time, in milliseconds since the unix epoch. It is read
+  /// once per statement, for the statement to see a single current time.
+  pub(crate) fn now(&self) -> i64 {
+    *self.now.get_or_init(|| {
+      SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+    })
+  }
+}
+
+/// Compiles the single `SELECT`, `EXPLAIN` or `PRAGMA` statement of `sql`
+/// and starts running it.
+pub(crate) fn query<'a>(
+  conn: &'a mut SqliteConnection,
+  sql: &str,
+) -> SqliteResult<Rows<'a>> {
+  let prepared = conn.statements.prepare(sql, "queried")?;
+  start_query(conn, &prepared, &[])
+}
+
+/// Starts running the `SELECT` statement `prepared`, with `parameters`
+/// bound.
+fn start_query<'a>(
+  conn: &'a mut SqliteConnection,
+  prepared: &Prepared,
+  parameters: &'a [Value],
+) -> SqliteResult<Rows<'a>> {
+  let plan = plan_query(conn, prepared)?;
+  Ok(Rows::new(conn, plan, parameters))
+}
+
+/// Compiles the `SELECT`, `EXPLAIN` or `PRAGMA` statement `prepared`.
+fn plan_query(
+  conn: &mut SqliteConnection,
+  prepared: &Prepared,
+) -> SqliteResult<Plan> {
+  if let StatementKind::Pragma(statement) = &prepared.statement.kind {
+    return pragma::plan(conn, statement);
+  }
+  let mut planner =
+    Planner::new(&mut conn.runtime, &conn.functions, &prepared.sql)
+      .with_foreign_keys(conn.foreign_keys);
+  match &prepared.statement.kind {
+    StatementKind::Select(select) => planner.select(select),
+    StatementKind::Explain {
+      statement,
+      query_plan: true,
+    } => match &statement.kind {
+      StatementKind::Select(select) => planner.explain_query_plan(select),
+      kind => Err(unsupported(&format!(
+        "EXPLAIN QUERY PLAN of {}",
+        statement_name(kind)
+      ))),
+    },
+    StatementKind::Explain { statement, .. } => match &statement.kind {
+      StatementKind::Select(select) => planner.explain(select),
+      StatementKind::Insert(insert) => {
+        let write = planner.insert(insert)?;
+        planner.explain_write(&write)
+      }
+      StatementKind::Update(update) => {
+        let write = planner.update(update)?;
+        planner.explain_write(&write)
+      }
+      StatementKind::Delete(delete) => {
+        let write = planner.delete(delete)?;
+        planner.explain_write(&write)
+      }
+      kind => Err(unsupported(&format!("EXPLAIN of {}", statement_name(kind)))),
+    },
+    _ => Err(SqliteError::Custom(
+      "Only SELECT statements can be queried".into(),
+    )),
+  }
+}
+
+/// The statements of `kind`, as errors name them.
+fn statement_name(kind: &StatementKind) -> &'static str {
+  match kind {
+    StatementKind::Insert(_) => "INSERT statements",
+    StatementKind::Update(_) => "UPDATE statements",
+    StatementKind::Delete(_) => "DELETE statements",
+    StatementKind::Pragma(_) => "PRAGMA statements",
+    StatementKind::Explain { .. } => "EXPLAIN statements",
+    _ => "schema and transaction statements",
+  }
+}
+
+/// Whether `kind` is a statement returning rows.
+fn is_query(kind: &StatementKind) -> bool {
+  matches!(
+    kind,
+    StatementKind::Select(_)
+      | StatementKind::Explain { .. }
+      | StatementKind::Pragma(_)
+  )
+}
+
+/// Runs the single `INSERT`, `UPDATE`, `DELETE`, `CREATE`, `DROP`, `ALTER
+/// TABLE` or `PRAGMA` statement of `sql`, and returns the number of rows it
+/// changed.
+pub(crate) fn execute(
+  conn: &mut SqliteConnection,
+  sql: &str,
+) -> SqliteResult<u64> {
+  let prepared = conn.statements.prepare(sql, "executed")?;
+  run(conn, &prepared, &[])
+}
+
+/// Runs the statement `prepared`, with `parameters` bound, and returns the
+/// number of rows it changed. Its changes are committed once it completes,
+/// unless a transaction is open. When it fails, they are undone, unless its
+/// conflict resolution is `FAIL`, which keeps the changes made before the
+/// failure, or `ROLLBACK`, which undoes the whole transaction.
+///
+/// *Reference:* https://www.sqlite.org/lang_conflict.html
+fn run(
+  conn: &mut SqliteConnection,
+  prepared: &Prepared,
+  parameters: &[Value],
+) -> SqliteResult<u64> {
+  // Schema objects keep the text of the statement up to its last token.
+  let sql = &prepared.sql[..prepared.statement.span.end];
+  if let StatementKind::Pragma(statement) = &prepared.statement.kind {
+    return pragma::plan(conn, statement).map(|_| 0);
+  }
+  let mut planner = Planner::new(&mut conn.runtime, &conn.functions, sql)
+    .with_foreign_keys(conn.foreign_keys);
+  let mut change = match &prepared.statement.kind {
+    StatementKind::Insert(insert) => planner.insert(insert)?,
+    StatementKind::Update(update) => planner.update(update)?,
+    StatementKind::Delete(delete) => planner.delete(delete)?,
+    kind @ (StatementKind::Begin(_)
+    | StatementKind::Commit
+    | StatementKind::Rollback(None)) => {
+      return transaction::run(conn, kind).map(|()| 0)
+    }
+    kind => return change_schema(conn, sql, kind, parameters).map(|()| 0),
+  };
+  let subqueries = change.take_subqueries();
+  transaction::begin_statement(conn);
+  let mut ctx = Context::new(conn, subqueries, parameters);
+  let result = change.run(&mut ctx);
+  let undo = match (&result, ctx.raised) {
+    (Ok(()), _) => Undo::Nothing,
+    (Err(_), Some(RaiseAction::Rollback)) => Undo::Transaction,
+    (Err(_), _) if change.keeps_changes() => Undo::Nothing,
+    (Err(_), _) => Undo::Statement,
+  };
+  transaction::end_statement(conn, undo)?;
+  result?;
+  conn.changes = change.changes();
+  conn.total_changes += change.changes();
+  if let Some(rowid) = change.last_rowid() {
+    conn.last_insert_rowid = rowid;
+  }
+  Ok(change.changes())
+}
+
+/// Runs the schema change `statement`, which is committed once it completes,
+/// unless a transaction is open, and undone when it fails.
+fn change_schema(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  statement: &StatementKind,
+  parameters: &[Value],
+) -> SqliteResult<()> {
+  transaction::begin_statement(conn);
+  let result = match statement {
+    StatementKind::CreateTable(create) => {
+      ddl::create_table(conn, sql, create, parameters)
+    }
+    StatementKind::CreateIndex(create) => ddl::create_index(conn, sql, create),
+    StatementKind::Drop(drop) => ddl::drop(conn, drop),
+    StatementKind::AlterTable(alter) => ddl::alter_table(conn, sql, alter),
+    StatementKind::CreateView(create) => ddl::create_view(conn, sql, create),
+    StatementKind::CreateTrigger(create) => {
+      ddl::create_trigger(conn, sql, create)
+    }
+    StatementKind::CreateVirtualTable(_) => {
+      Err(unsupported("CREATE VIRTUAL TABLE"))
+    }
+    _ => {
+      return Err(SqliteError::Custom(
+        "Only INSERT, UPDATE, DELETE, CREATE, DROP and ALTER TABLE \
+         statements can be executed"
+          .into(),
+      ))
+    }
+  };
+  let undo = match result {
+    Ok(()) => Undo::Nothing,
+    Err(_) => Undo::Statement,
+  };
+  transaction::end_statement(conn, undo)?;
+  result
+}
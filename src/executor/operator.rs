@@ -0,0 +1,1940 @@
+//! Operators of a query plan. Each operator produces rows on demand, pulling
+//! them from the operators it is built on.
+
+use super::expr::Expr;
+use super::json::JsonEach;
+use super::pragma::PragmaTable;
+use super::query_plan::QueryPlan;
+use super::sorter::{SortKey, SortedRows, Sorter};
+use super::subquery::SubqueryScan;
+use super::value::{is_true, KeySet};
+use super::vdbe::{
+  affinity_code, Builder, Codegen, Consumer, Label, Opcode, Source,
+  Unsupported, P4,
+};
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  Affinity, BtreeCursor, Collation, KeyColumn, KeyInfo, TableCursor,
+  TableDefinition, Value,
+};
+use crate::sql::ast::CompoundOperator;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+pub(crate) trait Operator: Debug {
+  /// The next row, or `None` once every row was produced.
+  fn next(&mut self, ctx: &mut Context<'_>)
+    -> SqliteResult<Option<Vec<Value>>>;
+
+  /// Starts over: the rows are produced again from the first one, computed
+  /// afresh, as a correlated subquery does for each row of its query.
+  fn reset(&mut self);
+
+  /// Generates the code producing the rows of the operator, each handed to
+  /// `consume`. Operators the virtual machine cannot run yet, and those
+  /// built on them, are `Unsupported`.
+  fn compile(
+    &self,
+    _builder: &mut Builder,
+    _consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    Err(Unsupported("this query"))
+  }
+
+  /// Describes how the operator finds its rows, for `EXPLAIN QUERY PLAN`.
+  fn describe(&self, _plan: &mut QueryPlan<'_>) {}
+
+  /// The compound query whose rows the operator returns unchanged, if any.
+  fn compound(&self) -> Option<&Compound> {
+    None
+  }
+}
+
+/// Reads the rows of a table. Each row holds the values of the table columns
+/// followed by the rowid, NULL for `WITHOUT ROWID` tables.
+#[derive(Debug)]
+pub(crate) struct TableScan {
+  cursor: TableCursor,
+  /// The name the query gives the table.
+  name: String,
+  access: Access,
+  /// Columns with REAL affinity, whose integer values are stored that way
+  /// only to save space.
+  real_columns: Vec<usize>,
+  /// Whether the rows are read backwards, last first, for a query wanting
+  /// them in the reverse of the order of the b-tree.
+  reverse: bool,
+  started: bool,
+  /// Whether the cursor is on an entry not returned yet.
+  pending: bool,
+  /// The values looked up, through an index or as the rowid.
+  key: Vec<Value>,
+  /// The keys left to look up once the rows of `key` are read, the next
+  /// one last.
+  keys: Vec<Vec<Value>>,
+  /// The values of the bounds of the range of an index, for each key.
+  bounds: [Option<BoundValue>; 2],
+  /// The last key of a range in the order the scan reads them, with whether
+  /// it is within the range.
+  end: Option<(Vec<Value>, bool)>,
+}
+
+/// How a table scan finds its rows.
+#[derive(Debug)]
+pub(crate) enum Access {
+  /// Every row, in rowid or primary key order.
+  Scan,
+  /// The rows whose rowid is one of the looked up values.
+  Rowid(Probe),
+  /// The rows whose rowid is within the range.
+  RowidRange(Range),
+  /// The rows found through an index b-tree, whose leading columns equal
+  /// one of the looked up values each and whose next column is within the
+  /// range.
+  Index {
+    cursor: BtreeCursor,
+    /// The name of the index.
+    name: String,
+    /// The table columns of the leading values of the entries.
+    columns: Vec<usize>,
+    key_info: KeyInfo,
+    probes: Vec<Probe>,
+    range: Range,
+    target: IndexTarget,
+    /// Set when the entries hold every column the query reads: the rows are
+    /// then made of the entries alone, their other columns NULL.
+    covering: bool,
+  },
+  /// The rows found through an index built the first time they are looked
+  /// up.
+  Automatic(AutomaticIndex),
+}
+
+/// What the entries of an index b-tree lead to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum IndexTarget {
+  /// The entries are the rows of a `WITHOUT ROWID` table.
+  Table,
+  /// The entries end with the rowid of their row.
+  Rowid,
+  /// The entries hold the primary key of their row, in a `WITHOUT ROWID`
+  /// table, at these positions.
+  PrimaryKey(Vec<usize>),
+}
+
+/// A value a table scan looks up, computed from the row of the tables on
+/// the left of its table, or the values of an `IN` list, each looked up in
+/// turn.
+#[derive(Debug)]
+pub(crate) struct Probe {
+  pub(crate) exprs: Vec<Expr>,
+  /// The affinity of the comparison the values come from, applied to them.
+  pub(crate) affinity: Option<Affinity>,
+}
+
+impl Probe {
+  /// The values, without those that are NULL, which are equal to nothing.
+  fn eval(
+    &self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<Vec<Value>> {
+    let mut values = Vec::with_capacity(self.exprs.len());
+    for expr in self.exprs.iter() {
+      match (expr.eval(ctx, row)?, self.affinity) {
+        (Value::Null, _) => {}
+        (value, Some(affinity)) if affinity != Affinity::Blob => {
+          values.push(affinity.apply(value));
+        }
+        (value, _) => values.push(value),
+      }
+    }
+    Ok(values)
+  }
+}
+
+/// The values a column is between, when it is compared to looked up values
+/// with `<`, `<=`, `>`, `>=` or `BETWEEN`, or is `NOT NULL`.
+#[derive(Debug, Default)]
+pub(crate) struct Range {
+  pub(crate) low: Option<RangeBound>,
+  pub(crate) high: Option<RangeBound>,
+  /// Set by `IS NOT NULL`, which leaves the NULLs out of a range with no
+  /// low bound.
+  pub(crate) not_null: bool,
+}
+
+/// A bound of a range.
+#[derive(Debug)]
+pub(crate) struct RangeBound {
+  pub(crate) probe: Probe,
+  /// Whether the value itself is within the range.
+  pub(crate) inclusive: bool,
+}
+
+/// The value of a bound, with whether it is within the range.
+type BoundValue = (Value, bool);
+
+impl Range {
+  pub(crate) fn is_empty(&self) -> bool {
+    self.low.is_none() && self.high.is_none() && !self.not_null
+  }
+
+  /// The values of the low and high bounds. `None` when one is NULL, which
+  /// no value is within.
+  fn eval(
+    &self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<Option<[Option<BoundValue>; 2]>> {
+    let mut values = [None, None];
+    for (bound, value) in [&self.low, &self.high].into_iter().zip(&mut values) {
+      if let Some(bound) = bound {
+        match bound.probe.eval(ctx, row)?.pop() {
+          Some(bound_value) => *value = Some((bound_value, bound.inclusive)),
+          None => return Ok(None),
+        }
+      }
+    }
+    Ok(Some(values))
+  }
+
+  /// The terms `EXPLAIN QUERY PLAN` shows for the range on `column`.
+  fn terms(&self, column: &str) -> Vec<String> {
+    let low =
+      (self.low.is_some() || self.not_null).then(|| format!("{column}>?"));
+    let high = self.high.as_ref().map(|_| format!("{column}<?"));
+    low.into_iter().chain(high).collect()
+  }
+}
+
+/// An index on columns of a table no index covers, built from its rows the
+/// first time they are looked up, for a join that would otherwise scan the
+/// whole table for each row of the tables on its left.
+///
+/// *Reference:* https://www.sqlite.org/optoverview.html#automatic_indexes
+#[derive(Debug)]
+pub(crate) struct AutomaticIndex {
+  columns: Vec<usize>,
+  key_info: KeyInfo,
+  probes: Vec<Probe>,
+  /// The positions in the row of the values the query reads, the others
+  /// being left out of the index.
+  kept: Vec<usize>,
+  /// The keys of the index with their rows, sorted by key, once built.
+  entries: Option<Vec<(Vec<Value>, Vec<Value>)>>,
+  key: Vec<Value>,
+  position: usize,
+}
+
+impl AutomaticIndex {
+  pub(crate) fn new(
+    columns: Vec<usize>,
+    key_info: KeyInfo,
+    probes: Vec<Probe>,
+    kept: Vec<usize>,
+  ) -> Self {
+    Self {
+      columns,
+      key_info,
+      probes,
+      kept,
+      entries: None,
+      key: vec![],
+      position: 0,
+    }
+  }
+
+  /// Looks the values computed from `row` up, building the index first if
+  /// needed. Whether any entry has them.
+  fn seek(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+    cursor: &mut TableCursor,
+    real_columns: &[usize],
+  ) -> SqliteResult<bool> {
+    self.key.clear();
+    for probe in self.probes.iter() {
+      match probe.eval(ctx, row)?.pop() {
+        Some(value) => self.key.push(value),
+        None => return Ok(false),
+      }
+    }
+    let entries = match &mut self.entries {
+      Some(entries) => entries,
+      entries => {
+        let mut built = vec![];
+        let mut btree = ctx.btree();
+        let mut has_row = cursor.first(&mut btree)?;
+        while has_row {
+          let mut row = cursor.row(&mut btree)?;
+          let rowid = cursor.rowid(&btree)?;
+          convert_real(&mut row, real_columns);
+          row.push(rowid.map_or(Value::Null, Value::Integer));
+          let key = self
+            .columns
+            .iter()
+            .map(|&c| row[c].clone())
+            .collect::<Vec<_>>();
+          for (idx, value) in row.iter_mut().enumerate() {
+            if !self.kept.contains(&idx) {
+              *value = Value::Null;
+            }
+          }
+          built.push((key, row));
+          has_row = cursor.next(&mut btree)?;
+        }
+        built
+          .sort_by(|(left, _), (right, _)| self.key_info.compare(left, right));
+        entries.insert(built)
+      }
+    };
+    self.position = entries.partition_point(|(key, _)| {
+      self.key_info.compare(key, &self.key).is_lt()
+    });
+    Ok(self.next_row().is_some())
+  }
+
+  /// The row of the current entry, if it has the looked up values.
+  fn next_row(&self) -> Option<&Vec<Value>> {
+    let (key, row) = self.entries.as_ref()?.get(self.position)?;
+    self.key_info.compare(key, &self.key).is_eq().then_some(row)
+  }
+}
+
+/// Stores as REAL the integer values of the `real_columns` of `row`.
+fn convert_real(row: &mut [Value], real_columns: &[usize]) {
+  for &column in real_columns.iter() {
+    if let Value::Integer(int) = row[column] {
+      row[column] = Value::Real(int as f64);
+    }
+  }
+}
+
+/// The first rowid within a range whose low bound is `value`, `None` when
+/// no rowid is.
+pub(crate) fn first_rowid(value: &Value, inclusive: bool) -> Option<i64> {
+  match value {
+    Value::Integer(int) if inclusive => Some(*int),
+    Value::Integer(int) => int.checked_add(1),
+    Value::Real(real) if real.is_nan() => None,
+    Value::Real(real) if *real < i64::MIN as f64 => Some(i64::MIN),
+    Value::Real(real) if *real >= i64::MAX as f64 => None,
+    Value::Real(real) => {
+      let int = real.ceil() as i64;
+      match !inclusive && int as f64 == *real {
+        true => int.checked_add(1),
+        false => Some(int),
+      }
+    }
+    // Text and blobs are greater than any number.
+    _ => None,
+  }
+}
+
+/// The last rowid within a range whose high bound is `value`, `None` when
+/// no rowid is.
+pub(crate) fn last_rowid(value: &Value, inclusive: bool) -> Option<i64> {
+  match value {
+    Value::Integer(int) if inclusive => Some(*int),
+    Value::Integer(int) => int.checked_sub(1),
+    Value::Real(real) if real.is_nan() => None,
+    Value::Real(real) if *real < i64::MIN as f64 => None,
+    Value::Real(real) if *real >= i64::MAX as f64 => Some(i64::MAX),
+    Value::Real(real) => {
+      let int = real.floor() as i64;
+      match !inclusive && int as f64 == *real {
+        true => int.checked_sub(1),
+        false => Some(int),
+      }
+    }
+    Value::Null => None,
+    // Text and blobs are greater than any number.
+    _ => Some(i64::MAX),
+  }
+}
+
+/// Whether the index entry `record` has `key`, and is not past the `end`
+/// of its range, read backwards when `reverse` is set.
+fn is_key_within(
+  key_info: &KeyInfo,
+  record: &[Value],
+  key: &[Value],
+  end: Option<&(Vec<Value>, bool)>,
+  reverse: bool,
+) -> bool {
+  !end.is_some_and(|end| is_past(key_info, record, end, reverse))
+    && key_info.compare(record, key).is_eq()
+}
+
+/// Whether `key` is past the `end` of a range, read backwards when
+/// `reverse` is set.
+fn is_past(
+  key_info: &KeyInfo,
+  key: &[Value],
+  end: &(Vec<Value>, bool),
+  reverse: bool,
+) -> bool {
+  let ordering = key_info.compare(key, &end.0);
+  match if reverse {
+    ordering.reverse()
+  } else {
+    ordering
+  } {
+    Ordering::Greater => true,
+    Ordering::Equal => !end.1,
+    Ordering::Less => false,
+  }
+}
+
+/// The row of an index entry holding every column the query reads, its
+/// other columns NULL, with its rowid.
+fn covered_row(
+  definition: &TableDefinition,
+  columns: &[usize],
+  target: &IndexTarget,
+  record: &[Value],
+) -> (Vec<Value>, Option<i64>) {
+  let mut row = vec![Value::Null; definition.columns().len()];
+  for (&column, value) in columns.iter().zip(record) {
+    row[column] = value.clone();
+  }
+  let rowid = match target {
+    IndexTarget::Rowid => record.last().and_then(as_rowid),
+    IndexTarget::PrimaryKey(positions) => {
+      for (key, &position) in definition.primary_key().iter().zip(positions) {
+        if let Some(value) = record.get(position) {
+          row[key.column()] = value.clone();
+        }
+      }
+      None
+    }
+    IndexTarget::Table => None,
+  };
+  if let (Some(alias), Some(rowid)) = (definition.rowid_alias(), rowid) {
+    row[alias] = Value::Integer(rowid);
+  }
+  (row, rowid)
+}
+
+impl TableScan {
+  pub(crate) fn new(
+    cursor: TableCursor,
+    name: String,
+    access: Access,
+    real_columns: Vec<usize>,
+    reverse: bool,
+  ) -> Self {
+    Self {
+      cursor,
+      name,
+      access,
+      real_columns,
+      reverse,
+      started: false,
+      pending: false,
+      key: vec![],
+      keys: vec![],
+      bounds: [None, None],
+      end: None,
+    }
+  }
+
+  /// Starts over, looking rows up with the values computed from `row`.
+  pub(crate) fn rewind(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    self.started = true;
+    self.end = None;
+    self.keys.clear();
+    let reverse = self.reverse;
+    self.pending = match &mut self.access {
+      Access::Scan if reverse => self.cursor.last(&mut ctx.btree())?,
+      Access::Scan => self.cursor.first(&mut ctx.btree())?,
+      Access::Rowid(probe) => {
+        let values = probe.eval(ctx, row)?;
+        let mut rowids = values.iter().filter_map(as_rowid).collect::<Vec<_>>();
+        // The next rowid to look up is the last one.
+        rowids.sort_unstable_by(|left, right| match reverse {
+          true => left.cmp(right),
+          false => right.cmp(left),
+        });
+        rowids.dedup();
+        let rowids = rowids.into_iter();
+        self.keys = rowids.map(|rowid| vec![Value::Integer(rowid)]).collect();
+        self.seek_next_key(ctx)?
+      }
+      Access::RowidRange(range) => match range.eval(ctx, row)? {
+        Some([low, high]) => {
+          let (start, end) = match reverse {
+            true => (high, low),
+            false => (low, high),
+          };
+          self.end = end.map(|(value, inclusive)| (vec![value], inclusive));
+          let mut btree = ctx.btree();
+          match (start, reverse) {
+            (None, false) => self.cursor.first(&mut btree)?,
+            (None, true) => self.cursor.last(&mut btree)?,
+            (Some((value, inclusive)), false) => {
+              match first_rowid(&value, inclusive) {
+                Some(rowid) => {
+                  self.cursor.seek_first_rowid(&mut btree, rowid)?
+                }
+                None => false,
+              }
+            }
+            (Some((value, inclusive)), true) => {
+              match last_rowid(&value, inclusive) {
+                Some(rowid) => {
+                  self.cursor.seek_last_rowid(&mut btree, rowid)?
+                }
+                None => false,
+              }
+            }
+          }
+        }
+        None => false,
+      },
+      Access::Index {
+        key_info,
+        probes,
+        range,
+        ..
+      } => {
+        // Every combination of the values of the probes is looked up, in
+        // the order of the scan.
+        let mut keys = vec![vec![]];
+        for probe in probes.iter() {
+          let values = probe.eval(ctx, row)?;
+          keys = keys
+            .iter()
+            .flat_map(|key| {
+              values.iter().map(|value| {
+                let mut key = key.clone();
+                key.push(value.clone());
+                key
+              })
+            })
+            .collect();
+        }
+        keys.sort_by(|left, right| match reverse {
+          true => key_info.compare(left, right),
+          false => key_info.compare(right, left),
+        });
+        keys.dedup_by(|left, right| key_info.compare(left, right).is_eq());
+        match range.eval(ctx, row)? {
+          Some(bounds) => {
+            self.keys = keys;
+            self.bounds = bounds;
+            self.seek_next_key(ctx)?
+          }
+          None => false,
+        }
+      }
+      Access::Automatic(index) => {
+        index.seek(ctx, row, &mut self.cursor, &self.real_columns)?
+      }
+    };
+    Ok(())
+  }
+
+  /// Looks the next of `keys` up, and the ones after it until a row is
+  /// found. Whether one is.
+  fn seek_next_key(&mut self, ctx: &mut Context<'_>) -> SqliteResult<bool> {
+    while let Some(key) = self.keys.pop() {
+      self.key = key;
+      if self.seek_key(ctx)? {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  /// Looks `key` up. Whether a row has it.
+  fn seek_key(&mut self, ctx: &mut Context<'_>) -> SqliteResult<bool> {
+    let mut btree = ctx.btree();
+    let Access::Index {
+      cursor,
+      key_info,
+      range,
+      ..
+    } = &mut self.access
+    else {
+      return match self.key.first().and_then(as_rowid) {
+        Some(rowid) => self.cursor.seek_rowid(&mut btree, rowid),
+        None => Ok(false),
+      };
+    };
+    // NULLs sort first and are within no range.
+    let [low, high] = self.bounds.clone();
+    let low = match low {
+      None if !range.is_empty() => Some((Value::Null, false)),
+      low => low,
+    };
+    let descending = key_info
+      .columns()
+      .get(self.key.len())
+      .is_some_and(|column| column.descending);
+    let (start, end) = match descending != self.reverse {
+      true => (high, low),
+      false => (low, high),
+    };
+    let with_key = |(value, inclusive): BoundValue| {
+      let mut key = self.key.clone();
+      key.push(value);
+      (key, inclusive)
+    };
+    self.end = end.map(with_key);
+    let start = start.map(with_key);
+    let seek = start.as_ref().map_or(&self.key, |(key, _)| key);
+    let mut found = match self.reverse {
+      true => cursor.seek_last_key(&mut btree, seek, key_info)?,
+      false => cursor.seek_first_key(&mut btree, seek, key_info)?,
+    };
+    if let Some((start, false)) = &start {
+      while found
+        && key_info.compare(&cursor.record(&mut btree)?, start).is_eq()
+      {
+        found = match self.reverse {
+          true => cursor.prev(&mut btree)?,
+          false => cursor.next(&mut btree)?,
+        };
+      }
+    }
+    if !found {
+      return Ok(false);
+    }
+    let record = cursor.record(&mut btree)?;
+    Ok(is_key_within(
+      key_info,
+      &record,
+      &self.key,
+      self.end.as_ref(),
+      self.reverse,
+    ))
+  }
+
+  /// The next row found, once rewound.
+  pub(crate) fn next_row(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.pending {
+      return Ok(None);
+    }
+    let mut btree = ctx.btree();
+    let (mut row, rowid) = match &mut self.access {
+      Access::Index {
+        cursor,
+        columns,
+        key_info,
+        target,
+        covering,
+        ..
+      } => {
+        let record = cursor.record(&mut btree)?;
+        let end = self.end.as_ref();
+        if !is_key_within(key_info, &record, &self.key, end, self.reverse) {
+          // The entries with the next key follow, if any.
+          self.pending = self.seek_next_key(ctx)?;
+          return self.next_row(ctx);
+        }
+        self.pending = match self.reverse {
+          true => cursor.prev(&mut btree)?,
+          false => cursor.next(&mut btree)?,
+        };
+        let found = match target {
+          _ if *covering => None,
+          IndexTarget::Table => None,
+          IndexTarget::Rowid => Some(match record.last().and_then(as_rowid) {
+            Some(rowid) => self.cursor.seek_rowid(&mut btree, rowid)?,
+            None => false,
+          }),
+          IndexTarget::PrimaryKey(positions) => {
+            let key = positions
+              .iter()
+              .map(|&idx| record.get(idx).cloned().unwrap_or(Value::Null))
+              .collect::<Vec<_>>();
+            Some(self.cursor.seek_primary_key(&mut btree, &key)?)
+          }
+        };
+        match found {
+          None if *covering => {
+            covered_row(self.cursor.definition(), columns, target, &record)
+          }
+          None => {
+            (self.cursor.definition().row_from_record(record, None), None)
+          }
+          Some(true) => {
+            (self.cursor.row(&mut btree)?, self.cursor.rowid(&btree)?)
+          }
+          Some(false) => {
+            return Err(SqliteError::Corrupt(format!(
+              "Index entry of table [{}] without a row",
+              self.cursor.definition().name()
+            )))
+          }
+        }
+      }
+      Access::Automatic(index) => {
+        let row = index.next_row().cloned();
+        index.position += 1;
+        self.pending = index.next_row().is_some();
+        return Ok(row);
+      }
+      Access::Scan | Access::Rowid(_) | Access::RowidRange(_) => {
+        let row = self.cursor.row(&mut btree)?;
+        let rowid = self.cursor.rowid(&btree)?;
+        if let (Some(end), Some(rowid)) = (&self.end, rowid) {
+          let key = [Value::Integer(rowid)];
+          if is_past(&KeyInfo::default(), &key, end, self.reverse) {
+            self.pending = false;
+            return Ok(None);
+          }
+        }
+        self.pending = match self.access {
+          Access::Rowid(_) => self.seek_next_key(ctx)?,
+          _ if self.reverse => self.cursor.prev(&mut btree)?,
+          _ => self.cursor.next(&mut btree)?,
+        };
+        (row, rowid)
+      }
+    };
+    convert_real(&mut row, &self.real_columns);
+    row.push(rowid.map_or(Value::Null, Value::Integer));
+    Ok(Some(row))
+  }
+
+  /// The line `EXPLAIN QUERY PLAN` shows for the scan.
+  pub(crate) fn detail(&self) -> String {
+    let definition = self.cursor.definition();
+    let column = |column: usize| definition.columns()[column].name();
+    let name = &self.name;
+    match &self.access {
+      Access::Scan => format!("SCAN {name}"),
+      Access::Rowid(_) => {
+        format!("SEARCH {name} USING INTEGER PRIMARY KEY (rowid=?)")
+      }
+      Access::RowidRange(range) => format!(
+        "SEARCH {name} USING INTEGER PRIMARY KEY ({})",
+        range.terms("rowid").join(" AND ")
+      ),
+      Access::Index {
+        name: index,
+        columns,
+        probes,
+        range,
+        target,
+        covering,
+        ..
+      } => {
+        let mut terms = columns
+          .iter()
+          .take(probes.len())
+          .map(|&idx| format!("{}=?", column(idx)))
+          .collect::<Vec<_>>();
+        if let Some(&idx) = columns.get(probes.len()) {
+          terms.extend(range.terms(column(idx)));
+        }
+        let using = match (target, covering) {
+          (IndexTarget::Table, _) => "PRIMARY KEY".to_owned(),
+          (_, true) => format!("COVERING INDEX {index}"),
+          (_, false) => format!("INDEX {index}"),
+        };
+        match terms.is_empty() {
+          true => format!("SCAN {name} USING {using}"),
+          false => {
+            format!("SEARCH {name} USING {using} ({})", terms.join(" AND "))
+          }
+        }
+      }
+      Access::Automatic(index) => {
+        let terms = index
+          .columns
+          .iter()
+          .map(|&idx| format!("{}=?", column(idx)))
+          .collect::<Vec<_>>();
+        format!(
+          "SEARCH {name} USING AUTOMATIC COVERING INDEX ({})",
+          terms.join(" AND ")
+        )
+      }
+    }
+  }
+}
+
+/// The cursors a table scan reads in the virtual machine.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Cursors {
+  /// On the table, unless an index covers the scan.
+  pub(crate) table: Option<i32>,
+  /// On the index rows are looked up through, if any.
+  pub(crate) index: Option<i32>,
+}
+
+impl Cursors {
+  pub(crate) fn iter(&self) -> impl Iterator<Item = i32> {
+    self.table.into_iter().chain(self.index)
+  }
+}
+
+impl TableScan {
+  /// Opens the cursors the scan reads: on the table, unless an index covers
+  /// the scan, then on the index it looks rows up through, if any.
+  pub(crate) fn open(&self, builder: &mut Builder) -> Codegen<Cursors> {
+    let definition = self.cursor.definition();
+    match &self.access {
+      Access::Scan | Access::Rowid(_) | Access::RowidRange(_) => {
+        let table = builder.open_table(definition, self.cursor.root());
+        Ok(Cursors {
+          table: Some(table),
+          index: None,
+        })
+      }
+      // The rows of `WITHOUT ROWID` tables are the entries looked up.
+      Access::Index {
+        target: IndexTarget::Table,
+        ..
+      } => {
+        let table = builder.open_table(definition, self.cursor.root());
+        Ok(Cursors {
+          table: Some(table),
+          index: Some(table),
+        })
+      }
+      Access::Index {
+        cursor,
+        name,
+        key_info,
+        target,
+        covering,
+        ..
+      } => {
+        let table = match covering {
+          true => None,
+          false => Some(builder.open_table(definition, self.cursor.root())),
+        };
+        let fields = match target {
+          IndexTarget::Rowid => key_info.columns().len() + 1,
+          _ => key_info.columns().len(),
+        };
+        let index = builder.open_index(cursor.root(), name, key_info, fields);
+        Ok(Cursors {
+          table,
+          index: Some(index),
+        })
+      }
+      Access::Automatic(_) => Err(Unsupported("automatic indexes")),
+    }
+  }
+
+  /// Where the values of the rows found with the `cursors` opened are read.
+  fn row_sources(&self, cursors: Cursors) -> Codegen<Vec<Source>> {
+    let definition = self.cursor.definition();
+    let rowid = match (&self.access, cursors.table, cursors.index) {
+      _ if definition.is_without_rowid() => Source::Null,
+      (_, Some(table), _) => Source::Rowid(table),
+      (Access::Index { key_info, .. }, None, Some(index)) => Source::Column {
+        cursor: index,
+        column: key_info.columns().len(),
+        real: false,
+      },
+      _ => return Err(Unsupported("this query")),
+    };
+    let covered = match &self.access {
+      Access::Index { columns, .. } => columns.as_slice(),
+      _ => &[],
+    };
+    // Entries of indexes on `WITHOUT ROWID` tables end with the primary key.
+    let key_positions = match &self.access {
+      Access::Index {
+        target: IndexTarget::PrimaryKey(positions),
+        ..
+      } => definition
+        .primary_key()
+        .iter()
+        .zip(positions)
+        .map(|(key, &position)| (key.column(), position))
+        .collect(),
+      _ => vec![],
+    };
+    let rowid_alias = definition.rowid_alias();
+    let mut row = (0..definition.columns().len())
+      .map(|column| {
+        let real = self.real_columns.contains(&column);
+        match (cursors.table, cursors.index) {
+          _ if Some(column) == rowid_alias => rowid.clone(),
+          (Some(table), _) => Source::Column {
+            cursor: table,
+            column,
+            real,
+          },
+          (None, Some(index)) => {
+            let position =
+              covered.iter().position(|&idx| idx == column).or_else(|| {
+                let key = key_positions.iter().find(|(key, _)| *key == column);
+                key.map(|(_, position)| *position)
+              });
+            match position {
+              Some(position) => Source::Column {
+                cursor: index,
+                column: position,
+                real,
+              },
+              None => Source::Null,
+            }
+          }
+          (None, None) => Source::Null,
+        }
+      })
+      .collect::<Vec<_>>();
+    row.push(rowid);
+    Ok(row)
+  }
+
+  /// Generates the loop over the rows found with the `cursors` opened, the
+  /// looked up values being computed from the row at `outer`.
+  pub(crate) fn compile_loop(
+    &self,
+    builder: &mut Builder,
+    cursors: Cursors,
+    outer: &[Source],
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let row = self.row_sources(cursors)?;
+    let end = builder.label();
+    let reverse = self.reverse;
+    let (rewind, next_opcode) = match reverse {
+      true => (Opcode::Last, Opcode::Prev),
+      false => (Opcode::Rewind, Opcode::Next),
+    };
+    match (&self.access, cursors.table, cursors.index) {
+      (Access::Scan, Some(table), _) => {
+        builder.jump(rewind, table, end, 0);
+        let top = builder.here();
+        let next = builder.label();
+        consume(builder, &row, next)?;
+        builder.resolve(next);
+        builder.jump(next_opcode, table, top, 0).p5 = 1;
+      }
+      (Access::Rowid(probe), Some(table), _) => {
+        let rowid = builder.register();
+        compile_lists(
+          builder,
+          &[probe],
+          &scan_order(&[KeyColumn::default()], reverse),
+          outer,
+          rowid,
+          end,
+          |builder, end| {
+            compile_probes(builder, &[probe], outer, rowid, end)?;
+            builder.jump(Opcode::SeekRowid, table, end, rowid);
+            consume(builder, &row, end)
+          },
+        )?;
+      }
+      (Access::RowidRange(range), Some(table), _) => {
+        let bounds = builder.registers(2);
+        let (start, stop) = match reverse {
+          true => (&range.high, &range.low),
+          false => (&range.low, &range.high),
+        };
+        match start {
+          Some(start) => {
+            compile_probes(builder, &[&start.probe], outer, bounds, end)?;
+            let opcode = match (reverse, start.inclusive) {
+              (false, true) => Opcode::SeekGE,
+              (false, false) => Opcode::SeekGT,
+              (true, true) => Opcode::SeekLE,
+              (true, false) => Opcode::SeekLT,
+            };
+            builder.jump(opcode, table, end, bounds);
+          }
+          None => {
+            builder.jump(rewind, table, end, 0);
+          }
+        }
+        if let Some(stop) = stop {
+          compile_probes(builder, &[&stop.probe], outer, bounds + 1, end)?;
+        }
+        let top = builder.here();
+        if let Some(stop) = stop {
+          let rowid = builder.register();
+          builder.add(Opcode::Rowid, table, rowid, 0);
+          let opcode = match (reverse, stop.inclusive) {
+            (false, true) => Opcode::Gt,
+            (false, false) => Opcode::Ge,
+            (true, true) => Opcode::Lt,
+            (true, false) => Opcode::Le,
+          };
+          builder.jump(opcode, bounds + 1, end, rowid).p5 =
+            u16::from(affinity_code(Some(Affinity::Numeric)));
+        }
+        let next = builder.label();
+        consume(builder, &row, next)?;
+        builder.resolve(next);
+        builder.jump(next_opcode, table, top, 0);
+      }
+      (
+        Access::Index {
+          key_info,
+          probes,
+          range,
+          target,
+          ..
+        },
+        table,
+        Some(index),
+      ) => {
+        let count = probes.len();
+        let first = builder.registers(count + 1);
+        let probes = probes.iter().collect::<Vec<_>>();
+        let keys = key_info.columns().get(..count).unwrap_or_default();
+        compile_lists(
+          builder,
+          &probes,
+          &scan_order(keys, reverse),
+          outer,
+          first,
+          end,
+          |builder, end| {
+            compile_probes(builder, &probes, outer, first, end)?;
+            let descending = key_info
+              .columns()
+              .get(count)
+              .is_some_and(|column| column.descending);
+            // NULLs sort first and are within no range.
+            let null_low = !range.is_empty() && range.low.is_none();
+            let (start, stop) = match descending != reverse {
+              true => (&range.high, &range.low),
+              false => (&range.low, &range.high),
+            };
+            let (start_len, start_inclusive) = compile_bound(
+              builder,
+              start,
+              null_low && descending == reverse,
+              outer,
+              first + count as i32,
+              end,
+            )?;
+            let seek = match (reverse, start_inclusive) {
+              (false, true) => Opcode::SeekGE,
+              (false, false) => Opcode::SeekGT,
+              (true, true) => Opcode::SeekLE,
+              (true, false) => Opcode::SeekLT,
+            };
+            match count + start_len {
+              0 => builder.jump(rewind, index, end, 0),
+              len => {
+                let seek = builder.jump(seek, index, end, first);
+                seek.p4 = P4::Int64(len as i64);
+                seek
+              }
+            };
+            let (stop_len, stop_inclusive) = compile_bound(
+              builder,
+              stop,
+              null_low && descending != reverse,
+              outer,
+              first + count as i32,
+              end,
+            )?;
+            let top = builder.here();
+            if count + stop_len > 0 {
+              let opcode = match (reverse, stop_inclusive) {
+                (false, true) => Opcode::IdxGT,
+                (false, false) => Opcode::IdxGE,
+                (true, true) => Opcode::IdxLT,
+                (true, false) => Opcode::IdxLE,
+              };
+              builder.jump(opcode, index, end, first).p4 =
+                P4::Int64((count + stop_len) as i64);
+            }
+            let next = builder.label();
+            match (table, target) {
+              (Some(table), IndexTarget::Rowid) => {
+                builder.add(Opcode::DeferredSeek, index, 0, table);
+              }
+              (Some(table), IndexTarget::PrimaryKey(positions)) => {
+                let key = builder.registers(positions.len());
+                for (register, &position) in (key..).zip(positions) {
+                  builder.add(Opcode::Column, index, position as i32, register);
+                }
+                builder.jump(Opcode::NotFound, table, next, key).p4 =
+                  P4::Int64(positions.len() as i64);
+              }
+              _ => {}
+            }
+            consume(builder, &row, next)?;
+            builder.resolve(next);
+            builder.jump(next_opcode, index, top, 0);
+            Ok(())
+          },
+        )?;
+      }
+      _ => return Err(Unsupported("this query")),
+    }
+    builder.resolve(end);
+    Ok(())
+  }
+}
+
+/// Generates the loops over the values of the `IN` lists among `probes`,
+/// each value loaded into the register of its probe, from `first`, and
+/// the code `body` generates in the innermost one, given the label to jump
+/// to for the next values. The values of each list are distinct, and read
+/// in the order of the key column of its probe, among `keys`.
+fn compile_lists(
+  builder: &mut Builder,
+  probes: &[&Probe],
+  keys: &[KeyColumn],
+  outer: &[Source],
+  first: i32,
+  end: Label,
+  body: impl FnOnce(&mut Builder, Label) -> Codegen,
+) -> Codegen {
+  let mut loops = vec![];
+  let mut done = end;
+  for (idx, probe) in probes.iter().enumerate() {
+    if probe.exprs.len() < 2 {
+      continue;
+    }
+    let key = keys.get(idx).cloned();
+    let list = builder.open_list(&KeyInfo::new(key.into_iter().collect()));
+    let value = builder.register();
+    for expr in probe.exprs.iter() {
+      builder.expr(expr, outer, value)?;
+      let skip = builder.label();
+      builder.jump(Opcode::IsNull, value, skip, 0);
+      match probe.affinity {
+        Some(Affinity::Blob) | None => {}
+        affinity => {
+          builder.add(Opcode::Affinity, value, 1, 0).p4 =
+            P4::Text(char::from(affinity_code(affinity)).into());
+        }
+      }
+      builder.add(Opcode::IdxInsert, list, value, 1);
+      builder.resolve(skip);
+    }
+    builder.jump(Opcode::Rewind, list, done, 0);
+    let top = builder.here();
+    builder.add(Opcode::Column, list, 0, first + idx as i32);
+    let next = builder.label();
+    loops.push((list, top, next));
+    done = next;
+  }
+  body(builder, done)?;
+  for (list, top, next) in loops.into_iter().rev() {
+    builder.resolve(next);
+    builder.jump(Opcode::Next, list, top, 0);
+  }
+  Ok(())
+}
+
+/// The order in which a scan reads the values of the key columns `keys`:
+/// theirs, or the reverse when it reads backwards.
+fn scan_order(keys: &[KeyColumn], reverse: bool) -> Vec<KeyColumn> {
+  let mut keys = keys.to_vec();
+  for key in keys.iter_mut() {
+    key.descending ^= reverse;
+  }
+  keys
+}
+
+/// Computes the value of a bound of an index range into `register`, or NULL
+/// into it for the implicit bound leaving NULLs out when `null` is set. The
+/// number of values the bound adds to the key, and whether it is inclusive.
+fn compile_bound(
+  builder: &mut Builder,
+  bound: &Option<RangeBound>,
+  null: bool,
+  outer: &[Source],
+  register: i32,
+  end: Label,
+) -> Codegen<(usize, bool)> {
+  match bound {
+    Some(bound) => {
+      compile_probes(builder, &[&bound.probe], outer, register, end)?;
+      Ok((1, bound.inclusive))
+    }
+    None if null => {
+      builder.add(Opcode::Null, 0, register, 0);
+      Ok((1, false))
+    }
+    None => Ok((0, true)),
+  }
+}
+
+/// Computes the looked up values into the registers from `first`, with the
+/// affinity of their comparison, jumping to `end` when any is NULL. Those
+/// of `IN` lists are loaded by the loops of [`compile_lists`].
+fn compile_probes(
+  builder: &mut Builder,
+  probes: &[&Probe],
+  outer: &[Source],
+  first: i32,
+  end: Label,
+) -> Codegen {
+  let mut affinities = String::new();
+  for (register, probe) in (first..).zip(probes) {
+    let code = match (probe.exprs.as_slice(), probe.affinity) {
+      ([expr], affinity) => {
+        builder.expr(expr, outer, register)?;
+        builder.jump(Opcode::IsNull, register, end, 0);
+        match affinity {
+          Some(Affinity::Blob) | None => None,
+          affinity => Some(affinity_code(affinity)),
+        }
+      }
+      _ => None,
+    };
+    affinities.push(char::from(code.unwrap_or(b'A')));
+  }
+  if affinities.bytes().any(|code| code != b'A') {
+    builder
+      .add(Opcode::Affinity, first, probes.len() as i32, 0)
+      .p4 = P4::Text(affinities);
+  }
+  Ok(())
+}
+
+impl Operator for TableScan {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.started {
+      self.rewind(ctx, &[])?;
+    }
+    self.next_row(ctx)
+  }
+
+  fn reset(&mut self) {
+    self.started = false;
+    self.pending = false;
+    if let Access::Automatic(index) = &mut self.access {
+      index.entries = None;
+    }
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    plan.add(self.detail());
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let cursors = self.open(builder)?;
+    self.compile_loop(builder, cursors, &[], consume)
+  }
+}
+
+/// The rows of a table of the `FROM` clause, read again for each row of the
+/// tables on its left.
+#[derive(Debug)]
+pub(crate) enum Scan {
+  Table(Box<TableScan>),
+  Subquery(SubqueryScan),
+  /// The rows of `json_each()` or `json_tree()`.
+  Json(Box<JsonEach>),
+  /// The rows of a `pragma_` table-valued function.
+  Pragma(Box<PragmaTable>),
+}
+
+impl Scan {
+  /// Starts over, looking rows up with the values computed from `row`.
+  pub(crate) fn rewind(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    match self {
+      Self::Table(scan) => scan.rewind(ctx, row),
+      Self::Subquery(scan) => scan.rewind(ctx),
+      Self::Json(each) => each.rewind(ctx, row),
+      Self::Pragma(pragma) => pragma.rewind(ctx, row),
+    }
+  }
+
+  /// The next row found, once rewound.
+  pub(crate) fn next_row(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    match self {
+      Self::Table(scan) => scan.next_row(ctx),
+      Self::Subquery(scan) => scan.next_row(ctx),
+      Self::Json(each) => Ok(each.next_row()),
+      Self::Pragma(pragma) => Ok(pragma.next_row()),
+    }
+  }
+}
+
+impl Operator for Scan {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    match self {
+      Self::Table(scan) => scan.next(ctx),
+      Self::Subquery(scan) => scan.next(ctx),
+      Self::Json(each) => each.next(ctx),
+      Self::Pragma(pragma) => pragma.next(ctx),
+    }
+  }
+
+  fn reset(&mut self) {
+    match self {
+      Self::Table(scan) => scan.reset(),
+      Self::Subquery(scan) => scan.reset(),
+      Self::Json(each) => each.reset(),
+      Self::Pragma(pragma) => pragma.reset(),
+    }
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    match self {
+      Self::Table(scan) => scan.describe(plan),
+      Self::Subquery(scan) => scan.describe(plan),
+      Self::Json(each) => each.describe(plan),
+      Self::Pragma(pragma) => pragma.describe(plan),
+    }
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    match self {
+      Self::Table(scan) => scan.compile(builder, consume),
+      Self::Subquery(_) => Err(Unsupported("subqueries in the FROM clause")),
+      Self::Json(_) | Self::Pragma(_) => {
+        Err(Unsupported("table-valued functions"))
+      }
+    }
+  }
+}
+
+/// The rowid designated by a value, when it is an integer.
+pub(crate) fn as_rowid(value: &Value) -> Option<i64> {
+  match value {
+    Value::Integer(int) => Some(*int),
+    Value::Real(real) => {
+      let int = *real as i64;
+      (int as f64 == *real).then_some(int)
+    }
+    _ => None,
+  }
+}
+
+/// Rows computed from expressions, like those of `VALUES` or of a `SELECT`
+/// without a `FROM` clause.
+#[derive(Debug)]
+pub(crate) struct Values {
+  rows: Vec<Vec<Expr>>,
+  position: usize,
+}
+
+impl Values {
+  pub(crate) fn new(rows: Vec<Vec<Expr>>) -> Self {
+    Self { rows, position: 0 }
+  }
+}
+
+impl Operator for Values {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let Some(exprs) = self.rows.get(self.position) else {
+      return Ok(None);
+    };
+    self.position += 1;
+    let row = exprs
+      .iter()
+      .map(|expr| expr.eval(ctx, &[]))
+      .collect::<SqliteResult<_>>()?;
+    Ok(Some(row))
+  }
+
+  fn reset(&mut self) {
+    self.position = 0;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    match self.rows.len() {
+      0 => {}
+      1 => plan.add("SCAN CONSTANT ROW"),
+      rows => plan.add(format!("SCAN {rows}-ROW VALUES CLAUSE")),
+    }
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    for exprs in &self.rows {
+      let first = builder.registers(exprs.len());
+      for (register, expr) in (first..).zip(exprs) {
+        builder.expr(expr, &[], register)?;
+      }
+      let row = (first..)
+        .take(exprs.len())
+        .map(Source::Register)
+        .collect::<Vec<_>>();
+      let next = builder.label();
+      consume(builder, &row, next)?;
+      builder.resolve(next);
+    }
+    Ok(())
+  }
+}
+
+/// Keeps the rows for which a condition is true.
+#[derive(Debug)]
+pub(crate) struct Filter {
+  input: Box<dyn Operator>,
+  condition: Expr,
+}
+
+impl Filter {
+  pub(crate) fn new(input: Box<dyn Operator>, condition: Expr) -> Self {
+    Self { input, condition }
+  }
+}
+
+impl Operator for Filter {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    while let Some(row) = self.input.next(ctx)? {
+      if is_true(&self.condition.eval(ctx, &row)?) == Some(true) {
+        return Ok(Some(row));
+      }
+    }
+    Ok(None)
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    self.input.compile(builder, &mut |builder, row, next| {
+      builder.if_false(&self.condition, row, next, true)?;
+      consume(builder, row, next)
+    })
+  }
+}
+
+/// Computes the result columns of each row.
+#[derive(Debug)]
+pub(crate) struct Project {
+  input: Box<dyn Operator>,
+  columns: Vec<Expr>,
+}
+
+impl Project {
+  pub(crate) fn new(input: Box<dyn Operator>, columns: Vec<Expr>) -> Self {
+    Self { input, columns }
+  }
+}
+
+impl Operator for Project {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let Some(row) = self.input.next(ctx)? else {
+      return Ok(None);
+    };
+    let values = self
+      .columns
+      .iter()
+      .map(|expr| expr.eval(ctx, &row))
+      .collect::<SqliteResult<_>>()?;
+    Ok(Some(values))
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    self.input.compile(builder, &mut |builder, row, next| {
+      let first = builder.registers(self.columns.len());
+      for (register, expr) in (first..).zip(&self.columns) {
+        builder.expr(expr, row, register)?;
+      }
+      let values = (first..)
+        .take(self.columns.len())
+        .map(Source::Register)
+        .collect::<Vec<_>>();
+      consume(builder, &values, next)
+    })
+  }
+}
+
+/// Sorts the rows, then drops the values past the first `width` ones, which
+/// only served as sort keys.
+#[derive(Debug)]
+pub(crate) struct Sort {
+  input: Box<dyn Operator>,
+  keys: Vec<SortKey>,
+  width: usize,
+  /// The clause the rows are sorted for, `ORDER BY` or `GROUP BY`, if
+  /// any: the rows of a compound query are sorted by the temporary b-tree
+  /// it is described with.
+  clause: Option<&'static str>,
+  /// The `LIMIT` applied to the sorted rows, so that only the rows it keeps
+  /// are held on to.
+  limit: Option<LimitClause>,
+  sorted: Option<SortedRows>,
+}
+
+impl Sort {
+  pub(crate) fn new(
+    input: Box<dyn Operator>,
+    keys: Vec<SortKey>,
+    width: usize,
+    clause: Option<&'static str>,
+    limit: Option<LimitClause>,
+  ) -> Self {
+    Self {
+      input,
+      keys,
+      width,
+      clause,
+      limit,
+      sorted: None,
+    }
+  }
+}
+
+impl Operator for Sort {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let sorted = match &mut self.sorted {
+      Some(sorted) => sorted,
+      None => {
+        let top = match &self.limit {
+          Some(limit) => match limit.eval(ctx)? {
+            (Some(limit), offset) => Some(limit.saturating_add(offset)),
+            (None, _) => None,
+          },
+          None => None,
+        };
+        let mut sorter =
+          Sorter::new(self.keys.clone(), ctx.sort_memory_limit(), top);
+        while let Some(row) = self.input.next(ctx)? {
+          sorter.push(row)?;
+        }
+        self.sorted.insert(sorter.finish()?)
+      }
+    };
+    let Some(mut row) = sorted.next_row()? else {
+      return Ok(None);
+    };
+    row.truncate(self.width);
+    Ok(Some(row))
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+    self.sorted = None;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+    if let Some(clause) = self.clause {
+      plan.add(format!("USE TEMP B-TREE FOR {clause}"));
+    }
+  }
+
+  fn compound(&self) -> Option<&Compound> {
+    match self.clause {
+      Some(_) => None,
+      None => self.input.compound(),
+    }
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let limit = self.limit.as_ref().and(builder.sort_limit);
+    let sorter = builder.open_sorter(&self.keys, limit);
+    self.input.compile(builder, &mut |builder, row, _| {
+      let first = builder.row_registers(row);
+      builder.add(Opcode::SorterInsert, sorter, first, row.len() as i32);
+      Ok(())
+    })?;
+    let end = builder.label();
+    builder.jump(Opcode::SorterSort, sorter, end, 0);
+    let top = builder.here();
+    let row = (0..self.width)
+      .map(|column| Source::Column {
+        cursor: sorter,
+        column,
+        real: false,
+      })
+      .collect::<Vec<_>>();
+    let next = builder.label();
+    consume(builder, &row, next)?;
+    builder.resolve(next);
+    builder.jump(Opcode::SorterNext, sorter, top, 0);
+    builder.resolve(end);
+    Ok(())
+  }
+}
+
+/// Drops the rows whose first `width` values are the same as those of an
+/// earlier row, comparing text with the collating sequence of its column.
+#[derive(Debug)]
+pub(crate) struct Distinct {
+  input: Box<dyn Operator>,
+  collations: Vec<Collation>,
+  seen: KeySet,
+}
+
+impl Distinct {
+  /// `collations` holds the collating sequence of each compared value.
+  pub(crate) fn new(
+    input: Box<dyn Operator>,
+    collations: Vec<Collation>,
+  ) -> Self {
+    Self {
+      input,
+      seen: KeySet::new(&collations),
+      collations,
+    }
+  }
+}
+
+impl Operator for Distinct {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    while let Some(row) = self.input.next(ctx)? {
+      if self.seen.insert(&row, ctx.sort_memory_limit())? {
+        return Ok(Some(row));
+      }
+    }
+    Ok(None)
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+    self.seen.clear();
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+    plan.add("USE TEMP B-TREE FOR DISTINCT");
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let set = builder.open_ephemeral(&self.collations);
+    self.input.compile(builder, &mut |builder, row, next| {
+      let width = self.collations.len().min(row.len());
+      let first = builder.row_registers(&row[..width]);
+      builder.jump(Opcode::Found, set, next, first).p4 =
+        P4::Int64(width as i64);
+      builder.add(Opcode::IdxInsert, set, first, width as i32);
+      consume(builder, row, next)
+    })
+  }
+}
+
+/// The rows of two queries combined by a compound operator. Except with
+/// `UNION ALL`, the rows that are the same as an earlier row are dropped,
+/// comparing text with the collating sequence of its column.
+///
+/// *Reference:* https://www.sqlite.org/lang_select.html#compound_select_statements
+#[derive(Debug)]
+pub(crate) struct Compound {
+  left: Box<dyn Operator>,
+  right: Box<dyn Operator>,
+  operator: CompoundOperator,
+  collations: Vec<Collation>,
+  /// The rows returned so far.
+  seen: KeySet,
+  /// For `INTERSECT` and `EXCEPT`, the rows of the right query once read.
+  right_rows: Option<KeySet>,
+  left_done: bool,
+}
+
+impl Compound {
+  pub(crate) fn new(
+    left: Box<dyn Operator>,
+    right: Box<dyn Operator>,
+    operator: CompoundOperator,
+    collations: Vec<Collation>,
+  ) -> Self {
+    Self {
+      left,
+      right,
+      operator,
+      seen: KeySet::new(&collations),
+      collations,
+      right_rows: None,
+      left_done: false,
+    }
+  }
+
+  /// Describes the selects of the compound query, those of the compound
+  /// queries on its left first, all at the same level.
+  fn describe_parts(&self, plan: &mut QueryPlan<'_>) {
+    match self.left.compound() {
+      Some(left) => left.describe_parts(plan),
+      None => plan.nest("LEFT-MOST SUBQUERY", |plan| self.left.describe(plan)),
+    }
+    let right = match self.operator {
+      CompoundOperator::Union => "UNION USING TEMP B-TREE",
+      CompoundOperator::UnionAll => "UNION ALL",
+      CompoundOperator::Intersect => "INTERSECT USING TEMP B-TREE",
+      CompoundOperator::Except => "EXCEPT USING TEMP B-TREE",
+    };
+    plan.nest(right, |plan| self.right.describe(plan));
+  }
+}
+
+impl Operator for Compound {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let keep_found = match self.operator {
+      CompoundOperator::UnionAll | CompoundOperator::Union => loop {
+        let row = match self.left_done {
+          false => match self.left.next(ctx)? {
+            Some(row) => row,
+            None => {
+              self.left_done = true;
+              continue;
+            }
+          },
+          true => match self.right.next(ctx)? {
+            Some(row) => row,
+            None => return Ok(None),
+          },
+        };
+        if self.operator == CompoundOperator::UnionAll
+          || self.seen.insert(&row, ctx.sort_memory_limit())?
+        {
+          return Ok(Some(row));
+        }
+      },
+      CompoundOperator::Intersect => true,
+      CompoundOperator::Except => false,
+    };
+    let right_rows = match &mut self.right_rows {
+      Some(right_rows) => right_rows,
+      None => {
+        let mut right_rows = KeySet::new(&self.collations);
+        while let Some(row) = self.right.next(ctx)? {
+          right_rows.insert(&row, ctx.sort_memory_limit())?;
+        }
+        self.right_rows.insert(right_rows)
+      }
+    };
+    while let Some(row) = self.left.next(ctx)? {
+      if right_rows.contains(&row)? == keep_found
+        && self.seen.insert(&row, ctx.sort_memory_limit())?
+      {
+        return Ok(Some(row));
+      }
+    }
+    Ok(None)
+  }
+
+  fn reset(&mut self) {
+    self.left.reset();
+    self.right.reset();
+    self.seen.clear();
+    self.right_rows = None;
+    self.left_done = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    plan.nest("COMPOUND QUERY", |plan| self.describe_parts(plan));
+  }
+
+  fn compound(&self) -> Option<&Compound> {
+    Some(self)
+  }
+}
+
+/// `LIMIT limit OFFSET offset`, whose expressions are evaluated once, when
+/// the statement starts.
+///
+/// *Reference:* https://www.sqlite.org/lang_select.html#the_limit_clause
+#[derive(Debug, Clone)]
+pub(crate) struct LimitClause {
+  limit: Expr,
+  offset: Option<Expr>,
+}
+
+impl LimitClause {
+  pub(crate) fn new(limit: Expr, offset: Option<Expr>) -> Self {
+    Self { limit, offset }
+  }
+
+  /// The number of rows to return, `None` when the limit is negative, and
+  /// the number of rows to skip first.
+  fn eval(
+    &self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<(Option<usize>, usize)> {
+    let limit = limit_value(self.limit.eval(ctx, &[])?)?;
+    let offset = match &self.offset {
+      Some(offset) => limit_value(offset.eval(ctx, &[])?)?,
+      None => 0,
+    };
+    Ok((
+      usize::try_from(limit).ok(),
+      usize::try_from(offset).unwrap_or(0),
+    ))
+  }
+}
+
+/// The value of a `LIMIT` or `OFFSET` expression, which must be an integer.
+pub(crate) fn limit_value(value: Value) -> SqliteResult<i64> {
+  match Affinity::Numeric.apply(value) {
+    Value::Integer(int) => Ok(int),
+    _ => Err(SqliteError::Custom("datatype mismatch".into())),
+  }
+}
+
+/// Skips the first rows and stops after the limit.
+#[derive(Debug)]
+pub(crate) struct Limit {
+  input: Box<dyn Operator>,
+  clause: LimitClause,
+  /// The rows still to return, when limited, and to skip, once evaluated.
+  remaining: Option<(Option<usize>, usize)>,
+}
+
+impl Limit {
+  pub(crate) fn new(input: Box<dyn Operator>, clause: LimitClause) -> Self {
+    Self {
+      input,
+      clause,
+      remaining: None,
+    }
+  }
+}
+
+impl Operator for Limit {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let (limit, offset) = match &mut self.remaining {
+      Some(remaining) => remaining,
+      None => self.remaining.insert(self.clause.eval(ctx)?),
+    };
+    if *limit == Some(0) {
+      return Ok(None);
+    }
+    while *offset > 0 {
+      if self.input.next(ctx)?.is_none() {
+        return Ok(None);
+      }
+      *offset -= 1;
+    }
+    let row = self.input.next(ctx)?;
+    if let (Some(limit), Some(_)) = (limit.as_mut(), &row) {
+      *limit -= 1;
+    }
+    Ok(row)
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+    self.remaining = None;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+  }
+
+  fn compile(
+    &self,
+    builder: &mut Builder,
+    consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    let limit = builder.register();
+    builder.expr(&self.clause.limit, &[], limit)?;
+    if !matches!(self.clause.limit, Expr::Literal(Value::Integer(_))) {
+      builder.add(Opcode::MustBeInt, limit, 0, 0);
+    }
+    builder.comment("LIMIT counter");
+    let offset = match &self.clause.offset {
+      Some(expr) => {
+        let offset = builder.register();
+        builder.expr(expr, &[], offset)?;
+        builder.add(Opcode::MustBeInt, offset, 0, 0).comment =
+          Some("OFFSET counter".into());
+        let total = builder.register();
+        builder
+          .add(Opcode::OffsetLimit, limit, total, offset)
+          .comment = Some("LIMIT+OFFSET".into());
+        Some((offset, total))
+      }
+      None => None,
+    };
+    let end = builder.label();
+    builder.jump(Opcode::IfNot, limit, end, 0);
+    let outer_limit = builder.sort_limit.replace(match offset {
+      Some((_, total)) => total,
+      None => limit,
+    });
+    let result = self.input.compile(builder, &mut |builder, row, next| {
+      if let Some((offset, _)) = offset {
+        builder.jump(Opcode::IfPos, offset, next, 1).comment =
+          Some("OFFSET".into());
+      }
+      consume(builder, row, next)?;
+      builder.jump(Opcode::DecrJumpZero, limit, end, 0);
+      Ok(())
+    });
+    builder.sort_limit = outer_limit;
+    result?;
+    builder.resolve(end);
+    Ok(())
+  }
+}
@@ -0,0 +1,187 @@
+//! # LIKE and GLOB
+//!
+//!  The LIKE operator does a pattern matching comparison: a percent symbol
+//! matches any sequence of zero or more characters and an underscore matches
+//! any single character. Upper and lower case ASCII characters match each
+//! other. The GLOB operator uses the Unix file globbing syntax instead, and
+//! is case sensitive.
+//!
+//! *Reference:* https://www.sqlite.org/lang_expr.html#like
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{to_text, Value};
+
+/// Wildcards of a pattern syntax.
+#[derive(Debug, Clone, Copy)]
+struct Wildcards {
+  many: char,
+  one: char,
+  /// Whether `[...]` matches a set of characters.
+  sets: bool,
+  escape: Option<char>,
+  ignore_case: bool,
+}
+
+/// Whether `text` matches `pattern`, as `LIKE` or else `GLOB` does, with
+/// the `ESCAPE` character of `LIKE`. `None` when any value is NULL.
+pub(crate) fn pattern_match(
+  is_glob: bool,
+  text: &Value,
+  pattern: &Value,
+  escape: Option<&Value>,
+) -> SqliteResult<Option<bool>> {
+  let escape = match escape {
+    Some(escape) => match to_text(escape) {
+      None => return Ok(None),
+      Some(escape) => {
+        let mut chars = escape.chars();
+        match (chars.next(), chars.next()) {
+          (Some(escape), None) => Some(escape),
+          _ => {
+            return Err(SqliteError::Custom(
+              "ESCAPE expression must be a single character".into(),
+            ))
+          }
+        }
+      }
+    },
+    None => None,
+  };
+  Ok(match (to_text(text), to_text(pattern)) {
+    (Some(text), Some(pattern)) => Some(match is_glob {
+      true => glob(&pattern, &text),
+      false => like(&pattern, &text, escape),
+    }),
+    _ => None,
+  })
+}
+
+/// `text LIKE pattern ESCAPE escape`.
+pub(crate) fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+  let wildcards = Wildcards {
+    many: '%',
+    one: '_',
+    sets: false,
+    escape,
+    ignore_case: true,
+  };
+  let pattern: Vec<char> = pattern.chars().collect();
+  let text: Vec<char> = text.chars().collect();
+  matches(&pattern, &text, &wildcards)
+}
+
+/// `text GLOB pattern`.
+pub(crate) fn glob(pattern: &str, text: &str) -> bool {
+  let wildcards = Wildcards {
+    many: '*',
+    one: '?',
+    sets: true,
+    escape: None,
+    ignore_case: false,
+  };
+  let pattern: Vec<char> = pattern.chars().collect();
+  let text: Vec<char> = text.chars().collect();
+  matches(&pattern, &text, &wildcards)
+}
+
+fn matches(pattern: &[char], text: &[char], wildcards: &Wildcards) -> bool {
+  let (mut p, mut t) = (0, 0);
+  while let Some(&c) = pattern.get(p) {
+    if Some(c) == wildcards.escape {
+      let Some(&literal) = pattern.get(p + 1) else {
+        return false;
+      };
+      if !text.get(t).is_some_and(|&ch| same(ch, literal, wildcards)) {
+        return false;
+      }
+      p += 2;
+      t += 1;
+    } else if c == wildcards.many {
+      // Consecutive wildcards collapse, each single one eating a character.
+      while let Some(&c) = pattern.get(p) {
+        if c == wildcards.many {
+          p += 1;
+        } else if c == wildcards.one {
+          if t >= text.len() {
+            return false;
+          }
+          p += 1;
+          t += 1;
+        } else {
+          break;
+        }
+      }
+      if p == pattern.len() {
+        return true;
+      }
+      return (t..=text.len())
+        .any(|start| matches(&pattern[p..], &text[start..], wildcards));
+    } else if c == wildcards.one {
+      if t >= text.len() {
+        return false;
+      }
+      p += 1;
+      t += 1;
+    } else if c == '[' && wildcards.sets {
+      let Some(&ch) = text.get(t) else {
+        return false;
+      };
+      match match_set(&pattern[p + 1..], ch) {
+        Some((true, len)) => {
+          p += len + 1;
+          t += 1;
+        }
+        _ => return false,
+      }
+    } else {
+      if !text.get(t).is_some_and(|&ch| same(ch, c, wildcards)) {
+        return false;
+      }
+      p += 1;
+      t += 1;
+    }
+  }
+  t == text.len()
+}
+
+fn same(left: char, right: char, wildcards: &Wildcards) -> bool {
+  match wildcards.ignore_case {
+    true => left.eq_ignore_ascii_case(&right),
+    false => left == right,
+  }
+}
+
+/// Matches `ch` against the set `[...]` that `set` starts right after the
+/// opening bracket of. Returns whether it matched and the length of the set,
+/// closing bracket included, or `None` if the set is not closed.
+fn match_set(set: &[char], ch: char) -> Option<(bool, usize)> {
+  let mut idx = 0;
+  let negated = set.first() == Some(&'^');
+  if negated {
+    idx += 1;
+  }
+  let mut found = false;
+  let mut previous = None;
+  // A closing bracket right at the start is a member of the set.
+  if set.get(idx) == Some(&']') {
+    found |= ch == ']';
+    previous = Some(']');
+    idx += 1;
+  }
+  loop {
+    match (set.get(idx)?, previous) {
+      (']', _) => break,
+      ('-', Some(low)) if !matches!(set.get(idx + 1), Some(']') | None) => {
+        found |= low <= ch && ch <= set[idx + 1];
+        previous = None;
+        idx += 2;
+      }
+      (&member, _) => {
+        found |= member == ch;
+        previous = Some(member);
+        idx += 1;
+      }
+    }
+  }
+  Some((found != negated, idx + 1))
+}
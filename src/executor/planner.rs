@@ -0,0 +1,3810 @@
+//! # Query planning
+//!
+//!  The planner resolves the names used by a statement against the schema of
+//! the database, compiles its expressions, and assembles the operators that
+//! run it. The statements changing data are planned in [`super::dml`], and
+//! views and trigger programs in [`super::trigger`].
+
+use super::aggregate::{Aggregate, AggregateCall, AggregateFunction};
+use super::dml::Write;
+use super::expr::{Comparator, Comparison, Expr};
+use super::function::ScalarFunction;
+use super::join::{Join, JoinType};
+use super::json::{self, JsonEach, JsonFunction};
+use super::operator::{
+  Access, AutomaticIndex, Compound, Distinct, Filter, IndexTarget, Limit,
+  LimitClause, Operator, Probe, Project, Range, RangeBound, Scan, Sort,
+  TableScan, Values,
+};
+use super::pragma::{Pragma, PragmaTable};
+use super::query_plan::QueryPlan;
+use super::sorter::SortKey;
+use super::subquery::{
+  Recursive, Subquery, SubqueryKind, SubqueryRef, SubqueryScan,
+};
+use super::user_function::Functions;
+use super::vdbe::{self, Codegen, Program, Unsupported, Vdbe};
+use super::window::{Bound, Frame, Window, WindowCall, WindowFunction};
+use crate::debug;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  Affinity, BtreeCursor, Collation, KeyColumn, KeyInfo, SqliteRuntime,
+  SqliteStat1, TableCursor, TableDefinition, Value,
+};
+use crate::sql::ast::{
+  self, BinaryOperator, CompoundOperator, ExprKind, FrameBound, FrameUnit,
+  FunctionArguments, FunctionCall, InTarget, JoinConstraint, JoinKind,
+  LikeOperator, Literal, Name, NamedWindow, NullsOrder, OrderingTerm, Over,
+  QualifiedName, ResultColumn, Select, SelectBody, SelectCore, SimpleSelect,
+  SortOrder, StatementKind, TableOrSubquery, UnaryOperator, WindowDefinition,
+  With,
+};
+use crate::sql::Parser;
+use core::{iter, mem, slice};
+use std::borrow::Cow;
+
+/// The operators running a query, the names of its result columns, and the
+/// subqueries its expressions and table scans run.
+#[derive(Debug)]
+pub(crate) struct Plan {
+  pub(crate) root: Box<dyn Operator>,
+  pub(crate) columns: Vec<String>,
+  pub(crate) subqueries: Vec<Subquery>,
+}
+
+/// A query: its rows hold the result columns, followed by the values that
+/// only serve as the sort keys still to apply.
+#[derive(Debug)]
+pub(super) struct Query {
+  pub(super) root: Box<dyn Operator>,
+  pub(super) columns: Vec<String>,
+  /// The expressions of the result columns of its first simple select,
+  /// whose affinity and collating sequence the columns have.
+  pub(super) exprs: Vec<Expr>,
+  pub(super) keys: Vec<SortKey>,
+  /// Whether it aggregates its rows.
+  pub(super) aggregate: bool,
+}
+
+/// The result columns of a query.
+#[derive(Debug, Default)]
+struct ResultColumns {
+  exprs: Vec<Expr>,
+  names: Vec<String>,
+  /// Whether each column has an alias, which `ORDER BY` can refer to.
+  aliased: Vec<bool>,
+  /// Whether each column calls an aggregate function.
+  aggregate: Vec<bool>,
+  /// Whether each column calls a window function.
+  window: Vec<bool>,
+}
+
+impl ResultColumns {
+  fn push(&mut self, expr: Expr, name: String, aliased: bool) {
+    self.exprs.push(expr);
+    self.names.push(name);
+    self.aliased.push(aliased);
+    self.aggregate.push(false);
+    self.window.push(false);
+  }
+}
+
+/// Where aggregate functions may be called.
+#[derive(Debug)]
+enum AggregateScope {
+  /// In the result columns, `HAVING` and `ORDER BY` clauses of a query: the
+  /// calls are gathered, and their results follow the `offset` values of
+  /// the rows being aggregated.
+  Collect {
+    offset: usize,
+    calls: Vec<AggregateCall>,
+  },
+  /// In the `GROUP BY` clause.
+  GroupBy,
+  /// Anywhere else, including the arguments of an aggregate.
+  Forbidden,
+}
+
+/// A result column with an alias, which the other clauses of its query can
+/// refer to as if it was a column.
+#[derive(Debug, Clone)]
+struct Alias {
+  name: String,
+  expr: Expr,
+  aggregate: bool,
+  window: bool,
+}
+
+/// A table of the `FROM` clause, as seen by the expressions of its query.
+#[derive(Debug, Clone)]
+pub(super) struct Source {
+  /// The alias of the table, or else its name.
+  pub(super) name: String,
+  pub(super) columns: Vec<SourceColumn>,
+  /// Position of the first value of the source in the rows of the query.
+  offset: usize,
+  /// Number of values of the source in the rows of the query.
+  pub(super) width: usize,
+  /// Position of the rowid among the values of the source.
+  rowid: Option<usize>,
+  /// Set for the rows of `json_each()` and `json_tree()`, whose `value`
+  /// column holds the JSON text of arrays and objects.
+  json: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct SourceColumn {
+  pub(super) name: String,
+  affinity: Affinity,
+  collation: Collation,
+  /// Set when a `USING` or `NATURAL` join merged the column into a column
+  /// of a table on its left: its name alone, and `*`, refer to that column.
+  merged: bool,
+  /// The columns merged into this one by right and full joins, which stand
+  /// for it, in order, when it is NULL.
+  fallbacks: Vec<Expr>,
+  /// Set for the columns `*` leaves out, like the arguments of a
+  /// table-valued function.
+  hidden: bool,
+}
+
+/// A column reference resolved to its position.
+#[derive(Debug)]
+struct ResolvedColumn {
+  depth: usize,
+  index: usize,
+  name: String,
+  affinity: Affinity,
+  collation: Collation,
+  fallbacks: Vec<Expr>,
+}
+
+/// Where the rows of a table of the `FROM` clause come from.
+#[derive(Debug)]
+pub(super) enum Input {
+  Table(Table),
+  /// The rows of a subquery planned for this table alone, and whether it
+  /// reads the rows of enclosing queries.
+  Subquery {
+    root: Box<dyn Operator>,
+    correlated: bool,
+  },
+  /// The rows of the subquery at this position among those of the
+  /// statement, shared with other tables.
+  Shared(usize),
+  /// The rows of a table-valued function.
+  Function(JsonEach),
+  /// The rows of a pragma read as a table-valued function.
+  Pragma(PragmaTable),
+}
+
+/// A table of the database, before the way its rows are found is chosen.
+#[derive(Debug)]
+pub(super) struct Table {
+  cursor: TableCursor,
+  /// Columns with REAL affinity.
+  real_columns: Vec<usize>,
+  indexes: Vec<TableIndex>,
+  /// The estimated number of rows.
+  rows: f64,
+}
+
+/// An index b-tree the rows of a table can be looked up through.
+#[derive(Debug)]
+struct TableIndex {
+  name: String,
+  root: u32,
+  /// The table columns of the leading columns of the index entries.
+  columns: Vec<usize>,
+  key_info: KeyInfo,
+  target: IndexTarget,
+  /// The estimated number of entries, then of those sharing the values of
+  /// their first column, of their first two columns, and so on.
+  rows: Vec<f64>,
+}
+
+/// A table of the `FROM` clause, and how it joins the tables on its left.
+#[derive(Debug)]
+pub(super) struct FromTable<'s> {
+  pub(super) input: Input,
+  pub(super) join_type: JoinType,
+  pub(super) on: Option<&'s ast::Expr>,
+  /// The conditions of its `USING` clause, or of its `NATURAL` join.
+  pub(super) using: Vec<Expr>,
+}
+
+/// A common table expression of a `WITH` clause, planned where a `FROM`
+/// clause reads it.
+#[derive(Debug)]
+pub(super) struct Cte {
+  name: String,
+  /// The names given to its columns, if any.
+  columns: Vec<String>,
+  select: Select,
+  /// Set by `AS MATERIALIZED`: its rows are computed once for every table
+  /// reading them, unless they depend on enclosing queries.
+  materialized: bool,
+  /// The number of common table expressions its select sees: those of the
+  /// `WITH` clauses it is in, up to the end of its own.
+  visible: usize,
+  /// Once materialized, the subquery holding its rows, and their columns.
+  shared: Option<(usize, Vec<SourceColumn>)>,
+  /// Set while its select is planned.
+  planning: bool,
+  /// Set while its recursive selects are planned.
+  working: Option<WorkingTable>,
+}
+
+/// The table the recursive selects of a common table expression read.
+#[derive(Debug)]
+struct WorkingTable {
+  /// The subquery whose rows the recursive common table expression sets.
+  id: usize,
+  columns: Vec<SourceColumn>,
+  /// The level in the scopes of the planner of the recursive selects.
+  level: usize,
+  /// Whether the recursive select being planned reads it yet.
+  read: bool,
+}
+
+/// The names the rowid goes by, unless a column has that name.
+pub(super) const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+#[derive(Debug)]
+pub(crate) struct Planner<'a> {
+  pub(super) runtime: &'a mut SqliteRuntime,
+  /// The functions registered on the connection.
+  functions: &'a Functions,
+  /// The text of the statement, or of the view being planned.
+  pub(super) sql: Cow<'a, str>,
+  /// The sources of each query being planned, the innermost last.
+  pub(super) scopes: Vec<Vec<Source>>,
+  aggregates: AggregateScope,
+  /// The window function calls of the result columns and `ORDER BY` clause
+  /// of the innermost query, gathered while they are being planned: window
+  /// functions may not be called elsewhere.
+  windows: Option<Vec<WindowCall>>,
+  /// The windows of the `WINDOW` clause of the innermost query.
+  named_windows: Vec<NamedWindow>,
+  /// The aliases of the innermost query being planned.
+  aliases: Vec<Alias>,
+  /// The common table expressions the query being planned sees, the
+  /// innermost last.
+  pub(super) ctes: Vec<Cte>,
+  /// The columns of enclosing queries read by the query being planned, as
+  /// the level of their query in `scopes` and their position in its rows.
+  pub(super) outer_columns: Vec<(usize, usize)>,
+  pub(super) subqueries: Vec<Subquery>,
+  /// The statistics of `sqlite_stat1`, once read.
+  statistics: Option<SqliteStat1>,
+  /// The part of a schema object being planned, whose functions must be
+  /// deterministic.
+  pub(super) deterministic_in: Option<&'static str>,
+  /// Whether the statement enforces foreign keys.
+  pub(super) foreign_keys: bool,
+  /// The views being planned, the innermost last.
+  pub(super) views: Vec<String>,
+  /// Whether the program of a trigger is being planned, where `RAISE()` may
+  /// be called.
+  pub(super) in_trigger: bool,
+}
+
+impl<'a> Planner<'a> {
+  pub(crate) fn new(
+    runtime: &'a mut SqliteRuntime,
+    functions: &'a Functions,
+    sql: &'a str,
+  ) -> Self {
+    Self {
+      runtime,
+      functions,
+      sql: Cow::Borrowed(sql),
+      scopes: vec![],
+      aggregates: AggregateScope::Forbidden,
+      windows: None,
+      named_windows: vec![],
+      aliases: vec![],
+      ctes: vec![],
+      outer_columns: vec![],
+      subqueries: vec![],
+      statistics: None,
+      deterministic_in: None,
+      foreign_keys: false,
+      views: vec![],
+      in_trigger: false,
+    }
+  }
+
+  /// Enforces foreign keys in the data changes planned, as `PRAGMA
+  /// foreign_keys` says.
+  pub(crate) fn with_foreign_keys(mut self, foreign_keys: bool) -> Self {
+    self.foreign_keys = foreign_keys;
+    self
+  }
+
+  /// Plans a `SELECT` statement, run by the virtual machine when it can
+  /// run every operator of it.
+  pub(crate) fn select(&mut self, select: &Select) -> SqliteResult<Plan> {
+    let query = self.query(select, None)?;
+    let root = match vdbe::compile(&*query.root, self.schema_cookie()) {
+      Ok(program) => Box::new(Vdbe::new(program)),
+      Err(Unsupported(feature)) => {
+        debug!("Running on operators, the machine does not run {feature}.");
+        query.root
+      }
+    };
+    Ok(Plan {
+      root,
+      columns: query.columns,
+      subqueries: mem::take(&mut self.subqueries),
+    })
+  }
+
+  /// Plans `EXPLAIN` of a `SELECT` statement, whose rows are the
+  /// instructions of the program it compiles to. It fails, naming what the
+  /// machine does not run, for queries that run on their operators.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_explain.html
+  pub(crate) fn explain(&mut self, select: &Select) -> SqliteResult<Plan> {
+    let query = self.query(select, None)?;
+    explain(vdbe::compile(&*query.root, self.schema_cookie()))
+  }
+
+  /// Plans `EXPLAIN` of the `INSERT`, `UPDATE` or `DELETE` statement
+  /// planned as `write`.
+  pub(crate) fn explain_write(&self, write: &Write) -> SqliteResult<Plan> {
+    explain(match write {
+      Write::Table(change) => {
+        vdbe::compile_change(change, self.schema_cookie())
+      }
+      Write::View(_) => Err(Unsupported("changes of views")),
+    })
+  }
+
+  /// Plans `EXPLAIN QUERY PLAN` of a `SELECT` statement, whose rows
+  /// describe how the query finds its rows.
+  ///
+  /// *Reference:* https://www.sqlite.org/eqp.html
+  pub(crate) fn explain_query_plan(
+    &mut self,
+    select: &Select,
+  ) -> SqliteResult<Plan> {
+    let query = self.query(select, None)?;
+    let rows = QueryPlan::rows(&*query.root, &self.subqueries)
+      .into_iter()
+      .map(|row| row.into_iter().map(Expr::Literal).collect())
+      .collect();
+    Ok(Plan {
+      root: Box::new(Values::new(rows)),
+      columns: QueryPlan::COLUMNS.map(String::from).into(),
+      subqueries: vec![],
+    })
+  }
+
+  pub(crate) fn schema_cookie(&self) -> u32 {
+    **self.runtime.header().schema_cookie()
+  }
+
+  /// Plans the `SELECT` of a `CREATE TABLE ... AS SELECT` statement, and
+  /// returns the affinity of each of its result columns, which the columns
+  /// of the new table take.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_createtable.html#create_table_as_select_statements
+  pub(crate) fn table_select(
+    &mut self,
+    select: &Select,
+  ) -> SqliteResult<(Plan, Vec<Option<Affinity>>)> {
+    let query = self.query(select, None)?;
+    let affinities = (0..query.columns.len())
+      .map(|idx| query.exprs.get(idx).and_then(Expr::affinity))
+      .collect();
+    let plan = Plan {
+      root: query.root,
+      columns: query.columns,
+      subqueries: mem::take(&mut self.subqueries),
+    };
+    Ok((plan, affinities))
+  }
+
+  /// Runs `f` with the common table expressions of a `WITH` clause visible.
+  pub(super) fn in_with<T>(
+    &mut self,
+    with: Option<&With>,
+    f: impl FnOnce(&mut Self) -> SqliteResult<T>,
+  ) -> SqliteResult<T> {
+    let ctes = self.ctes.len();
+    let result = self.with(with).and_then(|()| f(self));
+    self.ctes.truncate(ctes);
+    result
+  }
+
+  /// Plans a whole `SELECT`, with its common table expressions, and its
+  /// `ORDER BY` and `LIMIT` clauses applied. `cte` is the common table
+  /// expression it is the select of, which may read itself.
+  pub(super) fn query(
+    &mut self,
+    select: &Select,
+    cte: Option<usize>,
+  ) -> SqliteResult<Query> {
+    let ctes = self.ctes.len();
+    let query = self.with(select.with.as_ref()).and_then(|()| {
+      let recursive = match cte {
+        Some(cte) => self.recursive(cte, select)?,
+        None => None,
+      };
+      let query = match recursive {
+        Some(query) => query,
+        None => self.body(&select.body, &select.order_by)?,
+      };
+      self.limit(query, select.limit.as_ref())
+    });
+    self.ctes.truncate(ctes);
+    query
+  }
+
+  /// Sorts the rows of `query`, then applies the `LIMIT` clause.
+  pub(super) fn limit(
+    &mut self,
+    query: Query,
+    limit: Option<&ast::Limit>,
+  ) -> SqliteResult<Query> {
+    let limit = match limit {
+      Some(limit) => Some(self.in_scope(vec![], |planner| {
+        let offset = match &limit.offset {
+          Some(offset) => Some(planner.expr(offset)?),
+          None => None,
+        };
+        Ok(LimitClause::new(planner.expr(&limit.limit)?, offset))
+      })?),
+      None => None,
+    };
+    let mut root = query.root;
+    if !query.keys.is_empty() {
+      let width = query.columns.len();
+      root = Box::new(Sort::new(
+        root,
+        query.keys,
+        width,
+        Some("ORDER BY"),
+        limit.clone(),
+      ));
+    }
+    if let Some(limit) = limit {
+      root = Box::new(Limit::new(root, limit));
+    }
+    Ok(Query {
+      root,
+      keys: vec![],
+      ..query
+    })
+  }
+
+  /// Makes the common table expressions of a `WITH` clause visible to its
+  /// query, and to each other.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_with.html
+  fn with(&mut self, with: Option<&With>) -> SqliteResult<()> {
+    let Some(with) = with else {
+      return Ok(());
+    };
+    let first = self.ctes.len();
+    let visible = first + with.tables.len();
+    for table in with.tables.iter() {
+      if self.ctes[first..]
+        .iter()
+        .any(|cte| table.name.is(&cte.name))
+      {
+        return Err(SqliteError::Custom(format!(
+          "duplicate WITH table name: {}",
+          table.name.value
+        )));
+      }
+      self.ctes.push(Cte {
+        name: table.name.value.clone(),
+        columns: table.columns.iter().map(|c| c.value.clone()).collect(),
+        select: table.select.as_ref().clone(),
+        materialized: table.materialized == Some(true),
+        visible,
+        shared: None,
+        planning: false,
+        working: None,
+      });
+    }
+    Ok(())
+  }
+
+  /// Plans the simple selects of a compound select, and combines their
+  /// rows. The `ORDER BY` terms of a compound select can only refer to its
+  /// result columns.
+  fn body(
+    &mut self,
+    body: &SelectBody,
+    order_by: &[OrderingTerm],
+  ) -> SqliteResult<Query> {
+    if body.compounds.is_empty() {
+      return self.core(&body.first, order_by);
+    }
+    let mut query = self.compound(&body.first, &body.compounds)?;
+    let mut columns = result_columns_of(&query);
+    query.keys = self.order_by(order_by, &mut columns, false)?;
+    Ok(query)
+  }
+
+  fn core(
+    &mut self,
+    core: &SelectCore,
+    order_by: &[OrderingTerm],
+  ) -> SqliteResult<Query> {
+    match core {
+      SelectCore::Values(rows) => self.values(rows, order_by),
+      SelectCore::Select(core) => self.simple_select(core, order_by),
+    }
+  }
+
+  /// Combines the rows of `first` with those of the simple selects that
+  /// follow it, left to right.
+  fn compound(
+    &mut self,
+    first: &SelectCore,
+    compounds: &[(CompoundOperator, SelectCore)],
+  ) -> SqliteResult<Query> {
+    let mut query = self.core(first, &[])?;
+    for (operator, core) in compounds {
+      let right = self.core(core, &[])?;
+      query = combine(query, *operator, right)?;
+    }
+    Ok(query)
+  }
+
+  /// Plans the select of a recursive common table expression, the one at
+  /// `idx`: the simple selects of its compound select from the first one
+  /// whose `FROM` clause reads it are its recursive selects, and those
+  /// before them its initial select. `None` when the select is not of that
+  /// form.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_with.html#recursive_common_table_expressions
+  fn recursive(
+    &mut self,
+    idx: usize,
+    select: &Select,
+  ) -> SqliteResult<Option<Query>> {
+    let name = self.ctes[idx].name.clone();
+    let body = &select.body;
+    let split = iter::once(&body.first)
+      .chain(body.compounds.iter().map(|(_, core)| core))
+      .position(|core| reads_table(core, &name));
+    let Some(split @ 1..) = split else {
+      return Ok(None);
+    };
+    let recursive = &body.compounds[split - 1..];
+    let is_union = |operator: &CompoundOperator| {
+      matches!(
+        operator,
+        CompoundOperator::Union | CompoundOperator::UnionAll
+      )
+    };
+    if !recursive.iter().all(|(operator, _)| is_union(operator)) {
+      return Ok(None);
+    }
+    let initial = self.compound(&body.first, &body.compounds[..split - 1])?;
+    let columns = subquery_columns(&initial, &self.ctes[idx].columns, &name)?;
+    let table = self.add_subquery(None, SubqueryKind::Rows, false);
+    self.ctes[idx].working = Some(WorkingTable {
+      id: table,
+      columns,
+      level: self.scopes.len(),
+      read: false,
+    });
+    let step = self.recursive_step(idx, recursive, initial.columns.len());
+    self.ctes[idx].working = None;
+    let step = step?;
+    let mut columns = result_columns_of(&initial);
+    let keys = self.order_by(&select.order_by, &mut columns, false)?;
+    let distinct = recursive
+      .iter()
+      .any(|(operator, _)| *operator == CompoundOperator::Union)
+      .then(|| collations(&initial.exprs));
+    let root = Recursive::new(name, initial.root, step, table, keys, distinct);
+    Ok(Some(Query {
+      root: Box::new(root),
+      ..initial
+    }))
+  }
+
+  /// The rows of the recursive selects of the common table expression at
+  /// `idx`, one after the other. Each has `width` result columns, and reads
+  /// the common table expression once.
+  fn recursive_step(
+    &mut self,
+    idx: usize,
+    recursive: &[(CompoundOperator, SelectCore)],
+    width: usize,
+  ) -> SqliteResult<Box<dyn Operator>> {
+    let mut step: Option<Box<dyn Operator>> = None;
+    for (operator, core) in recursive {
+      if let Some(working) = &mut self.ctes[idx].working {
+        working.read = false;
+      }
+      let query = self.core(core, &[])?;
+      if query.aggregate {
+        return Err(SqliteError::Custom(
+          "recursive aggregate queries not supported".into(),
+        ));
+      }
+      if query.columns.len() != width {
+        return Err(different_widths(*operator));
+      }
+      step = Some(match step {
+        None => query.root,
+        Some(left) => Box::new(Compound::new(
+          left,
+          query.root,
+          CompoundOperator::UnionAll,
+          vec![],
+        )),
+      });
+    }
+    Ok(step.unwrap_or_else(|| Box::new(Values::new(vec![]))))
+  }
+
+  fn values(
+    &mut self,
+    rows: &[Vec<ast::Expr>],
+    order_by: &[OrderingTerm],
+  ) -> SqliteResult<Query> {
+    let rows = self.in_scope(vec![], |planner| {
+      rows
+        .iter()
+        .map(|row| row.iter().map(|expr| planner.expr(expr)).collect())
+        .collect::<SqliteResult<Vec<Vec<Expr>>>>()
+    })?;
+    let mut columns = ResultColumns::default();
+    for (idx, expr) in rows.first().into_iter().flatten().enumerate() {
+      columns.push(expr.clone(), format!("column{}", idx + 1), false);
+    }
+    let keys = self.order_by(order_by, &mut columns, false)?;
+    Ok(Query {
+      root: Box::new(Values::new(rows)),
+      columns: columns.names,
+      exprs: columns.exprs,
+      keys,
+      aggregate: false,
+    })
+  }
+
+  fn simple_select(
+    &mut self,
+    core: &SimpleSelect,
+    order_by: &[OrderingTerm],
+  ) -> SqliteResult<Query> {
+    let (tables, sources) = match &core.from {
+      Some(from) => self.from(from)?,
+      None => (vec![], vec![]),
+    };
+    let width = sources.iter().map(|source| source.width).sum();
+    self.in_scope(sources, |planner| {
+      let calls = AggregateScope::Collect {
+        offset: width,
+        calls: vec![],
+      };
+      let aggregates = mem::replace(&mut planner.aggregates, calls);
+      let windows = planner.windows.take();
+      let named_windows =
+        mem::replace(&mut planner.named_windows, core.windows.clone());
+      let aliases = mem::take(&mut planner.aliases);
+      let query = planner.select_core(core, order_by, tables, width);
+      planner.aggregates = aggregates;
+      planner.windows = windows;
+      planner.named_windows = named_windows;
+      planner.aliases = aliases;
+      query
+    })
+  }
+
+  /// The tables of a `FROM` clause, whose rows are laid out one after the
+  /// other in the rows of the query. The columns a `USING` clause or a
+  /// `NATURAL` join names are merged into the leftmost column of that name.
+  ///
+  /// *Reference:* https://www.sqlite.org/syntax/join-operator.html
+  pub(super) fn from<'s>(
+    &mut self,
+    from: &'s ast::From,
+  ) -> SqliteResult<(Vec<FromTable<'s>>, Vec<Source>)> {
+    let mut tables = vec![];
+    let mut sources: Vec<Source> = vec![];
+    let joins = from.joins.iter().map(|join| (&join.table, Some(join)));
+    for (table, join) in iter::once((&from.first, None)).chain(joins) {
+      let offset = sources.iter().map(|source| source.width).sum();
+      let (input, mut source) =
+        self.table_or_subquery(table, &sources, offset)?;
+      let join_type = match join.map(|join| join.kind) {
+        Some(JoinKind::Left) => JoinType::Left,
+        Some(JoinKind::Right) => JoinType::Right,
+        Some(JoinKind::Full) => JoinType::Full,
+        _ => JoinType::Inner,
+      };
+      let (on, names) = match join.and_then(|join| join.constraint.as_ref()) {
+        Some(JoinConstraint::On(on)) => (Some(on), vec![]),
+        Some(JoinConstraint::Using(names)) => {
+          (None, names.iter().map(|name| name.value.clone()).collect())
+        }
+        None if join.is_some_and(|join| join.natural) => {
+          let names = source.columns.iter().map(|column| &column.name);
+          let common = names.filter(|name| {
+            sources.iter().any(|left| {
+              left.columns.iter().any(|column| {
+                !column.merged
+                  && !column.hidden
+                  && column.name.eq_ignore_ascii_case(name)
+              })
+            })
+          });
+          (None, common.cloned().collect())
+        }
+        None => (None, vec![]),
+      };
+      let mut using = vec![];
+      for name in names {
+        let right = source
+          .columns
+          .iter()
+          .position(|column| column.name.eq_ignore_ascii_case(&name));
+        let left = sources.iter().enumerate().find_map(|(idx, left)| {
+          let column = left.columns.iter().position(|column| {
+            !column.merged && column.name.eq_ignore_ascii_case(&name)
+          })?;
+          Some((idx, column))
+        });
+        let (Some(right), Some((left, column))) = (right, left) else {
+          return Err(SqliteError::Custom(format!(
+            "cannot join using column {name} - column not present in both \
+             tables"
+          )));
+        };
+        let right_expr = source_column(&source, right, false);
+        using.push(compare(
+          Comparison::Eq,
+          Box::new(source_column(&sources[left], column, true)),
+          Box::new(right_expr.clone()),
+        ));
+        source.columns[right].merged = true;
+        if matches!(join_type, JoinType::Right | JoinType::Full) {
+          sources[left].columns[column].fallbacks.push(right_expr);
+        }
+      }
+      tables.push(FromTable {
+        input,
+        join_type,
+        on,
+        using,
+      });
+      sources.push(source);
+    }
+    Ok((tables, sources))
+  }
+
+  /// Plans a simple `SELECT` over the joined rows of `tables`, which hold
+  /// `width` values. The query aggregates its rows when it has a `GROUP BY`
+  /// clause or calls aggregate functions, and its window functions are
+  /// computed over the rows left after aggregation.
+  fn select_core(
+    &mut self,
+    core: &SimpleSelect,
+    order_by: &[OrderingTerm],
+    tables: Vec<FromTable<'_>>,
+    width: usize,
+  ) -> SqliteResult<Query> {
+    self.windows = Some(vec![]);
+    let mut columns = self.result_columns(&core.columns)?;
+    let windows = self.windows.take();
+    self.aliases = (0..columns.names.len())
+      .filter(|&idx| columns.aliased[idx])
+      .map(|idx| Alias {
+        name: columns.names[idx].clone(),
+        expr: columns.exprs[idx].clone(),
+        aggregate: columns.aggregate[idx],
+        window: columns.window[idx],
+      })
+      .collect();
+    let collations = columns
+      .exprs
+      .iter()
+      .map(|expr| expr.collation().map(|(c, _)| c).unwrap_or_default())
+      .collect();
+
+    let calls = mem::replace(&mut self.aggregates, AggregateScope::Forbidden);
+    let on = tables
+      .iter()
+      .map(|table| table.on.map(|on| self.expr(on)).transpose())
+      .collect::<SqliteResult<Vec<_>>>();
+    let condition = core
+      .where_clause
+      .as_ref()
+      .map(|condition| self.expr(condition));
+    self.aggregates = AggregateScope::GroupBy;
+    let groups = self.group_by(&core.group_by, &columns);
+    self.aggregates = calls;
+    let (on, condition, groups) = (on?, condition.transpose()?, groups?);
+    let having = match &core.having {
+      Some(having) => Some(self.expr(having)?),
+      None => None,
+    };
+    self.windows = windows;
+    let mut keys = self.order_by(order_by, &mut columns, true)?;
+    let windows = self.windows.take().unwrap_or_default();
+
+    let calls =
+      match mem::replace(&mut self.aggregates, AggregateScope::Forbidden) {
+        AggregateScope::Collect { calls, .. } => calls,
+        _ => vec![],
+      };
+    // The values of the joined rows the query reads, which an index holding
+    // them all gives without the table being read.
+    let mut used = vec![];
+    let mut visit = |expr: &Expr| expr.visit_columns(&mut |idx| used.push(idx));
+    let using = tables.iter().flat_map(|table| &table.using);
+    let exprs = columns.exprs.iter().chain(on.iter().flatten()).chain(using);
+    exprs
+      .chain(&condition)
+      .chain(&groups)
+      .chain(&having)
+      .for_each(&mut visit);
+    for call in calls.iter() {
+      call.visit_exprs(&mut visit);
+    }
+    for call in windows.iter() {
+      call.visit_exprs(&mut visit);
+    }
+    let aggregate = !groups.is_empty() || !calls.is_empty();
+    let aggregated_width = width + calls.len();
+    // Rows are sorted by their group, which follows their values, in the
+    // direction of the ORDER BY terms that are its first groups. Their rows
+    // are then in order.
+    let mut group_keys = groups
+      .iter()
+      .enumerate()
+      .map(|(idx, expr)| {
+        let collation = expr.collation().map(|(c, _)| c).unwrap_or_default();
+        SortKey::new(width + idx, false, None, collation)
+      })
+      .collect::<Vec<_>>();
+    let is_grouped =
+      |(key, (group, group_key)): (&SortKey, (&Expr, &SortKey))| {
+        columns.exprs[key.column] == *group
+          && key.collation == group_key.collation
+      };
+    if !groups.is_empty()
+      && windows.is_empty()
+      && keys.len() <= groups.len()
+      && keys
+        .iter()
+        .zip(groups.iter().zip(&group_keys))
+        .all(is_grouped)
+    {
+      for (group_key, key) in group_keys.iter_mut().zip(keys.drain(..)) {
+        group_key.descending = key.descending;
+        group_key.nulls_first = key.nulls_first;
+      }
+      columns.exprs.truncate(columns.names.len());
+    }
+    // The order of the rows of the first table that spares a sort.
+    let mut condition = condition;
+    let order = if !windows.is_empty() {
+      Order::default()
+    } else if !groups.is_empty() {
+      column_order(groups.iter().zip(&group_keys))
+    } else if calls.is_empty() {
+      column_order(keys.iter().map(|key| (&columns.exprs[key.column], key)))
+    } else {
+      let order = extreme_order(&calls, &tables);
+      // NULLs, which `min()` skips, come first in an ascending order.
+      if order.keys.first().is_some_and(|key| !key.descending) {
+        let not_null = Expr::IsNull {
+          expr: Box::new(calls[0].arguments[0].clone()),
+          not: true,
+        };
+        condition = Some(match condition {
+          Some(condition) => and(condition, not_null),
+          None => not_null,
+        });
+      }
+      order
+    };
+    let (mut input, ordered) =
+      self.join_in_order(tables, on, condition, Some(&used), &order)?;
+    let ordered = ordered && !order.keys.is_empty();
+    if ordered && order.first {
+      let one = Expr::Literal(Value::Integer(1));
+      input = Box::new(Limit::new(input, LimitClause::new(one, None)));
+    }
+    if ordered && !aggregate {
+      keys.clear();
+      columns.exprs.truncate(columns.names.len());
+    }
+    if !aggregate {
+      if having.is_some() {
+        return Err(SqliteError::Custom(
+          "HAVING clause on a non-aggregate query".into(),
+        ));
+      }
+    } else {
+      let collations = group_keys
+        .iter()
+        .map(|key| key.collation.clone())
+        .collect::<Vec<_>>();
+      if !groups.is_empty() {
+        let exprs = (0..width).map(Expr::Slot).chain(groups).collect();
+        input = Box::new(Project::new(input, exprs));
+        if !ordered {
+          let group_width = width + collations.len();
+          input = Box::new(Sort::new(
+            input,
+            group_keys,
+            group_width,
+            Some("GROUP BY"),
+            None,
+          ));
+        }
+      }
+      input = Box::new(Aggregate::new(input, width, collations, calls));
+      if let Some(having) = having {
+        input = Box::new(Filter::new(input, having));
+      }
+    }
+    if !windows.is_empty() {
+      input = windowed(input, aggregated_width, windows, &mut columns.exprs);
+    }
+
+    let exprs = columns.exprs[..columns.names.len()].to_vec();
+    let mut root: Box<dyn Operator> =
+      Box::new(Project::new(input, columns.exprs));
+    if core.distinct {
+      root = Box::new(Distinct::new(root, collations));
+    }
+    Ok(Query {
+      root,
+      columns: columns.names,
+      exprs,
+      keys,
+      aggregate,
+    })
+  }
+
+  /// The expressions of a `GROUP BY` clause. As in `ORDER BY`, an integer
+  /// `K` stands for the `K`-th result column.
+  fn group_by(
+    &mut self,
+    terms: &[ast::Expr],
+    columns: &ResultColumns,
+  ) -> SqliteResult<Vec<Expr>> {
+    let mut groups = vec![];
+    for (idx, term) in terms.iter().enumerate() {
+      let (expr, collation) = match &term.kind {
+        ExprKind::Collate { expr, collation } => (
+          expr.as_ref(),
+          Some(collation_named(self.runtime, collation)?),
+        ),
+        _ => (term, None),
+      };
+      let Some(number) = integer_constant(expr) else {
+        groups.push(self.expr(term)?);
+        continue;
+      };
+      let column = match usize::try_from(number) {
+        Ok(number @ 1..) if number <= columns.exprs.len() => number - 1,
+        _ => {
+          return Err(SqliteError::Custom(format!(
+            "{} GROUP BY term out of range - should be between 1 and {}",
+            ordinal(idx + 1),
+            columns.exprs.len()
+          )))
+        }
+      };
+      if columns.aggregate[column] {
+        return Err(aggregate_in_group_by());
+      }
+      let expr = columns.exprs[column].clone();
+      groups.push(match collation {
+        Some(collation) => Expr::Collate {
+          expr: Box::new(expr),
+          collation,
+        },
+        None => expr,
+      });
+    }
+    Ok(groups)
+  }
+
+  /// The sort keys of an `ORDER BY` clause. A term that is an integer `K`
+  /// sorts by the `K`-th result column, and one that is the alias of a
+  /// result column by that column. Other terms are expressions over the
+  /// rows of a simple `SELECT`, appended to its result columns, and must be
+  /// the name of a result column elsewhere.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_select.html#the_order_by_clause
+  fn order_by(
+    &mut self,
+    terms: &[OrderingTerm],
+    columns: &mut ResultColumns,
+    expressions: bool,
+  ) -> SqliteResult<Vec<SortKey>> {
+    let width = columns.names.len();
+    let mut keys = vec![];
+    for (idx, term) in terms.iter().enumerate() {
+      let (expr, collation) = match &term.expr.kind {
+        ExprKind::Collate { expr, collation } => (
+          expr.as_ref(),
+          Some(collation_named(self.runtime, collation)?),
+        ),
+        _ => (&term.expr, None),
+      };
+      let named = match &expr.kind {
+        ExprKind::Column {
+          schema: None,
+          table: None,
+          column,
+        } => columns.names.iter().zip(&columns.aliased).position(
+          |(name, aliased)| (*aliased || !expressions) && column.is(name),
+        ),
+        _ => None,
+      };
+      let column = if let Some(number) = integer_constant(expr) {
+        match usize::try_from(number) {
+          Ok(number @ 1..) if number <= width => number - 1,
+          _ => {
+            return Err(SqliteError::Custom(format!(
+              "{} ORDER BY term out of range - should be between 1 and \
+               {width}",
+              ordinal(idx + 1)
+            )))
+          }
+        }
+      } else if let Some(column) = named {
+        column
+      } else if expressions {
+        columns.exprs.push(self.expr(&term.expr)?);
+        columns.exprs.len() - 1
+      } else {
+        return Err(SqliteError::Custom(format!(
+          "{} ORDER BY term does not match any column in the result set",
+          ordinal(idx + 1)
+        )));
+      };
+      let collation = collation.unwrap_or_else(|| {
+        let collation = columns.exprs[column].collation();
+        collation.map(|(c, _)| c).unwrap_or_default()
+      });
+      keys.push(SortKey::new(
+        column,
+        term.order == SortOrder::Desc,
+        term.nulls.map(|nulls| nulls == NullsOrder::First),
+        collation,
+      ));
+    }
+    Ok(keys)
+  }
+
+  /// Joins the rows of `tables`, in order, with the conditions of their `ON`
+  /// clauses and the `WHERE` clause of their query. Without right or full
+  /// joins, each term of these conditions is evaluated as soon as the tables
+  /// it reads are joined, and the terms comparing a column to the tables on
+  /// its left can look its rows up through an index.
+  ///
+  /// *Reference:* https://www.sqlite.org/optoverview.html#joins
+  pub(super) fn join(
+    &mut self,
+    tables: Vec<FromTable<'_>>,
+    on: Vec<Option<Expr>>,
+    condition: Option<Expr>,
+    used: Option<&[usize]>,
+  ) -> SqliteResult<Box<dyn Operator>> {
+    let order = Order::default();
+    let (root, _) = self.join_in_order(tables, on, condition, used, &order)?;
+    Ok(root)
+  }
+
+  /// Joins the rows of `tables` as [`Self::join`] does, reading those of the
+  /// first table in `order` when that costs less than sorting them. Also
+  /// returns whether the joined rows come in that order.
+  fn join_in_order(
+    &mut self,
+    tables: Vec<FromTable<'_>>,
+    on: Vec<Option<Expr>>,
+    condition: Option<Expr>,
+    used: Option<&[usize]>,
+    order: &Order,
+  ) -> SqliteResult<(Box<dyn Operator>, bool)> {
+    if tables.is_empty() {
+      let input = Box::new(Values::new(vec![vec![]]));
+      let root = filtered(input, condition.into_iter().collect());
+      return Ok((root, order.keys.is_empty()));
+    }
+    let sources = self.scope().to_vec();
+    // The position of the table whose values come last among those `expr`
+    // reads.
+    let level = |expr: &Expr| {
+      let mut last = 0;
+      expr.visit_columns(&mut |index| last = last.max(index));
+      sources
+        .iter()
+        .rposition(|source| source.offset <= last)
+        .unwrap_or_default()
+    };
+    let has_right_join = tables
+      .iter()
+      .any(|table| matches!(table.join_type, JoinType::Right | JoinType::Full));
+    let is_inner =
+      |idx: usize| matches!(tables[idx].join_type, JoinType::Inner);
+    // The terms of each join condition, then those evaluated on the rows
+    // each join produces, then those evaluated once every table is joined.
+    let mut conditions: Vec<Vec<Expr>> =
+      tables.iter().map(|_| vec![]).collect();
+    let mut filters: Vec<Vec<Expr>> = tables.iter().map(|_| vec![]).collect();
+    let mut last = vec![];
+    // The terms that are the same wherever they are evaluated, once the
+    // tables they read are joined.
+    let mut free = vec![];
+    for (idx, (table, on)) in tables.iter().zip(on).enumerate() {
+      let mut terms = table.using.clone();
+      if let Some(on) = on {
+        conjuncts(on, &mut terms);
+      }
+      for term in terms {
+        let term_level = level(&term);
+        if !is_inner(idx) {
+          if term_level > idx {
+            return Err(SqliteError::Custom(
+              "ON clause references tables to its right".into(),
+            ));
+          }
+          conditions[idx].push(term);
+        } else if has_right_join && term_level <= idx {
+          conditions[idx].push(term);
+        } else {
+          free.push(term);
+        }
+      }
+    }
+    if let Some(condition) = condition {
+      conjuncts(condition, &mut free);
+    }
+    for term in free {
+      let term_level = level(&term);
+      if has_right_join {
+        last.push(term);
+      } else if is_inner(term_level) {
+        conditions[term_level].push(term);
+      } else {
+        filters[term_level].push(term);
+      }
+    }
+
+    let mut root: Option<Box<dyn Operator>> = None;
+    // The estimated number of rows of the tables joined so far.
+    let mut outer = 1.0;
+    // The joins keep the order of the rows of the first table, unless the
+    // unmatched rows of a right join follow them.
+    let mut ordered = order.keys.is_empty();
+    let order = match has_right_join {
+      true => &Order::default(),
+      false => order,
+    };
+    let parts = tables.into_iter().zip(conditions).zip(filters);
+    for (idx, ((table, condition), filter)) in parts.enumerate() {
+      let source = &sources[idx];
+      let used = used.map(|used| {
+        let values = source.offset..source.offset + source.width;
+        let mut used = used
+          .iter()
+          .filter(|index| values.contains(index))
+          .map(|index| index - source.offset)
+          .collect::<Vec<_>>();
+        used.sort_unstable();
+        used.dedup();
+        used
+      });
+      let used = used.as_deref();
+      let joined: Box<dyn Operator> = match root {
+        None => {
+          let first: Box<dyn Operator> = match table.input {
+            // Read once, its rows need not be kept.
+            Input::Subquery { root, .. } => {
+              outer = DEFAULT_ROWS;
+              root
+            }
+            input => {
+              let table = FromTable { input, ..table };
+              let (scan, rows, in_order) =
+                self.scan(table, source, &condition, used, outer, order);
+              outer = rows;
+              ordered |= in_order;
+              Box::new(scan)
+            }
+          };
+          filtered(first, condition)
+        }
+        Some(left) => {
+          let join_type = table.join_type;
+          let (scan, rows, _) = self.scan(
+            table,
+            source,
+            &condition,
+            used,
+            outer,
+            &Order::default(),
+          );
+          outer *= rows.max(1.0);
+          Box::new(Join::new(
+            left,
+            scan,
+            join_type,
+            condition.into_iter().reduce(and),
+            source.offset,
+            source.width,
+          ))
+        }
+      };
+      root = Some(filtered(joined, filter));
+    }
+    let root = root.unwrap_or_else(|| Box::new(Values::new(vec![])));
+    Ok((filtered(root, last), ordered))
+  }
+
+  /// How the rows of `table` are read, given the terms of the condition it is
+  /// joined with, the positions among its values of those the query reads,
+  /// the estimated number of rows of the tables on its left, and the `order`
+  /// the query wants its rows in. Also returns the estimated number of rows
+  /// read for each of them, and whether they come in that order.
+  fn scan(
+    &mut self,
+    table: FromTable<'_>,
+    source: &Source,
+    terms: &[Expr],
+    used: Option<&[usize]>,
+    outer: f64,
+    order: &Order,
+  ) -> (Scan, f64, bool) {
+    let name = source.name.clone();
+    let join_type = table.join_type;
+    let (scan, rows) = match table.input {
+      Input::Table(table) => {
+        let (access, rows, reverse) = match join_type {
+          JoinType::Right | JoinType::Full => (Access::Scan, table.rows, false),
+          _ => access(&table, source, terms, used, outer, order),
+        };
+        let definition = table.cursor.definition();
+        let given = order.given_by(&access, definition, source);
+        let scan = TableScan::new(
+          table.cursor,
+          name,
+          access,
+          table.real_columns,
+          reverse,
+        );
+        return (Scan::Table(Box::new(scan)), rows, given == Some(reverse));
+      }
+      Input::Subquery { root, correlated } => {
+        let id = self.add_subquery(Some(root), SubqueryKind::Rows, correlated);
+        (Scan::Subquery(SubqueryScan::new(id, name)), DEFAULT_ROWS)
+      }
+      Input::Shared(id) => {
+        (Scan::Subquery(SubqueryScan::new(id, name)), DEFAULT_ROWS)
+      }
+      Input::Function(each) => (Scan::Json(Box::new(each)), DEFAULT_ROWS),
+      Input::Pragma(pragma) => (Scan::Pragma(Box::new(pragma)), DEFAULT_ROWS),
+    };
+    (scan, rows, order.keys.is_empty())
+  }
+
+  /// The rows of a table of the `FROM` clause, and how its expressions see
+  /// them. The arguments of a table-valued function may read the columns
+  /// of the tables on its `left`.
+  fn table_or_subquery(
+    &mut self,
+    table: &TableOrSubquery,
+    left: &[Source],
+    offset: usize,
+  ) -> SqliteResult<(Input, Source)> {
+    match table {
+      TableOrSubquery::Table { name, alias, .. } => {
+        self.named_table(name, alias.as_ref(), offset)
+      }
+      TableOrSubquery::TableFunction {
+        name,
+        arguments,
+        alias,
+      } => self.table_function(name, arguments, alias.as_ref(), left, offset),
+      TableOrSubquery::Subquery { select, alias } => {
+        let current = self.scopes.len();
+        let (query, correlated, _) =
+          self.nested(current, |planner| planner.query(select, None))?;
+        let name = alias.as_ref().map_or("", |alias| &alias.value);
+        let columns = subquery_columns(&query, &[], name)?;
+        let input = Input::Subquery {
+          root: query.root,
+          correlated,
+        };
+        Ok((input, derived_source(name, columns, offset)))
+      }
+      TableOrSubquery::Join(_) => Err(unsupported("Parenthesized JOIN")),
+    }
+  }
+
+  /// The table-valued function `name`: `json_each()`, `json_tree()`, or a
+  /// pragma prefixed with `pragma_`.
+  fn table_function(
+    &mut self,
+    name: &QualifiedName,
+    arguments: &[ast::Expr],
+    alias: Option<&Name>,
+    left: &[Source],
+    offset: usize,
+  ) -> SqliteResult<(Input, Source)> {
+    let function = &name.name.value;
+    if let Some(pragma) = pragma_function(name) {
+      return self.pragma_table(pragma, arguments, alias, left, offset);
+    }
+    let recursive = match function.to_ascii_lowercase().as_str() {
+      "json_each" => false,
+      "json_tree" => true,
+      _ => {
+        return Err(SqliteError::Custom(format!(
+          "no such table-valued function: {function}"
+        )))
+      }
+    };
+    if arguments.len() > 2 {
+      return Err(SqliteError::Custom(format!(
+        "too many arguments on {function}() - max 2"
+      )));
+    }
+    let arguments = self.in_scope(left.to_vec(), |planner| {
+      arguments
+        .iter()
+        .map(|argument| planner.expr(argument))
+        .collect()
+    })?;
+    let columns = json::COLUMNS.into_iter().enumerate();
+    let columns = columns.map(|(idx, column)| SourceColumn {
+      name: column.into(),
+      affinity: Affinity::Blob,
+      collation: Collation::Binary,
+      merged: false,
+      fallbacks: vec![],
+      hidden: idx >= json::VISIBLE,
+    });
+    let name = alias.unwrap_or(&name.name).value.clone();
+    let source = Source {
+      json: true,
+      ..derived_source(&name, columns.collect(), offset)
+    };
+    let each = JsonEach::new(name, recursive, arguments);
+    Ok((Input::Function(each), source))
+  }
+
+  /// The rows of `pragma`, read as a table-valued function whose arguments
+  /// are the argument of the pragma, if it takes one, and the schema.
+  fn pragma_table(
+    &mut self,
+    pragma: &'static Pragma,
+    arguments: &[ast::Expr],
+    alias: Option<&Name>,
+    left: &[Source],
+    offset: usize,
+  ) -> SqliteResult<(Input, Source)> {
+    let mut columns = pragma.columns();
+    let visible = columns.len();
+    if pragma.takes_argument() {
+      columns.push("arg".into());
+    }
+    columns.push("schema".into());
+    let max = columns.len() - visible;
+    if arguments.len() > max {
+      return Err(SqliteError::Custom(format!(
+        "too many arguments on pragma_{}() - max {max}",
+        pragma.name()
+      )));
+    }
+    let arguments = self.in_scope(left.to_vec(), |planner| {
+      arguments
+        .iter()
+        .map(|argument| planner.expr(argument))
+        .collect()
+    })?;
+    let columns = columns.into_iter().enumerate();
+    let columns = columns.map(|(idx, column)| SourceColumn {
+      name: column,
+      affinity: Affinity::Blob,
+      collation: Collation::Binary,
+      merged: false,
+      fallbacks: vec![],
+      hidden: idx >= visible,
+    });
+    let name = match alias {
+      Some(alias) => alias.value.clone(),
+      None => format!("pragma_{}", pragma.name()),
+    };
+    let source = derived_source(&name, columns.collect(), offset);
+    let table = PragmaTable::new(pragma, name, arguments);
+    Ok((Input::Pragma(table), source))
+  }
+
+  /// The table `name`: a common table expression if one has that name,
+  /// else a table or a view of the database.
+  fn named_table(
+    &mut self,
+    name: &QualifiedName,
+    alias: Option<&Name>,
+    offset: usize,
+  ) -> SqliteResult<(Input, Source)> {
+    if name.schema.is_none() {
+      let cte = self.ctes.iter().rposition(|cte| name.name.is(&cte.name));
+      if let Some(idx) = cte {
+        return self.cte(idx, alias, offset);
+      }
+    }
+    // A pragma may be read without arguments as a table, unless a table has
+    // its name.
+    let (table, source) = match self.table(name, alias, offset) {
+      Ok(table) => table,
+      Err(error) => {
+        if let Some(view) = self.view(name, alias, offset)? {
+          return Ok(view);
+        }
+        match pragma_function(name) {
+          Some(pragma) => {
+            return self.pragma_table(pragma, &[], alias, &[], offset)
+          }
+          None => return Err(error),
+        }
+      }
+    };
+    Ok((Input::Table(table), source))
+  }
+
+  /// Reads the common table expression at `idx`. Its select is planned for
+  /// each table reading it, unless its rows are shared.
+  fn cte(
+    &mut self,
+    idx: usize,
+    alias: Option<&Name>,
+    offset: usize,
+  ) -> SqliteResult<(Input, Source)> {
+    let level = self.scopes.len();
+    let cte = &mut self.ctes[idx];
+    let name = alias.map_or(&cte.name, |alias| &alias.value).clone();
+    if let Some(working) = &mut cte.working {
+      if working.level != level {
+        return Err(SqliteError::Custom(format!(
+          "multiple recursive references: {}",
+          cte.name
+        )));
+      }
+      if working.read {
+        return Err(SqliteError::Custom(format!(
+          "multiple references to recursive table: {}",
+          cte.name
+        )));
+      }
+      working.read = true;
+      let source = derived_source(&name, working.columns.clone(), offset);
+      return Ok((Input::Shared(working.id), source));
+    }
+    if cte.planning {
+      return Err(SqliteError::Custom(format!(
+        "circular reference: {}",
+        cte.name
+      )));
+    }
+    if let Some((id, columns)) = &cte.shared {
+      let source = derived_source(&name, columns.clone(), offset);
+      return Ok((Input::Shared(*id), source));
+    }
+    cte.planning = true;
+    let select = cte.select.clone();
+    let visible = cte.visible.min(self.ctes.len());
+    let hidden = self.ctes.split_off(visible);
+    let planned =
+      self.nested(level, |planner| planner.query(&select, Some(idx)));
+    self.ctes.extend(hidden);
+    let cte = &mut self.ctes[idx];
+    cte.planning = false;
+    let (query, correlated, _) = planned?;
+    let columns = subquery_columns(&query, &cte.columns, &cte.name)?;
+    let source = derived_source(&name, columns.clone(), offset);
+    if !cte.materialized || correlated {
+      let input = Input::Subquery {
+        root: query.root,
+        correlated,
+      };
+      return Ok((input, source));
+    }
+    let id = self.add_subquery(Some(query.root), SubqueryKind::Rows, false);
+    self.ctes[idx].shared = Some((id, columns));
+    Ok((Input::Shared(id), source))
+  }
+
+  /// Plans a subquery with `f`. `current` is the level in `scopes` of the
+  /// query whose row is the current one where the subquery runs: that of
+  /// the query of an expression, or the level of the subquery itself for
+  /// one of the `FROM` clause, which has no current row. Also returns
+  /// whether the subquery reads the rows of enclosing queries, and the
+  /// positions of the values of the current row it reads.
+  pub(super) fn nested<T>(
+    &mut self,
+    current: usize,
+    f: impl FnOnce(&mut Self) -> SqliteResult<T>,
+  ) -> SqliteResult<(T, bool, Vec<usize>)> {
+    let outer = mem::take(&mut self.outer_columns);
+    let result = f(self);
+    let inner = mem::replace(&mut self.outer_columns, outer);
+    let result = result?;
+    let level = self.scopes.len();
+    let correlated = inner.iter().any(|&(outer, _)| outer < level);
+    let mut columns = vec![];
+    for (outer, index) in inner {
+      if outer == current {
+        if !columns.contains(&index) {
+          columns.push(index);
+        }
+      } else if outer < current {
+        self.outer_columns.push((outer, index));
+      }
+    }
+    Ok((result, correlated, columns))
+  }
+
+  /// Plans a subquery of an expression.
+  fn subquery(
+    &mut self,
+    select: &Select,
+  ) -> SqliteResult<(Query, bool, Vec<usize>)> {
+    let current = self.scopes.len().saturating_sub(1);
+    self.nested(current, |planner| planner.query(select, None))
+  }
+
+  /// Adds a subquery to those of the statement, and returns its position.
+  fn add_subquery(
+    &mut self,
+    root: Option<Box<dyn Operator>>,
+    kind: SubqueryKind,
+    correlated: bool,
+  ) -> usize {
+    self.subqueries.push(Subquery::new(root, kind, correlated));
+    self.subqueries.len() - 1
+  }
+
+  /// The table `name`, and the indexes its rows can be looked up through.
+  pub(super) fn table(
+    &mut self,
+    name: &QualifiedName,
+    alias: Option<&Name>,
+    offset: usize,
+  ) -> SqliteResult<(Table, Source)> {
+    if let Some(schema) = name.schema.as_ref().filter(|s| !s.is("main")) {
+      return Err(SqliteError::Custom(format!(
+        "unknown database {}",
+        schema.value
+      )));
+    }
+    let cursor = self.runtime.table(&name.name.value)?;
+    let indexes = self.indexes(&cursor)?;
+    let definition = cursor.definition();
+    let rows = self
+      .statistics()?
+      .table_rows(definition.name())
+      .map_or(DEFAULT_ROWS, |rows| rows as f64);
+    let name = alias.map_or(definition.name(), |alias| &alias.value);
+    let source = table_source(definition, name, offset);
+    let real_columns = source
+      .columns
+      .iter()
+      .enumerate()
+      .filter(|(_, column)| column.affinity == Affinity::Real)
+      .map(|(idx, _)| idx)
+      .collect();
+    let table = Table {
+      cursor,
+      real_columns,
+      indexes,
+      rows,
+    };
+    Ok((table, source))
+  }
+
+  /// The index b-trees of the table of `cursor`: those of its `CREATE INDEX`
+  /// statements, that of its primary key, and the table itself when it is
+  /// `WITHOUT ROWID`. Partial indexes are left out, and indexes on
+  /// expressions only cover the columns before the first expression.
+  fn indexes(&mut self, cursor: &TableCursor) -> SqliteResult<Vec<TableIndex>> {
+    let definition = cursor.definition();
+    let primary_key = definition
+      .primary_key()
+      .iter()
+      .map(|key| key.column())
+      .collect::<Vec<_>>();
+    let rows = self
+      .statistics()?
+      .table_rows(definition.name())
+      .map_or(DEFAULT_ROWS, |rows| rows as f64);
+    let mut indexes = vec![];
+    if definition.is_without_rowid() {
+      let name = format!("sqlite_autoindex_{}_1", definition.name());
+      indexes.push(TableIndex {
+        rows: self.index_rows(
+          definition,
+          &name,
+          rows,
+          primary_key.len(),
+          true,
+        )?,
+        name,
+        root: cursor.root(),
+        columns: primary_key.clone(),
+        key_info: definition.key_info(),
+        target: IndexTarget::Table,
+      });
+    }
+    let entries = self
+      .runtime
+      .schema()?
+      .into_iter()
+      .filter(|entry| {
+        entry.kind() == "index"
+          && entry.tbl_name().eq_ignore_ascii_case(definition.name())
+      })
+      .collect::<Vec<_>>();
+    // The indexes SQLite creates for PRIMARY KEY and UNIQUE constraints have
+    // no SQL text. Only when there is one can it be told to be that of the
+    // primary key.
+    let automatic = entries.iter().filter(|e| e.sql().is_none()).count();
+    for entry in entries.iter() {
+      let (columns, key_columns, unique) = match entry.sql() {
+        Some(sql) => match index_columns(self.runtime, sql, definition)? {
+          // Without a rowid, the entries are only understood when every
+          // indexed value comes from a column.
+          Some((columns, _))
+            if definition.is_without_rowid()
+              && columns.iter().any(Option::is_none) =>
+          {
+            continue
+          }
+          Some((columns, unique)) => {
+            let count = columns.len();
+            let (columns, key_columns): (Vec<_>, Vec<_>) =
+              columns.into_iter().map_while(|column| column).unzip();
+            // Only a key made of columns alone is known to be unique.
+            let unique = unique && count == key_columns.len();
+            (columns, key_columns, unique)
+          }
+          None => continue,
+        },
+        None
+          if automatic == 1
+            && !definition.is_without_rowid()
+            && definition.rowid_alias().is_none()
+            && !primary_key.is_empty() =>
+        {
+          (
+            primary_key.clone(),
+            definition.key_info().columns().to_vec(),
+            true,
+          )
+        }
+        None => continue,
+      };
+      if columns.is_empty() {
+        continue;
+      }
+      let target = match definition.is_without_rowid() {
+        true => {
+          // The primary key columns missing from the indexed columns follow
+          // them in the entries.
+          let mut missing = columns.len();
+          let positions = primary_key
+            .iter()
+            .map(|column| match columns.iter().position(|c| c == column) {
+              Some(position) => position,
+              None => {
+                missing += 1;
+                missing - 1
+              }
+            })
+            .collect();
+          IndexTarget::PrimaryKey(positions)
+        }
+        false => IndexTarget::Rowid,
+      };
+      let count = columns.len();
+      indexes.push(TableIndex {
+        name: entry.name().into(),
+        root: entry.rootpage(),
+        columns,
+        key_info: KeyInfo::new(key_columns),
+        target,
+        rows: self.index_rows(definition, entry.name(), rows, count, unique)?,
+      });
+    }
+    Ok(indexes)
+  }
+
+  /// The estimated number of entries of the index `name`, of a table of
+  /// `rows` rows, then of those sharing the values of their first column,
+  /// of their first two, and so on up to `count` columns. Without
+  /// `sqlite_stat1`, each column is guessed to leave 10 entries, then 9, 8
+  /// and so on, as SQLite does, and a single entry to have the values of
+  /// every column of a `unique` index.
+  fn index_rows(
+    &mut self,
+    definition: &TableDefinition,
+    name: &str,
+    rows: f64,
+    count: usize,
+    unique: bool,
+  ) -> SqliteResult<Vec<f64>> {
+    let mut estimates = match self.statistics()?.index(definition.name(), name)
+    {
+      Some(stat) => stat.iter().map(|&count| count as f64).collect(),
+      None => vec![rows],
+    };
+    while estimates.len() <= count {
+      let guess = 11.0 - estimates.len() as f64;
+      let last = estimates.last().copied().unwrap_or(rows);
+      estimates.push(guess.max(1.0).min(last));
+    }
+    estimates.truncate(count + 1);
+    if unique && count > 0 {
+      estimates[count] = 1.0;
+    }
+    Ok(estimates)
+  }
+
+  /// The statistics of `sqlite_stat1`, read the first time they are needed.
+  fn statistics(&mut self) -> SqliteResult<&SqliteStat1> {
+    if self.statistics.is_none() {
+      self.statistics = Some(self.runtime.statistics()?);
+    }
+    Ok(self.statistics.get_or_insert_with(SqliteStat1::default))
+  }
+
+  fn result_columns(
+    &mut self,
+    columns: &[ResultColumn],
+  ) -> SqliteResult<ResultColumns> {
+    let mut result = ResultColumns::default();
+    for column in columns {
+      match column {
+        ResultColumn::Expr { expr, alias } => {
+          let name = match alias {
+            Some(alias) => alias.value.clone(),
+            None => self.column_name(expr),
+          };
+          let (calls, windows) = (self.aggregate_calls(), self.window_calls());
+          let expr = self.expr(expr)?;
+          let idx = result.exprs.len();
+          result.push(expr, name, alias.is_some());
+          result.aggregate[idx] = self.aggregate_calls() > calls;
+          result.window[idx] = self.window_calls() > windows;
+        }
+        ResultColumn::Star => {
+          let sources = self.scope().to_vec();
+          if sources.is_empty() {
+            return Err(SqliteError::Custom("no tables specified".into()));
+          }
+          for source in sources.iter() {
+            expand_source(source, true, &mut result);
+          }
+        }
+        ResultColumn::TableStar(table) => {
+          let source = self
+            .scope()
+            .iter()
+            .find(|source| table.is(&source.name))
+            .ok_or(SqliteError::Custom(format!(
+              "no such table: {}",
+              table.value
+            )))?;
+          expand_source(source, false, &mut result);
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  /// The name of a result column without an alias: the name of the column
+  /// it reads, or else the expression as written.
+  fn column_name(&self, expr: &ast::Expr) -> String {
+    if let ExprKind::Column {
+      schema,
+      table,
+      column,
+    } = &expr.kind
+    {
+      if let Ok(Some(resolved)) =
+        self.resolve_column(schema.as_ref(), table.as_ref(), column)
+      {
+        return resolved.name;
+      }
+    }
+    expr.span.text(&self.sql).into()
+  }
+
+  pub(crate) fn expr(&mut self, expr: &ast::Expr) -> SqliteResult<Expr> {
+    Ok(match &expr.kind {
+      // `CURRENT_TIME`, `CURRENT_DATE` and `CURRENT_TIMESTAMP` are `time()`,
+      // `date()` and `datetime()` of the current time.
+      ExprKind::Literal(
+        literal @ (Literal::CurrentTime
+        | Literal::CurrentDate
+        | Literal::CurrentTimestamp),
+      ) => Expr::Function {
+        function: match literal {
+          Literal::CurrentTime => ScalarFunction::Time,
+          Literal::CurrentDate => ScalarFunction::Date,
+          _ => ScalarFunction::Datetime,
+        },
+        arguments: vec![],
+        collation: Collation::Binary,
+      },
+      ExprKind::Literal(literal) => Expr::Literal(match literal {
+        Literal::Null => Value::Null,
+        Literal::Integer(int) => Value::Integer(*int),
+        Literal::Real(real) => Value::Real(*real),
+        Literal::String(text) => Value::Text(text.clone()),
+        Literal::Blob(blob) => Value::Blob(blob.clone()),
+        Literal::CurrentTime
+        | Literal::CurrentDate
+        | Literal::CurrentTimestamp => unreachable!(),
+      }),
+      // Read from the bindings of the statement when it runs, NULL if unbound.
+      ExprKind::Variable(variable) => Expr::Parameter(variable.index),
+      ExprKind::Column {
+        schema,
+        table,
+        column,
+      } => self.column(schema.as_ref(), table.as_ref(), column)?,
+      ExprKind::Unary { operator, expr } => Expr::Unary {
+        operator: *operator,
+        expr: Box::new(self.expr(expr)?),
+      },
+      ExprKind::Binary {
+        left,
+        operator,
+        right,
+      } => {
+        let left = Box::new(self.expr(left)?);
+        let right = Box::new(self.expr(right)?);
+        let comparison = match operator {
+          BinaryOperator::And => return Ok(Expr::And(left, right)),
+          BinaryOperator::Or => return Ok(Expr::Or(left, right)),
+          BinaryOperator::Extract | BinaryOperator::ExtractValue => {
+            let function = match operator {
+              BinaryOperator::Extract => JsonFunction::Arrow,
+              _ => JsonFunction::LongArrow,
+            };
+            return Ok(Expr::Function {
+              function: ScalarFunction::Json {
+                function,
+                binary: false,
+              },
+              arguments: vec![*left, *right],
+              collation: Collation::Binary,
+            });
+          }
+          BinaryOperator::Eq => Comparison::Eq,
+          BinaryOperator::NotEq => Comparison::NotEq,
+          BinaryOperator::Lt => Comparison::Lt,
+          BinaryOperator::LtEq => Comparison::LtEq,
+          BinaryOperator::Gt => Comparison::Gt,
+          BinaryOperator::GtEq => Comparison::GtEq,
+          operator => {
+            return Ok(Expr::Binary {
+              operator: *operator,
+              left,
+              right,
+            })
+          }
+        };
+        compare(comparison, left, right)
+      }
+      ExprKind::Is { left, not, right } => {
+        let comparison = match not {
+          true => Comparison::IsNot,
+          false => Comparison::Is,
+        };
+        compare(
+          comparison,
+          Box::new(self.expr(left)?),
+          Box::new(self.expr(right)?),
+        )
+      }
+      ExprKind::IsNull { expr, not } => Expr::IsNull {
+        expr: Box::new(self.expr(expr)?),
+        not: *not,
+      },
+      ExprKind::Like {
+        expr,
+        not,
+        operator,
+        pattern,
+        escape,
+      } => {
+        let glob = match operator {
+          LikeOperator::Like => false,
+          LikeOperator::Glob => true,
+          LikeOperator::Regexp => return Err(no_such_function("REGEXP")),
+          LikeOperator::Match => return Err(no_such_function("MATCH")),
+        };
+        Expr::Like {
+          expr: Box::new(self.expr(expr)?),
+          not: *not,
+          glob,
+          pattern: Box::new(self.expr(pattern)?),
+          escape: match escape {
+            Some(escape) => Some(Box::new(self.expr(escape)?)),
+            None => None,
+          },
+        }
+      }
+      ExprKind::Between {
+        expr,
+        not,
+        low,
+        high,
+      } => {
+        let expr = self.expr(expr)?;
+        let low = self.expr(low)?;
+        let high = self.expr(high)?;
+        Expr::Between {
+          low_comparator: Comparator::new(&expr, &low),
+          high_comparator: Comparator::new(&expr, &high),
+          expr: Box::new(expr),
+          not: *not,
+          low: Box::new(low),
+          high: Box::new(high),
+        }
+      }
+      ExprKind::In { expr, not, target } => {
+        let expr = self.expr(expr)?;
+        let (root, column, correlated, columns) = match target {
+          InTarget::List(list) => {
+            return Ok(Expr::InList {
+              comparator: Comparator::for_in(&expr),
+              expr: Box::new(expr),
+              not: *not,
+              list: list
+                .iter()
+                .map(|item| self.expr(item))
+                .collect::<SqliteResult<_>>()?,
+            })
+          }
+          InTarget::Select(select) => {
+            let (query, correlated, columns) = self.subquery(select)?;
+            let column = single_column(&query)?;
+            (query.root, column, correlated, columns)
+          }
+          // `IN table` reads the only column of the table.
+          InTarget::Table(name) => {
+            let (input, source) = self.named_table(name, None, 0)?;
+            if source.columns.len() != 1 {
+              return Err(sub_select_columns(source.columns.len()));
+            }
+            let column = source_column(&source, 0, false);
+            let (root, correlated) = match input {
+              Input::Subquery { root, correlated } => (root, correlated),
+              input => {
+                let table = FromTable {
+                  input,
+                  join_type: JoinType::Inner,
+                  on: None,
+                  using: vec![],
+                };
+                let order = Order::default();
+                let (scan, ..) =
+                  self.scan(table, &source, &[], None, 1.0, &order);
+                (Box::new(scan) as Box<dyn Operator>, false)
+              }
+            };
+            (root, column, correlated, vec![])
+          }
+          InTarget::TableFunction { .. } => {
+            return Err(unsupported("Table-valued function"))
+          }
+        };
+        let kind = SubqueryKind::In(Comparator::new(&expr, &column));
+        let id = self.add_subquery(Some(root), kind, correlated);
+        Expr::InSubquery {
+          expr: Box::new(expr),
+          not: *not,
+          subquery: SubqueryRef { id, columns },
+        }
+      }
+      ExprKind::Collate { expr, collation } => Expr::Collate {
+        expr: Box::new(self.expr(expr)?),
+        collation: collation_named(self.runtime, collation)?,
+      },
+      ExprKind::Cast { expr, type_name } => Expr::Cast {
+        expr: Box::new(self.expr(expr)?),
+        affinity: Affinity::from_declared_type(Some(&type_name.name)),
+      },
+      ExprKind::Function(call) => self.function(call)?,
+      ExprKind::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        let when_then = when_then
+          .iter()
+          .map(|(when, then)| Ok((self.expr(when)?, self.expr(then)?)))
+          .collect::<SqliteResult<Vec<_>>>()?;
+        let operand = match operand {
+          Some(operand) => {
+            let operand = self.expr(operand)?;
+            let comparators = when_then
+              .iter()
+              .map(|(when, _)| Comparator::new(&operand, when))
+              .collect();
+            Some((Box::new(operand), comparators))
+          }
+          None => None,
+        };
+        Expr::Case {
+          operand,
+          when_then,
+          else_expr: match else_expr {
+            Some(else_expr) => Some(Box::new(self.expr(else_expr)?)),
+            None => None,
+          },
+        }
+      }
+      ExprKind::Exists(select) => {
+        let (query, correlated, columns) = self.subquery(select)?;
+        let kind = SubqueryKind::Exists;
+        let id = self.add_subquery(Some(query.root), kind, correlated);
+        Expr::Exists(SubqueryRef { id, columns })
+      }
+      ExprKind::Subquery(select) => {
+        let (query, correlated, columns) = self.subquery(select)?;
+        single_column(&query)?;
+        let kind = SubqueryKind::Scalar;
+        let id = self.add_subquery(Some(query.root), kind, correlated);
+        Expr::Subquery(SubqueryRef { id, columns })
+      }
+      ExprKind::Vector(_) => {
+        return Err(SqliteError::Custom("row value misused".into()))
+      }
+      ExprKind::Raise { action, message } if self.in_trigger => Expr::Raise {
+        action: *action,
+        message: match message {
+          Some(message) => Some(Box::new(self.expr(message)?)),
+          None => None,
+        },
+      },
+      ExprKind::Raise { .. } => {
+        return Err(SqliteError::Custom(
+          "RAISE() may only be used within a trigger-program".into(),
+        ))
+      }
+    })
+  }
+
+  fn function(&mut self, call: &FunctionCall) -> SqliteResult<Expr> {
+    let name = &call.name.value;
+    let arguments = match &call.arguments {
+      FunctionArguments::Star => &[],
+      FunctionArguments::List(arguments) => arguments.as_slice(),
+    };
+    let user = self.functions.find(name, arguments.len()).cloned();
+    let is_user = user.is_some();
+    if !is_user && self.functions.contains(name) && !is_builtin(name) {
+      return Err(SqliteError::Custom(format!(
+        "wrong number of arguments to function {name}()"
+      )));
+    }
+    let argc = [arguments.len()];
+    let aggregate = match user {
+      Some(function) => function
+        .is_aggregate()
+        .then(|| (AggregateFunction::User(function), &argc[..])),
+      // `min()` and `max()` with several arguments are scalar functions.
+      None => AggregateFunction::from_name(name).filter(|(function, _)| {
+        !matches!(function, AggregateFunction::Min | AggregateFunction::Max)
+          || arguments.len() == 1
+      }),
+    };
+    if let Some(over) = &call.over {
+      return self.window_function(call, over, arguments, aggregate);
+    }
+    if !is_user && WindowFunction::from_name(name).is_some() {
+      return Err(misuse_of_window_function(name));
+    }
+    let Some((function, counts)) = aggregate else {
+      if call.filter.is_some() {
+        return Err(SqliteError::Custom(format!(
+          "FILTER may not be used with non-aggregate {name}()"
+        )));
+      }
+      return self.scalar_function(call, arguments);
+    };
+    let is_star = call.arguments == FunctionArguments::Star;
+    if !counts.contains(&arguments.len())
+      || (is_star && function != AggregateFunction::Count)
+    {
+      return Err(SqliteError::Custom(format!(
+        "wrong number of arguments to function {name}()"
+      )));
+    }
+    if call.distinct && arguments.len() != 1 {
+      return Err(SqliteError::Custom(
+        "DISTINCT aggregates must have exactly one argument".into(),
+      ));
+    }
+    let (offset, mut calls) =
+      match mem::replace(&mut self.aggregates, AggregateScope::Forbidden) {
+        AggregateScope::Collect { offset, calls } => (offset, calls),
+        AggregateScope::GroupBy => {
+          self.aggregates = AggregateScope::GroupBy;
+          return Err(aggregate_in_group_by());
+        }
+        AggregateScope::Forbidden => {
+          return Err(SqliteError::Custom(format!(
+            "misuse of aggregate function {name}()"
+          )))
+        }
+      };
+    // Nor may window functions be called within the arguments.
+    let windows = self.windows.take();
+    let compiled = self.aggregate_call(function, call, arguments);
+    self.windows = windows;
+    let call = match compiled {
+      Ok(call) => call,
+      Err(error) => {
+        self.aggregates = AggregateScope::Collect { offset, calls };
+        return Err(error);
+      }
+    };
+    let index = match calls.iter().position(|other| *other == call) {
+      Some(index) => index,
+      None => {
+        calls.push(call);
+        calls.len() - 1
+      }
+    };
+    self.aggregates = AggregateScope::Collect { offset, calls };
+    Ok(Expr::Slot(offset + index))
+  }
+
+  /// Compiles a call to a scalar function. `iif()` becomes the `CASE`
+  /// expression it stands for.
+  fn scalar_function(
+    &mut self,
+    call: &FunctionCall,
+    arguments: &[ast::Expr],
+  ) -> SqliteResult<Expr> {
+    let name = &call.name.value;
+    let user = self.functions.find(name, arguments.len());
+    let (function, counts) = match user.cloned() {
+      Some(function) => (
+        Some(ScalarFunction::User(function)),
+        arguments.len()..=arguments.len(),
+      ),
+      None => match ScalarFunction::from_name(name) {
+        Some((function, counts)) => (Some(function), counts),
+        None if name.eq_ignore_ascii_case("iif") => (None, 2..=usize::MAX),
+        None => return Err(no_such_function(name)),
+      },
+    };
+    if !counts.contains(&arguments.len()) {
+      return Err(SqliteError::Custom(format!(
+        "wrong number of arguments to function {name}()"
+      )));
+    }
+    if !call.order_by.is_empty() {
+      return Err(SqliteError::Custom(format!(
+        "ORDER BY may not be used with non-aggregate {name}()"
+      )));
+    }
+    let mut arguments = arguments
+      .iter()
+      .map(|argument| self.expr(argument))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    let Some(function) = function else {
+      // `iif(C1, V1, C2, V2, ..., E)` is the value after the first true
+      // condition, else the last argument when their number is odd.
+      let else_expr = match arguments.len() % 2 {
+        1 => arguments.pop().map(Box::new),
+        _ => None,
+      };
+      let mut arguments = arguments.into_iter();
+      let mut when_then = vec![];
+      while let (Some(when), Some(then)) = (arguments.next(), arguments.next())
+      {
+        when_then.push((when, then));
+      }
+      return Ok(Expr::Case {
+        operand: None,
+        when_then,
+        else_expr,
+      });
+    };
+    if let Some(place) = self.deterministic_in {
+      if !function.is_deterministic() {
+        return Err(SqliteError::Custom(format!(
+          "non-deterministic functions prohibited in {place}"
+        )));
+      }
+    }
+    if let ScalarFunction::Json { function, .. } = &function {
+      for (idx, argument) in arguments.iter_mut().enumerate() {
+        if function.takes_value(idx) {
+          self.json_value(argument);
+        }
+      }
+    }
+    let collation = match function.needs_collation() {
+      true => arguments.iter().find_map(Expr::collation).map(|(c, _)| c),
+      false => None,
+    };
+    Ok(Expr::Function {
+      function,
+      arguments,
+      collation: collation.unwrap_or_default(),
+    })
+  }
+
+  /// Makes an argument a JSON function puts in the JSON it returns return
+  /// JSONB when it is JSON: a call to a JSON function returning JSON, or
+  /// the `value` column of `json_each()` or `json_tree()` for an array or
+  /// an object. Either would otherwise be taken for a string.
+  fn json_value(&self, argument: &mut Expr) {
+    match argument {
+      Expr::Function {
+        function: ScalarFunction::Json { function, binary },
+        ..
+      } if function.returns_json() => *binary = true,
+      Expr::Column { depth, index, .. } => {
+        let (depth, index) = (*depth, *index);
+        let sources = self.scopes.iter().rev().nth(depth);
+        let is_value = sources.is_some_and(|sources| {
+          sources
+            .iter()
+            .any(|source| source.json && index == source.offset + json::VALUE)
+        });
+        if !is_value {
+          return;
+        }
+        // `CASE WHEN atom IS NULL THEN jsonb(value) ELSE value END`
+        let atom = Expr::Column {
+          depth,
+          index: index - json::VALUE + json::ATOM,
+          affinity: Affinity::Blob,
+          collation: Collation::Binary,
+        };
+        let value = argument.clone();
+        *argument = Expr::Case {
+          operand: None,
+          when_then: vec![(
+            Expr::IsNull {
+              expr: Box::new(atom),
+              not: false,
+            },
+            Expr::Function {
+              function: ScalarFunction::Json {
+                function: JsonFunction::Json,
+                binary: true,
+              },
+              arguments: vec![value.clone()],
+              collation: Collation::Binary,
+            },
+          )],
+          else_expr: Some(Box::new(value)),
+        };
+      }
+      _ => {}
+    }
+  }
+
+  /// Compiles the arguments and clauses of a call to an aggregate function.
+  fn aggregate_call(
+    &mut self,
+    function: AggregateFunction,
+    call: &FunctionCall,
+    arguments: &[ast::Expr],
+  ) -> SqliteResult<AggregateCall> {
+    let mut arguments = arguments
+      .iter()
+      .map(|argument| self.expr(argument))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    if let AggregateFunction::JsonGroup { object, .. } = function {
+      if let Some(value) = arguments.get_mut(usize::from(object)) {
+        self.json_value(value);
+      }
+    }
+    let collation = arguments
+      .first()
+      .and_then(Expr::collation)
+      .map(|(collation, _)| collation)
+      .unwrap_or_default();
+    let filter = match &call.filter {
+      Some(filter) => Some(self.expr(filter)?),
+      None => None,
+    };
+    let mut order_by = vec![];
+    let mut order = vec![];
+    for term in call.order_by.iter() {
+      let expr = self.expr(&term.expr)?;
+      order.push(SortKey::new(
+        arguments.len() + order_by.len(),
+        term.order == SortOrder::Desc,
+        term.nulls.map(|nulls| nulls == NullsOrder::First),
+        expr.collation().map(|(c, _)| c).unwrap_or_default(),
+      ));
+      order_by.push(expr);
+    }
+    Ok(AggregateCall {
+      function,
+      arguments,
+      collation,
+      distinct: call.distinct,
+      filter,
+      order_by,
+      order,
+    })
+  }
+
+  /// The number of aggregate calls gathered so far.
+  fn aggregate_calls(&self) -> usize {
+    match &self.aggregates {
+      AggregateScope::Collect { calls, .. } => calls.len(),
+      _ => 0,
+    }
+  }
+
+  /// Compiles a call to a window function, or to an aggregate function used
+  /// as one, into the value its window computes for the current row.
+  ///
+  /// *Reference:* https://www.sqlite.org/windowfunctions.html
+  fn window_function(
+    &mut self,
+    call: &FunctionCall,
+    over: &Over,
+    arguments: &[ast::Expr],
+    aggregate: Option<(AggregateFunction, &[usize])>,
+  ) -> SqliteResult<Expr> {
+    let name = &call.name.value;
+    let user = self.functions.find(name, arguments.len());
+    let builtin = match user {
+      Some(_) => None,
+      None => WindowFunction::from_name(name),
+    };
+    let (counts, aggregate) = match (&builtin, aggregate) {
+      (Some((_, counts)), _) => (*counts, None),
+      (None, Some((AggregateFunction::User(function), _)))
+        if !function.is_window() =>
+      {
+        return Err(SqliteError::Custom(format!(
+          "{name}() may not be used as a window function"
+        )))
+      }
+      (None, Some((function, counts))) => (counts, Some(function)),
+      (None, None) if user.is_some() || is_scalar_function(name) => {
+        return Err(SqliteError::Custom(format!(
+          "{name}() may not be used as a window function"
+        )))
+      }
+      (None, None) => return Err(no_such_function(name)),
+    };
+    let is_star = call.arguments == FunctionArguments::Star;
+    // `*` stands for no arguments, which `count()` is the only aggregate to
+    // accept.
+    let takes_star = aggregate
+      .as_ref()
+      .map_or(true, |function| *function == AggregateFunction::Count);
+    if !counts.contains(&arguments.len()) || (is_star && !takes_star) {
+      return Err(SqliteError::Custom(format!(
+        "wrong number of arguments to function {name}()"
+      )));
+    }
+    if call.distinct {
+      return Err(SqliteError::Custom(
+        "DISTINCT is not supported for window functions".into(),
+      ));
+    }
+    if !call.order_by.is_empty() {
+      return Err(SqliteError::Custom(format!(
+        "ORDER BY may not be used with non-aggregate {name}()"
+      )));
+    }
+    if call.filter.is_some() && builtin.is_some() {
+      return Err(SqliteError::Custom(
+        "FILTER clause may only be used with aggregate window functions".into(),
+      ));
+    }
+    let Some(mut windows) = self.windows.take() else {
+      return Err(misuse_of_window_function(name));
+    };
+    let compiled = match (builtin, aggregate) {
+      (Some((function, _)), _) => self.window_call(function, arguments, over),
+      (None, Some(function)) => self
+        .aggregate_call(function, call, arguments)
+        .and_then(|call| {
+          self.window_call(WindowFunction::Aggregate(call), &[], over)
+        }),
+      (None, None) => Err(no_such_function(name)),
+    };
+    let index = windows.len();
+    let result = compiled.map(|call| windows.push(call));
+    self.windows = Some(windows);
+    result.map(|()| Expr::Window(index))
+  }
+
+  /// Compiles the arguments of a call to `function`, and the window of its
+  /// `OVER` clause.
+  fn window_call(
+    &mut self,
+    function: WindowFunction,
+    arguments: &[ast::Expr],
+    over: &Over,
+  ) -> SqliteResult<WindowCall> {
+    let arguments = arguments
+      .iter()
+      .map(|argument| self.expr(argument))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    let window = match over {
+      Over::Name(name) => self.named_window(name, self.named_windows.len())?,
+      Over::Window(window) => {
+        self.based_window(window, self.named_windows.len())?
+      }
+    };
+    let partition_by = window
+      .partition_by
+      .iter()
+      .map(|expr| self.expr(expr))
+      .collect::<SqliteResult<Vec<_>>>()?;
+    let mut order_by = vec![];
+    let mut order = vec![];
+    for term in window.order_by.iter() {
+      let expr = self.expr(&term.expr)?;
+      order.push(SortKey::new(
+        order_by.len(),
+        term.order == SortOrder::Desc,
+        term.nulls.map(|nulls| nulls == NullsOrder::First),
+        expr.collation().map(|(c, _)| c).unwrap_or_default(),
+      ));
+      order_by.push(expr);
+    }
+    let frame = match &window.frame {
+      Some(frame) => Frame {
+        unit: frame.unit,
+        start: self.frame_bound(&frame.start)?,
+        end: self.frame_bound(&frame.end)?,
+        exclude: frame.exclude,
+      },
+      None => Frame::default(),
+    };
+    let has_offset = [&frame.start, &frame.end]
+      .iter()
+      .any(|bound| matches!(bound, Bound::Preceding(_) | Bound::Following(_)));
+    if frame.unit == FrameUnit::Range && has_offset && order_by.len() != 1 {
+      return Err(SqliteError::Custom(
+        "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY \
+         expression"
+          .into(),
+      ));
+    }
+    Ok(WindowCall {
+      function,
+      arguments,
+      partition_by,
+      order_by,
+      order,
+      frame,
+    })
+  }
+
+  /// The window named `name` by the `WINDOW` clause of the query, among the
+  /// first `visible` ones. The last of that name wins.
+  fn named_window(
+    &self,
+    name: &Name,
+    visible: usize,
+  ) -> SqliteResult<WindowDefinition> {
+    let position = self.named_windows[..visible]
+      .iter()
+      .rposition(|window| window.name.is(&name.value))
+      .ok_or(SqliteError::Custom(format!(
+        "no such window: {}",
+        name.value
+      )))?;
+    self.based_window(&self.named_windows[position].window, position)
+  }
+
+  /// `window`, completed by the window it names as its base, among the first
+  /// `visible` windows of the `WINDOW` clause. It may only add an `ORDER BY`
+  /// clause the base does not have, and a frame.
+  fn based_window(
+    &self,
+    window: &WindowDefinition,
+    visible: usize,
+  ) -> SqliteResult<WindowDefinition> {
+    let Some(base) = &window.base else {
+      return Ok(window.clone());
+    };
+    let mut based = self.named_window(base, visible)?;
+    let cannot_override = |clause: &str| {
+      SqliteError::Custom(format!(
+        "cannot override {clause} of window: {}",
+        base.value
+      ))
+    };
+    if !window.partition_by.is_empty() {
+      return Err(cannot_override("PARTITION clause"));
+    }
+    if !window.order_by.is_empty() {
+      if !based.order_by.is_empty() {
+        return Err(cannot_override("ORDER BY clause"));
+      }
+      based.order_by = window.order_by.clone();
+    }
+    if based.frame.is_some() {
+      return Err(cannot_override("frame specification"));
+    }
+    based.frame = window.frame.clone();
+    Ok(based)
+  }
+
+  fn frame_bound(&mut self, bound: &FrameBound) -> SqliteResult<Bound> {
+    Ok(match bound {
+      FrameBound::UnboundedPreceding => Bound::UnboundedPreceding,
+      FrameBound::Preceding(offset) => Bound::Preceding(self.expr(offset)?),
+      FrameBound::CurrentRow => Bound::CurrentRow,
+      FrameBound::Following(offset) => Bound::Following(self.expr(offset)?),
+      FrameBound::UnboundedFollowing => Bound::UnboundedFollowing,
+    })
+  }
+
+  /// The number of window function calls gathered so far.
+  fn window_calls(&self) -> usize {
+    self.windows.as_ref().map_or(0, Vec::len)
+  }
+
+  /// A column reference. As in SQLite, an identifier in double quotes that
+  /// is not a column is a string, and `TRUE` and `FALSE` are the integers 1
+  /// and 0.
+  fn column(
+    &mut self,
+    schema: Option<&Name>,
+    table: Option<&Name>,
+    column: &Name,
+  ) -> SqliteResult<Expr> {
+    if let Some(resolved) = self.resolve_column(schema, table, column)? {
+      if resolved.depth > 0 {
+        let level = self.scopes.len() - 1 - resolved.depth;
+        self.outer_columns.push((level, resolved.index));
+      }
+      let expr = Expr::Column {
+        depth: resolved.depth,
+        index: resolved.index,
+        affinity: resolved.affinity,
+        collation: resolved.collation,
+      };
+      return Ok(coalesce(expr, &resolved.fallbacks));
+    }
+    if table.is_none() {
+      if let Some(alias) = self.aliases.iter().find(|a| column.is(&a.name)) {
+        if alias.window && self.windows.is_none() {
+          return Err(SqliteError::Custom(format!(
+            "misuse of aliased window function {}",
+            column.value
+          )));
+        }
+        if alias.aggregate {
+          match self.aggregates {
+            AggregateScope::Collect { .. } => {}
+            AggregateScope::GroupBy => return Err(aggregate_in_group_by()),
+            AggregateScope::Forbidden => {
+              return Err(SqliteError::Custom(format!(
+                "misuse of aliased aggregate {}",
+                column.value
+              )))
+            }
+          }
+        }
+        return Ok(alias.expr.clone());
+      }
+      if column.quoted {
+        return Ok(Expr::Literal(Value::Text(column.value.clone())));
+      }
+      if column.is("TRUE") || column.is("FALSE") {
+        return Ok(Expr::Literal(Value::Integer(i64::from(column.is("TRUE")))));
+      }
+    }
+    let qualified = [schema, table, Some(column)]
+      .into_iter()
+      .flatten()
+      .map(|name| name.value.as_str())
+      .collect::<Vec<_>>()
+      .join(".");
+    Err(SqliteError::Custom(format!("no such column: {qualified}")))
+  }
+
+  /// Looks a column up in the sources of the current query first, then in
+  /// those of the enclosing queries.
+  fn resolve_column(
+    &self,
+    schema: Option<&Name>,
+    table: Option<&Name>,
+    column: &Name,
+  ) -> SqliteResult<Option<ResolvedColumn>> {
+    if schema.is_some_and(|schema| !schema.is("main")) {
+      return Ok(None);
+    }
+    for (depth, sources) in self.scopes.iter().rev().enumerate() {
+      let sources = sources
+        .iter()
+        .filter(|source| table.map_or(true, |table| table.is(&source.name)));
+      let mut found: Option<ResolvedColumn> = None;
+      let mut found_rowid: Option<ResolvedColumn> = None;
+      for source in sources {
+        if let Some(idx) = source.columns.iter().position(|candidate| {
+          column.is(&candidate.name) && (table.is_some() || !candidate.merged)
+        }) {
+          if found.is_some() {
+            return Err(ambiguous(table, column));
+          }
+          let candidate = &source.columns[idx];
+          found = Some(ResolvedColumn {
+            depth,
+            index: source.offset + idx,
+            name: candidate.name.clone(),
+            affinity: candidate.affinity,
+            collation: candidate.collation.clone(),
+            fallbacks: match table {
+              Some(_) => vec![],
+              None => candidate.fallbacks.clone(),
+            },
+          });
+        } else if let Some(rowid) = source.rowid {
+          if !ROWID_NAMES.iter().any(|name| column.is(name)) {
+            continue;
+          }
+          if found_rowid.is_some() {
+            return Err(ambiguous(table, column));
+          }
+          let (name, collation) = match source.columns.get(rowid) {
+            Some(alias) => (alias.name.clone(), alias.collation.clone()),
+            None => ("rowid".into(), Collation::Binary),
+          };
+          found_rowid = Some(ResolvedColumn {
+            depth,
+            index: source.offset + rowid,
+            name,
+            affinity: Affinity::Integer,
+            collation,
+            fallbacks: vec![],
+          });
+        }
+      }
+      if let Some(found) = found.or(found_rowid) {
+        return Ok(Some(found));
+      }
+    }
+    Ok(None)
+  }
+
+  fn scope(&self) -> &[Source] {
+    self.scopes.last().map_or(&[], Vec::as_slice)
+  }
+
+  /// Runs `f` with `sources` visible to the expressions it compiles.
+  pub(super) fn in_scope<T>(
+    &mut self,
+    sources: Vec<Source>,
+    f: impl FnOnce(&mut Self) -> SqliteResult<T>,
+  ) -> SqliteResult<T> {
+    self.scopes.push(sources);
+    let result = f(self);
+    self.scopes.pop();
+    result
+  }
+}
+
+/// The plan of `EXPLAIN` of the statement compiled to `program`, whose
+/// rows are its instructions.
+fn explain(program: Codegen<Program>) -> SqliteResult<Plan> {
+  let program = program.map_err(|Unsupported(feature)| {
+    unsupported(&format!("EXPLAIN of {feature}"))
+  })?;
+  let rows = program
+    .explain()
+    .into_iter()
+    .map(|row| row.into_iter().map(Expr::Literal).collect())
+    .collect();
+  Ok(Plan {
+    root: Box::new(Values::new(rows)),
+    columns: Program::COLUMNS.map(String::from).into(),
+    subqueries: vec![],
+  })
+}
+
+/// Computes the window function `calls` of a query over its rows of `width`
+/// values, with a window for each way they partition and sort the rows, then
+/// puts the values computed in place of the calls in `exprs`.
+fn windowed(
+  mut input: Box<dyn Operator>,
+  mut width: usize,
+  calls: Vec<WindowCall>,
+  exprs: &mut [Expr],
+) -> Box<dyn Operator> {
+  let mut windows: Vec<Vec<usize>> = vec![];
+  for (idx, call) in calls.iter().enumerate() {
+    match windows
+      .iter_mut()
+      .find(|window| calls[window[0]].same_window(call))
+    {
+      Some(window) => window.push(idx),
+      None => windows.push(vec![idx]),
+    }
+  }
+  let mut slots = vec![0; calls.len()];
+  for window in windows {
+    let first = &calls[window[0]];
+    let partition = first
+      .partition_by
+      .iter()
+      .enumerate()
+      .map(|(idx, expr)| {
+        let collation = expr.collation().map(|(c, _)| c).unwrap_or_default();
+        SortKey::new(width + idx, false, None, collation)
+      })
+      .collect::<Vec<_>>();
+    let order_offset = width + partition.len();
+    let order = first
+      .order
+      .iter()
+      .map(|key| SortKey {
+        column: order_offset + key.column,
+        ..key.clone()
+      })
+      .collect::<Vec<_>>();
+    if !partition.is_empty() || !order.is_empty() {
+      let exprs = (0..width)
+        .map(Expr::Slot)
+        .chain(first.partition_by.iter().cloned())
+        .chain(first.order_by.iter().cloned())
+        .collect();
+      let rows = Box::new(Project::new(input, exprs));
+      let keys = partition.iter().chain(order.iter()).cloned().collect();
+      let row_width = order_offset + order.len();
+      input =
+        Box::new(Sort::new(rows, keys, row_width, Some("ORDER BY"), None));
+    }
+    for (position, &idx) in window.iter().enumerate() {
+      slots[idx] = width + position;
+    }
+    let window_calls = window.iter().map(|&idx| calls[idx].clone()).collect();
+    input = Box::new(Window::new(input, width, partition, order, window_calls));
+    width += window.len();
+  }
+  for expr in exprs.iter_mut() {
+    expr.visit_mut(&mut |expr| {
+      if let Expr::Window(idx) = *expr {
+        *expr = Expr::Slot(slots[idx]);
+      }
+    });
+  }
+  input
+}
+
+/// The rows of `left` and `right` combined by `operator`. As in SQLite,
+/// which keeps them in a temporary index, the rows of `UNION`, `INTERSECT`
+/// and `EXCEPT` come out sorted.
+fn combine(
+  left: Query,
+  operator: CompoundOperator,
+  right: Query,
+) -> SqliteResult<Query> {
+  if left.columns.len() != right.columns.len() {
+    return Err(different_widths(operator));
+  }
+  // A column compares with the collating sequence of the leftmost select
+  // that gives it one.
+  let mut exprs = left.exprs;
+  for (expr, right) in exprs.iter_mut().zip(right.exprs) {
+    if expr.collation().is_none() && right.collation().is_some() {
+      *expr = right;
+    }
+  }
+  let collations = collations(&exprs);
+  let mut root: Box<dyn Operator> = Box::new(Compound::new(
+    left.root,
+    right.root,
+    operator,
+    collations.clone(),
+  ));
+  if operator != CompoundOperator::UnionAll {
+    let keys = collations
+      .into_iter()
+      .enumerate()
+      .map(|(idx, collation)| SortKey::new(idx, false, None, collation))
+      .collect();
+    root = Box::new(Sort::new(root, keys, exprs.len(), None, None));
+  }
+  Ok(Query {
+    root,
+    exprs,
+    aggregate: left.aggregate || right.aggregate,
+    ..left
+  })
+}
+
+/// The collating sequence of each expression, BINARY when it has none.
+fn collations(exprs: &[Expr]) -> Vec<Collation> {
+  exprs
+    .iter()
+    .map(|expr| expr.collation().map(|(c, _)| c).unwrap_or_default())
+    .collect()
+}
+
+/// The result columns of `query`, as its `ORDER BY` clause sees them.
+fn result_columns_of(query: &Query) -> ResultColumns {
+  let mut columns = ResultColumns::default();
+  for (name, expr) in query.columns.iter().zip(&query.exprs) {
+    columns.push(expr.clone(), name.clone(), false);
+  }
+  columns
+}
+
+/// Whether the `FROM` clause of `core` reads the table `name` itself, and
+/// not through a subquery.
+fn reads_table(core: &SelectCore, name: &str) -> bool {
+  fn from_reads(from: &ast::From, name: &str) -> bool {
+    let joins = from.joins.iter().map(|join| &join.table);
+    iter::once(&from.first)
+      .chain(joins)
+      .any(|table| match table {
+        TableOrSubquery::Table { name: table, .. } => {
+          table.schema.is_none() && table.name.is(name)
+        }
+        TableOrSubquery::Join(from) => from_reads(from, name),
+        _ => false,
+      })
+  }
+  match core {
+    SelectCore::Select(core) => core
+      .from
+      .as_ref()
+      .is_some_and(|from| from_reads(from, name)),
+    SelectCore::Values(_) => false,
+  }
+}
+
+/// The columns of a subquery of the `FROM` clause, or of the common table
+/// expression `table`: they have the names given, if any, else those of
+/// its result columns, made unique with a numbered suffix, and the affinity
+/// and collating sequence of its result columns.
+pub(super) fn subquery_columns(
+  query: &Query,
+  names: &[String],
+  table: &str,
+) -> SqliteResult<Vec<SourceColumn>> {
+  if !names.is_empty() && names.len() != query.columns.len() {
+    return Err(SqliteError::Custom(format!(
+      "table {table} has {} values for {} columns",
+      query.columns.len(),
+      names.len()
+    )));
+  }
+  let names = match names.is_empty() {
+    true => &query.columns,
+    false => names,
+  };
+  let mut columns: Vec<SourceColumn> = vec![];
+  let mut count = 0;
+  for (name, expr) in names.iter().zip(&query.exprs) {
+    let mut unique = name.clone();
+    while columns.iter().any(|c| c.name.eq_ignore_ascii_case(&unique)) {
+      let base = match unique.trim_end_matches(|c: char| c.is_ascii_digit()) {
+        base if base.len() > 1 && base.ends_with(':') => {
+          &base[..base.len() - 1]
+        }
+        _ => &unique,
+      };
+      count += 1;
+      unique = format!("{base}:{count}");
+    }
+    columns.push(SourceColumn {
+      name: unique,
+      affinity: expr.affinity().unwrap_or(Affinity::Blob),
+      collation: expr.collation().map(|(c, _)| c).unwrap_or_default(),
+      merged: false,
+      fallbacks: vec![],
+      hidden: false,
+    });
+  }
+  Ok(columns)
+}
+
+/// The pragma the table-valued function `name` reads, named after it with
+/// the prefix `pragma_`.
+fn pragma_function(name: &QualifiedName) -> Option<&'static Pragma> {
+  let function = name.name.value.to_ascii_lowercase();
+  function
+    .strip_prefix("pragma_")
+    .and_then(Pragma::find)
+    .filter(|pragma| pragma.returns_rows())
+}
+
+/// A table of the `FROM` clause that is not a table of the database, and
+/// has no rowid.
+pub(super) fn derived_source(
+  name: &str,
+  columns: Vec<SourceColumn>,
+  offset: usize,
+) -> Source {
+  Source {
+    name: name.into(),
+    width: columns.len(),
+    columns,
+    offset,
+    rowid: None,
+    json: false,
+  }
+}
+
+/// The expression of the only result column of a subquery, whose values
+/// are compared to others.
+fn single_column(query: &Query) -> SqliteResult<Expr> {
+  match query.exprs.as_slice() {
+    [expr] => Ok(expr.clone()),
+    exprs => Err(sub_select_columns(exprs.len())),
+  }
+}
+
+/// Keeps the rows of `input` for which every term is true.
+fn filtered(input: Box<dyn Operator>, terms: Vec<Expr>) -> Box<dyn Operator> {
+  match terms.into_iter().reduce(and) {
+    Some(condition) => Box::new(Filter::new(input, condition)),
+    None => input,
+  }
+}
+
+fn and(left: Expr, right: Expr) -> Expr {
+  Expr::And(Box::new(left), Box::new(right))
+}
+
+/// Splits a condition into the terms joined by its `AND` operators.
+fn conjuncts(expr: Expr, terms: &mut Vec<Expr>) {
+  match expr {
+    Expr::And(left, right) => {
+      conjuncts(*left, terms);
+      conjuncts(*right, terms);
+    }
+    expr => terms.push(expr),
+  }
+}
+
+/// The number of rows of a table assumed when `sqlite_stat1` does not tell,
+/// as SQLite assumes.
+const DEFAULT_ROWS: f64 = 1_048_576.0;
+
+/// A term of a join condition comparing a column of the table being joined
+/// to a value computed from the tables on its left, or to each value of an
+/// `IN` list.
+#[derive(Debug)]
+struct Constraint<'e> {
+  column: usize,
+  /// How the column compares to the value, `Eq`, `Lt`, `LtEq`, `Gt` or
+  /// `GtEq`. The column equals one of the values of an `IN` list.
+  operator: Comparison,
+  values: &'e [Expr],
+  comparator: &'e Comparator,
+}
+
+impl Constraint<'_> {
+  fn probe(&self) -> Probe {
+    Probe {
+      exprs: self.values.to_vec(),
+      affinity: self.comparator.affinity(),
+    }
+  }
+}
+
+/// The order a query wants the rows of its first table in, which reading
+/// them in that order, or backwards, gives without sorting them.
+#[derive(Debug, Default)]
+struct Order {
+  /// The sort keys, over the values of the joined rows.
+  keys: Vec<SortKey>,
+  /// Set when only the first row is wanted, by `min()` or `max()`.
+  first: bool,
+}
+
+impl Order {
+  /// Whether the rows `access` finds in the table of `source` come in this
+  /// order: `Some(false)` when read forwards, `Some(true)` when read
+  /// backwards, `None` when they must be sorted. The columns an index
+  /// search gives a single value do not change the order.
+  fn given_by(
+    &self,
+    access: &Access,
+    definition: &TableDefinition,
+    source: &Source,
+  ) -> Option<bool> {
+    if self.keys.is_empty() {
+      return Some(false);
+    }
+    // The values the rows are sorted by, with whether in descending order
+    // and their collating sequence, `None` for the rowid, which is never
+    // NULL and compares the same in any.
+    let rowid = source.rowid.map(|rowid| (rowid, false, None));
+    let primary_key = definition
+      .primary_key()
+      .iter()
+      .map(|key| (key.column(), key.is_descending(), Some(key.collation())));
+    let mut constant = vec![];
+    let sorted = match access {
+      Access::Scan if definition.is_without_rowid() => primary_key.collect(),
+      Access::Scan | Access::RowidRange(_) => rowid.into_iter().collect(),
+      // A single row is in any order.
+      Access::Rowid(probe) if probe.exprs.len() < 2 => return Some(false),
+      Access::Rowid(_) => rowid.into_iter().collect(),
+      Access::Index {
+        columns,
+        key_info,
+        probes,
+        target,
+        ..
+      } => {
+        for (&column, probe) in columns.iter().zip(probes) {
+          if probe.exprs.len() == 1 {
+            constant.push(column);
+          }
+        }
+        let keys = columns.iter().zip(key_info.columns());
+        let mut sorted = keys
+          .map(|(&column, key)| (column, key.descending, Some(&key.collation)))
+          .collect::<Vec<_>>();
+        match target {
+          IndexTarget::Rowid => sorted.extend(rowid),
+          IndexTarget::PrimaryKey(_) => sorted.extend(primary_key),
+          IndexTarget::Table => {}
+        }
+        sorted
+      }
+      Access::Automatic(_) => return None,
+    };
+    let mut sorted = sorted
+      .into_iter()
+      .filter(|(column, ..)| !constant.contains(column));
+    let mut reverse = None;
+    for key in self.keys.iter() {
+      let column = key.column.checked_sub(source.offset)?;
+      if constant.contains(&column) {
+        continue;
+      }
+      let (sorted_column, descending, collation) = sorted.next()?;
+      let backwards = descending != key.descending;
+      // NULLs come first in an ascending order, read forwards.
+      let fits = match collation {
+        Some(collation) => {
+          *collation == key.collation && key.nulls_first != key.descending
+        }
+        None => true,
+      };
+      if sorted_column != column
+        || !fits
+        || *reverse.get_or_insert(backwards) != backwards
+      {
+        return None;
+      }
+    }
+    Some(reverse.unwrap_or_default())
+  }
+
+  /// The cost of finding `found` rows of a table of `rows` for `cost`, with
+  /// that of sorting them when they are not `given` in order. With only the
+  /// first row wanted, the rows in order cost a search and the share of the
+  /// table a row is, and the others need no sort.
+  fn cost(&self, cost: f64, found: f64, rows: f64, given: Option<bool>) -> f64 {
+    match given {
+      _ if self.keys.is_empty() => cost,
+      Some(_) if self.first => rows.max(2.0).log2() + cost / rows.max(1.0),
+      None if !self.first => found.mul_add(found.max(2.0).log2(), cost),
+      _ => cost,
+    }
+  }
+}
+
+/// The order of the rows of the first table that gives the sort `keys`
+/// over the values of `exprs`, if these are all its columns.
+fn column_order<'k>(
+  keys: impl Iterator<Item = (&'k Expr, &'k SortKey)>,
+) -> Order {
+  let keys = keys
+    .map(|(expr, key)| match uncollated(expr) {
+      Expr::Column {
+        depth: 0, index, ..
+      } => Some(SortKey {
+        column: *index,
+        ..key.clone()
+      }),
+      _ => None,
+    })
+    .collect::<Option<_>>();
+  Order {
+    keys: keys.unwrap_or_default(),
+    first: false,
+  }
+}
+
+/// The order that gives first the row holding the value `min()` or `max()`
+/// returns, for a query reading a single table that only calls one of
+/// them, with a column as argument.
+fn extreme_order(calls: &[AggregateCall], tables: &[FromTable<'_>]) -> Order {
+  let ([call], [table]) = (calls, tables) else {
+    return Order::default();
+  };
+  let descending = match call.function {
+    AggregateFunction::Min => false,
+    AggregateFunction::Max => true,
+    _ => return Order::default(),
+  };
+  match &call.arguments[..] {
+    [Expr::Column {
+      depth: 0, index, ..
+    }] if call.filter.is_none()
+      && call.order_by.is_empty()
+      && matches!(table.input, Input::Table(_)) =>
+    {
+      let collation = call.collation.clone();
+      Order {
+        keys: vec![SortKey::new(*index, descending, None, collation)],
+        first: true,
+      }
+    }
+    _ => Order::default(),
+  }
+}
+
+/// The terms of `terms` that constrain the values of a column of `source`,
+/// `BETWEEN` giving two.
+fn constraints<'e>(source: &Source, terms: &'e [Expr]) -> Vec<Constraint<'e>> {
+  // The column of `source` that `column` is, when `value` is computed from
+  // the tables on its left.
+  let constrained = |column: &Expr, value: &Expr| {
+    let mut is_bound = true;
+    value.visit_columns(&mut |index| is_bound &= index < source.offset);
+    let column = match column {
+      Expr::Collate { expr, .. } => expr.as_ref(),
+      column => column,
+    };
+    let values = source.offset..source.offset + source.width;
+    match column {
+      Expr::Column {
+        depth: 0, index, ..
+      } if is_bound && values.contains(index) => Some(index - source.offset),
+      _ => None,
+    }
+  };
+  let mut constraints = vec![];
+  for term in terms {
+    match term {
+      Expr::Compare {
+        operator,
+        left,
+        right,
+        comparator,
+      } => {
+        let reversed = match operator {
+          Comparison::Lt => Comparison::Gt,
+          Comparison::LtEq => Comparison::GtEq,
+          Comparison::Gt => Comparison::Lt,
+          Comparison::GtEq => Comparison::LtEq,
+          Comparison::Eq => Comparison::Eq,
+          _ => continue,
+        };
+        for (column, value, operator) in
+          [(left, right, *operator), (right, left, reversed)]
+        {
+          if let Some(column) = constrained(column, value) {
+            constraints.push(Constraint {
+              column,
+              operator,
+              values: slice::from_ref(value),
+              comparator,
+            });
+          }
+        }
+      }
+      Expr::Between {
+        expr,
+        not: false,
+        low,
+        high,
+        low_comparator,
+        high_comparator,
+      } => {
+        let bounds = [
+          (low, Comparison::GtEq, low_comparator),
+          (high, Comparison::LtEq, high_comparator),
+        ];
+        for (value, operator, comparator) in bounds {
+          if let Some(column) = constrained(expr, value) {
+            constraints.push(Constraint {
+              column,
+              operator,
+              values: slice::from_ref(value),
+              comparator,
+            });
+          }
+        }
+      }
+      Expr::InList {
+        expr,
+        not: false,
+        list,
+        comparator,
+      } => {
+        let mut columns = list.iter().map(|item| constrained(expr, item));
+        if let Some(Some(column)) = columns.next() {
+          if columns.all(|other| other == Some(column)) {
+            constraints.push(Constraint {
+              column,
+              operator: Comparison::Eq,
+              values: list,
+              comparator,
+            });
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  constraints
+}
+
+/// The columns of `source` that a term of `terms` says are not NULL, with
+/// `IS NOT NULL` or `NOTNULL`.
+fn not_null(source: &Source, terms: &[Expr]) -> Vec<usize> {
+  let values = source.offset..source.offset + source.width;
+  let null = Expr::Literal(Value::Null);
+  terms
+    .iter()
+    .filter_map(|term| match term {
+      Expr::IsNull { expr, not: true } => Some(expr.as_ref()),
+      Expr::Compare {
+        operator: Comparison::IsNot,
+        left,
+        right,
+        ..
+      } if **right == null => Some(left.as_ref()),
+      _ => None,
+    })
+    .filter_map(|expr| match uncollated(expr) {
+      Expr::Column {
+        depth: 0, index, ..
+      } if values.contains(index) => Some(index - source.offset),
+      _ => None,
+    })
+    .collect()
+}
+
+/// The constraint of `constraints` that `fits` giving the fewest values
+/// `column` equals, if any.
+fn equality<'c, 'e>(
+  constraints: &'c [Constraint<'e>],
+  column: usize,
+  fits: impl Fn(&Constraint<'_>) -> bool,
+) -> Option<&'c Constraint<'e>> {
+  constraints
+    .iter()
+    .filter(|constraint| {
+      constraint.column == column
+        && constraint.operator == Comparison::Eq
+        && fits(constraint)
+    })
+    .min_by_key(|constraint| constraint.values.len())
+}
+
+/// The range of the values of `column` the `constraints` that `fits` give.
+fn range(
+  constraints: &[Constraint<'_>],
+  column: usize,
+  fits: impl Fn(&Constraint<'_>) -> bool,
+) -> Range {
+  let bound = |operators: [Comparison; 2]| {
+    let constraint = constraints.iter().find(|constraint| {
+      constraint.column == column
+        && operators.contains(&constraint.operator)
+        && fits(constraint)
+    })?;
+    Some(RangeBound {
+      probe: constraint.probe(),
+      inclusive: operators[1] == constraint.operator,
+    })
+  };
+  Range {
+    low: bound([Comparison::Gt, Comparison::GtEq]),
+    high: bound([Comparison::Lt, Comparison::LtEq]),
+    not_null: false,
+  }
+}
+
+/// The share of the values of a column within a range, each bound being
+/// guessed to leave a quarter of them, as SQLite guesses. Leaving out NULLs
+/// counts as a low bound.
+fn selectivity(range: &Range) -> f64 {
+  let low = range.low.is_some() || range.not_null;
+  let bounds = usize::from(low) + usize::from(range.high.is_some());
+  0.25_f64.powi(bounds as i32)
+}
+
+/// How the rows of `table` are found, given the `terms` it is joined with,
+/// the positions among its values of those the query reads, all of them
+/// when `None`, the estimated number of rows of the tables on its left,
+/// for each of which its rows are looked up, and the `order` the query
+/// wants them in. The least costly way is chosen among a scan, a look up by
+/// rowid or by a range of rowids, a search of the index whose leading
+/// columns the terms give values, or a range of values, a scan of an index
+/// holding every value the query reads or giving the rows in order, and an
+/// index built for the query. The ways that do not give the rows in order
+/// cost sorting them too. Also returns the estimated number of rows found,
+/// and whether they are read backwards to be in order.
+///
+/// *Reference:* https://www.sqlite.org/optoverview.html
+fn access(
+  table: &Table,
+  source: &Source,
+  terms: &[Expr],
+  used: Option<&[usize]>,
+  outer: f64,
+  order: &Order,
+) -> (Access, f64, bool) {
+  let constraints = constraints(source, terms);
+  let not_null = not_null(source, terms);
+  let rows = table.rows;
+  // The cost of finding an entry of a b-tree.
+  let search = rows.max(2.0).log2();
+  let definition = table.cursor.definition();
+  if let Some(rowid) = source.rowid {
+    let equal = equality(&constraints, rowid, |_| true);
+    if let Some(constraint) = equal {
+      let found = constraint.values.len() as f64;
+      let access = Access::Rowid(constraint.probe());
+      let reverse = order.given_by(&access, definition, source);
+      return (access, found, reverse.unwrap_or_default());
+    }
+  }
+  let scan_order = order.given_by(&Access::Scan, definition, source);
+  let mut best = (Access::Scan, rows, order.cost(rows, rows, rows, scan_order));
+  let mut reverse = scan_order.unwrap_or_default();
+  let mut consider = |access: Access, found: f64, cost: f64| {
+    let given = order.given_by(&access, definition, source);
+    let cost = order.cost(cost, found, rows, given);
+    if cost < best.2 {
+      best = (access, found, cost);
+      reverse = given.unwrap_or_default();
+    }
+  };
+  if let Some(rowid) = source.rowid {
+    let range = range(&constraints, rowid, |_| true);
+    if !range.is_empty() {
+      let found = rows * selectivity(&range);
+      consider(Access::RowidRange(range), found, search + found);
+    }
+  }
+  for index in table.indexes.iter() {
+    // Whether a constraint of `column` looks its values up the way the
+    // index compares them.
+    let fits = |column: usize, key: &KeyColumn| {
+      let affinity = source.columns[column].affinity;
+      let key = key.clone();
+      move |constraint: &Constraint<'_>| {
+        is_indexable(constraint.comparator, affinity, &key)
+      }
+    };
+    let mut probes = vec![];
+    // The number of keys looked up, one for each combination of the values
+    // of `IN` lists.
+    let mut lookups = 1.0;
+    for (&column, key) in index.columns.iter().zip(index.key_info.columns()) {
+      match equality(&constraints, column, fits(column, key)) {
+        Some(constraint) => {
+          lookups *= constraint.values.len() as f64;
+          probes.push(constraint.probe());
+        }
+        None => break,
+      }
+    }
+    let next = index.columns.get(probes.len());
+    let range = match next.zip(index.key_info.columns().get(probes.len())) {
+      Some((&column, key)) => Range {
+        not_null: not_null.contains(&column),
+        ..range(&constraints, column, fits(column, key))
+      },
+      None => Range::default(),
+    };
+    let covering = index.target != IndexTarget::Table
+      && used.is_some_and(|used| {
+        used.iter().all(|&position| {
+          index.columns.contains(&position)
+            || match &index.target {
+              IndexTarget::Rowid => {
+                Some(position) == source.rowid || position == source.width - 1
+              }
+              _ => definition
+                .primary_key()
+                .iter()
+                .any(|k| k.column() == position),
+            }
+        })
+      });
+    let found = index.rows.get(probes.len()).copied().unwrap_or(1.0)
+      * selectivity(&range)
+      * lookups;
+    let full_scan = probes.is_empty() && range.is_empty();
+    let access = Access::Index {
+      cursor: BtreeCursor::new(index.root),
+      name: index.name.clone(),
+      columns: index.columns.clone(),
+      key_info: index.key_info.clone(),
+      probes,
+      range,
+      target: index.target.clone(),
+      covering,
+    };
+    let cost = match full_scan {
+      // A scan of the whole index only pays when it holds fewer values
+      // than the table, or gives the rows in order.
+      true
+        if covering
+          && index.key_info.columns().len() < source.columns.len() =>
+      {
+        let width = index.key_info.columns().len() + 1;
+        rows * width as f64 / source.width as f64
+      }
+      true if order.given_by(&access, definition, source).is_some() => {
+        match covering || index.target == IndexTarget::Table {
+          true => rows,
+          false => rows * 2.0,
+        }
+      }
+      true => continue,
+      false => match covering || index.target == IndexTarget::Table {
+        true => search * lookups + found,
+        false => search * lookups + found * 2.0,
+      },
+    };
+    consider(access, found, cost);
+  }
+  // An index built from every row pays when it is searched for enough rows
+  // of the tables on the left.
+  let mut columns = vec![];
+  let mut probes = vec![];
+  let mut keys = vec![];
+  for constraint in constraints.iter() {
+    let column = constraint.column;
+    let key = KeyColumn {
+      descending: false,
+      collation: source
+        .columns
+        .get(column)
+        .map_or(Collation::Binary, |c| c.collation.clone()),
+    };
+    let affinity = source.columns.get(column).map(|c| c.affinity);
+    let fits = affinity.is_some_and(|affinity| {
+      is_indexable(constraint.comparator, affinity, &key)
+    });
+    if constraint.operator == Comparison::Eq
+      && constraint.values.len() == 1
+      && fits
+      && !columns.contains(&column)
+    {
+      columns.push(column);
+      probes.push(constraint.probe());
+      keys.push(key);
+    }
+  }
+  if !columns.is_empty() {
+    let found = 10.0_f64.min(rows);
+    let cost = rows * search / outer + search + found;
+    let mut kept = match used {
+      Some(used) => used.to_vec(),
+      None => (0..source.width).collect(),
+    };
+    kept.extend(columns.iter().copied());
+    let index = AutomaticIndex::new(columns, KeyInfo::new(keys), probes, kept);
+    consider(Access::Automatic(index), found, cost);
+  }
+  (best.0, best.1, reverse)
+}
+
+/// Whether the values an index holds for a column of the given `affinity`
+/// compare as `comparator` compares them to other values, which makes the
+/// index usable to look them up.
+fn is_indexable(
+  comparator: &Comparator,
+  affinity: Affinity,
+  key: &KeyColumn,
+) -> bool {
+  let same_affinity = match comparator.affinity() {
+    None | Some(Affinity::Blob) => true,
+    Some(Affinity::Text) => affinity == Affinity::Text,
+    Some(_) => affinity.is_numeric(),
+  };
+  same_affinity && *comparator.collation() == key.collation
+}
+
+/// A table column, with its order and collating sequence in an index.
+type IndexColumn = (usize, KeyColumn);
+
+/// The table column and the order and collating sequence of each indexed
+/// value of an index of `definition`, `None` for those of expressions, and
+/// whether the index is unique. `None` for partial indexes.
+fn index_columns(
+  runtime: &SqliteRuntime,
+  sql: &str,
+  definition: &TableDefinition,
+) -> SqliteResult<Option<(Vec<Option<IndexColumn>>, bool)>> {
+  let statement = Parser::new(sql)?.next_statement()?;
+  let Some(StatementKind::CreateIndex(index)) =
+    statement.map(|statement| statement.kind)
+  else {
+    return Err(SqliteError::Corrupt(format!(
+      "Malformed CREATE INDEX statement: {sql}"
+    )));
+  };
+  if index.where_clause.is_some() {
+    return Ok(None);
+  }
+  let mut columns = vec![];
+  for indexed in index.columns.iter() {
+    let (expr, collation) = match &indexed.expr.kind {
+      ExprKind::Collate { expr, collation } => {
+        (expr.as_ref(), Some(collation_named(runtime, collation)?))
+      }
+      _ => (&indexed.expr, None),
+    };
+    let column = match &expr.kind {
+      ExprKind::Column {
+        schema: None,
+        table: None,
+        column,
+      } => definition.column_index(&column.value),
+      _ => None,
+    };
+    columns.push(column.map(|column| {
+      let key = KeyColumn {
+        descending: indexed.order == Some(SortOrder::Desc),
+        collation: collation
+          .unwrap_or_else(|| definition.columns()[column].collation().clone()),
+      };
+      (column, key)
+    }));
+  }
+  Ok(Some((columns, index.unique)))
+}
+
+/// The source of the rows of the table of `definition`, as seen under
+/// `name`: its columns followed by its rowid.
+pub(super) fn table_source(
+  definition: &TableDefinition,
+  name: &str,
+  offset: usize,
+) -> Source {
+  let columns: Vec<SourceColumn> = definition
+    .columns()
+    .iter()
+    .map(|column| SourceColumn {
+      name: column.name().into(),
+      affinity: column.affinity(),
+      collation: column.collation().clone(),
+      merged: false,
+      fallbacks: vec![],
+      hidden: false,
+    })
+    .collect();
+  let rowid = match definition.is_without_rowid() {
+    true => None,
+    false => Some(definition.rowid_alias().unwrap_or(columns.len())),
+  };
+  Source {
+    name: name.into(),
+    width: columns.len() + 1,
+    columns,
+    offset,
+    rowid,
+    json: false,
+  }
+}
+
+/// `expr` without the `COLLATE` clauses around it.
+pub(super) fn uncollated(mut expr: &Expr) -> &Expr {
+  while let Expr::Collate { expr: inner, .. } = expr {
+    expr = inner;
+  }
+  expr
+}
+
+/// Fails when `expr` holds a subquery or a parameter, which `place` may not
+/// have.
+pub(super) fn prohibit_subqueries(
+  expr: &ast::Expr,
+  place: &str,
+) -> SqliteResult<()> {
+  let mut prohibited = None;
+  expr.walk(&mut |expr| match &expr.kind {
+    ExprKind::Exists(_)
+    | ExprKind::Subquery(_)
+    | ExprKind::In {
+      target: InTarget::Select(_),
+      ..
+    } => prohibited = prohibited.or(Some("subqueries")),
+    ExprKind::Variable(_) => prohibited = prohibited.or(Some("parameters")),
+    _ => {}
+  });
+  match prohibited {
+    Some(what) => {
+      Err(SqliteError::Custom(format!("{what} prohibited in {place}")))
+    }
+    None => Ok(()),
+  }
+}
+
+pub(super) fn no_such_table(name: &QualifiedName) -> SqliteError {
+  SqliteError::Custom(format!("no such table: {}", name.name.value))
+}
+
+pub(super) fn malformed_table(sql: &str) -> SqliteError {
+  SqliteError::Corrupt(format!("Malformed CREATE TABLE statement: {sql}"))
+}
+
+/// The column at `idx` of `source`. Referred to by its name alone, a column
+/// right or full joins merged others into is the first of them not NULL.
+fn source_column(source: &Source, idx: usize, unqualified: bool) -> Expr {
+  let column = &source.columns[idx];
+  let expr = Expr::Column {
+    depth: 0,
+    index: source.offset + idx,
+    affinity: column.affinity,
+    collation: column.collation.clone(),
+  };
+  match unqualified {
+    true => coalesce(expr, &column.fallbacks),
+    false => expr,
+  }
+}
+
+/// The first of `expr` and `fallbacks` that is not NULL.
+fn coalesce(expr: Expr, fallbacks: &[Expr]) -> Expr {
+  let Some((next, rest)) = fallbacks.split_first() else {
+    return expr;
+  };
+  Expr::Case {
+    operand: None,
+    when_then: vec![(
+      Expr::IsNull {
+        expr: Box::new(expr.clone()),
+        not: true,
+      },
+      expr,
+    )],
+    else_expr: Some(Box::new(coalesce(next.clone(), rest))),
+  }
+}
+
+fn compare(operator: Comparison, left: Box<Expr>, right: Box<Expr>) -> Expr {
+  Expr::Compare {
+    operator,
+    comparator: Comparator::new(&left, &right),
+    left,
+    right,
+  }
+}
+
+/// Adds every column of `source` to the result columns. For `*`, the
+/// columns merged into others by a join, and hidden ones, are left out.
+fn expand_source(source: &Source, star: bool, result: &mut ResultColumns) {
+  for (idx, column) in source.columns.iter().enumerate() {
+    if star && (column.merged || column.hidden) {
+      continue;
+    }
+    let expr = source_column(source, idx, star);
+    result.push(expr, column.name.clone(), false);
+  }
+}
+
+/// The collating sequence `name`, built in or registered.
+fn collation_named(
+  runtime: &SqliteRuntime,
+  name: &Name,
+) -> SqliteResult<Collation> {
+  runtime
+    .collation(&name.value)
+    .ok_or(SqliteError::Custom(format!(
+      "no such collation sequence: {}",
+      name.value
+    )))
+}
+
+/// The value of an integer literal, possibly negated.
+fn integer_constant(expr: &ast::Expr) -> Option<i64> {
+  match &expr.kind {
+    ExprKind::Literal(Literal::Integer(int)) => Some(*int),
+    ExprKind::Unary {
+      operator: UnaryOperator::Negate,
+      expr,
+    } => integer_constant(expr).and_then(i64::checked_neg),
+    _ => None,
+  }
+}
+
+/// `1st`, `2nd`, `3rd`, `4th`...
+fn ordinal(number: usize) -> String {
+  let suffix = match (number % 10, number % 100) {
+    (_, 11..=13) => "th",
+    (1, _) => "st",
+    (2, _) => "nd",
+    (3, _) => "rd",
+    _ => "th",
+  };
+  format!("{number}{suffix}")
+}
+
+fn ambiguous(table: Option<&Name>, column: &Name) -> SqliteError {
+  let name = match table {
+    Some(table) => format!("{}.{}", table.value, column.value),
+    None => column.value.clone(),
+  };
+  SqliteError::Custom(format!("ambiguous column name: {name}"))
+}
+
+fn sub_select_columns(count: usize) -> SqliteError {
+  SqliteError::Custom(format!(
+    "sub-select returns {count} columns - expected 1"
+  ))
+}
+
+fn different_widths(operator: CompoundOperator) -> SqliteError {
+  let operator = match operator {
+    CompoundOperator::Union => "UNION",
+    CompoundOperator::UnionAll => "UNION ALL",
+    CompoundOperator::Intersect => "INTERSECT",
+    CompoundOperator::Except => "EXCEPT",
+  };
+  SqliteError::Custom(format!(
+    "SELECTs to the left and right of {operator} do not have the same \
+     number of result columns"
+  ))
+}
+
+fn aggregate_in_group_by() -> SqliteError {
+  SqliteError::Custom(
+    "aggregate functions are not allowed in the GROUP BY clause".into(),
+  )
+}
+
+fn misuse_of_window_function(name: &str) -> SqliteError {
+  SqliteError::Custom(format!("misuse of window function {name}()"))
+}
+
+/// Whether `name` is that of a built-in function.
+fn is_builtin(name: &str) -> bool {
+  is_scalar_function(name)
+    || AggregateFunction::from_name(name).is_some()
+    || WindowFunction::from_name(name).is_some()
+}
+
+/// Whether `name` is that of a scalar function, `iif()` included.
+fn is_scalar_function(name: &str) -> bool {
+  ScalarFunction::from_name(name).is_some() || name.eq_ignore_ascii_case("iif")
+}
+
+fn no_such_function(name: &str) -> SqliteError {
+  SqliteError::Custom(format!("no such function: {name}"))
+}
+
+pub(crate) fn unsupported(feature: &str) -> SqliteError {
+  SqliteError::Custom(format!("{feature} is not supported"))
+}
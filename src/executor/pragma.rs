@@ -0,0 +1,1044 @@
+//! # Pragmas
+//!
+//!  `PRAGMA` statements read and change the settings of a database, most of
+//! them kept in its header, and describe the objects of its schema. Those
+//! returning rows can also be read as the table-valued functions named after
+//! them, prefixed with `pragma_`, whose hidden `arg` and `schema` columns
+//! are the argument of the pragma and the schema it reads:
+//! `SELECT name FROM pragma_table_info('t')`.
+//!
+//!  As in SQLite, an unknown pragma is no error, and returns no rows.
+//!
+//! *Reference:* https://www.sqlite.org/pragma.html
+
+use super::aggregate::AggregateFunction;
+use super::ddl::{check_schema, find, parse_object, parse_table};
+use super::expr::Expr;
+use super::function::ScalarFunction;
+use super::operator::{Operator, Values};
+use super::planner::{Plan, Planner};
+use super::query_plan::QueryPlan;
+use super::transaction::{self, Undo};
+use super::window::WindowFunction;
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  to_integer, to_text, AutoVacuum, SqliteSchema, TableDefinition, Value,
+};
+use crate::sql::ast::{
+  self, ColumnConstraintKind, ExprKind, ForeignKeyAction, IndexedColumn,
+  Literal, Name, QualifiedName, SortOrder, Span, StatementKind,
+  TableConstraintKind, UnaryOperator,
+};
+use crate::SqliteConnection;
+use core::ops::RangeInclusive;
+
+/// The flag of deterministic functions in `function_list`.
+const DETERMINISTIC: i64 = 0x800;
+/// The flag of built-in functions, which are safe to call from the schema.
+const INNOCUOUS: i64 = 0x20_0000;
+
+/// The built-in functions, listed by `function_list`.
+const BUILTIN_FUNCTIONS: [&str; 91] = [
+  "abs",
+  "avg",
+  "changes",
+  "char",
+  "coalesce",
+  "count",
+  "cume_dist",
+  "date",
+  "datetime",
+  "dense_rank",
+  "first_value",
+  "format",
+  "glob",
+  "group_concat",
+  "hex",
+  "ifnull",
+  "iif",
+  "instr",
+  "json",
+  "json_array",
+  "json_array_length",
+  "json_error_position",
+  "json_extract",
+  "json_group_array",
+  "json_group_object",
+  "json_insert",
+  "json_object",
+  "json_patch",
+  "json_pretty",
+  "json_quote",
+  "json_remove",
+  "json_replace",
+  "json_set",
+  "json_type",
+  "json_valid",
+  "jsonb",
+  "jsonb_array",
+  "jsonb_extract",
+  "jsonb_group_array",
+  "jsonb_group_object",
+  "jsonb_insert",
+  "jsonb_object",
+  "jsonb_patch",
+  "jsonb_remove",
+  "jsonb_replace",
+  "jsonb_set",
+  "julianday",
+  "lag",
+  "last_insert_rowid",
+  "last_value",
+  "lead",
+  "length",
+  "like",
+  "likely",
+  "lower",
+  "ltrim",
+  "max",
+  "min",
+  "nth_value",
+  "ntile",
+  "nullif",
+  "octet_length",
+  "percent_rank",
+  "printf",
+  "quote",
+  "random",
+  "randomblob",
+  "rank",
+  "replace",
+  "round",
+  "row_number",
+  "rtrim",
+  "sqlite_source_id",
+  "sqlite_version",
+  "strftime",
+  "string_agg",
+  "substr",
+  "substring",
+  "sum",
+  "time",
+  "timediff",
+  "total",
+  "total_changes",
+  "trim",
+  "typeof",
+  "unhex",
+  "unicode",
+  "unixepoch",
+  "unlikely",
+  "upper",
+  "zeroblob",
+];
+
+/// A pragma, and the rows it returns.
+#[derive(Debug)]
+pub(crate) struct Pragma {
+  name: &'static str,
+  kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+  /// A setting, returned in a column named after the pragma, which
+  /// `PRAGMA name = value` changes unless it is read-only.
+  Setting,
+  /// Rows with `columns`, describing the schema object its argument names
+  /// when it takes one.
+  List {
+    columns: &'static [&'static str],
+    argument: bool,
+  },
+  /// A command, which returns no rows, and is not read as a table-valued
+  /// function.
+  Action,
+}
+
+const TABLE_INFO: [&str; 6] =
+  ["cid", "name", "type", "notnull", "dflt_value", "pk"];
+const TABLE_XINFO: [&str; 7] = [
+  "cid",
+  "name",
+  "type",
+  "notnull",
+  "dflt_value",
+  "pk",
+  "hidden",
+];
+
+const PRAGMAS: [Pragma; 21] = [
+  Pragma::setting("application_id"),
+  Pragma::setting("auto_vacuum"),
+  Pragma::setting("cache_size"),
+  Pragma::list("database_list", &["seq", "name", "file"], false),
+  Pragma::setting("encoding"),
+  Pragma::list(
+    "foreign_key_list",
+    &[
+      "id",
+      "seq",
+      "table",
+      "from",
+      "to",
+      "on_update",
+      "on_delete",
+      "match",
+    ],
+    true,
+  ),
+  Pragma::setting("foreign_keys"),
+  Pragma::setting("freelist_count"),
+  Pragma::list(
+    "function_list",
+    &["name", "builtin", "type", "enc", "narg", "flags"],
+    false,
+  ),
+  Pragma::action("incremental_vacuum"),
+  Pragma::list("index_info", &["seqno", "cid", "name"], true),
+  Pragma::list(
+    "index_list",
+    &["seq", "name", "unique", "origin", "partial"],
+    true,
+  ),
+  Pragma::list(
+    "index_xinfo",
+    &["seqno", "cid", "name", "desc", "coll", "key"],
+    true,
+  ),
+  Pragma::setting("journal_mode"),
+  Pragma::setting("page_count"),
+  Pragma::setting("page_size"),
+  Pragma::setting("recursive_triggers"),
+  Pragma::setting("schema_version"),
+  Pragma::list("table_info", &TABLE_INFO, true),
+  Pragma::list("table_xinfo", &TABLE_XINFO, true),
+  Pragma::setting("user_version"),
+];
+
+impl Pragma {
+  const fn setting(name: &'static str) -> Self {
+    Self {
+      name,
+      kind: Kind::Setting,
+    }
+  }
+
+  const fn list(
+    name: &'static str,
+    columns: &'static [&'static str],
+    argument: bool,
+  ) -> Self {
+    Self {
+      name,
+      kind: Kind::List { columns, argument },
+    }
+  }
+
+  const fn action(name: &'static str) -> Self {
+    Self {
+      name,
+      kind: Kind::Action,
+    }
+  }
+
+  /// The pragma named `name`, ignoring ASCII case.
+  pub(crate) fn find(name: &str) -> Option<&'static Self> {
+    PRAGMAS
+      .iter()
+      .find(|pragma| pragma.name.eq_ignore_ascii_case(name))
+  }
+
+  pub(crate) fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /// The columns of its rows.
+  pub(crate) fn columns(&self) -> Vec<String> {
+    match self.kind {
+      Kind::Setting => vec![self.name.into()],
+      Kind::List { columns, .. } => columns.iter().map(|&c| c.into()).collect(),
+      Kind::Action => vec![],
+    }
+  }
+
+  /// Whether it returns rows, which a table-valued function reads.
+  pub(crate) fn returns_rows(&self) -> bool {
+    !matches!(self.kind, Kind::Action)
+  }
+
+  /// Whether it takes the name of a schema object as its argument.
+  pub(crate) fn takes_argument(&self) -> bool {
+    matches!(self.kind, Kind::List { argument: true, .. })
+  }
+
+  /// Its rows, given its `argument`.
+  fn rows(
+    &self,
+    conn: &mut SqliteConnection,
+    argument: Option<&Value>,
+  ) -> SqliteResult<Vec<Vec<Value>>> {
+    let argument = match self.takes_argument() {
+      true => match argument.and_then(to_text) {
+        Some(argument) => argument,
+        None => return Ok(vec![]),
+      },
+      false => String::new(),
+    };
+    let header = conn.runtime.header();
+    let setting = match self.name {
+      "application_id" => Value::Integer(**header.application_id() as i32 as _),
+      "auto_vacuum" => {
+        Value::Integer(match conn.runtime.btree().auto_vacuum() {
+          AutoVacuum::None => 0,
+          AutoVacuum::Full => 1,
+          AutoVacuum::Incremental => 2,
+        })
+      }
+      "cache_size" => Value::Integer(conn.cache_size),
+      "encoding" => Value::Text(
+        match u32::from(header.database_text_encoding()) {
+          2 => "UTF-16le",
+          3 => "UTF-16be",
+          _ => "UTF-8",
+        }
+        .into(),
+      ),
+      "foreign_keys" => Value::Integer(conn.foreign_keys.into()),
+      "freelist_count" => {
+        Value::Integer(**header.freelist_pages().total() as _)
+      }
+      "journal_mode" => match conn.runtime.pager().io().path() {
+        Some(_) => Value::Text("delete".into()),
+        None => Value::Text("memory".into()),
+      },
+      "page_count" => Value::Integer(conn.runtime.pager().page_count().into()),
+      "page_size" => {
+        Value::Integer(u32::from(conn.runtime.pager().page_size()).into())
+      }
+      "recursive_triggers" => Value::Integer(conn.recursive_triggers.into()),
+      "schema_version" => Value::Integer(**header.schema_cookie() as i32 as _),
+      "user_version" => Value::Integer(**header.user_version() as i32 as _),
+      "database_list" => return Ok(database_list(conn)),
+      "foreign_key_list" => return foreign_key_list(conn, &argument),
+      "function_list" => return Ok(function_list(conn)),
+      "index_info" => return index_info(conn, &argument, false),
+      "index_list" => return index_list(conn, &argument),
+      "index_xinfo" => return index_info(conn, &argument, true),
+      "table_info" => return table_info(conn, &argument, false),
+      "table_xinfo" => return table_info(conn, &argument, true),
+      name => unreachable!("pragma {name} has no rows"),
+    };
+    Ok(vec![vec![setting]])
+  }
+
+  /// Changes its setting to `value`, and returns whether it then returns
+  /// its rows. The page size and text encoding of a database cannot be
+  /// changed, nor can auto-vacuum be turned on or off once a table is
+  /// created: setting them is ignored, like setting read-only values.
+  fn set(
+    &self,
+    conn: &mut SqliteConnection,
+    value: &Value,
+  ) -> SqliteResult<bool> {
+    transaction::begin_statement(conn);
+    let runtime = &mut conn.runtime;
+    let result = match self.name {
+      "application_id" => runtime.change_header(|header| {
+        header.set_application_id(to_integer(value) as u32)
+      }),
+      "auto_vacuum" => {
+        let mode = match to_text(value).unwrap_or_default().to_lowercase() {
+          mode if mode == "full" => AutoVacuum::Full,
+          mode if mode == "incremental" => AutoVacuum::Incremental,
+          _ => match to_integer(value) {
+            1 => AutoVacuum::Full,
+            2 => AutoVacuum::Incremental,
+            _ => AutoVacuum::None,
+          },
+        };
+        let current = runtime.btree().auto_vacuum();
+        // Turning it on or off rearranges the file, which is only done
+        // while it holds the schema table alone.
+        let is_layout_change =
+          (current == AutoVacuum::None) != (mode == AutoVacuum::None);
+        match is_layout_change && runtime.pager().page_count() > 1 {
+          true => Ok(()),
+          false => runtime.btree().set_auto_vacuum(mode),
+        }
+      }
+      "cache_size" => {
+        conn.cache_size = to_integer(value);
+        return Ok(false);
+      }
+      // It cannot change within a transaction.
+      "foreign_keys" if conn.in_transaction => return Ok(false),
+      "foreign_keys" => {
+        conn.foreign_keys = to_boolean(value);
+        return Ok(false);
+      }
+      "recursive_triggers" => {
+        conn.recursive_triggers = to_boolean(value);
+        return Ok(false);
+      }
+      "schema_version" => runtime.change_header(|header| {
+        header.set_schema_cookie(to_integer(value) as u32)
+      }),
+      "user_version" => runtime.change_header(|header| {
+        header.set_user_version(to_integer(value) as u32)
+      }),
+      "freelist_count" | "journal_mode" | "page_count" => return Ok(true),
+      _ => return Ok(false),
+    };
+    end_change(conn, result)?;
+    Ok(false)
+  }
+
+  /// Runs its command, given its `argument`.
+  fn run(
+    &self,
+    conn: &mut SqliteConnection,
+    argument: Option<&Value>,
+  ) -> SqliteResult<()> {
+    transaction::begin_statement(conn);
+    let result = match self.name {
+      "incremental_vacuum" => {
+        // Without a positive limit, the whole freelist is removed.
+        let limit = argument.map_or(0, to_integer);
+        let limit = u32::try_from(limit).ok().filter(|&limit| limit > 0);
+        conn.runtime.btree().incremental_vacuum(limit).map(|_| ())
+      }
+      name => unreachable!("pragma {name} is no command"),
+    };
+    end_change(conn, result)
+  }
+}
+
+/// Ends the change a pragma made, undoing it on `result` failing.
+fn end_change(
+  conn: &mut SqliteConnection,
+  result: SqliteResult<()>,
+) -> SqliteResult<()> {
+  let undo = match result {
+    Ok(()) => Undo::Nothing,
+    Err(_) => Undo::Statement,
+  };
+  transaction::end_statement(conn, undo)?;
+  result
+}
+
+/// Runs the statement `PRAGMA name`, `PRAGMA name = value` or `PRAGMA
+/// name(value)`, and plans returning its rows.
+pub(crate) fn plan(
+  conn: &mut SqliteConnection,
+  statement: &ast::Pragma,
+) -> SqliteResult<Plan> {
+  check_schema(&statement.name)?;
+  let Some(pragma) = Pragma::find(&statement.name.name.value) else {
+    return Ok(rows_plan(vec![], vec![]));
+  };
+  let value = statement.value.as_ref().map(value).transpose()?;
+  let returns_rows = match (&pragma.kind, &value) {
+    (Kind::Setting, Some(value)) => pragma.set(conn, value)?,
+    (Kind::Action, _) => {
+      pragma.run(conn, value.as_ref())?;
+      false
+    }
+    _ => true,
+  };
+  match returns_rows {
+    true => {
+      let rows = pragma.rows(conn, value.as_ref())?;
+      Ok(rows_plan(pragma.columns(), rows))
+    }
+    false => Ok(rows_plan(vec![], vec![])),
+  }
+}
+
+/// The value of a boolean pragma: `on`, `yes`, `true` or a number other
+/// than zero turn it on.
+fn to_boolean(value: &Value) -> bool {
+  match to_text(value).unwrap_or_default().to_lowercase().as_str() {
+    "on" | "yes" | "true" => true,
+    _ => to_integer(value) != 0,
+  }
+}
+
+fn rows_plan(columns: Vec<String>, rows: Vec<Vec<Value>>) -> Plan {
+  let rows = rows
+    .into_iter()
+    .map(|row| row.into_iter().map(Expr::Literal).collect())
+    .collect();
+  Plan {
+    root: Box::new(Values::new(rows)),
+    columns,
+    subqueries: vec![],
+  }
+}
+
+/// The value of a pragma: a signed number, a string, or a name, taken as
+/// text.
+fn value(expr: &ast::Expr) -> SqliteResult<Value> {
+  Ok(match &expr.kind {
+    ExprKind::Literal(Literal::Integer(int)) => Value::Integer(*int),
+    ExprKind::Literal(Literal::Real(real)) => Value::Real(*real),
+    ExprKind::Literal(Literal::String(text)) => Value::Text(text.clone()),
+    ExprKind::Column { column, .. } => Value::Text(column.value.clone()),
+    ExprKind::Unary {
+      operator: UnaryOperator::Plus,
+      expr,
+    } => value(expr)?,
+    ExprKind::Unary {
+      operator: UnaryOperator::Negate,
+      expr,
+    } => match value(expr)? {
+      Value::Integer(int) => Value::Integer(int.wrapping_neg()),
+      Value::Real(real) => Value::Real(-real),
+      value => value,
+    },
+    _ => return Err(SqliteError::Custom("syntax error in PRAGMA".into())),
+  })
+}
+
+/// The rows of a pragma read as the table-valued function `pragma_NAME()`,
+/// computed again for each row of the tables on its left, which its
+/// arguments may read. They hold the columns of the pragma followed by the
+/// hidden `arg` column, if it takes an argument, and `schema` column, which
+/// are the values of the arguments.
+#[derive(Debug)]
+pub(crate) struct PragmaTable {
+  pragma: &'static Pragma,
+  /// The name the query gives the function.
+  name: String,
+  arguments: Vec<Expr>,
+  rows: Vec<Vec<Value>>,
+  position: usize,
+  started: bool,
+}
+
+impl PragmaTable {
+  pub(crate) fn new(
+    pragma: &'static Pragma,
+    name: String,
+    arguments: Vec<Expr>,
+  ) -> Self {
+    Self {
+      pragma,
+      name,
+      arguments,
+      rows: vec![],
+      position: 0,
+      started: false,
+    }
+  }
+
+  /// Starts over, with the values the arguments evaluate to given `row`.
+  pub(crate) fn rewind(
+    &mut self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+  ) -> SqliteResult<()> {
+    let mut arguments = self
+      .arguments
+      .iter()
+      .map(|expr| expr.eval(ctx, row))
+      .collect::<SqliteResult<Vec<_>>>()?
+      .into_iter();
+    let argument = match self.pragma.takes_argument() {
+      true => Some(arguments.next().unwrap_or(Value::Null)),
+      false => None,
+    };
+    let schema = arguments.next().unwrap_or(Value::Null);
+    let is_main = match to_text(&schema) {
+      Some(schema) => schema.eq_ignore_ascii_case("main"),
+      None => true,
+    };
+    self.rows = match is_main {
+      true => self.pragma.rows(ctx.conn, argument.as_ref())?,
+      false => vec![],
+    };
+    for row in self.rows.iter_mut() {
+      row.extend(argument.clone());
+      row.push(schema.clone());
+    }
+    self.position = 0;
+    self.started = true;
+    Ok(())
+  }
+
+  /// The next row, once rewound.
+  pub(crate) fn next_row(&mut self) -> Option<Vec<Value>> {
+    let row = self.rows.get(self.position).cloned();
+    self.position += 1;
+    row
+  }
+}
+
+impl Operator for PragmaTable {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.started {
+      self.rewind(ctx, &[])?;
+    }
+    Ok(self.next_row())
+  }
+
+  fn reset(&mut self) {
+    self.started = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    plan.add(format!("SCAN {} VIRTUAL TABLE INDEX 0:", self.name));
+  }
+}
+
+fn database_list(conn: &SqliteConnection) -> Vec<Vec<Value>> {
+  let file = conn.runtime.pager().io().path();
+  let file = file.map_or(String::new(), |path| path.display().to_string());
+  vec![vec![
+    Value::Integer(0),
+    Value::Text("main".into()),
+    Value::Text(file),
+  ]]
+}
+
+/// A row for each overload of each built-in and registered function, by
+/// name.
+fn function_list(conn: &SqliteConnection) -> Vec<Vec<Value>> {
+  // The name, whether it is built in, the type, the number of arguments,
+  // `-1` for any, and the flags of each overload.
+  let mut overloads: Vec<(String, bool, &str, i64, i64)> = vec![];
+  let counts = |range: RangeInclusive<usize>| -> Vec<i64> {
+    match *range.end() == usize::MAX {
+      true => vec![-1],
+      false => range.map(|count| count as i64).collect(),
+    }
+  };
+  for name in BUILTIN_FUNCTIONS {
+    let scalar = match name {
+      "iif" => Some((3..=3, INNOCUOUS | DETERMINISTIC)),
+      _ => ScalarFunction::from_name(name).map(|(function, range)| {
+        let flags = match function.is_deterministic() {
+          true => INNOCUOUS | DETERMINISTIC,
+          false => INNOCUOUS,
+        };
+        (range, flags)
+      }),
+    };
+    if let Some((range, flags)) = scalar {
+      for count in counts(range) {
+        overloads.push((name.into(), true, "s", count, flags));
+      }
+    }
+    let aggregate =
+      AggregateFunction::from_name(name).map(|(_, counts)| counts);
+    let window = WindowFunction::from_name(name).map(|(_, counts)| counts);
+    // Every built-in aggregate function is a window function as well.
+    for &count in aggregate.into_iter().chain(window).flatten() {
+      overloads.push((name.into(), true, "w", count as i64, INNOCUOUS));
+    }
+  }
+  for function in conn.functions.iter() {
+    let kind = match (function.is_window(), function.is_aggregate()) {
+      (true, _) => "w",
+      (false, true) => "a",
+      (false, false) => "s",
+    };
+    let count = function.arity().map_or(-1, |arity| arity as i64);
+    let flags = match function.is_deterministic() {
+      true => DETERMINISTIC,
+      false => 0,
+    };
+    let name = function.name().to_ascii_lowercase();
+    overloads.push((name, false, kind, count, flags));
+  }
+  overloads.sort();
+  overloads
+    .into_iter()
+    .map(|(name, builtin, kind, count, flags)| {
+      vec![
+        Value::Text(name),
+        Value::Integer(builtin.into()),
+        Value::Text(kind.into()),
+        Value::Text("utf8".into()),
+        Value::Integer(count),
+        Value::Integer(flags),
+      ]
+    })
+    .collect()
+}
+
+/// The schema entry of the table `name`.
+fn table_entry(
+  conn: &mut SqliteConnection,
+  name: &str,
+) -> SqliteResult<Option<SqliteSchema>> {
+  let schema = conn.runtime.schema()?;
+  Ok(find(&schema, &["table"], name).cloned())
+}
+
+/// A row for each column of the table `name`. Generated columns are only
+/// listed by `table_xinfo`, whose `hidden` column is 2 for those computed
+/// when read and 3 for those stored.
+fn table_info(
+  conn: &mut SqliteConnection,
+  name: &str,
+  extended: bool,
+) -> SqliteResult<Vec<Vec<Value>>> {
+  let Some(entry) = table_entry(conn, name)? else {
+    return Ok(vec![]);
+  };
+  let sql = entry.sql().unwrap_or_default();
+  let definition = entry.table_definition()?;
+  let (_, declared, _, _) = parse_table(sql)?;
+  let mut rows = vec![];
+  for (idx, (column, declared)) in
+    definition.columns().iter().zip(&declared).enumerate()
+  {
+    let mut default = Value::Null;
+    let mut hidden = 0;
+    for constraint in declared.constraints.iter() {
+      match &constraint.kind {
+        ColumnConstraintKind::Default(expr) => {
+          default = Value::Text(default_text(sql, expr.span).into());
+        }
+        ColumnConstraintKind::Generated { stored, .. } => {
+          hidden = if *stored { 3 } else { 2 };
+        }
+        _ => {}
+      }
+    }
+    if hidden != 0 && !extended {
+      continue;
+    }
+    let key = definition
+      .primary_key()
+      .iter()
+      .position(|key| key.column() == idx);
+    // The primary key of a `WITHOUT ROWID` table is never NULL.
+    let not_null =
+      column.is_not_null() || (definition.is_without_rowid() && key.is_some());
+    let mut row = vec![
+      Value::Integer(rows.len() as i64),
+      Value::Text(column.name().into()),
+      Value::Text(column.declared_type().unwrap_or_default().into()),
+      Value::Integer(not_null.into()),
+      default,
+      Value::Integer(key.map_or(0, |key| key as i64 + 1)),
+    ];
+    if extended {
+      row[0] = Value::Integer(idx as i64);
+      row.push(Value::Integer(hidden));
+    }
+    rows.push(row);
+  }
+  Ok(rows)
+}
+
+/// The text of the `DEFAULT` value at `span` of `sql`, without the
+/// parentheses around an expression.
+fn default_text(sql: &str, span: Span) -> &str {
+  let text = span.text(sql);
+  match text
+    .strip_prefix('(')
+    .and_then(|text| text.strip_suffix(')'))
+  {
+    Some(expr) => expr.trim(),
+    None => text,
+  }
+}
+
+/// A row for each foreign key of the table `name` and each of its columns.
+/// Foreign keys are numbered from the last declared, as in SQLite.
+fn foreign_key_list(
+  conn: &mut SqliteConnection,
+  name: &str,
+) -> SqliteResult<Vec<Vec<Value>>> {
+  let Some(entry) = table_entry(conn, name)? else {
+    return Ok(vec![]);
+  };
+  let (_, columns, constraints, _) =
+    parse_table(entry.sql().unwrap_or_default())?;
+  let column_keys = columns.iter().flat_map(|column| {
+    column
+      .constraints
+      .iter()
+      .filter_map(|constraint| match &constraint.kind {
+        ColumnConstraintKind::ForeignKey(clause) => {
+          Some((vec![column.name.clone()], clause))
+        }
+        _ => None,
+      })
+  });
+  let table_keys =
+    constraints
+      .iter()
+      .filter_map(|constraint| match &constraint.kind {
+        TableConstraintKind::ForeignKey { columns, clause } => {
+          Some((columns.clone(), clause))
+        }
+        _ => None,
+      });
+  let keys = column_keys.chain(table_keys).collect::<Vec<_>>();
+  let mut rows = vec![];
+  for (id, (from, clause)) in keys.into_iter().rev().enumerate() {
+    for (seq, column) in from.iter().enumerate() {
+      let to = clause.columns.get(seq);
+      rows.push(vec![
+        Value::Integer(id as i64),
+        Value::Integer(seq as i64),
+        Value::Text(clause.table.value.clone()),
+        Value::Text(column.value.clone()),
+        to.map_or(Value::Null, |to| Value::Text(to.value.clone())),
+        Value::Text(action_name(clause.on_update).into()),
+        Value::Text(action_name(clause.on_delete).into()),
+        Value::Text("NONE".into()),
+      ]);
+    }
+  }
+  Ok(rows)
+}
+
+fn action_name(action: Option<ForeignKeyAction>) -> &'static str {
+  match action {
+    Some(ForeignKeyAction::SetNull) => "SET NULL",
+    Some(ForeignKeyAction::SetDefault) => "SET DEFAULT",
+    Some(ForeignKeyAction::Cascade) => "CASCADE",
+    Some(ForeignKeyAction::Restrict) => "RESTRICT",
+    Some(ForeignKeyAction::NoAction) | None => "NO ACTION",
+  }
+}
+
+/// An index of a table, as described by `index_list` and `index_info`.
+#[derive(Debug)]
+struct IndexDescription {
+  name: String,
+  unique: bool,
+  /// `c` for an index of a `CREATE INDEX` statement, `u` for that of a
+  /// `UNIQUE` constraint and `pk` for that of the primary key.
+  origin: &'static str,
+  partial: bool,
+  columns: Vec<IndexedColumn>,
+}
+
+/// The indexes of the table of `entry`, in the order they were created:
+/// those of its constraints, then those of `CREATE INDEX` statements. The
+/// primary key of a `WITHOUT ROWID` table is listed as an index, as it is
+/// one.
+fn table_indexes(
+  conn: &mut SqliteConnection,
+  entry: &SqliteSchema,
+) -> SqliteResult<Vec<IndexDescription>> {
+  let sql = entry.sql().unwrap_or_default();
+  let table = QualifiedName {
+    schema: None,
+    name: Name {
+      value: entry.name().into(),
+      quoted: false,
+      span: Span::default(),
+    },
+  };
+  let (automatic, primary_number) =
+    Planner::new(&mut conn.runtime, &conn.functions, sql)
+      .constraint_index_columns(&table)?;
+  let mut indexes = automatic
+    .into_iter()
+    .enumerate()
+    .map(|(idx, columns)| IndexDescription {
+      name: format!("sqlite_autoindex_{}_{}", entry.name(), idx + 1),
+      unique: true,
+      origin: match primary_number == Some(idx + 1) {
+        true => "pk",
+        false => "u",
+      },
+      partial: false,
+      columns,
+    })
+    .collect::<Vec<_>>();
+  for index in conn.runtime.schema()? {
+    let Some(sql) = index.sql() else {
+      continue;
+    };
+    if index.kind() != "index"
+      || !index.tbl_name().eq_ignore_ascii_case(entry.name())
+    {
+      continue;
+    }
+    let StatementKind::CreateIndex(create) = parse_object(sql)? else {
+      continue;
+    };
+    indexes.push(IndexDescription {
+      name: index.name().into(),
+      unique: create.unique,
+      origin: "c",
+      partial: create.where_clause.is_some(),
+      columns: create.columns,
+    });
+  }
+  Ok(indexes)
+}
+
+/// A row for each index of the table `name`, the last created first.
+fn index_list(
+  conn: &mut SqliteConnection,
+  name: &str,
+) -> SqliteResult<Vec<Vec<Value>>> {
+  let Some(entry) = table_entry(conn, name)? else {
+    return Ok(vec![]);
+  };
+  let indexes = table_indexes(conn, &entry)?;
+  let rows = indexes.into_iter().rev().enumerate().map(|(seq, index)| {
+    vec![
+      Value::Integer(seq as i64),
+      Value::Text(index.name),
+      Value::Integer(index.unique.into()),
+      Value::Text(index.origin.into()),
+      Value::Integer(index.partial.into()),
+    ]
+  });
+  Ok(rows.collect())
+}
+
+/// A row for each indexed value of the index `name`: its position, the
+/// table column it is, if any, and its name. `index_xinfo` also tells its
+/// order and collating sequence, and lists the values following the key
+/// in the entries, which make them unique: the rowid, or the primary key
+/// columns of a `WITHOUT ROWID` table.
+fn index_info(
+  conn: &mut SqliteConnection,
+  name: &str,
+  extended: bool,
+) -> SqliteResult<Vec<Vec<Value>>> {
+  let schema = conn.runtime.schema()?;
+  // The primary key of a `WITHOUT ROWID` table has no schema entry.
+  let tables = match find(&schema, &["index"], name) {
+    Some(index) => find(&schema, &["table"], index.tbl_name())
+      .into_iter()
+      .collect::<Vec<_>>(),
+    None => schema
+      .iter()
+      .filter(|entry| entry.kind() == "table" && entry.is_without_rowid())
+      .collect(),
+  };
+  for table in tables {
+    let indexes = table_indexes(conn, table)?;
+    let Some(index) = indexes
+      .iter()
+      .find(|index| index.name.eq_ignore_ascii_case(name))
+    else {
+      continue;
+    };
+    let definition = table.table_definition()?;
+    return Ok(index_rows(&definition, index, extended));
+  }
+  Ok(vec![])
+}
+
+fn index_rows(
+  definition: &TableDefinition,
+  index: &IndexDescription,
+  extended: bool,
+) -> Vec<Vec<Value>> {
+  let mut rows = vec![];
+  let mut key_columns = vec![];
+  for indexed in index.columns.iter() {
+    let (expr, collation) = match &indexed.expr.kind {
+      ExprKind::Collate { expr, collation } => {
+        (expr.as_ref(), Some(collation.value.clone()))
+      }
+      _ => (&indexed.expr, None),
+    };
+    let column = match &expr.kind {
+      ExprKind::Column {
+        schema: None,
+        table: None,
+        column,
+      } => definition.column_index(&column.value),
+      _ => None,
+    };
+    key_columns.extend(column);
+    let collation = collation.unwrap_or_else(|| match column {
+      Some(column) => {
+        definition.columns()[column].collation().name().to_owned()
+      }
+      None => "BINARY".into(),
+    });
+    let descending = indexed.order == Some(SortOrder::Desc);
+    rows.push(info_row(definition, column, descending, collation, true));
+  }
+  if extended {
+    match definition.is_without_rowid() {
+      true if index.origin == "pk" => {
+        for column in definition.storage_order() {
+          if !key_columns.contains(&column) {
+            let collation = definition.columns()[column].collation().name();
+            let collation = collation.to_owned();
+            rows.push(info_row(
+              definition,
+              Some(column),
+              false,
+              collation,
+              false,
+            ));
+          }
+        }
+      }
+      true => {
+        for key in definition.primary_key() {
+          if !key_columns.contains(&key.column()) {
+            let collation = key.collation().name().to_owned();
+            let descending = key.is_descending();
+            rows.push(info_row(
+              definition,
+              Some(key.column()),
+              descending,
+              collation,
+              false,
+            ));
+          }
+        }
+      }
+      false => rows.push(vec![
+        Value::Integer(-1),
+        Value::Null,
+        Value::Integer(0),
+        Value::Text("BINARY".into()),
+        Value::Integer(0),
+      ]),
+    }
+  }
+  rows
+    .into_iter()
+    .enumerate()
+    .map(|(seqno, mut row)| {
+      row.insert(0, Value::Integer(seqno as i64));
+      if !extended {
+        row.truncate(3);
+      }
+      row
+    })
+    .collect()
+}
+
+/// The row of `index_xinfo` for a value of an index entry, without its
+/// position: the table `column` it is, `None` for an expression, its order,
+/// its collating sequence, and whether it is part of the key.
+fn info_row(
+  definition: &TableDefinition,
+  column: Option<usize>,
+  descending: bool,
+  collation: String,
+  key: bool,
+) -> Vec<Value> {
+  let name = column.map(|column| definition.columns()[column].name());
+  vec![
+    Value::Integer(column.map_or(-2, |column| column as i64)),
+    name.map_or(Value::Null, |name| Value::Text(name.into())),
+    Value::Integer(descending.into()),
+    Value::Text(collation),
+    Value::Integer(key.into()),
+  ]
+}
@@ -0,0 +1,403 @@
+//! # printf
+//!
+//!  `printf(FORMAT, ...)`, also named `format()`, works like the `printf()`
+//! function of the standard C library: each `%` conversion of the format
+//! renders the next argument, as an integer, a floating point number or
+//! text. SQLite adds the `%q`, `%Q` and `%w` conversions, which quote text
+//! for use in SQL, the `,` flag, which separates thousands, and the `!`
+//! flag, which shows more digits of floating point numbers. Missing
+//! arguments count as NULL, which renders as zero or as empty text.
+//!
+//! *Reference:* https://www.sqlite.org/printf.html
+
+use crate::runtime::{to_integer, to_real, to_text, Value};
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// The flags, width and precision of a conversion.
+#[derive(Debug, Default)]
+struct Spec {
+  /// `-`: padded on the right rather than on the left.
+  left: bool,
+  /// `+` or ` `: put before numbers that are not negative.
+  prefix: Option<char>,
+  /// `#`: the alternate form.
+  alternate: bool,
+  /// `!`: the second alternate form.
+  alternate2: bool,
+  /// `0`: numbers are padded with zeros.
+  zero: bool,
+  /// `,`: thousands of integers are separated by commas.
+  thousands: bool,
+  width: usize,
+  precision: Option<usize>,
+}
+
+/// The digits of a floating point number, as SQLite decodes them.
+#[derive(Debug)]
+struct Decoded {
+  negative: bool,
+  /// The significant digits, without trailing zeros.
+  digits: Vec<u8>,
+  /// The position of the decimal point relative to the first digit.
+  point: i32,
+}
+
+/// Digits of a floating point number SQLite finds before rounding.
+const DECODED_DIGITS: usize = 19;
+
+/// The text of `format` with its conversions replaced by the `arguments`.
+pub(crate) fn format(format: &str, arguments: &[Value]) -> String {
+  let mut arguments = arguments.iter();
+  let mut next = || arguments.next().cloned().unwrap_or(Value::Null);
+  let mut out = String::new();
+  let mut chars = format.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '%' {
+      out.push(c);
+      continue;
+    }
+    if chars.peek().is_none() {
+      out.push('%');
+      break;
+    }
+    let mut spec = Spec::default();
+    while let Some(&flag) = chars.peek() {
+      match flag {
+        '-' => spec.left = true,
+        '+' | ' ' => spec.prefix = Some(flag),
+        '#' => spec.alternate = true,
+        '!' => spec.alternate2 = true,
+        '0' => spec.zero = true,
+        ',' => spec.thousands = true,
+        _ => break,
+      }
+      chars.next();
+    }
+    if chars.next_if_eq(&'*').is_some() {
+      let width = to_integer(&next());
+      spec.left |= width < 0;
+      spec.width = usize::try_from(width.unsigned_abs()).unwrap_or(usize::MAX);
+    } else {
+      spec.width = number(&mut chars);
+    }
+    if chars.next_if_eq(&'.').is_some() {
+      spec.precision = Some(match chars.next_if_eq(&'*') {
+        Some(_) => {
+          let precision = to_integer(&next()).unsigned_abs();
+          usize::try_from(precision).unwrap_or(usize::MAX)
+        }
+        None => number(&mut chars),
+      });
+    }
+    while chars.next_if_eq(&'l').is_some() {}
+    let text = match chars.next() {
+      Some('d' | 'i') => integer(to_integer(&next()), true, 10, "", &spec),
+      Some('u') => integer(to_integer(&next()), false, 10, "", &spec),
+      Some('x' | 'p') => integer(to_integer(&next()), false, 16, "0x", &spec),
+      Some('X') => {
+        integer(to_integer(&next()), false, 16, "0x", &spec).to_uppercase()
+      }
+      Some('o') => integer(to_integer(&next()), false, 8, "0", &spec),
+      Some(c @ ('f' | 'e' | 'E' | 'g' | 'G')) => {
+        let text = real(to_real(&next()), c.to_ascii_lowercase(), &spec);
+        match c.is_ascii_uppercase() {
+          true => text.replace('e', "E"),
+          false => text,
+        }
+      }
+      Some('s' | 'z') => {
+        let text = to_text(&next()).unwrap_or_default();
+        truncate(&text, &spec).to_owned()
+      }
+      Some(c @ ('q' | 'Q' | 'w')) => {
+        let quote = if c == 'w' { '"' } else { '\'' };
+        match (to_text(&next()), c) {
+          (None, 'Q') => "NULL".to_owned(),
+          (None, _) => "(NULL)".to_owned(),
+          (Some(text), _) => {
+            let text = truncate(&text, &spec);
+            let escaped = text.replace(quote, &format!("{quote}{quote}"));
+            match c {
+              'Q' => format!("'{escaped}'"),
+              _ => escaped,
+            }
+          }
+        }
+      }
+      Some('c') => {
+        let text = to_text(&next()).unwrap_or_default();
+        let c = text.chars().next().map(String::from).unwrap_or_default();
+        c.repeat(spec.precision.unwrap_or(1).max(1))
+      }
+      Some('%') => "%".to_owned(),
+      Some('n') => String::new(),
+      // An unknown conversion ends the text.
+      _ => break,
+    };
+    let padding = spec.width.saturating_sub(width(&text, &spec));
+    match spec.left {
+      true => out.extend([text, " ".repeat(padding)]),
+      false => out.extend([" ".repeat(padding), text]),
+    }
+  }
+  out
+}
+
+/// The decimal number at the start of `chars`, 0 without digits.
+fn number(chars: &mut Peekable<Chars<'_>>) -> usize {
+  let mut number = 0usize;
+  while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+    number = number.saturating_mul(10).saturating_add(digit as usize);
+    chars.next();
+  }
+  number
+}
+
+/// The first `precision` bytes of `text` that are whole characters, or
+/// the first `precision` characters with the `!` flag.
+fn truncate<'t>(text: &'t str, spec: &Spec) -> &'t str {
+  let Some(precision) = spec.precision else {
+    return text;
+  };
+  let end = match spec.alternate2 {
+    true => text.char_indices().nth(precision).map(|(end, _)| end),
+    false => (0..=precision.min(text.len()))
+      .rev()
+      .find(|&end| text.is_char_boundary(end)),
+  };
+  &text[..end.unwrap_or(text.len())]
+}
+
+/// The length `text` counts for its width: its bytes, or its characters
+/// with the `!` flag.
+fn width(text: &str, spec: &Spec) -> usize {
+  match spec.alternate2 {
+    true => text.chars().count(),
+    false => text.len(),
+  }
+}
+
+/// `%d`, `%i` and `%u` in base 10, `%x` in base 16 and `%o` in base 8.
+/// Values that are not `signed` are read as unsigned 64-bit integers.
+fn integer(
+  value: i64,
+  signed: bool,
+  base: u64,
+  alternate: &str,
+  spec: &Spec,
+) -> String {
+  let (magnitude, prefix) = match (signed, value < 0) {
+    (true, true) => (value.unsigned_abs(), Some('-')),
+    (true, false) => (value as u64, spec.prefix),
+    (false, _) => (value as u64, None),
+  };
+  let mut precision = spec.precision.unwrap_or(0);
+  if spec.zero {
+    precision =
+      precision.max(spec.width.saturating_sub(usize::from(prefix.is_some())));
+  }
+  let mut digits = match base {
+    16 => format!("{magnitude:x}"),
+    8 => format!("{magnitude:o}"),
+    _ => magnitude.to_string(),
+  };
+  if digits.len() < precision {
+    digits.insert_str(0, &"0".repeat(precision - digits.len()));
+  }
+  if spec.thousands && base == 10 {
+    digits = separate_thousands(&digits);
+  }
+  let mut text = String::new();
+  text.extend(prefix);
+  if spec.alternate && magnitude != 0 {
+    text.push_str(alternate);
+  }
+  text + &digits
+}
+
+/// `digits` with a comma before each group of three from the right.
+fn separate_thousands(digits: &str) -> String {
+  let mut separated = String::new();
+  for (idx, digit) in digits.chars().enumerate() {
+    if idx > 0 && (digits.len() - idx) % 3 == 0 {
+      separated.push(',');
+    }
+    separated.push(digit);
+  }
+  separated
+}
+
+/// `%f`, `%e` and `%g`, rendering `value` with `precision` digits after the
+/// decimal point for `%f` and `%e`, and with that many significant digits
+/// for `%g`.
+fn real(value: f64, conversion: char, spec: &Spec) -> String {
+  let mut precision = spec.precision.unwrap_or(6) as i64;
+  if value.is_nan() {
+    return match spec.zero {
+      true => "null".to_owned(),
+      false => "NaN".to_owned(),
+    };
+  }
+  let decoded = match value.is_infinite() {
+    true if spec.zero => Decoded {
+      negative: value < 0.0,
+      digits: vec![b'9'],
+      point: 1000,
+    },
+    true => {
+      return match (value < 0.0, spec.prefix) {
+        (true, _) => "-Inf".to_owned(),
+        (false, Some(prefix)) => format!("{prefix}Inf"),
+        (false, None) => "Inf".to_owned(),
+      }
+    }
+    false => {
+      if conversion == 'g' && precision == 0 {
+        precision = 1;
+      }
+      let round = match conversion {
+        'f' => -precision,
+        'g' => precision,
+        _ => precision + 1,
+      };
+      let limit = if spec.alternate2 { 26 } else { 16 };
+      decode(value, round, limit)
+    }
+  };
+  let exponent = i64::from(decoded.point) - 1;
+  let mut conversion = conversion;
+  if conversion == 'g' {
+    precision -= 1;
+  }
+  let remove_zeros = match conversion {
+    'g' => {
+      if exponent < -4 || exponent > precision {
+        conversion = 'e';
+      } else {
+        precision -= exponent;
+        conversion = 'f';
+      }
+      !spec.alternate
+    }
+    _ => spec.alternate2,
+  };
+  let mut text = String::new();
+  match decoded.negative {
+    true => text.push('-'),
+    false => text.extend(spec.prefix),
+  }
+  let mut digits = decoded.digits.iter().map(|&digit| char::from(digit));
+  let mut next_digit = || digits.next().unwrap_or('0');
+  let mut place = match conversion {
+    'e' => 0,
+    _ => exponent,
+  };
+  if place < 0 {
+    text.push('0');
+  } else {
+    while place >= 0 {
+      text.push(next_digit());
+      if spec.thousands && place % 3 == 0 && place > 1 {
+        text.push(',');
+      }
+      place -= 1;
+    }
+  }
+  let has_point = precision > 0 || spec.alternate || spec.alternate2;
+  if has_point {
+    text.push('.');
+  }
+  place += 1;
+  while place < 0 && precision > 0 {
+    text.push('0');
+    precision -= 1;
+    place += 1;
+  }
+  for _ in 0..precision {
+    text.push(next_digit());
+  }
+  if remove_zeros && has_point {
+    let trimmed = text.trim_end_matches('0').len();
+    text.truncate(trimmed);
+    if text.ends_with('.') {
+      match spec.alternate2 {
+        true => text.push('0'),
+        false => {
+          text.pop();
+        }
+      }
+    }
+  }
+  if conversion == 'e' {
+    let sign = if exponent < 0 { '-' } else { '+' };
+    text.push_str(&format!("e{sign}{:02}", exponent.abs()));
+  }
+  let length = text.len();
+  if spec.zero && !spec.left && length < spec.width {
+    let at = usize::from(decoded.negative || spec.prefix.is_some());
+    text.insert_str(at, &"0".repeat(spec.width - length));
+  }
+  text
+}
+
+/// The digits of the finite `value`, rounded half away from zero to `round`
+/// significant digits when it is positive, and to `-round` digits after the
+/// decimal point otherwise, keeping at most `limit` digits.
+fn decode(value: f64, round: i64, limit: i64) -> Decoded {
+  let negative = value.is_sign_negative() && value != 0.0;
+  if value == 0.0 {
+    return Decoded {
+      negative,
+      digits: vec![b'0'],
+      point: 1,
+    };
+  }
+  let scientific = format!("{:.*e}", DECODED_DIGITS + 20, value.abs());
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or_default();
+  let mut digits = mantissa
+    .bytes()
+    .filter(u8::is_ascii_digit)
+    .collect::<Vec<_>>();
+  digits.truncate(DECODED_DIGITS);
+  let mut point = exponent.parse::<i32>().unwrap_or_default() + 1;
+  let mut round = match round {
+    ..=0 => i64::from(point) - round,
+    round => round,
+  };
+  if round == 0 && digits[0] >= b'5' {
+    digits.insert(0, b'0');
+    point += 1;
+    round = 1;
+  }
+  let length = digits.len() as i64;
+  if round > 0 && (round < length || length > limit) {
+    let round = round.min(limit) as usize;
+    let up = digits[round] >= b'5';
+    digits.truncate(round);
+    if up {
+      let carried = digits.iter_mut().rev().all(|digit| {
+        *digit += 1;
+        match *digit > b'9' {
+          true => {
+            *digit = b'0';
+            true
+          }
+          false => false,
+        }
+      });
+      if carried {
+        digits.insert(0, b'1');
+        point += 1;
+      }
+    }
+  }
+  while digits.len() > 1 && digits.last() == Some(&b'0') {
+    digits.pop();
+  }
+  Decoded {
+    negative,
+    digits,
+    point,
+  }
+}
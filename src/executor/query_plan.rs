@@ -0,0 +1,101 @@
+//! # Query plans
+//!
+//!  `EXPLAIN QUERY PLAN` describes how a query finds its rows rather than
+//! running it: a line for each table scan and each temporary b-tree, nested
+//! under the subqueries and the parts of compound queries they belong to.
+//! The subqueries of expressions follow the lines of the query.
+//!
+//! *Reference:* https://www.sqlite.org/eqp.html
+
+use super::operator::Operator;
+use super::subquery::{Subquery, SubqueryKind};
+use crate::runtime::Value;
+use core::mem;
+
+/// The lines describing a query plan, added by its operators.
+#[derive(Debug)]
+pub(crate) struct QueryPlan<'a> {
+  subqueries: &'a [Subquery],
+  /// The id of each line, that of the line it is under, 0 at the top, and
+  /// its text.
+  lines: Vec<(i64, i64, String)>,
+  /// The line new lines are added under.
+  parent: i64,
+  /// Which subqueries were described already.
+  described: Vec<bool>,
+}
+
+impl<'a> QueryPlan<'a> {
+  pub(crate) const COLUMNS: [&'static str; 4] =
+    ["id", "parent", "notused", "detail"];
+
+  /// The rows of `EXPLAIN QUERY PLAN` for the query whose operators are
+  /// `root`, reading the `subqueries`.
+  pub(crate) fn rows(
+    root: &dyn Operator,
+    subqueries: &'a [Subquery],
+  ) -> Vec<Vec<Value>> {
+    let mut plan = Self {
+      subqueries,
+      lines: vec![],
+      parent: 0,
+      described: vec![false; subqueries.len()],
+    };
+    root.describe(&mut plan);
+    let mut number = 0;
+    for subquery in subqueries {
+      let kind = match subquery.kind() {
+        SubqueryKind::In(_) => "LIST",
+        SubqueryKind::Scalar | SubqueryKind::Exists => "SCALAR",
+        SubqueryKind::Rows => continue,
+      };
+      let Some(root) = subquery.root() else {
+        continue;
+      };
+      number += 1;
+      let correlated = match subquery.is_correlated() {
+        true => "CORRELATED ",
+        false => "",
+      };
+      let detail = format!("{correlated}{kind} SUBQUERY {number}");
+      plan.nest(detail, |plan| root.describe(plan));
+    }
+    plan
+      .lines
+      .into_iter()
+      .map(|(id, parent, detail)| {
+        vec![id.into(), parent.into(), 0.into(), detail.into()]
+      })
+      .collect()
+  }
+
+  /// Adds a line under the current one.
+  pub(crate) fn add(&mut self, detail: impl Into<String>) {
+    let id = self.lines.len() as i64 + 1;
+    self.lines.push((id, self.parent, detail.into()));
+  }
+
+  /// Adds a line, and under it the lines `f` adds.
+  pub(crate) fn nest(
+    &mut self,
+    detail: impl Into<String>,
+    f: impl FnOnce(&mut Self),
+  ) {
+    self.add(detail);
+    let parent = mem::replace(&mut self.parent, self.lines.len() as i64);
+    f(self);
+    self.parent = parent;
+  }
+
+  /// Describes the subquery `id`, read as the table `name`, under a line
+  /// telling its rows are computed before they are read, the first time it
+  /// is read.
+  pub(crate) fn materialize(&mut self, id: usize, name: &str) {
+    let Some(root) = self.subqueries.get(id).and_then(Subquery::root) else {
+      return;
+    };
+    if !mem::replace(&mut self.described[id], true) {
+      self.nest(format!("MATERIALIZE {name}"), |plan| root.describe(plan));
+    }
+  }
+}
@@ -0,0 +1,478 @@
+//! # External merge sort
+//!
+//!  Rows are sorted in memory until they exceed the memory budget of the
+//! connection. Past that point, each batch of sorted rows is written to a
+//! temporary file as a run, and the runs are merged as the sorted rows are
+//! read back, a bounded number at a time. When only the first rows are
+//! wanted, as with `ORDER BY ... LIMIT`, the rows that cannot make it are
+//! dropped as they come. The sets of rows of `DISTINCT`, compound queries
+//! and `IN` spill to sorted runs the same way, and are searched there.
+//!
+//! *Reference:* https://www.sqlite.org/tempfiles.html#transient_indices
+
+use crate::result::SqliteResult;
+use crate::runtime::{compare_values, Collation, Record, Value};
+use core::cmp::Ordering;
+use core::mem::{self, size_of, size_of_val};
+use core::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// One term of an `ORDER BY` clause.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SortKey {
+  /// Position of the sorted value in the rows.
+  pub(crate) column: usize,
+  pub(crate) descending: bool,
+  pub(crate) nulls_first: bool,
+  pub(crate) collation: Collation,
+}
+
+impl SortKey {
+  /// NULLs come first in ascending order and last in descending order,
+  /// unless `NULLS FIRST` or `NULLS LAST` says otherwise.
+  pub(crate) fn new(
+    column: usize,
+    descending: bool,
+    nulls_first: Option<bool>,
+    collation: Collation,
+  ) -> Self {
+    Self {
+      column,
+      descending,
+      nulls_first: nulls_first.unwrap_or(!descending),
+      collation,
+    }
+  }
+}
+
+/// Compares rows by their sort keys, in order.
+pub(crate) fn compare_rows(
+  keys: &[SortKey],
+  left: &[Value],
+  right: &[Value],
+) -> Ordering {
+  for key in keys {
+    let (left, right) = (&left[key.column], &right[key.column]);
+    let ordering = match (left.is_null(), right.is_null()) {
+      (true, true) => Ordering::Equal,
+      (true, false) if key.nulls_first => Ordering::Less,
+      (true, false) => Ordering::Greater,
+      (false, true) if key.nulls_first => Ordering::Greater,
+      (false, true) => Ordering::Less,
+      (false, false) => {
+        let ordering = compare_values(left, right, &key.collation);
+        match key.descending {
+          true => ordering.reverse(),
+          false => ordering,
+        }
+      }
+    };
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
+
+/// An estimate of the memory held by a row.
+pub(crate) fn row_size(row: &[Value]) -> usize {
+  let data: usize = row
+    .iter()
+    .map(|value| match value {
+      Value::Text(text) => text.len(),
+      Value::Blob(blob) => blob.len(),
+      _ => 0,
+    })
+    .sum();
+  size_of::<Vec<Value>>() + size_of_val(row) + data
+}
+
+/// The most runs merged at once. Past it, runs are first merged into longer
+/// ones, in as many passes as needed.
+const FAN_IN: usize = 16;
+
+/// Collects rows, then hands them back sorted. Rows with equal keys keep
+/// the order they were added in.
+#[derive(Debug)]
+pub(crate) struct Sorter {
+  keys: Arc<[SortKey]>,
+  memory_limit: usize,
+  /// How many of the first rows are wanted, when not all of them are.
+  top: Option<usize>,
+  rows: Vec<Vec<Value>>,
+  memory: usize,
+  runs: Vec<FileRun>,
+}
+
+impl Sorter {
+  pub(crate) fn new(
+    keys: Vec<SortKey>,
+    memory_limit: usize,
+    top: Option<usize>,
+  ) -> Self {
+    Self {
+      keys: keys.into(),
+      memory_limit,
+      top,
+      rows: vec![],
+      memory: 0,
+      runs: vec![],
+    }
+  }
+
+  pub(crate) fn push(&mut self, row: Vec<Value>) -> SqliteResult<()> {
+    if self.top == Some(0) {
+      return Ok(());
+    }
+    self.memory += row_size(&row);
+    self.rows.push(row);
+    if let Some(top) = self.top {
+      // Trimming only once twice the rows are held keeps pushes cheap.
+      if self.rows.len() >= top.saturating_mul(2) {
+        self.sort_rows();
+      }
+    }
+    if self.memory > self.memory_limit {
+      self.spill()?;
+    }
+    Ok(())
+  }
+
+  /// Sorts the rows held in memory, keeping only the top ones.
+  fn sort_rows(&mut self) {
+    let keys = &self.keys;
+    self
+      .rows
+      .sort_by(|left, right| compare_rows(keys, left, right));
+    if let Some(top) = self.top {
+      if self.rows.len() > top {
+        self.rows.truncate(top);
+        self.memory = self.rows.iter().map(|row| row_size(row)).sum();
+      }
+    }
+  }
+
+  /// Writes the rows held in memory to a new run.
+  fn spill(&mut self) -> SqliteResult<()> {
+    self.sort_rows();
+    let mut rows = mem::take(&mut self.rows).into_iter();
+    self.runs.push(FileRun::write(|| Ok(rows.next()))?);
+    self.memory = 0;
+    Ok(())
+  }
+
+  /// The rows in sorted order.
+  pub(crate) fn finish(mut self) -> SqliteResult<SortedRows> {
+    self.sort_rows();
+    let mut runs = self.runs;
+    // Leaves room for the rows held in memory.
+    while runs.len() >= FAN_IN {
+      let mut merged = vec![];
+      let mut pass = runs.into_iter().peekable();
+      while pass.peek().is_some() {
+        let group = pass.by_ref().take(FAN_IN).map(Run::File).collect();
+        let mut rows = SortedRows::new(self.keys.clone(), group)?;
+        merged.push(FileRun::write(|| rows.next_row())?);
+      }
+      runs = merged;
+    }
+    let mut runs = runs.into_iter().map(Run::File).collect::<Vec<_>>();
+    runs.push(Run::Memory(self.rows.into_iter()));
+    SortedRows::new(self.keys, runs)
+  }
+}
+
+/// Rows read back from a [`Sorter`], merging its runs.
+#[derive(Debug)]
+pub(crate) struct SortedRows {
+  runs: Vec<Run>,
+  /// The next row of each run that has one, the smallest on top.
+  heads: BinaryHeap<Head>,
+}
+
+impl SortedRows {
+  fn new(keys: Arc<[SortKey]>, mut runs: Vec<Run>) -> SqliteResult<Self> {
+    let mut heads = BinaryHeap::with_capacity(runs.len());
+    for (run, rows) in runs.iter_mut().enumerate() {
+      if let Some(row) = rows.next_row()? {
+        let keys = keys.clone();
+        heads.push(Head { keys, row, run });
+      }
+    }
+    Ok(Self { runs, heads })
+  }
+
+  pub(crate) fn next_row(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    let Some(Head { keys, row, run }) = self.heads.pop() else {
+      return Ok(None);
+    };
+    if let Some(next) = self.runs[run].next_row()? {
+      self.heads.push(Head {
+        keys,
+        row: next,
+        run,
+      });
+    }
+    Ok(Some(row))
+  }
+}
+
+/// The next row of a run being merged.
+#[derive(Debug)]
+struct Head {
+  keys: Arc<[SortKey]>,
+  row: Vec<Value>,
+  run: usize,
+}
+
+/// Heads are ordered so that the heap pops the smallest row first and, on
+/// ties, that of the earlier run, as it holds the earlier rows.
+impl Ord for Head {
+  fn cmp(&self, other: &Self) -> Ordering {
+    compare_rows(&self.keys, &other.row, &self.row)
+      .then_with(|| other.run.cmp(&self.run))
+  }
+}
+
+impl PartialOrd for Head {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Head {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other).is_eq()
+  }
+}
+
+impl Eq for Head {}
+
+/// Sorted rows, held in memory or spilled to a file.
+#[derive(Debug)]
+enum Run {
+  Memory(std::vec::IntoIter<Vec<Value>>),
+  File(FileRun),
+}
+
+impl Run {
+  fn next_row(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    match self {
+      Self::Memory(rows) => Ok(rows.next()),
+      Self::File(run) => run.next_row(),
+    }
+  }
+}
+
+/// Sorted rows written to a temporary file, each record after its length,
+/// then the offset of each record, by which rows are looked up.
+#[derive(Debug)]
+struct FileRun {
+  reader: BufReader<SpillFile>,
+  len: usize,
+  /// The position of the next row read in order.
+  next: usize,
+  /// Where the offsets of the records start.
+  index: u64,
+}
+
+impl FileRun {
+  /// Writes the rows `next` returns, until it returns `None`, to a new run.
+  fn write(
+    mut next: impl FnMut() -> SqliteResult<Option<Vec<Value>>>,
+  ) -> SqliteResult<Self> {
+    let mut file = SpillFile::create()?;
+    let mut offsets = vec![];
+    let mut index = 0;
+    {
+      let mut writer = BufWriter::new(&mut file.file);
+      while let Some(row) = next()? {
+        let record = Record::encode(&row);
+        offsets.push(index);
+        writer.write_all(&(record.len() as u64).to_be_bytes())?;
+        writer.write_all(&record)?;
+        index += 8 + record.len() as u64;
+      }
+      for offset in &offsets {
+        writer.write_all(&u64::to_be_bytes(*offset))?;
+      }
+      writer.flush()?;
+    }
+    file.file.rewind()?;
+    Ok(Self {
+      reader: BufReader::new(file),
+      len: offsets.len(),
+      next: 0,
+      index,
+    })
+  }
+
+  fn next_row(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    if self.next == self.len {
+      return Ok(None);
+    }
+    self.next += 1;
+    self.read_record().map(Some)
+  }
+
+  /// The row at `position`. Rows are only read in order again once
+  /// [`rewound`](Self::rewound).
+  fn row(&mut self, position: usize) -> SqliteResult<Vec<Value>> {
+    let index = self.index + 8 * position as u64;
+    self.reader.seek(SeekFrom::Start(index))?;
+    let offset = self.read_u64()?;
+    self.reader.seek(SeekFrom::Start(offset))?;
+    self.read_record()
+  }
+
+  /// The run, with rows read in order from the first one.
+  fn rewound(mut self) -> SqliteResult<Self> {
+    self.reader.rewind()?;
+    self.next = 0;
+    Ok(self)
+  }
+
+  /// Whether a row compares equal to `row` as `keys` say, which sorted the
+  /// rows.
+  fn contains(
+    &mut self,
+    keys: &[SortKey],
+    row: &[Value],
+  ) -> SqliteResult<bool> {
+    let (mut low, mut high) = (0, self.len);
+    while low < high {
+      let middle = low + (high - low) / 2;
+      match compare_rows(keys, &self.row(middle)?, row) {
+        Ordering::Less => low = middle + 1,
+        Ordering::Greater => high = middle,
+        Ordering::Equal => return Ok(true),
+      }
+    }
+    Ok(false)
+  }
+
+  fn read_u64(&mut self) -> SqliteResult<u64> {
+    let mut bytes = [0; 8];
+    self.reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+  }
+
+  fn read_record(&mut self) -> SqliteResult<Vec<Value>> {
+    let mut record = vec![0; self.read_u64()? as usize];
+    self.reader.read_exact(&mut record)?;
+    Record::decode(&record)
+  }
+}
+
+/// Distinct rows spilled to temporary files as sorted runs, in which rows
+/// are looked up. Runs are merged in groups as they pile up, so that a
+/// lookup searches few of them.
+#[derive(Debug, Default)]
+pub(crate) struct SpilledRows {
+  /// Each run, with how many merges made it, the most merged first.
+  runs: Vec<(FileRun, u32)>,
+}
+
+impl SpilledRows {
+  /// Writes `rows`, none of which is spilled already, to a new run sorted
+  /// by `keys`.
+  pub(crate) fn spill(
+    &mut self,
+    keys: &Arc<[SortKey]>,
+    mut rows: Vec<Vec<Value>>,
+  ) -> SqliteResult<()> {
+    rows.sort_by(|left, right| compare_rows(keys, left, right));
+    let mut rows = rows.into_iter();
+    self.runs.push((FileRun::write(|| Ok(rows.next()))?, 0));
+    while let Some(start) = self.runs.len().checked_sub(FAN_IN) {
+      let level = self.runs[start].1;
+      if self.runs[start..]
+        .iter()
+        .any(|(_, merges)| *merges != level)
+      {
+        break;
+      }
+      let group = self
+        .runs
+        .split_off(start)
+        .into_iter()
+        .map(|(run, _)| run.rewound().map(Run::File))
+        .collect::<SqliteResult<_>>()?;
+      let mut rows = SortedRows::new(keys.clone(), group)?;
+      self
+        .runs
+        .push((FileRun::write(|| rows.next_row())?, level + 1));
+    }
+    Ok(())
+  }
+
+  /// Whether a spilled row compares equal to `row` as `keys` say.
+  pub(crate) fn contains(
+    &mut self,
+    keys: &[SortKey],
+    row: &[Value],
+  ) -> SqliteResult<bool> {
+    for (run, _) in &mut self.runs {
+      if run.contains(keys, row)? {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  pub(crate) fn clear(&mut self) {
+    self.runs.clear();
+  }
+}
+
+/// A temporary file, removed once dropped.
+#[derive(Debug)]
+struct SpillFile {
+  path: PathBuf,
+  file: File,
+}
+
+impl SpillFile {
+  fn create() -> SqliteResult<Self> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+      let path = std::env::temp_dir().join(format!(
+        "sqlite-rs-sort-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+      ));
+      match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+      {
+        Ok(file) => return Ok(Self { path, file }),
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error.into()),
+      }
+    }
+  }
+}
+
+impl Read for SpillFile {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.file.read(buf)
+  }
+}
+
+impl Seek for SpillFile {
+  fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+    self.file.seek(position)
+  }
+}
+
+impl Drop for SpillFile {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
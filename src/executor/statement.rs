@@ -0,0 +1,307 @@
+//! # Prepared statements
+//!
+//!  A statement is parsed once and run any number of times, with values
+//! bound to its parameters rather than written into its SQL text. The
+//! parameters take any of the forms SQLite knows: `?`, `?NNN`, `:name`,
+//! `@name` and `$name`.
+//!
+//! ```
+//! use sqlite_rs::{runtime::Value, SqliteConnection};
+//!
+//! let mut conn = SqliteConnection::open(":memory:").unwrap();
+//! conn.execute("CREATE TABLE t(a, b)").unwrap();
+//! let mut insert = conn.prepare("INSERT INTO t VALUES (?, :b)").unwrap();
+//! assert_eq!(insert.parameter_count(), 2);
+//! for (a, b) in [(1, "one"), (2, "two")] {
+//!   insert.bind(1, a as i64).unwrap();
+//!   insert.bind_by_name(":b", b).unwrap();
+//!   assert_eq!(insert.execute(&mut conn).unwrap(), 1);
+//! }
+//! let mut select = conn.prepare("SELECT b FROM t WHERE a = ?1").unwrap();
+//! select.bind(1, 2i64).unwrap();
+//! let row = select.step(&mut conn).unwrap().unwrap();
+//! assert_eq!(row.values(), [Value::Text("two".into())]);
+//! assert!(select.step(&mut conn).unwrap().is_none());
+//! ```
+//!
+//! *Reference:* https://www.sqlite.org/c3ref/stmt.html
+
+use super::operator::Operator;
+use super::subquery::Subquery;
+use super::{is_query, plan_query, run, start_query, Context, Row, Rows};
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::Value;
+use crate::sql::ast;
+use crate::sql::Parser;
+use crate::SqliteConnection;
+use core::mem;
+use std::sync::Arc;
+
+/// A statement parsed from its SQL text.
+#[derive(Debug)]
+pub(crate) struct Prepared {
+  pub(crate) sql: String,
+  pub(crate) statement: ast::Statement,
+  /// Names of the parameters, by index minus one.
+  parameters: Vec<Option<String>>,
+}
+
+impl Prepared {
+  /// Parses the one statement of `sql`, which is to be `action`.
+  fn parse(sql: &str, action: &str) -> SqliteResult<Self> {
+    let mut parser = Parser::new(sql)?;
+    let statement = parser
+      .next_statement()?
+      .ok_or(SqliteError::Custom("No statement to run".into()))?;
+    let parameters = parser.parameter_names().to_vec();
+    if parser.next_statement()?.is_some() {
+      return Err(SqliteError::Custom(format!(
+        "Only one statement can be {action} at a time"
+      )));
+    }
+    Ok(Self {
+      sql: sql.into(),
+      statement,
+      parameters,
+    })
+  }
+}
+
+/// The statements a connection parsed most recently, for the same SQL text
+/// to be run again without parsing it. Once full, the statement used least
+/// recently makes room for the new one.
+#[derive(Debug)]
+pub(crate) struct StatementCache {
+  capacity: usize,
+  /// Most recently used last.
+  entries: Vec<Arc<Prepared>>,
+}
+
+impl StatementCache {
+  pub(crate) fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      entries: vec![],
+    }
+  }
+
+  pub(crate) fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub(crate) fn set_capacity(&mut self, capacity: usize) {
+    self.capacity = capacity;
+    let excess = self.entries.len().saturating_sub(capacity);
+    self.entries.drain(..excess);
+  }
+
+  /// The statement of `sql`, which is to be `action`, parsed unless it is
+  /// cached.
+  pub(crate) fn prepare(
+    &mut self,
+    sql: &str,
+    action: &str,
+  ) -> SqliteResult<Arc<Prepared>> {
+    let prepared = match self.entries.iter().position(|entry| entry.sql == sql)
+    {
+      Some(idx) => self.entries.remove(idx),
+      None => Arc::new(Prepared::parse(sql, action)?),
+    };
+    if self.capacity > 0 {
+      if self.entries.len() == self.capacity {
+        self.entries.remove(0);
+      }
+      self.entries.push(prepared.clone());
+    }
+    Ok(prepared)
+  }
+}
+
+/// A statement parsed once, to be run any number of times with the values
+/// bound to its parameters. Parameters without a value are NULL.
+///
+///  The statement is compiled each time it starts running, against the
+/// schema of the moment, so that it follows the changes made to the schema
+/// since it was prepared. It is not tied to the connection that prepared
+/// it. The database should not change while a query is part way through.
+#[derive(Debug)]
+pub struct Statement {
+  prepared: Arc<Prepared>,
+  bindings: Vec<Value>,
+  state: State,
+}
+
+#[derive(Debug)]
+enum State {
+  /// Not started since it was prepared or reset.
+  Ready,
+  /// A query with rows left to read.
+  Running(Box<Running>),
+  /// The statement ran to completion, or failed.
+  Done,
+}
+
+/// The plan of a query part way through.
+#[derive(Debug)]
+struct Running {
+  root: Box<dyn Operator>,
+  columns: Arc<[String]>,
+  subqueries: Vec<Subquery>,
+}
+
+impl Statement {
+  pub(crate) fn new(prepared: Arc<Prepared>) -> Self {
+    Self {
+      bindings: vec![Value::Null; prepared.parameters.len()],
+      prepared,
+      state: State::Ready,
+    }
+  }
+
+  /// The SQL text the statement was prepared from.
+  pub fn sql(&self) -> &str {
+    &self.prepared.sql
+  }
+
+  /// Number of parameters: the largest parameter index, as `?NNN` may skip
+  /// some.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/bind_parameter_count.html
+  pub fn parameter_count(&self) -> usize {
+    self.prepared.parameters.len()
+  }
+
+  /// Name of the parameter at `index`, counting from 1, with its `?`, `:`,
+  /// `@` or `$` prefix. Parameters written `?` have none.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/bind_parameter_name.html
+  pub fn parameter_name(&self, index: usize) -> Option<&str> {
+    let idx = index.checked_sub(1)?;
+    self.prepared.parameters.get(idx)?.as_deref()
+  }
+
+  /// Index of the parameter named `name`, prefix included.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/bind_parameter_index.html
+  pub fn parameter_index(&self, name: &str) -> Option<usize> {
+    let idx = self
+      .prepared
+      .parameters
+      .iter()
+      .position(|parameter| parameter.as_deref() == Some(name))?;
+    Some(idx + 1)
+  }
+
+  /// Binds `value` to the parameter at `index`, counting from 1. The
+  /// statement must not have started running since it was prepared or
+  /// reset.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/bind_blob.html
+  pub fn bind(
+    &mut self,
+    index: usize,
+    value: impl Into<Value>,
+  ) -> SqliteResult<()> {
+    if !matches!(self.state, State::Ready) {
+      return Err(SqliteError::Custom(
+        "bad parameter or other API misuse".into(),
+      ));
+    }
+    let binding = index
+      .checked_sub(1)
+      .and_then(|idx| self.bindings.get_mut(idx))
+      .ok_or(SqliteError::Custom("column index out of range".into()))?;
+    *binding = value.into();
+    Ok(())
+  }
+
+  /// Binds `value` to the parameter named `name`, prefix included.
+  pub fn bind_by_name(
+    &mut self,
+    name: &str,
+    value: impl Into<Value>,
+  ) -> SqliteResult<()> {
+    let index = self.parameter_index(name).unwrap_or(0);
+    self.bind(index, value)
+  }
+
+  /// Sets all the parameters back to NULL.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/clear_bindings.html
+  pub fn clear_bindings(&mut self) {
+    self.bindings.fill(Value::Null);
+  }
+
+  /// Ends the current run of the statement, for the next one to start over.
+  /// The values bound to the parameters are kept.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/reset.html
+  pub fn reset(&mut self) {
+    self.state = State::Ready;
+  }
+
+  /// Runs the statement on `conn` up to its next row. A query returns its
+  /// rows one at a time, and any other statement runs to completion at
+  /// once, without rows. Once done, the statement returns no more rows
+  /// until it is reset.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/step.html
+  pub fn step(
+    &mut self,
+    conn: &mut SqliteConnection,
+  ) -> SqliteResult<Option<Row>> {
+    if let State::Ready = self.state {
+      self.state = State::Done;
+      if !is_query(&self.prepared.statement.kind) {
+        run(conn, &self.prepared, &self.bindings)?;
+        return Ok(None);
+      }
+      let plan = plan_query(conn, &self.prepared)?;
+      self.state = State::Running(Box::new(Running {
+        root: plan.root,
+        columns: plan.columns.into(),
+        subqueries: plan.subqueries,
+      }));
+    }
+    let State::Running(running) = &mut self.state else {
+      return Ok(None);
+    };
+    let subqueries = mem::take(&mut running.subqueries);
+    let mut context = Context::new(conn, subqueries, &self.bindings);
+    let result = running.root.next(&mut context);
+    running.subqueries = context.subqueries;
+    match result {
+      Ok(Some(values)) => Ok(Some(Row {
+        columns: running.columns.clone(),
+        values,
+      })),
+      Ok(None) => {
+        self.state = State::Done;
+        Ok(None)
+      }
+      Err(error) => {
+        self.state = State::Done;
+        Err(error)
+      }
+    }
+  }
+
+  /// Runs the `SELECT` statement on `conn` from the start. Rows are read
+  /// from the database as the returned iterator advances.
+  pub fn query<'a>(
+    &'a mut self,
+    conn: &'a mut SqliteConnection,
+  ) -> SqliteResult<Rows<'a>> {
+    self.reset();
+    start_query(conn, &self.prepared, &self.bindings)
+  }
+
+  /// Runs the `INSERT`, `UPDATE` or `DELETE` statement on `conn` from the
+  /// start, and returns the number of rows it changed, or the `CREATE`,
+  /// `DROP` or `ALTER TABLE` statement. The changes are committed once the
+  /// statement completes, and undone if it fails.
+  pub fn execute(&mut self, conn: &mut SqliteConnection) -> SqliteResult<u64> {
+    self.reset();
+    run(conn, &self.prepared, &self.bindings)
+  }
+}
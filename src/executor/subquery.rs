@@ -0,0 +1,450 @@
+//! # Subqueries
+//!
+//!  The subqueries of a statement are planned with it, and run by its
+//! context when their result is needed. Those of expressions run again for
+//! each row of the query they are in when they read its values, and only
+//! once otherwise. The rows of those of the `FROM` clause are held in memory
+//! when their query reads them several times, and computed again when the
+//! rows of the enclosing queries they read change.
+//!
+//! *Reference:* https://www.sqlite.org/lang_expr.html#subquery_expressions
+
+use super::expr::Comparator;
+use super::operator::Operator;
+use super::query_plan::QueryPlan;
+use super::sorter::{compare_rows, SortKey};
+use super::value::KeySet;
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{Affinity, Collation, Value};
+use core::cmp::Ordering;
+use core::slice;
+use std::collections::VecDeque;
+
+/// A subquery of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubqueryRef {
+  /// The position of the subquery among those of the statement.
+  pub(crate) id: usize,
+  /// The positions of the values of the current row it reads.
+  pub(crate) columns: Vec<usize>,
+}
+
+/// What is wanted of the rows of a subquery.
+#[derive(Debug, Clone)]
+pub(crate) enum SubqueryKind {
+  /// The first value of the first row, NULL without rows.
+  Scalar,
+  /// Whether there are rows.
+  Exists,
+  /// The first value of every row, which the value on the left of `IN` is
+  /// compared to as the comparator says.
+  In(Comparator),
+  /// Every row, as those of a table of the `FROM` clause.
+  Rows,
+}
+
+/// A subquery, and its result once it ran.
+#[derive(Debug)]
+pub(crate) struct Subquery {
+  /// `None` for the table a recursive common table expression reads, whose
+  /// rows it sets as it runs.
+  root: Option<Box<dyn Operator>>,
+  kind: SubqueryKind,
+  /// Whether it reads the rows of enclosing queries.
+  correlated: bool,
+  /// The result of the last run, and the rows of the enclosing queries it
+  /// was computed from when it reads them.
+  result: Option<(Vec<Vec<Value>>, Outcome)>,
+}
+
+#[derive(Debug)]
+enum Outcome {
+  Value(Value),
+  /// The values of an `IN` subquery, after the affinity of the comparison.
+  Set {
+    keys: KeySet,
+    has_null: bool,
+    is_empty: bool,
+  },
+  Rows(Vec<Vec<Value>>),
+}
+
+impl Subquery {
+  pub(crate) fn new(
+    root: Option<Box<dyn Operator>>,
+    kind: SubqueryKind,
+    correlated: bool,
+  ) -> Self {
+    Self {
+      root,
+      kind,
+      correlated,
+      result: None,
+    }
+  }
+
+  /// The operators of the subquery, `None` for the table a recursive
+  /// common table expression reads.
+  pub(crate) fn root(&self) -> Option<&dyn Operator> {
+    self.root.as_deref()
+  }
+
+  pub(crate) fn kind(&self) -> &SubqueryKind {
+    &self.kind
+  }
+
+  pub(crate) fn is_correlated(&self) -> bool {
+    self.correlated
+  }
+}
+
+impl Context<'_> {
+  /// The value of the scalar or `EXISTS` subquery `id`, for the current
+  /// `row`.
+  pub(crate) fn subquery_value(
+    &mut self,
+    id: usize,
+    row: &[Value],
+  ) -> SqliteResult<Value> {
+    match self.subquery(id, Some(row))? {
+      Outcome::Value(value) => Ok(value.clone()),
+      _ => Err(not_a(id, "scalar")),
+    }
+  }
+
+  /// Whether `value` is among the values of the `IN` subquery `id`, for the
+  /// current `row`. `None` when it is NULL, or when it is not found but the
+  /// subquery has NULL values.
+  pub(crate) fn subquery_contains(
+    &mut self,
+    id: usize,
+    row: &[Value],
+    value: &Value,
+  ) -> SqliteResult<Option<bool>> {
+    let comparator = match self.subqueries.get(id).map(|s| &s.kind) {
+      Some(SubqueryKind::In(comparator)) => comparator.clone(),
+      _ => return Err(not_a(id, "IN")),
+    };
+    let Outcome::Set {
+      keys,
+      has_null,
+      is_empty,
+    } = self.subquery(id, Some(row))?
+    else {
+      return Err(not_a(id, "IN"));
+    };
+    Ok(match value {
+      _ if *is_empty => Some(false),
+      Value::Null => None,
+      value if keys.contains(&[in_value(value, &comparator)])? => Some(true),
+      _ if *has_null => None,
+      _ => Some(false),
+    })
+  }
+
+  /// The row at `position` among those of the subquery `id` of the `FROM`
+  /// clause, as it last ran.
+  pub(crate) fn subquery_row(
+    &mut self,
+    id: usize,
+    position: usize,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if self.subqueries.get(id).map_or(true, |s| s.result.is_none()) {
+      self.subquery(id, None)?;
+    }
+    match self.subqueries.get(id).and_then(|s| s.result.as_ref()) {
+      Some((_, Outcome::Rows(rows))) => Ok(rows.get(position).cloned()),
+      _ => Err(not_a(id, "FROM clause")),
+    }
+  }
+
+  /// Sets the rows of the table read by a recursive common table expression.
+  pub(crate) fn set_subquery_rows(&mut self, id: usize, rows: Vec<Vec<Value>>) {
+    if let Some(subquery) = self.subqueries.get_mut(id) {
+      subquery.result = Some((vec![], Outcome::Rows(rows)));
+    }
+  }
+
+  /// The result of the subquery `id`, run unless the rows it reads are the
+  /// same as when it last ran. For the subqueries of expressions, `row` is
+  /// the current row of the query they are in.
+  fn subquery(
+    &mut self,
+    id: usize,
+    row: Option<&[Value]>,
+  ) -> SqliteResult<&mut Outcome> {
+    if let Some(row) = row {
+      self.outer.push(row.to_vec());
+    }
+    let result = self.run_subquery(id);
+    if row.is_some() {
+      self.outer.pop();
+    }
+    result?;
+    match self.subqueries.get_mut(id).and_then(|s| s.result.as_mut()) {
+      Some((_, outcome)) => Ok(outcome),
+      None => Err(SqliteError::Custom(format!("Subquery {id} did not run"))),
+    }
+  }
+
+  fn run_subquery(&mut self, id: usize) -> SqliteResult<()> {
+    let subquery = self
+      .subqueries
+      .get_mut(id)
+      .ok_or(SqliteError::Custom(format!("No subquery {id}")))?;
+    let is_known = match &subquery.result {
+      Some((outer, _)) => !subquery.correlated || *outer == self.outer,
+      None => false,
+    };
+    if is_known {
+      return Ok(());
+    }
+    let Some(mut root) = subquery.root.take() else {
+      subquery.result = Some((vec![], Outcome::Rows(vec![])));
+      return Ok(());
+    };
+    root.reset();
+    let outcome = self.collect(id, root.as_mut());
+    let subquery = &mut self.subqueries[id];
+    subquery.root = Some(root);
+    let outer = match subquery.correlated {
+      true => self.outer.clone(),
+      false => vec![],
+    };
+    subquery.result = Some((outer, outcome?));
+    Ok(())
+  }
+
+  /// Reads as many rows of `root` as the kind of the subquery `id` needs.
+  fn collect(
+    &mut self,
+    id: usize,
+    root: &mut dyn Operator,
+  ) -> SqliteResult<Outcome> {
+    Ok(match self.subqueries[id].kind.clone() {
+      SubqueryKind::Scalar => {
+        let row = root.next(self)?;
+        Outcome::Value(
+          row
+            .and_then(|row| row.into_iter().next())
+            .unwrap_or(Value::Null),
+        )
+      }
+      SubqueryKind::Exists => {
+        Outcome::Value(Value::Integer(i64::from(root.next(self)?.is_some())))
+      }
+      SubqueryKind::In(comparator) => {
+        let mut keys = KeySet::new(slice::from_ref(comparator.collation()));
+        let (mut has_null, mut is_empty) = (false, true);
+        while let Some(row) = root.next(self)? {
+          is_empty = false;
+          match row.first() {
+            Some(Value::Null) | None => has_null = true,
+            Some(value) => {
+              let value = in_value(value, &comparator);
+              keys.insert(&[value], self.sort_memory_limit())?;
+            }
+          }
+        }
+        Outcome::Set {
+          keys,
+          has_null,
+          is_empty,
+        }
+      }
+      SubqueryKind::Rows => {
+        let mut rows = vec![];
+        while let Some(row) = root.next(self)? {
+          rows.push(row);
+        }
+        Outcome::Rows(rows)
+      }
+    })
+  }
+}
+
+/// The value `comparator` compares.
+fn in_value(value: &Value, comparator: &Comparator) -> Value {
+  match comparator.affinity() {
+    None | Some(Affinity::Blob) => value.clone(),
+    Some(affinity) => affinity.apply(value.clone()),
+  }
+}
+
+fn not_a(id: usize, kind: &str) -> SqliteError {
+  SqliteError::Custom(format!("Subquery {id} is not a {kind} subquery"))
+}
+
+/// Reads the rows of a subquery of the `FROM` clause, held in memory.
+#[derive(Debug)]
+pub(crate) struct SubqueryScan {
+  id: usize,
+  /// The name the query gives the subquery.
+  name: String,
+  position: usize,
+  started: bool,
+}
+
+impl SubqueryScan {
+  pub(crate) fn new(id: usize, name: String) -> Self {
+    Self {
+      id,
+      name,
+      position: 0,
+      started: false,
+    }
+  }
+
+  /// Starts over, running the subquery again if the rows of the enclosing
+  /// queries it reads changed.
+  pub(crate) fn rewind(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    ctx.subquery(self.id, None)?;
+    self.started = true;
+    self.position = 0;
+    Ok(())
+  }
+
+  /// The next row, once rewound.
+  pub(crate) fn next_row(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let row = ctx.subquery_row(self.id, self.position)?;
+    self.position += 1;
+    Ok(row)
+  }
+}
+
+impl Operator for SubqueryScan {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.started {
+      self.rewind(ctx)?;
+    }
+    self.next_row(ctx)
+  }
+
+  fn reset(&mut self) {
+    self.started = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    plan.materialize(self.id, &self.name);
+    plan.add(format!("SCAN {}", self.name));
+  }
+}
+
+/// The rows of a recursive common table expression: those of its initial
+/// select, queued, then those its recursive selects produce from each row
+/// taken off the queue in turn, each time alone in the table they read.
+///
+/// *Reference:* https://www.sqlite.org/lang_with.html#recursive_common_table_expressions
+#[derive(Debug)]
+pub(crate) struct Recursive {
+  /// The name of the common table expression.
+  name: String,
+  initial: Box<dyn Operator>,
+  recursive: Box<dyn Operator>,
+  /// The subquery standing for the table the recursive selects read.
+  table: usize,
+  /// The order of the queue, from its `ORDER BY` clause, or else first in,
+  /// first out.
+  keys: Vec<SortKey>,
+  queue: VecDeque<Vec<Value>>,
+  /// With `UNION`, the collating sequences of the values, and the rows
+  /// queued so far, which are not queued again.
+  distinct: Option<KeySet>,
+  /// The row taken off the queue last, which the recursive selects read
+  /// next.
+  current: Option<Vec<Value>>,
+  started: bool,
+}
+
+impl Recursive {
+  pub(crate) fn new(
+    name: String,
+    initial: Box<dyn Operator>,
+    recursive: Box<dyn Operator>,
+    table: usize,
+    keys: Vec<SortKey>,
+    distinct: Option<Vec<Collation>>,
+  ) -> Self {
+    Self {
+      name,
+      initial,
+      recursive,
+      table,
+      keys,
+      queue: VecDeque::new(),
+      distinct: distinct.map(|collations| KeySet::new(&collations)),
+      current: None,
+      started: false,
+    }
+  }
+
+  /// Queues `row`, unless `UNION` drops it, past `memory_limit` bytes of
+  /// rows seen spilling them.
+  fn enqueue(
+    &mut self,
+    row: Vec<Value>,
+    memory_limit: usize,
+  ) -> SqliteResult<()> {
+    if let Some(seen) = &mut self.distinct {
+      if !seen.insert(&row, memory_limit)? {
+        return Ok(());
+      }
+    }
+    let position = match self.keys.is_empty() {
+      true => self.queue.len(),
+      false => self.queue.partition_point(|queued| {
+        compare_rows(&self.keys, queued, &row) != Ordering::Greater
+      }),
+    };
+    self.queue.insert(position, row);
+    Ok(())
+  }
+}
+
+impl Operator for Recursive {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    if !self.started {
+      self.started = true;
+      while let Some(row) = self.initial.next(ctx)? {
+        self.enqueue(row, ctx.sort_memory_limit())?;
+      }
+    } else if let Some(current) = self.current.take() {
+      ctx.set_subquery_rows(self.table, vec![current]);
+      self.recursive.reset();
+      while let Some(row) = self.recursive.next(ctx)? {
+        self.enqueue(row, ctx.sort_memory_limit())?;
+      }
+    }
+    let row = self.queue.pop_front();
+    self.current = row.clone();
+    Ok(row)
+  }
+
+  fn reset(&mut self) {
+    self.initial.reset();
+    self.queue.clear();
+    if let Some(seen) = &mut self.distinct {
+      seen.clear();
+    }
+    self.current = None;
+    self.started = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    plan.nest(format!("CO-ROUTINE {}", self.name), |plan| {
+      plan.nest("SETUP", |plan| self.initial.describe(plan));
+      plan.nest("RECURSIVE STEP", |plan| self.recursive.describe(plan));
+    });
+    plan.add(format!("SCAN {}", self.name));
+  }
+}
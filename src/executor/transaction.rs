@@ -0,0 +1,145 @@
+//! # Transactions
+//!
+//!  Each statement changing the database runs in a transaction of its own,
+//! committed once it completes, unless `BEGIN` opened a transaction: the
+//! statements after it then run in that one, until `COMMIT` writes their
+//! changes or `ROLLBACK` discards them. A statement failing within it is
+//! undone as its conflict resolution says: `ABORT` undoes the statement
+//! alone, `FAIL` keeps the changes it made before failing, and `ROLLBACK`
+//! undoes the whole transaction.
+//!
+//!  Foreign keys declared `DEFERRABLE INITIALLY DEFERRED` are checked by
+//! `COMMIT` rather than by each statement, so that the rows of a parent and
+//! of its children may be written in any order. `COMMIT` fails while a row
+//! still misses its parent, and the transaction stays open.
+//!
+//! *Reference:* https://www.sqlite.org/lang_transaction.html,
+//! https://www.sqlite.org/foreignkeys.html#fk_deferred
+
+use super::planner::Planner;
+use super::Context;
+use crate::result::{ConstraintKind, SqliteError, SqliteResult};
+use crate::runtime::Value;
+use crate::sql::ast::{Name, QualifiedName, Span, StatementKind};
+use crate::SqliteConnection;
+use core::mem;
+
+/// A row found missing its parent by a statement of a transaction, through
+/// a deferred foreign key, for `COMMIT` to check again.
+#[derive(Debug)]
+pub(crate) struct DeferredViolation {
+  pub(crate) child: String,
+  pub(crate) parent: String,
+  pub(crate) child_columns: Vec<usize>,
+  /// The values of the child columns of the row.
+  pub(crate) values: Vec<Value>,
+}
+
+/// What the failure of a statement undoes, as its conflict resolution
+/// says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Undo {
+  /// Nothing: the statement completed, or `FAIL` keeps what it changed.
+  Nothing,
+  /// The changes of the statement, with `ABORT`.
+  Statement,
+  /// The changes of the whole transaction, with `ROLLBACK`.
+  Transaction,
+}
+
+/// Runs the statement `BEGIN`, `COMMIT` or `ROLLBACK`.
+pub(crate) fn run(
+  conn: &mut SqliteConnection,
+  statement: &StatementKind,
+) -> SqliteResult<()> {
+  match (statement, conn.in_transaction) {
+    (StatementKind::Begin(_), false) => {
+      conn.in_transaction = true;
+      Ok(())
+    }
+    (StatementKind::Begin(_), true) => Err(SqliteError::Custom(
+      "cannot start a transaction within a transaction".into(),
+    )),
+    (StatementKind::Commit, true) => {
+      check_deferred(conn)?;
+      conn.runtime_mut().commit()?;
+      conn.in_transaction = false;
+      Ok(())
+    }
+    (StatementKind::Commit, false) => Err(SqliteError::Custom(
+      "cannot commit - no transaction is active".into(),
+    )),
+    (_, true) => rollback(conn),
+    (_, false) => Err(SqliteError::Custom(
+      "cannot rollback - no transaction is active".into(),
+    )),
+  }
+}
+
+/// Starts a statement changing the database.
+pub(crate) fn begin_statement(conn: &mut SqliteConnection) {
+  conn.runtime_mut().begin_statement();
+}
+
+/// Ends the statement changing the database begun last. Out of a
+/// transaction, its changes are committed unless `undo` says otherwise.
+pub(crate) fn end_statement(
+  conn: &mut SqliteConnection,
+  undo: Undo,
+) -> SqliteResult<()> {
+  match (undo, conn.in_transaction) {
+    (Undo::Nothing, false) => conn.runtime_mut().commit(),
+    (Undo::Nothing, true) => Ok(()),
+    (Undo::Statement, true) => conn.runtime_mut().rollback_statement(),
+    (Undo::Statement | Undo::Transaction, false)
+    | (Undo::Transaction, true) => rollback(conn),
+  }
+}
+
+/// Discards the changes of the transaction, and ends it.
+fn rollback(conn: &mut SqliteConnection) -> SqliteResult<()> {
+  conn.in_transaction = false;
+  conn.deferred_violations.clear();
+  conn.runtime_mut().rollback()
+}
+
+/// Fails while a row found missing its parent in the transaction still
+/// does, keeping the rows to check again.
+fn check_deferred(conn: &mut SqliteConnection) -> SqliteResult<()> {
+  let violations = mem::take(&mut conn.deferred_violations);
+  let mut result = Ok(());
+  for violation in &violations {
+    let tables = conn.runtime.tables()?;
+    let child = &violation.child;
+    if !tables
+      .iter()
+      .any(|table| table.name().eq_ignore_ascii_case(child))
+    {
+      continue;
+    }
+    let name = QualifiedName {
+      schema: None,
+      name: Name {
+        value: violation.child.clone(),
+        quoted: false,
+        span: Span::default(),
+      },
+    };
+    let keys = Planner::new(&mut conn.runtime, &conn.functions, "")
+      .with_foreign_keys(true)
+      .foreign_keys(&name)?;
+    let Some(mut keys) = keys else { continue };
+    let mut ctx = Context::new(conn, vec![], &[]);
+    if keys.is_violated(&mut ctx, violation)? {
+      result = Err(SqliteError::Constraint {
+        kind: ConstraintKind::ForeignKey,
+        message: "FOREIGN KEY constraint failed".into(),
+      });
+      break;
+    }
+  }
+  if result.is_err() {
+    conn.deferred_violations = violations;
+  }
+  result
+}
@@ -0,0 +1,590 @@
+//! # Triggers
+//!
+//!  A trigger runs its program for each row an `INSERT`, `UPDATE` or
+//! `DELETE` statement changes in its table: before the change, after it,
+//! or, on a view, in its place. The program is planned from the text of the
+//! trigger each time it fires, and sees the values of the row before and
+//! after the change as those of the tables `OLD` and `NEW`.
+//!
+//!  Unless the `recursive_triggers` pragma is set, a trigger does not fire
+//! for the changes made by its own program.
+//!
+//! *Reference:* https://www.sqlite.org/lang_createtrigger.html
+
+use super::ddl::{find, parse_object};
+use super::dml::{assignments, Write};
+use super::expr::Expr;
+use super::operator::{Operator, Project};
+use super::planner::{
+  derived_source, no_such_table, subquery_columns, table_source, unsupported,
+  Input, Planner, Source,
+};
+use super::subquery::Subquery;
+use super::value::is_true;
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::Value;
+use crate::sql::ast::{
+  self, ConflictResolution, CreateTrigger, CreateView, Insert, Name,
+  QualifiedName, RaiseAction, StatementKind, TriggerEvent, TriggerTime, Update,
+};
+use core::mem;
+use std::borrow::Cow;
+
+/// How many trigger programs may run within one another.
+const MAX_DEPTH: usize = 1000;
+
+/// The change of a row that fires triggers.
+#[derive(Debug)]
+pub(crate) enum Event {
+  Insert,
+  /// An update of the columns with these names.
+  Update(Vec<String>),
+  Delete,
+}
+
+impl Event {
+  /// Whether a trigger on `event` fires: for updates, when one of the
+  /// columns of its `UPDATE OF` list is updated, if it has one.
+  pub(crate) fn fires(&self, event: &TriggerEvent) -> bool {
+    match (self, event) {
+      (Self::Insert, TriggerEvent::Insert)
+      | (Self::Delete, TriggerEvent::Delete) => true,
+      (Self::Update(updated), TriggerEvent::Update(columns)) => {
+        columns.is_empty()
+          || columns
+            .iter()
+            .any(|column| updated.iter().any(|name| column.is(name)))
+      }
+      _ => false,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub(crate) struct Trigger {
+  name: String,
+  /// The `CREATE TRIGGER` statement, as kept in the schema, and parsed.
+  sql: String,
+  create: CreateTrigger,
+}
+
+impl Trigger {
+  pub(crate) fn new(name: &str, sql: &str, create: CreateTrigger) -> Self {
+    Self {
+      name: name.into(),
+      sql: sql.into(),
+      create,
+    }
+  }
+
+  pub(crate) fn time(&self) -> TriggerTime {
+    self.create.time
+  }
+
+  /// Plans the program of the trigger, then runs it for `row`.
+  fn run(
+    &self,
+    ctx: &mut Context<'_>,
+    row: &[Value],
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<()> {
+    let conn = &mut *ctx.conn;
+    let mut program =
+      Planner::new(&mut conn.runtime, &conn.functions, &self.sql)
+        .with_foreign_keys(conn.foreign_keys)
+        .trigger_program(&self.create, conflict)?;
+    program.run(ctx, row)
+  }
+}
+
+/// The triggers a statement fires on the table or view it changes, the
+/// most recently created first.
+#[derive(Debug)]
+pub(crate) struct Triggers {
+  triggers: Vec<Trigger>,
+  /// The number of values of the rows of the table: those of its columns,
+  /// followed by the rowid for tables.
+  width: usize,
+  /// The `OR` clause of the statement, which overrides those of the
+  /// statements of the programs.
+  conflict: Option<ConflictResolution>,
+}
+
+impl Triggers {
+  /// The `triggers` of a table whose rows have `width` values, unless there
+  /// are none.
+  pub(crate) fn new(
+    triggers: Vec<Trigger>,
+    width: usize,
+    conflict: Option<ConflictResolution>,
+  ) -> Option<Self> {
+    match triggers.is_empty() {
+      true => None,
+      false => Some(Self {
+        triggers,
+        width,
+        conflict,
+      }),
+    }
+  }
+
+  /// Whether any of the triggers fires at `time`.
+  pub(crate) fn has(&self, time: TriggerTime) -> bool {
+    self.triggers.iter().any(|trigger| trigger.time() == time)
+  }
+
+  /// Runs the programs of the triggers firing at `time`, for a row whose
+  /// values are `old` before the change and `new` after it. Returns false
+  /// when a program raised `IGNORE`, which abandons the change of the row.
+  pub(crate) fn fire(
+    &self,
+    ctx: &mut Context<'_>,
+    time: TriggerTime,
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+  ) -> SqliteResult<bool> {
+    let mut row = vec![Value::Null; 2 * self.width];
+    if let Some(old) = old {
+      row[..self.width].clone_from_slice(old);
+    }
+    if let Some(new) = new {
+      row[self.width..].clone_from_slice(new);
+    }
+    for trigger in self.triggers.iter().filter(|t| t.time() == time) {
+      let is_running = ctx.triggers.contains(&trigger.name);
+      if is_running && !ctx.conn.recursive_triggers {
+        continue;
+      }
+      if ctx.triggers.len() >= MAX_DEPTH {
+        return Err(SqliteError::Custom(
+          "too many levels of trigger recursion".into(),
+        ));
+      }
+      ctx.triggers.push(trigger.name.clone());
+      let result = trigger.run(ctx, &row, self.conflict);
+      ctx.triggers.pop();
+      match result {
+        Ok(()) => {}
+        Err(_) if ctx.raised == Some(RaiseAction::Ignore) => {
+          ctx.raised = None;
+          return Ok(false);
+        }
+        Err(error) => return Err(error),
+      }
+    }
+    Ok(true)
+  }
+}
+
+/// The program of a trigger, planned to run for a row.
+#[derive(Debug)]
+pub(crate) struct TriggerProgram {
+  /// The `WHEN` clause, with its subqueries.
+  when: Option<(Expr, Vec<Subquery>)>,
+  steps: Vec<Step>,
+}
+
+/// A statement of the program of a trigger.
+#[derive(Debug)]
+pub(crate) enum Step {
+  Write(Write),
+  /// A `SELECT` statement, whose rows are read and thrown away, with its
+  /// subqueries.
+  Select(Box<dyn Operator>, Vec<Subquery>),
+}
+
+impl TriggerProgram {
+  pub(crate) fn new(
+    when: Option<(Expr, Vec<Subquery>)>,
+    steps: Vec<Step>,
+  ) -> Self {
+    Self { when, steps }
+  }
+
+  /// Runs the statements of the program in turn, unless the `WHEN` clause
+  /// is not true for `row`, which holds the values of `OLD` then `NEW`.
+  fn run(&mut self, ctx: &mut Context<'_>, row: &[Value]) -> SqliteResult<()> {
+    if let Some((when, subqueries)) = &mut self.when {
+      let value = ctx.with_subqueries(subqueries, |ctx| when.eval(ctx, row))?;
+      if is_true(&value) != Some(true) {
+        return Ok(());
+      }
+    }
+    // The statements read the row as that of an enclosing query.
+    ctx.outer.push(row.to_vec());
+    let result = self.steps.iter_mut().try_for_each(|step| step.run(ctx));
+    ctx.outer.pop();
+    result
+  }
+}
+
+impl Step {
+  fn run(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    match self {
+      Self::Write(write) => {
+        let mut subqueries = write.take_subqueries();
+        let result = ctx.with_subqueries(&mut subqueries, |ctx| write.run(ctx));
+        if result.is_err() && write.keeps_changes() {
+          ctx.raised = Some(RaiseAction::Fail);
+        }
+        result?;
+        ctx.conn.total_changes += write.changes();
+        Ok(())
+      }
+      Self::Select(root, subqueries) => {
+        ctx.with_subqueries(subqueries, |ctx| {
+          while root.next(ctx)?.is_some() {}
+          Ok(())
+        })
+      }
+    }
+  }
+}
+
+/// A planned `INSERT`, `UPDATE` or `DELETE` statement on a view, which its
+/// `INSTEAD OF` triggers carry out. It changes no rows itself.
+#[derive(Debug)]
+pub(crate) struct ViewChange {
+  /// The rows of the view changed: their values before the change, then
+  /// after it, NULL when the statement has none.
+  rows: Box<dyn Operator>,
+  triggers: Triggers,
+  pub(crate) subqueries: Vec<Subquery>,
+  /// Set when a program raised `FAIL`, which keeps the changes made
+  /// before.
+  failed: bool,
+}
+
+impl ViewChange {
+  pub(crate) fn new(
+    rows: Box<dyn Operator>,
+    triggers: Triggers,
+    subqueries: Vec<Subquery>,
+  ) -> Self {
+    Self {
+      rows,
+      triggers,
+      subqueries,
+      failed: false,
+    }
+  }
+
+  pub(crate) fn keeps_changes(&self) -> bool {
+    self.failed
+  }
+
+  pub(crate) fn run(&mut self, ctx: &mut Context<'_>) -> SqliteResult<()> {
+    let mut rows = vec![];
+    while let Some(row) = self.rows.next(ctx)? {
+      rows.push(row);
+    }
+    let width = self.triggers.width;
+    for row in rows {
+      let (old, new) = row.split_at(width);
+      let time = TriggerTime::InsteadOf;
+      let result = self.triggers.fire(ctx, time, Some(old), Some(new));
+      if result.is_err() && ctx.raised == Some(RaiseAction::Fail) {
+        self.failed = true;
+      }
+      result?;
+    }
+    Ok(())
+  }
+}
+
+impl Context<'_> {
+  /// Runs `f` with `subqueries` in place of those of the statement.
+  fn with_subqueries<T>(
+    &mut self,
+    subqueries: &mut Vec<Subquery>,
+    f: impl FnOnce(&mut Self) -> SqliteResult<T>,
+  ) -> SqliteResult<T> {
+    mem::swap(&mut self.subqueries, subqueries);
+    let result = f(self);
+    mem::swap(&mut self.subqueries, subqueries);
+    result
+  }
+}
+
+impl Planner<'_> {
+  /// Plans an `INSERT` statement on a view, whose `INSTEAD OF` triggers see
+  /// the values supplied as the new row, NULL for the other columns.
+  pub(super) fn insert_view(
+    &mut self,
+    insert: &Insert,
+    view: Source,
+  ) -> SqliteResult<Write> {
+    let width = view.width;
+    let named = match insert.columns.is_empty() {
+      true => (0..width).collect(),
+      false => insert
+        .columns
+        .iter()
+        .map(|name| {
+          view
+            .columns
+            .iter()
+            .position(|column| name.is(&column.name))
+            .ok_or(SqliteError::Custom(format!(
+              "table {} has no column named {}",
+              view.name, name.value
+            )))
+        })
+        .collect::<SqliteResult<Vec<_>>>()?,
+    };
+    let triggers =
+      self.view_triggers(&view.name, Event::Insert, width, insert.conflict)?;
+    let (rows, named) = self.inserted_rows(insert, &view.name, width, named)?;
+    let mut exprs = vec![Expr::Literal(Value::Null); 2 * width];
+    for (position, column) in named.into_iter().enumerate() {
+      exprs[width + column] = Expr::Slot(position);
+    }
+    let rows = Box::new(Project::new(rows, exprs));
+    let subqueries = mem::take(&mut self.subqueries);
+    Ok(Write::View(ViewChange::new(rows, triggers, subqueries)))
+  }
+
+  /// Plans an `UPDATE` statement on a view, whose `INSTEAD OF` triggers see
+  /// the rows of the view it selects, and their values once updated.
+  pub(super) fn update_view(
+    &mut self,
+    update: &Update,
+    view: Source,
+  ) -> SqliteResult<Write> {
+    let width = view.width;
+    let (columns, values) = assignments(update, |name| {
+      view.columns.iter().position(|column| name.is(&column.name))
+    })?;
+    let updated = columns
+      .iter()
+      .map(|&column| view.columns[column].name.clone())
+      .collect();
+    let event = Event::Update(updated);
+    let triggers =
+      self.view_triggers(&view.name, event, width, update.conflict)?;
+    let view_rows = |planner: &mut Self, offset| {
+      planner
+        .view(&update.table, update.alias.as_ref(), offset)?
+        .ok_or_else(|| no_such_table(&update.table))
+    };
+    let rows = self.update_rows(update, view_rows, |planner, offset| {
+      let old = (offset..offset + width).map(Expr::Slot).collect::<Vec<_>>();
+      let mut new = old.clone();
+      for (&column, value) in columns.iter().zip(values) {
+        new[column] = planner.expr(value)?;
+      }
+      Ok([old, new].concat())
+    })?;
+    let subqueries = mem::take(&mut self.subqueries);
+    Ok(Write::View(ViewChange::new(rows, triggers, subqueries)))
+  }
+
+  /// The triggers on the table or view `name` that `event` fires, for rows
+  /// of `width` values, the most recently created first. Their programs
+  /// resolve failed constraints as `conflict` says, when given.
+  pub(super) fn triggers(
+    &mut self,
+    name: &str,
+    event: &Event,
+    width: usize,
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<Option<Triggers>> {
+    let mut triggers = vec![];
+    for entry in self.runtime.schema()? {
+      if entry.kind() != "trigger"
+        || !entry.tbl_name().eq_ignore_ascii_case(name)
+      {
+        continue;
+      }
+      let Some(sql) = entry.sql() else {
+        continue;
+      };
+      let StatementKind::CreateTrigger(create) = parse_object(sql)? else {
+        return Err(SqliteError::Corrupt(format!(
+          "Malformed CREATE TRIGGER statement: {sql}"
+        )));
+      };
+      if event.fires(&create.event) {
+        triggers.push(Trigger::new(entry.name(), sql, *create));
+      }
+    }
+    triggers.reverse();
+    Ok(Triggers::new(triggers, width, conflict))
+  }
+
+  /// The `INSTEAD OF` triggers of the view `name` that `event` fires, which
+  /// carry out a statement changing the view: there must be some.
+  pub(super) fn view_triggers(
+    &mut self,
+    name: &str,
+    event: Event,
+    width: usize,
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<Triggers> {
+    self
+      .triggers(name, &event, width, conflict)?
+      .filter(|triggers| triggers.has(TriggerTime::InsteadOf))
+      .ok_or_else(|| {
+        SqliteError::Custom(format!(
+          "cannot modify {name} because it is a view"
+        ))
+      })
+  }
+
+  /// Plans the program of the trigger `create`, whose statements resolve
+  /// failed constraints as `conflict` says, when given. The rows it runs
+  /// for hold the values of `OLD`, then those of `NEW`.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_createtrigger.html
+  pub(crate) fn trigger_program(
+    &mut self,
+    create: &CreateTrigger,
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<TriggerProgram> {
+    let table = QualifiedName {
+      schema: None,
+      name: create.table.name.clone(),
+    };
+    let (old, new) = match self.view(&table, None, 0)? {
+      Some((_, view)) => (
+        derived_source("old", view.columns.clone(), 0),
+        derived_source("new", view.columns, view.width),
+      ),
+      None => {
+        let cursor = self.runtime.table(&table.name.value)?;
+        let definition = cursor.definition();
+        let width = definition.columns().len() + 1;
+        (
+          table_source(definition, "old", 0),
+          table_source(definition, "new", width),
+        )
+      }
+    };
+    self.subqueries.clear();
+    let sources = match create.event {
+      TriggerEvent::Insert => vec![new],
+      TriggerEvent::Update(_) => vec![old, new],
+      TriggerEvent::Delete => vec![old],
+    };
+    self.in_trigger = true;
+    self.in_scope(sources, |planner| {
+      let when = match &create.when {
+        Some(when) => {
+          let when = planner.expr(when)?;
+          Some((when, mem::take(&mut planner.subqueries)))
+        }
+        None => None,
+      };
+      let mut steps = vec![];
+      for statement in create.body.iter() {
+        steps.push(planner.trigger_step(statement, conflict)?);
+        // The values of the row are there whatever the statements read.
+        planner.outer_columns.clear();
+      }
+      Ok(TriggerProgram::new(when, steps))
+    })
+  }
+
+  /// Plans a statement of the program of a trigger.
+  fn trigger_step(
+    &mut self,
+    statement: &ast::Statement,
+    conflict: Option<ConflictResolution>,
+  ) -> SqliteResult<Step> {
+    let write = match &statement.kind {
+      StatementKind::Insert(insert) => self.insert(insert)?,
+      StatementKind::Update(update) => self.update(update)?,
+      StatementKind::Delete(delete) => self.delete(delete)?,
+      StatementKind::Select(select) => {
+        let query = self.query(select, None)?;
+        let subqueries = mem::take(&mut self.subqueries);
+        return Ok(Step::Select(query.root, subqueries));
+      }
+      _ => return Err(unsupported("This statement in a trigger program")),
+    };
+    Ok(Step::Write(write.with_conflict(conflict)))
+  }
+
+  /// The rows of the view `name`, if there is one: its select is planned
+  /// as a subquery of the `FROM` clause, which sees none of the tables of
+  /// the statement.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_createview.html
+  pub(super) fn view(
+    &mut self,
+    name: &QualifiedName,
+    alias: Option<&Name>,
+    offset: usize,
+  ) -> SqliteResult<Option<(Input, Source)>> {
+    let Some((sql, create)) = self.view_definition(name)? else {
+      return Ok(None);
+    };
+    let view = &create.name.name.value;
+    if self
+      .views
+      .iter()
+      .any(|other| other.eq_ignore_ascii_case(view))
+    {
+      return Err(SqliteError::Custom(format!(
+        "view {view} is circularly defined"
+      )));
+    }
+    self.views.push(view.clone());
+    let sql = mem::replace(&mut self.sql, Cow::Owned(sql));
+    let scopes = mem::take(&mut self.scopes);
+    let ctes = mem::take(&mut self.ctes);
+    let planned = self.nested(0, |planner| planner.query(&create.select, None));
+    self.sql = sql;
+    self.scopes = scopes;
+    self.ctes = ctes;
+    self.views.pop();
+    let (query, _, _) = planned?;
+    let names = create
+      .columns
+      .iter()
+      .map(|column| column.value.clone())
+      .collect::<Vec<_>>();
+    if !names.is_empty() && names.len() != query.columns.len() {
+      return Err(SqliteError::Custom(format!(
+        "expected {} columns for '{view}' but got {}",
+        names.len(),
+        query.columns.len()
+      )));
+    }
+    let columns = subquery_columns(&query, &names, view)?;
+    let name = alias.map_or(view, |alias| &alias.value);
+    let input = Input::Subquery {
+      root: query.root,
+      correlated: false,
+    };
+    Ok(Some((input, derived_source(name, columns, offset))))
+  }
+
+  /// The text of the view `name`, and its `CREATE VIEW` statement, if there
+  /// is a view of that name.
+  fn view_definition(
+    &mut self,
+    name: &QualifiedName,
+  ) -> SqliteResult<Option<(String, CreateView)>> {
+    if name
+      .schema
+      .as_ref()
+      .is_some_and(|schema| !schema.is("main"))
+    {
+      return Ok(None);
+    }
+    let schema = self.runtime.schema()?;
+    let Some(sql) =
+      find(&schema, &["view"], &name.name.value).and_then(|entry| entry.sql())
+    else {
+      return Ok(None);
+    };
+    match parse_object(sql)? {
+      StatementKind::CreateView(create) => Ok(Some((sql.to_owned(), *create))),
+      _ => Err(SqliteError::Corrupt(format!(
+        "Malformed CREATE VIEW statement: {sql}"
+      ))),
+    }
+  }
+}
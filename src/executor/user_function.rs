@@ -0,0 +1,342 @@
+//! # Application-defined functions
+//!
+//!  Functions registered on a connection are called by its statements like
+//! the built-in ones, over which they take precedence: a call is resolved
+//! to the function registered with its name and number of arguments, else
+//! to the one registered with its name and any number of arguments.
+//!
+//! *Reference:* https://www.sqlite.org/appfunc.html
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::Value;
+use core::any::Any;
+use core::fmt::{self, Debug, Formatter};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The largest number of arguments a function may be registered with.
+const MAX_ARITY: usize = 127;
+/// The longest name, in bytes, a function may be registered with.
+const MAX_NAME_LENGTH: usize = 255;
+
+type Scalar = dyn Fn(&[Value]) -> SqliteResult<Value> + Send + Sync;
+/// The state of an aggregate over the rows of a group or of a frame.
+pub(crate) type UserState = Box<dyn Any + Send>;
+type Start = dyn Fn() -> UserState + Send + Sync;
+type Step = dyn Fn(&mut dyn Any, &[Value]) -> SqliteResult<()> + Send + Sync;
+type Current = dyn Fn(&dyn Any) -> SqliteResult<Value> + Send + Sync;
+type Finalize = dyn Fn(UserState) -> SqliteResult<Value> + Send + Sync;
+
+/// The functions registered on a connection, by lowercase name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Functions {
+  by_name: HashMap<String, Vec<UserFunction>>,
+}
+
+impl Functions {
+  /// Registers `function`, in place of the one with the same name and
+  /// arity.
+  pub(crate) fn register(
+    &mut self,
+    function: UserFunction,
+  ) -> SqliteResult<()> {
+    let Definition { name, arity, .. } = &*function.0;
+    let is_valid = !name.is_empty()
+      && name.len() <= MAX_NAME_LENGTH
+      && arity.map_or(true, |arity| arity <= MAX_ARITY);
+    if !is_valid {
+      return Err(SqliteError::Custom(
+        "bad parameter or other API misuse".into(),
+      ));
+    }
+    let overloads = self.by_name.entry(name.to_ascii_lowercase()).or_default();
+    overloads.retain(|other| other.0.arity != function.0.arity);
+    overloads.push(function);
+    Ok(())
+  }
+
+  /// The function a call to `name` with `argc` arguments resolves to.
+  pub(crate) fn find(&self, name: &str, argc: usize) -> Option<&UserFunction> {
+    let overloads = self.by_name.get(&name.to_ascii_lowercase())?;
+    let exact = overloads.iter().find(|f| f.0.arity == Some(argc));
+    exact.or_else(|| overloads.iter().find(|f| f.0.arity.is_none()))
+  }
+
+  /// Every function registered.
+  pub(crate) fn iter(&self) -> impl Iterator<Item = &UserFunction> {
+    self.by_name.values().flatten()
+  }
+
+  /// Whether a function named `name` is registered, whatever its arity.
+  pub(crate) fn contains(&self, name: &str) -> bool {
+    self.by_name.contains_key(&name.to_ascii_lowercase())
+  }
+}
+
+/// A function registered on a connection, shared by the statements
+/// calling it.
+#[derive(Clone)]
+pub(crate) struct UserFunction(Arc<Definition>);
+
+struct Definition {
+  name: String,
+  /// The number of arguments it takes, `None` for any.
+  arity: Option<usize>,
+  /// Whether it always returns the same result for the same arguments.
+  deterministic: bool,
+  kind: Kind,
+}
+
+enum Kind {
+  Scalar(Box<Scalar>),
+  Aggregate(Aggregate),
+}
+
+/// The callbacks of an aggregate function. Those of a window function also
+/// take a row out of the state, and return the result without consuming
+/// it.
+struct Aggregate {
+  start: Box<Start>,
+  step: Box<Step>,
+  inverse: Option<Box<Step>>,
+  value: Option<Box<Current>>,
+  finalize: Box<Finalize>,
+}
+
+impl UserFunction {
+  pub(crate) fn scalar<F>(
+    name: &str,
+    arity: Option<usize>,
+    deterministic: bool,
+    function: F,
+  ) -> Self
+  where
+    F: Fn(&[Value]) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    Self(Arc::new(Definition {
+      name: name.to_owned(),
+      arity,
+      deterministic,
+      kind: Kind::Scalar(Box::new(function)),
+    }))
+  }
+
+  /// An aggregate function folding the rows of each group into a state
+  /// that starts as `S::default()`.
+  pub(crate) fn aggregate<S, F, G>(
+    name: &str,
+    arity: Option<usize>,
+    step: F,
+    finalize: G,
+  ) -> Self
+  where
+    S: Default + Send + 'static,
+    F: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    G: Fn(S) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    let finalize: Box<Finalize> =
+      Box::new(move |state: UserState| match state.downcast::<S>() {
+        Ok(state) => finalize(*state),
+        Err(_) => Err(wrong_state()),
+      });
+    Self::with_callbacks(
+      name,
+      arity,
+      Aggregate {
+        start: start::<S>(),
+        step: step_with(step),
+        inverse: None,
+        value: None,
+        finalize,
+      },
+    )
+  }
+
+  /// An aggregate function that may also be used as a window function:
+  /// rows leaving the frame are taken out of its state by `inverse`, and
+  /// `value` returns the result for the current frame.
+  pub(crate) fn window<S, F, I, V>(
+    name: &str,
+    arity: Option<usize>,
+    step: F,
+    inverse: I,
+    value: V,
+  ) -> Self
+  where
+    S: Default + Send + 'static,
+    F: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    I: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    V: Fn(&S) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    let value = Arc::new(value);
+    let current = value.clone();
+    let current: Box<Current> =
+      Box::new(move |state: &dyn Any| match state.downcast_ref::<S>() {
+        Some(state) => current(state),
+        None => Err(wrong_state()),
+      });
+    let finalize: Box<Finalize> =
+      Box::new(move |state: UserState| match state.downcast_ref::<S>() {
+        Some(state) => value(state),
+        None => Err(wrong_state()),
+      });
+    Self::with_callbacks(
+      name,
+      arity,
+      Aggregate {
+        start: start::<S>(),
+        step: step_with(step),
+        inverse: Some(step_with(inverse)),
+        value: Some(current),
+        finalize,
+      },
+    )
+  }
+
+  fn with_callbacks(
+    name: &str,
+    arity: Option<usize>,
+    aggregate: Aggregate,
+  ) -> Self {
+    Self(Arc::new(Definition {
+      name: name.to_owned(),
+      arity,
+      deterministic: false,
+      kind: Kind::Aggregate(aggregate),
+    }))
+  }
+
+  /// The name the function was registered with.
+  pub(crate) fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  /// The number of arguments it takes, `None` for any.
+  pub(crate) fn arity(&self) -> Option<usize> {
+    self.0.arity
+  }
+
+  pub(crate) fn is_deterministic(&self) -> bool {
+    self.0.deterministic
+  }
+
+  pub(crate) fn is_aggregate(&self) -> bool {
+    matches!(self.0.kind, Kind::Aggregate(_))
+  }
+
+  /// Whether the function may be used as a window function.
+  pub(crate) fn is_window(&self) -> bool {
+    matches!(&self.0.kind, Kind::Aggregate(aggregate) if aggregate.inverse.is_some())
+  }
+
+  /// Calls a scalar function with the values of its `arguments`.
+  pub(crate) fn call(&self, arguments: &[Value]) -> SqliteResult<Value> {
+    match &self.0.kind {
+      Kind::Scalar(function) => function(arguments),
+      Kind::Aggregate(_) => Err(self.misuse()),
+    }
+  }
+
+  /// The state of an aggregate function before any row.
+  pub(crate) fn start(&self) -> UserState {
+    match &self.0.kind {
+      Kind::Aggregate(aggregate) => (aggregate.start)(),
+      Kind::Scalar(_) => Box::new(()),
+    }
+  }
+
+  /// Adds a row to the state of an aggregate function.
+  pub(crate) fn step(
+    &self,
+    state: &mut UserState,
+    arguments: &[Value],
+  ) -> SqliteResult<()> {
+    match &self.0.kind {
+      Kind::Aggregate(aggregate) => (aggregate.step)(state.as_mut(), arguments),
+      Kind::Scalar(_) => Err(self.misuse()),
+    }
+  }
+
+  /// Takes a row added by [`step`](Self::step) out of the state of a
+  /// window function.
+  pub(crate) fn inverse(
+    &self,
+    state: &mut UserState,
+    arguments: &[Value],
+  ) -> SqliteResult<()> {
+    let inverse = match &self.0.kind {
+      Kind::Aggregate(aggregate) => aggregate.inverse.as_ref(),
+      Kind::Scalar(_) => None,
+    };
+    match inverse {
+      Some(inverse) => inverse(state.as_mut(), arguments),
+      None => Err(self.misuse()),
+    }
+  }
+
+  /// The result of a window function for the rows of its state.
+  pub(crate) fn value(&self, state: &UserState) -> SqliteResult<Value> {
+    let value = match &self.0.kind {
+      Kind::Aggregate(aggregate) => aggregate.value.as_ref(),
+      Kind::Scalar(_) => None,
+    };
+    match value {
+      Some(value) => value(state.as_ref()),
+      None => Err(self.misuse()),
+    }
+  }
+
+  /// The result of an aggregate function for the rows of its state.
+  pub(crate) fn finalize(&self, state: UserState) -> SqliteResult<Value> {
+    match &self.0.kind {
+      Kind::Aggregate(aggregate) => (aggregate.finalize)(state),
+      Kind::Scalar(_) => Err(self.misuse()),
+    }
+  }
+
+  fn misuse(&self) -> SqliteError {
+    SqliteError::Custom(format!("misuse of function {}()", self.0.name))
+  }
+}
+
+impl PartialEq for UserFunction {
+  /// Functions are equal when they are the same registration.
+  fn eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+impl Eq for UserFunction {}
+
+impl Debug for UserFunction {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("UserFunction")
+      .field("name", &self.0.name)
+      .field("arity", &self.0.arity)
+      .field("deterministic", &self.0.deterministic)
+      .field("aggregate", &self.is_aggregate())
+      .finish()
+  }
+}
+
+fn start<S: Default + Send + 'static>() -> Box<Start> {
+  Box::new(|| Box::new(S::default()))
+}
+
+/// Wraps a callback changing the state of an aggregate.
+fn step_with<S, F>(step: F) -> Box<Step>
+where
+  S: 'static,
+  F: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+{
+  Box::new(move |state: &mut dyn Any, arguments: &[Value]| {
+    match state.downcast_mut::<S>() {
+      Some(state) => step(state, arguments),
+      None => Err(wrong_state()),
+    }
+  })
+}
+
+fn wrong_state() -> SqliteError {
+  SqliteError::Custom("aggregate state of the wrong type".into())
+}
@@ -0,0 +1,353 @@
+//! Operations on values: truth, comparison, arithmetic and concatenation,
+//! with the conversions SQLite applies to their operands.
+//!
+//! *Reference:* https://www.sqlite.org/lang_expr.html#operators_and_parse_affecting_attributes
+
+use super::sorter::{compare_rows, row_size, SortKey, SpilledRows};
+use crate::result::SqliteResult;
+use crate::runtime::{
+  compare_values, to_integer, to_numeric, to_text, Affinity, Collation, Record,
+  Value,
+};
+use crate::sql::ast::BinaryOperator;
+use core::cmp::Ordering;
+use core::mem::{self, size_of};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+/// The truth of a value used as a condition: numbers are true when not
+/// zero, text and blobs are read as numbers first, and NULL is neither.
+pub(crate) fn is_true(value: &Value) -> Option<bool> {
+  match to_numeric(value) {
+    Value::Integer(int) => Some(int != 0),
+    Value::Real(real) => Some(real != 0.0),
+    _ => None,
+  }
+}
+
+pub(crate) fn from_bool(value: Option<bool>) -> Value {
+  value.map_or(Value::Null, |value| Value::Integer(i64::from(value)))
+}
+
+/// The affinity applied to both operands of a comparison, given their own.
+/// A numeric side makes the comparison numeric, otherwise a TEXT side is
+/// only imposed on a side without any affinity.
+///
+/// *Reference:* https://www.sqlite.org/datatype3.html#type_conversions_prior_to_comparison
+pub(crate) fn comparison_affinity(
+  left: Option<Affinity>,
+  right: Option<Affinity>,
+) -> Option<Affinity> {
+  match (left, right) {
+    (Some(left), Some(right)) => {
+      (left.is_numeric() || right.is_numeric()).then_some(Affinity::Numeric)
+    }
+    (Some(affinity), None) | (None, Some(affinity)) => Some(affinity),
+    (None, None) => None,
+  }
+}
+
+/// Compares two values after applying the comparison affinity. `None` when
+/// either is NULL.
+pub(crate) fn compare(
+  left: &Value,
+  right: &Value,
+  affinity: Option<Affinity>,
+  collation: &Collation,
+) -> Option<Ordering> {
+  if left.is_null() || right.is_null() {
+    return None;
+  }
+  match affinity {
+    Some(Affinity::Blob) | None => Some(compare_values(left, right, collation)),
+    Some(affinity) => Some(compare_values(
+      &affinity.apply(left.clone()),
+      &affinity.apply(right.clone()),
+      collation,
+    )),
+  }
+}
+
+/// A set of rows, holding the first values of each row, one for each
+/// collating sequence, and telling rows apart as they compare. Past the
+/// memory budget of the connection, the rows are spilled to temporary files.
+#[derive(Debug)]
+pub(crate) struct KeySet {
+  /// How the values compare, one key for each collating sequence.
+  keys: Arc<[SortKey]>,
+  rows: Rows,
+  /// An estimate of the memory held by the rows.
+  memory: usize,
+  spilled: SpilledRows,
+}
+
+/// The rows of a [`KeySet`] held in memory.
+#[derive(Debug)]
+enum Rows {
+  /// With built-in collating sequences, the rows are hashed by a key that
+  /// is the same for those that compare equal.
+  Hashed(HashSet<Vec<u8>>),
+  /// The text of a collating sequence registered by the application can
+  /// only be compared: the rows are kept sorted.
+  Sorted(BTreeSet<SortedRow>),
+}
+
+impl KeySet {
+  pub(crate) fn new(collations: &[Collation]) -> Self {
+    let keys = collations
+      .iter()
+      .enumerate()
+      .map(|(column, collation)| {
+        SortKey::new(column, false, None, collation.clone())
+      })
+      .collect();
+    let rows = match collations.iter().any(Collation::is_custom) {
+      false => Rows::Hashed(HashSet::new()),
+      true => Rows::Sorted(BTreeSet::new()),
+    };
+    Self {
+      keys,
+      rows,
+      memory: 0,
+      spilled: SpilledRows::default(),
+    }
+  }
+
+  /// Adds the row of `values`, spilling the rows once they hold more than
+  /// `memory_limit` bytes. Returns whether it was not in the set.
+  pub(crate) fn insert(
+    &mut self,
+    values: &[Value],
+    memory_limit: usize,
+  ) -> SqliteResult<bool> {
+    let values = &values[..self.keys.len().min(values.len())];
+    if self.spilled.contains(&self.keys[..values.len()], values)? {
+      return Ok(false);
+    }
+    let size = match &mut self.rows {
+      Rows::Hashed(keys) => {
+        let key = distinct_key(values, &self.keys);
+        let size = size_of::<Vec<u8>>() + key.len();
+        if !keys.insert(key) {
+          return Ok(false);
+        }
+        size
+      }
+      Rows::Sorted(rows) => {
+        let row = SortedRow {
+          keys: self.keys.clone(),
+          row: values.to_vec(),
+        };
+        if !rows.insert(row) {
+          return Ok(false);
+        }
+        row_size(values)
+      }
+    };
+    self.memory += size;
+    if self.memory > memory_limit {
+      self.spill()?;
+    }
+    Ok(true)
+  }
+
+  pub(crate) fn contains(&mut self, values: &[Value]) -> SqliteResult<bool> {
+    let values = &values[..self.keys.len().min(values.len())];
+    let found = match &self.rows {
+      Rows::Hashed(keys) => keys.contains(&distinct_key(values, &self.keys)),
+      Rows::Sorted(rows) => rows.contains(&SortedRow {
+        keys: self.keys.clone(),
+        row: values.to_vec(),
+      }),
+    };
+    Ok(found || self.spilled.contains(&self.keys[..values.len()], values)?)
+  }
+
+  pub(crate) fn clear(&mut self) {
+    match &mut self.rows {
+      Rows::Hashed(keys) => keys.clear(),
+      Rows::Sorted(rows) => rows.clear(),
+    }
+    self.memory = 0;
+    self.spilled.clear();
+  }
+
+  /// Writes the rows held in memory to a temporary file.
+  fn spill(&mut self) -> SqliteResult<()> {
+    let rows = match &mut self.rows {
+      // The values of a key compare as those it was made of.
+      Rows::Hashed(keys) => keys
+        .drain()
+        .map(|key| Record::decode(&key))
+        .collect::<SqliteResult<_>>()?,
+      Rows::Sorted(rows) => {
+        mem::take(rows).into_iter().map(|row| row.row).collect()
+      }
+    };
+    self.memory = 0;
+    self.spilled.spill(&self.keys, rows)
+  }
+}
+
+/// A row of a [`KeySet`] with custom collating sequences, ordered by the
+/// values both rows have.
+#[derive(Debug)]
+struct SortedRow {
+  keys: Arc<[SortKey]>,
+  row: Vec<Value>,
+}
+
+impl Ord for SortedRow {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let width = self.row.len().min(other.row.len()).min(self.keys.len());
+    compare_rows(&self.keys[..width], &self.row, &other.row)
+  }
+}
+
+impl PartialOrd for SortedRow {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for SortedRow {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other).is_eq()
+  }
+}
+
+impl Eq for SortedRow {}
+
+/// A key that is the same for values that compare equal as `keys` say:
+/// integral reals become integers, and text is folded as the collating
+/// sequence of its value compares it.
+fn distinct_key(values: &[Value], keys: &[SortKey]) -> Vec<u8> {
+  let values = keys
+    .iter()
+    .zip(values)
+    .map(|(key, value)| match value {
+      Value::Real(real)
+        if real.fract() == 0.0
+          && (i64::MIN as f64..i64::MAX as f64).contains(real) =>
+      {
+        Value::Integer(*real as i64)
+      }
+      Value::Text(text) => Value::Text(match &key.collation {
+        Collation::Binary => text.clone(),
+        Collation::NoCase => text.to_ascii_lowercase(),
+        Collation::RTrim => text.trim_end_matches(' ').into(),
+        Collation::Custom(_) => text.clone(),
+      }),
+      value => value.clone(),
+    })
+    .collect::<Vec<_>>();
+  Record::encode(&values)
+}
+
+/// Arithmetic and bitwise operators, and `||`.
+pub(crate) fn binary(
+  operator: BinaryOperator,
+  left: &Value,
+  right: &Value,
+) -> Value {
+  if left.is_null() || right.is_null() {
+    return Value::Null;
+  }
+  match operator {
+    BinaryOperator::Concat => {
+      let left = to_text(left).unwrap_or_default();
+      Value::Text(left + &to_text(right).unwrap_or_default())
+    }
+    BinaryOperator::BitAnd => {
+      Value::Integer(to_integer(left) & to_integer(right))
+    }
+    BinaryOperator::BitOr => {
+      Value::Integer(to_integer(left) | to_integer(right))
+    }
+    BinaryOperator::ShiftLeft => {
+      Value::Integer(shift(to_integer(left), to_integer(right)))
+    }
+    BinaryOperator::ShiftRight => Value::Integer(shift(
+      to_integer(left),
+      to_integer(right).checked_neg().unwrap_or(i64::MAX),
+    )),
+    operator => arithmetic(operator, to_numeric(left), to_numeric(right)),
+  }
+}
+
+/// `value << amount`, shifting right when `amount` is negative.
+fn shift(value: i64, amount: i64) -> i64 {
+  match amount {
+    64.. => 0,
+    0..=63 => ((value as u64) << amount) as i64,
+    -63..=-1 => value >> -amount,
+    _ if value < 0 => -1,
+    _ => 0,
+  }
+}
+
+/// `+`, `-`, `*`, `/` and `%`. Integer operations that overflow are done in
+/// floating point instead, and dividing by zero gives NULL.
+fn arithmetic(operator: BinaryOperator, left: Value, right: Value) -> Value {
+  if let (Value::Integer(left), Value::Integer(right)) = (&left, &right) {
+    let (left, right) = (*left, *right);
+    let result = match operator {
+      BinaryOperator::Add => left.checked_add(right),
+      BinaryOperator::Subtract => left.checked_sub(right),
+      BinaryOperator::Multiply => left.checked_mul(right),
+      BinaryOperator::Divide if right == 0 => return Value::Null,
+      BinaryOperator::Divide => left.checked_div(right),
+      BinaryOperator::Modulo if right == 0 => return Value::Null,
+      BinaryOperator::Modulo => Some(left.checked_rem(right).unwrap_or(0)),
+      _ => None,
+    };
+    if let Some(result) = result {
+      return Value::Integer(result);
+    }
+  }
+  let real = |value: &Value| match value {
+    Value::Integer(int) => *int as f64,
+    Value::Real(real) => *real,
+    _ => 0.0,
+  };
+  let (left_real, right_real) = (real(&left), real(&right));
+  let result = match operator {
+    BinaryOperator::Add => left_real + right_real,
+    BinaryOperator::Subtract => left_real - right_real,
+    BinaryOperator::Multiply => left_real * right_real,
+    BinaryOperator::Divide if right_real == 0.0 => return Value::Null,
+    BinaryOperator::Divide => left_real / right_real,
+    BinaryOperator::Modulo => {
+      // The remainder of reals is taken on their integer parts.
+      let divisor = to_integer(&right);
+      if divisor == 0 {
+        return Value::Null;
+      }
+      to_integer(&left).checked_rem(divisor).unwrap_or(0) as f64
+    }
+    _ => return Value::Null,
+  };
+  match result.is_nan() {
+    true => Value::Null,
+    false => Value::Real(result),
+  }
+}
+
+/// Unary `-`.
+pub(crate) fn negate(value: &Value) -> Value {
+  match to_numeric(value) {
+    Value::Integer(int) => int
+      .checked_neg()
+      .map_or(Value::Real(-(int as f64)), Value::Integer),
+    Value::Real(real) => Value::Real(-real),
+    value => value,
+  }
+}
+
+/// Unary `~`.
+pub(crate) fn bit_not(value: &Value) -> Value {
+  match value {
+    Value::Null => Value::Null,
+    value => Value::Integer(!to_integer(value)),
+  }
+}
@@ -0,0 +1,812 @@
+//! # Code generation
+//!
+//!  Each operator of a plan generates the loop producing its rows, and hands
+//! every row to the code of the operator above it, which is generated in the
+//! body of the loop. The tables of a join thus become nested loops, as in
+//! SQLite. Values of a row are read from where they are, cursor columns or
+//! registers, only when an expression needs them.
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+use super::program::{
+  affinity_code, Instruction, Opcode, Program, JUMP_IF_NULL, NULL_EQ, P4,
+};
+use crate::executor::expr::{Comparator, Comparison, Expr};
+use crate::executor::function::ScalarFunction;
+use crate::executor::sorter::SortKey;
+use crate::executor::value::negate;
+use crate::runtime::{Collation, KeyInfo, TableDefinition, Value};
+use crate::sql::ast::{BinaryOperator, UnaryOperator};
+
+/// Part of a plan the virtual machine cannot run, which runs on the
+/// operators instead, named for `EXPLAIN` to report it: `EXPLAIN of
+/// subqueries is not supported`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Unsupported(pub(crate) &'static str);
+
+pub(crate) type Codegen<T = ()> = Result<T, Unsupported>;
+
+/// An address jumped to before it is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Label(usize);
+
+/// Where a value of a row is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Source {
+  Register(i32),
+  /// A column of the row a cursor is on. Integers stored in a REAL column
+  /// to save space are read as REAL when `real` is set.
+  Column {
+    cursor: i32,
+    column: usize,
+    real: bool,
+  },
+  Rowid(i32),
+  Null,
+}
+
+/// Generates the code handling one row, given where its values are and the
+/// label to jump to for the next row.
+pub(crate) type Consumer<'a> =
+  dyn FnMut(&mut Builder, &[Source], Label) -> Codegen + 'a;
+
+/// Builds a program. Cursors are opened before the body runs, and constants
+/// are loaded once, at the end of the program, which `Init` jumps to.
+#[derive(Debug)]
+pub(crate) struct Builder {
+  opens: Vec<Instruction>,
+  body: Vec<Instruction>,
+  constants: Vec<(Expr, Instruction)>,
+  /// The address of each label in the body, once known.
+  labels: Vec<Option<usize>>,
+  registers: i32,
+  accumulators: usize,
+  /// The table each cursor reads, if any.
+  tables: Vec<Option<String>>,
+  /// Whether the program reads the database, in a transaction.
+  reads_database: bool,
+  /// Whether the program also writes to it.
+  writes_database: bool,
+  schema_cookie: u32,
+  /// The register holding how many of the first rows a sort keeps, set by
+  /// the `LIMIT` above it.
+  pub(crate) sort_limit: Option<i32>,
+}
+
+impl Builder {
+  pub(crate) fn new(schema_cookie: u32) -> Self {
+    Self {
+      opens: vec![],
+      body: vec![],
+      constants: vec![],
+      labels: vec![],
+      registers: 0,
+      accumulators: 0,
+      tables: vec![],
+      reads_database: false,
+      writes_database: false,
+      schema_cookie,
+      sort_limit: None,
+    }
+  }
+
+  pub(crate) fn finish(mut self) -> Program {
+    self.add(Opcode::Halt, 0, 0, 0);
+    let start = 1 + self.opens.len();
+    let end = start + self.body.len();
+    let mut instructions =
+      vec![Instruction::new(Opcode::Init, 0, end as i32, 0)];
+    instructions.append(&mut self.opens);
+    for mut instruction in self.body {
+      if instruction.opcode.jumps() && instruction.p2 < 0 {
+        let label = (-instruction.p2 - 1) as usize;
+        let address = self.labels.get(label).copied().flatten();
+        instruction.p2 = (start + address.unwrap_or(end - start - 1)) as i32;
+      }
+      instructions.push(instruction);
+    }
+    if self.reads_database {
+      let mut transaction = Instruction::new(
+        Opcode::Transaction,
+        0,
+        i32::from(self.writes_database),
+        self.schema_cookie as i32,
+      );
+      transaction.p5 = 1;
+      transaction.comment = Some("usesStmtJournal=0".into());
+      instructions.push(transaction);
+    }
+    instructions.extend(self.constants.into_iter().map(|(_, load)| load));
+    instructions.push(Instruction::new(Opcode::Goto, 0, 1, 0));
+    Program {
+      instructions,
+      registers: self.registers as usize,
+      cursors: self.tables.len(),
+      accumulators: self.accumulators,
+    }
+  }
+
+  /// Adds an instruction to the body.
+  pub(crate) fn add(
+    &mut self,
+    opcode: Opcode,
+    p1: i32,
+    p2: i32,
+    p3: i32,
+  ) -> &mut Instruction {
+    let idx = self.body.len();
+    self.body.push(Instruction::new(opcode, p1, p2, p3));
+    &mut self.body[idx]
+  }
+
+  /// Adds an instruction jumping to `label`.
+  pub(crate) fn jump(
+    &mut self,
+    opcode: Opcode,
+    p1: i32,
+    label: Label,
+    p3: i32,
+  ) -> &mut Instruction {
+    self.add(opcode, p1, -(label.0 as i32) - 1, p3)
+  }
+
+  /// Sets the comment of the last instruction added to the body.
+  pub(crate) fn comment(&mut self, comment: &str) {
+    if let Some(instruction) = self.body.last_mut() {
+      instruction.comment = Some(comment.into());
+    }
+  }
+
+  pub(crate) fn label(&mut self) -> Label {
+    self.labels.push(None);
+    Label(self.labels.len() - 1)
+  }
+
+  /// Places `label` at the next instruction.
+  pub(crate) fn resolve(&mut self, label: Label) {
+    if let Some(address) = self.labels.get_mut(label.0) {
+      *address = Some(self.body.len());
+    }
+  }
+
+  /// A label at the next instruction.
+  pub(crate) fn here(&mut self) -> Label {
+    let label = self.label();
+    self.resolve(label);
+    label
+  }
+
+  pub(crate) fn register(&mut self) -> i32 {
+    self.registers(1)
+  }
+
+  /// The first of `count` new consecutive registers.
+  pub(crate) fn registers(&mut self, count: usize) -> i32 {
+    let first = self.registers + 1;
+    self.registers += count as i32;
+    first
+  }
+
+  /// The first of `count` new accumulators of aggregate calls.
+  pub(crate) fn accumulators(&mut self, count: usize) -> i32 {
+    let first = self.accumulators;
+    self.accumulators += count;
+    first as i32
+  }
+
+  fn cursor(&mut self, table: Option<String>) -> i32 {
+    self.tables.push(table);
+    self.tables.len() as i32 - 1
+  }
+
+  /// Opens a cursor on the rows of `table`, in the b-tree rooted at `root`.
+  pub(crate) fn open_table(
+    &mut self,
+    table: &TableDefinition,
+    root: u32,
+  ) -> i32 {
+    let cursor = self.cursor(Some(table.name().into()));
+    self.reads_database = true;
+    let mut open = Instruction::new(Opcode::OpenRead, cursor, root as i32, 0);
+    open.p4 = P4::Table(table.clone());
+    open.comment = Some(table.name().into());
+    self.opens.push(open);
+    cursor
+  }
+
+  /// Opens a cursor changing the rows of `table`, in the b-tree rooted at
+  /// `root`.
+  pub(crate) fn open_write(
+    &mut self,
+    table: &TableDefinition,
+    root: u32,
+  ) -> i32 {
+    let cursor = self.cursor(Some(table.name().into()));
+    self.reads_database = true;
+    self.writes_database = true;
+    let mut open = Instruction::new(Opcode::OpenWrite, cursor, root as i32, 0);
+    open.p4 = P4::Table(table.clone());
+    open.comment = Some(table.name().into());
+    self.opens.push(open);
+    cursor
+  }
+
+  /// Opens a cursor on the entries of the index `name`, `fields` values
+  /// each, which are looked up by key.
+  pub(crate) fn open_index(
+    &mut self,
+    root: u32,
+    name: &str,
+    key_info: &KeyInfo,
+    fields: usize,
+  ) -> i32 {
+    let cursor = self.cursor(None);
+    self.reads_database = true;
+    let mut open = Instruction::new(Opcode::OpenRead, cursor, root as i32, 0);
+    open.p4 = P4::KeyInfo(key_info.clone(), fields);
+    open.p5 = 2;
+    open.comment = Some(name.into());
+    self.opens.push(open);
+    cursor
+  }
+
+  /// Opens a sorter of rows sorted by `keys`, which keeps only as many of
+  /// the first rows as register `limit` says, when given.
+  pub(crate) fn open_sorter(
+    &mut self,
+    keys: &[SortKey],
+    limit: Option<i32>,
+  ) -> i32 {
+    let cursor = self.cursor(None);
+    let mut open = Instruction::new(
+      Opcode::SorterOpen,
+      cursor,
+      keys.len() as i32,
+      limit.unwrap_or(0),
+    );
+    open.p4 = P4::SortKeys(keys.to_vec());
+    self.opens.push(open);
+    cursor
+  }
+
+  /// Opens a set of keys, whose values compare with `collations`.
+  pub(crate) fn open_ephemeral(&mut self, collations: &[Collation]) -> i32 {
+    let cursor = self.cursor(None);
+    let mut open = Instruction::new(
+      Opcode::OpenEphemeral,
+      cursor,
+      collations.len() as i32,
+      0,
+    );
+    open.p4 = P4::Collations(collations.to_vec());
+    self.opens.push(open);
+    cursor
+  }
+
+  /// Opens a list of the distinct values inserted into it, read in the
+  /// order `key_info` sorts them. The list is opened in the body, for its
+  /// values to be inserted afresh each time.
+  pub(crate) fn open_list(&mut self, key_info: &KeyInfo) -> i32 {
+    let cursor = self.cursor(None);
+    let open = self.add(Opcode::OpenEphemeral, cursor, 1, 0);
+    open.p4 = P4::KeyInfo(key_info.clone(), 1);
+    open.comment = Some("RHS of IN operator".into());
+    cursor
+  }
+
+  /// Loads the value at `source` into register `target`.
+  pub(crate) fn load(&mut self, source: &Source, target: i32) {
+    match source {
+      Source::Register(register) => {
+        if *register != target {
+          self.add(Opcode::SCopy, *register, target, 0);
+        }
+      }
+      Source::Column {
+        cursor,
+        column,
+        real,
+      } => {
+        self.add(Opcode::Column, *cursor, *column as i32, target);
+        if *real {
+          self.add(Opcode::RealAffinity, target, 0, 0);
+        }
+      }
+      Source::Rowid(cursor) => {
+        let table = self.tables.get(*cursor as usize).cloned().flatten();
+        self.add(Opcode::Rowid, *cursor, target, 0).comment =
+          table.map(|table| format!("{table}.rowid"));
+      }
+      Source::Null => {
+        self.add(Opcode::Null, 0, target, 0);
+      }
+    }
+  }
+
+  /// The first of consecutive registers holding the values of `row`, which
+  /// are loaded into new ones unless they already are.
+  pub(crate) fn row_registers(&mut self, row: &[Source]) -> i32 {
+    if let Some(Source::Register(first)) = row.first() {
+      let consecutive = (0..)
+        .zip(row)
+        .all(|(idx, source)| *source == Source::Register(first + idx));
+      if consecutive {
+        return *first;
+      }
+    }
+    let first = self.registers(row.len());
+    for (register, source) in (first..).zip(row) {
+      self.load(source, register);
+    }
+    first
+  }
+
+  /// A register holding the value of the constant `expr`, loaded once
+  /// before the body runs.
+  fn constant(&mut self, expr: &Expr) -> Codegen<i32> {
+    let known = self.constants.iter().find(|(constant, _)| constant == expr);
+    if let Some((_, load)) = known {
+      return Ok(load.p2);
+    }
+    let register = self.register();
+    let load =
+      load_constant(expr, register).ok_or(Unsupported("this expression"))?;
+    self.constants.push((expr.clone(), load));
+    Ok(register)
+  }
+
+  /// A register holding the value of `expr`: that of a constant, or of a
+  /// value of the row already in one, or else a new one.
+  fn expr_temp(&mut self, expr: &Expr, row: &[Source]) -> Codegen<i32> {
+    match expr {
+      Expr::Literal(_) | Expr::Parameter(_) => self.constant(expr),
+      Expr::Collate { expr, .. } => self.expr_temp(expr, row),
+      Expr::Slot(index)
+      | Expr::Column {
+        depth: 0, index, ..
+      } => match row.get(*index) {
+        Some(Source::Register(register)) => Ok(*register),
+        _ => self.expr_new(expr, row),
+      },
+      _ => self.expr_new(expr, row),
+    }
+  }
+
+  /// A new register holding the value of `expr`.
+  fn expr_new(&mut self, expr: &Expr, row: &[Source]) -> Codegen<i32> {
+    let register = self.register();
+    self.expr(expr, row, register)?;
+    Ok(register)
+  }
+
+  /// Generates the code computing `expr` into register `target`, with the
+  /// values of the current row at `row`.
+  pub(crate) fn expr(
+    &mut self,
+    expr: &Expr,
+    row: &[Source],
+    target: i32,
+  ) -> Codegen {
+    match expr {
+      Expr::Literal(_) | Expr::Parameter(_) => {
+        let load =
+          load_constant(expr, target).ok_or(Unsupported("this expression"))?;
+        self.body.push(load);
+      }
+      Expr::Slot(index)
+      | Expr::Column {
+        depth: 0, index, ..
+      } => {
+        let source = row.get(*index).ok_or(Unsupported("this expression"))?;
+        let source = source.clone();
+        self.load(&source, target);
+      }
+      Expr::Unary { operator, expr } => match (operator, &**expr) {
+        (UnaryOperator::Plus, expr) => self.expr(expr, row, target)?,
+        (UnaryOperator::Negate, Expr::Literal(value)) => {
+          self.body.push(load_value(&negate(value), target));
+        }
+        (UnaryOperator::Negate, expr) => {
+          let zero = self.constant(&Expr::Literal(Value::Integer(0)))?;
+          let value = self.expr_temp(expr, row)?;
+          self.add(Opcode::Subtract, value, zero, target);
+        }
+        (UnaryOperator::Not | UnaryOperator::BitNot, expr) => {
+          let value = self.expr_temp(expr, row)?;
+          let opcode = match operator {
+            UnaryOperator::Not => Opcode::Not,
+            _ => Opcode::BitNot,
+          };
+          self.add(opcode, value, target, 0);
+        }
+      },
+      Expr::Binary {
+        operator,
+        left,
+        right,
+      } => {
+        let opcode =
+          binary_opcode(*operator).ok_or(Unsupported("this expression"))?;
+        let left = self.expr_temp(left, row)?;
+        let right = self.expr_temp(right, row)?;
+        self.add(opcode, right, left, target);
+      }
+      Expr::And(left, right) | Expr::Or(left, right) => {
+        let opcode = match expr {
+          Expr::And(..) => Opcode::And,
+          _ => Opcode::Or,
+        };
+        let left = self.expr_temp(left, row)?;
+        let right = self.expr_temp(right, row)?;
+        self.add(opcode, left, right, target);
+      }
+      Expr::Compare {
+        operator,
+        left,
+        right,
+        comparator,
+      } => {
+        let left = self.expr_temp(left, row)?;
+        let right = self.expr_temp(right, row)?;
+        let done = self.label();
+        self.add(Opcode::Integer, 1, target, 0);
+        match operator {
+          Comparison::Is | Comparison::IsNot => {
+            let opcode = match operator {
+              Comparison::Is => Opcode::Eq,
+              _ => Opcode::Ne,
+            };
+            self.compare(opcode, left, right, comparator, done, NULL_EQ);
+            self.add(Opcode::Integer, 0, target, 0);
+          }
+          _ => {
+            let opcode = comparison_opcode(*operator);
+            self.compare(opcode, left, right, comparator, done, 0);
+            self.add(Opcode::ZeroOrNull, left, target, right);
+          }
+        }
+        self.resolve(done);
+      }
+      Expr::IsNull { expr, not } => {
+        let value = self.expr_temp(expr, row)?;
+        let done = self.label();
+        self.add(Opcode::Integer, 1, target, 0);
+        let opcode = match not {
+          true => Opcode::NotNull,
+          false => Opcode::IsNull,
+        };
+        self.jump(opcode, value, done, 0);
+        self.add(Opcode::Integer, 0, target, 0);
+        self.resolve(done);
+      }
+      Expr::Like {
+        expr,
+        not,
+        glob,
+        pattern,
+        escape,
+      } => {
+        let argc = 2 + usize::from(escape.is_some());
+        let first = self.registers(argc);
+        self.expr(pattern, row, first)?;
+        self.expr(expr, row, first + 1)?;
+        if let Some(escape) = escape {
+          self.expr(escape, row, first + 2)?;
+        }
+        let function = match glob {
+          true => ScalarFunction::Glob,
+          false => ScalarFunction::Like,
+        };
+        self.add(Opcode::Function, 0, first, target).p4 = P4::Function {
+          function,
+          argc,
+          collation: Collation::Binary,
+        };
+        if *not {
+          self.add(Opcode::Not, target, target, 0);
+        }
+      }
+      Expr::Between {
+        expr,
+        not,
+        low,
+        high,
+        low_comparator,
+        high_comparator,
+      } => {
+        // The value is computed once, and compared as a value of the row.
+        let value = self.expr_temp(expr, row)?;
+        let mut row = row.to_vec();
+        row.push(Source::Register(value));
+        let slot = Box::new(Expr::Slot(row.len() - 1));
+        let mut between = Expr::And(
+          Box::new(Expr::Compare {
+            operator: Comparison::GtEq,
+            left: slot.clone(),
+            right: low.clone(),
+            comparator: low_comparator.clone(),
+          }),
+          Box::new(Expr::Compare {
+            operator: Comparison::LtEq,
+            left: slot,
+            right: high.clone(),
+            comparator: high_comparator.clone(),
+          }),
+        );
+        if *not {
+          between = Expr::Unary {
+            operator: UnaryOperator::Not,
+            expr: Box::new(between),
+          };
+        }
+        self.expr(&between, &row, target)?;
+      }
+      Expr::InList {
+        expr,
+        not,
+        list,
+        comparator,
+      } => {
+        if list.is_empty() {
+          self.add(Opcode::Integer, i32::from(*not), target, 0);
+          return Ok(());
+        }
+        let value = self.expr_temp(expr, row)?;
+        let (found, done) = (self.label(), self.label());
+        self.add(Opcode::Null, 0, target, 0);
+        self.jump(Opcode::IsNull, value, done, 0);
+        self.add(Opcode::Integer, 0, target, 0);
+        for item in list {
+          let item = self.expr_temp(item, row)?;
+          self.compare(Opcode::Eq, value, item, comparator, found, 0);
+          // Without a match, a NULL in the list makes the result NULL.
+          let skip = self.label();
+          self.jump(Opcode::NotNull, item, skip, 0);
+          self.add(Opcode::Null, 0, target, 0);
+          self.resolve(skip);
+        }
+        self.jump(Opcode::Goto, 0, done, 0);
+        self.resolve(found);
+        self.add(Opcode::Integer, 1, target, 0);
+        self.resolve(done);
+        if *not {
+          self.add(Opcode::Not, target, target, 0);
+        }
+      }
+      Expr::Collate { expr, .. } => self.expr(expr, row, target)?,
+      Expr::Cast { expr, affinity } => {
+        self.expr(expr, row, target)?;
+        let code = affinity_code(Some(*affinity));
+        self.add(Opcode::Cast, target, code.into(), 0);
+      }
+      Expr::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        let done = self.label();
+        let operand = match operand {
+          Some((operand, comparators)) => {
+            Some((self.expr_temp(operand, row)?, comparators))
+          }
+          None => None,
+        };
+        for (idx, (when, then)) in when_then.iter().enumerate() {
+          let next = self.label();
+          match &operand {
+            Some((operand, comparators)) => {
+              let comparator =
+                comparators.get(idx).ok_or(Unsupported("this expression"))?;
+              let when = self.expr_temp(when, row)?;
+              self.compare(
+                Opcode::Ne,
+                *operand,
+                when,
+                comparator,
+                next,
+                JUMP_IF_NULL,
+              );
+            }
+            None => self.if_false(when, row, next, true)?,
+          }
+          self.expr(then, row, target)?;
+          self.jump(Opcode::Goto, 0, done, 0);
+          self.resolve(next);
+        }
+        match else_expr {
+          Some(else_expr) => self.expr(else_expr, row, target)?,
+          None => {
+            self.add(Opcode::Null, 0, target, 0);
+          }
+        }
+        self.resolve(done);
+      }
+      // The arguments after the first that is not NULL are not evaluated.
+      Expr::Function {
+        function: ScalarFunction::Coalesce,
+        arguments,
+        ..
+      } => {
+        let done = self.label();
+        for (idx, argument) in arguments.iter().enumerate() {
+          if idx > 0 {
+            self.jump(Opcode::NotNull, target, done, 0);
+          }
+          self.expr(argument, row, target)?;
+        }
+        self.resolve(done);
+      }
+      Expr::Function {
+        function,
+        arguments,
+        collation,
+      } => {
+        let argc = arguments.len();
+        let first = self.registers(argc);
+        for (idx, argument) in arguments.iter().enumerate() {
+          self.expr(argument, row, first + idx as i32)?;
+        }
+        self.add(Opcode::Function, 0, first, target).p4 = P4::Function {
+          function: function.clone(),
+          argc,
+          collation: collation.clone(),
+        };
+      }
+      Expr::Column { .. } => return Err(Unsupported("correlated subqueries")),
+      Expr::InSubquery { .. } | Expr::Subquery(_) | Expr::Exists(_) => {
+        return Err(Unsupported("subqueries"))
+      }
+      Expr::Window(_) => return Err(Unsupported("window functions")),
+      Expr::Raise { .. } => return Err(Unsupported("RAISE()")),
+    }
+    Ok(())
+  }
+
+  /// Jumps to `label` unless `expr` is true: when it is false, and when it
+  /// is NULL if `jump_if_null` is set.
+  pub(crate) fn if_false(
+    &mut self,
+    expr: &Expr,
+    row: &[Source],
+    label: Label,
+    jump_if_null: bool,
+  ) -> Codegen {
+    match expr {
+      Expr::And(left, right) => {
+        self.if_false(left, row, label, jump_if_null)?;
+        self.if_false(right, row, label, jump_if_null)?;
+      }
+      Expr::Compare {
+        operator,
+        left,
+        right,
+        comparator,
+      } => {
+        let left = self.expr_temp(left, row)?;
+        let right = self.expr_temp(right, row)?;
+        let (opcode, flags) = match operator {
+          Comparison::Is => (Opcode::Ne, NULL_EQ),
+          Comparison::IsNot => (Opcode::Eq, NULL_EQ),
+          operator => (
+            negated_opcode(*operator),
+            if jump_if_null { JUMP_IF_NULL } else { 0 },
+          ),
+        };
+        self.compare(opcode, left, right, comparator, label, flags);
+      }
+      Expr::IsNull { expr, not } => {
+        let value = self.expr_temp(expr, row)?;
+        let opcode = match not {
+          true => Opcode::IsNull,
+          false => Opcode::NotNull,
+        };
+        self.jump(opcode, value, label, 0);
+      }
+      expr => {
+        let value = self.expr_temp(expr, row)?;
+        self.jump(Opcode::IfNot, value, label, i32::from(jump_if_null));
+      }
+    }
+    Ok(())
+  }
+
+  /// Jumps to `label` when `left` and `right`, in registers, compare as
+  /// `opcode` says.
+  fn compare(
+    &mut self,
+    opcode: Opcode,
+    left: i32,
+    right: i32,
+    comparator: &Comparator,
+    label: Label,
+    flags: u16,
+  ) {
+    let compare = self.jump(opcode, right, label, left);
+    compare.p4 = P4::Collation(comparator.collation().clone());
+    compare.p5 = flags | u16::from(affinity_code(comparator.affinity()));
+  }
+
+  /// Jumps to `label` when the values of registers `left` and `right` are
+  /// not the same, compared with `collation`, NULLs being the same.
+  pub(crate) fn if_distinct(
+    &mut self,
+    left: i32,
+    right: i32,
+    collation: &Collation,
+    label: Label,
+  ) {
+    let compare = self.jump(Opcode::Ne, right, label, left);
+    compare.p4 = P4::Collation(collation.clone());
+    compare.p5 = NULL_EQ | u16::from(affinity_code(None));
+  }
+}
+
+/// The instruction loading the constant `expr` into register `target`.
+fn load_constant(expr: &Expr, target: i32) -> Option<Instruction> {
+  match expr {
+    Expr::Literal(value) => Some(load_value(value, target)),
+    Expr::Parameter(index) => {
+      Some(Instruction::new(Opcode::Variable, *index as i32, target, 0))
+    }
+    _ => None,
+  }
+}
+
+fn load_value(value: &Value, target: i32) -> Instruction {
+  let (opcode, p1, p4) = match value {
+    Value::Null => (Opcode::Null, 0, P4::None),
+    Value::Integer(int) => match i32::try_from(*int) {
+      Ok(int) => (Opcode::Integer, int, P4::None),
+      Err(_) => (Opcode::Int64, 0, P4::Int64(*int)),
+    },
+    Value::Real(real) => (Opcode::Real, 0, P4::Real(*real)),
+    Value::Text(text) => (Opcode::String8, 0, P4::Text(text.clone())),
+    Value::Blob(blob) => {
+      (Opcode::Blob, blob.len() as i32, P4::Blob(blob.clone()))
+    }
+  };
+  let mut load = Instruction::new(opcode, p1, target, 0);
+  load.p4 = p4;
+  load
+}
+
+fn binary_opcode(operator: BinaryOperator) -> Option<Opcode> {
+  Some(match operator {
+    BinaryOperator::Add => Opcode::Add,
+    BinaryOperator::Subtract => Opcode::Subtract,
+    BinaryOperator::Multiply => Opcode::Multiply,
+    BinaryOperator::Divide => Opcode::Divide,
+    BinaryOperator::Modulo => Opcode::Remainder,
+    BinaryOperator::Concat => Opcode::Concat,
+    BinaryOperator::BitAnd => Opcode::BitAnd,
+    BinaryOperator::BitOr => Opcode::BitOr,
+    BinaryOperator::ShiftLeft => Opcode::ShiftLeft,
+    BinaryOperator::ShiftRight => Opcode::ShiftRight,
+    _ => return None,
+  })
+}
+
+/// The opcode jumping when a comparison is true. `IS` and `IS NOT` are
+/// `Eq` and `Ne` with NULLs equal.
+fn comparison_opcode(operator: Comparison) -> Opcode {
+  match operator {
+    Comparison::Eq | Comparison::Is => Opcode::Eq,
+    Comparison::NotEq | Comparison::IsNot => Opcode::Ne,
+    Comparison::Lt => Opcode::Lt,
+    Comparison::LtEq => Opcode::Le,
+    Comparison::Gt => Opcode::Gt,
+    Comparison::GtEq => Opcode::Ge,
+  }
+}
+
+/// The opcode jumping when a comparison is false.
+fn negated_opcode(operator: Comparison) -> Opcode {
+  match operator {
+    Comparison::Eq | Comparison::Is => Opcode::Ne,
+    Comparison::NotEq | Comparison::IsNot => Opcode::Eq,
+    Comparison::Lt => Opcode::Ge,
+    Comparison::LtEq => Opcode::Gt,
+    Comparison::Gt => Opcode::Le,
+    Comparison::GtEq => Opcode::Lt,
+  }
+}
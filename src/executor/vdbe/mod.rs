@@ -0,0 +1,960 @@
+//! # The virtual machine
+//!
+//!  SQLite compiles each statement into a program of its virtual database
+//! engine: a register machine whose instructions read the b-trees of the
+//! database through cursors, compute values in registers, and return the
+//! rows of the result one at a time. Queries are compiled the same way, from
+//! the operators of their plan, and `EXPLAIN` lists the instructions of the
+//! program rather than running it.
+//!
+//!  The machine runs scans of rowid and `WITHOUT ROWID` tables, joins,
+//! filters, sorts, aggregates, `DISTINCT`, compound queries and `LIMIT`, and
+//! the `INSERT`, `UPDATE` and `DELETE` statements on tables, whose rows it
+//! reads before writing them. Queries with window functions, subqueries,
+//! table-valued functions or `RIGHT` or `FULL` joins, and changes of views,
+//! run on their operators instead, and `EXPLAIN` of them fails, as it does
+//! for schema and transaction statements.
+//!
+//! ```
+//! use sqlite_rs::{runtime::Value, SqliteConnection};
+//!
+//! let mut conn = SqliteConnection::open(":memory:").unwrap();
+//! let rows = conn
+//!   .query("EXPLAIN SELECT 1")
+//!   .unwrap()
+//!   .map(|row| row.unwrap().into_values())
+//!   .collect::<Vec<_>>();
+//! assert_eq!(rows[1][1], Value::Text("Integer".into()));
+//! assert_eq!(rows[2][1], Value::Text("ResultRow".into()));
+//! ```
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+mod codegen;
+mod program;
+
+pub(crate) use self::codegen::{
+  Builder, Codegen, Consumer, Label, Source, Unsupported,
+};
+pub(crate) use self::program::{affinity_code, Opcode, Program, P4};
+
+use self::program::{
+  affinity_from_code, Instruction, AFFINITY_MASK, JUMP_IF_NULL, NULL_EQ,
+};
+use super::aggregate::Accumulator;
+use super::dml::Change;
+use super::operator::{
+  as_rowid, first_rowid, last_rowid, limit_value, Operator,
+};
+use super::sorter::{SortKey, SortedRows, Sorter};
+use super::value::{binary, bit_not, compare, from_bool, is_true, KeySet};
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{
+  BtreeCursor, Collation, KeyInfo, SqliteBtree, TableCursor, Value,
+};
+use crate::sql::ast::BinaryOperator;
+use core::cmp::Ordering;
+
+/// Runs a program, as the root operator of a query.
+#[derive(Debug)]
+pub(crate) struct Vdbe {
+  program: Program,
+  /// Address of the next instruction.
+  pc: usize,
+  registers: Registers,
+  cursors: Vec<Option<Cursor>>,
+  accumulators: Vec<Option<Accumulator>>,
+}
+
+/// The registers of a program, numbered from 1.
+#[derive(Debug)]
+struct Registers(Vec<Value>);
+
+impl Registers {
+  fn get(&self, register: i32) -> SqliteResult<&Value> {
+    usize::try_from(register)
+      .ok()
+      .and_then(|idx| self.0.get(idx))
+      .ok_or_else(|| out_of_range(register))
+  }
+
+  fn set(&mut self, register: i32, value: Value) -> SqliteResult<()> {
+    let slot = usize::try_from(register)
+      .ok()
+      .and_then(|idx| self.0.get_mut(idx))
+      .ok_or_else(|| out_of_range(register))?;
+    *slot = value;
+    Ok(())
+  }
+
+  /// The values of the `count` registers from `first`.
+  fn range(&self, first: i32, count: i32) -> SqliteResult<&[Value]> {
+    let start = usize::try_from(first).map_err(|_| out_of_range(first))?;
+    let end = start + usize::try_from(count).unwrap_or(0);
+    self
+      .0
+      .get(start..end)
+      .ok_or_else(|| out_of_range(first + count - 1))
+  }
+
+  fn integer(&self, register: i32) -> SqliteResult<i64> {
+    match self.get(register)? {
+      Value::Integer(int) => Ok(*int),
+      _ => Ok(0),
+    }
+  }
+}
+
+fn out_of_range(register: i32) -> SqliteError {
+  SqliteError::Custom(format!("Register {register} is out of range"))
+}
+
+#[derive(Debug)]
+enum Cursor {
+  Table {
+    cursor: TableCursor,
+    /// The values of the current row, once read.
+    row: Option<Vec<Value>>,
+    /// Set by `NullRow`: every column reads NULL until the cursor moves.
+    null_row: bool,
+  },
+  Index {
+    cursor: BtreeCursor,
+    key_info: KeyInfo,
+    record: Option<Vec<Value>>,
+    null_row: bool,
+  },
+  Sorter {
+    keys: Vec<SortKey>,
+    /// The register holding how many of the first rows to keep, if any.
+    limit: i32,
+    sorter: Option<Sorter>,
+    sorted: Option<SortedRows>,
+    row: Option<Vec<Value>>,
+  },
+  Ephemeral(KeySet),
+  /// The distinct values of an `IN` list, sorted as `key_info` says.
+  List {
+    key_info: KeyInfo,
+    rows: Vec<Vec<Value>>,
+    /// The position of the current row, once rewound.
+    position: usize,
+  },
+  /// The rows a statement changes, written once all are read.
+  Write(Vec<Vec<Value>>),
+}
+
+impl Cursor {
+  /// Forgets the values read from the current row, once the cursor moved.
+  fn moved(&mut self) {
+    match self {
+      Self::Table { row, null_row, .. } => {
+        *row = None;
+        *null_row = false;
+      }
+      Self::Index {
+        record, null_row, ..
+      } => {
+        *record = None;
+        *null_row = false;
+      }
+      Self::Sorter { .. }
+      | Self::Ephemeral(_)
+      | Self::List { .. }
+      | Self::Write(_) => {}
+    }
+  }
+
+  /// The values of the current entry of an index cursor.
+  fn record(&mut self, ctx: &mut Context<'_>) -> SqliteResult<&[Value]> {
+    let Self::Index { cursor, record, .. } = self else {
+      return Err(not_an("index"));
+    };
+    let record = match record {
+      Some(record) => record,
+      None => record.insert(cursor.record(&mut ctx.btree())?),
+    };
+    Ok(record)
+  }
+}
+
+fn not_an(kind: &str) -> SqliteError {
+  SqliteError::Custom(format!("Cursor is not on an {kind}"))
+}
+
+fn no_change() -> SqliteError {
+  SqliteError::Custom("No statement to write rows with".into())
+}
+
+fn cursor(
+  cursors: &mut [Option<Cursor>],
+  idx: i32,
+) -> SqliteResult<&mut Cursor> {
+  usize::try_from(idx)
+    .ok()
+    .and_then(|idx| cursors.get_mut(idx))
+    .and_then(Option::as_mut)
+    .ok_or(SqliteError::Custom(format!("Cursor {idx} is not open")))
+}
+
+/// The key values of seeks and index comparisons: P4 registers from P3.
+fn key<'r>(
+  registers: &'r Registers,
+  op: &Instruction,
+) -> SqliteResult<&'r [Value]> {
+  let count = match op.p4 {
+    P4::Int64(count) => count as i32,
+    _ => 1,
+  };
+  registers.range(op.p3, count)
+}
+
+impl Vdbe {
+  pub(crate) fn new(program: Program) -> Self {
+    Self {
+      registers: Registers(vec![Value::Null; program.registers + 1]),
+      cursors: (0..program.cursors).map(|_| None).collect(),
+      accumulators: (0..program.accumulators).map(|_| None).collect(),
+      program,
+      pc: 0,
+    }
+  }
+
+  /// Runs the program of a data change, which writes its rows with
+  /// `change`, from the start.
+  pub(crate) fn change(
+    &mut self,
+    ctx: &mut Context<'_>,
+    change: &mut Change,
+  ) -> SqliteResult<()> {
+    self.reset();
+    match self.run(ctx, Some(change))? {
+      Some(_) => Err(SqliteError::Custom("Data changes return no rows".into())),
+      None => Ok(()),
+    }
+  }
+
+  /// Runs the program up to its next row, `None` once it halted.
+  fn run(
+    &mut self,
+    ctx: &mut Context<'_>,
+    mut change: Option<&mut Change>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    let Self {
+      program,
+      pc,
+      registers,
+      cursors,
+      accumulators,
+    } = self;
+    loop {
+      let Some(op) = program.instructions.get(*pc) else {
+        return Ok(None);
+      };
+      *pc += 1;
+      let target = op.p2 as usize;
+      match op.opcode {
+        Opcode::Init | Opcode::Goto => *pc = target,
+        Opcode::Gosub => {
+          registers.set(op.p1, Value::Integer(*pc as i64))?;
+          *pc = target;
+        }
+        Opcode::Return => *pc = registers.integer(op.p1)? as usize,
+        Opcode::Halt => {
+          // Halted programs stay halted until reset.
+          *pc -= 1;
+          return match (op.p1, &op.p4) {
+            (0, _) => Ok(None),
+            (11, P4::Text(message)) => {
+              Err(SqliteError::Corrupt(message.clone()))
+            }
+            (_, p4) => Err(SqliteError::Custom(p4.to_string())),
+          };
+        }
+        Opcode::Transaction => {
+          if ctx.schema_cookie() as i32 != op.p3 {
+            return Err(SqliteError::Custom(
+              "database schema has changed".into(),
+            ));
+          }
+        }
+        Opcode::If | Opcode::IfNot => {
+          let jumps = match is_true(registers.get(op.p1)?) {
+            Some(is) => is == (op.opcode == Opcode::If),
+            None => op.p3 != 0,
+          };
+          if jumps {
+            *pc = target;
+          }
+        }
+        Opcode::IsNull | Opcode::NotNull => {
+          let is_null = registers.get(op.p1)?.is_null();
+          if is_null == (op.opcode == Opcode::IsNull) {
+            *pc = target;
+          }
+        }
+        Opcode::IfPos => {
+          if let Value::Integer(int) = *registers.get(op.p1)? {
+            if int > 0 {
+              registers.set(op.p1, Value::Integer(int - i64::from(op.p3)))?;
+              *pc = target;
+            }
+          }
+        }
+        Opcode::DecrJumpZero => {
+          let int = registers.integer(op.p1)?.saturating_sub(1);
+          registers.set(op.p1, Value::Integer(int))?;
+          if int == 0 {
+            *pc = target;
+          }
+        }
+        Opcode::MustBeInt => match limit_value(registers.get(op.p1)?.clone()) {
+          Ok(int) => registers.set(op.p1, Value::Integer(int))?,
+          Err(error) if op.p2 == 0 => return Err(error),
+          Err(_) => *pc = target,
+        },
+        Opcode::OffsetLimit => {
+          let limit = registers.integer(op.p1)?;
+          let offset = registers.integer(op.p3)?;
+          let total = match limit > 0 {
+            true => limit.saturating_add(offset.max(0)),
+            false => -1,
+          };
+          registers.set(op.p2, Value::Integer(total))?;
+        }
+        Opcode::Integer => {
+          registers.set(op.p2, Value::Integer(op.p1.into()))?;
+        }
+        Opcode::Int64 | Opcode::Real | Opcode::String8 | Opcode::Blob => {
+          let value = match &op.p4 {
+            P4::Int64(int) => Value::Integer(*int),
+            P4::Real(real) => Value::Real(*real),
+            P4::Text(text) => Value::Text(text.clone()),
+            P4::Blob(blob) => Value::Blob(blob.clone()),
+            _ => Value::Null,
+          };
+          registers.set(op.p2, value)?;
+        }
+        Opcode::Null => {
+          for register in op.p2..=op.p3.max(op.p2) {
+            registers.set(register, Value::Null)?;
+          }
+        }
+        Opcode::Variable => {
+          registers.set(op.p2, ctx.parameter(op.p1 as usize))?;
+        }
+        Opcode::SCopy => {
+          let value = registers.get(op.p1)?.clone();
+          registers.set(op.p2, value)?;
+        }
+        Opcode::ResultRow => {
+          return Ok(Some(registers.range(op.p1, op.p2)?.to_vec()));
+        }
+        Opcode::Add
+        | Opcode::Subtract
+        | Opcode::Multiply
+        | Opcode::Divide
+        | Opcode::Remainder
+        | Opcode::Concat
+        | Opcode::BitAnd
+        | Opcode::BitOr
+        | Opcode::ShiftLeft
+        | Opcode::ShiftRight => {
+          let operator = match op.opcode {
+            Opcode::Add => BinaryOperator::Add,
+            Opcode::Subtract => BinaryOperator::Subtract,
+            Opcode::Multiply => BinaryOperator::Multiply,
+            Opcode::Divide => BinaryOperator::Divide,
+            Opcode::Remainder => BinaryOperator::Modulo,
+            Opcode::Concat => BinaryOperator::Concat,
+            Opcode::BitAnd => BinaryOperator::BitAnd,
+            Opcode::BitOr => BinaryOperator::BitOr,
+            Opcode::ShiftLeft => BinaryOperator::ShiftLeft,
+            _ => BinaryOperator::ShiftRight,
+          };
+          let value =
+            binary(operator, registers.get(op.p2)?, registers.get(op.p1)?);
+          registers.set(op.p3, value)?;
+        }
+        Opcode::And | Opcode::Or => {
+          let left = is_true(registers.get(op.p1)?);
+          let right = is_true(registers.get(op.p2)?);
+          // The value that decides the result whatever the other one is.
+          let decisive = op.opcode == Opcode::Or;
+          let value = match (left, right) {
+            (Some(left), _) if left == decisive => Some(decisive),
+            (_, Some(right)) if right == decisive => Some(decisive),
+            (Some(_), Some(_)) => Some(!decisive),
+            _ => None,
+          };
+          registers.set(op.p3, from_bool(value))?;
+        }
+        Opcode::Not => {
+          let value = is_true(registers.get(op.p1)?).map(|is| !is);
+          registers.set(op.p2, from_bool(value))?;
+        }
+        Opcode::BitNot => {
+          let value = bit_not(registers.get(op.p1)?);
+          registers.set(op.p2, value)?;
+        }
+        Opcode::Eq
+        | Opcode::Ne
+        | Opcode::Lt
+        | Opcode::Le
+        | Opcode::Gt
+        | Opcode::Ge => {
+          let left = registers.get(op.p3)?;
+          let right = registers.get(op.p1)?;
+          let ordering =
+            if op.p5 & NULL_EQ != 0 && (left.is_null() || right.is_null()) {
+              Some(match left.is_null() && right.is_null() {
+                true => Ordering::Equal,
+                false => Ordering::Less,
+              })
+            } else {
+              let affinity = affinity_from_code((op.p5 & AFFINITY_MASK) as u8);
+              let collation = match &op.p4 {
+                P4::Collation(collation) => collation,
+                _ => &Collation::Binary,
+              };
+              compare(left, right, affinity, collation)
+            };
+          let jumps = match ordering {
+            None => op.p5 & JUMP_IF_NULL != 0,
+            Some(ordering) => match op.opcode {
+              Opcode::Eq => ordering.is_eq(),
+              Opcode::Ne => ordering.is_ne(),
+              Opcode::Lt => ordering.is_lt(),
+              Opcode::Le => ordering.is_le(),
+              Opcode::Gt => ordering.is_gt(),
+              _ => ordering.is_ge(),
+            },
+          };
+          if jumps {
+            *pc = target;
+          }
+        }
+        Opcode::ZeroOrNull => {
+          let is_null =
+            registers.get(op.p1)?.is_null() || registers.get(op.p3)?.is_null();
+          let value = match is_null {
+            true => Value::Null,
+            false => Value::Integer(0),
+          };
+          registers.set(op.p2, value)?;
+        }
+        Opcode::Cast => {
+          let affinity = affinity_from_code(op.p2 as u8).ok_or(
+            SqliteError::Custom(format!("Unknown affinity {}", op.p2)),
+          )?;
+          let value = affinity.cast(registers.get(op.p1)?.clone());
+          registers.set(op.p1, value)?;
+        }
+        Opcode::Affinity => {
+          let P4::Text(affinities) = &op.p4 else {
+            continue;
+          };
+          for (register, code) in (op.p1..).zip(affinities.bytes()) {
+            if let Some(affinity) = affinity_from_code(code) {
+              let value = affinity.apply(registers.get(register)?.clone());
+              registers.set(register, value)?;
+            }
+          }
+        }
+        Opcode::RealAffinity => {
+          if let Value::Integer(int) = *registers.get(op.p1)? {
+            registers.set(op.p1, Value::Real(int as f64))?;
+          }
+        }
+        Opcode::Function => {
+          let P4::Function {
+            function,
+            argc,
+            collation,
+          } = &op.p4
+          else {
+            return Err(SqliteError::Custom("No function to call".into()));
+          };
+          let arguments = registers.range(op.p2, *argc as i32)?;
+          let value = function.call(ctx, arguments, collation)?;
+          registers.set(op.p3, value)?;
+        }
+        Opcode::AggStep | Opcode::AggFinal => {
+          let P4::Aggregate(call) = &op.p4 else {
+            return Err(SqliteError::Custom("No aggregate to call".into()));
+          };
+          let slot = match op.opcode {
+            Opcode::AggStep => op.p3,
+            _ => op.p1,
+          };
+          let accumulator = usize::try_from(slot)
+            .ok()
+            .and_then(|idx| accumulators.get_mut(idx))
+            .ok_or(SqliteError::Custom(format!(
+              "Accumulator {slot} is out of range"
+            )))?;
+          if op.opcode == Opcode::AggFinal {
+            let finished = accumulator.take();
+            let finished = finished.unwrap_or_else(|| Accumulator::new(call));
+            registers.set(op.p3, finished.finish(call)?)?;
+            continue;
+          }
+          let row = registers.range(op.p2, op.p5.into())?;
+          let accumulator =
+            accumulator.get_or_insert_with(|| Accumulator::new(call));
+          let is_best = accumulator.step(call, ctx, row)?;
+          if op.p1 != 0 {
+            registers.set(op.p1, Value::Integer(is_best.into()))?;
+          }
+        }
+        Opcode::OpenRead => {
+          let root = op.p2 as u32;
+          let opened = match &op.p4 {
+            P4::Table(definition) => Cursor::Table {
+              cursor: TableCursor::new(definition.clone(), root),
+              row: None,
+              null_row: false,
+            },
+            P4::KeyInfo(key_info, _) => Cursor::Index {
+              cursor: BtreeCursor::new(root),
+              key_info: key_info.clone(),
+              record: None,
+              null_row: false,
+            },
+            _ => {
+              return Err(SqliteError::Custom(
+                "OpenRead without a table or index".into(),
+              ))
+            }
+          };
+          open(cursors, op.p1, opened)?;
+        }
+        Opcode::Rewind | Opcode::Next | Opcode::Last | Opcode::Prev => {
+          let cursor = cursor(cursors, op.p1)?;
+          let is_null_row = matches!(
+            cursor,
+            Cursor::Table { null_row: true, .. }
+              | Cursor::Index { null_row: true, .. }
+          );
+          let rewind = matches!(op.opcode, Opcode::Rewind | Opcode::Last);
+          if is_null_row && !rewind {
+            continue;
+          }
+          cursor.moved();
+          let mut btree = ctx.btree();
+          let has_row = match (cursor, op.opcode) {
+            (Cursor::Table { cursor, .. }, Opcode::Rewind) => {
+              cursor.first(&mut btree)?
+            }
+            (Cursor::Table { cursor, .. }, Opcode::Next) => {
+              cursor.next(&mut btree)?
+            }
+            (Cursor::Table { cursor, .. }, Opcode::Last) => {
+              cursor.last(&mut btree)?
+            }
+            (Cursor::Table { cursor, .. }, _) => cursor.prev(&mut btree)?,
+            (Cursor::Index { cursor, .. }, Opcode::Rewind) => {
+              cursor.first(&mut btree)?
+            }
+            (Cursor::Index { cursor, .. }, Opcode::Next) => {
+              cursor.next(&mut btree)?
+            }
+            (Cursor::Index { cursor, .. }, Opcode::Last) => {
+              cursor.last(&mut btree)?
+            }
+            (Cursor::Index { cursor, .. }, _) => cursor.prev(&mut btree)?,
+            (Cursor::List { rows, position, .. }, opcode) => {
+              // Once before the first row, the position is past the last.
+              *position = match opcode {
+                Opcode::Rewind => 0,
+                Opcode::Next => *position + 1,
+                Opcode::Last => rows.len().wrapping_sub(1),
+                _ => position.wrapping_sub(1),
+              };
+              *position < rows.len()
+            }
+            _ => return Err(not_an("index")),
+          };
+          if has_row != rewind {
+            *pc = target;
+          }
+        }
+        Opcode::Column => {
+          let column = op.p2 as usize;
+          let value = match cursor(cursors, op.p1)? {
+            Cursor::Table { null_row: true, .. }
+            | Cursor::Index { null_row: true, .. } => None,
+            Cursor::Table { cursor, row, .. } => {
+              let row = match row {
+                Some(row) => row,
+                None => row.insert(cursor.row(&mut ctx.btree())?),
+              };
+              row.get(column).cloned()
+            }
+            index @ Cursor::Index { .. } => {
+              index.record(ctx)?.get(column).cloned()
+            }
+            Cursor::Sorter { row, .. } => {
+              row.as_ref().and_then(|row| row.get(column)).cloned()
+            }
+            Cursor::List { rows, position, .. } => {
+              rows.get(*position).and_then(|row| row.get(column)).cloned()
+            }
+            Cursor::Ephemeral(_) | Cursor::Write(_) => {
+              return Err(not_an("table"))
+            }
+          };
+          registers.set(op.p3, value.unwrap_or(Value::Null))?;
+        }
+        Opcode::Rowid => {
+          let rowid = match cursor(cursors, op.p1)? {
+            Cursor::Table {
+              cursor,
+              null_row: false,
+              ..
+            } => cursor.rowid(&ctx.btree())?,
+            Cursor::Table { .. } => None,
+            _ => return Err(not_an("table")),
+          };
+          registers.set(op.p2, rowid.map_or(Value::Null, Value::Integer))?;
+        }
+        Opcode::SeekRowid => {
+          let rowid = as_rowid(registers.get(op.p3)?);
+          let cursor = cursor(cursors, op.p1)?;
+          cursor.moved();
+          let Cursor::Table { cursor, .. } = cursor else {
+            return Err(not_an("table"));
+          };
+          let found = match rowid {
+            Some(rowid) => cursor.seek_rowid(&mut ctx.btree(), rowid)?,
+            None => false,
+          };
+          if !found {
+            *pc = target;
+          }
+        }
+        Opcode::SeekGE | Opcode::SeekGT | Opcode::SeekLE | Opcode::SeekLT => {
+          let inclusive = matches!(op.opcode, Opcode::SeekGE | Opcode::SeekLE);
+          let backwards = matches!(op.opcode, Opcode::SeekLE | Opcode::SeekLT);
+          let key = key(registers, op)?;
+          let cursor = cursor(cursors, op.p1)?;
+          cursor.moved();
+          let mut btree = ctx.btree();
+          let found = match cursor {
+            Cursor::Table { cursor, .. } => match cursor.key_cursor() {
+              Some((cursor, key_info)) => seek_key(
+                cursor, &mut btree, key_info, key, backwards, inclusive,
+              )?,
+              None if backwards => {
+                match key.first().and_then(|value| last_rowid(value, inclusive))
+                {
+                  Some(rowid) => cursor.seek_last_rowid(&mut btree, rowid)?,
+                  None => false,
+                }
+              }
+              None => match key
+                .first()
+                .and_then(|value| first_rowid(value, inclusive))
+              {
+                Some(rowid) => cursor.seek_first_rowid(&mut btree, rowid)?,
+                None => false,
+              },
+            },
+            Cursor::Index {
+              cursor, key_info, ..
+            } => {
+              seek_key(cursor, &mut btree, key_info, key, backwards, inclusive)?
+            }
+            _ => return Err(not_an("index")),
+          };
+          if !found {
+            *pc = target;
+          }
+        }
+        Opcode::IdxGT | Opcode::IdxGE | Opcode::IdxLT | Opcode::IdxLE => {
+          let key = key(registers, op)?;
+          let ordering = match cursor(cursors, op.p1)? {
+            Cursor::Table { cursor, .. } => {
+              let (cursor, key_info) =
+                cursor.key_cursor().ok_or_else(|| not_an("index"))?;
+              key_info.compare(&cursor.record(&mut ctx.btree())?, key)
+            }
+            cursor => {
+              let record = cursor.record(ctx)?.to_vec();
+              let Cursor::Index { key_info, .. } = cursor else {
+                return Err(not_an("index"));
+              };
+              key_info.compare(&record, key)
+            }
+          };
+          let jumps = match op.opcode {
+            Opcode::IdxGT => ordering.is_gt(),
+            Opcode::IdxGE => ordering.is_ge(),
+            Opcode::IdxLT => ordering.is_lt(),
+            _ => ordering.is_le(),
+          };
+          if jumps {
+            *pc = target;
+          }
+        }
+        Opcode::IdxRowid | Opcode::DeferredSeek => {
+          let record = cursor(cursors, op.p1)?.record(ctx)?;
+          let rowid = record.last().and_then(as_rowid);
+          if op.opcode == Opcode::IdxRowid {
+            let rowid = rowid.map_or(Value::Null, Value::Integer);
+            registers.set(op.p2, rowid)?;
+            continue;
+          }
+          let table = cursor(cursors, op.p3)?;
+          table.moved();
+          let Cursor::Table { cursor, .. } = table else {
+            return Err(not_an("table"));
+          };
+          let found = match rowid {
+            Some(rowid) => cursor.seek_rowid(&mut ctx.btree(), rowid)?,
+            None => false,
+          };
+          if !found {
+            return Err(SqliteError::Corrupt(format!(
+              "Index entry of table [{}] without a row",
+              cursor.definition().name()
+            )));
+          }
+        }
+        Opcode::NotFound => {
+          let key = key(registers, op)?;
+          let cursor = cursor(cursors, op.p1)?;
+          cursor.moved();
+          let Cursor::Table { cursor, .. } = cursor else {
+            return Err(not_an("table"));
+          };
+          if !cursor.seek_primary_key(&mut ctx.btree(), key)? {
+            *pc = target;
+          }
+        }
+        Opcode::NullRow => {
+          let cursor = cursor(cursors, op.p1)?;
+          cursor.moved();
+          if let Cursor::Table { null_row, .. }
+          | Cursor::Index { null_row, .. } = cursor
+          {
+            *null_row = true;
+          }
+        }
+        Opcode::SorterOpen => {
+          let P4::SortKeys(keys) = &op.p4 else {
+            return Err(SqliteError::Custom("Sorter without keys".into()));
+          };
+          let sorter = Cursor::Sorter {
+            keys: keys.clone(),
+            limit: op.p3,
+            sorter: None,
+            sorted: None,
+            row: None,
+          };
+          open(cursors, op.p1, sorter)?;
+        }
+        Opcode::SorterInsert => {
+          let row = registers.range(op.p2, op.p3)?.to_vec();
+          let Cursor::Sorter {
+            keys,
+            limit,
+            sorter,
+            ..
+          } = cursor(cursors, op.p1)?
+          else {
+            return Err(not_an("sorter"));
+          };
+          let sorter = match sorter {
+            Some(sorter) => sorter,
+            None => {
+              // The limit is known once the first row comes.
+              let top = match *limit {
+                0 => None,
+                limit => usize::try_from(registers.integer(limit)?)
+                  .ok()
+                  .filter(|top| *top > 0),
+              };
+              let memory_limit = ctx.sort_memory_limit();
+              sorter.insert(Sorter::new(keys.clone(), memory_limit, top))
+            }
+          };
+          sorter.push(row)?;
+        }
+        Opcode::SorterSort | Opcode::SorterNext => {
+          let Cursor::Sorter {
+            keys,
+            sorter,
+            sorted,
+            row,
+            ..
+          } = cursor(cursors, op.p1)?
+          else {
+            return Err(not_an("sorter"));
+          };
+          let sorted = match (sorted, op.opcode) {
+            (sorted, Opcode::SorterSort) => {
+              let sorter = sorter.take().unwrap_or_else(|| {
+                Sorter::new(keys.clone(), ctx.sort_memory_limit(), None)
+              });
+              sorted.insert(sorter.finish()?)
+            }
+            (Some(sorted), _) => sorted,
+            (None, _) => return Err(not_an("sorted sorter")),
+          };
+          *row = sorted.next_row()?;
+          if row.is_some() == (op.opcode == Opcode::SorterNext) {
+            *pc = target;
+          }
+        }
+        Opcode::OpenEphemeral => {
+          let opened = match &op.p4 {
+            P4::Collations(collations) => {
+              Cursor::Ephemeral(KeySet::new(collations))
+            }
+            P4::KeyInfo(key_info, _) => Cursor::List {
+              key_info: key_info.clone(),
+              rows: vec![],
+              position: 0,
+            },
+            _ => {
+              return Err(SqliteError::Custom(
+                "OpenEphemeral without collations or key".into(),
+              ))
+            }
+          };
+          open(cursors, op.p1, opened)?;
+        }
+        Opcode::Found | Opcode::IdxInsert => {
+          let values = match op.opcode {
+            Opcode::Found => key(registers, op)?,
+            _ => registers.range(op.p2, op.p3)?,
+          };
+          match cursor(cursors, op.p1)? {
+            Cursor::Ephemeral(keys) if op.opcode == Opcode::IdxInsert => {
+              keys.insert(values, ctx.sort_memory_limit())?;
+            }
+            Cursor::Ephemeral(keys) => {
+              if keys.contains(values)? {
+                *pc = target;
+              }
+            }
+            Cursor::List { key_info, rows, .. }
+              if op.opcode == Opcode::IdxInsert =>
+            {
+              let found =
+                rows.binary_search_by(|row| key_info.compare(row, values));
+              if let Err(position) = found {
+                rows.insert(position, values.to_vec());
+              }
+            }
+            _ => return Err(not_an("ephemeral table")),
+          }
+        }
+        Opcode::OpenWrite => open(cursors, op.p1, Cursor::Write(vec![]))?,
+        Opcode::Insert | Opcode::Delete => {
+          let row = registers.range(op.p2, op.p3)?.to_vec();
+          let Cursor::Write(rows) = cursor(cursors, op.p1)? else {
+            return Err(not_an("table being changed"));
+          };
+          rows.push(row);
+        }
+        Opcode::Close => {
+          let idx = usize::try_from(op.p1).unwrap_or(usize::MAX);
+          let closed = cursors.get_mut(idx).and_then(Option::take);
+          if let Some(Cursor::Write(rows)) = closed {
+            let change = change.as_deref_mut().ok_or_else(no_change)?;
+            change.write(ctx, rows)?;
+          }
+        }
+        Opcode::FkCheck => {
+          let change = change.as_deref_mut().ok_or_else(no_change)?;
+          change.check_foreign_keys(ctx)?;
+        }
+      }
+    }
+  }
+}
+
+/// Moves an index cursor to the first entry whose key is not less than
+/// `key`, or the last one not greater when `backwards`, or past those equal
+/// to it unless `inclusive`. Returns false when there is none.
+fn seek_key(
+  cursor: &mut BtreeCursor,
+  btree: &mut SqliteBtree<'_>,
+  key_info: &KeyInfo,
+  key: &[Value],
+  backwards: bool,
+  inclusive: bool,
+) -> SqliteResult<bool> {
+  let mut found = match backwards {
+    true => cursor.seek_last_key(btree, key, key_info)?,
+    false => cursor.seek_first_key(btree, key, key_info)?,
+  };
+  while !inclusive
+    && found
+    && key_info.compare(&cursor.record(btree)?, key).is_eq()
+  {
+    found = match backwards {
+      true => cursor.prev(btree)?,
+      false => cursor.next(btree)?,
+    };
+  }
+  Ok(found)
+}
+
+fn open(
+  cursors: &mut [Option<Cursor>],
+  idx: i32,
+  cursor: Cursor,
+) -> SqliteResult<()> {
+  let slot = usize::try_from(idx)
+    .ok()
+    .and_then(|idx| cursors.get_mut(idx))
+    .ok_or(SqliteError::Custom(format!("Cursor {idx} is out of range")))?;
+  *slot = Some(cursor);
+  Ok(())
+}
+
+impl Operator for Vdbe {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    self.run(ctx, None)
+  }
+
+  fn reset(&mut self) {
+    self.pc = 0;
+    self.registers.0.fill(Value::Null);
+    self.cursors.iter_mut().for_each(|cursor| *cursor = None);
+    self.accumulators.iter_mut().for_each(|slot| *slot = None);
+  }
+}
+
+/// Compiles the plan of a query into a program returning its rows, when the
+/// machine can run every operator of it.
+pub(crate) fn compile(
+  root: &dyn Operator,
+  schema_cookie: u32,
+) -> Codegen<Program> {
+  let mut builder = Builder::new(schema_cookie);
+  root.compile(&mut builder, &mut |builder, row, _| {
+    let first = builder.row_registers(row);
+    builder.add(Opcode::ResultRow, first, row.len() as i32, 0);
+    Ok(())
+  })?;
+  Ok(builder.finish())
+}
+
+/// Compiles an `INSERT`, `UPDATE` or `DELETE` statement into a program
+/// writing the rows it changes with `change`.
+pub(crate) fn compile_change(
+  change: &Change,
+  schema_cookie: u32,
+) -> Codegen<Program> {
+  let mut builder = Builder::new(schema_cookie);
+  change.compile(&mut builder)?;
+  Ok(builder.finish())
+}
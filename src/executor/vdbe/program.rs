@@ -0,0 +1,627 @@
+//! The instructions of a program: an opcode and up to five operands each,
+//! displayed the way `EXPLAIN` does.
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+use crate::executor::aggregate::AggregateCall;
+use crate::executor::function::ScalarFunction;
+use crate::executor::sorter::SortKey;
+use crate::runtime::{Affinity, Collation, KeyInfo, TableDefinition, Value};
+use core::fmt::{self, Display, Formatter};
+
+/// Flag of the comparison opcodes: jump when either operand is NULL.
+pub(crate) const JUMP_IF_NULL: u16 = 0x10;
+/// Flag of the comparison opcodes: NULLs are equal to each other and unequal
+/// to anything else, as `IS` and `IS NOT` compare.
+pub(crate) const NULL_EQ: u16 = 0x80;
+/// Bits of the comparison opcodes holding the affinity of the comparison.
+pub(crate) const AFFINITY_MASK: u16 = 0x47;
+
+/// The operations of the virtual machine, named as SQLite names them. Jumps
+/// go to the address in P2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Opcode {
+  /// Jumps to P2, where the program starts.
+  Init,
+  Goto,
+  /// Stores the address of the next instruction in r\[P1\], and jumps to
+  /// the subroutine at P2.
+  Gosub,
+  /// Returns from a subroutine, to the address in r\[P1\].
+  Return,
+  /// Ends the program. An error code in P1 makes it fail with the message
+  /// in P4.
+  Halt,
+  /// Checks that the schema cookie is still P3, as the program was compiled
+  /// against that schema. P2 is 1 when the program writes.
+  Transaction,
+  /// Jumps when r\[P1\] is true, or NULL and P3 is not zero.
+  If,
+  /// Jumps when r\[P1\] is false, or NULL and P3 is not zero.
+  IfNot,
+  IsNull,
+  NotNull,
+  IfPos,
+  DecrJumpZero,
+  /// Converts r\[P1\] to an integer, failing with "datatype mismatch", or
+  /// jumping to P2 when it is not zero, if that cannot be done.
+  MustBeInt,
+  OffsetLimit,
+  Integer,
+  Int64,
+  Real,
+  String8,
+  Blob,
+  Null,
+  Variable,
+  SCopy,
+  /// Returns the P2 registers from P1 as a row of the result.
+  ResultRow,
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Remainder,
+  Concat,
+  BitAnd,
+  BitOr,
+  ShiftLeft,
+  ShiftRight,
+  And,
+  Or,
+  Not,
+  BitNot,
+  /// Compares r\[P3\] to r\[P1\] and jumps when they are equal, as the
+  /// collating sequence in P4 and the affinity and flags in P5 say.
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  ZeroOrNull,
+  /// Converts r\[P1\] to the affinity in P2, as `CAST` does.
+  Cast,
+  /// Applies the affinities of P4, one per character, to the P2 registers
+  /// from P1.
+  Affinity,
+  RealAffinity,
+  /// Calls the function in P4 with the registers from P2 as arguments.
+  Function,
+  /// Feeds a row, the P5 registers from P2, to accumulator P3 of the
+  /// aggregate call in P4, which computes its arguments from the row,
+  /// rather than to a register given the arguments as in SQLite. When P1
+  /// is not zero, r\[P1\] is set to whether the row is the new minimum or
+  /// maximum.
+  AggStep,
+  /// Stores the result of accumulator P1 of the aggregate call in P4 in
+  /// r\[P3\], and starts it afresh.
+  AggFinal,
+  /// Opens cursor P1 on the b-tree rooted at page P2: a table when P4 is
+  /// its column count, or else an index.
+  OpenRead,
+  Rewind,
+  Next,
+  /// Moves cursor P1 to its last entry, jumping to P2 when it has none.
+  Last,
+  /// Moves cursor P1 back to its previous entry, jumping to P2 when it has
+  /// one.
+  Prev,
+  Column,
+  Rowid,
+  SeekRowid,
+  SeekGE,
+  SeekGT,
+  /// Moves cursor P1 to its last entry whose key is not greater than the
+  /// P4 registers from P3, jumping to P2 when it has none.
+  SeekLE,
+  SeekLT,
+  IdxGT,
+  IdxGE,
+  /// Jumps when the key of the current entry of cursor P1 is less than the
+  /// P4 registers from P3.
+  IdxLT,
+  IdxLE,
+  IdxRowid,
+  /// Moves table cursor P3 to the row of the current entry of index cursor
+  /// P1. The row is looked up right away, rather than when read first.
+  DeferredSeek,
+  /// Moves table cursor P1 to the row whose primary key is the P4
+  /// registers from P3, jumping to P2 when there is none.
+  NotFound,
+  NullRow,
+  /// Opens cursor P1 on a sorter of rows of P2 values, sorted by the keys
+  /// in P4. When P3 is not zero, only the first r\[P3\] rows are kept.
+  SorterOpen,
+  /// Adds a row to sorter P1: the P3 registers from P2, rather than a
+  /// record as in SQLite.
+  SorterInsert,
+  SorterSort,
+  SorterNext,
+  /// Opens cursor P1 on a set of keys of P2 values, compared with the
+  /// collating sequences in P4.
+  OpenEphemeral,
+  Found,
+  /// Adds a key to the set of cursor P1: the P3 registers from P2, rather
+  /// than a record as in SQLite.
+  IdxInsert,
+  /// Opens cursor P1 to change the table rooted at P2, as the statement
+  /// compiled does.
+  OpenWrite,
+  /// Adds a row to those cursor P1 inserts, or updates in place of their
+  /// old values: the P3 registers from P2, rather than a record as in
+  /// SQLite. The rows are written once all are read, when the cursor is
+  /// closed.
+  Insert,
+  /// Adds a row to those cursor P1 deletes: the P3 registers from P2.
+  Delete,
+  /// Closes cursor P1. A cursor opened by `OpenWrite` first writes the
+  /// rows added to it, checking the constraints of the table, keeping its
+  /// indexes up to date and firing its triggers.
+  Close,
+  /// Fails when the changes of the statement left foreign key constraints
+  /// unsatisfied.
+  FkCheck,
+}
+
+impl Opcode {
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      Self::Init => "Init",
+      Self::Goto => "Goto",
+      Self::Gosub => "Gosub",
+      Self::Return => "Return",
+      Self::Halt => "Halt",
+      Self::Transaction => "Transaction",
+      Self::If => "If",
+      Self::IfNot => "IfNot",
+      Self::IsNull => "IsNull",
+      Self::NotNull => "NotNull",
+      Self::IfPos => "IfPos",
+      Self::DecrJumpZero => "DecrJumpZero",
+      Self::MustBeInt => "MustBeInt",
+      Self::OffsetLimit => "OffsetLimit",
+      Self::Integer => "Integer",
+      Self::Int64 => "Int64",
+      Self::Real => "Real",
+      Self::String8 => "String8",
+      Self::Blob => "Blob",
+      Self::Null => "Null",
+      Self::Variable => "Variable",
+      Self::SCopy => "SCopy",
+      Self::ResultRow => "ResultRow",
+      Self::Add => "Add",
+      Self::Subtract => "Subtract",
+      Self::Multiply => "Multiply",
+      Self::Divide => "Divide",
+      Self::Remainder => "Remainder",
+      Self::Concat => "Concat",
+      Self::BitAnd => "BitAnd",
+      Self::BitOr => "BitOr",
+      Self::ShiftLeft => "ShiftLeft",
+      Self::ShiftRight => "ShiftRight",
+      Self::And => "And",
+      Self::Or => "Or",
+      Self::Not => "Not",
+      Self::BitNot => "BitNot",
+      Self::Eq => "Eq",
+      Self::Ne => "Ne",
+      Self::Lt => "Lt",
+      Self::Le => "Le",
+      Self::Gt => "Gt",
+      Self::Ge => "Ge",
+      Self::ZeroOrNull => "ZeroOrNull",
+      Self::Cast => "Cast",
+      Self::Affinity => "Affinity",
+      Self::RealAffinity => "RealAffinity",
+      Self::Function => "Function",
+      Self::AggStep => "AggStep",
+      Self::AggFinal => "AggFinal",
+      Self::OpenRead => "OpenRead",
+      Self::Rewind => "Rewind",
+      Self::Next => "Next",
+      Self::Last => "Last",
+      Self::Prev => "Prev",
+      Self::Column => "Column",
+      Self::Rowid => "Rowid",
+      Self::SeekRowid => "SeekRowid",
+      Self::SeekGE => "SeekGE",
+      Self::SeekGT => "SeekGT",
+      Self::SeekLE => "SeekLE",
+      Self::SeekLT => "SeekLT",
+      Self::IdxGT => "IdxGT",
+      Self::IdxGE => "IdxGE",
+      Self::IdxLT => "IdxLT",
+      Self::IdxLE => "IdxLE",
+      Self::IdxRowid => "IdxRowid",
+      Self::DeferredSeek => "DeferredSeek",
+      Self::NotFound => "NotFound",
+      Self::NullRow => "NullRow",
+      Self::SorterOpen => "SorterOpen",
+      Self::SorterInsert => "SorterInsert",
+      Self::SorterSort => "SorterSort",
+      Self::SorterNext => "SorterNext",
+      Self::OpenEphemeral => "OpenEphemeral",
+      Self::Found => "Found",
+      Self::IdxInsert => "IdxInsert",
+      Self::OpenWrite => "OpenWrite",
+      Self::Insert => "Insert",
+      Self::Delete => "Delete",
+      Self::Close => "Close",
+      Self::FkCheck => "FkCheck",
+    }
+  }
+
+  /// What the instruction does, in terms of its operands, from which the
+  /// comment of `EXPLAIN` is made.
+  fn synopsis(self) -> Option<&'static str> {
+    Some(match self {
+      Self::Init => "Start at P2",
+      Self::IsNull => "if r[P1]==NULL goto P2",
+      Self::NotNull => "if r[P1]!=NULL goto P2",
+      Self::IfPos => "if r[P1]>0 then r[P1]-=P3, goto P2",
+      Self::DecrJumpZero => "if (--r[P1])==0 goto P2",
+      Self::OffsetLimit => {
+        "if r[P1]>0 then r[P2]=r[P1]+max(0,r[P3]) else r[P2]=(-1)"
+      }
+      Self::Integer => "r[P2]=P1",
+      Self::Int64 | Self::Real => "r[P2]=P4",
+      Self::String8 => "r[P2]='P4'",
+      Self::Blob => "r[P2]=P4 (len=P1)",
+      Self::Null => "r[P2..P3]=NULL",
+      Self::Variable => "r[P2]=parameter(P1)",
+      Self::SCopy => "r[P2]=r[P1]",
+      Self::ResultRow => "output=r[P1@P2]",
+      Self::Add => "r[P3]=r[P1]+r[P2]",
+      Self::Subtract => "r[P3]=r[P2]-r[P1]",
+      Self::Multiply => "r[P3]=r[P1]*r[P2]",
+      Self::Divide => "r[P3]=r[P2]/r[P1]",
+      Self::Remainder => "r[P3]=r[P2]%r[P1]",
+      Self::Concat => "r[P3]=r[P2]+r[P1]",
+      Self::BitAnd => "r[P3]=r[P1]&r[P2]",
+      Self::BitOr => "r[P3]=r[P1]|r[P2]",
+      Self::ShiftLeft => "r[P3]=r[P2]<<r[P1]",
+      Self::ShiftRight => "r[P3]=r[P2]>>r[P1]",
+      Self::And => "r[P3]=(r[P1] && r[P2])",
+      Self::Or => "r[P3]=(r[P1] || r[P2])",
+      Self::Not => "r[P2]= !r[P1]",
+      Self::BitNot => "r[P2]= ~r[P1]",
+      Self::Eq => "IF r[P3]==r[P1]",
+      Self::Ne => "IF r[P3]!=r[P1]",
+      Self::Lt => "IF r[P3]<r[P1]",
+      Self::Le => "IF r[P3]<=r[P1]",
+      Self::Gt => "IF r[P3]>r[P1]",
+      Self::Ge => "IF r[P3]>=r[P1]",
+      Self::ZeroOrNull => "r[P2] = 0 OR NULL",
+      Self::Cast => "affinity(r[P1])",
+      Self::Affinity => "affinity(r[P1@P2])",
+      Self::Function => "r[P3]=func(r[P2@NP])",
+      Self::AggStep => "accum=P3 step(r[P2@P5])",
+      Self::AggFinal => "r[P3]=final(accum=P1)",
+      Self::OpenRead | Self::OpenWrite => "root=P2 iDb=P3",
+      Self::Column => "r[P3]=PX cursor P1 column P2",
+      Self::Rowid => "r[P2]=PX rowid of P1",
+      Self::SeekRowid => "intkey=r[P3]",
+      Self::SeekGE
+      | Self::SeekGT
+      | Self::SeekLE
+      | Self::SeekLT
+      | Self::IdxGT
+      | Self::IdxGE
+      | Self::IdxLT
+      | Self::IdxLE
+      | Self::Found
+      | Self::NotFound => "key=r[P3@P4]",
+      Self::IdxRowid => "r[P2]=rowid",
+      Self::DeferredSeek => "Move P3 to P1.rowid if needed",
+      Self::SorterInsert | Self::IdxInsert => "key=r[P2@P3]",
+      Self::Insert | Self::Delete => "row=r[P2@P3]",
+      Self::OpenEphemeral => "nColumn=P2",
+      _ => return None,
+    })
+  }
+
+  /// Whether P2 is the address the instruction may jump to.
+  pub(crate) fn jumps(self) -> bool {
+    matches!(
+      self,
+      Self::Init
+        | Self::Goto
+        | Self::Gosub
+        | Self::If
+        | Self::IfNot
+        | Self::IsNull
+        | Self::NotNull
+        | Self::IfPos
+        | Self::DecrJumpZero
+        | Self::MustBeInt
+        | Self::Eq
+        | Self::Ne
+        | Self::Lt
+        | Self::Le
+        | Self::Gt
+        | Self::Ge
+        | Self::Rewind
+        | Self::Next
+        | Self::Last
+        | Self::Prev
+        | Self::SeekRowid
+        | Self::SeekGE
+        | Self::SeekGT
+        | Self::SeekLE
+        | Self::SeekLT
+        | Self::IdxGT
+        | Self::IdxGE
+        | Self::IdxLT
+        | Self::IdxLE
+        | Self::SorterSort
+        | Self::SorterNext
+        | Self::Found
+        | Self::NotFound
+    )
+  }
+}
+
+/// The fourth operand, which holds what does not fit in an integer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum P4 {
+  None,
+  Int64(i64),
+  Real(f64),
+  Text(String),
+  Blob(Vec<u8>),
+  Collation(Collation),
+  /// The table a cursor reads.
+  Table(TableDefinition),
+  /// How the entries of an index compare, and how many values they hold.
+  KeyInfo(KeyInfo, usize),
+  SortKeys(Vec<SortKey>),
+  Collations(Vec<Collation>),
+  /// The function an instruction calls, the number of its arguments, and
+  /// the collating sequence it compares them with.
+  Function {
+    function: ScalarFunction,
+    argc: usize,
+    collation: Collation,
+  },
+  Aggregate(AggregateCall),
+}
+
+impl Display for P4 {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::None => Ok(()),
+      Self::Int64(int) => write!(f, "{int}"),
+      Self::Real(real) => write!(f, "{real}"),
+      Self::Text(text) => f.write_str(text),
+      // Blobs are shown by their length only, in the comment.
+      Self::Blob(_) => Ok(()),
+      Self::Collation(collation) => write!(f, "{}-8", collation.name()),
+      Self::Table(table) => write!(f, "{}", table.columns().len()),
+      Self::KeyInfo(key_info, fields) => {
+        write!(f, "k({fields}")?;
+        for idx in 0..*fields {
+          let column = key_info.columns().get(idx);
+          let descending = column.is_some_and(|column| column.descending);
+          let collation = match column.map(|column| &column.collation) {
+            Some(Collation::Binary) | None => "",
+            Some(collation) => collation.name(),
+          };
+          write!(f, ",{}{collation}", if descending { "-" } else { "" })?;
+        }
+        f.write_str(")")
+      }
+      Self::SortKeys(keys) => {
+        write!(f, "k({}", keys.len())?;
+        for key in keys {
+          let descending = if key.descending { "-" } else { "" };
+          write!(f, ",{descending}{}", short_name(&key.collation))?;
+        }
+        f.write_str(")")
+      }
+      Self::Collations(collations) => {
+        write!(f, "k({}", collations.len())?;
+        for collation in collations {
+          write!(f, ",{}", short_name(collation))?;
+        }
+        f.write_str(")")
+      }
+      Self::Function { function, argc, .. } => {
+        write!(f, "{}({argc})", function.name())
+      }
+      Self::Aggregate(call) => {
+        write!(f, "{}({})", call.function.name(), call.arguments.len())
+      }
+    }
+  }
+}
+
+/// The name of a collating sequence in a key, `B` standing for `BINARY`.
+fn short_name(collation: &Collation) -> &str {
+  match collation {
+    Collation::Binary => "B",
+    collation => collation.name(),
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Instruction {
+  pub(crate) opcode: Opcode,
+  pub(crate) p1: i32,
+  pub(crate) p2: i32,
+  pub(crate) p3: i32,
+  pub(crate) p4: P4,
+  pub(crate) p5: u16,
+  /// What the instruction is about, like the table a cursor reads.
+  pub(crate) comment: Option<String>,
+}
+
+impl Instruction {
+  pub(crate) fn new(opcode: Opcode, p1: i32, p2: i32, p3: i32) -> Self {
+    Self {
+      opcode,
+      p1,
+      p2,
+      p3,
+      p4: P4::None,
+      p5: 0,
+      comment: None,
+    }
+  }
+
+  /// The comment of `EXPLAIN`: the synopsis of the opcode with the values
+  /// of the operands filled in, followed by the comment of the instruction.
+  fn explain_comment(&self) -> Option<String> {
+    let Some(synopsis) = self.opcode.synopsis() else {
+      return self.comment.clone();
+    };
+    let synopsis = match synopsis.strip_prefix("IF ") {
+      Some(condition) => format!("if {condition} goto P2"),
+      None => synopsis.into(),
+    };
+    let mut out = String::new();
+    let mut seen_comment = false;
+    let mut rest = synopsis.as_str();
+    while let Some(idx) = rest.find('P') {
+      out.push_str(&rest[..idx]);
+      let operand = rest[idx + 1..].chars().next();
+      rest = &rest[idx + 1..];
+      match operand {
+        Some('4') => {
+          out.push_str(&self.p4.to_string());
+          rest = &rest[1..];
+        }
+        Some('X') => {
+          if let Some(comment) = self.comment.as_deref() {
+            out.push_str(comment);
+            seen_comment = true;
+            rest = "";
+            break;
+          }
+          rest = &rest[1..];
+        }
+        Some(operand @ '1'..='5') => {
+          let value = self.operand(operand);
+          rest = &rest[1..];
+          if let Some(after) = rest.strip_prefix("@NP") {
+            let count = match self.p4 {
+              P4::Function { argc, .. } => argc as i64,
+              _ => 1,
+            };
+            out.push_str(&range(value, count));
+            rest = after;
+          } else if let Some(after) = rest.strip_prefix("@P") {
+            let mut count = self.operand(after.chars().next().unwrap_or('1'));
+            rest = &after[1..];
+            if let Some(after) = rest.strip_prefix("+1") {
+              count += 1;
+              rest = after;
+            }
+            out.push_str(&range(value, count));
+          } else {
+            out.push_str(&value.to_string());
+            if self.p3 == 0 {
+              if let Some(after) = rest.strip_prefix("..P3") {
+                rest = after;
+              }
+            }
+          }
+        }
+        _ => out.push('P'),
+      }
+    }
+    out.push_str(rest);
+    if let (false, Some(comment)) = (seen_comment, &self.comment) {
+      out.push_str("; ");
+      out.push_str(comment);
+    }
+    Some(out)
+  }
+
+  fn operand(&self, operand: char) -> i64 {
+    match operand {
+      '1' => self.p1.into(),
+      '2' => self.p2.into(),
+      '3' => self.p3.into(),
+      '4' => match self.p4 {
+        P4::Int64(int) => int,
+        _ => 0,
+      },
+      _ => self.p5.into(),
+    }
+  }
+}
+
+/// `first` alone, or the range of `count` registers from it.
+fn range(first: i64, count: i64) -> String {
+  match count < 2 {
+    true => first.to_string(),
+    false => format!("{first}..{}", first + count - 1),
+  }
+}
+
+/// A compiled statement, ready to run.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Program {
+  pub(crate) instructions: Vec<Instruction>,
+  /// Number of registers, numbered from 1.
+  pub(crate) registers: usize,
+  pub(crate) cursors: usize,
+  /// Number of accumulators of aggregate calls.
+  pub(crate) accumulators: usize,
+}
+
+impl Program {
+  /// Names of the columns of `EXPLAIN`.
+  pub(crate) const COLUMNS: [&'static str; 8] =
+    ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"];
+
+  /// The rows of `EXPLAIN`, one per instruction.
+  pub(crate) fn explain(&self) -> Vec<Vec<Value>> {
+    let text = |text: String| match text.is_empty() {
+      true => Value::Null,
+      false => Value::Text(text),
+    };
+    self
+      .instructions
+      .iter()
+      .enumerate()
+      .map(|(addr, instruction)| {
+        vec![
+          Value::Integer(addr as i64),
+          Value::Text(instruction.opcode.name().into()),
+          Value::Integer(instruction.p1.into()),
+          Value::Integer(instruction.p2.into()),
+          Value::Integer(instruction.p3.into()),
+          text(instruction.p4.to_string()),
+          Value::Integer(instruction.p5.into()),
+          text(instruction.explain_comment().unwrap_or_default()),
+        ]
+      })
+      .collect()
+  }
+}
+
+/// The code SQLite gives an affinity, in comparison flags and in the P4 of
+/// `Affinity`, with `0x40` standing for none.
+pub(crate) fn affinity_code(affinity: Option<Affinity>) -> u8 {
+  match affinity {
+    None => 0x40,
+    Some(Affinity::Blob) => b'A',
+    Some(Affinity::Text) => b'B',
+    Some(Affinity::Numeric) => b'C',
+    Some(Affinity::Integer) => b'D',
+    Some(Affinity::Real) => b'E',
+  }
+}
+
+pub(crate) fn affinity_from_code(code: u8) -> Option<Affinity> {
+  match code {
+    b'A' => Some(Affinity::Blob),
+    b'B' => Some(Affinity::Text),
+    b'C' => Some(Affinity::Numeric),
+    b'D' => Some(Affinity::Integer),
+    b'E' => Some(Affinity::Real),
+    _ => None,
+  }
+}
@@ -0,0 +1,585 @@
+//! # Window functions
+//!
+//!  Window functions compute a value for each row from the rows of its
+//! partition: those with the same values of the `PARTITION BY` clause,
+//! sorted by the `ORDER BY` clause of the window. Rows reach a window sorted
+//! that way, and each partition is held in memory while the values of its
+//! rows are computed. Ranking functions number the rows and their peers,
+//! the rows that sort the same, while the other functions read the rows of
+//! the frame of the current row.
+//!
+//! *Reference:* https://www.sqlite.org/windowfunctions.html
+
+use super::aggregate::{Accumulator, AggregateCall, AggregateFunction};
+use super::expr::Expr;
+use super::operator::Operator;
+use super::query_plan::QueryPlan;
+use super::sorter::{compare_rows, SortKey};
+use super::value::binary;
+use super::vdbe::{Builder, Codegen, Consumer, Unsupported};
+use super::Context;
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{to_integer, Affinity, Value};
+use crate::sql::ast::{BinaryOperator, FrameExclude, FrameUnit};
+use core::cmp::Ordering;
+use core::slice;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum WindowFunction {
+  RowNumber,
+  Rank,
+  DenseRank,
+  PercentRank,
+  CumeDist,
+  Ntile,
+  Lag,
+  Lead,
+  FirstValue,
+  LastValue,
+  NthValue,
+  /// An aggregate function, over the rows of the frame.
+  Aggregate(AggregateCall),
+}
+
+impl WindowFunction {
+  /// The built-in window function named `name`, and the numbers of
+  /// arguments it takes.
+  pub(crate) fn from_name(name: &str) -> Option<(Self, &'static [usize])> {
+    Some(match name.to_ascii_lowercase().as_str() {
+      "row_number" => (Self::RowNumber, &[0]),
+      "rank" => (Self::Rank, &[0]),
+      "dense_rank" => (Self::DenseRank, &[0]),
+      "percent_rank" => (Self::PercentRank, &[0]),
+      "cume_dist" => (Self::CumeDist, &[0]),
+      "ntile" => (Self::Ntile, &[1]),
+      "lag" => (Self::Lag, &[1, 2, 3]),
+      "lead" => (Self::Lead, &[1, 2, 3]),
+      "first_value" => (Self::FirstValue, &[1]),
+      "last_value" => (Self::LastValue, &[1]),
+      "nth_value" => (Self::NthValue, &[2]),
+      _ => return None,
+    })
+  }
+}
+
+/// A call to a window function.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WindowCall {
+  pub(crate) function: WindowFunction,
+  /// The arguments of a built-in window function. Those of an aggregate
+  /// are part of its call.
+  pub(crate) arguments: Vec<Expr>,
+  pub(crate) partition_by: Vec<Expr>,
+  /// The `ORDER BY` clause of the window: the rows sort by the keys,
+  /// evaluated after the values of their partition.
+  pub(crate) order_by: Vec<Expr>,
+  pub(crate) order: Vec<SortKey>,
+  pub(crate) frame: Frame,
+}
+
+impl WindowCall {
+  /// Calls `f` with each expression of the call and of its window.
+  pub(crate) fn visit_exprs(&self, f: &mut impl FnMut(&Expr)) {
+    if let WindowFunction::Aggregate(call) = &self.function {
+      call.visit_exprs(f);
+    }
+    let exprs = self.arguments.iter().chain(&self.partition_by);
+    exprs.chain(&self.order_by).for_each(f);
+  }
+
+  /// Whether the rows of `other` are partitioned and sorted the same way,
+  /// so that a single window computes both.
+  pub(crate) fn same_window(&self, other: &WindowCall) -> bool {
+    self.partition_by == other.partition_by
+      && self.order_by == other.order_by
+      && self.order == other.order
+  }
+}
+
+/// The rows of the partition that a window function reads for the current
+/// row.
+///
+/// *Reference:* https://www.sqlite.org/windowfunctions.html#frame_specifications
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Frame {
+  pub(crate) unit: FrameUnit,
+  pub(crate) start: Bound,
+  pub(crate) end: Bound,
+  pub(crate) exclude: FrameExclude,
+}
+
+impl Default for Frame {
+  /// `RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW`: the rows up to
+  /// the last peer of the current row.
+  fn default() -> Self {
+    Self {
+      unit: FrameUnit::Range,
+      start: Bound::UnboundedPreceding,
+      end: Bound::CurrentRow,
+      exclude: FrameExclude::NoOthers,
+    }
+  }
+}
+
+/// Where a frame starts or ends. Offsets count rows with `ROWS`, groups of
+/// peers with `GROUPS`, and are the difference with the value of the
+/// `ORDER BY` clause with `RANGE`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Bound {
+  UnboundedPreceding,
+  Preceding(Expr),
+  CurrentRow,
+  Following(Expr),
+  UnboundedFollowing,
+}
+
+/// The rows of a partition, and their groups of partition.
+#[derive(Debug)]
+struct Partition {
+  rows: Vec<Vec<Value>>,
+  /// The group of each row.
+  group: Vec<usize>,
+  /// The position of the first row of each group, followed by the number
+  /// of rows.
+  starts: Vec<usize>,
+}
+
+/// Computes window functions over rows sorted by partition, then by the
+/// `ORDER BY` clause of the window. Each input row holds `width` values,
+/// followed by the values of its partition and those of its sort keys; the
+/// output rows hold the `width` values, followed by the value of each call.
+#[derive(Debug)]
+pub(crate) struct Window {
+  input: Box<dyn Operator>,
+  width: usize,
+  /// Compare the values of the partitions.
+  partition: Vec<SortKey>,
+  /// Compare the values of the `ORDER BY` clause.
+  order: Vec<SortKey>,
+  calls: Vec<WindowCall>,
+  /// The first row of the next partition.
+  pending: Option<Vec<Value>>,
+  /// The rows of the last partition, with their values computed.
+  output: VecDeque<Vec<Value>>,
+  done: bool,
+}
+
+impl Window {
+  pub(crate) fn new(
+    input: Box<dyn Operator>,
+    width: usize,
+    partition: Vec<SortKey>,
+    order: Vec<SortKey>,
+    calls: Vec<WindowCall>,
+  ) -> Self {
+    Self {
+      input,
+      width,
+      partition,
+      order,
+      calls,
+      pending: None,
+      output: VecDeque::new(),
+      done: false,
+    }
+  }
+
+  /// The rows of the next partition, none once there are no more.
+  fn partition(&mut self, ctx: &mut Context<'_>) -> SqliteResult<Partition> {
+    let first = match self.pending.take() {
+      Some(row) => row,
+      None => match self.input.next(ctx)? {
+        Some(row) => row,
+        None => {
+          self.done = true;
+          return Ok(self.peers(vec![]));
+        }
+      },
+    };
+    let mut rows = vec![first];
+    loop {
+      match self.input.next(ctx)? {
+        Some(row) if compare_rows(&self.partition, &rows[0], &row).is_eq() => {
+          rows.push(row)
+        }
+        Some(row) => {
+          self.pending = Some(row);
+          break;
+        }
+        None => {
+          self.done = true;
+          break;
+        }
+      }
+    }
+    Ok(self.peers(rows))
+  }
+
+  /// Splits the rows of a partition into groups of partition.
+  fn peers(&self, rows: Vec<Vec<Value>>) -> Partition {
+    let mut group = Vec::with_capacity(rows.len());
+    let mut starts = vec![];
+    for (idx, row) in rows.iter().enumerate() {
+      let is_peer = idx > 0
+        && compare_rows(&self.order, &rows[idx - 1], row) == Ordering::Equal;
+      if !is_peer {
+        starts.push(idx);
+      }
+      group.push(starts.len() - 1);
+    }
+    starts.push(rows.len());
+    Partition {
+      rows,
+      group,
+      starts,
+    }
+  }
+
+  /// The value of `call` for each row of a partition.
+  fn evaluate(
+    &self,
+    call: &WindowCall,
+    ctx: &mut Context<'_>,
+    partition: &Partition,
+  ) -> SqliteResult<Vec<Value>> {
+    if let WindowFunction::Aggregate(aggregate) = &call.function {
+      return self.aggregate(call, aggregate, ctx, partition);
+    }
+    let rows = &partition.rows;
+    let count = rows.len();
+    let mut values = Vec::with_capacity(count);
+    for (idx, row) in rows.iter().enumerate() {
+      let group = partition.group[idx];
+      let rank = partition.starts[group] + 1;
+      let argument = |ctx: &mut Context<'_>, position: usize, row| match call
+        .arguments
+        .get(position)
+      {
+        Some(argument) => argument.eval(ctx, row),
+        None => Ok(Value::Null),
+      };
+      values.push(match &call.function {
+        WindowFunction::RowNumber => Value::Integer(idx as i64 + 1),
+        WindowFunction::Rank => Value::Integer(rank as i64),
+        WindowFunction::DenseRank => Value::Integer(group as i64 + 1),
+        WindowFunction::PercentRank => Value::Real(match count {
+          1 => 0.0,
+          _ => (rank - 1) as f64 / (count - 1) as f64,
+        }),
+        WindowFunction::CumeDist => {
+          Value::Real(partition.starts[group + 1] as f64 / count as f64)
+        }
+        WindowFunction::Ntile => {
+          let buckets = to_integer(&argument(ctx, 0, row)?);
+          if buckets <= 0 {
+            return Err(SqliteError::Custom(
+              "argument of ntile must be a positive integer".into(),
+            ));
+          }
+          Value::Integer(ntile(idx, count, buckets as usize) as i64)
+        }
+        WindowFunction::Lag | WindowFunction::Lead => {
+          let offset = match call.arguments.len() {
+            1 => Value::Integer(1),
+            _ => Affinity::Numeric.apply(argument(ctx, 1, row)?),
+          };
+          // An offset that is not an integer finds no row, unless it is
+          // text or a blob, read as an integer.
+          let offset = match offset {
+            Value::Integer(offset) => Some(offset),
+            Value::Text(_) | Value::Blob(_) => Some(to_integer(&offset)),
+            _ => None,
+          };
+          let target = offset.and_then(|offset| {
+            match call.function == WindowFunction::Lag {
+              true => (idx as i64).checked_sub(offset),
+              false => (idx as i64).checked_add(offset),
+            }
+          });
+          let target = target.and_then(|target| usize::try_from(target).ok());
+          match target.and_then(|target| rows.get(target)) {
+            Some(other) => argument(ctx, 0, other)?,
+            None => argument(ctx, 2, row)?,
+          }
+        }
+        WindowFunction::FirstValue
+        | WindowFunction::LastValue
+        | WindowFunction::NthValue => {
+          let (start, end) = self.bounds(ctx, &call.frame, partition, idx)?;
+          let mut frame = (start..end).filter(|&other| {
+            !excluded(call.frame.exclude, partition, idx, other)
+          });
+          let found = match call.function {
+            WindowFunction::FirstValue => frame.next(),
+            WindowFunction::LastValue => frame.next_back(),
+            _ => {
+              let nth = match Affinity::Numeric.apply(argument(ctx, 1, row)?) {
+                Value::Integer(nth @ 1..) => nth,
+                _ => {
+                  return Err(SqliteError::Custom(
+                    "second argument to nth_value must be a positive integer"
+                      .into(),
+                  ))
+                }
+              };
+              frame.nth(usize::try_from(nth - 1).unwrap_or(usize::MAX))
+            }
+          };
+          match found {
+            Some(other) => argument(ctx, 0, &rows[other])?,
+            None => Value::Null,
+          }
+        }
+        WindowFunction::Aggregate(_) => Value::Null,
+      });
+    }
+    Ok(values)
+  }
+
+  /// The value of an aggregate over the frame of each row. When frames
+  /// start with the partition, each one only adds rows to the previous one.
+  /// Window functions registered on the connection take the rows leaving
+  /// the frame out instead of starting over.
+  fn aggregate(
+    &self,
+    call: &WindowCall,
+    aggregate: &AggregateCall,
+    ctx: &mut Context<'_>,
+    partition: &Partition,
+  ) -> SqliteResult<Vec<Value>> {
+    let (rows, frame) = (&partition.rows, &call.frame);
+    let growing = frame.start == Bound::UnboundedPreceding
+      && frame.exclude == FrameExclude::NoOthers;
+    let sliding = frame.exclude == FrameExclude::NoOthers
+      && matches!(&aggregate.function, AggregateFunction::User(function) if function.is_window());
+    let mut values = Vec::with_capacity(rows.len());
+    let mut accumulator = Accumulator::new(aggregate);
+    let (mut removed, mut added) = (0, 0);
+    for idx in 0..rows.len() {
+      let (start, end) = self.bounds(ctx, frame, partition, idx)?;
+      if !growing {
+        if !sliding || start < removed || start > added || end < added {
+          accumulator = Accumulator::new(aggregate);
+          (removed, added) = (start, start);
+        }
+        while removed < start {
+          accumulator.inverse(aggregate, ctx, &rows[removed])?;
+          removed += 1;
+        }
+      }
+      while added < end {
+        if !excluded(frame.exclude, partition, idx, added) {
+          accumulator.step(aggregate, ctx, &rows[added])?;
+        }
+        added += 1;
+      }
+      values.push(accumulator.value(aggregate)?);
+    }
+    Ok(values)
+  }
+
+  /// The positions of the first row of the frame of the row at `idx`, and
+  /// of the row past its last one.
+  fn bounds(
+    &self,
+    ctx: &mut Context<'_>,
+    frame: &Frame,
+    partition: &Partition,
+    idx: usize,
+  ) -> SqliteResult<(usize, usize)> {
+    let start =
+      self.bound(ctx, frame.unit, &frame.start, true, partition, idx)?;
+    let end = self.bound(ctx, frame.unit, &frame.end, false, partition, idx)?;
+    Ok((start, end.max(start)))
+  }
+
+  fn bound(
+    &self,
+    ctx: &mut Context<'_>,
+    unit: FrameUnit,
+    bound: &Bound,
+    is_start: bool,
+    partition: &Partition,
+    idx: usize,
+  ) -> SqliteResult<usize> {
+    let rows = &partition.rows;
+    let count = rows.len();
+    let groups = partition.starts.len() - 1;
+    let group = partition.group[idx];
+    let (offset, preceding) = match bound {
+      Bound::UnboundedPreceding => return Ok(0),
+      Bound::UnboundedFollowing => return Ok(count),
+      Bound::CurrentRow => {
+        return Ok(match (unit, is_start) {
+          (FrameUnit::Rows, true) => idx,
+          (FrameUnit::Rows, false) => idx + 1,
+          (_, true) => partition.starts[group],
+          (_, false) => partition.starts[group + 1],
+        })
+      }
+      Bound::Preceding(offset) => (offset, true),
+      Bound::Following(offset) => (offset, false),
+    };
+    let offset = Affinity::Numeric.apply(offset.eval(ctx, &rows[idx])?);
+    let invalid = || {
+      SqliteError::Custom(format!(
+        "frame {} offset must be a non-negative {}",
+        if is_start { "starting" } else { "ending" },
+        if unit == FrameUnit::Range {
+          "number"
+        } else {
+          "integer"
+        }
+      ))
+    };
+    if unit == FrameUnit::Range {
+      let is_valid = match offset {
+        Value::Integer(offset) => offset >= 0,
+        Value::Real(offset) => offset >= 0.0,
+        _ => false,
+      };
+      if !is_valid {
+        return Err(invalid());
+      }
+      return Ok(
+        self.range_bound(&offset, preceding, is_start, partition, idx),
+      );
+    }
+    let offset = match offset {
+      Value::Integer(offset @ 0..) => {
+        usize::try_from(offset).unwrap_or(usize::MAX)
+      }
+      _ => return Err(invalid()),
+    };
+    let (position, last) = match unit {
+      FrameUnit::Rows => (idx, count),
+      _ => (group, groups),
+    };
+    let position = match preceding {
+      true => position.checked_sub(offset),
+      false => Some(position.saturating_add(offset)),
+    };
+    // A frame bound before the partition leaves out every row.
+    let Some(position) = position else {
+      return Ok(0);
+    };
+    let position = match is_start {
+      true => position.min(last),
+      false => position.saturating_add(1).min(last),
+    };
+    Ok(match unit {
+      FrameUnit::Rows => position,
+      _ => partition.starts[position],
+    })
+  }
+
+  /// A bound at `offset` from the value of the `ORDER BY` clause of the row
+  /// at `idx`. The frame of a row whose value is not a number only holds its
+  /// partition.
+  fn range_bound(
+    &self,
+    offset: &Value,
+    preceding: bool,
+    is_start: bool,
+    partition: &Partition,
+    idx: usize,
+  ) -> usize {
+    let (rows, key) = (&partition.rows, &self.order[0]);
+    let value = &rows[idx][key.column];
+    if !matches!(value, Value::Integer(_) | Value::Real(_)) {
+      let group = partition.group[idx];
+      return partition.starts[group + usize::from(!is_start)];
+    }
+    let operator = match preceding == key.descending {
+      true => BinaryOperator::Add,
+      false => BinaryOperator::Subtract,
+    };
+    let mut target = rows[idx].clone();
+    target[key.column] = binary(operator, value, offset);
+    let keys = slice::from_ref(key);
+    rows.partition_point(|row| match compare_rows(keys, row, &target) {
+      Ordering::Less => true,
+      Ordering::Equal => !is_start,
+      Ordering::Greater => false,
+    })
+  }
+}
+
+/// Whether the `EXCLUDE` clause of a frame leaves the row at `other` out of
+/// the frame of the row at `idx`.
+fn excluded(
+  exclude: FrameExclude,
+  partition: &Partition,
+  idx: usize,
+  other: usize,
+) -> bool {
+  let is_peer = partition.group[idx] == partition.group[other];
+  match exclude {
+    FrameExclude::NoOthers => false,
+    FrameExclude::CurrentRow => other == idx,
+    FrameExclude::Group => is_peer,
+    FrameExclude::Ties => is_peer && other != idx,
+  }
+}
+
+/// The bucket of the row at `idx` among `count` rows split into `buckets`
+/// buckets, numbered from 1. The first buckets take one more row than the
+/// others when the rows cannot be split evenly.
+fn ntile(idx: usize, count: usize, buckets: usize) -> usize {
+  let size = count / buckets;
+  if size == 0 {
+    return idx + 1;
+  }
+  let larger = count % buckets;
+  let first = larger * (size + 1);
+  match idx < first {
+    true => idx / (size + 1) + 1,
+    false => larger + (idx - first) / size + 1,
+  }
+}
+
+impl Operator for Window {
+  fn next(
+    &mut self,
+    ctx: &mut Context<'_>,
+  ) -> SqliteResult<Option<Vec<Value>>> {
+    loop {
+      if let Some(row) = self.output.pop_front() {
+        return Ok(Some(row));
+      }
+      if self.done {
+        return Ok(None);
+      }
+      let partition = self.partition(ctx)?;
+      let mut results = vec![];
+      for call in self.calls.iter() {
+        results.push(self.evaluate(call, ctx, &partition)?.into_iter());
+      }
+      for mut row in partition.rows {
+        row.truncate(self.width);
+        row.extend(results.iter_mut().map(|v| v.next().unwrap_or(Value::Null)));
+        self.output.push_back(row);
+      }
+    }
+  }
+
+  fn reset(&mut self) {
+    self.input.reset();
+    self.pending = None;
+    self.output.clear();
+    self.done = false;
+  }
+
+  fn describe(&self, plan: &mut QueryPlan<'_>) {
+    self.input.describe(plan);
+  }
+
+  fn compile(
+    &self,
+    _builder: &mut Builder,
+    _consume: &mut Consumer<'_>,
+  ) -> Codegen {
+    Err(Unsupported("window functions"))
+  }
+}
@@ -15,6 +15,12 @@ use core::ops::Deref;
 #[derive(Debug, Default)]
 pub struct ApplicationId(u32);
 
+impl From<u32> for ApplicationId {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
+
 impl Deref for ApplicationId {
   type Target = u32;
 
@@ -34,6 +34,11 @@ impl Default for DatabaseFileSizeInPages {
     Self(1)
   }
 }
+impl From<u32> for DatabaseFileSizeInPages {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for DatabaseFileSizeInPages {
   type Target = u32;
 
@@ -17,6 +17,11 @@ use core::ops::Deref;
 /// incremented on each transaction in WAL mode.
 #[derive(Debug, Default)]
 pub struct FileChangeCounter(u32);
+impl From<u32> for FileChangeCounter {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for FileChangeCounter {
   type Target = u32;
 
@@ -20,6 +20,14 @@ impl FreeListPages {
   pub fn total(&self) -> &FreeListPagesTotalPages {
     &self.total
   }
+
+  pub(crate) fn set_first(&mut self, first: u32) {
+    self.first = first.into();
+  }
+
+  pub(crate) fn set_total(&mut self, total: u32) {
+    self.total = total.into();
+  }
 }
 impl_name! {FreeListPages}
 
@@ -39,6 +47,11 @@ impl ParseBytes for FreeListPages {
 /// freelist is empty.
 #[derive(Debug, Default)]
 pub struct FreeListPagesFirstTrunkPage(u32);
+impl From<u32> for FreeListPagesFirstTrunkPage {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for FreeListPagesFirstTrunkPage {
   type Target = u32;
 
@@ -63,6 +76,11 @@ impl ParseBytes for FreeListPagesFirstTrunkPage {
 /// stores the total number of pages on the freelist.
 #[derive(Debug, Default)]
 pub struct FreeListPagesTotalPages(u32);
+impl From<u32> for FreeListPagesTotalPages {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for FreeListPagesTotalPages {
   type Target = u32;
 
@@ -33,6 +33,14 @@ impl IncrementalVacuumSettings {
   pub fn incremental_vacuum_mode(&self) -> &IncrementalVacuumMode {
     &self.incremental_vacuum_mode
   }
+
+  pub(crate) fn set_largest_root_btree_page(&mut self, page_number: u32) {
+    self.largest_root_btree_page = page_number.into();
+  }
+
+  pub(crate) fn set_incremental_vacuum_mode(&mut self, incremental: bool) {
+    self.incremental_vacuum_mode = incremental.into();
+  }
 }
 
 ///  #  Largest root b-tree page (4 Bytes)
@@ -41,6 +49,12 @@ impl IncrementalVacuumSettings {
 #[derive(Debug, Default)]
 pub struct LargestRootBtreePage(u32);
 
+impl From<u32> for LargestRootBtreePage {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
+
 impl Deref for LargestRootBtreePage {
   type Target = u32;
 
@@ -71,6 +85,15 @@ pub enum IncrementalVacuumMode {
   False,
   True,
 }
+impl From<bool> for IncrementalVacuumMode {
+  fn from(value: bool) -> Self {
+    if value {
+      Self::True
+    } else {
+      Self::False
+    }
+  }
+}
 impl From<&IncrementalVacuumMode> for bool {
   fn from(value: &IncrementalVacuumMode) -> Self {
     match value {
@@ -214,6 +214,91 @@ impl SqliteHeader {
   pub fn write_library_version(&self) -> &WriteLibraryVersion {
     &self.write_library_version
   }
+
+  pub(crate) fn set_db_filesize_in_pages(&mut self, pages: u32) {
+    self.db_filesize_in_pages = pages.into();
+  }
+
+  pub(crate) fn freelist_pages_mut(&mut self) -> &mut FreeListPages {
+    &mut self.freelist_pages
+  }
+
+  pub(crate) fn incremental_vacuum_settings_mut(
+    &mut self,
+  ) -> &mut IncrementalVacuumSettings {
+    &mut self.incremental_vacuum_settings
+  }
+
+  pub(crate) fn set_user_version(&mut self, version: u32) {
+    self.user_version = version.into();
+  }
+
+  pub(crate) fn set_application_id(&mut self, id: u32) {
+    self.application_id = id.into();
+  }
+
+  pub(crate) fn set_schema_cookie(&mut self, cookie: u32) {
+    self.schema_cookie = cookie.into();
+  }
+
+  /// Bumps the file change counter and stamps the version-valid-for and
+  /// write library version numbers, as done at the end of every write
+  /// transaction.
+  pub(crate) fn bump_file_change_counter(&mut self) {
+    let counter = self.file_change_counter.wrapping_add(1);
+    self.file_change_counter = counter.into();
+    self.version_valid_for = counter.into();
+    self.write_library_version = WriteLibraryVersion::default();
+  }
+
+  /// Bumps the schema cookie, telling connections that cached the schema to
+  /// read it again.
+  pub(crate) fn bump_schema_cookie(&mut self) {
+    self.schema_cookie = self.schema_cookie.wrapping_add(1).into();
+  }
+
+  /// Serializes the header back into its 100 bytes on-disk format.
+  pub fn to_bytes(&self) -> [u8; Self::LENGTH_BYTES] {
+    let mut bytes = [0u8; Self::LENGTH_BYTES];
+    bytes[0..=15].copy_from_slice(self.magic_header_string.as_bytes());
+    let page_size = match self.page_size {
+      PageSize::L65536 => 1,
+      ref page_size => u32::from(page_size) as u16,
+    };
+    bytes[16..=17].copy_from_slice(&page_size.to_be_bytes());
+    bytes[18] = u8::from(self.file_format_version_numbers.write_version());
+    bytes[19] = u8::from(self.file_format_version_numbers.read_version());
+    bytes[20] = *self.reserved_bytes_per_page;
+    bytes[21] = **self.payload_fractions.maximum();
+    bytes[22] = **self.payload_fractions.minimum();
+    bytes[23] = **self.payload_fractions.leaf();
+    bytes[24..=27].copy_from_slice(&self.file_change_counter.to_be_bytes());
+    bytes[28..=31].copy_from_slice(&self.db_filesize_in_pages.to_be_bytes());
+    bytes[32..=35].copy_from_slice(&self.freelist_pages.first().to_be_bytes());
+    bytes[36..=39].copy_from_slice(&self.freelist_pages.total().to_be_bytes());
+    bytes[40..=43].copy_from_slice(&self.schema_cookie.to_be_bytes());
+    bytes[44..=47]
+      .copy_from_slice(&u32::from(&self.schema_format).to_be_bytes());
+    bytes[48..=51].copy_from_slice(&self.suggested_cache_size.to_be_bytes());
+    bytes[52..=55].copy_from_slice(
+      &self
+        .incremental_vacuum_settings
+        .largest_root_btree_page()
+        .to_be_bytes(),
+    );
+    bytes[56..=59]
+      .copy_from_slice(&u32::from(&self.database_text_encoding).to_be_bytes());
+    bytes[60..=63].copy_from_slice(&self.user_version.to_be_bytes());
+    bytes[64..=67].copy_from_slice(
+      &u32::from(self.incremental_vacuum_settings.incremental_vacuum_mode())
+        .to_be_bytes(),
+    );
+    bytes[68..=71].copy_from_slice(&self.application_id.to_be_bytes());
+    bytes[72..=91].copy_from_slice(self.reserved_for_expansion.as_bytes());
+    bytes[92..=95].copy_from_slice(&self.version_valid_for.to_be_bytes());
+    bytes[96..=99].copy_from_slice(&self.write_library_version.to_be_bytes());
+    bytes
+  }
 }
 
 impl_name! {SqliteHeader}
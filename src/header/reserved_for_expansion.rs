@@ -6,6 +6,12 @@ use core::fmt::Debug;
 #[derive(Default)]
 pub struct ReservedForExpansion([u8; 20]);
 
+impl ReservedForExpansion {
+  pub fn as_bytes(&self) -> &[u8; 20] {
+    &self.0
+  }
+}
+
 impl Debug for ReservedForExpansion {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     f.debug_tuple(Self::NAME).finish()
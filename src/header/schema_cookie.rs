@@ -15,6 +15,11 @@ use core::ops::Deref;
 /// error.
 #[derive(Debug, Default)]
 pub struct SchemaCookie(u32);
+impl From<u32> for SchemaCookie {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for SchemaCookie {
   type Target = u32;
 
@@ -9,6 +9,13 @@ use core::ops::Deref;
 /// Sqlite.
 #[derive(Debug, Default)]
 pub struct UserVersion(u32);
+
+impl From<u32> for UserVersion {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
+
 impl Deref for UserVersion {
   type Target = u32;
 
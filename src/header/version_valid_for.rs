@@ -30,6 +30,11 @@ impl Default for VersionValidFor {
     Self(*VERSION_NUMBER.get().unwrap_or(&0))
   }
 }
+impl From<u32> for VersionValidFor {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for VersionValidFor {
   type Target = u32;
 
@@ -28,6 +28,11 @@ impl Default for WriteLibraryVersion {
     Self(*VERSION_NUMBER.get().unwrap_or(&0))
   }
 }
+impl From<u32> for WriteLibraryVersion {
+  fn from(value: u32) -> Self {
+    Self(value)
+  }
+}
 impl Deref for WriteLibraryVersion {
   type Target = u32;
 
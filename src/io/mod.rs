@@ -2,11 +2,11 @@ use crate::result::{SqliteError, SqliteResult};
 use crate::traits::SqliteRawIo;
 use crate::{error, trace};
 use std::fmt::{Debug, Display};
-use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::Seek;
 use std::io::SeekFrom;
-use std::io::{Cursor, Read};
-use std::path::PathBuf;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 // #[cfg(test)]
@@ -14,6 +14,9 @@ use std::str::FromStr;
 
 pub struct SqliteIo {
   mode: SqliteIoMode,
+  /// The absolute path of the database file, `None` in memory.
+  path: Option<PathBuf>,
+  is_read_only: bool,
   raw_io: Box<dyn SqliteRawIo>,
 }
 
@@ -21,6 +24,8 @@ impl Debug for SqliteIo {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     f.debug_struct("SqliteIo")
       .field("mode", &self.mode)
+      .field("path", &self.path)
+      .field("is_read_only", &self.is_read_only)
       .finish()
   }
 }
@@ -54,30 +59,84 @@ impl SqliteIo {
       SqliteIoMode::InMemory => {
         let cursor: Box<Cursor<Vec<u8>>> = Box::new(Cursor::new(vec![]));
         let raw_io = cursor as Box<dyn SqliteRawIo>;
-        Ok(Self { mode, raw_io })
+        Ok(Self {
+          mode,
+          path: None,
+          is_read_only: false,
+          raw_io,
+        })
       }
 
       SqliteIoMode::File => {
         let uri = conn_str.parse::<SqliteUri>()?;
-        let file = Box::new(File::open(uri.path())?);
+        let is_read_only = *uri.mode() == SqliteUriFileMode::ReadOnly;
+        let file = Box::new(
+          OpenOptions::new()
+            .read(true)
+            .write(!is_read_only)
+            .open(uri.path())?,
+        );
         let raw_io: Box<dyn SqliteRawIo> = file as Box<dyn SqliteRawIo>;
-        Ok(Self { mode, raw_io })
+        Ok(Self {
+          mode,
+          path: Some(std::fs::canonicalize(uri.path())?),
+          is_read_only,
+          raw_io,
+        })
       }
     }
   }
 
   pub fn is_empty(&mut self) -> SqliteResult<bool> {
-    if self.raw_io.read(&mut [0u8; 1])? == 0 {
-      Ok(true)
-    } else {
-      Ok(false)
-    }
+    Ok(self.len()? == 0)
+  }
+
+  /// Length in bytes of the underlying storage.
+  pub fn len(&mut self) -> SqliteResult<u64> {
+    let current = self.raw_io.stream_position()?;
+    let len = self.raw_io.seek(SeekFrom::End(0))?;
+    self.raw_io.seek(SeekFrom::Start(current))?;
+    Ok(len)
   }
 
   pub fn read(&mut self, buf: &mut [u8]) -> SqliteResult<usize> {
     Ok(self.raw_io.read(buf)?)
   }
 
+  /// Fills `buf` from the current position. Bytes beyond the end of the
+  /// storage are left as zeroes.
+  pub fn read_exact_or_zeroed(&mut self, buf: &mut [u8]) -> SqliteResult<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+      let bytes_read = self.raw_io.read(&mut buf[filled..])?;
+      if bytes_read == 0 {
+        buf[filled..].fill(0);
+        break;
+      }
+      filled += bytes_read;
+    }
+    Ok(())
+  }
+
+  pub fn write_all(&mut self, buf: &[u8]) -> SqliteResult<()> {
+    if self.is_read_only {
+      return Err(SqliteError::ReadOnly);
+    }
+    Ok(self.raw_io.write_all(buf)?)
+  }
+
+  pub fn flush(&mut self) -> SqliteResult<()> {
+    Ok(self.raw_io.flush()?)
+  }
+
+  /// Truncates or extends the storage to `len` bytes.
+  pub fn set_len(&mut self, len: u64) -> SqliteResult<()> {
+    if self.is_read_only {
+      return Err(SqliteError::ReadOnly);
+    }
+    Ok(self.raw_io.set_len(len)?)
+  }
+
   pub fn seek(&mut self, pos: u64) -> SqliteResult<u64> {
     Ok(self.raw_io.seek(SeekFrom::Start(pos))?)
   }
@@ -89,13 +148,22 @@ impl SqliteIo {
     Ok(self.raw_io.stream_position()?)
   }
 
-  pub fn close() -> SqliteResult<()> {
-    todo!("Close not yet implemented");
+  pub fn close(mut self) -> SqliteResult<()> {
+    self.flush()
   }
 
   pub fn mode(&self) -> &SqliteIoMode {
     &self.mode
   }
+
+  /// The absolute path of the database file, `None` in memory.
+  pub fn path(&self) -> Option<&Path> {
+    self.path.as_deref()
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    self.is_read_only
+  }
 }
 
 #[derive(Debug)]
@@ -106,9 +174,17 @@ pub struct SqliteUri {
 }
 
 impl SqliteUri {
+  pub fn uri(&self) -> &str {
+    &self.uri
+  }
+
   pub fn path(&self) -> &PathBuf {
     &self.path
   }
+
+  pub fn mode(&self) -> &SqliteUriFileMode {
+    &self.mode
+  }
 }
 impl FromStr for SqliteUri {
   type Err = SqliteError;
@@ -198,9 +274,8 @@ impl FromStr for SqliteUriFileMode {
 
 fn create_file(path: &PathBuf) -> SqliteResult<()> {
   let maybe_parent_dir = path.parent();
-  maybe_parent_dir
-    .map(std::fs::create_dir_all)
-    .transpose()?;
-  File::create(path)?;
+  maybe_parent_dir.map(std::fs::create_dir_all).transpose()?;
+  // Never truncate: `mode=rwc` must keep an existing database intact.
+  OpenOptions::new().create(true).append(true).open(path)?;
   Ok(())
 }
@@ -3,12 +3,17 @@
 //! # SQLite arquitecture
 //! *Reference:* https://www.sqlite.org/arch.html
 
+use crate::executor::{
+  DeferredViolation, Functions, Rows, Statement, StatementCache, UserFunction,
+};
 use crate::io::SqliteIo;
 use crate::pager::SqlitePager;
 use crate::result::SqliteResult;
-use crate::runtime::SqliteRuntime;
+use crate::runtime::{SqliteRuntime, Value};
+use core::cmp::Ordering;
 use std::sync::OnceLock;
 
+pub mod executor;
 pub mod header;
 pub mod io;
 #[cfg(feature = "log")]
@@ -18,6 +23,7 @@ pub(crate) mod log_macros;
 pub mod pager;
 pub mod result;
 pub mod runtime;
+pub mod sql;
 pub mod traits;
 #[macro_use]
 pub mod macros;
@@ -28,6 +34,30 @@ mod tests;
 #[derive(Debug)]
 pub struct SqliteConnection {
   runtime: SqliteRuntime,
+  sort_memory_limit: usize,
+  /// Rows changed by the last `INSERT`, `UPDATE` or `DELETE` statement.
+  changes: u64,
+  /// Rows changed since the connection was opened.
+  total_changes: u64,
+  /// Rowid of the last row inserted in a rowid table.
+  last_insert_rowid: i64,
+  statements: StatementCache,
+  /// Functions registered by the application.
+  functions: Functions,
+  /// The `cache_size` pragma. Pages are cached by the pager regardless; it
+  /// is kept for the tools reading it back.
+  cache_size: i64,
+  /// The `foreign_keys` pragma: whether foreign keys are enforced.
+  foreign_keys: bool,
+  /// The `recursive_triggers` pragma: whether triggers fire again for the
+  /// changes of their own programs.
+  recursive_triggers: bool,
+  /// Whether `BEGIN` opened a transaction, which `COMMIT` or `ROLLBACK`
+  /// ends.
+  in_transaction: bool,
+  /// The rows of deferred foreign keys found missing their parent in the
+  /// transaction, for `COMMIT` to check again.
+  deferred_violations: Vec<DeferredViolation>,
 }
 static VERSION_NUMBER: OnceLock<u32> = OnceLock::new();
 
@@ -54,7 +84,208 @@ impl SqliteConnection {
     let runtime = SqliteRuntime::start(pager)?;
     trace!("SqliteRuntime started: [{runtime:?}].");
 
-    Ok(Self { runtime })
+    Ok(Self {
+      runtime,
+      sort_memory_limit: executor::DEFAULT_SORT_MEMORY_LIMIT,
+      changes: 0,
+      total_changes: 0,
+      last_insert_rowid: 0,
+      statements: StatementCache::new(
+        executor::DEFAULT_STATEMENT_CACHE_CAPACITY,
+      ),
+      functions: Functions::default(),
+      cache_size: executor::DEFAULT_CACHE_SIZE,
+      foreign_keys: false,
+      recursive_triggers: false,
+      in_transaction: false,
+      deferred_violations: vec![],
+    })
+  }
+
+  /// Runs the `SELECT` or `PRAGMA` statement `sql`. Rows are read from the
+  /// database as the returned iterator advances.
+  pub fn query(&mut self, sql: &str) -> SqliteResult<Rows<'_>> {
+    executor::query(self, sql)
+  }
+
+  /// Runs the `INSERT`, `UPDATE` or `DELETE` statement `sql`, and returns
+  /// the number of rows it changed, or the `CREATE`, `DROP`, `ALTER TABLE`
+  /// or `PRAGMA` statement `sql`. The changes are committed once the statement
+  /// completes, and undone if it fails.
+  pub fn execute(&mut self, sql: &str) -> SqliteResult<u64> {
+    executor::execute(self, sql)
+  }
+
+  /// Parses the statement `sql`, to run it any number of times with values
+  /// bound to its parameters. The statements prepared most recently are
+  /// cached, and so are those of [`query`](Self::query) and
+  /// [`execute`](Self::execute).
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/prepare.html
+  pub fn prepare(&mut self, sql: &str) -> SqliteResult<Statement> {
+    let prepared = self.statements.prepare(sql, "prepared")?;
+    Ok(Statement::new(prepared))
+  }
+
+  /// Number of parsed statements the connection keeps.
+  pub fn statement_cache_capacity(&self) -> usize {
+    self.statements.capacity()
+  }
+
+  /// Sets the number of parsed statements the connection keeps, dropping
+  /// those used least recently when there are too many. Zero disables the
+  /// cache.
+  pub fn set_statement_cache_capacity(&mut self, capacity: usize) {
+    self.statements.set_capacity(capacity);
+  }
+
+  /// Rows inserted, updated or deleted by the last statement that changed
+  /// the database. Rows deleted to resolve `REPLACE` conflicts are not
+  /// counted.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/changes.html
+  pub fn changes(&self) -> u64 {
+    self.changes
+  }
+
+  /// Rows inserted, updated or deleted since the connection was opened.
+  pub fn total_changes(&self) -> u64 {
+    self.total_changes
+  }
+
+  /// Rowid of the most recent successful insert into a rowid table, or 0 if
+  /// there was none.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/last_insert_rowid.html
+  pub fn last_insert_rowid(&self) -> i64 {
+    self.last_insert_rowid
+  }
+
+  /// Bytes of rows a sort holds in memory before it spills them to
+  /// temporary files.
+  pub fn sort_memory_limit(&self) -> usize {
+    self.sort_memory_limit
+  }
+
+  pub fn set_sort_memory_limit(&mut self, bytes: usize) {
+    self.sort_memory_limit = bytes;
+  }
+
+  /// Registers `function` as the SQL scalar function `name`, taking
+  /// `arity` arguments, or any number of them for `None`. It replaces the
+  /// function registered with the same name and arity, and the built-in
+  /// function of that name. A function that is not `deterministic` may not
+  /// be called by index expressions or the `WHERE` clause of partial
+  /// indexes. An error it returns fails the statement calling it.
+  ///
+  /// ```
+  /// use sqlite_rs::{runtime::Value, SqliteConnection};
+  ///
+  /// let mut conn = SqliteConnection::open(":memory:").unwrap();
+  /// conn
+  ///   .create_scalar_function("twice", Some(1), true, |args| {
+  ///     Ok(match &args[0] {
+  ///       Value::Integer(int) => Value::Integer(int * 2),
+  ///       _ => Value::Null,
+  ///     })
+  ///   })
+  ///   .unwrap();
+  /// let row = conn.query("SELECT twice(21)").unwrap().next().unwrap();
+  /// assert_eq!(row.unwrap().get(0), Some(&Value::Integer(42)));
+  /// ```
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/create_function.html
+  pub fn create_scalar_function<F>(
+    &mut self,
+    name: &str,
+    arity: Option<usize>,
+    deterministic: bool,
+    function: F,
+  ) -> SqliteResult<()>
+  where
+    F: Fn(&[Value]) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    let function = UserFunction::scalar(name, arity, deterministic, function);
+    self.functions.register(function)
+  }
+
+  /// Registers the SQL aggregate function `name`, taking `arity` arguments,
+  /// or any number of them for `None`. The rows of each group are folded
+  /// by `step` into a state starting as `S::default()`, which `finalize`
+  /// turns into the result.
+  pub fn create_aggregate_function<S, F, G>(
+    &mut self,
+    name: &str,
+    arity: Option<usize>,
+    step: F,
+    finalize: G,
+  ) -> SqliteResult<()>
+  where
+    S: Default + Send + 'static,
+    F: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    G: Fn(S) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    let function = UserFunction::aggregate(name, arity, step, finalize);
+    self.functions.register(function)
+  }
+
+  /// Registers the SQL aggregate window function `name`, taking `arity`
+  /// arguments, or any number of them for `None`. Rows entering the frame
+  /// are added by `step` to a state starting as `S::default()`, rows
+  /// leaving it are taken out by `inverse`, and `value` returns the result
+  /// for the rows of the state. Without an `OVER` clause, it is an
+  /// aggregate function.
+  ///
+  /// *Reference:* https://www.sqlite.org/windowfunctions.html#udfwinfunc
+  pub fn create_window_function<S, F, I, V>(
+    &mut self,
+    name: &str,
+    arity: Option<usize>,
+    step: F,
+    inverse: I,
+    value: V,
+  ) -> SqliteResult<()>
+  where
+    S: Default + Send + 'static,
+    F: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    I: Fn(&mut S, &[Value]) -> SqliteResult<()> + Send + Sync + 'static,
+    V: Fn(&S) -> SqliteResult<Value> + Send + Sync + 'static,
+  {
+    let function = UserFunction::window(name, arity, step, inverse, value);
+    self.functions.register(function)
+  }
+
+  /// Registers the collating sequence `name`, comparing text with
+  /// `compare`, in place of the one registered with the same name. It is
+  /// used wherever it is named: `COLLATE` clauses of queries, column
+  /// definitions and indexes, whose entries it orders. Tables and indexes
+  /// naming a collating sequence that is not registered fail the statements
+  /// using them with a `no such collation sequence` error.
+  ///
+  /// ```
+  /// use sqlite_rs::{runtime::Value, SqliteConnection};
+  ///
+  /// let mut conn = SqliteConnection::open(":memory:").unwrap();
+  /// conn
+  ///   .create_collation("reverse", |left, right| right.cmp(left))
+  ///   .unwrap();
+  /// let mut rows = conn
+  ///   .query("SELECT 'a' UNION SELECT 'b' ORDER BY 1 COLLATE reverse")
+  ///   .unwrap();
+  /// let row = rows.next().unwrap().unwrap();
+  /// assert_eq!(row.get(0), Some(&Value::Text("b".into())));
+  /// ```
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/create_collation.html
+  pub fn create_collation<F>(
+    &mut self,
+    name: &str,
+    compare: F,
+  ) -> SqliteResult<()>
+  where
+    F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+  {
+    self.runtime.register_collation(name, Box::new(compare))
   }
 
   pub fn runtime(&self) -> &SqliteRuntime {
@@ -14,7 +14,7 @@ fn main() -> ExitCode {
 fn smain() -> SqliteCliResult<()> {
   let app = SQliteCli::parse()?;
   if app.cli().is_help() {
-    app.usage();
+    SQliteCli::usage();
     Ok(())
   } else {
     app.run()?;
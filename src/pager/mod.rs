@@ -1,11 +1,12 @@
 pub mod page;
 
+use std::collections::BTreeMap;
 use std::num::NonZeroU32;
 
 use crate::{
   header::{
     FileFormatReadVersion, FileFormatWriteVersion, MagicHeaderString, PageSize,
-    ReservedBytesPerPage,
+    ReservedBytesPerPage, SqliteHeader,
   },
   io::SqliteIo,
   result::{SqliteError, SqliteResult},
@@ -14,13 +15,29 @@ use crate::{
 
 use self::page::Page;
 
+/// The pager reads pages from the database file and keeps the pages modified
+/// by the current write transaction in memory until they are committed.
 #[derive(Debug)]
 pub struct SqlitePager {
   io: SqliteIo,
   page_size: PageSize,
   reserved_bytes_per_page: ReservedBytesPerPage,
-  // cur_page_number: usize,
-  // btree_page_header: BtreePageHeader,
+  /// Number of pages in the database, uncommitted growth included.
+  page_count: u32,
+  /// Number of pages in the database file as of the last commit.
+  committed_page_count: u32,
+  /// Pages written by the current transaction.
+  dirty_pages: BTreeMap<u32, Page>,
+  /// What the current statement changed, to undo it alone.
+  statement: Option<Journal>,
+}
+
+/// The pages staged before a statement changed them, `None` for those it
+/// staged first, and the number of pages then.
+#[derive(Debug)]
+struct Journal {
+  page_count: u32,
+  pages: BTreeMap<u32, Option<Page>>,
 }
 
 impl SqlitePager {
@@ -35,126 +52,174 @@ impl SqlitePager {
 
     let bytes_read = io.read(&mut buf)?;
     trace!("[{bytes_read}] Bytes read from [{}]", io.mode());
-    let pager = if bytes_read > 0 {
-      Self {
-        io,
-        page_size: PageSize::parse_bytes(&buf[16..=17])?,
-        reserved_bytes_per_page: ReservedBytesPerPage::parse_bytes(&[buf[20]])?,
-      }
+    let (page_size, reserved_bytes_per_page) = if bytes_read > 0 {
+      (
+        PageSize::parse_bytes(&buf[16..=17])?,
+        ReservedBytesPerPage::parse_bytes(&[buf[20]])?,
+      )
     } else {
-      Self {
-        io,
-        page_size: PageSize::default(),
-        reserved_bytes_per_page: ReservedBytesPerPage::default(),
-      }
+      (PageSize::default(), ReservedBytesPerPage::default())
     };
-    Ok(pager)
+    let page_count = (io.len()? / u64::from(u32::from(&page_size))) as u32;
+    Ok(Self {
+      io,
+      page_size,
+      reserved_bytes_per_page,
+      page_count,
+      committed_page_count: page_count,
+      dirty_pages: BTreeMap::new(),
+      statement: None,
+    })
   }
+
   pub fn first(&mut self) -> SqliteResult<Page> {
     self.read(1)
   }
 
   pub fn read(&mut self, page_number: u32) -> SqliteResult<Page> {
-    if self.io.is_empty()? {
-      return Err(SqliteError::EmptyDb);
-    }
     let page_number = NonZeroU32::new(page_number)
       .ok_or(SqliteError::Custom("page number can't be zero `0`.".into()))?
       .get();
+    if let Some(page) = self.dirty_pages.get(&page_number) {
+      return Ok(page.clone());
+    }
+    if self.io.is_empty()? {
+      return Err(SqliteError::EmptyDb);
+    }
+    if page_number > self.page_count {
+      return Err(SqliteError::Corrupt(format!(
+        "Page [{page_number}] is beyond the end of the database"
+      )));
+    }
     let page_size = self.page_size().clone();
-    let offset_from_start = (page_number - 1) * u32::from(&page_size);
-    // dbg!(&offset_from_start);
-    self.io.seek(offset_from_start.into())?;
-
-    match page_size {
-      PageSize::L512 => {
-        const BUF_SIZE: usize = 512;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        // TODO: Write tests
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L1024 => {
-        const BUF_SIZE: usize = 1024;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L2048 => {
-        const BUF_SIZE: usize = 2048;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L4096 => {
-        const BUF_SIZE: usize = 4096;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L8192 => {
-        const BUF_SIZE: usize = 8192;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L16384 => {
-        const BUF_SIZE: usize = 16384;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L32768 => {
-        const BUF_SIZE: usize = 32768;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-        let mut final_buffer = [0u8; Page::MAX_LENGTH];
-        for (idx, byte) in buf.iter().enumerate() {
-          final_buffer[idx] = *byte;
-        }
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
-      PageSize::L65536 => {
-        const BUF_SIZE: usize = 65536;
-        let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        self.io.read(&mut buf)?;
-        let mut final_buffer = [0u8; Page::MAX_LENGTH];
-        for (idx, byte) in buf.iter().enumerate() {
-          final_buffer[idx] = *byte;
-        }
-        Ok(Page {
-          length: page_size,
-          raw_data: buf.to_vec(),
-        })
-      }
+    let offset_from_start =
+      u64::from(page_number - 1) * u64::from(u32::from(&page_size));
+    self.io.seek(offset_from_start)?;
+
+    let mut raw_data = vec![0u8; page_size.as_usize()];
+    self.io.read_exact_or_zeroed(&mut raw_data)?;
+    Ok(Page {
+      length: page_size,
+      raw_data,
+    })
+  }
+
+  /// Stages `page` to be written at `page_number` on the next commit.
+  pub fn write(&mut self, page_number: u32, page: Page) -> SqliteResult<()> {
+    if self.io.is_read_only() {
+      return Err(SqliteError::ReadOnly);
+    }
+    if page_number == 0 || page_number > self.page_count {
+      return Err(SqliteError::Custom(format!(
+        "Page [{page_number}] is out of bounds"
+      )));
+    }
+    self.journal(page_number);
+    self.dirty_pages.insert(page_number, page);
+    Ok(())
+  }
+
+  /// Extends the database by one zeroed page and returns its number.
+  pub fn append(&mut self) -> SqliteResult<u32> {
+    if self.io.is_read_only() {
+      return Err(SqliteError::ReadOnly);
+    }
+    self.page_count = self
+      .page_count
+      .checked_add(1)
+      .ok_or(SqliteError::Custom("Database is full".into()))?;
+    let page = Page::new(self.page_size.clone());
+    self.journal(self.page_count);
+    self.dirty_pages.insert(self.page_count, page);
+    Ok(self.page_count)
+  }
+
+  /// Shrinks the database to `page_count` pages, dropping any staged write
+  /// past the new end.
+  pub fn truncate(&mut self, page_count: u32) {
+    let dropped = self.dirty_pages.range(page_count + 1..);
+    let dropped = dropped.map(|(number, _)| *number).collect::<Vec<_>>();
+    dropped.into_iter().for_each(|number| self.journal(number));
+    self.dirty_pages.retain(|number, _| *number <= page_count);
+    self.page_count = page_count;
+  }
+
+  /// Writes every staged page and the `header` to the database file.
+  pub fn commit(&mut self, header: &SqliteHeader) -> SqliteResult<()> {
+    if !self.is_dirty() {
+      return Ok(());
+    }
+    let mut first = self.first()?;
+    first.raw_data[..SqliteHeader::LENGTH_BYTES]
+      .copy_from_slice(&header.to_bytes());
+    self.dirty_pages.insert(1, first);
+
+    let page_size = u64::from(u32::from(&self.page_size));
+    let dirty_pages = std::mem::take(&mut self.dirty_pages);
+    for (page_number, page) in dirty_pages.iter() {
+      self.io.seek(u64::from(page_number - 1) * page_size)?;
+      self.io.write_all(page.raw_data())?;
     }
+    if self.page_count < self.committed_page_count {
+      self.io.set_len(u64::from(self.page_count) * page_size)?;
+    }
+    self.io.flush()?;
+    self.committed_page_count = self.page_count;
+    self.statement = None;
+    trace!("[{}] pages committed.", dirty_pages.len());
+    Ok(())
+  }
+
+  /// Discards every staged page.
+  pub fn rollback(&mut self) {
+    self.dirty_pages.clear();
+    self.page_count = self.committed_page_count;
+    self.statement = None;
+  }
+
+  /// Starts recording the pages the next statement stages, for
+  /// [`Self::rollback_statement`] to undo it without the statements before.
+  pub fn begin_statement(&mut self) {
+    self.statement = Some(Journal {
+      page_count: self.page_count,
+      pages: BTreeMap::new(),
+    });
+  }
+
+  /// Restores the pages staged as they were when the statement began.
+  pub fn rollback_statement(&mut self) {
+    let Some(journal) = self.statement.take() else {
+      return;
+    };
+    for (page_number, page) in journal.pages {
+      match page {
+        Some(page) => self.dirty_pages.insert(page_number, page),
+        None => self.dirty_pages.remove(&page_number),
+      };
+    }
+    self.page_count = journal.page_count;
+  }
+
+  /// Records the page `page_number` as staged before the statement changes
+  /// it, the first time it does.
+  fn journal(&mut self, page_number: u32) {
+    if let Some(journal) = &mut self.statement {
+      let staged = &self.dirty_pages;
+      journal
+        .pages
+        .entry(page_number)
+        .or_insert_with(|| staged.get(&page_number).cloned());
+    }
+  }
+
+  /// Whether pages were staged, or the file truncated, since the last
+  /// commit.
+  pub fn is_dirty(&self) -> bool {
+    !self.dirty_pages.is_empty() || self.page_count != self.committed_page_count
+  }
+
+  pub fn page_count(&self) -> u32 {
+    self.page_count
   }
 
   pub fn page_size(&self) -> &PageSize {
@@ -165,6 +230,12 @@ impl SqlitePager {
     &self.reserved_bytes_per_page
   }
 
+  /// The "usable size" of a database page: the page size less the reserved
+  /// space at the end of each page.
+  pub fn usable_size(&self) -> usize {
+    self.page_size.as_usize() - usize::from(*self.reserved_bytes_per_page)
+  }
+
   pub fn io(&self) -> &SqliteIo {
     &self.io
   }
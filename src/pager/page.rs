@@ -50,7 +50,7 @@
 
 use crate::header::PageSize;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Page {
   pub length: PageSize,
   pub raw_data: Vec<u8>,
@@ -59,6 +59,12 @@ pub struct Page {
 impl Page {
   pub const MAX_LENGTH: usize = PageSize::MAX.as_usize();
 
+  /// A zero-filled page.
+  pub fn new(length: PageSize) -> Self {
+    let raw_data = vec![0; length.as_usize()];
+    Self { length, raw_data }
+  }
+
   pub fn length(&self) -> &PageSize {
     &self.length
   }
@@ -66,4 +72,8 @@ impl Page {
   pub fn raw_data(&self) -> &Vec<u8> {
     &self.raw_data
   }
+
+  pub fn raw_data_mut(&mut self) -> &mut Vec<u8> {
+    &mut self.raw_data
+  }
 }
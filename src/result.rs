@@ -1,3 +1,4 @@
+use crate::sql::SyntaxError;
 use core::array::TryFromSliceError;
 use core::fmt::Display;
 use std::error::Error as StdError;
@@ -9,14 +10,47 @@ pub type SqliteResult<T> = Result<T, SqliteError>;
 pub enum SqliteError {
   EmptyDb,
   InvalidFileUriMode,
+  /// Attempt to write to a database opened with `mode=ro`.
+  ReadOnly,
+  /// The database file is malformed.
+  Corrupt(String),
+  /// The SQL text is malformed.
+  Syntax(SyntaxError),
   HeaderValidationError(String),
   TryFromSliceError(TryFromSliceError),
   StdioError(StdioError),
   Custom(String),
+  /// A constraint of a table failed, with the message telling which.
+  Constraint {
+    kind: ConstraintKind,
+    message: String,
+  },
   ParsingField(FieldParsingError),
   InvalidPayloadSize(InvalidPayloadSizeError),
 }
 
+/// The kinds of constraint failures, as the extended result codes of
+/// `SQLITE_CONSTRAINT` tell them apart.
+///
+/// *Reference:* https://www.sqlite.org/rescode.html#constraint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+  /// `SQLITE_CONSTRAINT_NOTNULL`
+  NotNull,
+  /// `SQLITE_CONSTRAINT_UNIQUE`
+  Unique,
+  /// `SQLITE_CONSTRAINT_CHECK`
+  Check,
+  /// `SQLITE_CONSTRAINT_FOREIGNKEY`
+  ForeignKey,
+  /// `SQLITE_CONSTRAINT_PRIMARYKEY`
+  PrimaryKey,
+  /// `SQLITE_CONSTRAINT_DATATYPE`, from `STRICT` tables.
+  Datatype,
+  /// `SQLITE_CONSTRAINT_TRIGGER`, from `RAISE()` in a trigger program.
+  Trigger,
+}
+
 #[derive(Debug)]
 pub struct FieldParsingError {
   pub error: String,
@@ -0,0 +1,387 @@
+//! # Balancing
+//!
+//!  After a cell is inserted or removed, the page it lives on may hold more
+//! content than fits on it or become mostly empty. Balancing redistributes
+//! the cells of the page and up to two of its siblings among as many pages as
+//! are needed, updating the divider cells in the parent, which may in turn
+//! need balancing. An overfull root is handled by moving its content to a new
+//! child page, which increases the height of the tree by one, and a root left
+//! with a single child absorbs it, which decreases the height by one.
+//!
+//! *Reference:* https://www.sqlite.org/src/file?name=src/btree.c
+
+use super::cell::{allocated_size, build_cell, CellInfo};
+use super::page::{read_u32, BtreeNode, BtreePageType};
+use super::SqliteBtree;
+use crate::result::{SqliteError, SqliteResult};
+
+/// Number of siblings taking part in a balance: the page itself and one
+/// neighbour on each side.
+const BALANCE_SIBLINGS: usize = 3;
+
+impl SqliteBtree<'_> {
+  /// Writes `node` after cells were inserted on it or removed from it,
+  /// balancing it when it is overfull or underfull, then walks up its
+  /// ancestors in `path` balancing the ones left overfull or underfull in
+  /// turn. `is_append` tells that a cell was added past the last one of the
+  /// node.
+  pub(crate) fn balance(
+    &mut self,
+    path: Vec<(BtreeNode, usize)>,
+    node: BtreeNode,
+    is_append: bool,
+  ) -> SqliteResult<()> {
+    self.balance_from(path, node, is_append, true)
+  }
+
+  /// Walks up from `node`, an already written ancestor of a modified page,
+  /// balancing it and its own ancestors when they are overfull.
+  pub(crate) fn balance_ancestor(
+    &mut self,
+    path: Vec<(BtreeNode, usize)>,
+    node: BtreeNode,
+  ) -> SqliteResult<()> {
+    self.balance_from(path, node, false, false)
+  }
+
+  fn balance_from(
+    &mut self,
+    mut path: Vec<(BtreeNode, usize)>,
+    mut node: BtreeNode,
+    is_append: bool,
+    is_modified: bool,
+  ) -> SqliteResult<()> {
+    let usable_size = self.usable_size();
+    let mut is_append = is_append;
+    // Whether `node` was modified and needs writing. Ancestors that were not
+    // are only visited to catch pages left overfull.
+    let mut is_modified = is_modified;
+    loop {
+      let is_overfull = !node.fits(usable_size);
+      match path.pop() {
+        None if is_overfull => {
+          let (root, child) = self.balance_deeper(node)?;
+          path.push((root, 0));
+          node = child;
+          is_modified = true;
+          continue;
+        }
+        None if is_modified && node.child_count() == 1 => {
+          return self.balance_shallower(node);
+        }
+        None if is_modified => return self.write_node(&node),
+        None => return Ok(()),
+        Some((mut parent, idx))
+          if is_overfull || (is_modified && node.is_underfull(usable_size)) =>
+        {
+          let is_quick = is_append
+            && is_overfull
+            && node.page_type == BtreePageType::LeafTable
+            && idx == parent.cells.len();
+          if is_quick {
+            self.balance_quick(&mut parent, node)?;
+          } else {
+            self.balance_nonroot(&mut parent, idx, node)?;
+          }
+          node = parent;
+          is_modified = true;
+        }
+        Some((parent, _)) => {
+          if is_modified {
+            self.write_node(&node)?;
+          }
+          node = parent;
+          is_modified = false;
+        }
+      }
+      is_append = false;
+    }
+  }
+
+  /// Moves the content of the overfull root into a new child page, leaving
+  /// the root as an interior page whose only child is the new page.
+  fn balance_deeper(
+    &mut self,
+    root: BtreeNode,
+  ) -> SqliteResult<(BtreeNode, BtreeNode)> {
+    let child_number = self.allocate_page()?;
+    let mut child = root.clone();
+    child.page_number = child_number;
+    let mut new_root =
+      BtreeNode::new(root.page_number, root.page_type.interior());
+    new_root.right_pointer = Some(child_number);
+    self.write_node(&new_root)?;
+    debug!(
+      "Root page [{}] deepened with child [{child_number}].",
+      root.page_number
+    );
+    Ok((new_root, child))
+  }
+
+  /// Copies the content of the only child of an interior root into the root,
+  /// reducing the height of the tree by one. The child is kept when its cells
+  /// do not fit on the root, which can happen on page 1.
+  fn balance_shallower(&mut self, root: BtreeNode) -> SqliteResult<()> {
+    let child_number = root.child(0)?;
+    let mut child = self.read_node(child_number)?;
+    child.page_number = root.page_number;
+    if !child.fits(self.usable_size()) {
+      return self.write_node(&root);
+    }
+    self.write_node(&child)?;
+    self.free_page(child_number)?;
+    debug!(
+      "Root page [{}] absorbed its child [{child_number}].",
+      root.page_number
+    );
+    Ok(())
+  }
+
+  /// Fast path for appending to the right-most leaf of a table b-tree: the
+  /// new cell goes alone to a new right-most page, leaving the existing page
+  /// full.
+  fn balance_quick(
+    &mut self,
+    parent: &mut BtreeNode,
+    mut node: BtreeNode,
+  ) -> SqliteResult<()> {
+    let usable_size = self.usable_size();
+    let last = node
+      .cells
+      .pop()
+      .ok_or(SqliteError::Custom("Nothing to balance".into()))?;
+    let divider_key = self.last_rowid(&node)?;
+    let new_number = self.allocate_page()?;
+    let mut new_leaf = BtreeNode::new(new_number, BtreePageType::LeafTable);
+    new_leaf.cells.push(last);
+    self.write_node(&node)?;
+    self.write_node(&new_leaf)?;
+
+    let (divider, _) = build_cell(
+      BtreePageType::InteriorTable,
+      Some(node.page_number),
+      Some(divider_key),
+      &[],
+      usable_size,
+    );
+    parent.cells.push(divider);
+    parent.right_pointer = Some(new_number);
+    trace!("Quick balance of page [{}].", node.page_number);
+    Ok(())
+  }
+
+  /// Redistributes the cells of the child at `child_idx` of `parent`, which is
+  /// replaced by `node`, and of up to two of its siblings. New divider cells
+  /// are set on `parent`, which is left for the caller to write.
+  pub(crate) fn balance_nonroot(
+    &mut self,
+    parent: &mut BtreeNode,
+    child_idx: usize,
+    node: BtreeNode,
+  ) -> SqliteResult<()> {
+    let usable_size = self.usable_size();
+    let child_count = parent.child_count();
+    let sibling_count = child_count.min(BALANCE_SIBLINGS);
+    let first = child_idx.saturating_sub(1).min(child_count - sibling_count);
+
+    let mut node = Some(node);
+    let mut siblings = Vec::with_capacity(sibling_count);
+    for idx in first..first + sibling_count {
+      let sibling = match (idx == child_idx, node.take()) {
+        (true, Some(node)) => node,
+        (_, maybe_node) => {
+          node = maybe_node;
+          self.read_node(parent.child(idx)?)?
+        }
+      };
+      siblings.push(sibling);
+    }
+    let page_type = siblings[0].page_type;
+    if siblings
+      .iter()
+      .any(|sibling| sibling.page_type != page_type)
+    {
+      return Err(SqliteError::Corrupt(format!(
+        "Siblings of different types under page [{}]",
+        parent.page_number
+      )));
+    }
+    let leaf_data = page_type == BtreePageType::LeafTable;
+
+    // Gather the cells of every sibling, along with the dividers in between.
+    let old_pages: Vec<u32> =
+      siblings.iter().map(|sibling| sibling.page_number).collect();
+    let final_right = siblings.last().and_then(|last| last.right_pointer);
+    let mut cells = vec![];
+    for (idx, sibling) in siblings.into_iter().enumerate() {
+      let right_pointer = sibling.right_pointer;
+      cells.extend(sibling.cells);
+      if idx + 1 == sibling_count {
+        break;
+      }
+      let divider = &parent.cells[first + idx];
+      match page_type {
+        BtreePageType::LeafTable => (),
+        BtreePageType::LeafIndex => cells.push(divider[4..].to_vec()),
+        BtreePageType::InteriorIndex | BtreePageType::InteriorTable => {
+          let mut divider = divider.clone();
+          divider[0..4]
+            .copy_from_slice(&right_pointer.unwrap_or(0).to_be_bytes());
+          cells.push(divider);
+        }
+      }
+    }
+
+    let bounds = distribute(&cells, page_type, leaf_data, usable_size)?;
+    let page_count = bounds.len();
+
+    // Reuse the pages of the siblings, allocating or freeing the difference.
+    let mut new_pages = Vec::with_capacity(page_count);
+    for idx in 0..page_count {
+      match old_pages.get(idx) {
+        Some(page_number) => new_pages.push(*page_number),
+        None => new_pages.push(self.allocate_page()?),
+      }
+    }
+    for page_number in old_pages.iter().skip(page_count) {
+      self.free_page(*page_number)?;
+    }
+
+    let mut cells: Vec<Option<Vec<u8>>> = cells.into_iter().map(Some).collect();
+    let mut dividers = Vec::with_capacity(page_count - 1);
+    let mut start = 0;
+    for (idx, end) in bounds.iter().copied().enumerate() {
+      let mut new_node = BtreeNode::new(new_pages[idx], page_type);
+      new_node.cells = cells[start..end]
+        .iter_mut()
+        .map(|cell| cell.take().unwrap_or_default())
+        .collect();
+      let is_last = idx + 1 == page_count;
+      if is_last {
+        new_node.right_pointer = final_right.filter(|_| !page_type.is_leaf());
+      } else if leaf_data {
+        let key = self.last_rowid(&new_node)?;
+        let (divider, _) = build_cell(
+          BtreePageType::InteriorTable,
+          Some(new_node.page_number),
+          Some(key),
+          &[],
+          usable_size,
+        );
+        dividers.push(divider);
+      } else {
+        let cell = cells[end].take().unwrap_or_default();
+        let divider = if page_type.is_leaf() {
+          let mut divider = new_node.page_number.to_be_bytes().to_vec();
+          divider.extend_from_slice(&cell);
+          divider
+        } else {
+          new_node.right_pointer = Some(read_u32(&cell, 0));
+          let mut divider = cell;
+          divider[0..4].copy_from_slice(&new_node.page_number.to_be_bytes());
+          divider
+        };
+        dividers.push(divider);
+      }
+      self.write_node(&new_node)?;
+      start = if leaf_data { end } else { end + 1 };
+    }
+
+    parent
+      .cells
+      .splice(first..first + sibling_count - 1, dividers);
+    parent.set_child(first + page_count - 1, new_pages[page_count - 1]);
+    debug!(
+      "Balanced pages {old_pages:?} into {new_pages:?} under page [{}].",
+      parent.page_number
+    );
+    Ok(())
+  }
+
+  /// Rowid of the last cell of a table leaf.
+  fn last_rowid(&self, node: &BtreeNode) -> SqliteResult<i64> {
+    let cell = node
+      .cells
+      .last()
+      .ok_or(SqliteError::Corrupt("Empty table leaf".into()))?;
+    CellInfo::parse(cell, node.page_type, self.usable_size())?
+      .rowid
+      .ok_or(SqliteError::Corrupt("Table cell without rowid".into()))
+  }
+}
+
+/// Splits `cells` among pages, returning for each page the index one past its
+/// last cell. Unless `leaf_data`, the cell following each page but the last
+/// one is consumed as the divider moved up to the parent.
+///
+///  Pages are first filled left to right, then cells are shifted from each
+/// page onto its right neighbour while that evens out their sizes, as SQLite
+/// does.
+fn distribute(
+  cells: &[Vec<u8>],
+  page_type: BtreePageType,
+  leaf_data: bool,
+  usable_size: usize,
+) -> SqliteResult<Vec<usize>> {
+  let capacity = usable_size - page_type.header_size();
+  let sizes: Vec<usize> =
+    cells.iter().map(|cell| allocated_size(cell) + 2).collect();
+  if sizes.iter().any(|size| *size > capacity) {
+    return Err(SqliteError::Custom("Cell larger than a page".into()));
+  }
+  let divider_len = usize::from(!leaf_data);
+  let start_of = |bounds: &[usize], page: usize| {
+    if page == 0 {
+      0
+    } else {
+      bounds[page - 1] + divider_len
+    }
+  };
+
+  let mut bounds: Vec<usize> = vec![];
+  let mut totals: Vec<usize> = vec![];
+  let mut idx = 0;
+  loop {
+    let mut total = 0;
+    while idx < cells.len() && total + sizes[idx] <= capacity {
+      total += sizes[idx];
+      idx += 1;
+    }
+    if !leaf_data && idx + 1 == cells.len() {
+      // Consuming the last cell as a divider would leave the last page empty:
+      // the previous cell becomes the divider instead.
+      let page = bounds.len();
+      if idx - start_of(&bounds, page) < 2 {
+        return Err(SqliteError::Custom("Cannot split page".into()));
+      }
+      idx -= 1;
+      total -= sizes[idx];
+    }
+    bounds.push(idx);
+    totals.push(total);
+    if idx >= cells.len() {
+      break;
+    }
+    idx += divider_len;
+  }
+
+  for page in (1..bounds.len()).rev() {
+    loop {
+      let left_start = start_of(&bounds, page - 1);
+      if bounds[page - 1] - left_start <= 1 {
+        break;
+      }
+      let moved_out = bounds[page - 1] - 1;
+      let moved_in = moved_out + divider_len;
+      let right = totals[page] + sizes[moved_in];
+      let left = totals[page - 1] - sizes[moved_out];
+      if right > capacity || (totals[page] != 0 && right > left) {
+        break;
+      }
+      totals[page] = right;
+      totals[page - 1] = left;
+      bounds[page - 1] -= 1;
+    }
+  }
+  Ok(bounds)
+}
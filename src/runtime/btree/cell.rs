@@ -0,0 +1,157 @@
+//! # B-tree Cell Format
+//!
+//! |Datatype       | Table Leaf (0x0d) | Table Interior (0x05) | Index Leaf (0x0a) | Index Interior (0x02) | Description |
+//! |---------------|-------------------|-----------------------|-------------------|-----------------------|-------------|
+//! | 4-byte integer|                   | ✔                     |                   | ✔                     | Page number of left child |
+//! | varint        | ✔                 |                       | ✔                 | ✔                     | Number of bytes of payload |
+//! | varint        | ✔                 | ✔                     |                   |                       | Rowid |
+//! | byte array    | ✔                 |                       | ✔                 | ✔                     | Payload |
+//! | 4-byte integer| ✔                 |                       | ✔                 | ✔                     | Page number of first overflow page |
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+use super::page::{read_u32, BtreePageType};
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::record::varint::{read_varint, write_varint};
+
+/// Decoded layout of a single cell.
+#[derive(Debug)]
+pub(crate) struct CellInfo {
+  /// Rowid, on table pages.
+  pub(crate) rowid: Option<i64>,
+  /// Total number of bytes of payload, overflow included.
+  pub(crate) payload_len: usize,
+  /// Offset of the local payload from the start of the cell.
+  pub(crate) local_offset: usize,
+  /// Number of payload bytes stored on the b-tree page.
+  pub(crate) local_len: usize,
+  /// Page number of the first overflow page, if the payload spills.
+  pub(crate) overflow: Option<u32>,
+  /// Size of the cell in bytes.
+  pub(crate) size: usize,
+}
+
+impl CellInfo {
+  pub(crate) fn parse(
+    cell: &[u8],
+    page_type: BtreePageType,
+    usable_size: usize,
+  ) -> SqliteResult<Self> {
+    let mut offset = if page_type.is_leaf() { 0 } else { 4 };
+    if page_type == BtreePageType::InteriorTable {
+      let (rowid, consumed) = read_varint(&cell[offset..])?;
+      return Ok(Self {
+        rowid: Some(rowid as i64),
+        payload_len: 0,
+        local_offset: offset + consumed,
+        local_len: 0,
+        overflow: None,
+        size: offset + consumed,
+      });
+    }
+    let (payload_len, consumed) = read_varint(&cell[offset..])?;
+    offset += consumed;
+    let payload_len = usize::try_from(payload_len)
+      .map_err(|_| SqliteError::Corrupt("Payload too large".into()))?;
+    let rowid = if page_type == BtreePageType::LeafTable {
+      let (rowid, consumed) = read_varint(&cell[offset..])?;
+      offset += consumed;
+      Some(rowid as i64)
+    } else {
+      None
+    };
+    let local_len = local_payload_len(payload_len, page_type, usable_size);
+    let overflow = (local_len < payload_len)
+      .then(|| cell.get(offset + local_len..offset + local_len + 4))
+      .map(|bytes| {
+        bytes
+          .map(|bytes| read_u32(bytes, 0))
+          .ok_or(SqliteError::Corrupt("Truncated cell".into()))
+      })
+      .transpose()?;
+    let size = offset + local_len + overflow.map_or(0, |_| 4);
+    if size > cell.len() {
+      return Err(SqliteError::Corrupt("Cell exceeds page bounds".into()));
+    }
+    Ok(Self {
+      rowid,
+      payload_len,
+      local_offset: offset,
+      local_len,
+      overflow,
+      size,
+    })
+  }
+}
+
+/// Number of payload bytes stored directly on the b-tree page.
+///
+///  Let U be the usable size, P the payload size and X the threshold: U-35
+/// for table leaves and ((U-12)*64/255)-23 for index pages. If P<=X the
+/// payload is stored entirely on the page. Otherwise, with
+/// M=((U-12)*32/255)-23 and K=M+((P-M)%(U-4)), K bytes are stored on the page
+/// if K<=X, else M bytes; the remainder spills onto overflow pages.
+pub(crate) fn local_payload_len(
+  payload_len: usize,
+  page_type: BtreePageType,
+  usable_size: usize,
+) -> usize {
+  let max_local = if page_type == BtreePageType::LeafTable {
+    usable_size - 35
+  } else {
+    ((usable_size - 12) * 64 / 255) - 23
+  };
+  if payload_len <= max_local {
+    return payload_len;
+  }
+  let min_local = ((usable_size - 12) * 32 / 255) - 23;
+  let surplus = min_local + ((payload_len - min_local) % (usable_size - 4));
+  if surplus <= max_local {
+    surplus
+  } else {
+    min_local
+  }
+}
+
+/// Builds a cell around `payload`, returning it along with the payload bytes
+/// that must be written to overflow pages. The overflow page pointer is left
+/// zeroed for the caller to fill in.
+pub(crate) fn build_cell(
+  page_type: BtreePageType,
+  left_child: Option<u32>,
+  rowid: Option<i64>,
+  payload: &[u8],
+  usable_size: usize,
+) -> (Vec<u8>, Vec<u8>) {
+  let mut cell = vec![];
+  if let Some(left_child) = left_child {
+    cell.extend_from_slice(&left_child.to_be_bytes());
+  }
+  if page_type == BtreePageType::InteriorTable {
+    write_varint(&mut cell, rowid.unwrap_or_default() as u64);
+    return (cell, vec![]);
+  }
+  write_varint(&mut cell, payload.len() as u64);
+  if let Some(rowid) = rowid {
+    write_varint(&mut cell, rowid as u64);
+  }
+  let local_len = local_payload_len(payload.len(), page_type, usable_size);
+  cell.extend_from_slice(&payload[..local_len]);
+  let spilled = payload[local_len..].to_vec();
+  if !spilled.is_empty() {
+    cell.extend_from_slice(&[0; 4]);
+  }
+  (cell, spilled)
+}
+
+/// Space taken by a cell in the content area: cells are never allocated
+/// fewer than 4 bytes.
+pub(crate) fn allocated_size(cell: &[u8]) -> usize {
+  cell.len().max(4)
+}
+
+/// Sets the overflow page pointer stored in the last 4 bytes of `cell`.
+pub(crate) fn set_overflow(cell: &mut [u8], overflow: u32) {
+  let len = cell.len();
+  cell[len - 4..].copy_from_slice(&overflow.to_be_bytes());
+}
@@ -0,0 +1,419 @@
+use super::cell::CellInfo;
+use super::page::BtreeNode;
+use super::{SqliteBtree, MAX_DEPTH};
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::record::{KeyInfo, Record, Value};
+
+/// A position within a b-tree.
+///
+///  The cursor only keeps the pages on the path from the root to the current
+/// cell; every move borrows the [`SqliteBtree`] handle, so several cursors
+/// can be open on the same database at once. Entries are visited in key
+/// order, interior cells of index b-trees included.
+#[derive(Debug)]
+pub struct BtreeCursor {
+  root: u32,
+  /// Pages from the root down to the current cell, each with the index of the
+  /// child followed or, for the last one, of the current cell.
+  stack: Vec<(BtreeNode, usize)>,
+}
+
+impl BtreeCursor {
+  pub fn new(root: u32) -> Self {
+    Self {
+      root,
+      stack: vec![],
+    }
+  }
+
+  pub fn root(&self) -> u32 {
+    self.root
+  }
+
+  /// True when the cursor does not point to an entry.
+  pub fn is_eof(&self) -> bool {
+    self
+      .stack
+      .last()
+      .map_or(true, |(node, idx)| *idx >= node.cells.len())
+  }
+
+  /// Moves past the last entry.
+  pub fn reset(&mut self) {
+    self.stack.clear();
+  }
+
+  /// Moves to the first entry. Returns false if the b-tree is empty.
+  pub fn first(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.stack.clear();
+    let root = btree.read_node(self.root)?;
+    self.descend_leftmost(btree, root)?;
+    Ok(!self.is_eof())
+  }
+
+  /// Moves to the last entry. Returns false if the b-tree is empty.
+  pub fn last(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.stack.clear();
+    let root = btree.read_node(self.root)?;
+    self.descend_rightmost(btree, root)?;
+    Ok(!self.is_eof())
+  }
+
+  /// Advances to the next entry. Returns false once past the last one.
+  pub fn next(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    let Some((node, idx)) = self.stack.pop() else {
+      return Ok(false);
+    };
+    if !node.page_type.is_leaf() {
+      // Positioned on an interior index cell: continue with the subtree to
+      // its right.
+      let child = btree.read_node(node.child(idx + 1)?)?;
+      self.push(node, idx + 1)?;
+      self.descend_leftmost(btree, child)?;
+      return Ok(!self.is_eof());
+    }
+    if idx + 1 < node.cells.len() {
+      self.push(node, idx + 1)?;
+      return Ok(true);
+    }
+    while let Some((parent, idx)) = self.stack.pop() {
+      if parent.page_type.is_table() {
+        if idx + 1 < parent.child_count() {
+          let child = btree.read_node(parent.child(idx + 1)?)?;
+          self.push(parent, idx + 1)?;
+          self.descend_leftmost(btree, child)?;
+          return Ok(!self.is_eof());
+        }
+      } else if idx < parent.cells.len() {
+        self.push(parent, idx)?;
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  /// Moves back to the previous entry. Returns false once before the first
+  /// one.
+  pub fn prev(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    let Some((node, idx)) = self.stack.pop() else {
+      return Ok(false);
+    };
+    if !node.page_type.is_leaf() {
+      // Positioned on an interior index cell: continue with the subtree to
+      // its left.
+      let child = btree.read_node(node.child(idx)?)?;
+      self.push(node, idx)?;
+      self.descend_rightmost(btree, child)?;
+      return Ok(!self.is_eof());
+    }
+    if idx > 0 {
+      self.push(node, idx - 1)?;
+      return Ok(true);
+    }
+    while let Some((parent, idx)) = self.stack.pop() {
+      if idx == 0 {
+        continue;
+      }
+      if parent.page_type.is_table() {
+        let child = btree.read_node(parent.child(idx - 1)?)?;
+        self.push(parent, idx - 1)?;
+        self.descend_rightmost(btree, child)?;
+        return Ok(!self.is_eof());
+      }
+      self.push(parent, idx - 1)?;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+
+  /// Moves to the entry with the given `rowid` of a table b-tree. Returns
+  /// false, leaving the cursor at end of file, when there is none.
+  pub fn seek_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    let usable_size = btree.usable_size();
+    let position = |leaf: &BtreeNode| {
+      leaf.cells.iter().position(|cell| {
+        CellInfo::parse(cell, leaf.page_type, usable_size)
+          .ok()
+          .and_then(|info| info.rowid)
+          == Some(rowid)
+      })
+    };
+    // Lookups in rowid order often land on the leaf the cursor is on.
+    if let Some((leaf, idx)) = self.stack.last_mut() {
+      if leaf.page_type.is_leaf() {
+        if let Some(found) = position(leaf) {
+          *idx = found;
+          return Ok(true);
+        }
+      }
+    }
+    self.stack.clear();
+    let (path, leaf) = btree.seek_table_leaf(self.root, rowid)?;
+    let found = position(&leaf);
+    match found {
+      Some(idx) => {
+        self.stack = path;
+        self.push(leaf, idx)?;
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+
+  /// Moves to the first entry of a table b-tree whose rowid is not less than
+  /// `rowid`. Returns false, leaving the cursor at end of file, when there
+  /// is none.
+  pub fn seek_first_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    let usable_size = btree.usable_size();
+    self.stack.clear();
+    let (path, leaf) = btree.seek_table_leaf(self.root, rowid)?;
+    let idx = leaf.cells.partition_point(|cell| {
+      CellInfo::parse(cell, leaf.page_type, usable_size)
+        .ok()
+        .and_then(|info| info.rowid)
+        .is_some_and(|key| key < rowid)
+    });
+    if leaf.cells.is_empty() {
+      return Ok(false);
+    }
+    self.stack = path;
+    if idx < leaf.cells.len() {
+      self.push(leaf, idx)?;
+      return Ok(true);
+    }
+    // Every row of the leaf has a smaller rowid: the first one not less
+    // than `rowid` is the row that follows the leaf, if any.
+    let last = leaf.cells.len() - 1;
+    self.push(leaf, last)?;
+    self.next(btree)
+  }
+
+  /// Moves to the last entry of a table b-tree whose rowid is not greater
+  /// than `rowid`. Returns false, leaving the cursor at end of file, when
+  /// there is none.
+  pub fn seek_last_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    if !self.seek_first_rowid(btree, rowid)? {
+      return self.last(btree);
+    }
+    if self.rowid(btree)? == rowid {
+      return Ok(true);
+    }
+    let found = self.prev(btree)?;
+    if !found {
+      self.stack.clear();
+    }
+    Ok(found)
+  }
+
+  /// Moves to the entry of an index b-tree whose leading columns equal `key`,
+  /// ordered according to `key_info`. Returns false, leaving the cursor at
+  /// end of file, when there is none.
+  pub fn seek_key(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<bool> {
+    self.stack.clear();
+    let seek = btree.seek_index(self.root, key, key_info)?;
+    if !seek.found {
+      return Ok(false);
+    }
+    self.stack = seek.path;
+    self.push(seek.node, seek.idx)?;
+    Ok(true)
+  }
+
+  /// Moves to the first entry of an index b-tree whose leading columns are
+  /// not less than `key`, ordered according to `key_info`, so that all the
+  /// entries matching `key` are visited from there with [`Self::next`].
+  /// Returns false, leaving the cursor at end of file, when there is none.
+  pub fn seek_first_key(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<bool> {
+    self.stack.clear();
+    let mut node = btree.read_node(self.root)?;
+    loop {
+      if node.page_type.is_table() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{}] is not the root of an index b-tree",
+          self.root
+        )));
+      }
+      let (mut low, mut high) = (0, node.cells.len());
+      while low < high {
+        let middle = low + (high - low) / 2;
+        let cell_key = btree.index_key(&node, middle)?;
+        match key_info.compare(&cell_key, key) {
+          core::cmp::Ordering::Less => low = middle + 1,
+          _ => high = middle,
+        }
+      }
+      if !node.page_type.is_leaf() {
+        let child = node.child(low)?;
+        self.push(node, low)?;
+        node = btree.read_node(child)?;
+        continue;
+      }
+      if low < node.cells.len() {
+        self.push(node, low)?;
+        return Ok(true);
+      }
+      if node.cells.is_empty() {
+        self.stack.clear();
+        return Ok(false);
+      }
+      // Every entry of the leaf is smaller: the first one not less than
+      // `key` is the entry that follows the leaf, if any.
+      let last = node.cells.len() - 1;
+      self.push(node, last)?;
+      return self.next(btree);
+    }
+  }
+
+  /// Moves to the last entry of an index b-tree whose leading columns are
+  /// not greater than `key`, ordered according to `key_info`, so that all
+  /// the entries matching `key` are visited from there with [`Self::prev`].
+  /// Returns false, leaving the cursor at end of file, when there is none.
+  pub fn seek_last_key(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<bool> {
+    self.stack.clear();
+    let mut node = btree.read_node(self.root)?;
+    loop {
+      if node.page_type.is_table() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{}] is not the root of an index b-tree",
+          self.root
+        )));
+      }
+      let (mut low, mut high) = (0, node.cells.len());
+      while low < high {
+        let middle = low + (high - low) / 2;
+        let cell_key = btree.index_key(&node, middle)?;
+        match key_info.compare(&cell_key, key) {
+          core::cmp::Ordering::Greater => high = middle,
+          _ => low = middle + 1,
+        }
+      }
+      if !node.page_type.is_leaf() {
+        let child = node.child(low)?;
+        self.push(node, low)?;
+        node = btree.read_node(child)?;
+        continue;
+      }
+      if low > 0 {
+        self.push(node, low - 1)?;
+        return Ok(true);
+      }
+      // Every entry of the leaf is greater: the last one not greater than
+      // `key` is the entry before the leaf, if any.
+      let is_empty = node.cells.is_empty();
+      self.push(node, 0)?;
+      let found = !is_empty && self.prev(btree)?;
+      if !found {
+        self.stack.clear();
+      }
+      return Ok(found);
+    }
+  }
+
+  /// Rowid of the current entry of a table b-tree.
+  pub fn rowid(&self, btree: &SqliteBtree<'_>) -> SqliteResult<i64> {
+    self.cell_info(btree)?.rowid.ok_or(SqliteError::Custom(
+      "Cursor is not on a table b-tree".into(),
+    ))
+  }
+
+  /// Full payload of the current entry, overflow pages included.
+  pub fn payload(&self, btree: &mut SqliteBtree<'_>) -> SqliteResult<Vec<u8>> {
+    let info = self.cell_info(btree)?;
+    btree.read_payload(self.cell()?, &info)
+  }
+
+  /// Payload of the current entry decoded as a record.
+  pub fn record(
+    &self,
+    btree: &mut SqliteBtree<'_>,
+  ) -> SqliteResult<Vec<Value>> {
+    let payload = self.payload(btree)?;
+    Record::decode_with_encoding(
+      &payload,
+      btree.header().database_text_encoding(),
+    )
+  }
+
+  pub(crate) fn cell(&self) -> SqliteResult<&[u8]> {
+    self
+      .stack
+      .last()
+      .and_then(|(node, idx)| node.cells.get(*idx))
+      .map(|cell| cell.as_slice())
+      .ok_or(SqliteError::Custom("Cursor is not on an entry".into()))
+  }
+
+  pub(crate) fn cell_info(
+    &self,
+    btree: &SqliteBtree<'_>,
+  ) -> SqliteResult<CellInfo> {
+    let (node, _) = self
+      .stack
+      .last()
+      .ok_or(SqliteError::Custom("Cursor is not on an entry".into()))?;
+    CellInfo::parse(self.cell()?, node.page_type, btree.usable_size())
+  }
+
+  fn descend_leftmost(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    mut node: BtreeNode,
+  ) -> SqliteResult<()> {
+    while !node.page_type.is_leaf() {
+      let child = node.child(0)?;
+      self.push(node, 0)?;
+      node = btree.read_node(child)?;
+    }
+    self.push(node, 0)
+  }
+
+  fn descend_rightmost(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    mut node: BtreeNode,
+  ) -> SqliteResult<()> {
+    while !node.page_type.is_leaf() {
+      let idx = node.cells.len();
+      let child = node.child(idx)?;
+      self.push(node, idx)?;
+      node = btree.read_node(child)?;
+    }
+    let idx = node.cells.len().saturating_sub(1);
+    self.push(node, idx)
+  }
+
+  fn push(&mut self, node: BtreeNode, idx: usize) -> SqliteResult<()> {
+    if self.stack.len() > MAX_DEPTH {
+      return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+    }
+    self.stack.push((node, idx));
+    Ok(())
+  }
+}
@@ -0,0 +1,416 @@
+//! # Freeblocks
+//!
+//!  A freeblock is a structure used to identify unallocated space within a
+//! b-tree page. Freeblocks are organized as a chain. The first 2 bytes of a
+//! freeblock are a big-endian integer which is the offset in the b-tree page
+//! of the next freeblock in the chain, or zero if the freeblock is the last
+//! on the chain. The third and fourth bytes of each freeblock form a
+//! big-endian integer which is the size of the freeblock in bytes, including
+//! the 4-byte header. Freeblocks are always connected in order of increasing
+//! offset.
+//!
+//!  If a group of fewer than 4 unused bytes sits between two cells, they are
+//! counted as fragmented bytes instead, in the header of the page. In a
+//! well-formed b-tree page, the total number of bytes in fragments may not
+//! exceed 60.
+//!
+//!  Cells are added and removed in place: space is taken first-fit from the
+//! freeblocks or from the unallocated area between the cell pointer array and
+//! the cell content area, and the page is defragmented when there is enough
+//! free space overall but no single region large enough.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+use super::cell::{allocated_size, CellInfo};
+use super::page::{
+  header_offset, read_u16, write_u16, BtreeNode, BtreePageType,
+};
+use super::{SqliteBtree, MAX_DEPTH};
+use crate::pager::page::Page;
+use crate::result::{SqliteError, SqliteResult};
+
+/// Upper bound SQLite keeps on the fragmented bytes of a page.
+const MAX_FRAGMENTED_BYTES: usize = 60;
+
+/// How the space of a b-tree page is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BtreePageStats {
+  page_number: u32,
+  page_type: BtreePageType,
+  cell_count: usize,
+  /// Bytes between the cell pointer array and the cell content area.
+  unallocated_bytes: usize,
+  freeblock_count: usize,
+  /// Bytes held in freeblocks, their 4-byte headers included.
+  freeblock_bytes: usize,
+  fragmented_bytes: usize,
+}
+
+impl BtreePageStats {
+  pub fn page_number(&self) -> u32 {
+    self.page_number
+  }
+
+  pub fn page_type(&self) -> BtreePageType {
+    self.page_type
+  }
+
+  pub fn cell_count(&self) -> usize {
+    self.cell_count
+  }
+
+  pub fn unallocated_bytes(&self) -> usize {
+    self.unallocated_bytes
+  }
+
+  pub fn freeblock_count(&self) -> usize {
+    self.freeblock_count
+  }
+
+  pub fn freeblock_bytes(&self) -> usize {
+    self.freeblock_bytes
+  }
+
+  pub fn fragmented_bytes(&self) -> usize {
+    self.fragmented_bytes
+  }
+
+  /// Total free space on the page, wherever it lies.
+  pub fn free_bytes(&self) -> usize {
+    self.unallocated_bytes + self.freeblock_bytes + self.fragmented_bytes
+  }
+
+  /// Whether some of the free space lies outside the unallocated area, so
+  /// that defragmenting the page would gather it.
+  pub fn is_fragmented(&self) -> bool {
+    self.freeblock_bytes + self.fragmented_bytes > 0
+  }
+}
+
+impl SqliteBtree<'_> {
+  /// Space usage of the b-tree page `page_number`.
+  pub fn page_stats(
+    &mut self,
+    page_number: u32,
+  ) -> SqliteResult<BtreePageStats> {
+    let page = self.read_page(page_number)?;
+    page_stats(&page, page_number, self.usable_size())
+  }
+
+  /// Space usage of every page of the b-tree rooted at `root`, overflow pages
+  /// aside, in depth-first order.
+  pub fn btree_stats(
+    &mut self,
+    root: u32,
+  ) -> SqliteResult<Vec<BtreePageStats>> {
+    let mut stats = vec![];
+    let mut pending = vec![(root, 0)];
+    while let Some((page_number, depth)) = pending.pop() {
+      if depth > MAX_DEPTH {
+        return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+      }
+      let page = self.read_page(page_number)?;
+      let node = BtreeNode::parse(page_number, &page, self.usable_size())?;
+      for idx in (0..node.child_count()).rev() {
+        pending.push((node.child(idx)?, depth + 1));
+      }
+      stats.push(page_stats(&page, page_number, self.usable_size())?);
+    }
+    Ok(stats)
+  }
+}
+
+/// Walks the header and the freeblock list of a b-tree page.
+pub(crate) fn page_stats(
+  page: &Page,
+  page_number: u32,
+  usable_size: usize,
+) -> SqliteResult<BtreePageStats> {
+  let offset = header_offset(page_number);
+  let data = page.raw_data();
+  let page_type = BtreePageType::try_from(data[offset])?;
+  let cell_count = read_u16(data, offset + 3);
+  let pointers_end = pointers_end(data, offset, page_type);
+  let content_start = content_start(data, offset);
+  if pointers_end > content_start || content_start > usable_size {
+    return Err(corrupt(page_number));
+  }
+  let mut freeblock_count = 0;
+  let mut freeblock_bytes = 0;
+  let mut block = read_u16(data, offset + 1);
+  let mut previous = 0;
+  while block != 0 {
+    if block <= previous || block < content_start || block + 4 > usable_size {
+      return Err(corrupt(page_number));
+    }
+    freeblock_count += 1;
+    freeblock_bytes += read_u16(data, block + 2);
+    previous = block;
+    block = read_u16(data, block);
+  }
+  Ok(BtreePageStats {
+    page_number,
+    page_type,
+    cell_count,
+    unallocated_bytes: content_start - pointers_end,
+    freeblock_count,
+    freeblock_bytes,
+    fragmented_bytes: usize::from(data[offset + 7]),
+  })
+}
+
+/// Inserts `cell` at position `idx` of the b-tree page `page_number` in
+/// place. Returns false, leaving the page untouched, when it lacks the space.
+pub(crate) fn insert_cell(
+  page: &mut Page,
+  page_number: u32,
+  idx: usize,
+  cell: &[u8],
+  usable_size: usize,
+) -> SqliteResult<bool> {
+  let offset = header_offset(page_number);
+  let size = allocated_size(cell);
+  let Some(start) = allocate_space(page, page_number, size, usable_size)?
+  else {
+    return Ok(false);
+  };
+  let data = page.raw_data_mut();
+  let page_type = BtreePageType::try_from(data[offset])?;
+  let cell_count = read_u16(data, offset + 3);
+  if idx > cell_count {
+    return Err(SqliteError::Custom("Cell index out of range".into()));
+  }
+  data[start..start + size].fill(0);
+  data[start..start + cell.len()].copy_from_slice(cell);
+  let pointer = offset + page_type.header_size() + 2 * idx;
+  let pointers_end = pointers_end(data, offset, page_type);
+  data.copy_within(pointer..pointers_end, pointer + 2);
+  write_u16(data, pointer, start);
+  write_u16(data, offset + 3, cell_count + 1);
+  Ok(true)
+}
+
+/// Finds `size` bytes for a new cell, along with 2 bytes for its pointer, on
+/// the b-tree page `page_number`. Returns the offset of the space, or `None`
+/// when the page has too little free space in total.
+pub(crate) fn allocate_space(
+  page: &mut Page,
+  page_number: u32,
+  size: usize,
+  usable_size: usize,
+) -> SqliteResult<Option<usize>> {
+  let stats = page_stats(page, page_number, usable_size)?;
+  let offset = header_offset(page_number);
+  let data = page.raw_data_mut();
+  let gap = pointers_end(data, offset, stats.page_type) + 2;
+  let mut top = content_start(data, offset);
+  if stats.freeblock_count > 0 && gap <= top {
+    if let Some(start) = find_slot(data, offset, size) {
+      return Ok(Some(start));
+    }
+  }
+  if gap + size > top {
+    if stats.free_bytes() < size + 2 {
+      return Ok(None);
+    }
+    defragment(page, page_number, usable_size)?;
+    top = content_start(page.raw_data(), offset);
+    if gap + size > top {
+      return Err(corrupt(page_number));
+    }
+  }
+  top -= size;
+  write_u16(page.raw_data_mut(), offset + 5, top);
+  Ok(Some(top))
+}
+
+/// First-fit search of the freeblock list for `size` bytes. What remains of
+/// a freeblock when less than 4 bytes would be left becomes fragmented bytes,
+/// unless that would exceed the limit SQLite keeps on them.
+fn find_slot(data: &mut [u8], offset: usize, size: usize) -> Option<usize> {
+  let mut previous = offset + 1;
+  let mut block = read_u16(data, previous);
+  while block != 0 {
+    let block_size = read_u16(data, block + 2);
+    if block_size >= size {
+      let remainder = block_size - size;
+      if remainder >= 4 {
+        // Keep the front of the block free, handing out its end.
+        write_u16(data, block + 2, remainder);
+        return Some(block + remainder);
+      }
+      let fragments = usize::from(data[offset + 7]);
+      if fragments + remainder > MAX_FRAGMENTED_BYTES {
+        return None;
+      }
+      let next = read_u16(data, block);
+      write_u16(data, previous, next);
+      data[offset + 7] = (fragments + remainder) as u8;
+      return Some(block);
+    }
+    previous = block;
+    block = read_u16(data, block);
+  }
+  None
+}
+
+/// Moves every cell of the b-tree page `page_number` to the end of the page,
+/// gathering its free space into the unallocated area.
+pub(crate) fn defragment(
+  page: &mut Page,
+  page_number: u32,
+  usable_size: usize,
+) -> SqliteResult<()> {
+  let offset = header_offset(page_number);
+  let data = page.raw_data_mut();
+  let page_type = BtreePageType::try_from(data[offset])?;
+  let cell_count = read_u16(data, offset + 3);
+  let pointers_start = offset + page_type.header_size();
+  let mut cells = Vec::with_capacity(cell_count);
+  for idx in 0..cell_count {
+    let cell_offset = read_u16(data, pointers_start + 2 * idx);
+    if cell_offset >= usable_size {
+      return Err(corrupt(page_number));
+    }
+    let info =
+      CellInfo::parse(&data[cell_offset..usable_size], page_type, usable_size)?;
+    cells.push(data[cell_offset..cell_offset + info.size].to_vec());
+  }
+  let pointers_end = pointers_start + 2 * cell_count;
+  data[pointers_end..usable_size].fill(0);
+  let mut content_start = usable_size;
+  for (idx, cell) in cells.iter().enumerate() {
+    content_start -= allocated_size(cell);
+    data[content_start..content_start + cell.len()].copy_from_slice(cell);
+    write_u16(data, pointers_start + 2 * idx, content_start);
+  }
+  write_u16(data, offset + 1, 0);
+  write_u16(data, offset + 5, content_start & 0xffff);
+  data[offset + 7] = 0;
+  trace!("Page [{page_number}] defragmented.");
+  Ok(())
+}
+
+/// Removes cell `idx` from the b-tree page `page_number` in place, returning
+/// the space of the cell to the freeblock list.
+pub(crate) fn drop_cell(
+  page: &mut Page,
+  page_number: u32,
+  idx: usize,
+  usable_size: usize,
+) -> SqliteResult<()> {
+  let offset = header_offset(page_number);
+  let data = page.raw_data_mut();
+  let page_type = BtreePageType::try_from(data[offset])?;
+  let cell_count = read_u16(data, offset + 3);
+  if idx >= cell_count {
+    return Err(SqliteError::Custom("Cell index out of range".into()));
+  }
+  let pointers_start = offset + page_type.header_size();
+  let pointer = pointers_start + 2 * idx;
+  let cell_offset = read_u16(data, pointer);
+  if cell_offset >= usable_size {
+    return Err(SqliteError::Corrupt(format!(
+      "Cell pointer out of bounds on page [{page_number}]"
+    )));
+  }
+  let info =
+    CellInfo::parse(&data[cell_offset..usable_size], page_type, usable_size)?;
+  free_space(data, offset, cell_offset, info.size.max(4), usable_size)?;
+  let pointers_end = pointers_start + 2 * cell_count;
+  data.copy_within(pointer + 2..pointers_end, pointer);
+  data[pointers_end - 2..pointers_end].fill(0);
+  write_u16(data, offset + 3, cell_count - 1);
+  Ok(())
+}
+
+/// Releases `size` bytes at `start`, merging them with the neighbouring
+/// freeblocks and absorbing the fragments in between, or extending the cell
+/// content area when they border its start.
+pub(crate) fn free_space(
+  data: &mut [u8],
+  offset: usize,
+  start: usize,
+  size: usize,
+  usable_size: usize,
+) -> SqliteResult<()> {
+  let malformed = || SqliteError::Corrupt("Malformed freeblock list".into());
+  let mut start = start;
+  let mut end = start + size;
+  if end > usable_size {
+    return Err(malformed());
+  }
+  let mut absorbed_fragments = 0;
+
+  // Find the freeblocks on either side of the released range.
+  let mut previous = offset + 1;
+  let mut next = read_u16(data, previous);
+  while next != 0 && next < start {
+    if next <= previous || next + 4 > usable_size {
+      return Err(malformed());
+    }
+    previous = next;
+    next = read_u16(data, next);
+  }
+  if next != 0 {
+    if next < end || next + 4 > usable_size {
+      return Err(malformed());
+    }
+    if next <= end + 3 {
+      absorbed_fragments += next - end;
+      end = next + read_u16(data, next + 2);
+      next = read_u16(data, next);
+    }
+  }
+  let merges_previous = previous > offset + 1 && {
+    let previous_end = previous + read_u16(data, previous + 2);
+    if previous_end > start {
+      return Err(malformed());
+    }
+    previous_end + 3 >= start && {
+      absorbed_fragments += start - previous_end;
+      start = previous;
+      true
+    }
+  };
+
+  let fragments = usize::from(data[offset + 7]);
+  if absorbed_fragments > fragments {
+    return Err(malformed());
+  }
+  data[offset + 7] = (fragments - absorbed_fragments) as u8;
+
+  let content_start = content_start(data, offset);
+  if start <= content_start {
+    // The range borders the cell content area, which grows instead.
+    if start < content_start || previous != offset + 1 {
+      return Err(malformed());
+    }
+    write_u16(data, offset + 1, next);
+    write_u16(data, offset + 5, end);
+  } else {
+    if !merges_previous {
+      write_u16(data, previous, start);
+    }
+    write_u16(data, start, next);
+    write_u16(data, start + 2, end - start);
+  }
+  Ok(())
+}
+
+/// Offset of the cell content area. A zero value is interpreted as 65536.
+fn content_start(data: &[u8], offset: usize) -> usize {
+  match read_u16(data, offset + 5) {
+    0 => 65536,
+    content_start => content_start,
+  }
+}
+
+/// Offset one past the end of the cell pointer array.
+fn pointers_end(data: &[u8], offset: usize, page_type: BtreePageType) -> usize {
+  offset + page_type.header_size() + 2 * read_u16(data, offset + 3)
+}
+
+fn corrupt(page_number: u32) -> SqliteError {
+  SqliteError::Corrupt(format!("Malformed free space on page [{page_number}]"))
+}
@@ -0,0 +1,129 @@
+//! # The Freelist
+//!
+//!  A database file might contain one or more pages that are not in active
+//! use. Unused pages can come about, for example, when information is deleted
+//! from the database. Unused pages are stored on the freelist and are reused
+//! when additional pages are required.
+//!
+//!  The freelist is organized as a linked list of freelist trunk pages with
+//! each trunk page containing page numbers for zero or more freelist leaf
+//! pages. A freelist trunk page consists of an array of 4-byte big-endian
+//! integers. The size of the array is as many integers as will fit in the
+//! usable space of a page. The first integer on a freelist trunk page is the
+//! page number of the next freelist trunk page in the list or zero if this is
+//! the last freelist trunk page. The second integer on a freelist trunk page
+//! is the number of leaf page pointers to follow.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#the_freelist
+
+use super::page::read_u32;
+use super::ptrmap::PtrmapType;
+use super::SqliteBtree;
+use crate::pager::page::Page;
+use crate::result::{SqliteError, SqliteResult};
+
+/// The lock-byte page sits at the 1073741824 byte offset and is never used.
+const PENDING_BYTE: u64 = 0x4000_0000;
+
+impl SqliteBtree<'_> {
+  /// Page number of the lock-byte page.
+  pub(crate) fn lock_byte_page(&self) -> u32 {
+    (PENDING_BYTE / self.pager.page_size().as_usize() as u64) as u32 + 1
+  }
+
+  /// Allocates a page, taking it from the freelist before growing the file.
+  /// The returned page is zero-filled.
+  pub(crate) fn allocate_page(&mut self) -> SqliteResult<u32> {
+    let page_number = match self.pop_freelist()? {
+      Some(page_number) => page_number,
+      None => self.append_page()?,
+    };
+    self.write_page(page_number, Page::new(self.pager.page_size().clone()))?;
+    trace!("Page [{page_number}] allocated.");
+    Ok(page_number)
+  }
+
+  /// Grows the database file by one page, skipping the lock-byte page and
+  /// leaving ptrmap pages zero-filled.
+  pub(crate) fn append_page(&mut self) -> SqliteResult<u32> {
+    loop {
+      let page_number = self.pager.append()?;
+      if page_number != self.lock_byte_page()
+        && !self.is_ptrmap_page(page_number)
+      {
+        return Ok(page_number);
+      }
+    }
+  }
+
+  pub(crate) fn pop_freelist(&mut self) -> SqliteResult<Option<u32>> {
+    let trunk_number = **self.header.freelist_pages().first();
+    if trunk_number == 0 {
+      return Ok(None);
+    }
+    let mut trunk = self.read_page(trunk_number)?;
+    let next_trunk = read_u32(trunk.raw_data(), 0);
+    let leaf_count = read_u32(trunk.raw_data(), 4) as usize;
+    let page_number = if leaf_count > 0 {
+      let offset = 8 + 4 * (leaf_count - 1);
+      let leaf = read_u32(trunk.raw_data(), offset);
+      let data = trunk.raw_data_mut();
+      data[4..8].copy_from_slice(&(leaf_count as u32 - 1).to_be_bytes());
+      data[offset..offset + 4].fill(0);
+      self.write_page(trunk_number, trunk)?;
+      leaf
+    } else {
+      self.header.freelist_pages_mut().set_first(next_trunk);
+      trunk_number
+    };
+    if page_number == 0 || page_number > self.pager.page_count() {
+      return Err(SqliteError::Corrupt(format!(
+        "Freelist page [{page_number}] out of range"
+      )));
+    }
+    let total = **self.header.freelist_pages().total();
+    self
+      .header
+      .freelist_pages_mut()
+      .set_total(total.saturating_sub(1));
+    Ok(Some(page_number))
+  }
+
+  /// Adds `page_number` to the freelist, as a leaf of the first trunk page
+  /// when it has room or else as the new first trunk page.
+  pub(crate) fn free_page(&mut self, page_number: u32) -> SqliteResult<()> {
+    if page_number < 2 || page_number > self.pager.page_count() {
+      return Err(SqliteError::Corrupt(format!(
+        "Cannot free page [{page_number}]"
+      )));
+    }
+    let trunk_number = **self.header.freelist_pages().first();
+    let total = **self.header.freelist_pages().total();
+    // Legacy versions of SQLite read at most `usable / 4 - 8` leaves.
+    let max_leaves = self.pager.usable_size() / 4 - 8;
+    if trunk_number != 0 {
+      let mut trunk = self.read_page(trunk_number)?;
+      let leaf_count = read_u32(trunk.raw_data(), 4) as usize;
+      if leaf_count < max_leaves {
+        let data = trunk.raw_data_mut();
+        let offset = 8 + 4 * leaf_count;
+        data[offset..offset + 4].copy_from_slice(&page_number.to_be_bytes());
+        data[4..8].copy_from_slice(&(leaf_count as u32 + 1).to_be_bytes());
+        self.write_page(trunk_number, trunk)?;
+        self.header.freelist_pages_mut().set_total(total + 1);
+        self.set_ptrmap(page_number, PtrmapType::FreePage, 0)?;
+        trace!("Page [{page_number}] freed as a freelist leaf.");
+        return Ok(());
+      }
+    }
+    let mut trunk = Page::new(self.pager.page_size().clone());
+    trunk.raw_data_mut()[0..4].copy_from_slice(&trunk_number.to_be_bytes());
+    self.write_page(page_number, trunk)?;
+    let freelist_pages = self.header.freelist_pages_mut();
+    freelist_pages.set_first(page_number);
+    freelist_pages.set_total(total + 1);
+    self.set_ptrmap(page_number, PtrmapType::FreePage, 0)?;
+    trace!("Page [{page_number}] freed as a freelist trunk.");
+    Ok(())
+  }
+}
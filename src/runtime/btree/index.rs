@@ -0,0 +1,190 @@
+//! # Index B-trees
+//!
+//!  An index b-tree stores keys only: the payload of each cell is a record
+//! holding the indexed columns followed by the rowid of the table row. Keys
+//! live on interior pages as well as on leaves, so a key removed from an
+//! interior page is replaced by its predecessor, taken from the leaf at the
+//! right edge of its left subtree.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+use super::cell::{build_cell, set_overflow, CellInfo};
+use super::page::{BtreeNode, BtreePageType};
+use super::{SqliteBtree, MAX_DEPTH};
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::record::{KeyInfo, Record, Value};
+use core::cmp::Ordering;
+
+/// Outcome of a search for a key in an index b-tree.
+#[derive(Debug)]
+pub(crate) struct IndexSeek {
+  /// Interior pages visited, with the index of the child followed on each.
+  pub(crate) path: Vec<(BtreeNode, usize)>,
+  /// Page holding the key when `found`, otherwise the leaf where it belongs.
+  pub(crate) node: BtreeNode,
+  /// Index of the key on `node`, or the position where it belongs.
+  pub(crate) idx: usize,
+  pub(crate) found: bool,
+}
+
+impl SqliteBtree<'_> {
+  /// Allocates the root page of a new, empty index b-tree.
+  pub fn create_index(&mut self) -> SqliteResult<u32> {
+    self.create_btree(BtreePageType::LeafIndex)
+  }
+
+  /// Inserts `key` in the index b-tree rooted at `root`, ordered according
+  /// to `key_info`. Inserting a key already present is a no-op.
+  pub fn insert_index(
+    &mut self,
+    root: u32,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<()> {
+    let IndexSeek {
+      path,
+      node: mut leaf,
+      idx,
+      found,
+    } = self.seek_index(root, key, key_info)?;
+    if found {
+      return Ok(());
+    }
+    let usable_size = self.usable_size();
+    let payload = Record::encode(key);
+    let (mut cell, spilled) =
+      build_cell(BtreePageType::LeafIndex, None, None, &payload, usable_size);
+    if !spilled.is_empty() {
+      let overflow = self.write_overflow(&spilled)?;
+      set_overflow(&mut cell, overflow);
+    }
+    leaf.cells.insert(idx, cell);
+    trace!("Index key inserted on page [{}].", leaf.page_number);
+    if leaf.fits(usable_size) {
+      return self.store_cell(&leaf, idx, false);
+    }
+    self.balance(path, leaf, false)
+  }
+
+  /// Deletes `key` from the index b-tree rooted at `root`, ordered according
+  /// to `key_info`. Returns false when the key is not present.
+  pub fn delete_index(
+    &mut self,
+    root: u32,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<bool> {
+    let IndexSeek {
+      mut path,
+      node,
+      idx,
+      found,
+    } = self.seek_index(root, key, key_info)?;
+    if !found {
+      return Ok(false);
+    }
+    let usable_size = self.usable_size();
+    let info = CellInfo::parse(&node.cells[idx], node.page_type, usable_size)?;
+    self.free_overflow(&info)?;
+    trace!("Index key deleted from page [{}].", node.page_number);
+    if node.page_type.is_leaf() {
+      self.remove_cell(path, node, idx)?;
+      return Ok(true);
+    }
+
+    // Replace the interior cell with its predecessor, which keeps the left
+    // child pointer of the removed cell.
+    let child = node.child(idx)?;
+    let interior_depth = path.len();
+    path.push((node, idx));
+    let mut leaf = self.read_node(child)?;
+    while !leaf.page_type.is_leaf() {
+      let child_idx = leaf.cells.len();
+      let child = leaf.child(child_idx)?;
+      path.push((leaf, child_idx));
+      if path.len() > MAX_DEPTH {
+        return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+      }
+      leaf = self.read_node(child)?;
+    }
+    let last = leaf
+      .cells
+      .last()
+      .ok_or(SqliteError::Corrupt("Empty index leaf".into()))?;
+    let mut replacement = child.to_be_bytes().to_vec();
+    replacement.extend_from_slice(last);
+    let interior = &mut path[interior_depth].0;
+    interior.cells[idx] = replacement;
+    if interior.fits(usable_size) {
+      let interior = interior.clone();
+      self.write_node(&interior)?;
+    }
+    let last_idx = leaf.cells.len() - 1;
+    self.remove_cell(path, leaf, last_idx)?;
+    Ok(true)
+  }
+
+  /// Descends the index b-tree rooted at `root` looking for `key`.
+  pub(crate) fn seek_index(
+    &mut self,
+    root: u32,
+    key: &[Value],
+    key_info: &KeyInfo,
+  ) -> SqliteResult<IndexSeek> {
+    let mut path = vec![];
+    let mut node = self.read_node(root)?;
+    loop {
+      if node.page_type.is_table() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{root}] is not the root of an index b-tree"
+        )));
+      }
+      let (mut low, mut high) = (0, node.cells.len());
+      while low < high {
+        let middle = low + (high - low) / 2;
+        let cell_key = self.index_key(&node, middle)?;
+        match key_info.compare(&cell_key, key) {
+          Ordering::Less => low = middle + 1,
+          Ordering::Greater => high = middle,
+          Ordering::Equal => {
+            return Ok(IndexSeek {
+              path,
+              node,
+              idx: middle,
+              found: true,
+            })
+          }
+        }
+      }
+      if node.page_type.is_leaf() {
+        return Ok(IndexSeek {
+          path,
+          node,
+          idx: low,
+          found: false,
+        });
+      }
+      let child = node.child(low)?;
+      path.push((node, low));
+      if path.len() > MAX_DEPTH {
+        return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+      }
+      node = self.read_node(child)?;
+    }
+  }
+
+  /// Decodes the key stored in cell `idx` of the index page `node`.
+  pub(crate) fn index_key(
+    &mut self,
+    node: &BtreeNode,
+    idx: usize,
+  ) -> SqliteResult<Vec<Value>> {
+    let cell = &node.cells[idx];
+    let info = CellInfo::parse(cell, node.page_type, self.usable_size())?;
+    let payload = self.read_payload(cell, &info)?;
+    Record::decode_with_encoding(
+      &payload,
+      self.header().database_text_encoding(),
+    )
+  }
+}
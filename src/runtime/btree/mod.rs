@@ -1,2 +1,357 @@
-#[derive(Debug, Default)]
-pub(super) struct SqliteBtree(());
+//! # B-tree
+//!
+//!  The b-tree algorithm provides key/data storage with unique and ordered
+//! keys on page-oriented storage devices. SQLite uses two variants of b-trees.
+//! "Table b-trees" use a 64-bit signed integer key and store all data in the
+//! leaves. "Index b-trees" use arbitrary keys and store no data at all.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+mod balance;
+mod cell;
+mod cursor;
+mod freeblock;
+mod freelist;
+mod index;
+mod overflow;
+mod page;
+mod ptrmap;
+mod vacuum;
+
+use self::cell::{build_cell, set_overflow, CellInfo};
+use self::freeblock::{drop_cell, insert_cell};
+use crate::header::SqliteHeader;
+use crate::pager::{page::Page, SqlitePager};
+use crate::result::{SqliteError, SqliteResult};
+
+pub(crate) use self::page::BtreeNode;
+pub use self::{
+  cursor::BtreeCursor,
+  freeblock::BtreePageStats,
+  page::BtreePageType,
+  ptrmap::{PtrmapEntry, PtrmapType},
+  vacuum::AutoVacuum,
+};
+
+/// Access to the b-trees of a database: a handle borrowing the pager and the
+/// database header for the duration of a b-tree operation.
+#[derive(Debug)]
+pub struct SqliteBtree<'a> {
+  pager: &'a mut SqlitePager,
+  header: &'a mut SqliteHeader,
+}
+
+impl<'a> SqliteBtree<'a> {
+  pub(crate) fn new(
+    pager: &'a mut SqlitePager,
+    header: &'a mut SqliteHeader,
+  ) -> Self {
+    Self { pager, header }
+  }
+
+  pub fn header(&self) -> &SqliteHeader {
+    self.header
+  }
+
+  pub fn usable_size(&self) -> usize {
+    self.pager.usable_size()
+  }
+
+  /// Allocates the root page of a new, empty b-tree and returns its number.
+  pub fn create_btree(
+    &mut self,
+    page_type: BtreePageType,
+  ) -> SqliteResult<u32> {
+    let page_number = if self.has_ptrmap() {
+      self.allocate_root_page()?
+    } else {
+      self.allocate_page()?
+    };
+    self.write_node(&BtreeNode::new(page_number, page_type.leaf()))?;
+    self.set_ptrmap(page_number, PtrmapType::RootPage, 0)?;
+    debug!("B-tree created with root page [{page_number}].");
+    Ok(page_number)
+  }
+
+  /// Allocates the root page of a new, empty table b-tree.
+  pub fn create_table(&mut self) -> SqliteResult<u32> {
+    self.create_btree(BtreePageType::LeafTable)
+  }
+
+  /// Deletes the b-tree rooted at `root`, returning its pages to the
+  /// freelist. In auto-vacuum mode root pages are kept at the start of the
+  /// file: the root with the largest page number moves into the dropped
+  /// root's page, and its former page number is returned for the schema to
+  /// be updated.
+  pub fn drop_btree(&mut self, root: u32) -> SqliteResult<Option<u32>> {
+    self.clear_page(root, 0)?;
+    if !self.has_ptrmap() {
+      self.free_page(root)?;
+      debug!("B-tree rooted at page [{root}] dropped.");
+      return Ok(None);
+    }
+    let settings = self.header.incremental_vacuum_settings();
+    let mut largest_root = **settings.largest_root_btree_page();
+    let moved = if root == largest_root {
+      self.free_page(root)?;
+      None
+    } else {
+      let page = self.read_page(largest_root)?;
+      let node = BtreeNode::parse(root, &page, self.usable_size())?;
+      self.write_node(&node)?;
+      self.set_ptrmap(root, PtrmapType::RootPage, 0)?;
+      self.free_page(largest_root)?;
+      Some(largest_root)
+    };
+    largest_root -= 1;
+    while self.is_ptrmap_page(largest_root)
+      || largest_root == self.lock_byte_page()
+    {
+      largest_root -= 1;
+    }
+    self
+      .header
+      .incremental_vacuum_settings_mut()
+      .set_largest_root_btree_page(largest_root);
+    debug!("B-tree rooted at page [{root}] dropped.");
+    Ok(moved)
+  }
+
+  /// Frees the overflow chains of the cells of `page_number` and the pages
+  /// below it, in the order SQLite does: children before their parent.
+  fn clear_page(&mut self, page_number: u32, depth: usize) -> SqliteResult<()> {
+    if depth > MAX_DEPTH {
+      return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+    }
+    let node = self.read_node(page_number)?;
+    let usable_size = self.usable_size();
+    for (idx, cell) in node.cells.iter().enumerate() {
+      if !node.page_type.is_leaf() {
+        let child = node.child(idx)?;
+        self.clear_page(child, depth + 1)?;
+        self.free_page(child)?;
+      }
+      let info = CellInfo::parse(cell, node.page_type, usable_size)?;
+      self.free_overflow(&info)?;
+    }
+    if !node.page_type.is_leaf() {
+      let child = node.child(node.cells.len())?;
+      self.clear_page(child, depth + 1)?;
+      self.free_page(child)?;
+    }
+    Ok(())
+  }
+
+  /// Inserts the `payload` record under `rowid` in the table b-tree rooted at
+  /// `root`, replacing any existing entry with the same rowid.
+  pub fn insert(
+    &mut self,
+    root: u32,
+    rowid: i64,
+    payload: &[u8],
+  ) -> SqliteResult<()> {
+    let (path, mut leaf) = self.seek_table_leaf(root, rowid)?;
+    if leaf.page_type != BtreePageType::LeafTable {
+      return Err(SqliteError::Custom(format!(
+        "Page [{root}] is not the root of a table b-tree"
+      )));
+    }
+    let usable_size = self.usable_size();
+    let (idx, exists) = self.search_table_leaf(&leaf, rowid)?;
+    if exists {
+      let info =
+        CellInfo::parse(&leaf.cells[idx], leaf.page_type, usable_size)?;
+      self.free_overflow(&info)?;
+    }
+
+    let (mut cell, spilled) = build_cell(
+      BtreePageType::LeafTable,
+      None,
+      Some(rowid),
+      payload,
+      usable_size,
+    );
+    if !spilled.is_empty() {
+      let overflow = self.write_overflow(&spilled)?;
+      set_overflow(&mut cell, overflow);
+    }
+
+    let is_append = !exists && idx == leaf.cells.len();
+    if exists {
+      leaf.cells[idx] = cell;
+    } else {
+      leaf.cells.insert(idx, cell);
+    }
+    trace!("Rowid [{rowid}] inserted on page [{}].", leaf.page_number);
+    if leaf.fits(usable_size) {
+      return self.store_cell(&leaf, idx, exists);
+    }
+    self.balance(path, leaf, is_append)
+  }
+
+  /// Writes cell `idx` of `node` to its page in place, replacing the cell
+  /// previously at `idx` when `replaces` is set. The node must fit on its
+  /// page.
+  pub(crate) fn store_cell(
+    &mut self,
+    node: &BtreeNode,
+    idx: usize,
+    replaces: bool,
+  ) -> SqliteResult<()> {
+    let usable_size = self.usable_size();
+    let mut page = self.read_page(node.page_number)?;
+    if replaces {
+      drop_cell(&mut page, node.page_number, idx, usable_size)?;
+    }
+    if insert_cell(
+      &mut page,
+      node.page_number,
+      idx,
+      &node.cells[idx],
+      usable_size,
+    )? {
+      self.write_page(node.page_number, page)?;
+      let info =
+        CellInfo::parse(&node.cells[idx], node.page_type, usable_size)?;
+      match info.overflow {
+        Some(overflow) => {
+          self.set_ptrmap(overflow, PtrmapType::Overflow1, node.page_number)
+        }
+        None => Ok(()),
+      }
+    } else {
+      // The free space of the page disagrees with its cells: rebuild it.
+      self.write_node(node)
+    }
+  }
+
+  /// Deletes the entry with the given `rowid` from the table b-tree rooted at
+  /// `root`, releasing its overflow pages. Returns false when there is no
+  /// such entry.
+  pub fn delete(&mut self, root: u32, rowid: i64) -> SqliteResult<bool> {
+    let (path, leaf) = self.seek_table_leaf(root, rowid)?;
+    if leaf.page_type != BtreePageType::LeafTable {
+      return Err(SqliteError::Custom(format!(
+        "Page [{root}] is not the root of a table b-tree"
+      )));
+    }
+    let (idx, exists) = self.search_table_leaf(&leaf, rowid)?;
+    if !exists {
+      return Ok(false);
+    }
+    let usable_size = self.usable_size();
+    let info = CellInfo::parse(&leaf.cells[idx], leaf.page_type, usable_size)?;
+    self.free_overflow(&info)?;
+    trace!("Rowid [{rowid}] deleted from page [{}].", leaf.page_number);
+    self.remove_cell(path, leaf, idx)?;
+    Ok(true)
+  }
+
+  /// Removes cell `idx` of `node`. The space of the cell goes to the
+  /// freeblock list of the page unless the page is left underfull, in which
+  /// case it is balanced with its siblings.
+  pub(crate) fn remove_cell(
+    &mut self,
+    mut path: Vec<(BtreeNode, usize)>,
+    mut node: BtreeNode,
+    idx: usize,
+  ) -> SqliteResult<()> {
+    node.cells.remove(idx);
+    let usable_size = self.usable_size();
+    if path.is_empty() || !node.is_underfull(usable_size) {
+      let mut page = self.read_page(node.page_number)?;
+      drop_cell(&mut page, node.page_number, idx, usable_size)?;
+      self.write_page(node.page_number, page)?;
+      // Ancestors modified by the caller may have been left overfull.
+      return match path.pop() {
+        Some((parent, _)) => self.balance_ancestor(path, parent),
+        None => Ok(()),
+      };
+    }
+    self.balance(path, node, false)
+  }
+
+  /// Descends the table b-tree rooted at `root` to the leaf where `rowid`
+  /// belongs, returning the interior pages visited along with the index of
+  /// the child followed on each.
+  pub(crate) fn seek_table_leaf(
+    &mut self,
+    root: u32,
+    rowid: i64,
+  ) -> SqliteResult<(Vec<(BtreeNode, usize)>, BtreeNode)> {
+    let usable_size = self.usable_size();
+    let mut path = vec![];
+    let mut node = self.read_node(root)?;
+    while !node.page_type.is_leaf() {
+      if !node.page_type.is_table() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{root}] is not the root of a table b-tree"
+        )));
+      }
+      let idx = node.cells.partition_point(|cell| {
+        CellInfo::parse(cell, node.page_type, usable_size)
+          .ok()
+          .and_then(|info| info.rowid)
+          .is_some_and(|key| key < rowid)
+      });
+      let child = node.child(idx)?;
+      path.push((node, idx));
+      if path.len() > MAX_DEPTH {
+        return Err(SqliteError::Corrupt("B-tree is too deep".into()));
+      }
+      node = self.read_node(child)?;
+    }
+    Ok((path, node))
+  }
+
+  /// Position of `rowid` on a table leaf and whether it is already present.
+  fn search_table_leaf(
+    &self,
+    leaf: &BtreeNode,
+    rowid: i64,
+  ) -> SqliteResult<(usize, bool)> {
+    let usable_size = self.usable_size();
+    let rowids = leaf
+      .cells
+      .iter()
+      .map(|cell| {
+        CellInfo::parse(cell, leaf.page_type, usable_size)
+          .map(|info| info.rowid.unwrap_or_default())
+      })
+      .collect::<SqliteResult<Vec<i64>>>()?;
+    Ok(match rowids.binary_search(&rowid) {
+      Ok(idx) => (idx, true),
+      Err(idx) => (idx, false),
+    })
+  }
+
+  pub(crate) fn read_page(&mut self, page_number: u32) -> SqliteResult<Page> {
+    self.pager.read(page_number)
+  }
+
+  pub(crate) fn write_page(
+    &mut self,
+    page_number: u32,
+    page: Page,
+  ) -> SqliteResult<()> {
+    self.pager.write(page_number, page)
+  }
+
+  pub(crate) fn read_node(
+    &mut self,
+    page_number: u32,
+  ) -> SqliteResult<BtreeNode> {
+    let page = self.read_page(page_number)?;
+    BtreeNode::parse(page_number, &page, self.usable_size())
+  }
+
+  pub(crate) fn write_node(&mut self, node: &BtreeNode) -> SqliteResult<()> {
+    let mut page = self.read_page(node.page_number)?;
+    node.write_into(&mut page, self.usable_size())?;
+    self.write_page(node.page_number, page)?;
+    self.track_children(node)
+  }
+}
+
+/// Guard against reference cycles in corrupt files.
+const MAX_DEPTH: usize = 20;
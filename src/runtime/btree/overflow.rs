@@ -0,0 +1,85 @@
+//! # Cell Payload Overflow Pages
+//!
+//!  When the payload of a b-tree cell is too large for the b-tree page, the
+//! surplus is spilled onto overflow pages. Overflow pages form a linked list.
+//! The first four bytes of each overflow page are a big-endian integer which
+//! is the page number of the next page in the chain, or zero for the final
+//! page in the chain. The fifth byte through the last usable byte are used to
+//! hold overflow content.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#ovflpgs
+
+use super::cell::CellInfo;
+use super::page::read_u32;
+use super::ptrmap::{PtrmapEntry, PtrmapType};
+use super::SqliteBtree;
+use crate::result::{SqliteError, SqliteResult};
+
+impl SqliteBtree<'_> {
+  /// Writes `spilled` to a new chain of overflow pages and returns the number
+  /// of the first page.
+  pub(crate) fn write_overflow(&mut self, spilled: &[u8]) -> SqliteResult<u32> {
+    let chunk_len = self.pager.usable_size() - 4;
+    let pages = spilled
+      .chunks(chunk_len)
+      .map(|_| self.allocate_page())
+      .collect::<SqliteResult<Vec<u32>>>()?;
+    for (idx, chunk) in spilled.chunks(chunk_len).enumerate() {
+      let mut page = self.read_page(pages[idx])?;
+      let next = pages.get(idx + 1).copied().unwrap_or(0);
+      let data = page.raw_data_mut();
+      data[0..4].copy_from_slice(&next.to_be_bytes());
+      data[4..4 + chunk.len()].copy_from_slice(chunk);
+      self.write_page(pages[idx], page)?;
+    }
+    // The first page points back to the b-tree page the cell ends up on.
+    let entries: Vec<(u32, PtrmapEntry)> = pages
+      .windows(2)
+      .map(|pair| (pair[1], PtrmapEntry::new(PtrmapType::Overflow2, pair[0])))
+      .collect();
+    self.set_ptrmap_entries(&entries)?;
+    Ok(pages[0])
+  }
+
+  /// Reads the full payload of `cell`, following its overflow chain.
+  pub(crate) fn read_payload(
+    &mut self,
+    cell: &[u8],
+    info: &CellInfo,
+  ) -> SqliteResult<Vec<u8>> {
+    let mut payload = Vec::with_capacity(info.payload_len);
+    payload.extend_from_slice(
+      &cell[info.local_offset..info.local_offset + info.local_len],
+    );
+    let chunk_len = self.pager.usable_size() - 4;
+    let mut next = info.overflow.unwrap_or(0);
+    while payload.len() < info.payload_len {
+      if next == 0 {
+        return Err(SqliteError::Corrupt("Overflow chain too short".into()));
+      }
+      let page = self.read_page(next)?;
+      let len = chunk_len.min(info.payload_len - payload.len());
+      payload.extend_from_slice(&page.raw_data()[4..4 + len]);
+      next = read_u32(page.raw_data(), 0);
+    }
+    Ok(payload)
+  }
+
+  /// Returns every page of the overflow chain of `info` to the freelist.
+  pub(crate) fn free_overflow(&mut self, info: &CellInfo) -> SqliteResult<()> {
+    let Some(first) = info.overflow else {
+      return Ok(());
+    };
+    let chunk_len = self.pager.usable_size() - 4;
+    let mut remaining = (info.payload_len - info.local_len).div_ceil(chunk_len);
+    let mut next = first;
+    while next != 0 && remaining > 0 {
+      let page = self.read_page(next)?;
+      let following = read_u32(page.raw_data(), 0);
+      self.free_page(next)?;
+      next = following;
+      remaining -= 1;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,270 @@
+//! # B-tree Pages
+//!
+//!  A b-tree page is divided into regions in the following order:
+//!
+//! 1. The 100-byte database file header (found on page 1 only)
+//! 2. The 8 or 12 byte b-tree page header
+//! 3. The cell pointer array
+//! 4. Unallocated space
+//! 5. The cell content area
+//! 6. The reserved region.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+use super::cell::{allocated_size, CellInfo};
+use crate::header::SqliteHeader;
+use crate::pager::page::Page;
+use crate::result::{SqliteError, SqliteResult};
+
+/// The one-byte flag at offset 0 indicating the b-tree page type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtreePageType {
+  /// A value of 2 (0x02) means the page is an interior index b-tree page.
+  InteriorIndex,
+  /// A value of 5 (0x05) means the page is an interior table b-tree page.
+  InteriorTable,
+  /// A value of 10 (0x0a) means the page is a leaf index b-tree page.
+  LeafIndex,
+  /// A value of 13 (0x0d) means the page is a leaf table b-tree page.
+  LeafTable,
+}
+
+impl BtreePageType {
+  pub fn is_leaf(&self) -> bool {
+    matches!(self, Self::LeafIndex | Self::LeafTable)
+  }
+
+  pub fn is_table(&self) -> bool {
+    matches!(self, Self::InteriorTable | Self::LeafTable)
+  }
+
+  /// The b-tree page header is 8 bytes in size for leaf pages and 12 bytes
+  /// for interior pages.
+  pub fn header_size(&self) -> usize {
+    if self.is_leaf() {
+      8
+    } else {
+      12
+    }
+  }
+
+  /// Interior page type of the same b-tree kind.
+  pub fn interior(&self) -> Self {
+    if self.is_table() {
+      Self::InteriorTable
+    } else {
+      Self::InteriorIndex
+    }
+  }
+
+  /// Leaf page type of the same b-tree kind.
+  pub fn leaf(&self) -> Self {
+    if self.is_table() {
+      Self::LeafTable
+    } else {
+      Self::LeafIndex
+    }
+  }
+}
+
+impl TryFrom<u8> for BtreePageType {
+  type Error = SqliteError;
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    match value {
+      0x02 => Ok(Self::InteriorIndex),
+      0x05 => Ok(Self::InteriorTable),
+      0x0a => Ok(Self::LeafIndex),
+      0x0d => Ok(Self::LeafTable),
+      _ => Err(SqliteError::Corrupt(format!(
+        "Invalid b-tree page type [{value}]"
+      ))),
+    }
+  }
+}
+
+impl From<&BtreePageType> for u8 {
+  fn from(value: &BtreePageType) -> Self {
+    match value {
+      BtreePageType::InteriorIndex => 0x02,
+      BtreePageType::InteriorTable => 0x05,
+      BtreePageType::LeafIndex => 0x0a,
+      BtreePageType::LeafTable => 0x0d,
+    }
+  }
+}
+
+/// Offset of the b-tree page header: page 1 starts with the database file
+/// header.
+pub(crate) fn header_offset(page_number: u32) -> usize {
+  if page_number == 1 {
+    SqliteHeader::LENGTH_BYTES
+  } else {
+    0
+  }
+}
+
+/// A b-tree page decoded into its cells.
+///
+///  Nodes are edited in memory and serialized back into a fully defragmented
+/// page, which is how the balance algorithm rebuilds the pages it touches.
+#[derive(Debug, Clone)]
+pub(crate) struct BtreeNode {
+  pub(crate) page_number: u32,
+  pub(crate) page_type: BtreePageType,
+  /// Raw bytes of each cell, in key order.
+  pub(crate) cells: Vec<Vec<u8>>,
+  /// The right-most pointer, present on interior pages only.
+  pub(crate) right_pointer: Option<u32>,
+}
+
+impl BtreeNode {
+  pub(crate) fn new(page_number: u32, page_type: BtreePageType) -> Self {
+    let right_pointer = (!page_type.is_leaf()).then_some(0);
+    Self {
+      page_number,
+      page_type,
+      cells: vec![],
+      right_pointer,
+    }
+  }
+
+  pub(crate) fn parse(
+    page_number: u32,
+    page: &Page,
+    usable_size: usize,
+  ) -> SqliteResult<Self> {
+    let data = page.raw_data();
+    let offset = header_offset(page_number);
+    let page_type = BtreePageType::try_from(data[offset])?;
+    let cell_count = read_u16(data, offset + 3);
+    let right_pointer =
+      (!page_type.is_leaf()).then(|| read_u32(data, offset + 8));
+    let pointers_start = offset + page_type.header_size();
+    let mut cells = Vec::with_capacity(cell_count);
+    for idx in 0..cell_count {
+      let cell_offset = read_u16(data, pointers_start + 2 * idx);
+      if cell_offset >= usable_size {
+        return Err(SqliteError::Corrupt(format!(
+          "Cell pointer out of bounds on page [{page_number}]"
+        )));
+      }
+      let info = CellInfo::parse(
+        &data[cell_offset..usable_size],
+        page_type,
+        usable_size,
+      )?;
+      cells.push(data[cell_offset..cell_offset + info.size].to_vec());
+    }
+    Ok(Self {
+      page_number,
+      page_type,
+      cells,
+      right_pointer,
+    })
+  }
+
+  /// Child page at `idx`: the left child of cell `idx` or the right-most
+  /// pointer when `idx` equals the number of cells.
+  pub(crate) fn child(&self, idx: usize) -> SqliteResult<u32> {
+    match self.cells.get(idx) {
+      Some(cell) => Ok(read_u32(cell, 0)),
+      None if idx == self.cells.len() => self
+        .right_pointer
+        .ok_or(SqliteError::Corrupt("Leaf page has no children".into())),
+      None => Err(SqliteError::Corrupt("Child index out of range".into())),
+    }
+  }
+
+  pub(crate) fn set_child(&mut self, idx: usize, child: u32) {
+    match self.cells.get_mut(idx) {
+      Some(cell) => cell[0..4].copy_from_slice(&child.to_be_bytes()),
+      None => self.right_pointer = Some(child),
+    }
+  }
+
+  pub(crate) fn child_count(&self) -> usize {
+    if self.page_type.is_leaf() {
+      0
+    } else {
+      self.cells.len() + 1
+    }
+  }
+
+  /// Bytes used by the header, the cell pointer array and the cells.
+  pub(crate) fn used_bytes(&self) -> usize {
+    header_offset(self.page_number)
+      + self.page_type.header_size()
+      + self
+        .cells
+        .iter()
+        .map(|cell| allocated_size(cell) + 2)
+        .sum::<usize>()
+  }
+
+  pub(crate) fn fits(&self, usable_size: usize) -> bool {
+    self.used_bytes() <= usable_size
+  }
+
+  /// Bytes left unused once the node is written.
+  pub(crate) fn free_bytes(&self, usable_size: usize) -> usize {
+    usable_size.saturating_sub(self.used_bytes())
+  }
+
+  /// Whether the node is mostly empty and should be merged with its siblings:
+  /// SQLite balances a page once more than two thirds of it are free.
+  pub(crate) fn is_underfull(&self, usable_size: usize) -> bool {
+    self.free_bytes(usable_size) > usable_size * 2 / 3
+  }
+
+  /// Serializes the node into `page`, keeping the database header of page 1.
+  pub(crate) fn write_into(
+    &self,
+    page: &mut Page,
+    usable_size: usize,
+  ) -> SqliteResult<()> {
+    if !self.fits(usable_size) {
+      return Err(SqliteError::Custom(format!(
+        "Cells do not fit on page [{}]",
+        self.page_number
+      )));
+    }
+    let offset = header_offset(self.page_number);
+    let data = page.raw_data_mut();
+    data[offset..usable_size].fill(0);
+    data[offset] = u8::from(&self.page_type);
+    write_u16(data, offset + 3, self.cells.len());
+
+    let pointers_start = offset + self.page_type.header_size();
+    let mut content_start = usable_size;
+    for (idx, cell) in self.cells.iter().enumerate() {
+      content_start -= allocated_size(cell);
+      data[content_start..content_start + cell.len()].copy_from_slice(cell);
+      write_u16(data, pointers_start + 2 * idx, content_start);
+    }
+    // A zero value for the content area start is interpreted as 65536.
+    write_u16(data, offset + 5, content_start & 0xffff);
+    if let Some(right_pointer) = self.right_pointer {
+      data[offset + 8..offset + 12]
+        .copy_from_slice(&right_pointer.to_be_bytes());
+    }
+    Ok(())
+  }
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> usize {
+  usize::from(u16::from_be_bytes([data[offset], data[offset + 1]]))
+}
+
+pub(crate) fn write_u16(data: &mut [u8], offset: usize, value: usize) {
+  data[offset..offset + 2].copy_from_slice(&(value as u16).to_be_bytes());
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+  u32::from_be_bytes([
+    data[offset],
+    data[offset + 1],
+    data[offset + 2],
+    data[offset + 3],
+  ])
+}
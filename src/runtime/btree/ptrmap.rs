@@ -0,0 +1,332 @@
+//! # Pointer Map or Ptrmap Pages
+//!
+//!  Pointer map or ptrmap pages are extra pages inserted into the database to
+//! make the operation of auto_vacuum and incremental_vacuum modes more
+//! efficient. Other page types in the database typically have pointers from
+//! parent to child. For example, an interior b-tree page contains pointers to
+//! its child b-tree pages and an overflow chain has a pointer from earlier to
+//! later links in the chain. A ptrmap page contains linkage information going
+//! in the opposite direction, from child to parent.
+//!
+//!  Ptrmap pages must exist in any database file which has a non-zero largest
+//! root b-tree page value at offset 52 in the database header. The first
+//! ptrmap page will be page 2. A ptrmap page consists of an array of 5-byte
+//! entries. Let J be the number of 5-byte entries that will fit in the usable
+//! space of a page. (In other words, J=U/5.) The first ptrmap page will
+//! contain back pointer information for pages 3 through J+2, inclusive. The
+//! second pointer map page will be on page J+3 and that ptrmap page will
+//! provide back pointer information for pages J+4 through 2*J+3 inclusive.
+//! And so forth for the entire database file.
+//!
+//!  Each 5-byte ptrmap entry consists of one byte of "page type" information
+//! followed by a 4-byte big-endian page number.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#pointer_map_or_ptrmap_pages
+
+use super::cell::CellInfo;
+use super::page::{read_u32, BtreeNode};
+use super::SqliteBtree;
+use crate::pager::page::Page;
+use crate::result::{SqliteError, SqliteResult};
+use std::collections::BTreeMap;
+
+/// Size of an entry of a ptrmap page.
+const ENTRY_SIZE: usize = 5;
+
+/// Page type of a ptrmap entry, telling what the parent page number is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtrmapType {
+  /// A b-tree root page. The page number should be zero.
+  RootPage,
+  /// A freelist page. The page number should be zero.
+  FreePage,
+  /// The first page of a cell payload overflow chain. The page number is the
+  /// b-tree page that contains the cell whose content has overflowed.
+  Overflow1,
+  /// A page in an overflow chain other than the first page. The page number
+  /// is the prior page of the overflow chain.
+  Overflow2,
+  /// A non-root b-tree page. The page number is the parent b-tree page.
+  Btree,
+}
+
+impl TryFrom<u8> for PtrmapType {
+  type Error = SqliteError;
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(Self::RootPage),
+      2 => Ok(Self::FreePage),
+      3 => Ok(Self::Overflow1),
+      4 => Ok(Self::Overflow2),
+      5 => Ok(Self::Btree),
+      _ => Err(SqliteError::Corrupt(format!(
+        "Invalid ptrmap type [{value}]"
+      ))),
+    }
+  }
+}
+
+impl From<PtrmapType> for u8 {
+  fn from(value: PtrmapType) -> Self {
+    match value {
+      PtrmapType::RootPage => 1,
+      PtrmapType::FreePage => 2,
+      PtrmapType::Overflow1 => 3,
+      PtrmapType::Overflow2 => 4,
+      PtrmapType::Btree => 5,
+    }
+  }
+}
+
+/// Back pointer of a page, as kept on ptrmap pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtrmapEntry {
+  page_type: PtrmapType,
+  parent: u32,
+}
+
+impl PtrmapEntry {
+  pub fn new(page_type: PtrmapType, parent: u32) -> Self {
+    Self { page_type, parent }
+  }
+
+  pub fn page_type(&self) -> PtrmapType {
+    self.page_type
+  }
+
+  /// Parent page number, zero for root and freelist pages.
+  pub fn parent(&self) -> u32 {
+    self.parent
+  }
+}
+
+impl SqliteBtree<'_> {
+  /// Whether the database file contains ptrmap pages.
+  pub fn has_ptrmap(&self) -> bool {
+    **self
+      .header
+      .incremental_vacuum_settings()
+      .largest_root_btree_page()
+      != 0
+  }
+
+  /// Number of the ptrmap page holding the entry of `page_number`.
+  pub(crate) fn ptrmap_page(&self, page_number: u32) -> u32 {
+    if page_number < 2 {
+      return 0;
+    }
+    let pages_per_map = (self.usable_size() / ENTRY_SIZE) as u32 + 1;
+    let map_page = (page_number - 2) / pages_per_map * pages_per_map + 2;
+    if map_page == self.lock_byte_page() {
+      map_page + 1
+    } else {
+      map_page
+    }
+  }
+
+  /// Whether `page_number` is a ptrmap page, when the file has them.
+  pub(crate) fn is_ptrmap_page(&self, page_number: u32) -> bool {
+    self.has_ptrmap() && self.ptrmap_page(page_number) == page_number
+  }
+
+  /// Back pointer of `page_number`. `None` when the file has no ptrmap
+  /// pages, or for pages without an entry: page 1, the ptrmap pages
+  /// themselves and the lock-byte page.
+  pub fn ptrmap_entry(
+    &mut self,
+    page_number: u32,
+  ) -> SqliteResult<Option<PtrmapEntry>> {
+    let map_page = self.ptrmap_page(page_number);
+    if !self.has_ptrmap()
+      || map_page == 0
+      || map_page == page_number
+      || page_number == self.lock_byte_page()
+    {
+      return Ok(None);
+    }
+    let page = self.read_page(map_page)?;
+    let offset = ENTRY_SIZE * (page_number - map_page - 1) as usize;
+    let data = page.raw_data();
+    let page_type = PtrmapType::try_from(data[offset]).map_err(|_| {
+      SqliteError::Corrupt(format!("No ptrmap entry for page [{page_number}]"))
+    })?;
+    Ok(Some(PtrmapEntry::new(
+      page_type,
+      read_u32(data, offset + 1),
+    )))
+  }
+
+  /// Back pointers of every page of the file that has one.
+  pub fn ptrmap_entries(&mut self) -> SqliteResult<Vec<(u32, PtrmapEntry)>> {
+    let mut entries = vec![];
+    for page_number in 2..=self.pager.page_count() {
+      if let Some(entry) = self.ptrmap_entry(page_number)? {
+        entries.push((page_number, entry));
+      }
+    }
+    Ok(entries)
+  }
+
+  /// Records back pointers, reading and writing each ptrmap page once. Does
+  /// nothing when the file has no ptrmap pages.
+  pub(crate) fn set_ptrmap_entries(
+    &mut self,
+    entries: &[(u32, PtrmapEntry)],
+  ) -> SqliteResult<()> {
+    if !self.has_ptrmap() {
+      return Ok(());
+    }
+    let mut by_map_page: BTreeMap<u32, Vec<(u32, PtrmapEntry)>> =
+      BTreeMap::new();
+    for (page_number, entry) in entries.iter().copied() {
+      let map_page = self.ptrmap_page(page_number);
+      if map_page == 0 || map_page == page_number {
+        return Err(SqliteError::Corrupt(format!(
+          "Page [{page_number}] cannot have a ptrmap entry"
+        )));
+      }
+      by_map_page
+        .entry(map_page)
+        .or_default()
+        .push((page_number, entry));
+    }
+    for (map_page, entries) in by_map_page {
+      let mut page = self.read_page(map_page)?;
+      let mut is_changed = false;
+      for (page_number, entry) in entries {
+        let offset = ENTRY_SIZE * (page_number - map_page - 1) as usize;
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0] = u8::from(entry.page_type);
+        bytes[1..].copy_from_slice(&entry.parent.to_be_bytes());
+        let data = page.raw_data_mut();
+        if data[offset..offset + ENTRY_SIZE] != bytes {
+          data[offset..offset + ENTRY_SIZE].copy_from_slice(&bytes);
+          is_changed = true;
+        }
+      }
+      if is_changed {
+        self.write_page(map_page, page)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn set_ptrmap(
+    &mut self,
+    page_number: u32,
+    page_type: PtrmapType,
+    parent: u32,
+  ) -> SqliteResult<()> {
+    self
+      .set_ptrmap_entries(&[(page_number, PtrmapEntry::new(page_type, parent))])
+  }
+
+  /// Points the children of `node` and the overflow chains of its cells back
+  /// to it.
+  pub(crate) fn track_children(
+    &mut self,
+    node: &BtreeNode,
+  ) -> SqliteResult<()> {
+    if !self.has_ptrmap() {
+      return Ok(());
+    }
+    let usable_size = self.usable_size();
+    let mut entries = vec![];
+    for idx in 0..node.child_count() {
+      let entry = PtrmapEntry::new(PtrmapType::Btree, node.page_number);
+      entries.push((node.child(idx)?, entry));
+    }
+    for cell in node.cells.iter() {
+      let info = CellInfo::parse(cell, node.page_type, usable_size)?;
+      if let Some(overflow) = info.overflow {
+        let entry = PtrmapEntry::new(PtrmapType::Overflow1, node.page_number);
+        entries.push((overflow, entry));
+      }
+    }
+    self.set_ptrmap_entries(&entries)
+  }
+
+  /// Moves the content of page `from` to the unused page `to`, updating the
+  /// pointers to it and its back pointers. Root and freelist pages cannot be
+  /// moved.
+  pub(crate) fn relocate_page(
+    &mut self,
+    from: u32,
+    to: u32,
+  ) -> SqliteResult<()> {
+    let entry =
+      self
+        .ptrmap_entry(from)?
+        .ok_or(SqliteError::Corrupt(format!(
+          "No ptrmap entry for page [{from}]"
+        )))?;
+    let page = self.read_page(from)?;
+    self.write_page(to, page.clone())?;
+    match entry.page_type {
+      PtrmapType::RootPage | PtrmapType::FreePage => {
+        return Err(SqliteError::Corrupt(format!(
+          "Cannot relocate page [{from}] of type {:?}",
+          entry.page_type
+        )));
+      }
+      PtrmapType::Btree => {
+        let mut parent = self.read_node(entry.parent)?;
+        let idx = (0..parent.child_count())
+          .find(|idx| parent.child(*idx).is_ok_and(|child| child == from))
+          .ok_or(SqliteError::Corrupt(format!(
+            "Page [{from}] is not a child of page [{}]",
+            entry.parent
+          )))?;
+        parent.set_child(idx, to);
+        self.write_node(&parent)?;
+        let node = BtreeNode::parse(to, &page, self.usable_size())?;
+        self.track_children(&node)?;
+      }
+      PtrmapType::Overflow1 => {
+        let usable_size = self.usable_size();
+        let mut parent = self.read_node(entry.parent)?;
+        let mut is_found = false;
+        for cell in parent.cells.iter_mut() {
+          let info = CellInfo::parse(cell, parent.page_type, usable_size)?;
+          if info.overflow == Some(from) {
+            let offset = info.size - 4;
+            cell[offset..offset + 4].copy_from_slice(&to.to_be_bytes());
+            is_found = true;
+            break;
+          }
+        }
+        if !is_found {
+          return Err(SqliteError::Corrupt(format!(
+            "No cell of page [{}] overflows to page [{from}]",
+            entry.parent
+          )));
+        }
+        self.write_node(&parent)?;
+        self.track_overflow_successor(&page, to)?;
+      }
+      PtrmapType::Overflow2 => {
+        let mut previous = self.read_page(entry.parent)?;
+        previous.raw_data_mut()[0..4].copy_from_slice(&to.to_be_bytes());
+        self.write_page(entry.parent, previous)?;
+        self.track_overflow_successor(&page, to)?;
+      }
+    }
+    self.set_ptrmap_entries(&[(to, entry)])?;
+    trace!("Page [{from}] relocated to page [{to}].");
+    Ok(())
+  }
+
+  /// Points the page following the overflow page `page`, now numbered
+  /// `page_number`, back to it.
+  fn track_overflow_successor(
+    &mut self,
+    page: &Page,
+    page_number: u32,
+  ) -> SqliteResult<()> {
+    match read_u32(page.raw_data(), 0) {
+      0 => Ok(()),
+      next => self.set_ptrmap(next, PtrmapType::Overflow2, page_number),
+    }
+  }
+}
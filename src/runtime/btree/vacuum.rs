@@ -0,0 +1,240 @@
+//! # Auto-vacuum
+//!
+//!  When the auto-vacuum mode is 1 or "full", the freelist pages are moved to
+//! the end of the database file and the database file is truncated to remove
+//! the freelist pages at every transaction commit. When the value of
+//! auto-vacuum is 2 or "incremental" then the additional information needed
+//! to do auto-vacuuming is stored in the database file but auto-vacuuming
+//! does not occur automatically at each commit as it does with
+//! auto_vacuum=full. In incremental mode, the separate incremental_vacuum
+//! pragma must be invoked to cause the auto-vacuum to occur.
+//!
+//!  Pages are moved using the back pointers of the ptrmap pages: the last
+//! page of the file is copied to a free page and every pointer to it is
+//! updated, then the file is shortened by one page.
+//!
+//! *Reference:* https://www.sqlite.org/pragma.html#pragma_auto_vacuum
+
+use super::page::read_u32;
+use super::ptrmap::PtrmapType;
+use super::SqliteBtree;
+use crate::pager::page::Page;
+use crate::result::{SqliteError, SqliteResult};
+
+/// How freed pages are given back to the file system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AutoVacuum {
+  /// Freed pages stay on the freelist for reuse.
+  #[default]
+  None,
+  /// The file is truncated at every commit.
+  Full,
+  /// The file is truncated on request, by [`SqliteBtree::incremental_vacuum`].
+  Incremental,
+}
+
+impl SqliteBtree<'_> {
+  pub fn auto_vacuum(&self) -> AutoVacuum {
+    let settings = self.header.incremental_vacuum_settings();
+    match (
+      self.has_ptrmap(),
+      bool::from(settings.incremental_vacuum_mode()),
+    ) {
+      (false, _) => AutoVacuum::None,
+      (true, false) => AutoVacuum::Full,
+      (true, true) => AutoVacuum::Incremental,
+    }
+  }
+
+  /// Changes the auto-vacuum mode. Turning auto-vacuum on or off changes the
+  /// layout of the file and is only possible before any table is created.
+  pub fn set_auto_vacuum(&mut self, mode: AutoVacuum) -> SqliteResult<()> {
+    let has_ptrmap = mode != AutoVacuum::None;
+    if has_ptrmap != self.has_ptrmap() {
+      if self.pager.page_count() > 1 {
+        return Err(SqliteError::Custom(
+          "Auto-vacuum can only be turned on or off before any table is \
+           created"
+            .into(),
+        ));
+      }
+      self
+        .header
+        .incremental_vacuum_settings_mut()
+        .set_largest_root_btree_page(u32::from(has_ptrmap));
+    }
+    self
+      .header
+      .incremental_vacuum_settings_mut()
+      .set_incremental_vacuum_mode(mode == AutoVacuum::Incremental);
+    // Stage page 1 for the header change to be committed.
+    let first = self.read_page(1)?;
+    self.write_page(1, first)?;
+    debug!("Auto-vacuum set to {mode:?}.");
+    Ok(())
+  }
+
+  /// Allocates the page following the largest root page for a new root, as
+  /// roots must not be moved by vacuuming. The page found there is moved away
+  /// when in use.
+  pub(crate) fn allocate_root_page(&mut self) -> SqliteResult<u32> {
+    let settings = self.header.incremental_vacuum_settings();
+    let mut root = **settings.largest_root_btree_page() + 1;
+    while self.is_ptrmap_page(root) || root == self.lock_byte_page() {
+      root += 1;
+    }
+    if root > self.pager.page_count() {
+      let page_number = self.append_page()?;
+      if page_number != root {
+        return Err(SqliteError::Corrupt(format!(
+          "Largest root page [{}] past the end of the file",
+          root - 1
+        )));
+      }
+    } else if !self.take_free_page(root)? {
+      let destination = self.allocate_page()?;
+      self.relocate_page(root, destination)?;
+    }
+    self.write_page(root, Page::new(self.pager.page_size().clone()))?;
+    self
+      .header
+      .incremental_vacuum_settings_mut()
+      .set_largest_root_btree_page(root);
+    Ok(root)
+  }
+
+  /// Removes up to `limit` pages from the freelist, or the whole freelist
+  /// when `None`, truncating the file accordingly. Returns the number of
+  /// freelist pages removed. Does nothing unless the file has ptrmap pages.
+  pub fn incremental_vacuum(
+    &mut self,
+    limit: Option<u32>,
+  ) -> SqliteResult<u32> {
+    if !self.has_ptrmap() {
+      return Ok(0);
+    }
+    let mut removed = 0;
+    while limit.map_or(true, |limit| removed < limit)
+      && **self.header.freelist_pages().total() > 0
+    {
+      let last = self.pager.page_count();
+      if last <= 1 {
+        return Err(SqliteError::Corrupt("Freelist larger than file".into()));
+      }
+      if !self.take_free_page(last)? {
+        let destination = self
+          .pop_freelist()?
+          .ok_or(SqliteError::Corrupt("Freelist is empty".into()))?;
+        self.relocate_page(last, destination)?;
+      }
+      self.pager.truncate(last - 1);
+      self.trim_unused_pages();
+      removed += 1;
+    }
+    if removed > 0 {
+      debug!(
+        "Incremental vacuum removed [{removed}] pages, [{}] left.",
+        self.pager.page_count()
+      );
+    }
+    Ok(removed)
+  }
+
+  /// Drops the ptrmap and lock-byte pages left at the end of the file.
+  fn trim_unused_pages(&mut self) {
+    loop {
+      let last = self.pager.page_count();
+      if last > 1
+        && (self.is_ptrmap_page(last) || last == self.lock_byte_page())
+      {
+        self.pager.truncate(last - 1);
+      } else {
+        return;
+      }
+    }
+  }
+
+  /// Removes `page_number` from the freelist, wherever it is. Returns false
+  /// when the page is not free.
+  pub(crate) fn take_free_page(
+    &mut self,
+    page_number: u32,
+  ) -> SqliteResult<bool> {
+    if self.has_ptrmap() {
+      // The ptrmap tells whether the page is free without walking the list.
+      let entry = self.ptrmap_entry(page_number)?;
+      if entry.is_some_and(|entry| entry.page_type() != PtrmapType::FreePage) {
+        return Ok(false);
+      }
+    }
+    let mut previous_trunk = 0;
+    let mut trunk_number = **self.header.freelist_pages().first();
+    let mut visited = 0;
+    while trunk_number != 0 {
+      visited += 1;
+      if visited > self.pager.page_count() {
+        return Err(SqliteError::Corrupt("Freelist cycle".into()));
+      }
+      let mut trunk = self.read_page(trunk_number)?;
+      let data = trunk.raw_data_mut();
+      let next_trunk = read_u32(data, 0);
+      let leaf_count = read_u32(data, 4) as usize;
+      if trunk_number == page_number {
+        // A trunk with leaves hands its list over to its last leaf.
+        let replacement = match leaf_count {
+          0 => next_trunk,
+          _ => {
+            let offset = 8 + 4 * (leaf_count - 1);
+            let leaf = read_u32(data, offset);
+            data[offset..offset + 4].fill(0);
+            data[4..8].copy_from_slice(&(leaf_count as u32 - 1).to_be_bytes());
+            self.write_page(leaf, trunk)?;
+            leaf
+          }
+        };
+        self.set_next_trunk(previous_trunk, replacement)?;
+        self.decrement_freelist_total();
+        return Ok(true);
+      }
+      let position =
+        (0..leaf_count).find(|idx| read_u32(data, 8 + 4 * idx) == page_number);
+      if let Some(position) = position {
+        let last_offset = 8 + 4 * (leaf_count - 1);
+        let last_leaf = read_u32(data, last_offset);
+        data[8 + 4 * position..12 + 4 * position]
+          .copy_from_slice(&last_leaf.to_be_bytes());
+        data[last_offset..last_offset + 4].fill(0);
+        data[4..8].copy_from_slice(&(leaf_count as u32 - 1).to_be_bytes());
+        self.write_page(trunk_number, trunk)?;
+        self.decrement_freelist_total();
+        return Ok(true);
+      }
+      previous_trunk = trunk_number;
+      trunk_number = next_trunk;
+    }
+    Ok(false)
+  }
+
+  /// Links `trunk_number` after `previous_trunk`, or first when zero.
+  fn set_next_trunk(
+    &mut self,
+    previous_trunk: u32,
+    trunk_number: u32,
+  ) -> SqliteResult<()> {
+    if previous_trunk == 0 {
+      self.header.freelist_pages_mut().set_first(trunk_number);
+      return Ok(());
+    }
+    let mut previous = self.read_page(previous_trunk)?;
+    previous.raw_data_mut()[0..4].copy_from_slice(&trunk_number.to_be_bytes());
+    self.write_page(previous_trunk, previous)
+  }
+
+  fn decrement_freelist_total(&mut self) {
+    let total = **self.header.freelist_pages().total();
+    self
+      .header
+      .freelist_pages_mut()
+      .set_total(total.saturating_sub(1));
+  }
+}
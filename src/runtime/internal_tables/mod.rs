@@ -1,2 +1,3 @@
 pub(super) mod sqlite_master;
 pub(super) mod sqlite_sequence;
+pub(super) mod sqlite_stat1;
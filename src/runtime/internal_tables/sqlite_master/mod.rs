@@ -1,2 +1,85 @@
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{BtreeCursor, Record, SqliteBtree, SqliteSchema};
+
+/// The schema table, whose b-tree is always rooted at page 1.
+///
+/// *Reference:* https://www.sqlite.org/schematab.html
 #[derive(Debug, Default)]
-pub(super) struct SqliteMaster(());
+pub(crate) struct SqliteMaster {
+  entries: Vec<SqliteSchema>,
+}
+
+impl SqliteMaster {
+  pub(crate) const ROOT_PAGE: u32 = 1;
+  /// The schema table goes by both names.
+  pub(crate) const NAMES: [&'static str; 2] =
+    ["sqlite_schema", "sqlite_master"];
+  pub(crate) const SQL: &'static str = "CREATE TABLE sqlite_schema(type text, \
+    name text, tbl_name text, rootpage integer, sql text)";
+
+  /// Reads every row of the schema table.
+  pub(crate) fn read(btree: &mut SqliteBtree<'_>) -> SqliteResult<Self> {
+    let mut entries = vec![];
+    let mut cursor = BtreeCursor::new(Self::ROOT_PAGE);
+    let mut has_row = cursor.first(btree)?;
+    while has_row {
+      let entry = SqliteSchema::from_record(cursor.record(btree)?)
+        .ok_or(SqliteError::Corrupt("Malformed sqlite_schema row".into()))?;
+      entries.push(entry);
+      has_row = cursor.next(btree)?;
+    }
+    Ok(Self { entries })
+  }
+
+  /// Appends `entry` to the schema table, after its last row.
+  pub(crate) fn insert(
+    btree: &mut SqliteBtree<'_>,
+    entry: &SqliteSchema,
+  ) -> SqliteResult<()> {
+    let mut cursor = BtreeCursor::new(Self::ROOT_PAGE);
+    let rowid = match cursor.last(btree)? {
+      true => cursor.rowid(btree)? + 1,
+      false => 1,
+    };
+    let record = Record::encode(&entry.to_record());
+    btree.insert(Self::ROOT_PAGE, rowid, &record)
+  }
+
+  /// Passes every row of the schema table through `update`, which returns
+  /// the new content of the row or `None` to delete it.
+  pub(crate) fn update(
+    btree: &mut SqliteBtree<'_>,
+    mut update: impl FnMut(&SqliteSchema) -> Option<SqliteSchema>,
+  ) -> SqliteResult<()> {
+    let mut rows = vec![];
+    let mut cursor = BtreeCursor::new(Self::ROOT_PAGE);
+    let mut has_row = cursor.first(btree)?;
+    while has_row {
+      let entry = SqliteSchema::from_record(cursor.record(btree)?)
+        .ok_or(SqliteError::Corrupt("Malformed sqlite_schema row".into()))?;
+      rows.push((cursor.rowid(btree)?, entry));
+      has_row = cursor.next(btree)?;
+    }
+    for (rowid, entry) in rows {
+      match update(&entry) {
+        Some(updated) if updated == entry => {}
+        Some(updated) => {
+          let record = Record::encode(&updated.to_record());
+          btree.insert(Self::ROOT_PAGE, rowid, &record)?;
+        }
+        None => {
+          btree.delete(Self::ROOT_PAGE, rowid)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn entries(&self) -> &[SqliteSchema] {
+    &self.entries
+  }
+
+  pub(crate) fn into_entries(self) -> Vec<SqliteSchema> {
+    self.entries
+  }
+}
@@ -1,2 +1,134 @@
+use super::sqlite_master::SqliteMaster;
+use crate::result::SqliteResult;
+use crate::runtime::{BtreeCursor, Record, SqliteBtree, Value};
+
+/// The `sqlite_sequence` table, created for tables with an `AUTOINCREMENT`
+/// column, which keeps the largest rowid ever used by each of those tables.
+///
+/// *Reference:* https://www.sqlite.org/autoinc.html
 #[derive(Debug, Default)]
-pub(super) struct SqliteSequence(());
+pub(crate) struct SqliteSequence {
+  entries: Vec<(String, i64)>,
+}
+
+impl SqliteSequence {
+  pub(crate) const NAME: &'static str = "sqlite_sequence";
+  pub(crate) const SQL: &'static str = "CREATE TABLE sqlite_sequence(name,seq)";
+
+  /// Reads every row of `sqlite_sequence`, which is empty when the table does
+  /// not exist.
+  pub(crate) fn read(
+    btree: &mut SqliteBtree<'_>,
+    master: &SqliteMaster,
+  ) -> SqliteResult<Self> {
+    let Some(root) = Self::root(master) else {
+      return Ok(Self::default());
+    };
+    let mut entries = vec![];
+    let mut cursor = BtreeCursor::new(root);
+    let mut has_row = cursor.first(btree)?;
+    while has_row {
+      let mut values = cursor.record(btree)?.into_iter();
+      if let (Some(Value::Text(name)), Some(Value::Integer(seq))) =
+        (values.next(), values.next())
+      {
+        entries.push((name, seq));
+      }
+      has_row = cursor.next(btree)?;
+    }
+    Ok(Self { entries })
+  }
+
+  /// Records `seq` as the largest rowid handed out to `table`, in its row of
+  /// `sqlite_sequence` or else in a new one. Does nothing when the table
+  /// does not exist.
+  pub(crate) fn write(
+    btree: &mut SqliteBtree<'_>,
+    master: &SqliteMaster,
+    table: &str,
+    seq: i64,
+  ) -> SqliteResult<()> {
+    let Some(root) = Self::root(master) else {
+      return Ok(());
+    };
+    let rowid = match Self::find(btree, root, table)? {
+      Some((rowid, _)) => rowid,
+      None => {
+        let mut cursor = BtreeCursor::new(root);
+        match cursor.last(btree)? {
+          true => cursor.rowid(btree)? + 1,
+          false => 1,
+        }
+      }
+    };
+    let record = Record::encode(&[Value::Text(table.into()), seq.into()]);
+    btree.insert(root, rowid, &record)
+  }
+
+  /// Gives the row of `table` to `new_name`, when there is one.
+  pub(crate) fn rename(
+    btree: &mut SqliteBtree<'_>,
+    master: &SqliteMaster,
+    table: &str,
+    new_name: &str,
+  ) -> SqliteResult<()> {
+    let Some(root) = Self::root(master) else {
+      return Ok(());
+    };
+    let Some((rowid, mut values)) = Self::find(btree, root, table)? else {
+      return Ok(());
+    };
+    values[0] = Value::Text(new_name.into());
+    btree.insert(root, rowid, &Record::encode(&values))
+  }
+
+  /// Deletes the row of `table`, when there is one.
+  pub(crate) fn remove(
+    btree: &mut SqliteBtree<'_>,
+    master: &SqliteMaster,
+    table: &str,
+  ) -> SqliteResult<()> {
+    let Some(root) = Self::root(master) else {
+      return Ok(());
+    };
+    if let Some((rowid, _)) = Self::find(btree, root, table)? {
+      btree.delete(root, rowid)?;
+    }
+    Ok(())
+  }
+
+  /// Rowid and values of the row of `table`.
+  fn find(
+    btree: &mut SqliteBtree<'_>,
+    root: u32,
+    table: &str,
+  ) -> SqliteResult<Option<(i64, Vec<Value>)>> {
+    let mut cursor = BtreeCursor::new(root);
+    let mut has_row = cursor.first(btree)?;
+    while has_row {
+      let values = cursor.record(btree)?;
+      if values.first() == Some(&Value::Text(table.into())) {
+        return Ok(Some((cursor.rowid(btree)?, values)));
+      }
+      has_row = cursor.next(btree)?;
+    }
+    Ok(None)
+  }
+
+  pub(crate) fn root(master: &SqliteMaster) -> Option<u32> {
+    master
+      .entries()
+      .iter()
+      .find(|entry| entry.kind() == "table" && entry.name() == Self::NAME)
+      .map(|entry| entry.rootpage())
+  }
+
+  /// Largest rowid recorded for `table`.
+  pub(crate) fn get(&self, table: &str) -> Option<i64> {
+    self
+      .entries
+      .iter()
+      .find(|(name, _)| name == table)
+      .map(|(_, seq)| *seq)
+  }
+}
@@ -0,0 +1,86 @@
+use super::sqlite_master::SqliteMaster;
+use crate::result::SqliteResult;
+use crate::runtime::{BtreeCursor, SqliteBtree, Value};
+
+/// The `sqlite_stat1` table, which `ANALYZE` fills with the number of rows
+/// of tables and indexes, and with the average number of rows sharing the
+/// same values of the leading columns of each index.
+///
+/// *Reference:* https://www.sqlite.org/fileformat2.html#stat1tab
+#[derive(Debug, Default)]
+pub struct SqliteStat1 {
+  /// The table, the index if any, and the integers of the `stat` column.
+  entries: Vec<(String, Option<String>, Vec<u64>)>,
+}
+
+impl SqliteStat1 {
+  pub(crate) const NAME: &'static str = "sqlite_stat1";
+
+  /// Reads every row of `sqlite_stat1`, which is empty when the table does
+  /// not exist. Rows whose `stat` does not start with an integer are left
+  /// out.
+  pub(crate) fn read(
+    btree: &mut SqliteBtree<'_>,
+    master: &SqliteMaster,
+  ) -> SqliteResult<Self> {
+    let root = master
+      .entries()
+      .iter()
+      .find(|entry| {
+        entry.kind() == "table" && entry.name().eq_ignore_ascii_case(Self::NAME)
+      })
+      .map(|entry| entry.rootpage());
+    let Some(root) = root else {
+      return Ok(Self::default());
+    };
+    let mut entries = vec![];
+    let mut cursor = BtreeCursor::new(root);
+    let mut has_row = cursor.first(btree)?;
+    while has_row {
+      let mut values = cursor.record(btree)?.into_iter();
+      let (table, index, stat) = (values.next(), values.next(), values.next());
+      if let (Some(Value::Text(table)), Some(Value::Text(stat))) = (table, stat)
+      {
+        let index = match index {
+          Some(Value::Text(index)) => Some(index),
+          _ => None,
+        };
+        // Words that are not integers, like `unordered`, follow the counts.
+        let counts = stat
+          .split_ascii_whitespace()
+          .map_while(|word| word.parse().ok())
+          .collect::<Vec<_>>();
+        if !counts.is_empty() {
+          entries.push((table, index, counts));
+        }
+      }
+      has_row = cursor.next(btree)?;
+    }
+    Ok(Self { entries })
+  }
+
+  /// The number of rows of `table`.
+  pub fn table_rows(&self, table: &str) -> Option<u64> {
+    self
+      .entries
+      .iter()
+      .find(|(name, ..)| name.eq_ignore_ascii_case(table))
+      .and_then(|(.., counts)| counts.first().copied())
+  }
+
+  /// The number of entries of the index `index`, followed by the average
+  /// number of entries sharing the values of its first column, of its first
+  /// two columns, and so on.
+  pub fn index(&self, table: &str, index: &str) -> Option<&[u64]> {
+    self
+      .entries
+      .iter()
+      .find(|(name, idx, _)| {
+        name.eq_ignore_ascii_case(table)
+          && idx
+            .as_ref()
+            .is_some_and(|idx| idx.eq_ignore_ascii_case(index))
+      })
+      .map(|(.., counts)| counts.as_slice())
+  }
+}
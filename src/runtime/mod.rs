@@ -1,20 +1,51 @@
 mod btree;
 mod internal_tables;
+mod record;
 mod schema;
+mod table;
 
-use self::btree::SqliteBtree;
+use self::btree::BtreeNode;
+use self::internal_tables::{
+  sqlite_master::SqliteMaster, sqlite_sequence::SqliteSequence,
+};
 use crate::{
-  header::SqliteHeader, pager::SqlitePager, result::SqliteResult,
+  header::SqliteHeader,
+  pager::SqlitePager,
+  result::{SqliteError, SqliteResult},
   traits::ParseBytes,
 };
+use std::collections::HashMap;
 
-pub use self::schema::SqliteSchema;
+pub use self::btree::{
+  AutoVacuum, BtreeCursor, BtreePageStats, BtreePageType, PtrmapEntry,
+  PtrmapType, SqliteBtree,
+};
+pub use self::internal_tables::sqlite_stat1::SqliteStat1;
+pub use self::record::{
+  compare_values, Affinity, Collation, CompareText, CustomCollation, KeyColumn,
+  KeyInfo, Record, Value,
+};
+pub use self::schema::{
+  ColumnDefinition, PrimaryKeyColumn, SqliteSchema, TableDefinition,
+};
+pub use self::table::TableCursor;
+
+pub(crate) use self::record::{
+  parse_number, to_integer, to_numeric, to_real, to_text,
+};
 
 #[derive(Debug)]
 pub struct SqliteRuntime {
   pager: SqlitePager,
   header: SqliteHeader,
-  btree: SqliteBtree,
+  /// Whether the schema changed since the last commit, for the schema cookie
+  /// to be bumped.
+  is_schema_changed: bool,
+  /// The header and whether the schema changed, as the current statement
+  /// began, to undo it alone.
+  statement: Option<([u8; SqliteHeader::LENGTH_BYTES], bool)>,
+  /// Collating sequences registered by the application, by uppercase name.
+  collations: HashMap<String, Collation>,
 }
 
 impl SqliteRuntime {
@@ -25,20 +56,209 @@ impl SqliteRuntime {
       SqliteHeader::parse_bytes(pager.first()?.raw_data())?
     };
 
-    let btree = Default::default();
-    Ok(Self {
+    let mut runtime = Self {
       pager,
       header,
-      btree,
-    })
+      is_schema_changed: false,
+      statement: None,
+      collations: HashMap::new(),
+    };
+    runtime.init_empty_database()?;
+    Ok(runtime)
   }
 
   pub fn header(&self) -> &SqliteHeader {
     &self.header
   }
 
-  pub fn tables(&self) -> SqliteResult<Vec<SqliteSchema>> {
-    todo!("Show tables not implemented")
+  /// Changes fields of the database header with `change`. Like the changes
+  /// done through [`Self::btree`], they are written by the next commit.
+  pub(crate) fn change_header(
+    &mut self,
+    change: impl FnOnce(&mut SqliteHeader),
+  ) -> SqliteResult<()> {
+    change(&mut self.header);
+    // Stage page 1 for the header change to be committed.
+    let first = self.pager.first()?;
+    self.pager.write(1, first)
+  }
+
+  /// Registers the collating sequence `name`, comparing text with
+  /// `compare`, in place of the one registered with the same name.
+  /// Built-in collating sequences cannot be replaced.
+  pub fn register_collation(
+    &mut self,
+    name: &str,
+    compare: Box<CompareText>,
+  ) -> SqliteResult<()> {
+    if name.is_empty() || Collation::from_name(name).is_some() {
+      return Err(SqliteError::Custom(format!(
+        "cannot register collating sequence: {name}"
+      )));
+    }
+    let collation = Collation::Custom(CustomCollation::new(name, compare));
+    self.collations.insert(name.to_ascii_uppercase(), collation);
+    Ok(())
+  }
+
+  /// The collating sequence named `name`, built in or registered.
+  pub fn collation(&self, name: &str) -> Option<Collation> {
+    Collation::from_name(name)
+      .or_else(|| self.collations.get(&name.to_ascii_uppercase()).cloned())
+  }
+
+  /// Looks up a collating sequence named in the schema among those
+  /// registered.
+  fn resolve_collation(&self, collation: &mut Collation) -> SqliteResult<()> {
+    if let Collation::Custom(custom) = collation {
+      if !custom.is_resolved() {
+        *collation = self.collation(custom.name()).ok_or_else(|| {
+          SqliteError::Custom(format!(
+            "no such collation sequence: {}",
+            custom.name()
+          ))
+        })?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Every object of the schema table: tables, indexes, views and triggers.
+  pub fn schema(&mut self) -> SqliteResult<Vec<SqliteSchema>> {
+    Ok(SqliteMaster::read(&mut self.btree())?.into_entries())
+  }
+
+  pub fn tables(&mut self) -> SqliteResult<Vec<SqliteSchema>> {
+    let mut tables = self.schema()?;
+    tables.retain(|entry| entry.kind() == "table");
+    Ok(tables)
+  }
+
+  /// Cursor over the rows of the table `name`, whatever the kind of b-tree
+  /// it is stored in. The schema table itself is available under its names
+  /// `sqlite_schema` and `sqlite_master`.
+  pub fn table(&mut self, name: &str) -> SqliteResult<TableCursor> {
+    if SqliteMaster::NAMES
+      .iter()
+      .any(|table| table.eq_ignore_ascii_case(name))
+    {
+      return Ok(TableCursor::new(
+        TableDefinition::parse(SqliteMaster::SQL)?,
+        SqliteMaster::ROOT_PAGE,
+      ));
+    }
+    let entry = self
+      .tables()?
+      .into_iter()
+      .find(|entry| entry.name().eq_ignore_ascii_case(name))
+      .ok_or(SqliteError::Custom(format!("no such table: {name}")))?;
+    let mut definition = entry.table_definition()?;
+    for collation in definition.collations_mut() {
+      self.resolve_collation(collation)?;
+    }
+    Ok(TableCursor::new(definition, entry.rootpage()))
+  }
+
+  /// The statistics `ANALYZE` kept in `sqlite_stat1`, if any.
+  pub fn statistics(&mut self) -> SqliteResult<SqliteStat1> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    SqliteStat1::read(&mut btree, &master)
+  }
+
+  /// Largest rowid handed out to the `AUTOINCREMENT` table `table`, as kept
+  /// in `sqlite_sequence`.
+  pub fn autoincrement_sequence(
+    &mut self,
+    table: &str,
+  ) -> SqliteResult<Option<i64>> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    Ok(SqliteSequence::read(&mut btree, &master)?.get(table))
+  }
+
+  /// Records `seq` as the largest rowid handed out to the `AUTOINCREMENT`
+  /// table `table`, when the database has a `sqlite_sequence` table.
+  pub fn set_autoincrement_sequence(
+    &mut self,
+    table: &str,
+    seq: i64,
+  ) -> SqliteResult<()> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    SqliteSequence::write(&mut btree, &master, table, seq)
+  }
+
+  /// Adds `entry` to the schema table.
+  pub fn add_schema_entry(&mut self, entry: &SqliteSchema) -> SqliteResult<()> {
+    SqliteMaster::insert(&mut self.btree(), entry)?;
+    self.is_schema_changed = true;
+    Ok(())
+  }
+
+  /// Passes every object of the schema table through `update`, which returns
+  /// the new description of the object or `None` to remove it.
+  pub fn update_schema(
+    &mut self,
+    update: impl FnMut(&SqliteSchema) -> Option<SqliteSchema>,
+  ) -> SqliteResult<()> {
+    SqliteMaster::update(&mut self.btree(), update)?;
+    self.is_schema_changed = true;
+    Ok(())
+  }
+
+  /// Deletes the b-tree rooted at `root`. The objects of the schema whose
+  /// root page was moved by auto-vacuum are updated.
+  pub fn drop_btree(&mut self, root: u32) -> SqliteResult<()> {
+    let Some(moved) = self.btree().drop_btree(root)? else {
+      return Ok(());
+    };
+    self.update_schema(|entry| {
+      let mut entry = entry.clone();
+      if entry.rootpage() == moved {
+        entry.set_rootpage(root);
+      }
+      Some(entry)
+    })
+  }
+
+  /// Creates the `sqlite_sequence` table, unless the database has one.
+  pub fn create_sequence_table(&mut self) -> SqliteResult<()> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    if SqliteSequence::root(&master).is_some() {
+      return Ok(());
+    }
+    let root = btree.create_table()?;
+    self.add_schema_entry(&SqliteSchema::new(
+      "table",
+      SqliteSequence::NAME,
+      SqliteSequence::NAME,
+      root,
+      Some(SqliteSequence::SQL.into()),
+    ))
+  }
+
+  /// Moves the `sqlite_sequence` row of the table `table` over to the table
+  /// `new_name`.
+  pub fn rename_autoincrement_sequence(
+    &mut self,
+    table: &str,
+    new_name: &str,
+  ) -> SqliteResult<()> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    SqliteSequence::rename(&mut btree, &master, table, new_name)
+  }
+
+  /// Forgets the largest rowid handed out to the table `table`.
+  pub fn remove_autoincrement_sequence(
+    &mut self,
+    table: &str,
+  ) -> SqliteResult<()> {
+    let mut btree = self.btree();
+    let master = SqliteMaster::read(&mut btree)?;
+    SqliteSequence::remove(&mut btree, &master, table)
   }
 
   pub fn pager(&self) -> &SqlitePager {
@@ -48,4 +268,83 @@ impl SqliteRuntime {
   pub fn pager_mut(&mut self) -> &mut SqlitePager {
     &mut self.pager
   }
+
+  /// Handle to the b-trees of the database.
+  pub fn btree(&mut self) -> SqliteBtree<'_> {
+    SqliteBtree::new(&mut self.pager, &mut self.header)
+  }
+
+  /// Makes every change done through [`Self::btree`] durable.
+  pub fn commit(&mut self) -> SqliteResult<()> {
+    self.statement = None;
+    if !self.pager.is_dirty() {
+      return Ok(());
+    }
+    let mut btree = self.btree();
+    if btree.auto_vacuum() == AutoVacuum::Full {
+      btree.incremental_vacuum(None)?;
+    }
+    self
+      .header
+      .set_db_filesize_in_pages(self.pager.page_count());
+    self.header.bump_file_change_counter();
+    if self.is_schema_changed {
+      self.header.bump_schema_cookie();
+      self.is_schema_changed = false;
+    }
+    self.pager.commit(&self.header)?;
+    debug!("Transaction committed.");
+    Ok(())
+  }
+
+  /// Discards every change done through [`Self::btree`] since the last
+  /// commit.
+  pub fn rollback(&mut self) -> SqliteResult<()> {
+    self.pager.rollback();
+    self.is_schema_changed = false;
+    self.statement = None;
+    self.header = if self.pager.io_mut().is_empty()? {
+      SqliteHeader::default()
+    } else {
+      SqliteHeader::parse_bytes(self.pager.first()?.raw_data())?
+    };
+    self.init_empty_database()?;
+    debug!("Transaction rolled back.");
+    Ok(())
+  }
+
+  /// Starts a statement, whose changes [`Self::rollback_statement`] can
+  /// discard while keeping those of the statements before.
+  pub fn begin_statement(&mut self) {
+    self.pager.begin_statement();
+    self.statement = Some((self.header.to_bytes(), self.is_schema_changed));
+  }
+
+  /// Discards the changes done since [`Self::begin_statement`].
+  pub fn rollback_statement(&mut self) -> SqliteResult<()> {
+    let Some((header, is_schema_changed)) = self.statement.take() else {
+      return Ok(());
+    };
+    self.pager.rollback_statement();
+    self.header = SqliteHeader::parse_bytes(&header)?;
+    self.is_schema_changed = is_schema_changed;
+    debug!("Statement rolled back.");
+    Ok(())
+  }
+
+  /// Stages page 1 of a brand new database: the database header followed by
+  /// the empty root page of the `sqlite_schema` table. Nothing is written to
+  /// storage until the first commit.
+  fn init_empty_database(&mut self) -> SqliteResult<()> {
+    if self.pager.page_count() > 0 || self.pager.io().is_read_only() {
+      return Ok(());
+    }
+    let page_number = self.pager.append()?;
+    let mut page = self.pager.read(page_number)?;
+    page.raw_data_mut()[..SqliteHeader::LENGTH_BYTES]
+      .copy_from_slice(&self.header.to_bytes());
+    BtreeNode::new(page_number, BtreePageType::LeafTable)
+      .write_into(&mut page, self.pager.usable_size())?;
+    self.pager.write(page_number, page)
+  }
 }
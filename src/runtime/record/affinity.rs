@@ -0,0 +1,294 @@
+//! # Type Affinity
+//!
+//!  Each column in an SQLite 3 database is assigned one of the following type
+//! affinities: TEXT, NUMERIC, INTEGER, REAL, BLOB. The type affinity of a
+//! column is the recommended type for data stored in that column: values are
+//! converted to it when that can be done without loss.
+//!
+//! *Reference:* https://www.sqlite.org/datatype3.html#type_affinity
+
+use super::Value;
+
+/// The affinity of a column, or of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+  /// A column with affinity BLOB does not prefer one storage class over
+  /// another and no attempt is made to coerce data from one storage class
+  /// into another.
+  Blob,
+  /// A column with TEXT affinity stores all data using storage classes NULL,
+  /// TEXT or BLOB.
+  Text,
+  /// A column with NUMERIC affinity may contain values using all five
+  /// storage classes. Text that is a well-formed number is converted to
+  /// INTEGER or REAL.
+  Numeric,
+  /// Behaves the same as a column with NUMERIC affinity.
+  Integer,
+  /// Like NUMERIC affinity, except that it forces integer values into
+  /// floating point representation.
+  Real,
+}
+
+impl Affinity {
+  /// The affinity of a column declared with type `declared_type`, following
+  /// the five rules of SQLite, in order.
+  ///
+  /// *Reference:* https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+  pub fn from_declared_type(declared_type: Option<&str>) -> Self {
+    let declared_type = declared_type.unwrap_or_default().to_uppercase();
+    let has = |part: &str| declared_type.contains(part);
+    if has("INT") {
+      Self::Integer
+    } else if has("CHAR") || has("CLOB") || has("TEXT") {
+      Self::Text
+    } else if has("BLOB") || declared_type.is_empty() {
+      Self::Blob
+    } else if has("REAL") || has("FLOA") || has("DOUB") {
+      Self::Real
+    } else {
+      Self::Numeric
+    }
+  }
+
+  pub fn is_numeric(&self) -> bool {
+    matches!(self, Self::Numeric | Self::Integer | Self::Real)
+  }
+
+  /// Converts `value` to the preferred storage class, when that can be done
+  /// without loss.
+  pub fn apply(&self, value: Value) -> Value {
+    match (self, value) {
+      (Self::Blob, value) => value,
+      (Self::Text, value @ (Value::Integer(_) | Value::Real(_))) => {
+        Value::Text(to_text(&value).unwrap_or_default())
+      }
+      (Self::Numeric | Self::Integer, Value::Text(text)) => {
+        match parse_number(&text) {
+          Some((number, true)) => narrow_real(number),
+          _ => Value::Text(text),
+        }
+      }
+      (Self::Numeric | Self::Integer, value) => narrow_real(value),
+      (Self::Real, Value::Text(text)) => match parse_number(&text) {
+        Some((number, true)) => Value::Real(to_real(&number)),
+        _ => Value::Text(text),
+      },
+      (Self::Real, Value::Integer(int)) => Value::Real(int as f64),
+      (_, value) => value,
+    }
+  }
+
+  /// `CAST(value AS type)`, where `type` has this affinity. Unlike
+  /// [`Self::apply`], the conversion always happens, taking the longest
+  /// prefix of text that is a number.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_expr.html#castexpr
+  pub fn cast(&self, value: Value) -> Value {
+    if value.is_null() {
+      return value;
+    }
+    match self {
+      Self::Blob => match value {
+        Value::Blob(blob) => Value::Blob(blob),
+        value => Value::Blob(to_text(&value).unwrap_or_default().into_bytes()),
+      },
+      Self::Text => Value::Text(to_text(&value).unwrap_or_default()),
+      Self::Integer => Value::Integer(to_integer(&value)),
+      Self::Real => Value::Real(to_real(&value)),
+      Self::Numeric => narrow_real(to_numeric(&value)),
+    }
+  }
+}
+
+/// Parses a number the way SQLite does: leading and trailing spaces are
+/// ignored, and integers too large for 64 bits become REAL. Returns the
+/// number found at the start of `text`, if any, and whether it spans the
+/// whole text.
+pub(crate) fn parse_number(text: &str) -> Option<(Value, bool)> {
+  let bytes = text.as_bytes();
+  let is_space =
+    |idx: usize| bytes.get(idx).is_some_and(u8::is_ascii_whitespace);
+  let is_digit = |idx: usize| bytes.get(idx).is_some_and(u8::is_ascii_digit);
+
+  let mut idx = 0;
+  while is_space(idx) {
+    idx += 1;
+  }
+  let start = idx;
+  if matches!(bytes.get(idx), Some(b'+' | b'-')) {
+    idx += 1;
+  }
+  let mut digits = 0;
+  while is_digit(idx) {
+    idx += 1;
+    digits += 1;
+  }
+  let mut is_real = false;
+  if bytes.get(idx) == Some(&b'.') {
+    is_real = true;
+    idx += 1;
+    while is_digit(idx) {
+      idx += 1;
+      digits += 1;
+    }
+  }
+  if digits == 0 {
+    return None;
+  }
+  if matches!(bytes.get(idx), Some(b'e' | b'E')) {
+    let mut exponent = idx + 1;
+    if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+      exponent += 1;
+    }
+    if is_digit(exponent) {
+      is_real = true;
+      idx = exponent;
+      while is_digit(idx) {
+        idx += 1;
+      }
+    }
+  }
+  let number = &text[start..idx];
+  while is_space(idx) {
+    idx += 1;
+  }
+  let complete = idx == bytes.len();
+  let value = match number.parse::<i64>() {
+    Ok(int) if !is_real => Value::Integer(int),
+    _ => Value::Real(number.parse().unwrap_or_default()),
+  };
+  Some((value, complete))
+}
+
+/// The value of `value` as a number, INTEGER or REAL, for arithmetic. Text
+/// that does not start with a number counts as zero.
+pub(crate) fn to_numeric(value: &Value) -> Value {
+  match value {
+    Value::Null => Value::Null,
+    Value::Integer(_) | Value::Real(_) => value.clone(),
+    Value::Text(_) | Value::Blob(_) => {
+      let text = to_text(value).unwrap_or_default();
+      parse_number(&text).map_or(Value::Integer(0), |(number, _)| number)
+    }
+  }
+}
+
+/// `CAST(value AS INTEGER)`: reals are truncated toward zero and text is
+/// read up to its first character that is not part of an integer.
+pub(crate) fn to_integer(value: &Value) -> i64 {
+  match value {
+    Value::Null => 0,
+    Value::Integer(int) => *int,
+    Value::Real(real) => *real as i64,
+    Value::Text(_) | Value::Blob(_) => {
+      let text = to_text(value).unwrap_or_default();
+      let text = text.trim_start();
+      let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+      };
+      let mut int: i64 = 0;
+      for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = i64::from(digit - b'0');
+        int = match int.checked_mul(10).and_then(|int| match negative {
+          true => int.checked_sub(digit),
+          false => int.checked_add(digit),
+        }) {
+          Some(int) => int,
+          None if negative => return i64::MIN,
+          None => return i64::MAX,
+        };
+      }
+      int
+    }
+  }
+}
+
+/// `CAST(value AS REAL)`.
+pub(crate) fn to_real(value: &Value) -> f64 {
+  match to_numeric(value) {
+    Value::Integer(int) => int as f64,
+    Value::Real(real) => real,
+    _ => 0.0,
+  }
+}
+
+/// `CAST(value AS TEXT)`, `None` for NULL.
+pub(crate) fn to_text(value: &Value) -> Option<String> {
+  match value {
+    Value::Null => None,
+    Value::Integer(int) => Some(int.to_string()),
+    Value::Real(real) => Some(format_real(*real)),
+    Value::Text(text) => Some(text.clone()),
+    Value::Blob(blob) => Some(String::from_utf8_lossy(blob).into_owned()),
+  }
+}
+
+/// Renders a floating point value like SQLite's `"%!.15g"`: 15 significant
+/// digits, and a decimal point even when the value is integral.
+pub(crate) fn format_real(real: f64) -> String {
+  if real.is_nan() {
+    return "NaN".into();
+  }
+  if real.is_infinite() {
+    return match real.is_sign_positive() {
+      true => "Inf".into(),
+      false => "-Inf".into(),
+    };
+  }
+  if real == 0.0 {
+    return "0.0".into();
+  }
+  let scientific = format!("{real:.14e}");
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or_default();
+  let exponent: i32 = exponent.parse().unwrap_or_default();
+  let (sign, mantissa) = match mantissa.strip_prefix('-') {
+    Some(mantissa) => ("-", mantissa),
+    None => ("", mantissa),
+  };
+  let digits = mantissa.replace('.', "");
+  let digits = digits.trim_end_matches('0');
+  if !(-4..15).contains(&exponent) {
+    let fraction = match &digits[1..] {
+      "" => "0",
+      fraction => fraction,
+    };
+    let exponent_sign = if exponent < 0 { '-' } else { '+' };
+    return format!(
+      "{sign}{}.{fraction}e{exponent_sign}{:02}",
+      &digits[..1],
+      exponent.abs()
+    );
+  }
+  let (integer, fraction) = if exponent < 0 {
+    let zeros = "0".repeat((-exponent - 1) as usize);
+    ("0".to_owned(), format!("{zeros}{digits}"))
+  } else {
+    let split = exponent as usize + 1;
+    let digits = format!("{digits:0<split$}");
+    (digits[..split].to_owned(), digits[split..].to_owned())
+  };
+  let fraction = match fraction.as_str() {
+    "" => "0",
+    fraction => fraction,
+  };
+  format!("{sign}{integer}.{fraction}")
+}
+
+/// Whether a floating point value is exactly an integer small enough for
+/// SQLite to store it as one.
+pub(crate) fn real_as_integer(real: f64) -> Option<i64> {
+  const LIMIT: i64 = 1 << 51;
+  let int = real as i64;
+  (int as f64 == real && (-LIMIT..LIMIT).contains(&int)).then_some(int)
+}
+
+/// Turns integral REAL values into INTEGER ones, as NUMERIC affinity does.
+fn narrow_real(value: Value) -> Value {
+  match value {
+    Value::Real(real) => real_as_integer(real).map_or(value, Value::Integer),
+    value => value,
+  }
+}
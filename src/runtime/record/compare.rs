@@ -0,0 +1,226 @@
+//! # Sort Order
+//!
+//!  Values are compared first by storage class: NULLs sort first, then
+//! INTEGER and REAL values in numerical order, then TEXT values in the order
+//! given by the collating sequence, then BLOB values in `memcmp()` order.
+//!
+//! *Reference:* https://www.sqlite.org/datatype3.html#sort_order
+
+use super::Value;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+/// The comparison function of a collating sequence registered by the
+/// application.
+pub type CompareText = dyn Fn(&str, &str) -> Ordering + Send + Sync;
+
+/// A collating sequence, used to compare two text values.
+///
+/// *Reference:* https://www.sqlite.org/datatype3.html#collating_sequences
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Collation {
+  /// Compares string data using `memcmp()`, regardless of text encoding.
+  #[default]
+  Binary,
+  /// Like binary, except the 26 upper case characters of ASCII are folded to
+  /// their lower case equivalents before the comparison is performed.
+  NoCase,
+  /// Like binary, except that trailing space characters are ignored.
+  RTrim,
+  /// A collating sequence registered by the application.
+  Custom(CustomCollation),
+}
+
+impl Collation {
+  /// The built-in collating sequence named `name`, case-insensitively. See
+  /// [`SqliteRuntime::collation`](crate::runtime::SqliteRuntime::collation)
+  /// for those registered by the application as well.
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name.to_ascii_uppercase().as_str() {
+      "BINARY" => Some(Self::Binary),
+      "NOCASE" => Some(Self::NoCase),
+      "RTRIM" => Some(Self::RTrim),
+      _ => None,
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    match self {
+      Self::Binary => "BINARY",
+      Self::NoCase => "NOCASE",
+      Self::RTrim => "RTRIM",
+      Self::Custom(custom) => custom.name(),
+    }
+  }
+
+  pub fn compare(&self, left: &str, right: &str) -> Ordering {
+    match self {
+      Self::Binary => left.as_bytes().cmp(right.as_bytes()),
+      Self::NoCase => left
+        .bytes()
+        .map(|byte| byte.to_ascii_lowercase())
+        .cmp(right.bytes().map(|byte| byte.to_ascii_lowercase())),
+      Self::RTrim => left
+        .trim_end_matches(' ')
+        .as_bytes()
+        .cmp(right.trim_end_matches(' ').as_bytes()),
+      Self::Custom(custom) => match &custom.0.compare {
+        Some(compare) => compare(left, right),
+        None => left.as_bytes().cmp(right.as_bytes()),
+      },
+    }
+  }
+
+  /// Whether the collating sequence was registered by the application.
+  pub fn is_custom(&self) -> bool {
+    matches!(self, Self::Custom(_))
+  }
+}
+
+/// A collating sequence registered by the application. One named in the
+/// schema is unresolved, and compares as `BINARY`, until it is looked up
+/// among those registered on the connection.
+#[derive(Clone)]
+pub struct CustomCollation(Arc<Definition>);
+
+struct Definition {
+  name: String,
+  compare: Option<Box<CompareText>>,
+}
+
+impl CustomCollation {
+  pub fn new(name: impl Into<String>, compare: Box<CompareText>) -> Self {
+    Self(Arc::new(Definition {
+      name: name.into(),
+      compare: Some(compare),
+    }))
+  }
+
+  /// The collating sequence named `name`, to be resolved.
+  pub fn unresolved(name: impl Into<String>) -> Self {
+    Self(Arc::new(Definition {
+      name: name.into(),
+      compare: None,
+    }))
+  }
+
+  pub fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  pub fn is_resolved(&self) -> bool {
+    self.0.compare.is_some()
+  }
+}
+
+impl PartialEq for CustomCollation {
+  /// Collating sequences are the same when they have the same name.
+  fn eq(&self, other: &Self) -> bool {
+    self.0.name.eq_ignore_ascii_case(&other.0.name)
+  }
+}
+
+impl Eq for CustomCollation {}
+
+impl Debug for CustomCollation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("CustomCollation")
+      .field(&self.0.name)
+      .finish()
+  }
+}
+
+/// Sort order and collating sequence of one column of an index key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyColumn {
+  pub descending: bool,
+  pub collation: Collation,
+}
+
+/// Describes how the columns of index keys compare to one another. Columns
+/// past the ones described use ascending order and binary collation, as the
+/// trailing rowid of index keys does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyInfo {
+  columns: Vec<KeyColumn>,
+}
+
+impl KeyInfo {
+  pub fn new(columns: Vec<KeyColumn>) -> Self {
+    Self { columns }
+  }
+
+  pub fn columns(&self) -> &[KeyColumn] {
+    &self.columns
+  }
+
+  /// Compares the common prefix of two keys: a key that is a prefix of the
+  /// other compares equal to it.
+  pub fn compare(&self, left: &[Value], right: &[Value]) -> Ordering {
+    let default_column = KeyColumn::default();
+    for (idx, (left, right)) in left.iter().zip(right).enumerate() {
+      let column = self.columns.get(idx).unwrap_or(&default_column);
+      let ordering = compare_values(left, right, &column.collation);
+      let ordering = if column.descending {
+        ordering.reverse()
+      } else {
+        ordering
+      };
+      if ordering != Ordering::Equal {
+        return ordering;
+      }
+    }
+    Ordering::Equal
+  }
+}
+
+/// Compares two values following SQLite's sort order.
+pub fn compare_values(
+  left: &Value,
+  right: &Value,
+  collation: &Collation,
+) -> Ordering {
+  fn class(value: &Value) -> u8 {
+    match value {
+      Value::Null => 0,
+      Value::Integer(_) | Value::Real(_) => 1,
+      Value::Text(_) => 2,
+      Value::Blob(_) => 3,
+    }
+  }
+  match (left, right) {
+    (Value::Integer(left), Value::Integer(right)) => left.cmp(right),
+    (Value::Real(left), Value::Real(right)) => {
+      left.partial_cmp(right).unwrap_or(Ordering::Equal)
+    }
+    (Value::Integer(left), Value::Real(right)) => {
+      compare_int_real(*left, *right)
+    }
+    (Value::Real(left), Value::Integer(right)) => {
+      compare_int_real(*right, *left).reverse()
+    }
+    (Value::Text(left), Value::Text(right)) => collation.compare(left, right),
+    (Value::Blob(left), Value::Blob(right)) => left.cmp(right),
+    (left, right) => class(left).cmp(&class(right)),
+  }
+}
+
+/// Exact comparison of an integer and a floating point value.
+pub(crate) fn compare_int_real(int: i64, real: f64) -> Ordering {
+  // -2^63 converts exactly, unlike 2^63 - 1.
+  let lower_bound = i64::MIN as f64;
+  if real.is_nan() || real < lower_bound {
+    return Ordering::Greater;
+  }
+  if real >= -lower_bound {
+    return Ordering::Less;
+  }
+  let truncated = real as i64;
+  match int.cmp(&truncated) {
+    Ordering::Equal => {
+      (int as f64).partial_cmp(&real).unwrap_or(Ordering::Equal)
+    }
+    ordering => ordering,
+  }
+}
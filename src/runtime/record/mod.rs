@@ -0,0 +1,178 @@
+//! # Record Format
+//!
+//!  The data for a table b-tree leaf page and the key of an index b-tree page
+//! was characterized above as an arbitrary sequence of bytes. The prior
+//! discussion mentioned one key being less than another, but did not define
+//! what "less than" meant. The current section addresses these omissions.
+//!
+//!  Payload, either table b-tree data or index b-tree keys, is always in the
+//! "record format". The record format defines a sequence of values
+//! corresponding to columns in a table or index. The record format specifies
+//! the number of columns, the datatype of each column, and the content of each
+//! column.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#record_format
+
+mod affinity;
+mod compare;
+mod value;
+pub(crate) mod varint;
+
+use self::varint::{read_varint, varint_len, write_varint};
+use crate::header::DatabaseTextEncoding;
+use crate::result::{SqliteError, SqliteResult};
+
+pub use self::affinity::Affinity;
+pub(crate) use self::affinity::{
+  parse_number, to_integer, to_numeric, to_real, to_text,
+};
+pub use self::compare::{
+  compare_values, Collation, CompareText, CustomCollation, KeyColumn, KeyInfo,
+};
+pub use self::value::Value;
+
+/// Encoder and decoder of the record format.
+#[derive(Debug)]
+pub struct Record;
+
+impl Record {
+  /// Encodes `values` into the record format.
+  pub fn encode(values: &[Value]) -> Vec<u8> {
+    let serial_types: Vec<u64> = values.iter().map(Self::serial_type).collect();
+    let types_len: usize = serial_types.iter().map(|t| varint_len(*t)).sum();
+    // The header size varint counts itself.
+    let mut header_len = types_len + 1;
+    while header_len < types_len + varint_len(header_len as u64) {
+      header_len = types_len + varint_len(header_len as u64);
+    }
+
+    let mut buf = Vec::with_capacity(header_len);
+    write_varint(&mut buf, header_len as u64);
+    serial_types.iter().for_each(|t| write_varint(&mut buf, *t));
+
+    for (value, serial_type) in values.iter().zip(&serial_types) {
+      match value {
+        Value::Null => (),
+        Value::Integer(int) => {
+          let len = Self::serial_type_len(*serial_type);
+          buf.extend_from_slice(&int.to_be_bytes()[8 - len..]);
+        }
+        Value::Real(real) => buf.extend_from_slice(&real.to_be_bytes()),
+        Value::Text(text) => buf.extend_from_slice(text.as_bytes()),
+        Value::Blob(blob) => buf.extend_from_slice(blob),
+      }
+    }
+    buf
+  }
+
+  /// Decodes a record assuming UTF-8 text encoding.
+  pub fn decode(payload: &[u8]) -> SqliteResult<Vec<Value>> {
+    Self::decode_with_encoding(payload, &DatabaseTextEncoding::Utf8)
+  }
+
+  /// Decodes a record whose text values are stored with `encoding`.
+  pub fn decode_with_encoding(
+    payload: &[u8],
+    encoding: &DatabaseTextEncoding,
+  ) -> SqliteResult<Vec<Value>> {
+    let (header_len, mut header_offset) = read_varint(payload)?;
+    let header_len = header_len as usize;
+    if header_len > payload.len() {
+      return Err(SqliteError::Corrupt("Record header overflow".into()));
+    }
+    let mut body_offset = header_len;
+    let mut values = vec![];
+    while header_offset < header_len {
+      let (serial_type, consumed) =
+        read_varint(&payload[header_offset..header_len])?;
+      header_offset += consumed;
+      let len = Self::serial_type_len(serial_type);
+      let bytes = payload
+        .get(body_offset..body_offset + len)
+        .ok_or(SqliteError::Corrupt("Record body overflow".into()))?;
+      body_offset += len;
+      values.push(Self::decode_value(serial_type, bytes, encoding)?);
+    }
+    Ok(values)
+  }
+
+  /// Serial type code describing how `value` is stored.
+  pub(crate) fn serial_type(value: &Value) -> u64 {
+    match value {
+      Value::Null => 0,
+      Value::Integer(0) => 8,
+      Value::Integer(1) => 9,
+      Value::Integer(int) => match *int {
+        -128..=127 => 1,
+        -32_768..=32_767 => 2,
+        -8_388_608..=8_388_607 => 3,
+        -2_147_483_648..=2_147_483_647 => 4,
+        -140_737_488_355_328..=140_737_488_355_327 => 5,
+        _ => 6,
+      },
+      Value::Real(_) => 7,
+      Value::Text(text) => (text.len() as u64) * 2 + 13,
+      Value::Blob(blob) => (blob.len() as u64) * 2 + 12,
+    }
+  }
+
+  /// Size in bytes of the content stored for `serial_type`.
+  pub(crate) fn serial_type_len(serial_type: u64) -> usize {
+    match serial_type {
+      0 | 8..=11 => 0,
+      1 => 1,
+      2 => 2,
+      3 => 3,
+      4 => 4,
+      5 => 6,
+      6 | 7 => 8,
+      n => ((n - 12) / 2) as usize,
+    }
+  }
+
+  fn decode_value(
+    serial_type: u64,
+    bytes: &[u8],
+    encoding: &DatabaseTextEncoding,
+  ) -> SqliteResult<Value> {
+    let value = match serial_type {
+      0 => Value::Null,
+      1..=6 => {
+        let mut buf = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+          [0xffu8; 8]
+        } else {
+          [0u8; 8]
+        };
+        buf[8 - bytes.len()..].copy_from_slice(bytes);
+        Value::Integer(i64::from_be_bytes(buf))
+      }
+      7 => Value::Real(f64::from_be_bytes(bytes.try_into()?)),
+      8 => Value::Integer(0),
+      9 => Value::Integer(1),
+      10 | 11 => {
+        return Err(SqliteError::Corrupt("Reserved serial type".into()))
+      }
+      n if n % 2 == 0 => Value::Blob(bytes.to_vec()),
+      _ => Value::Text(Self::decode_text(bytes, encoding)),
+    };
+    Ok(value)
+  }
+
+  fn decode_text(bytes: &[u8], encoding: &DatabaseTextEncoding) -> String {
+    let units = |to_u16: fn([u8; 2]) -> u16| -> Vec<u16> {
+      bytes
+        .chunks_exact(2)
+        .map(|pair| to_u16([pair[0], pair[1]]))
+        .collect()
+    };
+    match encoding {
+      DatabaseTextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+      DatabaseTextEncoding::Utf16Le => {
+        String::from_utf16_lossy(&units(u16::from_le_bytes))
+      }
+      DatabaseTextEncoding::Utf16Be => {
+        String::from_utf16_lossy(&units(u16::from_be_bytes))
+      }
+    }
+  }
+}
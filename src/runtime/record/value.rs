@@ -0,0 +1,70 @@
+use super::affinity::format_real;
+use core::fmt::Display;
+
+/// A single SQL value, using SQLite's five storage classes.
+///
+/// *Reference:* https://www.sqlite.org/datatype3.html#storage_classes_and_datatypes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  /// The value is a NULL value.
+  Null,
+  /// The value is a signed integer.
+  Integer(i64),
+  /// The value is a floating point value, stored as an 8-byte IEEE floating
+  /// point number.
+  Real(f64),
+  /// The value is a text string, stored using the database encoding.
+  Text(String),
+  /// The value is a blob of data, stored exactly as it was input.
+  Blob(Vec<u8>),
+}
+
+impl Value {
+  pub fn is_null(&self) -> bool {
+    matches!(self, Self::Null)
+  }
+}
+
+/// Values display as their text conversion, like the `sqlite3` shell shows
+/// them.
+impl Display for Value {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      Value::Null => Ok(()),
+      Value::Integer(int) => write!(f, "{int}"),
+      Value::Real(real) => write!(f, "{}", format_real(*real)),
+      Value::Text(text) => write!(f, "{text}"),
+      Value::Blob(blob) => write!(f, "{}", String::from_utf8_lossy(blob)),
+    }
+  }
+}
+
+impl From<i64> for Value {
+  fn from(value: i64) -> Self {
+    Self::Integer(value)
+  }
+}
+
+impl From<f64> for Value {
+  fn from(value: f64) -> Self {
+    Self::Real(value)
+  }
+}
+
+impl From<&str> for Value {
+  fn from(value: &str) -> Self {
+    Self::Text(value.into())
+  }
+}
+
+impl From<String> for Value {
+  fn from(value: String) -> Self {
+    Self::Text(value)
+  }
+}
+
+impl From<Vec<u8>> for Value {
+  fn from(value: Vec<u8>) -> Self {
+    Self::Blob(value)
+  }
+}
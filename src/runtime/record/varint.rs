@@ -0,0 +1,65 @@
+//! # Variable-length integers
+//!
+//!  A variable-length integer or "varint" is a static Huffman encoding of
+//! 64-bit twos-complement integers that uses less space for small positive
+//! values. A varint is between 1 and 9 bytes in length. The varint consists of
+//! either zero or more bytes which have the high-order bit set followed by a
+//! single byte with the high-order bit clear, or nine bytes, whichever is
+//! shorter. The lower seven bits of each of the first eight bytes and all 8
+//! bits of the ninth byte are used to reconstruct the 64-bit twos-complement
+//! integer. Varints are big-endian: bits taken from the earlier byte of the
+//! varint are more significant than bits taken from the later bytes.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#varint
+
+use crate::result::{SqliteError, SqliteResult};
+
+/// Maximum length in bytes of a varint.
+pub(crate) const MAX_VARINT_LENGTH: usize = 9;
+
+/// Decodes the varint at the start of `bytes`, returning the value and the
+/// number of bytes consumed.
+pub(crate) fn read_varint(bytes: &[u8]) -> SqliteResult<(u64, usize)> {
+  let mut value = 0u64;
+  for (idx, byte) in bytes.iter().take(MAX_VARINT_LENGTH).enumerate() {
+    if idx == MAX_VARINT_LENGTH - 1 {
+      value = (value << 8) | u64::from(*byte);
+      return Ok((value, MAX_VARINT_LENGTH));
+    }
+    value = (value << 7) | u64::from(byte & 0x7f);
+    if byte & 0x80 == 0 {
+      return Ok((value, idx + 1));
+    }
+  }
+  Err(SqliteError::Corrupt("Truncated varint".into()))
+}
+
+/// Number of bytes needed to encode `value` as a varint.
+pub(crate) fn varint_len(value: u64) -> usize {
+  if value > 0x00ff_ffff_ffff_ffff {
+    MAX_VARINT_LENGTH
+  } else {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+  }
+}
+
+/// Appends the varint encoding of `value` to `buf`.
+pub(crate) fn write_varint(buf: &mut Vec<u8>, value: u64) {
+  if value > 0x00ff_ffff_ffff_ffff {
+    let mut bytes = [0u8; MAX_VARINT_LENGTH];
+    bytes[8] = value as u8;
+    let mut rest = value >> 8;
+    for byte in bytes[..8].iter_mut().rev() {
+      *byte = (rest as u8 & 0x7f) | 0x80;
+      rest >>= 7;
+    }
+    buf.extend_from_slice(&bytes);
+    return;
+  }
+  let len = varint_len(value);
+  for idx in (0..len).rev() {
+    let group = ((value >> (7 * idx)) & 0x7f) as u8;
+    buf.push(if idx == 0 { group } else { group | 0x80 });
+  }
+}
@@ -1,2 +1,146 @@
-#[derive(Debug, Default)]
-pub struct SqliteSchema(());
+mod table;
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::Value;
+
+pub use self::table::{ColumnDefinition, PrimaryKeyColumn, TableDefinition};
+
+/// # Schema object
+///
+///  Every SQLite database contains a single "schema table" that stores the
+/// schema for that database. The schema for a database is a description of
+/// all of the other tables, indexes, triggers, and views that are contained
+/// within the database. The schema table looks like this:
+///
+/// ```sql
+/// CREATE TABLE sqlite_schema(
+///   type text,
+///   name text,
+///   tbl_name text,
+///   rootpage integer,
+///   sql text
+/// );
+/// ```
+///
+/// *Reference:* https://www.sqlite.org/schematab.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SqliteSchema {
+  /// One of: 'table', 'index', 'view', or 'trigger'.
+  kind: String,
+  /// Name of the object.
+  name: String,
+  /// Name of the table or view that the object is associated with.
+  tbl_name: String,
+  /// Page number of the root b-tree page for tables and indexes, zero
+  /// otherwise.
+  rootpage: u32,
+  /// SQL text that describes the object.
+  sql: Option<String>,
+}
+
+impl SqliteSchema {
+  pub fn new(
+    kind: impl Into<String>,
+    name: impl Into<String>,
+    tbl_name: impl Into<String>,
+    rootpage: u32,
+    sql: Option<String>,
+  ) -> Self {
+    Self {
+      kind: kind.into(),
+      name: name.into(),
+      tbl_name: tbl_name.into(),
+      rootpage,
+      sql,
+    }
+  }
+
+  pub fn kind(&self) -> &str {
+    &self.kind
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn tbl_name(&self) -> &str {
+    &self.tbl_name
+  }
+
+  pub fn rootpage(&self) -> u32 {
+    self.rootpage
+  }
+
+  pub fn sql(&self) -> Option<&str> {
+    self.sql.as_deref()
+  }
+
+  pub(crate) fn set_name(&mut self, name: impl Into<String>) {
+    self.name = name.into();
+  }
+
+  pub(crate) fn set_tbl_name(&mut self, tbl_name: impl Into<String>) {
+    self.tbl_name = tbl_name.into();
+  }
+
+  pub(crate) fn set_rootpage(&mut self, rootpage: u32) {
+    self.rootpage = rootpage;
+  }
+
+  pub(crate) fn set_sql(&mut self, sql: impl Into<String>) {
+    self.sql = Some(sql.into());
+  }
+
+  /// Columns and primary key of a table, recovered from its SQL text.
+  pub fn table_definition(&self) -> SqliteResult<TableDefinition> {
+    match (self.kind.as_str(), self.sql.as_deref()) {
+      ("table", Some(sql)) => TableDefinition::parse(sql),
+      _ => Err(SqliteError::Custom(format!(
+        "[{}] is not a table with a definition",
+        self.name
+      ))),
+    }
+  }
+
+  /// Whether this is a table declared `WITHOUT ROWID`, stored in an index
+  /// b-tree keyed by its primary key.
+  pub fn is_without_rowid(&self) -> bool {
+    self
+      .table_definition()
+      .is_ok_and(|definition| definition.is_without_rowid())
+  }
+
+  /// The row of the schema table describing this object.
+  pub fn to_record(&self) -> Vec<Value> {
+    vec![
+      Value::Text(self.kind.clone()),
+      Value::Text(self.name.clone()),
+      Value::Text(self.tbl_name.clone()),
+      Value::Integer(i64::from(self.rootpage)),
+      self.sql.clone().map_or(Value::Null, Value::Text),
+    ]
+  }
+
+  pub(crate) fn from_record(values: Vec<Value>) -> Option<Self> {
+    let mut values = values.into_iter();
+    let text = |value: Option<Value>| match value {
+      Some(Value::Text(text)) => Some(text),
+      _ => None,
+    };
+    let kind = text(values.next())?;
+    let name = text(values.next())?;
+    let tbl_name = text(values.next())?;
+    let rootpage = match values.next() {
+      Some(Value::Integer(page)) => u32::try_from(page).ok()?,
+      _ => 0,
+    };
+    let sql = text(values.next());
+    Some(Self {
+      kind,
+      name,
+      tbl_name,
+      rootpage,
+      sql,
+    })
+  }
+}
@@ -0,0 +1,682 @@
+//! # Table definitions
+//!
+//!  The `sql` column of the schema table keeps the original `CREATE TABLE`
+//! text of every table. The columns of a table, its primary key and whether
+//! it was declared `WITHOUT ROWID` are all recovered from that text.
+//!
+//! *Reference:* https://www.sqlite.org/lang_createtable.html
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::record::{
+  parse_number, Affinity, Collation, CustomCollation, KeyColumn, KeyInfo, Value,
+};
+
+/// The columns and primary key of a table, as declared in its `CREATE TABLE`
+/// statement.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableDefinition {
+  name: String,
+  columns: Vec<ColumnDefinition>,
+  primary_key: Vec<PrimaryKeyColumn>,
+  /// Set by `PRIMARY KEY DESC` on an `INTEGER` column, which, due to a
+  /// long-standing quirk of SQLite, does not make it an alias of the rowid.
+  descending_column_key: bool,
+  without_rowid: bool,
+  strict: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnDefinition {
+  name: String,
+  declared_type: Option<String>,
+  collation: Collation,
+  not_null: bool,
+  /// The `DEFAULT` of the column when it is a literal, which columns added
+  /// by `ALTER TABLE` always have.
+  default: Option<Value>,
+}
+
+/// A column of the primary key, by its position among the table columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrimaryKeyColumn {
+  column: usize,
+  descending: bool,
+  collation: Collation,
+}
+
+impl TableDefinition {
+  /// Recovers the definition of a table from its `CREATE TABLE` statement.
+  pub fn parse(sql: &str) -> SqliteResult<Self> {
+    Parser::new(tokenize(sql)?).create_table()
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn columns(&self) -> &[ColumnDefinition] {
+    &self.columns
+  }
+
+  pub fn primary_key(&self) -> &[PrimaryKeyColumn] {
+    &self.primary_key
+  }
+
+  /// Position of the column named `name`, ignoring ASCII case.
+  pub fn column_index(&self, name: &str) -> Option<usize> {
+    self
+      .columns
+      .iter()
+      .position(|column| column.name.eq_ignore_ascii_case(name))
+  }
+
+  pub fn is_without_rowid(&self) -> bool {
+    self.without_rowid
+  }
+
+  pub fn is_strict(&self) -> bool {
+    self.strict
+  }
+
+  /// The column acting as an alias of the rowid: the only column of the
+  /// primary key of a rowid table when declared with type `INTEGER`.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_createtable.html#rowid
+  pub fn rowid_alias(&self) -> Option<usize> {
+    match self.primary_key.as_slice() {
+      [key] if !self.without_rowid && !self.descending_column_key => {
+        let declared_type = self.columns[key.column].declared_type.as_deref();
+        declared_type
+          .is_some_and(|ty| ty.eq_ignore_ascii_case("INTEGER"))
+          .then_some(key.column)
+      }
+      _ => None,
+    }
+  }
+
+  /// The collating sequences of the columns and of the primary key.
+  pub(crate) fn collations_mut(
+    &mut self,
+  ) -> impl Iterator<Item = &mut Collation> {
+    let columns = self.columns.iter_mut().map(|column| &mut column.collation);
+    columns.chain(self.primary_key.iter_mut().map(|key| &mut key.collation))
+  }
+
+  /// Order and collating sequences of the primary key columns, the key of
+  /// the index b-tree of a `WITHOUT ROWID` table.
+  pub fn key_info(&self) -> KeyInfo {
+    KeyInfo::new(
+      self
+        .primary_key
+        .iter()
+        .map(|key| KeyColumn {
+          descending: key.descending,
+          collation: key.collation.clone(),
+        })
+        .collect(),
+    )
+  }
+
+  /// Table columns in the order their values are stored in records. A
+  /// `WITHOUT ROWID` table stores the primary key columns first, followed by
+  /// the other columns in the order they were declared.
+  ///
+  /// *Reference:* https://www.sqlite.org/withoutrowid.html
+  pub fn storage_order(&self) -> Vec<usize> {
+    if !self.without_rowid {
+      return (0..self.columns.len()).collect();
+    }
+    let mut order: Vec<usize> = vec![];
+    for key in self.primary_key.iter() {
+      if !order.contains(&key.column) {
+        order.push(key.column);
+      }
+    }
+    let others: Vec<usize> = (0..self.columns.len())
+      .filter(|idx| !order.contains(idx))
+      .collect();
+    order.extend(others);
+    order
+  }
+
+  /// Maps a stored record back to the declared column order. Columns missing
+  /// from records written before they were added read as their default, and
+  /// the rowid alias reads as the rowid.
+  pub fn row_from_record(
+    &self,
+    record: Vec<Value>,
+    rowid: Option<i64>,
+  ) -> Vec<Value> {
+    let order = self.storage_order();
+    let mut row = vec![Value::Null; self.columns.len()];
+    for column in order.iter().skip(record.len()) {
+      let definition = &self.columns[*column];
+      if let Some(default) = &definition.default {
+        row[*column] = definition.affinity().apply(default.clone());
+      }
+    }
+    for (column, value) in order.into_iter().zip(record) {
+      row[column] = value;
+    }
+    if let (Some(alias), Some(rowid)) = (self.rowid_alias(), rowid) {
+      row[alias] = Value::Integer(rowid);
+    }
+    row
+  }
+}
+
+impl ColumnDefinition {
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn declared_type(&self) -> Option<&str> {
+    self.declared_type.as_deref()
+  }
+
+  /// The affinity given to the column by its declared type.
+  pub fn affinity(&self) -> Affinity {
+    Affinity::from_declared_type(self.declared_type.as_deref())
+  }
+
+  pub fn collation(&self) -> &Collation {
+    &self.collation
+  }
+
+  pub fn is_not_null(&self) -> bool {
+    self.not_null
+  }
+
+  /// The `DEFAULT` of the column, when it is a literal.
+  pub fn default(&self) -> Option<&Value> {
+    self.default.as_ref()
+  }
+}
+
+impl PrimaryKeyColumn {
+  pub fn column(&self) -> usize {
+    self.column
+  }
+
+  pub fn is_descending(&self) -> bool {
+    self.descending
+  }
+
+  pub fn collation(&self) -> &Collation {
+    &self.collation
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  /// A keyword or identifier. Quoted identifiers are never keywords.
+  Word {
+    text: String,
+    quoted: bool,
+  },
+  Literal(String),
+  Punct(char),
+}
+
+impl Token {
+  fn is_keyword(&self, keyword: &str) -> bool {
+    matches!(
+      self,
+      Self::Word { text, quoted: false } if text.eq_ignore_ascii_case(keyword)
+    )
+  }
+
+  fn text(&self) -> String {
+    match self {
+      Self::Word { text, .. } | Self::Literal(text) => text.clone(),
+      Self::Punct(punct) => punct.to_string(),
+    }
+  }
+}
+
+fn malformed(reason: &str) -> SqliteError {
+  SqliteError::Corrupt(format!("Malformed CREATE TABLE statement: {reason}"))
+}
+
+/// Splits SQL text into the few kinds of tokens table definitions need.
+fn tokenize(sql: &str) -> SqliteResult<Vec<Token>> {
+  let mut tokens = vec![];
+  let mut chars = sql.chars().peekable();
+  while let Some(char) = chars.next() {
+    match char {
+      _ if char.is_whitespace() => (),
+      '-' if chars.peek() == Some(&'-') => {
+        chars.by_ref().find(|char| *char == '\n');
+      }
+      '/' if chars.peek() == Some(&'*') => {
+        chars.next();
+        let mut previous = ' ';
+        for char in chars.by_ref() {
+          if previous == '*' && char == '/' {
+            break;
+          }
+          previous = char;
+        }
+      }
+      '"' | '`' | '[' | '\'' => {
+        let close = if char == '[' { ']' } else { char };
+        let mut text = String::new();
+        loop {
+          match chars.next() {
+            Some(next) if next == close => {
+              // Quotes are escaped by doubling them.
+              if close != ']' && chars.peek() == Some(&close) {
+                chars.next();
+                text.push(close);
+              } else {
+                break;
+              }
+            }
+            Some(next) => text.push(next),
+            None => return Err(malformed("unterminated quote")),
+          }
+        }
+        tokens.push(match char {
+          '\'' => Token::Literal(text),
+          _ => Token::Word { text, quoted: true },
+        });
+      }
+      _ if char.is_alphanumeric() || char == '_' || char == '$' => {
+        let mut text = char.to_string();
+        while let Some(next) = chars.peek() {
+          let is_word = next.is_alphanumeric() || *next == '_' || *next == '$';
+          let is_number = char.is_ascii_digit() && *next == '.';
+          if !is_word && !is_number {
+            break;
+          }
+          text.push(*next);
+          chars.next();
+        }
+        tokens.push(Token::Word {
+          text,
+          quoted: false,
+        });
+      }
+      _ => tokens.push(Token::Punct(char)),
+    }
+  }
+  Ok(tokens)
+}
+
+/// The value of a numeric literal, decimal or hexadecimal.
+fn number(text: &str) -> Option<Value> {
+  match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+    Some(hex) => u64::from_str_radix(hex, 16)
+      .ok()
+      .map(|int| Value::Integer(int as i64)),
+    None => match parse_number(text)? {
+      (value, true) => Some(value),
+      (_, false) => None,
+    },
+  }
+}
+
+/// The bytes of the hexadecimal digits of a blob literal.
+fn blob(hex: &str) -> Option<Vec<u8>> {
+  if hex.len() % 2 != 0 {
+    return None;
+  }
+  (0..hex.len())
+    .step_by(2)
+    .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+    .collect()
+}
+
+/// Words that end the type of a column definition and start its
+/// constraints.
+const COLUMN_CONSTRAINTS: [&str; 11] = [
+  "CONSTRAINT",
+  "PRIMARY",
+  "NOT",
+  "NULL",
+  "UNIQUE",
+  "CHECK",
+  "DEFAULT",
+  "COLLATE",
+  "REFERENCES",
+  "GENERATED",
+  "AS",
+];
+
+struct Parser {
+  tokens: Vec<Token>,
+  position: usize,
+}
+
+impl Parser {
+  fn new(tokens: Vec<Token>) -> Self {
+    Self {
+      tokens,
+      position: 0,
+    }
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.position).cloned();
+    self.position += 1;
+    token
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    let is_match = self.peek().is_some_and(|token| token.is_keyword(keyword));
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn expect_keyword(&mut self, keyword: &str) -> SqliteResult<()> {
+    if self.eat_keyword(keyword) {
+      Ok(())
+    } else {
+      Err(malformed(&format!("expected {keyword}")))
+    }
+  }
+
+  fn eat_punct(&mut self, punct: char) -> bool {
+    let is_match = self.peek() == Some(&Token::Punct(punct));
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn name(&mut self) -> SqliteResult<String> {
+    match self.next() {
+      Some(Token::Word { text, .. } | Token::Literal(text)) => Ok(text),
+      _ => Err(malformed("expected a name")),
+    }
+  }
+
+  /// Skips a parenthesized group, the opening parenthesis being the next
+  /// token.
+  fn skip_group(&mut self) -> SqliteResult<()> {
+    let mut depth = 0;
+    while let Some(token) = self.next() {
+      match token {
+        Token::Punct('(') => depth += 1,
+        Token::Punct(')') => depth -= 1,
+        _ => (),
+      }
+      if depth == 0 {
+        return Ok(());
+      }
+    }
+    Err(malformed("unbalanced parentheses"))
+  }
+
+  /// Whether the next token ends the current column or table constraint.
+  fn at_item_end(&self) -> bool {
+    matches!(self.peek(), None | Some(Token::Punct(',' | ')')))
+  }
+
+  /// Skips tokens up to the end of the current item, or up to the next
+  /// token matching `stop`.
+  fn skip_until(&mut self, stop: impl Fn(&Token) -> bool) -> SqliteResult<()> {
+    while !self.at_item_end() {
+      match self.peek() {
+        Some(token) if stop(token) => break,
+        Some(Token::Punct('(')) => self.skip_group()?,
+        _ => self.position += 1,
+      }
+    }
+    Ok(())
+  }
+
+  fn create_table(mut self) -> SqliteResult<TableDefinition> {
+    self.expect_keyword("CREATE")?;
+    let _ = self.eat_keyword("TEMP") || self.eat_keyword("TEMPORARY");
+    self.expect_keyword("TABLE")?;
+    if self.eat_keyword("IF") {
+      self.expect_keyword("NOT")?;
+      self.expect_keyword("EXISTS")?;
+    }
+    let mut name = self.name()?;
+    if self.eat_punct('.') {
+      name = self.name()?;
+    }
+    let mut table = TableDefinition {
+      name,
+      ..Default::default()
+    };
+    if !self.eat_punct('(') {
+      return Err(malformed("expected column definitions"));
+    }
+    loop {
+      self.table_item(&mut table)?;
+      if self.eat_punct(')') {
+        break;
+      }
+      if !self.eat_punct(',') {
+        return Err(malformed("expected , or )"));
+      }
+    }
+    loop {
+      if self.eat_keyword("WITHOUT") {
+        self.expect_keyword("ROWID")?;
+        table.without_rowid = true;
+      } else if self.eat_keyword("STRICT") {
+        table.strict = true;
+      }
+      if !self.eat_punct(',') {
+        break;
+      }
+    }
+    if table.without_rowid && table.primary_key.is_empty() {
+      return Err(malformed("WITHOUT ROWID table without a PRIMARY KEY"));
+    }
+    Ok(table)
+  }
+
+  /// A column definition or a table constraint.
+  fn table_item(&mut self, table: &mut TableDefinition) -> SqliteResult<()> {
+    if self.eat_keyword("CONSTRAINT") {
+      self.name()?;
+    }
+    if self.eat_keyword("PRIMARY") {
+      self.expect_keyword("KEY")?;
+      return self.table_primary_key(table);
+    }
+    let is_constraint = ["UNIQUE", "CHECK", "FOREIGN"].iter().any(|keyword| {
+      self.peek().is_some_and(|token| token.is_keyword(keyword))
+    });
+    if is_constraint {
+      return self.skip_until(|_| false);
+    }
+    self.column_definition(table)
+  }
+
+  fn table_primary_key(
+    &mut self,
+    table: &mut TableDefinition,
+  ) -> SqliteResult<()> {
+    if !self.eat_punct('(') {
+      return Err(malformed("expected PRIMARY KEY columns"));
+    }
+    loop {
+      let name = self.name()?;
+      let column = table
+        .column_index(&name)
+        .ok_or(malformed(&format!("no such column: {name}")))?;
+      let mut key = PrimaryKeyColumn {
+        column,
+        descending: false,
+        collation: table.columns[column].collation.clone(),
+      };
+      self.key_column_options(&mut key)?;
+      table.primary_key.push(key);
+      if self.eat_punct(')') {
+        break;
+      }
+      if !self.eat_punct(',') {
+        return Err(malformed("expected , or )"));
+      }
+    }
+    self.skip_until(|_| false)
+  }
+
+  /// The `COLLATE` and `ASC` or `DESC` clauses following a key column.
+  fn key_column_options(
+    &mut self,
+    key: &mut PrimaryKeyColumn,
+  ) -> SqliteResult<()> {
+    loop {
+      if self.eat_keyword("COLLATE") {
+        key.collation = self.collation()?;
+      } else if self.eat_keyword("DESC") {
+        key.descending = true;
+      } else if !self.eat_keyword("ASC") {
+        return Ok(());
+      }
+    }
+  }
+
+  /// A collating sequence, unresolved unless it is built in.
+  fn collation(&mut self) -> SqliteResult<Collation> {
+    let name = self.name()?;
+    Ok(
+      Collation::from_name(&name).unwrap_or_else(|| {
+        Collation::Custom(CustomCollation::unresolved(name))
+      }),
+    )
+  }
+
+  /// A literal value, possibly signed or parenthesized.
+  fn literal(&mut self) -> Option<Value> {
+    if self.eat_punct('(') {
+      let value = self.literal()?;
+      return self.eat_punct(')').then_some(value);
+    }
+    let negative = self.eat_punct('-');
+    let signed = negative || self.eat_punct('+');
+    let value = match self.next()? {
+      Token::Literal(text) if !signed => Value::Text(text),
+      Token::Word {
+        mut text,
+        quoted: false,
+      } => {
+        if text.starts_with(|char: char| char.is_ascii_digit()) {
+          // The sign of an exponent splits the number in several tokens.
+          if text.ends_with(['e', 'E']) {
+            if let Some(Token::Punct(sign @ ('+' | '-'))) = self.peek() {
+              text.push(*sign);
+              self.position += 1;
+              text.push_str(&self.next()?.text());
+            }
+          }
+          number(&text)?
+        } else if signed {
+          return None;
+        } else if text.eq_ignore_ascii_case("NULL") {
+          Value::Null
+        } else if text.eq_ignore_ascii_case("TRUE") {
+          Value::Integer(1)
+        } else if text.eq_ignore_ascii_case("FALSE") {
+          Value::Integer(0)
+        } else if text.eq_ignore_ascii_case("X") {
+          match self.next()? {
+            Token::Literal(hex) => Value::Blob(blob(&hex)?),
+            _ => return None,
+          }
+        } else {
+          return None;
+        }
+      }
+      _ => return None,
+    };
+    Some(match (negative, value) {
+      (true, Value::Integer(int)) => Value::Integer(int.wrapping_neg()),
+      (true, Value::Real(real)) => Value::Real(-real),
+      (_, value) => value,
+    })
+  }
+
+  fn column_definition(
+    &mut self,
+    table: &mut TableDefinition,
+  ) -> SqliteResult<()> {
+    let name = self.name()?;
+    // The type is a sequence of names, optionally followed by one or two
+    // numbers within parentheses.
+    let mut names: Vec<String> = vec![];
+    while let Some(Token::Word { text, .. }) = self.peek() {
+      if COLUMN_CONSTRAINTS.iter().any(|keyword| {
+        self.peek().is_some_and(|token| token.is_keyword(keyword))
+      }) {
+        break;
+      }
+      names.push(text.clone());
+      self.position += 1;
+    }
+    let mut declared_type = (!names.is_empty()).then(|| names.join(" "));
+    if self.peek() == Some(&Token::Punct('(')) {
+      let start = self.position;
+      self.skip_group()?;
+      let size: String = self.tokens[start..self.position]
+        .iter()
+        .map(Token::text)
+        .collect();
+      declared_type = declared_type.map(|names| names + &size);
+    }
+
+    let column = table.columns.len();
+    table.columns.push(ColumnDefinition {
+      name,
+      declared_type,
+      ..Default::default()
+    });
+    let mut primary_key = None;
+    while !self.at_item_end() {
+      if self.eat_keyword("CONSTRAINT") {
+        self.name()?;
+      } else if self.eat_keyword("PRIMARY") {
+        self.expect_keyword("KEY")?;
+        let mut key = PrimaryKeyColumn {
+          column,
+          ..Default::default()
+        };
+        self.key_column_options(&mut key)?;
+        primary_key = Some(key);
+      } else if self.eat_keyword("NOT") {
+        self.expect_keyword("NULL")?;
+        table.columns[column].not_null = true;
+      } else if self.eat_keyword("COLLATE") {
+        table.columns[column].collation = self.collation()?;
+      } else if self.eat_keyword("DEFAULT") {
+        let start = self.position;
+        table.columns[column].default = self.literal();
+        if table.columns[column].default.is_none() {
+          self.position = start;
+          self.skip_until(|token| {
+            COLUMN_CONSTRAINTS
+              .iter()
+              .any(|keyword| token.is_keyword(keyword))
+          })?;
+        }
+      } else {
+        self.position += 1;
+        self.skip_until(|token| {
+          COLUMN_CONSTRAINTS
+            .iter()
+            .any(|keyword| token.is_keyword(keyword))
+        })?;
+      }
+    }
+    if let Some(mut key) = primary_key {
+      // The COLLATE clause may follow PRIMARY KEY.
+      key.collation = table.columns[column].collation.clone();
+      table.descending_column_key = key.descending;
+      table.primary_key = vec![key];
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,197 @@
+//! # Table access
+//!
+//!  Ordinary tables are stored in table b-trees keyed by rowid, while tables
+//! declared `WITHOUT ROWID` are stored in index b-trees keyed by their
+//! primary key, with the primary key columns moved to the front of each
+//! record. A table cursor hides that difference and reads rows in the
+//! declared column order.
+//!
+//! *Reference:* https://www.sqlite.org/withoutrowid.html
+
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::record::{compare_values, KeyInfo, Value};
+use crate::runtime::schema::TableDefinition;
+use crate::runtime::{BtreeCursor, SqliteBtree};
+use core::cmp::Ordering;
+
+/// A position within a table, in rowid order for rowid tables and in primary
+/// key order for `WITHOUT ROWID` tables.
+#[derive(Debug)]
+pub struct TableCursor {
+  definition: TableDefinition,
+  key_info: KeyInfo,
+  cursor: BtreeCursor,
+}
+
+impl TableCursor {
+  pub fn new(definition: TableDefinition, root: u32) -> Self {
+    Self {
+      key_info: definition.key_info(),
+      definition,
+      cursor: BtreeCursor::new(root),
+    }
+  }
+
+  pub fn definition(&self) -> &TableDefinition {
+    &self.definition
+  }
+
+  /// Root page of the b-tree holding the rows.
+  pub fn root(&self) -> u32 {
+    self.cursor.root()
+  }
+
+  /// True when the cursor does not point to a row.
+  pub fn is_eof(&self) -> bool {
+    self.cursor.is_eof()
+  }
+
+  /// Moves to the first row. Returns false if the table is empty.
+  pub fn first(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.cursor.first(btree)
+  }
+
+  /// Advances to the next row. Returns false once past the last one.
+  pub fn next(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.cursor.next(btree)
+  }
+
+  /// Moves to the last row. Returns false if the table is empty.
+  pub fn last(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.cursor.last(btree)
+  }
+
+  /// Moves back to the previous row. Returns false once before the first
+  /// one.
+  pub fn prev(&mut self, btree: &mut SqliteBtree<'_>) -> SqliteResult<bool> {
+    self.cursor.prev(btree)
+  }
+
+  /// The cursor on the entries of the b-tree of a `WITHOUT ROWID` table,
+  /// which is looked up by primary key as an index is, and how the entries
+  /// compare. `None` on rowid tables.
+  pub fn key_cursor(&mut self) -> Option<(&mut BtreeCursor, &KeyInfo)> {
+    match self.definition.is_without_rowid() {
+      true => Some((&mut self.cursor, &self.key_info)),
+      false => None,
+    }
+  }
+
+  /// Rowid of the current row, `None` on `WITHOUT ROWID` tables.
+  pub fn rowid(&self, btree: &SqliteBtree<'_>) -> SqliteResult<Option<i64>> {
+    if self.definition.is_without_rowid() {
+      return Ok(None);
+    }
+    self.cursor.rowid(btree).map(Some)
+  }
+
+  /// Values of the current row, in the declared column order.
+  pub fn row(&self, btree: &mut SqliteBtree<'_>) -> SqliteResult<Vec<Value>> {
+    let rowid = self.rowid(btree)?;
+    let record = self.cursor.record(btree)?;
+    Ok(self.definition.row_from_record(record, rowid))
+  }
+
+  /// Moves to the row with the given `rowid`. Returns false, leaving the
+  /// cursor at end of file, when there is none or the table is `WITHOUT
+  /// ROWID`.
+  pub fn seek_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    if self.definition.is_without_rowid() {
+      self.cursor.reset();
+      return Ok(false);
+    }
+    self.cursor.seek_rowid(btree, rowid)
+  }
+
+  /// Moves to the first row whose rowid is not less than `rowid`. Returns
+  /// false, leaving the cursor at end of file, when there is none or the
+  /// table is `WITHOUT ROWID`.
+  pub fn seek_first_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    if self.definition.is_without_rowid() {
+      self.cursor.reset();
+      return Ok(false);
+    }
+    self.cursor.seek_first_rowid(btree, rowid)
+  }
+
+  /// Moves to the last row whose rowid is not greater than `rowid`. Returns
+  /// false, leaving the cursor at end of file, when there is none or the
+  /// table is `WITHOUT ROWID`.
+  pub fn seek_last_rowid(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    rowid: i64,
+  ) -> SqliteResult<bool> {
+    if self.definition.is_without_rowid() {
+      self.cursor.reset();
+      return Ok(false);
+    }
+    self.cursor.seek_last_rowid(btree, rowid)
+  }
+
+  /// Moves to the row whose primary key equals `key`, given in the order of
+  /// the primary key columns. Returns false, leaving the cursor at end of
+  /// file, when there is none.
+  ///
+  ///  `WITHOUT ROWID` tables and tables whose primary key is an alias of the
+  /// rowid are searched through their b-tree; other tables are scanned.
+  pub fn seek_primary_key(
+    &mut self,
+    btree: &mut SqliteBtree<'_>,
+    key: &[Value],
+  ) -> SqliteResult<bool> {
+    let key_len = self.definition.primary_key().len();
+    if key_len == 0 || key.len() != key_len {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] has a primary key of {key_len} columns",
+        self.definition.name()
+      )));
+    }
+    if self.definition.is_without_rowid() {
+      return self.cursor.seek_key(btree, key, &self.key_info);
+    }
+    if self.definition.rowid_alias().is_some() {
+      return match as_rowid(&key[0]) {
+        Some(rowid) => self.cursor.seek_rowid(btree, rowid),
+        None => {
+          self.cursor.reset();
+          Ok(false)
+        }
+      };
+    }
+    let mut has_row = self.first(btree)?;
+    while has_row {
+      let row = self.row(btree)?;
+      let primary_key = self.definition.primary_key().iter();
+      let is_match = primary_key.zip(key).all(|(column, value)| {
+        compare_values(&row[column.column()], value, column.collation())
+          == Ordering::Equal
+      });
+      if is_match {
+        return Ok(true);
+      }
+      has_row = self.next(btree)?;
+    }
+    Ok(false)
+  }
+}
+
+/// The rowid designated by a primary key value, when it is an integer.
+fn as_rowid(value: &Value) -> Option<i64> {
+  match value {
+    Value::Integer(int) => Some(*int),
+    Value::Real(real) => {
+      let int = *real as i64;
+      (int as f64 == *real).then_some(int)
+    }
+    _ => None,
+  }
+}
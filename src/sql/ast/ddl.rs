@@ -0,0 +1,213 @@
+use super::{
+  ConflictResolution, Expr, Name, QualifiedName, Select, SortOrder, Statement,
+  TypeName,
+};
+
+/// *Reference:* https://www.sqlite.org/lang_createtable.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+  pub temporary: bool,
+  pub if_not_exists: bool,
+  pub name: QualifiedName,
+  pub body: CreateTableBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CreateTableBody {
+  Columns {
+    columns: Vec<ColumnDefinition>,
+    constraints: Vec<TableConstraint>,
+    options: TableOptions,
+  },
+  AsSelect(Box<Select>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableOptions {
+  pub without_rowid: bool,
+  pub strict: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDefinition {
+  pub name: Name,
+  pub type_name: Option<TypeName>,
+  pub constraints: Vec<ColumnConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnConstraint {
+  /// `CONSTRAINT name`.
+  pub name: Option<Name>,
+  pub kind: ColumnConstraintKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraintKind {
+  PrimaryKey {
+    order: Option<SortOrder>,
+    conflict: Option<ConflictResolution>,
+    autoincrement: bool,
+  },
+  NotNull(Option<ConflictResolution>),
+  /// `NULL`, which SQLite accepts and ignores.
+  Null,
+  Unique(Option<ConflictResolution>),
+  Check(Expr),
+  Default(Expr),
+  Collate(Name),
+  ForeignKey(ForeignKeyClause),
+  /// `GENERATED ALWAYS AS (expr)`, `VIRTUAL` unless `stored`.
+  Generated {
+    expr: Expr,
+    stored: bool,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableConstraint {
+  pub name: Option<Name>,
+  pub kind: TableConstraintKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraintKind {
+  PrimaryKey {
+    columns: Vec<IndexedColumn>,
+    conflict: Option<ConflictResolution>,
+    autoincrement: bool,
+  },
+  Unique {
+    columns: Vec<IndexedColumn>,
+    conflict: Option<ConflictResolution>,
+  },
+  Check(Expr),
+  ForeignKey {
+    columns: Vec<Name>,
+    clause: ForeignKeyClause,
+  },
+}
+
+/// `REFERENCES table (columns) ...`
+///
+/// *Reference:* https://www.sqlite.org/foreignkeys.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKeyClause {
+  pub table: Name,
+  pub columns: Vec<Name>,
+  pub on_delete: Option<ForeignKeyAction>,
+  pub on_update: Option<ForeignKeyAction>,
+  /// `DEFERRABLE INITIALLY DEFERRED`. Every other form is immediate.
+  pub deferred: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignKeyAction {
+  SetNull,
+  SetDefault,
+  Cascade,
+  Restrict,
+  NoAction,
+}
+
+/// A column of an index or key: a column name or an expression, possibly
+/// with a `COLLATE` clause, and a sort order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+  pub expr: Expr,
+  pub order: Option<SortOrder>,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_createindex.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+  pub unique: bool,
+  pub if_not_exists: bool,
+  pub name: QualifiedName,
+  pub table: Name,
+  pub columns: Vec<IndexedColumn>,
+  pub where_clause: Option<Expr>,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_createview.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateView {
+  pub temporary: bool,
+  pub if_not_exists: bool,
+  pub name: QualifiedName,
+  pub columns: Vec<Name>,
+  pub select: Box<Select>,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_createtrigger.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTrigger {
+  pub temporary: bool,
+  pub if_not_exists: bool,
+  pub name: QualifiedName,
+  pub time: TriggerTime,
+  pub event: TriggerEvent,
+  pub table: QualifiedName,
+  /// `FOR EACH ROW`, the only kind of trigger SQLite has.
+  pub for_each_row: bool,
+  pub when: Option<Expr>,
+  /// `INSERT`, `UPDATE`, `DELETE` and `SELECT` statements.
+  pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TriggerTime {
+  #[default]
+  Before,
+  After,
+  InsteadOf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerEvent {
+  Delete,
+  Insert,
+  /// `UPDATE` or `UPDATE OF columns`.
+  Update(Vec<Name>),
+}
+
+/// *Reference:* https://www.sqlite.org/lang_createvtab.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateVirtualTable {
+  pub if_not_exists: bool,
+  pub name: QualifiedName,
+  pub module: Name,
+  /// The module arguments, as written.
+  pub arguments: Vec<String>,
+}
+
+/// `DROP TABLE`, `DROP INDEX`, `DROP VIEW` or `DROP TRIGGER`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drop {
+  pub object: ObjectKind,
+  pub if_exists: bool,
+  pub name: QualifiedName,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+  Table,
+  Index,
+  View,
+  Trigger,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_altertable.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterTable {
+  pub name: QualifiedName,
+  pub action: AlterTableAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableAction {
+  RenameTo(Name),
+  RenameColumn { old: Name, new: Name },
+  AddColumn(ColumnDefinition),
+  DropColumn(Name),
+}
@@ -0,0 +1,97 @@
+use super::{
+  Expr, From, IndexedBy, IndexedColumn, Limit, Name, OrderingTerm,
+  QualifiedName, ResultColumn, Select, With,
+};
+
+/// The `ON CONFLICT` algorithm, also written `INSERT OR ...`.
+///
+/// *Reference:* https://www.sqlite.org/lang_conflict.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+  Rollback,
+  Abort,
+  Fail,
+  Ignore,
+  Replace,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_insert.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+  pub with: Option<With>,
+  /// `INSERT OR ...`, `REPLACE` being `OR REPLACE`.
+  pub conflict: Option<ConflictResolution>,
+  pub table: QualifiedName,
+  pub alias: Option<Name>,
+  pub columns: Vec<Name>,
+  pub source: InsertSource,
+  pub upserts: Vec<Upsert>,
+  pub returning: Vec<ResultColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertSource {
+  /// `VALUES` rows or a `SELECT`.
+  Select(Box<Select>),
+  DefaultValues,
+}
+
+/// An `ON CONFLICT` clause of an `INSERT`.
+///
+/// *Reference:* https://www.sqlite.org/lang_upsert.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upsert {
+  pub target: Option<UpsertTarget>,
+  pub action: UpsertAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpsertTarget {
+  pub columns: Vec<IndexedColumn>,
+  pub where_clause: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpsertAction {
+  Nothing,
+  Update {
+    assignments: Vec<Assignment>,
+    where_clause: Option<Expr>,
+  },
+}
+
+/// `column = expr` or `(column, ...) = expr` in an `UPDATE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+  pub columns: Vec<Name>,
+  pub value: Expr,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_update.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+  pub with: Option<With>,
+  pub conflict: Option<ConflictResolution>,
+  pub table: QualifiedName,
+  pub alias: Option<Name>,
+  pub indexed_by: Option<IndexedBy>,
+  pub assignments: Vec<Assignment>,
+  pub from: Option<From>,
+  pub where_clause: Option<Expr>,
+  pub returning: Vec<ResultColumn>,
+  pub order_by: Vec<OrderingTerm>,
+  pub limit: Option<Limit>,
+}
+
+/// *Reference:* https://www.sqlite.org/lang_delete.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+  pub with: Option<With>,
+  pub table: QualifiedName,
+  pub alias: Option<Name>,
+  pub indexed_by: Option<IndexedBy>,
+  pub where_clause: Option<Expr>,
+  pub returning: Vec<ResultColumn>,
+  pub order_by: Vec<OrderingTerm>,
+  pub limit: Option<Limit>,
+}
@@ -0,0 +1,369 @@
+use super::{Name, OrderingTerm, QualifiedName, Select, Span};
+
+/// An expression, and the text it was parsed from.
+///
+/// *Reference:* https://www.sqlite.org/lang_expr.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+  pub kind: ExprKind,
+  pub span: Span,
+}
+
+impl Expr {
+  pub fn new(kind: ExprKind, span: Span) -> Self {
+    Self { kind, span }
+  }
+
+  /// Calls `f` on the expression and on every expression within it, those
+  /// of subqueries aside, parents before their children.
+  pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+    f(self);
+    match &self.kind {
+      ExprKind::Literal(_)
+      | ExprKind::Variable(_)
+      | ExprKind::Column { .. }
+      | ExprKind::Exists(_)
+      | ExprKind::Subquery(_) => {}
+      ExprKind::Unary { expr, .. }
+      | ExprKind::IsNull { expr, .. }
+      | ExprKind::Collate { expr, .. }
+      | ExprKind::Cast { expr, .. } => expr.walk(f),
+      ExprKind::Binary { left, right, .. }
+      | ExprKind::Is { left, right, .. } => {
+        left.walk(f);
+        right.walk(f);
+      }
+      ExprKind::Like {
+        expr,
+        pattern,
+        escape,
+        ..
+      } => {
+        expr.walk(f);
+        pattern.walk(f);
+        if let Some(escape) = escape {
+          escape.walk(f);
+        }
+      }
+      ExprKind::Between {
+        expr, low, high, ..
+      } => {
+        expr.walk(f);
+        low.walk(f);
+        high.walk(f);
+      }
+      ExprKind::In { expr, target, .. } => {
+        expr.walk(f);
+        match target {
+          InTarget::List(exprs)
+          | InTarget::TableFunction {
+            arguments: exprs, ..
+          } => exprs.iter().for_each(|expr| expr.walk(f)),
+          InTarget::Select(_) | InTarget::Table(_) => {}
+        }
+      }
+      ExprKind::Function(call) => call.walk(f),
+      ExprKind::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        if let Some(operand) = operand {
+          operand.walk(f);
+        }
+        for (when, then) in when_then {
+          when.walk(f);
+          then.walk(f);
+        }
+        if let Some(else_expr) = else_expr {
+          else_expr.walk(f);
+        }
+      }
+      ExprKind::Vector(exprs) => exprs.iter().for_each(|expr| expr.walk(f)),
+      ExprKind::Raise { message, .. } => {
+        if let Some(message) = message {
+          message.walk(f);
+        }
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+  Literal(Literal),
+  Variable(Variable),
+  /// A column reference, `column`, `table.column` or `schema.table.column`.
+  /// Bare `TRUE` and `FALSE` are parsed as columns, as in SQLite, and only
+  /// become literals when no such column exists.
+  Column {
+    schema: Option<Name>,
+    table: Option<Name>,
+    column: Name,
+  },
+  Unary {
+    operator: UnaryOperator,
+    expr: Box<Expr>,
+  },
+  Binary {
+    left: Box<Expr>,
+    operator: BinaryOperator,
+    right: Box<Expr>,
+  },
+  /// `left IS right`, `left IS NOT right` and their `IS [NOT] DISTINCT FROM`
+  /// spellings.
+  Is {
+    left: Box<Expr>,
+    not: bool,
+    right: Box<Expr>,
+  },
+  /// `expr ISNULL`, `expr NOTNULL` or `expr NOT NULL`.
+  IsNull {
+    expr: Box<Expr>,
+    not: bool,
+  },
+  Like {
+    expr: Box<Expr>,
+    not: bool,
+    operator: LikeOperator,
+    pattern: Box<Expr>,
+    escape: Option<Box<Expr>>,
+  },
+  Between {
+    expr: Box<Expr>,
+    not: bool,
+    low: Box<Expr>,
+    high: Box<Expr>,
+  },
+  In {
+    expr: Box<Expr>,
+    not: bool,
+    target: InTarget,
+  },
+  Collate {
+    expr: Box<Expr>,
+    collation: Name,
+  },
+  Cast {
+    expr: Box<Expr>,
+    type_name: TypeName,
+  },
+  Function(Box<FunctionCall>),
+  Case {
+    operand: Option<Box<Expr>>,
+    when_then: Vec<(Expr, Expr)>,
+    else_expr: Option<Box<Expr>>,
+  },
+  Exists(Box<Select>),
+  /// A scalar subquery.
+  Subquery(Box<Select>),
+  /// A row value, `(a, b, ...)`, with at least two members. A single
+  /// parenthesized expression is not kept as such.
+  Vector(Vec<Expr>),
+  /// `RAISE(...)`, only valid within the body of a trigger.
+  Raise {
+    action: RaiseAction,
+    message: Option<Box<Expr>>,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+  Null,
+  Integer(i64),
+  Real(f64),
+  String(String),
+  Blob(Vec<u8>),
+  CurrentTime,
+  CurrentDate,
+  CurrentTimestamp,
+}
+
+/// A statement parameter. Parameters are numbered like in SQLite: `?NNN`
+/// takes number NNN, other parameters take the number following the largest
+/// one so far, and every occurrence of a named parameter shares its number.
+///
+/// *Reference:* https://www.sqlite.org/c3ref/bind_blob.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+  /// The parameter as written, like `?`, `?3` or `:name`.
+  pub name: String,
+  pub index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+  /// `-`
+  Negate,
+  /// `+`
+  Plus,
+  /// `NOT`
+  Not,
+  /// `~`
+  BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+  Or,
+  And,
+  /// `=` or `==`
+  Eq,
+  /// `!=` or `<>`
+  NotEq,
+  Lt,
+  LtEq,
+  Gt,
+  GtEq,
+  BitAnd,
+  BitOr,
+  ShiftLeft,
+  ShiftRight,
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Modulo,
+  /// `||`
+  Concat,
+  /// `->`
+  Extract,
+  /// `->>`
+  ExtractValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LikeOperator {
+  Like,
+  Glob,
+  Regexp,
+  Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InTarget {
+  /// `IN (expr, ...)`, possibly empty.
+  List(Vec<Expr>),
+  Select(Box<Select>),
+  /// `IN table`.
+  Table(QualifiedName),
+  /// `IN table_function(args)`.
+  TableFunction {
+    name: QualifiedName,
+    arguments: Vec<Expr>,
+  },
+}
+
+/// The declared type of a column or the target type of a `CAST`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeName {
+  /// The type name words, separated by single spaces, like `VARCHAR` or
+  /// `UNSIGNED BIG INT`.
+  pub name: String,
+  /// The numbers between parentheses, as written, like `["10", "2"]`.
+  pub arguments: Vec<String>,
+}
+
+/// A function call, with its aggregate and window clauses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+  pub name: Name,
+  pub distinct: bool,
+  pub arguments: FunctionArguments,
+  /// `ORDER BY` within the arguments of an aggregate.
+  pub order_by: Vec<OrderingTerm>,
+  pub filter: Option<Expr>,
+  pub over: Option<Over>,
+}
+
+impl FunctionCall {
+  /// Walks the arguments and clauses of the call, see [`Expr::walk`].
+  fn walk(&self, f: &mut impl FnMut(&Expr)) {
+    if let FunctionArguments::List(arguments) = &self.arguments {
+      arguments.iter().for_each(|argument| argument.walk(f));
+    }
+    self.order_by.iter().for_each(|term| term.expr.walk(f));
+    if let Some(filter) = &self.filter {
+      filter.walk(f);
+    }
+    if let Some(Over::Window(window)) = &self.over {
+      window.partition_by.iter().for_each(|expr| expr.walk(f));
+      window.order_by.iter().for_each(|term| term.expr.walk(f));
+      if let Some(frame) = &window.frame {
+        for bound in [&frame.start, &frame.end] {
+          if let FrameBound::Preceding(expr) | FrameBound::Following(expr) =
+            bound
+          {
+            expr.walk(f);
+          }
+        }
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionArguments {
+  /// `count(*)`.
+  Star,
+  List(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Over {
+  /// `OVER name`, a window of the `WINDOW` clause.
+  Name(Name),
+  Window(WindowDefinition),
+}
+
+/// *Reference:* https://www.sqlite.org/windowfunctions.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowDefinition {
+  /// The window this one is based on.
+  pub base: Option<Name>,
+  pub partition_by: Vec<Expr>,
+  pub order_by: Vec<OrderingTerm>,
+  pub frame: Option<WindowFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFrame {
+  pub unit: FrameUnit,
+  pub start: FrameBound,
+  /// `CURRENT ROW` when there is no `BETWEEN`.
+  pub end: FrameBound,
+  pub exclude: FrameExclude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnit {
+  Rows,
+  Range,
+  Groups,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBound {
+  UnboundedPreceding,
+  Preceding(Box<Expr>),
+  CurrentRow,
+  Following(Box<Expr>),
+  UnboundedFollowing,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameExclude {
+  #[default]
+  NoOthers,
+  CurrentRow,
+  Group,
+  Ties,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaiseAction {
+  Ignore,
+  Rollback,
+  Abort,
+  Fail,
+}
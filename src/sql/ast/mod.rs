@@ -0,0 +1,137 @@
+//! # Abstract syntax tree
+//!
+//!  The parser assembles tokens into a parse tree. Statements and expressions
+//! keep the byte range of the SQL text they were parsed from.
+//!
+//! *Reference:* https://www.sqlite.org/lang.html
+
+mod ddl;
+mod dml;
+mod expr;
+mod select;
+
+pub use self::ddl::{
+  AlterTable, AlterTableAction, ColumnConstraint, ColumnConstraintKind,
+  ColumnDefinition, CreateIndex, CreateTable, CreateTableBody, CreateTrigger,
+  CreateView, CreateVirtualTable, Drop, ForeignKeyAction, ForeignKeyClause,
+  IndexedColumn, ObjectKind, TableConstraint, TableConstraintKind,
+  TableOptions, TriggerEvent, TriggerTime,
+};
+pub use self::dml::{
+  Assignment, ConflictResolution, Delete, Insert, InsertSource, Update, Upsert,
+  UpsertAction, UpsertTarget,
+};
+pub use self::expr::{
+  BinaryOperator, Expr, ExprKind, FrameBound, FrameExclude, FrameUnit,
+  FunctionArguments, FunctionCall, InTarget, LikeOperator, Literal, Over,
+  RaiseAction, TypeName, UnaryOperator, Variable, WindowDefinition,
+  WindowFrame,
+};
+pub use self::select::{
+  CommonTableExpression, CompoundOperator, From, IndexedBy, Join,
+  JoinConstraint, JoinKind, Limit, NamedWindow, NullsOrder, OrderingTerm,
+  ResultColumn, Select, SelectBody, SelectCore, SimpleSelect, SortOrder,
+  TableOrSubquery, With,
+};
+
+/// Byte range of a piece of SQL text, `start` inclusive and `end` exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Self {
+    Self { start, end }
+  }
+
+  /// The text covered by the span.
+  pub fn text<'a>(&self, sql: &'a str) -> &'a str {
+    &sql[self.start..self.end]
+  }
+}
+
+/// An identifier, without its quotes. Identifiers are compared without
+/// regard to case.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+  pub value: String,
+  /// Whether the identifier was quoted, with `"`, `[]` or `` ` ``.
+  pub quoted: bool,
+  pub span: Span,
+}
+
+impl Name {
+  /// Whether the identifier is `other`, ignoring ASCII case like SQLite.
+  pub fn is(&self, other: &str) -> bool {
+    self.value.eq_ignore_ascii_case(other)
+  }
+}
+
+/// A name optionally qualified by the schema name, like `main.t1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifiedName {
+  pub schema: Option<Name>,
+  pub name: Name,
+}
+
+/// A parsed statement, and the text it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+  pub kind: StatementKind,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+  Select(Box<Select>),
+  Insert(Box<Insert>),
+  Update(Box<Update>),
+  Delete(Box<Delete>),
+  CreateTable(Box<CreateTable>),
+  CreateIndex(Box<CreateIndex>),
+  CreateView(Box<CreateView>),
+  CreateTrigger(Box<CreateTrigger>),
+  CreateVirtualTable(Box<CreateVirtualTable>),
+  Drop(Drop),
+  AlterTable(Box<AlterTable>),
+  Pragma(Box<Pragma>),
+  Begin(Option<TransactionKind>),
+  Commit,
+  /// `ROLLBACK`, or `ROLLBACK TO` a savepoint.
+  Rollback(Option<Name>),
+  Savepoint(Name),
+  Release(Name),
+  Vacuum {
+    schema: Option<Name>,
+    into: Option<Expr>,
+  },
+  Analyze(Option<QualifiedName>),
+  Reindex(Option<QualifiedName>),
+  Attach {
+    file: Expr,
+    schema: Expr,
+  },
+  Detach(Expr),
+  Explain {
+    /// `EXPLAIN QUERY PLAN` rather than `EXPLAIN`.
+    query_plan: bool,
+    statement: Box<Statement>,
+  },
+}
+
+/// `BEGIN DEFERRED`, `IMMEDIATE` or `EXCLUSIVE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+  Deferred,
+  Immediate,
+  Exclusive,
+}
+
+/// `PRAGMA name`, `PRAGMA name = value` or `PRAGMA name(value)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pragma {
+  pub name: QualifiedName,
+  pub value: Option<Expr>,
+}
@@ -0,0 +1,164 @@
+use super::{Expr, Name, QualifiedName, Span, WindowDefinition};
+
+/// A `SELECT` statement, including compound selects and `VALUES`.
+///
+/// *Reference:* https://www.sqlite.org/lang_select.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+  pub with: Option<With>,
+  pub body: SelectBody,
+  pub order_by: Vec<OrderingTerm>,
+  pub limit: Option<Limit>,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct With {
+  pub recursive: bool,
+  pub tables: Vec<CommonTableExpression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonTableExpression {
+  pub name: Name,
+  pub columns: Vec<Name>,
+  /// `AS MATERIALIZED` or `AS NOT MATERIALIZED`, when given.
+  pub materialized: Option<bool>,
+  pub select: Box<Select>,
+}
+
+/// The simple selects of a compound select, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectBody {
+  pub first: SelectCore,
+  pub compounds: Vec<(CompoundOperator, SelectCore)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompoundOperator {
+  Union,
+  UnionAll,
+  Intersect,
+  Except,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectCore {
+  Select(Box<SimpleSelect>),
+  Values(Vec<Vec<Expr>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleSelect {
+  pub distinct: bool,
+  pub columns: Vec<ResultColumn>,
+  pub from: Option<From>,
+  pub where_clause: Option<Expr>,
+  pub group_by: Vec<Expr>,
+  pub having: Option<Expr>,
+  pub windows: Vec<NamedWindow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultColumn {
+  Expr {
+    expr: Expr,
+    alias: Option<Name>,
+  },
+  /// `*`
+  Star,
+  /// `table.*`
+  TableStar(Name),
+}
+
+/// A window of the `WINDOW` clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedWindow {
+  pub name: Name,
+  pub window: WindowDefinition,
+}
+
+/// The `FROM` clause: tables joined left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct From {
+  pub first: TableOrSubquery,
+  pub joins: Vec<Join>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+  pub kind: JoinKind,
+  pub natural: bool,
+  pub table: TableOrSubquery,
+  pub constraint: Option<JoinConstraint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+  /// Tables separated by a comma.
+  Comma,
+  Inner,
+  Cross,
+  Left,
+  Right,
+  Full,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+  On(Expr),
+  Using(Vec<Name>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableOrSubquery {
+  Table {
+    name: QualifiedName,
+    alias: Option<Name>,
+    indexed_by: Option<IndexedBy>,
+  },
+  TableFunction {
+    name: QualifiedName,
+    arguments: Vec<Expr>,
+    alias: Option<Name>,
+  },
+  Subquery {
+    select: Box<Select>,
+    alias: Option<Name>,
+  },
+  /// A parenthesized join.
+  Join(Box<From>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexedBy {
+  Index(Name),
+  NotIndexed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+  pub expr: Expr,
+  pub order: SortOrder,
+  pub nulls: Option<NullsOrder>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+  #[default]
+  Asc,
+  Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullsOrder {
+  First,
+  Last,
+}
+
+/// `LIMIT limit OFFSET offset`, also written `LIMIT offset, limit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+  pub limit: Expr,
+  pub offset: Option<Expr>,
+}
@@ -0,0 +1,63 @@
+use super::ast::Span;
+use crate::result::SqliteError;
+use core::fmt::Display;
+
+/// A malformed SQL text, with the location of the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+  message: String,
+  span: Span,
+  line: usize,
+  column: usize,
+}
+
+impl SyntaxError {
+  pub(crate) fn new(sql: &str, span: Span, message: String) -> Self {
+    let before = &sql[..span.start.min(sql.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let column = before[line_start..].chars().count() + 1;
+    Self {
+      message,
+      span,
+      line,
+      column,
+    }
+  }
+
+  /// The message SQLite gives for the error, like `near "FROM": syntax
+  /// error`.
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+
+  pub fn span(&self) -> Span {
+    self.span
+  }
+
+  /// Line of the offending token, starting at 1.
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  /// Column of the offending token, in characters, starting at 1.
+  pub fn column(&self) -> usize {
+    self.column
+  }
+}
+
+impl Display for SyntaxError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(
+      f,
+      "{} (line {}, column {})",
+      self.message, self.line, self.column
+    )
+  }
+}
+
+impl From<SyntaxError> for SqliteError {
+  fn from(error: SyntaxError) -> Self {
+    Self::Syntax(error)
+  }
+}
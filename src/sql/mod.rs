@@ -0,0 +1,24 @@
+//! # SQL front end
+//!
+//!  The tokenizer and the parser turn SQL text into a parse tree, which the
+//! code generator then compiles into a program.
+//!
+//! ```
+//! use sqlite_rs::sql::{parse, ast::StatementKind};
+//!
+//! let statements = parse("SELECT 1; SELECT 2 -- comment").unwrap();
+//! assert_eq!(statements.len(), 2);
+//! assert!(matches!(statements[0].kind, StatementKind::Select(_)));
+//! ```
+//!
+//! *Reference:* https://www.sqlite.org/arch.html
+
+pub mod ast;
+mod error;
+mod parser;
+mod tokenizer;
+
+pub use self::error::SyntaxError;
+pub(crate) use self::parser::is_keyword;
+pub use self::parser::{parse, Parser};
+pub use self::tokenizer::{tokenize, Token, TokenKind};
@@ -0,0 +1,508 @@
+use super::Parser;
+use crate::result::SqliteResult;
+use crate::sql::ast::{
+  AlterTable, AlterTableAction, ColumnConstraint, ColumnConstraintKind,
+  ColumnDefinition, ConflictResolution, CreateIndex, CreateTable,
+  CreateTableBody, CreateTrigger, CreateView, CreateVirtualTable, Drop, Expr,
+  ForeignKeyAction, ForeignKeyClause, ObjectKind, StatementKind,
+  TableConstraint, TableConstraintKind, TableOptions, TriggerEvent,
+  TriggerTime,
+};
+use crate::sql::tokenizer::TokenKind;
+
+/// Statements allowed in the body of a trigger.
+const TRIGGER_STATEMENTS: &[&str] = &[
+  "SELECT", "VALUES", "WITH", "INSERT", "REPLACE", "UPDATE", "DELETE",
+];
+
+impl Parser<'_> {
+  pub(crate) fn create(&mut self) -> SqliteResult<StatementKind> {
+    self.expect_keyword("CREATE")?;
+    let temporary = self.eat_keyword("TEMP") || self.eat_keyword("TEMPORARY");
+    if self.eat_keyword("TABLE") {
+      return Ok(StatementKind::CreateTable(Box::new(
+        self.create_table(temporary)?,
+      )));
+    }
+    if self.eat_keyword("VIEW") {
+      return Ok(StatementKind::CreateView(Box::new(
+        self.create_view(temporary)?,
+      )));
+    }
+    if self.eat_keyword("TRIGGER") {
+      return Ok(StatementKind::CreateTrigger(Box::new(
+        self.create_trigger(temporary)?,
+      )));
+    }
+    if temporary {
+      return Err(self.error());
+    }
+    if self.eat_keyword("VIRTUAL") {
+      self.expect_keyword("TABLE")?;
+      return Ok(StatementKind::CreateVirtualTable(Box::new(
+        self.create_virtual_table()?,
+      )));
+    }
+    let unique = self.eat_keyword("UNIQUE");
+    self.expect_keyword("INDEX")?;
+    Ok(StatementKind::CreateIndex(Box::new(
+      self.create_index(unique)?,
+    )))
+  }
+
+  fn create_table(&mut self, temporary: bool) -> SqliteResult<CreateTable> {
+    let if_not_exists = self.if_exists(true)?;
+    let name = self.qualified_name()?;
+    if self.eat_keyword("AS") {
+      return Ok(CreateTable {
+        temporary,
+        if_not_exists,
+        name,
+        body: CreateTableBody::AsSelect(Box::new(self.select()?)),
+      });
+    }
+    self.expect(&TokenKind::LeftParen)?;
+    let mut columns = vec![];
+    let mut constraints = vec![];
+    loop {
+      if self.is_table_constraint() {
+        break;
+      }
+      columns.push(self.column_definition()?);
+      if !self.eat(&TokenKind::Comma) {
+        break;
+      }
+    }
+    while self.peek().kind != TokenKind::RightParen {
+      constraints.push(self.table_constraint()?);
+      // Table constraints may be separated by a comma or not at all.
+      self.eat(&TokenKind::Comma);
+    }
+    self.expect(&TokenKind::RightParen)?;
+    let options = self.table_options()?;
+    Ok(CreateTable {
+      temporary,
+      if_not_exists,
+      name,
+      body: CreateTableBody::Columns {
+        columns,
+        constraints,
+        options,
+      },
+    })
+  }
+
+  fn is_table_constraint(&self) -> bool {
+    ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+      .iter()
+      .any(|keyword| self.is_keyword(keyword))
+  }
+
+  /// `WITHOUT ROWID` and `STRICT`, separated by commas.
+  fn table_options(&mut self) -> SqliteResult<TableOptions> {
+    let mut options = TableOptions::default();
+    if !matches!(self.peek().kind, TokenKind::Word(_)) {
+      return Ok(options);
+    }
+    loop {
+      let token = self.peek().clone();
+      if self.eat_keyword("WITHOUT") {
+        if !self.eat_keyword("ROWID") {
+          let name = self.peek().span.text(self.sql());
+          return Err(self.error_at(
+            self.peek().span,
+            format!("unknown table option: {name}"),
+          ));
+        }
+        options.without_rowid = true;
+      } else if self.eat_keyword("STRICT") {
+        options.strict = true;
+      } else {
+        return Err(self.error_at(
+          token.span,
+          format!("unknown table option: {}", token.span.text(self.sql())),
+        ));
+      }
+      if !self.eat(&TokenKind::Comma) {
+        return Ok(options);
+      }
+    }
+  }
+
+  pub(crate) fn column_definition(&mut self) -> SqliteResult<ColumnDefinition> {
+    let name = self.name()?;
+    let type_name = self.type_name()?;
+    let mut constraints = vec![];
+    while let Some(constraint) = self.column_constraint()? {
+      constraints.push(constraint);
+    }
+    Ok(ColumnDefinition {
+      name,
+      type_name,
+      constraints,
+    })
+  }
+
+  fn column_constraint(&mut self) -> SqliteResult<Option<ColumnConstraint>> {
+    let name = match self.eat_keyword("CONSTRAINT") {
+      true => Some(self.name()?),
+      false => None,
+    };
+    let kind = if self.eat_keyword("PRIMARY") {
+      self.expect_keyword("KEY")?;
+      let order = self.sort_order();
+      let conflict = self.on_conflict()?;
+      let autoincrement = self.eat_keyword("AUTOINCREMENT");
+      ColumnConstraintKind::PrimaryKey {
+        order,
+        conflict,
+        autoincrement,
+      }
+    } else if self.is_keyword("NOT") && self.is_keyword_at(1, "NULL") {
+      self.advance();
+      self.advance();
+      ColumnConstraintKind::NotNull(self.on_conflict()?)
+    } else if self.eat_keyword("NULL") {
+      self.on_conflict()?;
+      ColumnConstraintKind::Null
+    } else if self.eat_keyword("UNIQUE") {
+      ColumnConstraintKind::Unique(self.on_conflict()?)
+    } else if self.eat_keyword("CHECK") {
+      ColumnConstraintKind::Check(self.parenthesized_expr()?)
+    } else if self.eat_keyword("DEFAULT") {
+      ColumnConstraintKind::Default(self.default_value()?)
+    } else if self.eat_keyword("COLLATE") {
+      ColumnConstraintKind::Collate(self.name()?)
+    } else if self.is_keyword("REFERENCES") {
+      ColumnConstraintKind::ForeignKey(self.foreign_key_clause()?)
+    } else if self.is_keyword("GENERATED") || self.is_keyword("AS") {
+      if self.eat_keyword("GENERATED") {
+        self.expect_keyword("ALWAYS")?;
+      }
+      self.expect_keyword("AS")?;
+      let expr = self.parenthesized_expr()?;
+      let stored = self.eat_keyword("STORED");
+      if !stored {
+        self.eat_keyword("VIRTUAL");
+      }
+      ColumnConstraintKind::Generated { expr, stored }
+    } else if name.is_some() {
+      return Err(self.error());
+    } else {
+      return Ok(None);
+    };
+    Ok(Some(ColumnConstraint { name, kind }))
+  }
+
+  fn table_constraint(&mut self) -> SqliteResult<TableConstraint> {
+    let name = match self.eat_keyword("CONSTRAINT") {
+      true => Some(self.name()?),
+      false => None,
+    };
+    let kind = if self.eat_keyword("PRIMARY") {
+      self.expect_keyword("KEY")?;
+      self.expect(&TokenKind::LeftParen)?;
+      let columns = self.indexed_columns()?;
+      let autoincrement = self.eat_keyword("AUTOINCREMENT");
+      self.expect(&TokenKind::RightParen)?;
+      TableConstraintKind::PrimaryKey {
+        columns,
+        conflict: self.on_conflict()?,
+        autoincrement,
+      }
+    } else if self.eat_keyword("UNIQUE") {
+      self.expect(&TokenKind::LeftParen)?;
+      let columns = self.indexed_columns()?;
+      self.expect(&TokenKind::RightParen)?;
+      TableConstraintKind::Unique {
+        columns,
+        conflict: self.on_conflict()?,
+      }
+    } else if self.eat_keyword("CHECK") {
+      TableConstraintKind::Check(self.parenthesized_expr()?)
+    } else if self.eat_keyword("FOREIGN") {
+      self.expect_keyword("KEY")?;
+      let columns = self.name_list()?;
+      TableConstraintKind::ForeignKey {
+        columns,
+        clause: self.foreign_key_clause()?,
+      }
+    } else {
+      return Err(self.error());
+    };
+    Ok(TableConstraint { name, kind })
+  }
+
+  fn parenthesized_expr(&mut self) -> SqliteResult<Expr> {
+    self.expect(&TokenKind::LeftParen)?;
+    let expr = self.expr()?;
+    self.expect(&TokenKind::RightParen)?;
+    Ok(expr)
+  }
+
+  /// `ON CONFLICT` of a constraint.
+  fn on_conflict(&mut self) -> SqliteResult<Option<ConflictResolution>> {
+    if !(self.is_keyword("ON") && self.is_keyword_at(1, "CONFLICT")) {
+      return Ok(None);
+    }
+    self.advance();
+    self.advance();
+    Ok(Some(self.conflict_resolution()?))
+  }
+
+  fn foreign_key_clause(&mut self) -> SqliteResult<ForeignKeyClause> {
+    self.expect_keyword("REFERENCES")?;
+    let table = self.name()?;
+    let columns = match self.peek().kind == TokenKind::LeftParen {
+      true => self.name_list()?,
+      false => vec![],
+    };
+    let mut clause = ForeignKeyClause {
+      table,
+      columns,
+      on_delete: None,
+      on_update: None,
+      deferred: false,
+    };
+    loop {
+      if self.is_keyword("ON") && !self.is_keyword_at(1, "CONFLICT") {
+        self.advance();
+        let is_delete = self.eat_keyword("DELETE");
+        if !is_delete {
+          self.expect_keyword("UPDATE")?;
+        }
+        let action = self.foreign_key_action()?;
+        match is_delete {
+          true => clause.on_delete = Some(action),
+          false => clause.on_update = Some(action),
+        }
+      } else if self.eat_keyword("MATCH") {
+        // Only MATCH SIMPLE is implemented by SQLite, whatever the name.
+        self.name()?;
+      } else if self.is_keyword("DEFERRABLE")
+        || self.is_keyword("NOT") && self.is_keyword_at(1, "DEFERRABLE")
+      {
+        let not = self.eat_keyword("NOT");
+        self.advance();
+        let mut deferred = false;
+        if self.eat_keyword("INITIALLY") {
+          deferred = self.eat_keyword("DEFERRED");
+          if !deferred {
+            self.expect_keyword("IMMEDIATE")?;
+          }
+        }
+        clause.deferred = deferred && !not;
+      } else {
+        return Ok(clause);
+      }
+    }
+  }
+
+  fn foreign_key_action(&mut self) -> SqliteResult<ForeignKeyAction> {
+    if self.eat_keyword("SET") {
+      if self.eat_keyword("NULL") {
+        return Ok(ForeignKeyAction::SetNull);
+      }
+      self.expect_keyword("DEFAULT")?;
+      return Ok(ForeignKeyAction::SetDefault);
+    }
+    if self.eat_keyword("CASCADE") {
+      return Ok(ForeignKeyAction::Cascade);
+    }
+    if self.eat_keyword("RESTRICT") {
+      return Ok(ForeignKeyAction::Restrict);
+    }
+    self.expect_keyword("NO")?;
+    self.expect_keyword("ACTION")?;
+    Ok(ForeignKeyAction::NoAction)
+  }
+
+  fn create_index(&mut self, unique: bool) -> SqliteResult<CreateIndex> {
+    let if_not_exists = self.if_exists(true)?;
+    let name = self.qualified_name()?;
+    self.expect_keyword("ON")?;
+    let table = self.name()?;
+    self.expect(&TokenKind::LeftParen)?;
+    let columns = self.indexed_columns()?;
+    self.expect(&TokenKind::RightParen)?;
+    let where_clause = self.where_clause()?;
+    Ok(CreateIndex {
+      unique,
+      if_not_exists,
+      name,
+      table,
+      columns,
+      where_clause,
+    })
+  }
+
+  fn create_view(&mut self, temporary: bool) -> SqliteResult<CreateView> {
+    let if_not_exists = self.if_exists(true)?;
+    let name = self.qualified_name()?;
+    let columns = match self.peek().kind == TokenKind::LeftParen {
+      true => self.name_list()?,
+      false => vec![],
+    };
+    self.expect_keyword("AS")?;
+    Ok(CreateView {
+      temporary,
+      if_not_exists,
+      name,
+      columns,
+      select: Box::new(self.select()?),
+    })
+  }
+
+  fn create_trigger(&mut self, temporary: bool) -> SqliteResult<CreateTrigger> {
+    let if_not_exists = self.if_exists(true)?;
+    let name = self.qualified_name()?;
+    let time = if self.eat_keyword("BEFORE") {
+      TriggerTime::Before
+    } else if self.eat_keyword("AFTER") {
+      TriggerTime::After
+    } else if self.eat_keyword("INSTEAD") {
+      self.expect_keyword("OF")?;
+      TriggerTime::InsteadOf
+    } else {
+      TriggerTime::Before
+    };
+    let event = if self.eat_keyword("DELETE") {
+      TriggerEvent::Delete
+    } else if self.eat_keyword("INSERT") {
+      TriggerEvent::Insert
+    } else {
+      self.expect_keyword("UPDATE")?;
+      let mut columns = vec![];
+      if self.eat_keyword("OF") {
+        columns.push(self.name()?);
+        while self.eat(&TokenKind::Comma) {
+          columns.push(self.name()?);
+        }
+      }
+      TriggerEvent::Update(columns)
+    };
+    self.expect_keyword("ON")?;
+    let table = self.qualified_name()?;
+    let for_each_row = self.eat_keyword("FOR");
+    if for_each_row {
+      self.expect_keyword("EACH")?;
+      self.expect_keyword("ROW")?;
+    }
+    let when = match self.eat_keyword("WHEN") {
+      true => Some(self.expr()?),
+      false => None,
+    };
+    self.expect_keyword("BEGIN")?;
+    let mut body = vec![];
+    while body.is_empty() || !self.eat_keyword("END") {
+      let is_allowed = TRIGGER_STATEMENTS
+        .iter()
+        .any(|keyword| self.is_keyword(keyword));
+      if !is_allowed {
+        return Err(self.error());
+      }
+      body.push(self.statement()?);
+      self.expect(&TokenKind::Semicolon)?;
+    }
+    Ok(CreateTrigger {
+      temporary,
+      if_not_exists,
+      name,
+      time,
+      event,
+      table,
+      for_each_row,
+      when,
+      body,
+    })
+  }
+
+  fn create_virtual_table(&mut self) -> SqliteResult<CreateVirtualTable> {
+    let if_not_exists = self.if_exists(true)?;
+    let name = self.qualified_name()?;
+    self.expect_keyword("USING")?;
+    let module = self.name()?;
+    let mut arguments = vec![];
+    if self.eat(&TokenKind::LeftParen) {
+      // Module arguments are kept as written, split at top-level commas.
+      let mut depth = 0;
+      let mut start = self.offset();
+      loop {
+        match self.peek().kind {
+          TokenKind::Eof => return Err(self.error()),
+          TokenKind::LeftParen => depth += 1,
+          TokenKind::RightParen if depth == 0 => break,
+          TokenKind::RightParen => depth -= 1,
+          TokenKind::Comma if depth == 0 => {
+            arguments.push(self.argument_text(start));
+            self.advance();
+            start = self.offset();
+            continue;
+          }
+          _ => (),
+        }
+        self.advance();
+      }
+      arguments.push(self.argument_text(start));
+      self.advance();
+      arguments.retain(|argument| !argument.is_empty());
+    }
+    Ok(CreateVirtualTable {
+      if_not_exists,
+      name,
+      module,
+      arguments,
+    })
+  }
+
+  fn argument_text(&self, start: usize) -> String {
+    self.sql()[start..self.offset()].trim().into()
+  }
+
+  pub(crate) fn drop(&mut self) -> SqliteResult<Drop> {
+    self.expect_keyword("DROP")?;
+    let object = if self.eat_keyword("TABLE") {
+      ObjectKind::Table
+    } else if self.eat_keyword("INDEX") {
+      ObjectKind::Index
+    } else if self.eat_keyword("VIEW") {
+      ObjectKind::View
+    } else {
+      self.expect_keyword("TRIGGER")?;
+      ObjectKind::Trigger
+    };
+    let if_exists = self.if_exists(false)?;
+    Ok(Drop {
+      object,
+      if_exists,
+      name: self.qualified_name()?,
+    })
+  }
+
+  pub(crate) fn alter_table(&mut self) -> SqliteResult<AlterTable> {
+    self.expect_keyword("ALTER")?;
+    self.expect_keyword("TABLE")?;
+    let name = self.qualified_name()?;
+    let action = if self.eat_keyword("RENAME") {
+      if self.eat_keyword("TO") {
+        AlterTableAction::RenameTo(self.name()?)
+      } else {
+        self.eat_keyword("COLUMN");
+        let old = self.name()?;
+        self.expect_keyword("TO")?;
+        AlterTableAction::RenameColumn {
+          old,
+          new: self.name()?,
+        }
+      }
+    } else if self.eat_keyword("ADD") {
+      self.eat_keyword("COLUMN");
+      AlterTableAction::AddColumn(self.column_definition()?)
+    } else {
+      self.expect_keyword("DROP")?;
+      self.eat_keyword("COLUMN");
+      AlterTableAction::DropColumn(self.name()?)
+    };
+    Ok(AlterTable { name, action })
+  }
+}
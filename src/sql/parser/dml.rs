@@ -0,0 +1,227 @@
+use super::Parser;
+use crate::result::SqliteResult;
+use crate::sql::ast::{
+  Assignment, ConflictResolution, Delete, IndexedColumn, Insert, InsertSource,
+  Name, ResultColumn, Update, Upsert, UpsertAction, UpsertTarget, With,
+};
+use crate::sql::tokenizer::TokenKind;
+
+impl Parser<'_> {
+  /// `INSERT` or `REPLACE`, following an optional `WITH` clause.
+  pub(crate) fn insert(&mut self, with: Option<With>) -> SqliteResult<Insert> {
+    let start = self.offset();
+    let conflict = if self.eat_keyword("REPLACE") {
+      Some(ConflictResolution::Replace)
+    } else {
+      self.expect_keyword("INSERT")?;
+      match self.eat_keyword("OR") {
+        true => Some(self.conflict_resolution()?),
+        false => None,
+      }
+    };
+    self.expect_keyword("INTO")?;
+    let table = self.qualified_name()?;
+    let alias = match self.eat_keyword("AS") {
+      true => Some(self.name()?),
+      false => None,
+    };
+    let columns = match self.peek().kind == TokenKind::LeftParen {
+      true => self.name_list()?,
+      false => vec![],
+    };
+    let source = if self.eat_keyword("DEFAULT") {
+      self.expect_keyword("VALUES")?;
+      InsertSource::DefaultValues
+    } else {
+      InsertSource::Select(Box::new(self.select()?))
+    };
+    let mut upserts = vec![];
+    while self.is_keyword("ON") && self.is_keyword_at(1, "CONFLICT") {
+      if matches!(source, InsertSource::DefaultValues) {
+        return Err(self.error());
+      }
+      upserts.push(self.upsert()?);
+    }
+    // Only the last ON CONFLICT clause may omit the conflict target.
+    let last = upserts.len().saturating_sub(1);
+    if upserts[..last].iter().any(|upsert| upsert.target.is_none()) {
+      let message = "ON CONFLICT clause does not match any PRIMARY KEY or \
+                     UNIQUE constraint";
+      return Err(self.error_at(self.span_from(start), message.into()));
+    }
+    let returning = self.returning()?;
+    Ok(Insert {
+      with,
+      conflict,
+      table,
+      alias,
+      columns,
+      source,
+      upserts,
+      returning,
+    })
+  }
+
+  fn upsert(&mut self) -> SqliteResult<Upsert> {
+    self.expect_keyword("ON")?;
+    self.expect_keyword("CONFLICT")?;
+    let target = match self.eat(&TokenKind::LeftParen) {
+      true => {
+        let columns = self.indexed_columns()?;
+        self.expect(&TokenKind::RightParen)?;
+        let where_clause = self.where_clause()?;
+        Some(UpsertTarget {
+          columns,
+          where_clause,
+        })
+      }
+      false => None,
+    };
+    self.expect_keyword("DO")?;
+    let action = if self.eat_keyword("NOTHING") {
+      UpsertAction::Nothing
+    } else {
+      self.expect_keyword("UPDATE")?;
+      self.expect_keyword("SET")?;
+      let assignments = self.assignments()?;
+      let where_clause = self.where_clause()?;
+      UpsertAction::Update {
+        assignments,
+        where_clause,
+      }
+    };
+    Ok(Upsert { target, action })
+  }
+
+  pub(crate) fn update(&mut self, with: Option<With>) -> SqliteResult<Update> {
+    self.expect_keyword("UPDATE")?;
+    let conflict = match self.eat_keyword("OR") {
+      true => Some(self.conflict_resolution()?),
+      false => None,
+    };
+    let table = self.qualified_name()?;
+    let alias = self.dml_alias()?;
+    let indexed_by = self.indexed_by()?;
+    self.expect_keyword("SET")?;
+    let assignments = self.assignments()?;
+    let from = match self.eat_keyword("FROM") {
+      true => Some(self.from()?),
+      false => None,
+    };
+    let where_clause = self.where_clause()?;
+    let returning = self.returning()?;
+    let order_by = match self.is_keyword("ORDER") {
+      true => self.order_by()?,
+      false => vec![],
+    };
+    let limit = self.limit()?;
+    Ok(Update {
+      with,
+      conflict,
+      table,
+      alias,
+      indexed_by,
+      assignments,
+      from,
+      where_clause,
+      returning,
+      order_by,
+      limit,
+    })
+  }
+
+  pub(crate) fn delete(&mut self, with: Option<With>) -> SqliteResult<Delete> {
+    self.expect_keyword("DELETE")?;
+    self.expect_keyword("FROM")?;
+    let table = self.qualified_name()?;
+    let alias = self.dml_alias()?;
+    let indexed_by = self.indexed_by()?;
+    let where_clause = self.where_clause()?;
+    let returning = self.returning()?;
+    let order_by = match self.is_keyword("ORDER") {
+      true => self.order_by()?,
+      false => vec![],
+    };
+    let limit = self.limit()?;
+    Ok(Delete {
+      with,
+      table,
+      alias,
+      indexed_by,
+      where_clause,
+      returning,
+      order_by,
+      limit,
+    })
+  }
+
+  /// The alias of the table of an `UPDATE` or `DELETE`.
+  fn dml_alias(&mut self) -> SqliteResult<Option<Name>> {
+    if self.eat_keyword("AS") || self.is_alias() {
+      return Ok(Some(self.name()?));
+    }
+    Ok(None)
+  }
+
+  fn assignments(&mut self) -> SqliteResult<Vec<Assignment>> {
+    let mut assignments = vec![];
+    loop {
+      let columns = match self.peek().kind == TokenKind::LeftParen {
+        true => self.name_list()?,
+        false => vec![self.name()?],
+      };
+      self.expect(&TokenKind::Eq)?;
+      let value = self.expr()?;
+      assignments.push(Assignment { columns, value });
+      if !self.eat(&TokenKind::Comma) {
+        return Ok(assignments);
+      }
+    }
+  }
+
+  fn returning(&mut self) -> SqliteResult<Vec<ResultColumn>> {
+    if !self.eat_keyword("RETURNING") {
+      return Ok(vec![]);
+    }
+    let mut columns = vec![self.result_column()?];
+    while self.eat(&TokenKind::Comma) {
+      columns.push(self.result_column()?);
+    }
+    Ok(columns)
+  }
+
+  /// `ROLLBACK`, `ABORT`, `FAIL`, `IGNORE` or `REPLACE`.
+  pub(crate) fn conflict_resolution(
+    &mut self,
+  ) -> SqliteResult<ConflictResolution> {
+    let resolution = [
+      ("ROLLBACK", ConflictResolution::Rollback),
+      ("ABORT", ConflictResolution::Abort),
+      ("FAIL", ConflictResolution::Fail),
+      ("IGNORE", ConflictResolution::Ignore),
+      ("REPLACE", ConflictResolution::Replace),
+    ]
+    .into_iter()
+    .find(|(keyword, _)| self.is_keyword(keyword));
+    match resolution {
+      Some((_, resolution)) => {
+        self.advance();
+        Ok(resolution)
+      }
+      None => Err(self.error()),
+    }
+  }
+
+  /// Comma-separated columns of an index or key, at least one.
+  pub(crate) fn indexed_columns(&mut self) -> SqliteResult<Vec<IndexedColumn>> {
+    let mut columns = vec![];
+    loop {
+      let expr = self.expr()?;
+      let order = self.sort_order();
+      columns.push(IndexedColumn { expr, order });
+      if !self.eat(&TokenKind::Comma) {
+        return Ok(columns);
+      }
+    }
+  }
+}
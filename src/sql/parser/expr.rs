@@ -0,0 +1,778 @@
+//! Expressions, by increasing operator precedence.
+//!
+//! *Reference:* https://www.sqlite.org/lang_expr.html#operators_and_parse_affecting_attributes
+
+use super::Parser;
+use crate::result::SqliteResult;
+use crate::sql::ast::{
+  BinaryOperator, Expr, ExprKind, FrameBound, FrameExclude, FrameUnit,
+  FunctionArguments, FunctionCall, InTarget, LikeOperator, Literal, Name, Over,
+  RaiseAction, Span, TypeName, UnaryOperator, Variable, WindowDefinition,
+  WindowFrame,
+};
+use crate::sql::tokenizer::TokenKind;
+
+impl Parser<'_> {
+  pub(crate) fn expr(&mut self) -> SqliteResult<Expr> {
+    let mut left = self.and_expr()?;
+    while self.eat_keyword("OR") {
+      let right = self.and_expr()?;
+      left = binary(left, BinaryOperator::Or, right);
+    }
+    Ok(left)
+  }
+
+  /// Comma-separated expressions, at least one.
+  pub(crate) fn expr_list(&mut self) -> SqliteResult<Vec<Expr>> {
+    let mut exprs = vec![self.expr()?];
+    while self.eat(&TokenKind::Comma) {
+      exprs.push(self.expr()?);
+    }
+    Ok(exprs)
+  }
+
+  fn and_expr(&mut self) -> SqliteResult<Expr> {
+    let mut left = self.not_expr()?;
+    while self.eat_keyword("AND") {
+      let right = self.not_expr()?;
+      left = binary(left, BinaryOperator::And, right);
+    }
+    Ok(left)
+  }
+
+  fn not_expr(&mut self) -> SqliteResult<Expr> {
+    let start = self.offset();
+    if self.eat_keyword("NOT") {
+      let expr = self.not_expr()?;
+      return Ok(unary(UnaryOperator::Not, expr, start));
+    }
+    self.equality_expr()
+  }
+
+  /// `=`, `!=`, `IS`, `LIKE`, `BETWEEN`, `IN` and the null tests, which all
+  /// share the same precedence.
+  fn equality_expr(&mut self) -> SqliteResult<Expr> {
+    let mut left = self.comparison_expr()?;
+    loop {
+      let start = left.span.start;
+      let kind = if self.eat(&TokenKind::Eq) {
+        let right = self.comparison_expr()?;
+        left = binary(left, BinaryOperator::Eq, right);
+        continue;
+      } else if self.eat(&TokenKind::NotEq) {
+        let right = self.comparison_expr()?;
+        left = binary(left, BinaryOperator::NotEq, right);
+        continue;
+      } else if self.eat_keyword("IS") {
+        let mut not = self.eat_keyword("NOT");
+        if self.eat_keyword("DISTINCT") {
+          self.expect_keyword("FROM")?;
+          not = !not;
+        }
+        let right = self.comparison_expr()?;
+        ExprKind::Is {
+          left: Box::new(left),
+          not,
+          right: Box::new(right),
+        }
+      } else if self.eat_keyword("ISNULL") {
+        ExprKind::IsNull {
+          expr: Box::new(left),
+          not: false,
+        }
+      } else if self.eat_keyword("NOTNULL") {
+        ExprKind::IsNull {
+          expr: Box::new(left),
+          not: true,
+        }
+      } else if self.is_keyword("NOT") && self.is_keyword_at(1, "NULL") {
+        self.advance();
+        self.advance();
+        ExprKind::IsNull {
+          expr: Box::new(left),
+          not: true,
+        }
+      } else {
+        let not = self.is_keyword("NOT")
+          && ["LIKE", "GLOB", "REGEXP", "MATCH", "BETWEEN", "IN"]
+            .iter()
+            .any(|keyword| self.is_keyword_at(1, keyword));
+        if not {
+          self.advance();
+        }
+        if let Some(operator) = self.like_operator() {
+          let pattern = self.comparison_expr()?;
+          let escape = match self.eat_keyword("ESCAPE") {
+            true => Some(Box::new(self.comparison_expr()?)),
+            false => None,
+          };
+          ExprKind::Like {
+            expr: Box::new(left),
+            not,
+            operator,
+            pattern: Box::new(pattern),
+            escape,
+          }
+        } else if self.eat_keyword("BETWEEN") {
+          let low = self.comparison_expr()?;
+          self.expect_keyword("AND")?;
+          let high = self.comparison_expr()?;
+          ExprKind::Between {
+            expr: Box::new(left),
+            not,
+            low: Box::new(low),
+            high: Box::new(high),
+          }
+        } else if self.eat_keyword("IN") {
+          ExprKind::In {
+            expr: Box::new(left),
+            not,
+            target: self.in_target()?,
+          }
+        } else {
+          return Ok(left);
+        }
+      };
+      left = Expr::new(kind, self.span_from(start));
+    }
+  }
+
+  fn like_operator(&mut self) -> Option<LikeOperator> {
+    let operator = [
+      ("LIKE", LikeOperator::Like),
+      ("GLOB", LikeOperator::Glob),
+      ("REGEXP", LikeOperator::Regexp),
+      ("MATCH", LikeOperator::Match),
+    ]
+    .into_iter()
+    .find(|(keyword, _)| self.is_keyword(keyword))
+    .map(|(_, operator)| operator);
+    if operator.is_some() {
+      self.advance();
+    }
+    operator
+  }
+
+  fn in_target(&mut self) -> SqliteResult<InTarget> {
+    if self.eat(&TokenKind::LeftParen) {
+      let target = if self.eat(&TokenKind::RightParen) {
+        return Ok(InTarget::List(vec![]));
+      } else if self.is_select_start() {
+        InTarget::Select(Box::new(self.select()?))
+      } else {
+        InTarget::List(self.expr_list()?)
+      };
+      self.expect(&TokenKind::RightParen)?;
+      return Ok(target);
+    }
+    let name = self.qualified_name()?;
+    if !self.eat(&TokenKind::LeftParen) {
+      return Ok(InTarget::Table(name));
+    }
+    let arguments = match self.eat(&TokenKind::RightParen) {
+      true => vec![],
+      false => {
+        let arguments = self.expr_list()?;
+        self.expect(&TokenKind::RightParen)?;
+        arguments
+      }
+    };
+    Ok(InTarget::TableFunction { name, arguments })
+  }
+
+  fn comparison_expr(&mut self) -> SqliteResult<Expr> {
+    self.binary_level(
+      &[
+        (TokenKind::Lt, BinaryOperator::Lt),
+        (TokenKind::LtEq, BinaryOperator::LtEq),
+        (TokenKind::Gt, BinaryOperator::Gt),
+        (TokenKind::GtEq, BinaryOperator::GtEq),
+      ],
+      Self::bit_expr,
+    )
+  }
+
+  fn bit_expr(&mut self) -> SqliteResult<Expr> {
+    self.binary_level(
+      &[
+        (TokenKind::BitAnd, BinaryOperator::BitAnd),
+        (TokenKind::BitOr, BinaryOperator::BitOr),
+        (TokenKind::ShiftLeft, BinaryOperator::ShiftLeft),
+        (TokenKind::ShiftRight, BinaryOperator::ShiftRight),
+      ],
+      Self::additive_expr,
+    )
+  }
+
+  fn additive_expr(&mut self) -> SqliteResult<Expr> {
+    self.binary_level(
+      &[
+        (TokenKind::Plus, BinaryOperator::Add),
+        (TokenKind::Minus, BinaryOperator::Subtract),
+      ],
+      Self::multiplicative_expr,
+    )
+  }
+
+  fn multiplicative_expr(&mut self) -> SqliteResult<Expr> {
+    self.binary_level(
+      &[
+        (TokenKind::Star, BinaryOperator::Multiply),
+        (TokenKind::Slash, BinaryOperator::Divide),
+        (TokenKind::Percent, BinaryOperator::Modulo),
+      ],
+      Self::concat_expr,
+    )
+  }
+
+  fn concat_expr(&mut self) -> SqliteResult<Expr> {
+    self.binary_level(
+      &[
+        (TokenKind::Concat, BinaryOperator::Concat),
+        (TokenKind::Arrow, BinaryOperator::Extract),
+        (TokenKind::LongArrow, BinaryOperator::ExtractValue),
+      ],
+      Self::collate_expr,
+    )
+  }
+
+  /// Left-associative binary operators of the same precedence.
+  fn binary_level(
+    &mut self,
+    operators: &[(TokenKind, BinaryOperator)],
+    operand: fn(&mut Self) -> SqliteResult<Expr>,
+  ) -> SqliteResult<Expr> {
+    let mut left = operand(self)?;
+    loop {
+      let operator = operators
+        .iter()
+        .find(|(kind, _)| self.peek().kind == *kind)
+        .map(|(_, operator)| *operator);
+      let Some(operator) = operator else {
+        return Ok(left);
+      };
+      self.advance();
+      let right = operand(self)?;
+      left = binary(left, operator, right);
+    }
+  }
+
+  fn collate_expr(&mut self) -> SqliteResult<Expr> {
+    let mut expr = self.unary_expr()?;
+    while self.eat_keyword("COLLATE") {
+      let collation = self.name()?;
+      let span = Span::new(expr.span.start, collation.span.end);
+      let kind = ExprKind::Collate {
+        expr: Box::new(expr),
+        collation,
+      };
+      expr = Expr::new(kind, span);
+    }
+    Ok(expr)
+  }
+
+  fn unary_expr(&mut self) -> SqliteResult<Expr> {
+    let start = self.offset();
+    let operator = match self.peek().kind {
+      TokenKind::Minus => UnaryOperator::Negate,
+      TokenKind::Plus => UnaryOperator::Plus,
+      TokenKind::BitNot => UnaryOperator::BitNot,
+      _ => return self.primary_expr(),
+    };
+    self.advance();
+    // -9223372036854775808 is the only integer literal that does not fit
+    // without its sign.
+    if operator == UnaryOperator::Negate {
+      if let TokenKind::Number(number) = &self.peek().kind {
+        if number == "9223372036854775808" {
+          self.advance();
+          let literal = ExprKind::Literal(Literal::Integer(i64::MIN));
+          return Ok(Expr::new(literal, self.span_from(start)));
+        }
+      }
+    }
+    let expr = self.unary_expr()?;
+    Ok(unary(operator, expr, start))
+  }
+
+  fn primary_expr(&mut self) -> SqliteResult<Expr> {
+    let start = self.offset();
+    let token = self.peek().clone();
+    let kind = match token.kind {
+      TokenKind::Number(number) => {
+        self.advance();
+        ExprKind::Literal(self.number(&number, token.span)?)
+      }
+      TokenKind::String(text) => {
+        self.advance();
+        ExprKind::Literal(Literal::String(text))
+      }
+      TokenKind::Blob(blob) => {
+        self.advance();
+        ExprKind::Literal(Literal::Blob(blob))
+      }
+      TokenKind::Variable(name) => {
+        self.advance();
+        let index = self.variable_index(&name, token.span)?;
+        ExprKind::Variable(Variable { name, index })
+      }
+      TokenKind::LeftParen => {
+        self.advance();
+        if self.is_select_start() {
+          let select = self.select()?;
+          self.expect(&TokenKind::RightParen)?;
+          ExprKind::Subquery(Box::new(select))
+        } else {
+          let mut exprs = self.expr_list()?;
+          self.expect(&TokenKind::RightParen)?;
+          if exprs.len() == 1 {
+            return Ok(exprs.remove(0));
+          }
+          ExprKind::Vector(exprs)
+        }
+      }
+      TokenKind::Word(word) => match word.to_ascii_uppercase().as_str() {
+        "NULL" => {
+          self.advance();
+          ExprKind::Literal(Literal::Null)
+        }
+        "CURRENT_TIME" => {
+          self.advance();
+          ExprKind::Literal(Literal::CurrentTime)
+        }
+        "CURRENT_DATE" => {
+          self.advance();
+          ExprKind::Literal(Literal::CurrentDate)
+        }
+        "CURRENT_TIMESTAMP" => {
+          self.advance();
+          ExprKind::Literal(Literal::CurrentTimestamp)
+        }
+        "NOT" => {
+          self.advance();
+          let expr = self.not_expr()?;
+          return Ok(unary(UnaryOperator::Not, expr, start));
+        }
+        "CAST" if self.peek_nth(1).kind == TokenKind::LeftParen => {
+          self.advance();
+          self.advance();
+          let expr = self.expr()?;
+          self.expect_keyword("AS")?;
+          let type_name = self.type_name()?.ok_or_else(|| self.error())?;
+          self.expect(&TokenKind::RightParen)?;
+          ExprKind::Cast {
+            expr: Box::new(expr),
+            type_name,
+          }
+        }
+        "CASE" => self.case_expr()?,
+        "EXISTS" => {
+          self.advance();
+          self.expect(&TokenKind::LeftParen)?;
+          let select = self.select()?;
+          self.expect(&TokenKind::RightParen)?;
+          ExprKind::Exists(Box::new(select))
+        }
+        "RAISE" if self.peek_nth(1).kind == TokenKind::LeftParen => {
+          self.raise_expr()?
+        }
+        _ => self.name_expr(start)?,
+      },
+      TokenKind::QuotedIdentifier(_) => self.name_expr(start)?,
+      _ => return Err(self.error()),
+    };
+    Ok(Expr::new(kind, self.span_from(start)))
+  }
+
+  /// A column reference or a function call.
+  fn name_expr(&mut self, start: usize) -> SqliteResult<ExprKind> {
+    let name = self.name()?;
+    if self.peek().kind == TokenKind::LeftParen {
+      return self.function_call(name, start);
+    }
+    if !self.eat(&TokenKind::Dot) {
+      return Ok(ExprKind::Column {
+        schema: None,
+        table: None,
+        column: name,
+      });
+    }
+    let second = self.name()?;
+    if !self.eat(&TokenKind::Dot) {
+      return Ok(ExprKind::Column {
+        schema: None,
+        table: Some(name),
+        column: second,
+      });
+    }
+    Ok(ExprKind::Column {
+      schema: Some(name),
+      table: Some(second),
+      column: self.name()?,
+    })
+  }
+
+  fn function_call(
+    &mut self,
+    name: Name,
+    start: usize,
+  ) -> SqliteResult<ExprKind> {
+    self.expect(&TokenKind::LeftParen)?;
+    let mut distinct = false;
+    let mut order_by = vec![];
+    let arguments = if self.eat(&TokenKind::Star) {
+      FunctionArguments::Star
+    } else if self.peek().kind == TokenKind::RightParen {
+      FunctionArguments::List(vec![])
+    } else {
+      distinct = self.eat_keyword("DISTINCT");
+      if !distinct {
+        self.eat_keyword("ALL");
+      }
+      let arguments = self.expr_list()?;
+      if self.is_keyword("ORDER") {
+        order_by = self.order_by()?;
+      }
+      FunctionArguments::List(arguments)
+    };
+    self.expect(&TokenKind::RightParen)?;
+    let filter = if self.is_keyword("FILTER")
+      && self.peek_nth(1).kind == TokenKind::LeftParen
+    {
+      self.advance();
+      self.advance();
+      self.expect_keyword("WHERE")?;
+      let filter = self.expr()?;
+      self.expect(&TokenKind::RightParen)?;
+      Some(filter)
+    } else {
+      None
+    };
+    let over = match self.eat_keyword("OVER") {
+      true if self.peek().kind == TokenKind::LeftParen => {
+        Some(Over::Window(self.window_definition()?))
+      }
+      true => Some(Over::Name(self.name()?)),
+      false => None,
+    };
+    if distinct && arguments_len(&arguments) != 1 {
+      return Err(self.error_at(
+        self.span_from(start),
+        "DISTINCT aggregates must have exactly one argument".into(),
+      ));
+    }
+    Ok(ExprKind::Function(Box::new(FunctionCall {
+      name,
+      distinct,
+      arguments,
+      order_by,
+      filter,
+      over,
+    })))
+  }
+
+  fn case_expr(&mut self) -> SqliteResult<ExprKind> {
+    self.expect_keyword("CASE")?;
+    let operand = match self.is_keyword("WHEN") {
+      true => None,
+      false => Some(Box::new(self.expr()?)),
+    };
+    let mut when_then = vec![];
+    while self.eat_keyword("WHEN") {
+      let when = self.expr()?;
+      self.expect_keyword("THEN")?;
+      when_then.push((when, self.expr()?));
+    }
+    if when_then.is_empty() {
+      return Err(self.error());
+    }
+    let else_expr = match self.eat_keyword("ELSE") {
+      true => Some(Box::new(self.expr()?)),
+      false => None,
+    };
+    self.expect_keyword("END")?;
+    Ok(ExprKind::Case {
+      operand,
+      when_then,
+      else_expr,
+    })
+  }
+
+  fn raise_expr(&mut self) -> SqliteResult<ExprKind> {
+    self.expect_keyword("RAISE")?;
+    self.expect(&TokenKind::LeftParen)?;
+    let action = if self.eat_keyword("IGNORE") {
+      RaiseAction::Ignore
+    } else if self.eat_keyword("ROLLBACK") {
+      RaiseAction::Rollback
+    } else if self.eat_keyword("ABORT") {
+      RaiseAction::Abort
+    } else if self.eat_keyword("FAIL") {
+      RaiseAction::Fail
+    } else {
+      return Err(self.error());
+    };
+    let message = match action {
+      RaiseAction::Ignore => None,
+      _ => {
+        self.expect(&TokenKind::Comma)?;
+        Some(Box::new(self.expr()?))
+      }
+    };
+    self.expect(&TokenKind::RightParen)?;
+    Ok(ExprKind::Raise { action, message })
+  }
+
+  /// A numeric literal: an integer when it fits in 64 bits, else a real.
+  fn number(&self, number: &str, span: Span) -> SqliteResult<Literal> {
+    if let Some(hex) = number.strip_prefix("0x").or(number.strip_prefix("0X")) {
+      // Hexadecimal literals are 64-bit two's-complement integers.
+      return match u64::from_str_radix(hex, 16) {
+        Ok(value) => Ok(Literal::Integer(value as i64)),
+        Err(_) => {
+          Err(self.error_at(span, format!("hex literal too big: {number}")))
+        }
+      };
+    }
+    if let Ok(value) = number.parse::<i64>() {
+      return Ok(Literal::Integer(value));
+    }
+    number
+      .parse::<f64>()
+      .map(Literal::Real)
+      .map_err(|_| self.error_at(span, format!("malformed number: {number}")))
+  }
+
+  /// A type name, like `VARCHAR(10)` or `UNSIGNED BIG INT`. `None` when
+  /// there is none.
+  pub(crate) fn type_name(&mut self) -> SqliteResult<Option<TypeName>> {
+    let mut words: Vec<String> = vec![];
+    loop {
+      // `GENERATED ALWAYS AS` ends the type of a generated column.
+      if self.is_keyword("GENERATED") && self.is_keyword_at(1, "ALWAYS") {
+        break;
+      }
+      match &self.peek().kind {
+        TokenKind::Word(word) if !super::is_reserved(word) => {
+          words.push(word.clone())
+        }
+        TokenKind::QuotedIdentifier(word) | TokenKind::String(word) => {
+          words.push(word.clone())
+        }
+        _ => break,
+      }
+      self.advance();
+    }
+    if words.is_empty() {
+      return Ok(None);
+    }
+    let mut arguments = vec![];
+    if self.eat(&TokenKind::LeftParen) {
+      arguments.push(self.signed_number()?);
+      if self.eat(&TokenKind::Comma) {
+        arguments.push(self.signed_number()?);
+      }
+      self.expect(&TokenKind::RightParen)?;
+    }
+    Ok(Some(TypeName {
+      name: words.join(" "),
+      arguments,
+    }))
+  }
+
+  fn signed_number(&mut self) -> SqliteResult<String> {
+    let sign = match self.peek().kind {
+      TokenKind::Plus => "+",
+      TokenKind::Minus => "-",
+      _ => "",
+    };
+    if !sign.is_empty() {
+      self.advance();
+    }
+    match &self.peek().kind {
+      TokenKind::Number(number) => {
+        let number = format!("{sign}{number}");
+        self.advance();
+        Ok(number)
+      }
+      _ => Err(self.error()),
+    }
+  }
+
+  /// The value of a `PRAGMA`: a signed number, a string, or a name, which
+  /// may be a keyword like `ON` or `DELETE`.
+  pub(crate) fn pragma_value(&mut self) -> SqliteResult<Expr> {
+    let start = self.offset();
+    match self.peek().kind.clone() {
+      TokenKind::Plus | TokenKind::Minus | TokenKind::Number(_) => {
+        self.unary_expr()
+      }
+      TokenKind::String(_) => self.primary_expr(),
+      TokenKind::Word(value) | TokenKind::QuotedIdentifier(value) => {
+        let quoted = matches!(self.peek().kind, TokenKind::QuotedIdentifier(_));
+        let span = self.advance().span;
+        let column = Name {
+          value,
+          quoted,
+          span,
+        };
+        let kind = ExprKind::Column {
+          schema: None,
+          table: None,
+          column,
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+      }
+      _ => Err(self.error()),
+    }
+  }
+
+  /// The value of a `DEFAULT` constraint: a parenthesized expression, a
+  /// literal, a signed number or an identifier.
+  pub(crate) fn default_value(&mut self) -> SqliteResult<Expr> {
+    match self.peek().kind {
+      TokenKind::LeftParen => {
+        let start = self.offset();
+        self.advance();
+        let expr = self.expr()?;
+        self.expect(&TokenKind::RightParen)?;
+        let mut expr = expr;
+        expr.span = self.span_from(start);
+        Ok(expr)
+      }
+      TokenKind::Plus | TokenKind::Minus => self.unary_expr(),
+      TokenKind::Word(_) if self.peek_nth(1).kind == TokenKind::LeftParen => {
+        Err(self.error_at(
+          self.peek_nth(1).span,
+          "default value of column is not constant".into(),
+        ))
+      }
+      _ => self.primary_expr(),
+    }
+  }
+
+  /// `(base PARTITION BY ... ORDER BY ... frame)`.
+  pub(crate) fn window_definition(&mut self) -> SqliteResult<WindowDefinition> {
+    self.expect(&TokenKind::LeftParen)?;
+    let mut window = WindowDefinition::default();
+    let is_clause = ["PARTITION", "ORDER", "RANGE", "ROWS", "GROUPS"]
+      .iter()
+      .any(|keyword| self.is_keyword(keyword));
+    if !is_clause && self.is_name() {
+      window.base = Some(self.name()?);
+    }
+    if self.eat_keyword("PARTITION") {
+      self.expect_keyword("BY")?;
+      window.partition_by = self.expr_list()?;
+    }
+    if self.is_keyword("ORDER") {
+      window.order_by = self.order_by()?;
+    }
+    let unit = if self.eat_keyword("ROWS") {
+      Some(FrameUnit::Rows)
+    } else if self.eat_keyword("RANGE") {
+      Some(FrameUnit::Range)
+    } else if self.eat_keyword("GROUPS") {
+      Some(FrameUnit::Groups)
+    } else {
+      None
+    };
+    if let Some(unit) = unit {
+      window.frame = Some(self.window_frame(unit)?);
+    }
+    self.expect(&TokenKind::RightParen)?;
+    Ok(window)
+  }
+
+  fn window_frame(&mut self, unit: FrameUnit) -> SqliteResult<WindowFrame> {
+    let (start, end) = if self.eat_keyword("BETWEEN") {
+      let start = self.frame_bound()?;
+      self.expect_keyword("AND")?;
+      (start, self.frame_bound()?)
+    } else {
+      (self.frame_bound()?, FrameBound::CurrentRow)
+    };
+    let invalid = matches!(start, FrameBound::UnboundedFollowing)
+      || matches!(end, FrameBound::UnboundedPreceding)
+      || matches!(start, FrameBound::Following(_))
+        && matches!(end, FrameBound::CurrentRow | FrameBound::Preceding(_))
+      || matches!(start, FrameBound::CurrentRow)
+        && matches!(end, FrameBound::Preceding(_));
+    if invalid {
+      return Err(
+        self
+          .error_at(self.peek().span, "unsupported frame specification".into()),
+      );
+    }
+    let exclude = if self.eat_keyword("EXCLUDE") {
+      if self.eat_keyword("NO") {
+        self.expect_keyword("OTHERS")?;
+        FrameExclude::NoOthers
+      } else if self.eat_keyword("CURRENT") {
+        self.expect_keyword("ROW")?;
+        FrameExclude::CurrentRow
+      } else if self.eat_keyword("GROUP") {
+        FrameExclude::Group
+      } else if self.eat_keyword("TIES") {
+        FrameExclude::Ties
+      } else {
+        return Err(self.error());
+      }
+    } else {
+      FrameExclude::NoOthers
+    };
+    Ok(WindowFrame {
+      unit,
+      start,
+      end,
+      exclude,
+    })
+  }
+
+  fn frame_bound(&mut self) -> SqliteResult<FrameBound> {
+    if self.eat_keyword("UNBOUNDED") {
+      if self.eat_keyword("PRECEDING") {
+        return Ok(FrameBound::UnboundedPreceding);
+      }
+      self.expect_keyword("FOLLOWING")?;
+      return Ok(FrameBound::UnboundedFollowing);
+    }
+    if self.is_keyword("CURRENT") && self.is_keyword_at(1, "ROW") {
+      self.advance();
+      self.advance();
+      return Ok(FrameBound::CurrentRow);
+    }
+    let expr = Box::new(self.expr()?);
+    if self.eat_keyword("PRECEDING") {
+      return Ok(FrameBound::Preceding(expr));
+    }
+    self.expect_keyword("FOLLOWING")?;
+    Ok(FrameBound::Following(expr))
+  }
+}
+
+fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+  let span = Span::new(left.span.start, right.span.end);
+  let kind = ExprKind::Binary {
+    left: Box::new(left),
+    operator,
+    right: Box::new(right),
+  };
+  Expr::new(kind, span)
+}
+
+fn unary(operator: UnaryOperator, expr: Expr, start: usize) -> Expr {
+  let span = Span::new(start, expr.span.end);
+  let kind = ExprKind::Unary {
+    operator,
+    expr: Box::new(expr),
+  };
+  Expr::new(kind, span)
+}
+
+fn arguments_len(arguments: &FunctionArguments) -> usize {
+  match arguments {
+    FunctionArguments::Star => 0,
+    FunctionArguments::List(arguments) => arguments.len(),
+  }
+}
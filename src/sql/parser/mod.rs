@@ -0,0 +1,681 @@
+//! # Parser
+//!
+//!  The parser assigns meaning to tokens based on their context. SQLite's
+//! grammar is followed closely: most keywords can still be used as
+//! identifiers, and only the reserved ones cannot.
+//!
+//! *Reference:* https://www.sqlite.org/arch.html#parser
+
+mod ddl;
+mod dml;
+mod expr;
+mod select;
+
+use super::ast::{
+  Name, Pragma, QualifiedName, Span, Statement, StatementKind, TransactionKind,
+};
+use super::error::SyntaxError;
+use super::tokenizer::{tokenize, Token, TokenKind};
+use crate::result::{SqliteError, SqliteResult};
+
+/// Keywords that cannot be used as identifiers without quotes.
+const RESERVED_KEYWORDS: &[&str] = &[
+  "ADD",
+  "ALL",
+  "ALTER",
+  "AND",
+  "AS",
+  "AUTOINCREMENT",
+  "BETWEEN",
+  "CASE",
+  "CHECK",
+  "COLLATE",
+  "COMMIT",
+  "CONSTRAINT",
+  "CREATE",
+  "DEFAULT",
+  "DEFERRABLE",
+  "DELETE",
+  "DISTINCT",
+  "DROP",
+  "ELSE",
+  "ESCAPE",
+  "EXCEPT",
+  "EXISTS",
+  "FOREIGN",
+  "FROM",
+  "GROUP",
+  "HAVING",
+  "IN",
+  "INDEX",
+  "INDEXED",
+  "INSERT",
+  "INTERSECT",
+  "INTO",
+  "IS",
+  "ISNULL",
+  "JOIN",
+  "LIMIT",
+  "NOT",
+  "NOTHING",
+  "NOTNULL",
+  "NULL",
+  "ON",
+  "OR",
+  "ORDER",
+  "PRIMARY",
+  "REFERENCES",
+  "RETURNING",
+  "SELECT",
+  "SET",
+  "TABLE",
+  "THEN",
+  "TO",
+  "TRANSACTION",
+  "UNION",
+  "UNIQUE",
+  "UPDATE",
+  "USING",
+  "VALUES",
+  "WHEN",
+  "WHERE",
+];
+
+/// Every keyword of SQLite, reserved or not.
+///
+/// *Reference:* https://www.sqlite.org/lang_keywords.html
+const KEYWORDS: &[&str] = &[
+  "ABORT",
+  "ACTION",
+  "ADD",
+  "AFTER",
+  "ALL",
+  "ALTER",
+  "ALWAYS",
+  "ANALYZE",
+  "AND",
+  "AS",
+  "ASC",
+  "ATTACH",
+  "AUTOINCREMENT",
+  "BEFORE",
+  "BEGIN",
+  "BETWEEN",
+  "BY",
+  "CASCADE",
+  "CASE",
+  "CAST",
+  "CHECK",
+  "COLLATE",
+  "COLUMN",
+  "COMMIT",
+  "CONFLICT",
+  "CONSTRAINT",
+  "CREATE",
+  "CROSS",
+  "CURRENT",
+  "CURRENT_DATE",
+  "CURRENT_TIME",
+  "CURRENT_TIMESTAMP",
+  "DATABASE",
+  "DEFAULT",
+  "DEFERRABLE",
+  "DEFERRED",
+  "DELETE",
+  "DESC",
+  "DETACH",
+  "DISTINCT",
+  "DO",
+  "DROP",
+  "EACH",
+  "ELSE",
+  "END",
+  "ESCAPE",
+  "EXCEPT",
+  "EXCLUDE",
+  "EXCLUSIVE",
+  "EXISTS",
+  "EXPLAIN",
+  "FAIL",
+  "FILTER",
+  "FIRST",
+  "FOLLOWING",
+  "FOR",
+  "FOREIGN",
+  "FROM",
+  "FULL",
+  "GENERATED",
+  "GLOB",
+  "GROUP",
+  "GROUPS",
+  "HAVING",
+  "IF",
+  "IGNORE",
+  "IMMEDIATE",
+  "IN",
+  "INDEX",
+  "INDEXED",
+  "INITIALLY",
+  "INNER",
+  "INSERT",
+  "INSTEAD",
+  "INTERSECT",
+  "INTO",
+  "IS",
+  "ISNULL",
+  "JOIN",
+  "KEY",
+  "LAST",
+  "LEFT",
+  "LIKE",
+  "LIMIT",
+  "MATCH",
+  "MATERIALIZED",
+  "NATURAL",
+  "NO",
+  "NOT",
+  "NOTHING",
+  "NOTNULL",
+  "NULL",
+  "NULLS",
+  "OF",
+  "OFFSET",
+  "ON",
+  "OR",
+  "ORDER",
+  "OTHERS",
+  "OUTER",
+  "OVER",
+  "PARTITION",
+  "PLAN",
+  "PRAGMA",
+  "PRECEDING",
+  "PRIMARY",
+  "QUERY",
+  "RAISE",
+  "RANGE",
+  "RECURSIVE",
+  "REFERENCES",
+  "REGEXP",
+  "REINDEX",
+  "RELEASE",
+  "RENAME",
+  "REPLACE",
+  "RESTRICT",
+  "RETURNING",
+  "RIGHT",
+  "ROLLBACK",
+  "ROW",
+  "ROWS",
+  "SAVEPOINT",
+  "SELECT",
+  "SET",
+  "TABLE",
+  "TEMP",
+  "TEMPORARY",
+  "THEN",
+  "TIES",
+  "TO",
+  "TRANSACTION",
+  "TRIGGER",
+  "UNBOUNDED",
+  "UNION",
+  "UNIQUE",
+  "UPDATE",
+  "USING",
+  "VACUUM",
+  "VALUES",
+  "VIEW",
+  "VIRTUAL",
+  "WHEN",
+  "WHERE",
+  "WINDOW",
+  "WITH",
+  "WITHOUT",
+];
+
+/// Join keywords are identifiers everywhere but where an alias may follow a
+/// table without `AS`.
+const JOIN_KEYWORDS: &[&str] = &[
+  "CROSS", "FULL", "INNER", "LEFT", "NATURAL", "OUTER", "RIGHT",
+];
+
+/// Splits `sql` into statements and parses them.
+pub fn parse(sql: &str) -> SqliteResult<Vec<Statement>> {
+  let mut parser = Parser::new(sql)?;
+  let mut statements = vec![];
+  while let Some(statement) = parser.next_statement()? {
+    statements.push(statement);
+  }
+  Ok(statements)
+}
+
+/// Parses the statements of an SQL text one at a time.
+#[derive(Debug)]
+pub struct Parser<'a> {
+  sql: &'a str,
+  tokens: Vec<Token>,
+  position: usize,
+  /// Names of the parameters of the current statement, by index minus one:
+  /// `None` for those only ever written `?`.
+  variables: Vec<Option<String>>,
+}
+
+impl<'a> Parser<'a> {
+  /// Tokenizes `sql`, reporting unrecognized tokens.
+  pub fn new(sql: &'a str) -> SqliteResult<Self> {
+    Ok(Self {
+      sql,
+      tokens: tokenize(sql)?,
+      position: 0,
+      variables: vec![],
+    })
+  }
+
+  /// Parses the next statement, skipping empty ones. Returns `None` at the
+  /// end of the input.
+  pub fn next_statement(&mut self) -> SqliteResult<Option<Statement>> {
+    while self.eat(&TokenKind::Semicolon) {}
+    if self.peek().kind == TokenKind::Eof {
+      return Ok(None);
+    }
+    self.variables.clear();
+    let statement = self.statement()?;
+    if !self.eat(&TokenKind::Semicolon) && self.peek().kind != TokenKind::Eof {
+      return Err(self.error());
+    }
+    Ok(Some(statement))
+  }
+
+  /// Byte offset of the input not parsed yet.
+  pub fn offset(&self) -> usize {
+    self.peek().span.start
+  }
+
+  /// Number of parameters of the last statement parsed: the largest
+  /// parameter index.
+  pub fn parameter_count(&self) -> usize {
+    self.variables.len()
+  }
+
+  /// Names of the parameters of the last statement parsed, by index minus
+  /// one. A parameter first written `?NNN` is named so, and one only ever
+  /// written `?` has no name.
+  ///
+  /// *Reference:* https://www.sqlite.org/c3ref/bind_parameter_name.html
+  pub fn parameter_names(&self) -> &[Option<String>] {
+    &self.variables
+  }
+
+  pub(crate) fn statement(&mut self) -> SqliteResult<Statement> {
+    let start = self.offset();
+    let kind = if self.eat_keyword("EXPLAIN") {
+      let query_plan = self.eat_keyword("QUERY");
+      if query_plan {
+        self.expect_keyword("PLAN")?;
+      }
+      StatementKind::Explain {
+        query_plan,
+        statement: Box::new(self.statement()?),
+      }
+    } else {
+      self.statement_kind()?
+    };
+    Ok(Statement {
+      kind,
+      span: self.span_from(start),
+    })
+  }
+
+  fn statement_kind(&mut self) -> SqliteResult<StatementKind> {
+    let token = self.peek();
+    let keyword = match &token.kind {
+      TokenKind::Word(word) => word.to_ascii_uppercase(),
+      _ => return Err(self.error()),
+    };
+    match keyword.as_str() {
+      "SELECT" | "VALUES" => {
+        Ok(StatementKind::Select(Box::new(self.select()?)))
+      }
+      "WITH" => self.with_statement(),
+      "INSERT" | "REPLACE" => {
+        Ok(StatementKind::Insert(Box::new(self.insert(None)?)))
+      }
+      "UPDATE" => Ok(StatementKind::Update(Box::new(self.update(None)?))),
+      "DELETE" => Ok(StatementKind::Delete(Box::new(self.delete(None)?))),
+      "CREATE" => self.create(),
+      "DROP" => Ok(StatementKind::Drop(self.drop()?)),
+      "ALTER" => Ok(StatementKind::AlterTable(Box::new(self.alter_table()?))),
+      "PRAGMA" => Ok(StatementKind::Pragma(Box::new(self.pragma()?))),
+      "BEGIN" => self.begin(),
+      "COMMIT" | "END" => {
+        self.advance();
+        self.eat_keyword("TRANSACTION");
+        Ok(StatementKind::Commit)
+      }
+      "ROLLBACK" => self.rollback(),
+      "SAVEPOINT" => {
+        self.advance();
+        Ok(StatementKind::Savepoint(self.name()?))
+      }
+      "RELEASE" => {
+        self.advance();
+        self.eat_keyword("SAVEPOINT");
+        Ok(StatementKind::Release(self.name()?))
+      }
+      "VACUUM" => self.vacuum(),
+      "ANALYZE" | "REINDEX" => {
+        self.advance();
+        let name = match self.is_name() {
+          true => Some(self.qualified_name()?),
+          false => None,
+        };
+        Ok(match keyword.as_str() {
+          "ANALYZE" => StatementKind::Analyze(name),
+          _ => StatementKind::Reindex(name),
+        })
+      }
+      "ATTACH" => {
+        self.advance();
+        self.eat_keyword("DATABASE");
+        let file = self.expr()?;
+        self.expect_keyword("AS")?;
+        let schema = self.expr()?;
+        Ok(StatementKind::Attach { file, schema })
+      }
+      "DETACH" => {
+        self.advance();
+        self.eat_keyword("DATABASE");
+        Ok(StatementKind::Detach(self.expr()?))
+      }
+      _ => Err(self.error()),
+    }
+  }
+
+  /// A statement starting with a `WITH` clause.
+  fn with_statement(&mut self) -> SqliteResult<StatementKind> {
+    let start = self.offset();
+    let with = self.with_clause()?;
+    if self.is_keyword("INSERT") || self.is_keyword("REPLACE") {
+      Ok(StatementKind::Insert(Box::new(self.insert(Some(with))?)))
+    } else if self.is_keyword("UPDATE") {
+      Ok(StatementKind::Update(Box::new(self.update(Some(with))?)))
+    } else if self.is_keyword("DELETE") {
+      Ok(StatementKind::Delete(Box::new(self.delete(Some(with))?)))
+    } else {
+      let select = self.select_after_with(Some(with), start)?;
+      Ok(StatementKind::Select(Box::new(select)))
+    }
+  }
+
+  fn pragma(&mut self) -> SqliteResult<Pragma> {
+    self.expect_keyword("PRAGMA")?;
+    let name = self.qualified_name()?;
+    let value = if self.eat(&TokenKind::Eq) {
+      Some(self.pragma_value()?)
+    } else if self.eat(&TokenKind::LeftParen) {
+      let value = self.pragma_value()?;
+      self.expect(&TokenKind::RightParen)?;
+      Some(value)
+    } else {
+      None
+    };
+    Ok(Pragma { name, value })
+  }
+
+  fn begin(&mut self) -> SqliteResult<StatementKind> {
+    self.expect_keyword("BEGIN")?;
+    let kind = if self.eat_keyword("DEFERRED") {
+      Some(TransactionKind::Deferred)
+    } else if self.eat_keyword("IMMEDIATE") {
+      Some(TransactionKind::Immediate)
+    } else if self.eat_keyword("EXCLUSIVE") {
+      Some(TransactionKind::Exclusive)
+    } else {
+      None
+    };
+    if self.eat_keyword("TRANSACTION") && self.is_name() {
+      // Transaction names are accepted and ignored.
+      self.name()?;
+    }
+    Ok(StatementKind::Begin(kind))
+  }
+
+  fn rollback(&mut self) -> SqliteResult<StatementKind> {
+    self.expect_keyword("ROLLBACK")?;
+    self.eat_keyword("TRANSACTION");
+    if !self.eat_keyword("TO") {
+      return Ok(StatementKind::Rollback(None));
+    }
+    self.eat_keyword("SAVEPOINT");
+    Ok(StatementKind::Rollback(Some(self.name()?)))
+  }
+
+  fn vacuum(&mut self) -> SqliteResult<StatementKind> {
+    self.expect_keyword("VACUUM")?;
+    let schema = match self.is_name() && !self.is_keyword("INTO") {
+      true => Some(self.name()?),
+      false => None,
+    };
+    let into = match self.eat_keyword("INTO") {
+      true => Some(self.expr()?),
+      false => None,
+    };
+    Ok(StatementKind::Vacuum { schema, into })
+  }
+
+  // Token helpers
+
+  pub(crate) fn peek(&self) -> &Token {
+    self.peek_nth(0)
+  }
+
+  pub(crate) fn peek_nth(&self, offset: usize) -> &Token {
+    let last = self.tokens.len() - 1;
+    &self.tokens[(self.position + offset).min(last)]
+  }
+
+  pub(crate) fn advance(&mut self) -> Token {
+    let token = self.peek().clone();
+    if token.kind != TokenKind::Eof {
+      self.position += 1;
+    }
+    token
+  }
+
+  /// Span from `start` to the end of the last token consumed.
+  pub(crate) fn span_from(&self, start: usize) -> Span {
+    let end = match self.position {
+      0 => start,
+      position => self.tokens[position - 1].span.end,
+    };
+    Span::new(start, end.max(start))
+  }
+
+  pub(crate) fn is_keyword(&self, keyword: &str) -> bool {
+    self.peek().is_keyword(keyword)
+  }
+
+  pub(crate) fn is_keyword_at(&self, offset: usize, keyword: &str) -> bool {
+    self.peek_nth(offset).is_keyword(keyword)
+  }
+
+  pub(crate) fn eat_keyword(&mut self, keyword: &str) -> bool {
+    let is_keyword = self.is_keyword(keyword);
+    if is_keyword {
+      self.advance();
+    }
+    is_keyword
+  }
+
+  pub(crate) fn expect_keyword(&mut self, keyword: &str) -> SqliteResult<()> {
+    match self.eat_keyword(keyword) {
+      true => Ok(()),
+      false => Err(self.error()),
+    }
+  }
+
+  pub(crate) fn eat(&mut self, kind: &TokenKind) -> bool {
+    let is_kind = self.peek().kind == *kind;
+    if is_kind {
+      self.advance();
+    }
+    is_kind
+  }
+
+  pub(crate) fn expect(&mut self, kind: &TokenKind) -> SqliteResult<()> {
+    match self.eat(kind) {
+      true => Ok(()),
+      false => Err(self.error()),
+    }
+  }
+
+  /// The error SQLite reports for an unexpected token.
+  pub(crate) fn error(&self) -> SqliteError {
+    let token = self.peek();
+    let message = match token.kind {
+      TokenKind::Eof => "incomplete input".into(),
+      _ => format!("near \"{}\": syntax error", token.span.text(self.sql)),
+    };
+    self.error_at(token.span, message)
+  }
+
+  pub(crate) fn error_at(&self, span: Span, message: String) -> SqliteError {
+    SyntaxError::new(self.sql, span, message).into()
+  }
+
+  pub(crate) fn sql(&self) -> &'a str {
+    self.sql
+  }
+
+  // Names
+
+  /// Whether the next token can be an identifier.
+  pub(crate) fn is_name(&self) -> bool {
+    match &self.peek().kind {
+      TokenKind::Word(word) => !is_reserved(word),
+      TokenKind::QuotedIdentifier(_) | TokenKind::String(_) => true,
+      _ => false,
+    }
+  }
+
+  /// Whether the next token can be an alias without `AS`.
+  pub(crate) fn is_alias(&self) -> bool {
+    match &self.peek().kind {
+      TokenKind::Word(word) => {
+        !is_reserved(word)
+          && !JOIN_KEYWORDS.iter().any(|kw| word.eq_ignore_ascii_case(kw))
+          && !self.is_window_clause()
+      }
+      TokenKind::QuotedIdentifier(_) | TokenKind::String(_) => true,
+      _ => false,
+    }
+  }
+
+  /// An identifier. String literals are accepted as identifiers, like
+  /// SQLite does.
+  pub(crate) fn name(&mut self) -> SqliteResult<Name> {
+    let (value, quoted) = match &self.peek().kind {
+      TokenKind::Word(word) if !is_reserved(word) => (word.clone(), false),
+      TokenKind::QuotedIdentifier(name) => (name.clone(), true),
+      TokenKind::String(name) => (name.clone(), false),
+      _ => return Err(self.error()),
+    };
+    let span = self.advance().span;
+    Ok(Name {
+      value,
+      quoted,
+      span,
+    })
+  }
+
+  /// `name` or `schema.name`.
+  pub(crate) fn qualified_name(&mut self) -> SqliteResult<QualifiedName> {
+    let name = self.name()?;
+    if self.eat(&TokenKind::Dot) {
+      return Ok(QualifiedName {
+        schema: Some(name),
+        name: self.name()?,
+      });
+    }
+    Ok(QualifiedName { schema: None, name })
+  }
+
+  /// A comma-separated list of names within parentheses.
+  pub(crate) fn name_list(&mut self) -> SqliteResult<Vec<Name>> {
+    self.expect(&TokenKind::LeftParen)?;
+    let mut names = vec![self.name()?];
+    while self.eat(&TokenKind::Comma) {
+      names.push(self.name()?);
+    }
+    self.expect(&TokenKind::RightParen)?;
+    Ok(names)
+  }
+
+  /// `[NOT] EXISTS` within `IF NOT EXISTS` and `IF EXISTS`.
+  pub(crate) fn if_exists(&mut self, not: bool) -> SqliteResult<bool> {
+    if !self.eat_keyword("IF") {
+      return Ok(false);
+    }
+    if not {
+      self.expect_keyword("NOT")?;
+    }
+    self.expect_keyword("EXISTS")?;
+    Ok(true)
+  }
+
+  /// Registers a parameter, returning its index.
+  pub(crate) fn variable_index(
+    &mut self,
+    name: &str,
+    span: Span,
+  ) -> SqliteResult<usize> {
+    let index = if name == "?" {
+      self.variables.len() + 1
+    } else if let Some(number) = name.strip_prefix('?') {
+      match number.parse::<usize>() {
+        Ok(number) if (1..=MAX_VARIABLE_NUMBER).contains(&number) => number,
+        _ => {
+          return Err(self.error_at(
+            span,
+            format!(
+              "variable number must be between ?1 and ?{MAX_VARIABLE_NUMBER}"
+            ),
+          ))
+        }
+      }
+    } else {
+      let existing = self
+        .variables
+        .iter()
+        .position(|variable| variable.as_deref() == Some(name));
+      match existing {
+        Some(idx) => return Ok(idx + 1),
+        None => self.variables.len() + 1,
+      }
+    };
+    if self.variables.len() < index {
+      self.variables.resize(index, None);
+    }
+    if name != "?" && self.variables[index - 1].is_none() {
+      self.variables[index - 1] = Some(name.into());
+    }
+    Ok(index)
+  }
+}
+
+/// The largest parameter number, `SQLITE_MAX_VARIABLE_NUMBER`.
+const MAX_VARIABLE_NUMBER: usize = 32766;
+
+pub(crate) fn is_reserved(word: &str) -> bool {
+  RESERVED_KEYWORDS
+    .iter()
+    .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+/// Whether `word` is a keyword of SQLite, reserved or not.
+pub(crate) fn is_keyword(word: &str) -> bool {
+  KEYWORDS
+    .iter()
+    .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
@@ -0,0 +1,434 @@
+use super::Parser;
+use crate::result::SqliteResult;
+use crate::sql::ast::{
+  CommonTableExpression, CompoundOperator, Expr, From, IndexedBy, Join,
+  JoinConstraint, JoinKind, Limit, Name, NamedWindow, NullsOrder, OrderingTerm,
+  ResultColumn, Select, SelectBody, SelectCore, SimpleSelect, SortOrder,
+  TableOrSubquery, With,
+};
+use crate::sql::tokenizer::TokenKind;
+
+impl Parser<'_> {
+  /// Whether a `SELECT`, `VALUES` or `WITH` follows.
+  pub(crate) fn is_select_start(&self) -> bool {
+    self.is_keyword("SELECT")
+      || self.is_keyword("VALUES")
+      || self.is_keyword("WITH")
+  }
+
+  pub(crate) fn select(&mut self) -> SqliteResult<Select> {
+    let start = self.offset();
+    let with = match self.is_keyword("WITH") {
+      true => Some(self.with_clause()?),
+      false => None,
+    };
+    self.select_after_with(with, start)
+  }
+
+  /// The rest of a `SELECT` whose `WITH` clause, starting at `start`, was
+  /// already parsed.
+  pub(crate) fn select_after_with(
+    &mut self,
+    with: Option<With>,
+    start: usize,
+  ) -> SqliteResult<Select> {
+    let first = self.select_core()?;
+    let mut compounds = vec![];
+    loop {
+      let operator = if self.eat_keyword("UNION") {
+        match self.eat_keyword("ALL") {
+          true => CompoundOperator::UnionAll,
+          false => CompoundOperator::Union,
+        }
+      } else if self.eat_keyword("INTERSECT") {
+        CompoundOperator::Intersect
+      } else if self.eat_keyword("EXCEPT") {
+        CompoundOperator::Except
+      } else {
+        break;
+      };
+      compounds.push((operator, self.select_core()?));
+    }
+    let order_by = match self.is_keyword("ORDER") {
+      true => self.order_by()?,
+      false => vec![],
+    };
+    let limit = self.limit()?;
+    Ok(Select {
+      with,
+      body: SelectBody { first, compounds },
+      order_by,
+      limit,
+      span: self.span_from(start),
+    })
+  }
+
+  pub(crate) fn with_clause(&mut self) -> SqliteResult<With> {
+    self.expect_keyword("WITH")?;
+    let recursive = self.eat_keyword("RECURSIVE");
+    let mut tables = vec![self.common_table_expression()?];
+    while self.eat(&TokenKind::Comma) {
+      tables.push(self.common_table_expression()?);
+    }
+    Ok(With { recursive, tables })
+  }
+
+  fn common_table_expression(&mut self) -> SqliteResult<CommonTableExpression> {
+    let name = self.name()?;
+    let columns = match self.peek().kind == TokenKind::LeftParen {
+      true => self.name_list()?,
+      false => vec![],
+    };
+    self.expect_keyword("AS")?;
+    let materialized = if self.eat_keyword("MATERIALIZED") {
+      Some(true)
+    } else if self.is_keyword("NOT") && self.is_keyword_at(1, "MATERIALIZED") {
+      self.advance();
+      self.advance();
+      Some(false)
+    } else {
+      None
+    };
+    self.expect(&TokenKind::LeftParen)?;
+    let select = Box::new(self.select()?);
+    self.expect(&TokenKind::RightParen)?;
+    Ok(CommonTableExpression {
+      name,
+      columns,
+      materialized,
+      select,
+    })
+  }
+
+  fn select_core(&mut self) -> SqliteResult<SelectCore> {
+    if self.eat_keyword("VALUES") {
+      let mut rows = vec![self.values_row()?];
+      while self.eat(&TokenKind::Comma) {
+        let start = self.offset();
+        let row = self.values_row()?;
+        if row.len() != rows[0].len() {
+          return Err(self.error_at(
+            self.span_from(start),
+            "all VALUES must have the same number of terms".into(),
+          ));
+        }
+        rows.push(row);
+      }
+      return Ok(SelectCore::Values(rows));
+    }
+    self.expect_keyword("SELECT")?;
+    let distinct = self.eat_keyword("DISTINCT");
+    if !distinct {
+      self.eat_keyword("ALL");
+    }
+    let mut columns = vec![self.result_column()?];
+    while self.eat(&TokenKind::Comma) {
+      columns.push(self.result_column()?);
+    }
+    let from = match self.eat_keyword("FROM") {
+      true => Some(self.from()?),
+      false => None,
+    };
+    let where_clause = self.where_clause()?;
+    let group_by = match self.is_keyword("GROUP") {
+      true => {
+        self.advance();
+        self.expect_keyword("BY")?;
+        self.expr_list()?
+      }
+      false => vec![],
+    };
+    let having = match self.eat_keyword("HAVING") {
+      true => Some(self.expr()?),
+      false => None,
+    };
+    let mut windows = vec![];
+    if self.is_window_clause() {
+      self.advance();
+      loop {
+        let name = self.name()?;
+        self.expect_keyword("AS")?;
+        let window = self.window_definition()?;
+        windows.push(NamedWindow { name, window });
+        if !self.eat(&TokenKind::Comma) {
+          break;
+        }
+      }
+    }
+    Ok(SelectCore::Select(Box::new(SimpleSelect {
+      distinct,
+      columns,
+      from,
+      where_clause,
+      group_by,
+      having,
+      windows,
+    })))
+  }
+
+  /// `WINDOW` starts a clause only when followed by `name AS`.
+  pub(crate) fn is_window_clause(&self) -> bool {
+    self.is_keyword("WINDOW")
+      && matches!(
+        self.peek_nth(1).kind,
+        TokenKind::Word(_) | TokenKind::QuotedIdentifier(_)
+      )
+      && self.is_keyword_at(2, "AS")
+  }
+
+  fn values_row(&mut self) -> SqliteResult<Vec<Expr>> {
+    self.expect(&TokenKind::LeftParen)?;
+    let row = self.expr_list()?;
+    self.expect(&TokenKind::RightParen)?;
+    Ok(row)
+  }
+
+  pub(crate) fn result_column(&mut self) -> SqliteResult<ResultColumn> {
+    if self.eat(&TokenKind::Star) {
+      return Ok(ResultColumn::Star);
+    }
+    if self.is_name()
+      && self.peek_nth(1).kind == TokenKind::Dot
+      && self.peek_nth(2).kind == TokenKind::Star
+    {
+      let table = self.name()?;
+      self.advance();
+      self.advance();
+      return Ok(ResultColumn::TableStar(table));
+    }
+    let expr = self.expr()?;
+    let alias = self.alias()?;
+    Ok(ResultColumn::Expr { expr, alias })
+  }
+
+  /// `[AS] alias`.
+  fn alias(&mut self) -> SqliteResult<Option<Name>> {
+    if self.eat_keyword("AS") {
+      return Ok(Some(self.name()?));
+    }
+    match self.is_alias() {
+      true => Ok(Some(self.name()?)),
+      false => Ok(None),
+    }
+  }
+
+  pub(crate) fn where_clause(&mut self) -> SqliteResult<Option<Expr>> {
+    match self.eat_keyword("WHERE") {
+      true => Ok(Some(self.expr()?)),
+      false => Ok(None),
+    }
+  }
+
+  pub(crate) fn from(&mut self) -> SqliteResult<From> {
+    let first = self.table_or_subquery()?;
+    let mut joins = vec![];
+    while let Some((kind, natural)) = self.join_operator()? {
+      let table = self.table_or_subquery()?;
+      let constraint = if self.eat_keyword("ON") {
+        Some(JoinConstraint::On(self.expr()?))
+      } else if self.eat_keyword("USING") {
+        Some(JoinConstraint::Using(self.name_list()?))
+      } else {
+        None
+      };
+      if natural && constraint.is_some() {
+        return Err(self.error_at(
+          self.tokens[self.position - 1].span,
+          "a NATURAL join may not have an ON or USING clause".into(),
+        ));
+      }
+      joins.push(Join {
+        kind,
+        natural,
+        table,
+        constraint,
+      });
+    }
+    Ok(From { first, joins })
+  }
+
+  /// `,` or `[NATURAL] [LEFT | RIGHT | FULL [OUTER] | INNER | CROSS] JOIN`.
+  fn join_operator(&mut self) -> SqliteResult<Option<(JoinKind, bool)>> {
+    if self.eat(&TokenKind::Comma) {
+      return Ok(Some((JoinKind::Comma, false)));
+    }
+    let start = self.position;
+    let natural = self.eat_keyword("NATURAL");
+    let kind = if self.eat_keyword("LEFT") {
+      self.eat_keyword("OUTER");
+      JoinKind::Left
+    } else if self.eat_keyword("RIGHT") {
+      self.eat_keyword("OUTER");
+      JoinKind::Right
+    } else if self.eat_keyword("FULL") {
+      self.eat_keyword("OUTER");
+      JoinKind::Full
+    } else if self.eat_keyword("INNER") {
+      JoinKind::Inner
+    } else if self.eat_keyword("CROSS") {
+      JoinKind::Cross
+    } else {
+      JoinKind::Inner
+    };
+    if self.eat_keyword("JOIN") {
+      return Ok(Some((kind, natural)));
+    }
+    if self.position == start {
+      return Ok(None);
+    }
+    let message = format!(
+      "unknown join type: {}",
+      self
+        .span_from(self.tokens[start].span.start)
+        .text(self.sql())
+    );
+    Err(self.error_at(self.tokens[start].span, message))
+  }
+
+  fn table_or_subquery(&mut self) -> SqliteResult<TableOrSubquery> {
+    if self.eat(&TokenKind::LeftParen) {
+      if self.is_select_start() {
+        let select = Box::new(self.select()?);
+        self.expect(&TokenKind::RightParen)?;
+        let alias = self.alias()?;
+        return Ok(TableOrSubquery::Subquery { select, alias });
+      }
+      let from = self.from()?;
+      self.expect(&TokenKind::RightParen)?;
+      if !from.joins.is_empty() {
+        return Ok(TableOrSubquery::Join(Box::new(from)));
+      }
+      // A single parenthesized table is the table itself.
+      let alias = self.alias()?;
+      return Ok(match (from.first, alias) {
+        (
+          TableOrSubquery::Table {
+            name, indexed_by, ..
+          },
+          Some(alias),
+        ) => TableOrSubquery::Table {
+          name,
+          alias: Some(alias),
+          indexed_by,
+        },
+        (
+          TableOrSubquery::TableFunction {
+            name, arguments, ..
+          },
+          Some(alias),
+        ) => TableOrSubquery::TableFunction {
+          name,
+          arguments,
+          alias: Some(alias),
+        },
+        (TableOrSubquery::Subquery { select, .. }, Some(alias)) => {
+          TableOrSubquery::Subquery {
+            select,
+            alias: Some(alias),
+          }
+        }
+        (table, _) => table,
+      });
+    }
+    let name = self.qualified_name()?;
+    if self.eat(&TokenKind::LeftParen) {
+      let arguments = match self.eat(&TokenKind::RightParen) {
+        true => vec![],
+        false => {
+          let arguments = self.expr_list()?;
+          self.expect(&TokenKind::RightParen)?;
+          arguments
+        }
+      };
+      let alias = self.alias()?;
+      return Ok(TableOrSubquery::TableFunction {
+        name,
+        arguments,
+        alias,
+      });
+    }
+    let alias = self.alias()?;
+    let indexed_by = self.indexed_by()?;
+    Ok(TableOrSubquery::Table {
+      name,
+      alias,
+      indexed_by,
+    })
+  }
+
+  /// `INDEXED BY name` or `NOT INDEXED`.
+  pub(crate) fn indexed_by(&mut self) -> SqliteResult<Option<IndexedBy>> {
+    if self.eat_keyword("INDEXED") {
+      self.expect_keyword("BY")?;
+      return Ok(Some(IndexedBy::Index(self.name()?)));
+    }
+    if self.is_keyword("NOT") && self.is_keyword_at(1, "INDEXED") {
+      self.advance();
+      self.advance();
+      return Ok(Some(IndexedBy::NotIndexed));
+    }
+    Ok(None)
+  }
+
+  /// `ORDER BY` and its terms.
+  pub(crate) fn order_by(&mut self) -> SqliteResult<Vec<OrderingTerm>> {
+    self.expect_keyword("ORDER")?;
+    self.expect_keyword("BY")?;
+    let mut terms = vec![self.ordering_term()?];
+    while self.eat(&TokenKind::Comma) {
+      terms.push(self.ordering_term()?);
+    }
+    Ok(terms)
+  }
+
+  fn ordering_term(&mut self) -> SqliteResult<OrderingTerm> {
+    let expr = self.expr()?;
+    let order = self.sort_order().unwrap_or_default();
+    let nulls = if self.eat_keyword("NULLS") {
+      if self.eat_keyword("FIRST") {
+        Some(NullsOrder::First)
+      } else {
+        self.expect_keyword("LAST")?;
+        Some(NullsOrder::Last)
+      }
+    } else {
+      None
+    };
+    Ok(OrderingTerm { expr, order, nulls })
+  }
+
+  /// `ASC` or `DESC`, when given.
+  pub(crate) fn sort_order(&mut self) -> Option<SortOrder> {
+    if self.eat_keyword("ASC") {
+      Some(SortOrder::Asc)
+    } else if self.eat_keyword("DESC") {
+      Some(SortOrder::Desc)
+    } else {
+      None
+    }
+  }
+
+  /// `LIMIT limit [OFFSET offset]` or `LIMIT offset, limit`.
+  pub(crate) fn limit(&mut self) -> SqliteResult<Option<Limit>> {
+    if !self.eat_keyword("LIMIT") {
+      return Ok(None);
+    }
+    let first = self.expr()?;
+    if self.eat_keyword("OFFSET") {
+      return Ok(Some(Limit {
+        limit: first,
+        offset: Some(self.expr()?),
+      }));
+    }
+    if self.eat(&TokenKind::Comma) {
+      return Ok(Some(Limit {
+        limit: self.expr()?,
+        offset: Some(first),
+      }));
+    }
+    Ok(Some(Limit {
+      limit: first,
+      offset: None,
+    }))
+  }
+}
@@ -0,0 +1,343 @@
+//! # Tokenizer
+//!
+//!  When a string containing SQL statements is to be evaluated it is first
+//! sent to the tokenizer. The tokenizer breaks the SQL text into tokens and
+//! hands those tokens one by one to the parser.
+//!
+//! *Reference:* https://www.sqlite.org/arch.html#tokenizer
+
+use super::ast::Span;
+use super::error::SyntaxError;
+use crate::result::SqliteResult;
+
+/// A token of SQL text, with its location.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+  pub kind: TokenKind,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+  /// A keyword or an unquoted identifier.
+  Word(String),
+  /// An identifier in double-quotes, square brackets or grave accents,
+  /// without the quotes.
+  QuotedIdentifier(String),
+  /// A string literal in single quotes, without the quotes.
+  String(String),
+  /// A blob literal, `X'...'`.
+  Blob(Vec<u8>),
+  /// A numeric literal, as written.
+  Number(String),
+  /// A parameter: `?`, `?NNN`, `:AAAA`, `@AAAA` or `$AAAA`, as written.
+  Variable(String),
+  LeftParen,
+  RightParen,
+  Semicolon,
+  Comma,
+  Dot,
+  Plus,
+  Minus,
+  Star,
+  Slash,
+  Percent,
+  /// `=` or `==`.
+  Eq,
+  /// `!=` or `<>`.
+  NotEq,
+  Lt,
+  LtEq,
+  Gt,
+  GtEq,
+  ShiftLeft,
+  ShiftRight,
+  BitAnd,
+  BitOr,
+  BitNot,
+  /// `||`.
+  Concat,
+  /// `->`.
+  Arrow,
+  /// `->>`.
+  LongArrow,
+  /// End of the input.
+  Eof,
+}
+
+impl Token {
+  /// Whether the token is the keyword `keyword`, given in upper case.
+  pub fn is_keyword(&self, keyword: &str) -> bool {
+    matches!(&self.kind, TokenKind::Word(word) if word.eq_ignore_ascii_case(keyword))
+  }
+}
+
+/// Splits `sql` into tokens, skipping whitespace and comments. The last token
+/// is always [`TokenKind::Eof`].
+pub fn tokenize(sql: &str) -> SqliteResult<Vec<Token>> {
+  let mut tokenizer = Tokenizer {
+    sql,
+    bytes: sql.as_bytes(),
+    position: 0,
+  };
+  let mut tokens = vec![];
+  while let Some(token) = tokenizer.next_token()? {
+    tokens.push(token);
+  }
+  tokens.push(Token {
+    kind: TokenKind::Eof,
+    span: Span::new(sql.len(), sql.len()),
+  });
+  Ok(tokens)
+}
+
+struct Tokenizer<'a> {
+  sql: &'a str,
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl Tokenizer<'_> {
+  fn next_token(&mut self) -> SqliteResult<Option<Token>> {
+    self.skip_whitespace_and_comments();
+    let start = self.position;
+    let Some(byte) = self.peek(0) else {
+      return Ok(None);
+    };
+    let kind = match byte {
+      b'(' => self.punct(1, TokenKind::LeftParen),
+      b')' => self.punct(1, TokenKind::RightParen),
+      b';' => self.punct(1, TokenKind::Semicolon),
+      b',' => self.punct(1, TokenKind::Comma),
+      b'+' => self.punct(1, TokenKind::Plus),
+      b'*' => self.punct(1, TokenKind::Star),
+      b'/' => self.punct(1, TokenKind::Slash),
+      b'%' => self.punct(1, TokenKind::Percent),
+      b'&' => self.punct(1, TokenKind::BitAnd),
+      b'~' => self.punct(1, TokenKind::BitNot),
+      b'-' => match (self.peek(1), self.peek(2)) {
+        (Some(b'>'), Some(b'>')) => self.punct(3, TokenKind::LongArrow),
+        (Some(b'>'), _) => self.punct(2, TokenKind::Arrow),
+        _ => self.punct(1, TokenKind::Minus),
+      },
+      b'=' => match self.peek(1) {
+        Some(b'=') => self.punct(2, TokenKind::Eq),
+        _ => self.punct(1, TokenKind::Eq),
+      },
+      b'<' => match self.peek(1) {
+        Some(b'=') => self.punct(2, TokenKind::LtEq),
+        Some(b'>') => self.punct(2, TokenKind::NotEq),
+        Some(b'<') => self.punct(2, TokenKind::ShiftLeft),
+        _ => self.punct(1, TokenKind::Lt),
+      },
+      b'>' => match self.peek(1) {
+        Some(b'=') => self.punct(2, TokenKind::GtEq),
+        Some(b'>') => self.punct(2, TokenKind::ShiftRight),
+        _ => self.punct(1, TokenKind::Gt),
+      },
+      b'!' => match self.peek(1) {
+        Some(b'=') => self.punct(2, TokenKind::NotEq),
+        _ => return Err(self.unrecognized(start, start + 1)),
+      },
+      b'|' => match self.peek(1) {
+        Some(b'|') => self.punct(2, TokenKind::Concat),
+        _ => self.punct(1, TokenKind::BitOr),
+      },
+      b'\'' => TokenKind::String(self.quoted(b'\'', b'\'')?),
+      b'"' => TokenKind::QuotedIdentifier(self.quoted(b'"', b'"')?),
+      b'`' => TokenKind::QuotedIdentifier(self.quoted(b'`', b'`')?),
+      b'[' => TokenKind::QuotedIdentifier(self.quoted(b'[', b']')?),
+      b'.' if self.peek(1).is_some_and(|byte| byte.is_ascii_digit()) => {
+        self.number()?
+      }
+      b'.' => self.punct(1, TokenKind::Dot),
+      b'0'..=b'9' => self.number()?,
+      b'?' => {
+        self.position += 1;
+        self.skip_while(|byte| byte.is_ascii_digit());
+        TokenKind::Variable(self.sql[start..self.position].into())
+      }
+      b':' | b'@' | b'$' => self.named_variable()?,
+      b'x' | b'X' if self.peek(1) == Some(b'\'') => self.blob()?,
+      byte if is_identifier_start(byte) => {
+        self.skip_while(is_identifier_char);
+        TokenKind::Word(self.sql[start..self.position].into())
+      }
+      _ => {
+        let end = start + self.char_len(start);
+        return Err(self.unrecognized(start, end));
+      }
+    };
+    Ok(Some(Token {
+      kind,
+      span: Span::new(start, self.position),
+    }))
+  }
+
+  fn peek(&self, offset: usize) -> Option<u8> {
+    self.bytes.get(self.position + offset).copied()
+  }
+
+  fn punct(&mut self, len: usize, kind: TokenKind) -> TokenKind {
+    self.position += len;
+    kind
+  }
+
+  fn skip_while(&mut self, predicate: impl Fn(u8) -> bool) {
+    while self.peek(0).is_some_and(&predicate) {
+      self.position += 1;
+    }
+  }
+
+  fn char_len(&self, position: usize) -> usize {
+    self.sql[position..]
+      .chars()
+      .next()
+      .map_or(1, char::len_utf8)
+  }
+
+  fn skip_whitespace_and_comments(&mut self) {
+    loop {
+      match (self.peek(0), self.peek(1)) {
+        (Some(b' ' | b'\t' | b'\n' | b'\x0c' | b'\r'), _) => self.position += 1,
+        (Some(b'-'), Some(b'-')) => self.skip_while(|byte| byte != b'\n'),
+        (Some(b'/'), Some(b'*')) => {
+          // An unterminated comment runs to the end of the input.
+          self.position = self.sql[self.position + 2..]
+            .find("*/")
+            .map_or(self.sql.len(), |end| self.position + 2 + end + 2);
+        }
+        _ => return,
+      }
+    }
+  }
+
+  /// Reads text between `open` and `close`, where a doubled `close` stands
+  /// for itself, except within square brackets.
+  fn quoted(&mut self, open: u8, close: u8) -> SqliteResult<String> {
+    let start = self.position;
+    self.position += 1;
+    let mut text = String::new();
+    let mut chunk_start = self.position;
+    loop {
+      match self.peek(0) {
+        None => return Err(self.unrecognized(start, self.sql.len())),
+        Some(byte) if byte == close => {
+          text.push_str(&self.sql[chunk_start..self.position]);
+          self.position += 1;
+          if open != b'[' && self.peek(0) == Some(close) {
+            chunk_start = self.position;
+            self.position += 1;
+          } else {
+            return Ok(text);
+          }
+        }
+        Some(_) => self.position += 1,
+      }
+    }
+  }
+
+  fn number(&mut self) -> SqliteResult<TokenKind> {
+    let start = self.position;
+    if self.peek(0) == Some(b'0')
+      && matches!(self.peek(1), Some(b'x' | b'X'))
+      && self.peek(2).is_some_and(|byte| byte.is_ascii_hexdigit())
+    {
+      self.position += 2;
+      self.skip_while(|byte| byte.is_ascii_hexdigit());
+    } else {
+      self.skip_while(|byte| byte.is_ascii_digit());
+      if self.peek(0) == Some(b'.') {
+        self.position += 1;
+        self.skip_while(|byte| byte.is_ascii_digit());
+      }
+      let has_exponent = matches!(self.peek(0), Some(b'e' | b'E'))
+        && match self.peek(1) {
+          Some(b'+' | b'-') => self.peek(2).is_some_and(|b| b.is_ascii_digit()),
+          Some(byte) => byte.is_ascii_digit(),
+          None => false,
+        };
+      if has_exponent {
+        self.position += 2;
+        self.skip_while(|byte| byte.is_ascii_digit());
+      }
+    }
+    // A number running into an identifier, like `12abc`, is not a token.
+    if self.peek(0).is_some_and(is_identifier_char) {
+      self.skip_while(is_identifier_char);
+      return Err(self.unrecognized(start, self.position));
+    }
+    Ok(TokenKind::Number(self.sql[start..self.position].into()))
+  }
+
+  fn named_variable(&mut self) -> SqliteResult<TokenKind> {
+    let start = self.position;
+    let is_tcl = self.peek(0) == Some(b'$');
+    self.position += 1;
+    loop {
+      match self.peek(0) {
+        Some(byte) if is_identifier_char(byte) => self.position += 1,
+        // TCL-style names may contain `::` and end with `(...)`.
+        Some(b':') if is_tcl && self.peek(1) == Some(b':') => {
+          self.position += 2
+        }
+        Some(b'(') if is_tcl && self.position > start + 1 => {
+          match self.sql[self.position..].find(')') {
+            Some(end)
+              if !self.sql[self.position..self.position + end]
+                .contains(char::is_whitespace) =>
+            {
+              self.position += end + 1;
+              break;
+            }
+            _ => return Err(self.unrecognized(start, self.position + 1)),
+          }
+        }
+        _ => break,
+      }
+    }
+    if self.position == start + 1 {
+      return Err(self.unrecognized(start, self.position));
+    }
+    Ok(TokenKind::Variable(self.sql[start..self.position].into()))
+  }
+
+  fn blob(&mut self) -> SqliteResult<TokenKind> {
+    let start = self.position;
+    self.position += 2;
+    self.skip_while(|byte| byte.is_ascii_hexdigit());
+    let digits = &self.sql[start + 2..self.position];
+    if self.peek(0) != Some(b'\'') || digits.len() % 2 != 0 {
+      self.skip_while(|byte| byte != b'\'');
+      let end = (self.position + 1).min(self.sql.len());
+      return Err(self.unrecognized(start, end));
+    }
+    self.position += 1;
+    let blob = (0..digits.len())
+      .step_by(2)
+      .map(|idx| u8::from_str_radix(&digits[idx..idx + 2], 16).unwrap_or(0))
+      .collect();
+    Ok(TokenKind::Blob(blob))
+  }
+
+  fn unrecognized(
+    &self,
+    start: usize,
+    end: usize,
+  ) -> crate::result::SqliteError {
+    SyntaxError::new(
+      self.sql,
+      Span::new(start, end),
+      format!("unrecognized token: \"{}\"", &self.sql[start..end]),
+    )
+    .into()
+  }
+}
+
+fn is_identifier_start(byte: u8) -> bool {
+  byte.is_ascii_alphabetic() || byte == b'_' || byte >= 0x80
+}
+
+fn is_identifier_char(byte: u8) -> bool {
+  is_identifier_start(byte) || byte.is_ascii_digit() || byte == b'$'
+}
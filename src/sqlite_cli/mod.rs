@@ -12,7 +12,7 @@ pub(crate) struct SQliteCli {
 }
 
 impl SQliteCli {
-  pub(crate) fn usage(&self) {
+  pub(crate) fn usage() {
     eprintln!(
       "Usage:{pkg_name} [OPTION]",
       pkg_name = env!("CARGO_PKG_NAME")
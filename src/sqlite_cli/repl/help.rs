@@ -24,7 +24,7 @@ impl ReplHelp {
     match command.as_str().trim_start_matches('.') {
       "dbinfo" => ReplDbInfo::help()?,
       "open" => ReplOpen::help()?,
-      _ => todo!(),
+      s => println!(r#"Error: no help for "{s}". Enter ".help" for help"#),
     }
     Ok(())
   }
@@ -14,7 +14,6 @@ use sqlite_rs::{io::SqliteIoMode, SqliteConnection};
 #[derive(Debug)]
 pub(crate) struct SqliteCliRepl {
   is_tty: bool,
-  cli: Cli,
   conn: SqliteConnection,
 }
 
@@ -40,7 +39,7 @@ impl SqliteCliRepl {
 
     let is_tty = stdin().is_terminal();
 
-    Ok(Self { cli, conn, is_tty })
+    Ok(Self { conn, is_tty })
   }
   fn run_from_pipe(&mut self) -> SqliteCliResult<()> {
     use std::io;
@@ -55,7 +54,7 @@ impl SqliteCliRepl {
         s => self.internal_command(s)?,
       };
     } else {
-      self::sql::run(normalized_input)?;
+      self::sql::run(&mut self.conn, normalized_input)?;
     }
     Ok(())
   }
@@ -89,7 +88,7 @@ impl SqliteCliRepl {
           s => self.internal_command(s)?,
         };
       } else {
-        self::sql::run(normalized_input)?;
+        self::sql::run(&mut self.conn, normalized_input)?;
       }
     }
     Ok(())
@@ -1,6 +1,178 @@
 use crate::sqlite_cli::result::SqliteCliResult;
+use sqlite_rs::result::{SqliteError, SqliteResult};
+use sqlite_rs::sql::{ast::StatementKind, Parser};
+use sqlite_rs::SqliteConnection;
 
-pub(super) fn run(normalized_input: impl AsRef<str>) -> SqliteCliResult<()> {
-  println!("SQL queries is not implemented");
+pub(super) fn run(
+  conn: &mut SqliteConnection,
+  normalized_input: impl AsRef<str>,
+) -> SqliteCliResult<()> {
+  let sql = normalized_input.as_ref();
+  let output = output(sql);
+  match output {
+    Output::Nothing => return Ok(()),
+    Output::Change => return report(conn.execute(sql).map(|_| ())),
+    _ => {}
+  }
+  let rows = match conn.query(sql) {
+    Ok(rows) => rows,
+    Err(error) => return report(Err(error)),
+  };
+  let mut lines = vec![];
+  for row in rows {
+    match row {
+      Ok(row) => {
+        let values: Vec<String> =
+          row.values().iter().map(ToString::to_string).collect();
+        match output {
+          Output::Program | Output::QueryPlan => lines.push(values),
+          _ => println!("{}", values.join("|")),
+        }
+      }
+      Err(error) => return report(Err(error)),
+    }
+  }
+  match output {
+    Output::Program => print_program(&lines),
+    Output::QueryPlan => print_query_plan(&lines),
+    _ => {}
+  }
   Ok(())
 }
+
+/// How the results of a statement are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+  /// Nothing, as there is no statement, only blanks or comments.
+  Nothing,
+  /// Nothing: the statement changes the database.
+  Change,
+  /// One line per row, its values separated by `|`.
+  Rows,
+  /// The instructions of `EXPLAIN`, in aligned columns.
+  Program,
+  /// The lines of `EXPLAIN QUERY PLAN`, as a tree.
+  QueryPlan,
+}
+
+/// How the results of `sql` are shown. Statements that do not parse are
+/// left for the query to report.
+fn output(sql: &str) -> Output {
+  let statement =
+    Parser::new(sql).and_then(|mut parser| parser.next_statement());
+  match statement.map(|statement| statement.map(|statement| statement.kind)) {
+    Ok(Some(StatementKind::Select(_) | StatementKind::Pragma(_))) => {
+      Output::Rows
+    }
+    Ok(Some(StatementKind::Explain {
+      query_plan: false, ..
+    })) => Output::Program,
+    Ok(Some(StatementKind::Explain { .. })) => Output::QueryPlan,
+    Ok(Some(_)) => Output::Change,
+    Ok(None) => Output::Nothing,
+    Err(_) => Output::Rows,
+  }
+}
+
+/// Prints the instructions of a program as the `sqlite3` shell does, the
+/// bodies of loops indented.
+fn print_program(lines: &[Vec<String>]) {
+  const WIDTHS: [usize; 8] = [4, 13, 4, 4, 4, 13, 2, 13];
+  const HEADER: [&str; 8] =
+    ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"];
+  let header = HEADER
+    .iter()
+    .zip(WIDTHS)
+    .map(|(name, width)| format!("{name:width$}"))
+    .collect::<Vec<_>>();
+  println!("{}", header.join("  "));
+  let dashes = WIDTHS.map(|width| "-".repeat(width));
+  println!("{}", dashes.join("  "));
+  let mut indents = vec![0; lines.len()];
+  for (addr, line) in lines.iter().enumerate() {
+    let is_next =
+      matches!(line.get(1).map(String::as_str), Some("Next" | "SorterNext"));
+    let target = line.get(3).and_then(|p2| p2.parse::<usize>().ok());
+    if let (true, Some(target)) = (is_next, target) {
+      for indent in indents.iter_mut().take(addr).skip(target) {
+        *indent += 1;
+      }
+    }
+  }
+  for (line, indent) in lines.iter().zip(indents) {
+    let mut fields = line
+      .iter()
+      .zip(WIDTHS)
+      .map(|(value, width)| format!("{value:width$}"))
+      .collect::<Vec<_>>();
+    if let Some(opcode) = fields.get_mut(1) {
+      opcode.insert_str(0, &"  ".repeat(indent));
+    }
+    if let (Some(comment), Some(value)) = (fields.last_mut(), line.last()) {
+      comment.clone_from(value);
+    }
+    println!("{}", fields.join("  "));
+  }
+}
+
+/// Prints the lines of a query plan as the `sqlite3` shell does, each under
+/// its parent.
+fn print_query_plan(lines: &[Vec<String>]) {
+  let field = |line: &Vec<String>, i: usize| {
+    line
+      .get(i)
+      .and_then(|value| value.parse::<i64>().ok())
+      .unwrap_or(0)
+  };
+  let nodes = lines
+    .iter()
+    .map(|line| {
+      let detail = line.get(3).cloned().unwrap_or_default();
+      (field(line, 0), field(line, 1), detail)
+    })
+    .collect::<Vec<_>>();
+  // Whether each line is the last one under its parent.
+  let is_last = nodes
+    .iter()
+    .enumerate()
+    .map(|(i, (_, parent, _))| nodes[i + 1..].iter().all(|n| n.1 != *parent))
+    .collect::<Vec<_>>();
+  println!("QUERY PLAN");
+  for (i, (_, parent, detail)) in nodes.iter().enumerate() {
+    let mut prefix = String::new();
+    let mut ancestor = *parent;
+    while let Some(j) = nodes.iter().position(|n| n.0 == ancestor) {
+      prefix.insert_str(0, if is_last[j] { "   " } else { "|  " });
+      ancestor = nodes[j].1;
+    }
+    let branch = if is_last[i] { "`--" } else { "|--" };
+    println!("{prefix}{branch}{detail}");
+  }
+}
+
+/// Prints the errors a user can fix, and returns the others.
+fn report(result: SqliteResult<()>) -> SqliteCliResult<()> {
+  match result {
+    Ok(()) => Ok(()),
+    Err(SqliteError::Syntax(error)) => {
+      println!("Parse error: {error}");
+      Ok(())
+    }
+    Err(
+      SqliteError::Custom(error)
+      | SqliteError::Constraint { message: error, .. },
+    ) => {
+      println!("Error: {error}");
+      Ok(())
+    }
+    Err(SqliteError::ReadOnly) => {
+      println!("Error: attempt to write a readonly database");
+      Ok(())
+    }
+    Err(SqliteError::Corrupt(_)) => {
+      println!("Error: database disk image is malformed");
+      Ok(())
+    }
+    Err(error) => Err(error.into()),
+  }
+}
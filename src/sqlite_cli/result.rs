@@ -18,7 +18,15 @@ pub(crate) enum SqliteCliError {
 
 impl Display for SqliteCliError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{:?}", self)
+    match self {
+      Self::Infallible(_) => write!(f, "Infallible"),
+      Self::SqliteRsLib(error) => write!(f, "{error}"),
+      Self::Custom(error) => write!(f, "{error}"),
+      Self::StdIo(error) => write!(f, "{error}"),
+      Self::InvalidCLiArgs(error) => write!(f, "Invalid CLI args: {error}"),
+      Self::AddrParseError(error) => write!(f, "{error}"),
+      Self::ParseIntError(error) => write!(f, "{error}"),
+    }
   }
 }
 impl From<Infallible> for SqliteCliError {
@@ -0,0 +1,346 @@
+use crate::runtime::{
+  AutoVacuum, BtreeCursor, Collation, KeyColumn, KeyInfo, PtrmapType, Record,
+  Value,
+};
+use crate::SqliteConnection;
+
+fn row(rowid: i64, len: usize) -> Vec<u8> {
+  Record::encode(&[Value::Integer(rowid), Value::Text("x".repeat(len))])
+}
+
+fn scan(conn: &mut SqliteConnection, root: u32) -> Vec<(i64, Vec<Value>)> {
+  let mut btree = conn.runtime_mut().btree();
+  let mut cursor = BtreeCursor::new(root);
+  let mut rows = vec![];
+  let mut has_row = cursor.first(&mut btree).unwrap();
+  while has_row {
+    let rowid = cursor.rowid(&btree).unwrap_or_default();
+    rows.push((rowid, cursor.record(&mut btree).unwrap()));
+    has_row = cursor.next(&mut btree).unwrap();
+  }
+  rows
+}
+
+#[test]
+fn ok_on_table_insert_with_splits() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let root = conn.runtime_mut().btree().create_table().unwrap();
+  let mut rowids: Vec<i64> = (1..=3000).map(|i| (i * 7919) % 3001).collect();
+  for rowid in rowids.iter() {
+    let payload = row(*rowid, (*rowid as usize) % 150);
+    conn
+      .runtime_mut()
+      .btree()
+      .insert(root, *rowid, &payload)
+      .unwrap();
+  }
+  conn.runtime_mut().commit().unwrap();
+
+  rowids.sort();
+  let rows = scan(&mut conn, root);
+  assert_eq!(
+    rows.iter().map(|(rowid, _)| *rowid).collect::<Vec<_>>(),
+    rowids
+  );
+  for (rowid, values) in rows {
+    assert_eq!(values[0], Value::Integer(rowid));
+    assert_eq!(values[1], Value::Text("x".repeat((rowid as usize) % 150)));
+  }
+  assert!(conn.runtime().pager().page_count() > 10);
+}
+
+#[test]
+fn ok_on_overflow_payloads_and_replace() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let root = conn.runtime_mut().btree().create_table().unwrap();
+  for rowid in 1..=20 {
+    let payload = row(rowid, 10_000 + rowid as usize);
+    conn
+      .runtime_mut()
+      .btree()
+      .insert(root, rowid, &payload)
+      .unwrap();
+  }
+  // Replacing a large row frees its overflow chain, which is then reused.
+  conn
+    .runtime_mut()
+    .btree()
+    .insert(root, 5, &row(5, 10))
+    .unwrap();
+  let freed = **conn.runtime().header().freelist_pages().total();
+  assert!(freed > 0);
+  conn
+    .runtime_mut()
+    .btree()
+    .insert(root, 21, &row(21, 9_000))
+    .unwrap();
+  conn.runtime_mut().commit().unwrap();
+  assert!(**conn.runtime().header().freelist_pages().total() < freed);
+
+  let rows = scan(&mut conn, root);
+  assert_eq!(rows.len(), 21);
+  assert_eq!(rows[4].1[1], Value::Text("x".repeat(10)));
+  assert_eq!(rows[20].1[1], Value::Text("x".repeat(9_000)));
+  assert_eq!(rows[19].1[1], Value::Text("x".repeat(10_020)));
+}
+
+#[test]
+fn ok_on_persisted_table_btree() {
+  let path = std::env::temp_dir().join("sqlite-rs-btree-persisted.db");
+  let _ = std::fs::remove_file(&path);
+  let uri = format!("sqlite://{}?mode=rwc", path.display());
+  let root = {
+    let mut conn = SqliteConnection::open(&uri).unwrap();
+    let root = conn.runtime_mut().btree().create_table().unwrap();
+    for rowid in 1..=500 {
+      conn
+        .runtime_mut()
+        .btree()
+        .insert(root, rowid, &row(rowid, 40))
+        .unwrap();
+    }
+    conn.runtime_mut().commit().unwrap();
+    root
+  };
+
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  let header = conn.runtime().header();
+  assert_eq!(
+    **header.db_filesize_in_pages(),
+    conn.runtime().pager().page_count()
+  );
+  assert_eq!(**header.file_change_counter(), **header.version_valid_for());
+  assert_eq!(scan(&mut conn, root).len(), 500);
+  let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn ok_on_table_delete_with_merges() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let root = conn.runtime_mut().btree().create_table().unwrap();
+  for rowid in 1..=2000 {
+    let payload = row(rowid, if rowid % 50 == 1 { 6_000 } else { 60 });
+    conn
+      .runtime_mut()
+      .btree()
+      .insert(root, rowid, &payload)
+      .unwrap();
+  }
+  conn.runtime_mut().commit().unwrap();
+  let page_count = conn.runtime().pager().page_count();
+
+  for rowid in (1..=2000).filter(|rowid| rowid % 10 != 0) {
+    assert!(conn.runtime_mut().btree().delete(root, rowid).unwrap());
+  }
+  assert!(!conn.runtime_mut().btree().delete(root, 1).unwrap());
+  conn.runtime_mut().commit().unwrap();
+
+  let rows = scan(&mut conn, root);
+  assert_eq!(
+    rows.iter().map(|(rowid, _)| *rowid).collect::<Vec<_>>(),
+    (1..=200).map(|rowid| rowid * 10).collect::<Vec<_>>()
+  );
+  let free_pages = **conn.runtime().header().freelist_pages().total();
+  assert!(free_pages > page_count / 2);
+
+  // Freed pages are reused before the file grows.
+  for rowid in 2001..=2100 {
+    conn
+      .runtime_mut()
+      .btree()
+      .insert(root, rowid, &row(rowid, 60))
+      .unwrap();
+  }
+  conn.runtime_mut().commit().unwrap();
+  assert_eq!(conn.runtime().pager().page_count(), page_count);
+  assert!(**conn.runtime().header().freelist_pages().total() < free_pages);
+
+  for rowid in (10..=2000).step_by(10).chain(2001..=2100) {
+    assert!(conn.runtime_mut().btree().delete(root, rowid).unwrap());
+  }
+  conn.runtime_mut().commit().unwrap();
+  assert!(scan(&mut conn, root).is_empty());
+  assert_eq!(
+    **conn.runtime().header().freelist_pages().total(),
+    page_count - 2
+  );
+}
+
+#[test]
+fn ok_on_index_insert_and_delete() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let key_info = KeyInfo::new(vec![KeyColumn {
+    descending: true,
+    collation: Collation::NoCase,
+  }]);
+  let key = |rowid: i64| {
+    let name = format!("{}-{}", ["Ab", "aC", "B"][rowid as usize % 3], rowid);
+    vec![
+      Value::Text(name.repeat(1 + rowid as usize % 40)),
+      rowid.into(),
+    ]
+  };
+  let root = conn.runtime_mut().btree().create_index().unwrap();
+  for rowid in 1..=1500 {
+    conn
+      .runtime_mut()
+      .btree()
+      .insert_index(root, &key(rowid), &key_info)
+      .unwrap();
+  }
+  for rowid in (1..=1500).filter(|rowid| rowid % 3 != 0) {
+    assert!(conn
+      .runtime_mut()
+      .btree()
+      .delete_index(root, &key(rowid), &key_info)
+      .unwrap());
+  }
+  assert!(!conn
+    .runtime_mut()
+    .btree()
+    .delete_index(root, &key(1), &key_info)
+    .unwrap());
+  conn.runtime_mut().commit().unwrap();
+
+  let mut expected: Vec<Vec<Value>> =
+    (1..=1500).filter(|rowid| rowid % 3 == 0).map(key).collect();
+  expected.sort_by(|left, right| key_info.compare(left, right));
+  let keys: Vec<Vec<Value>> = scan(&mut conn, root)
+    .into_iter()
+    .map(|(_, key)| key)
+    .collect();
+  assert_eq!(keys, expected);
+}
+
+#[test]
+fn ok_on_freeblock_reuse_and_defragmentation() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let mut btree = conn.runtime_mut().btree();
+  let root = btree.create_table().unwrap();
+  for rowid in 1..=20 {
+    btree.insert(root, rowid, &row(rowid, 150)).unwrap();
+  }
+  let stats = btree.page_stats(root).unwrap();
+  assert_eq!(stats.cell_count(), 20);
+  assert!(!stats.is_fragmented());
+
+  // Deleting every other row leaves freeblocks behind, except for the last
+  // row, which borders the unallocated area.
+  for rowid in (2..=20).step_by(2) {
+    assert!(btree.delete(root, rowid).unwrap());
+  }
+  let stats = btree.page_stats(root).unwrap();
+  assert_eq!(stats.cell_count(), 10);
+  assert_eq!(stats.freeblock_count(), 9);
+  assert!(stats.is_fragmented());
+
+  // A row of the same size goes to a freeblock.
+  btree.insert(root, 2, &row(2, 150)).unwrap();
+  let stats = btree.page_stats(root).unwrap();
+  assert_eq!(stats.freeblock_count(), 8);
+
+  // A row larger than any freeblock or the unallocated area defragments the
+  // page instead of splitting it.
+  let free_bytes = stats.free_bytes();
+  assert!(stats.unallocated_bytes() < free_bytes - 40);
+  btree.insert(root, 100, &row(100, free_bytes - 40)).unwrap();
+  let stats = btree.btree_stats(root).unwrap();
+  assert_eq!(stats.len(), 1);
+  assert_eq!(stats[0].freeblock_count(), 0);
+  assert_eq!(stats[0].fragmented_bytes(), 0);
+  assert_eq!(stats[0].cell_count(), 12);
+
+  let rows = scan(&mut conn, root);
+  assert_eq!(rows.len(), 12);
+  assert_eq!(rows[11].1[1], Value::Text("x".repeat(free_bytes - 40)));
+}
+
+#[test]
+fn ok_on_auto_vacuum_and_ptrmap() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  conn
+    .runtime_mut()
+    .btree()
+    .set_auto_vacuum(AutoVacuum::Full)
+    .unwrap();
+  let root = conn.runtime_mut().btree().create_table().unwrap();
+  let index_root = conn.runtime_mut().btree().create_index().unwrap();
+  // Page 2 is the first ptrmap page, roots follow it.
+  assert_eq!((root, index_root), (3, 4));
+  for rowid in 1..=1000 {
+    let payload = row(rowid, if rowid % 25 == 1 { 9_000 } else { 80 });
+    let mut btree = conn.runtime_mut().btree();
+    btree.insert(root, rowid, &payload).unwrap();
+    btree
+      .insert_index(index_root, &[rowid.into()], &KeyInfo::default())
+      .unwrap();
+  }
+  conn.runtime_mut().commit().unwrap();
+  let page_count = conn.runtime().pager().page_count();
+
+  let mut btree = conn.runtime_mut().btree();
+  assert_eq!(btree.auto_vacuum(), AutoVacuum::Full);
+  let entries = btree.ptrmap_entries().unwrap();
+  assert_eq!(entries.len() as u32, page_count - 2);
+  let roots: Vec<u32> = entries
+    .iter()
+    .filter(|(_, entry)| entry.page_type() == PtrmapType::RootPage)
+    .map(|(page_number, _)| *page_number)
+    .collect();
+  assert_eq!(roots, vec![root, index_root]);
+  assert!(entries
+    .iter()
+    .any(|(_, entry)| entry.page_type() == PtrmapType::Overflow2));
+
+  // Full auto-vacuum gives freed pages back at commit.
+  for rowid in (1..=1000).filter(|rowid| rowid % 4 != 0) {
+    assert!(btree.delete(root, rowid).unwrap());
+  }
+  conn.runtime_mut().commit().unwrap();
+  assert_eq!(**conn.runtime().header().freelist_pages().total(), 0);
+  assert!(conn.runtime().pager().page_count() < page_count / 2);
+  let rows = scan(&mut conn, root);
+  assert_eq!(
+    rows.iter().map(|(rowid, _)| *rowid).collect::<Vec<_>>(),
+    (1..=250).map(|rowid| rowid * 4).collect::<Vec<_>>()
+  );
+  assert_eq!(scan(&mut conn, index_root).len(), 1000);
+}
+
+#[test]
+fn ok_on_incremental_vacuum() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let mut btree = conn.runtime_mut().btree();
+  btree.set_auto_vacuum(AutoVacuum::Incremental).unwrap();
+  let root = btree.create_table().unwrap();
+  for rowid in 1..=500 {
+    btree.insert(root, rowid, &row(rowid, 200)).unwrap();
+  }
+  conn.runtime_mut().commit().unwrap();
+
+  let mut btree = conn.runtime_mut().btree();
+  for rowid in 1..=400 {
+    assert!(btree.delete(root, rowid).unwrap());
+  }
+  conn.runtime_mut().commit().unwrap();
+  let free_pages = **conn.runtime().header().freelist_pages().total();
+  let page_count = conn.runtime().pager().page_count();
+  assert!(free_pages > 10);
+
+  let mut btree = conn.runtime_mut().btree();
+  assert_eq!(btree.incremental_vacuum(Some(10)).unwrap(), 10);
+  conn.runtime_mut().commit().unwrap();
+  assert_eq!(
+    **conn.runtime().header().freelist_pages().total(),
+    free_pages - 10
+  );
+  assert_eq!(conn.runtime().pager().page_count(), page_count - 10);
+
+  let mut btree = conn.runtime_mut().btree();
+  assert_eq!(btree.incremental_vacuum(None).unwrap(), free_pages - 10);
+  conn.runtime_mut().commit().unwrap();
+  assert_eq!(**conn.runtime().header().freelist_pages().total(), 0);
+  let rows = scan(&mut conn, root);
+  assert_eq!(rows.len(), 100);
+  assert_eq!(rows[0].0, 401);
+}
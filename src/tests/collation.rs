@@ -0,0 +1,188 @@
+use super::{execute_error, query_error, rows};
+use crate::SqliteConnection;
+use core::cmp::Ordering;
+
+/// Folds the case and accents of a name.
+fn folded(text: &str) -> Vec<char> {
+  text
+    .chars()
+    .map(|c| match c.to_lowercase().next().unwrap_or(c) {
+      'à' | 'â' => 'a',
+      'é' | 'è' | 'ê' => 'e',
+      'ç' => 'c',
+      c => c,
+    })
+    .collect()
+}
+
+/// Compares names letter by letter, ignoring case and accents, then with
+/// accents, the way a phone book sorts them.
+fn names(left: &str, right: &str) -> Ordering {
+  folded(left)
+    .cmp(&folded(right))
+    .then_with(|| left.to_lowercase().cmp(&right.to_lowercase()))
+}
+
+/// Compares names ignoring case and accents altogether.
+fn loose_names(left: &str, right: &str) -> Ordering {
+  folded(left).cmp(&folded(right))
+}
+
+fn open() -> SqliteConnection {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  conn.create_collation("names", names).unwrap();
+  conn
+}
+
+#[test]
+fn ok_on_custom_collations() {
+  let mut conn = open();
+  conn
+    .create_collation("accents", |left: &str, right: &str| {
+      let fold = |text: &str| text.to_lowercase().replace(['é', 'è'], "e");
+      fold(left).cmp(&fold(right))
+    })
+    .unwrap();
+  conn
+    .execute("CREATE TABLE people(name TEXT COLLATE names, city TEXT)")
+    .unwrap();
+  conn
+    .execute(
+      "INSERT INTO people VALUES ('Zoé', 'Lyon'), ('émile', 'Paris'),
+         ('Eric', 'Lyon'), ('Édith', 'Nice'), ('élodie', 'Paris')",
+    )
+    .unwrap();
+  for (sql, expected) in [
+    (
+      "SELECT name FROM people ORDER BY name",
+      vec!["Édith", "élodie", "émile", "Eric", "Zoé"],
+    ),
+    (
+      "SELECT name FROM people ORDER BY name COLLATE binary",
+      vec!["Eric", "Zoé", "Édith", "élodie", "émile"],
+    ),
+    (
+      "SELECT name FROM people WHERE name > 'emile' ORDER BY name DESC",
+      vec!["Zoé", "Eric", "émile"],
+    ),
+    (
+      "SELECT 'Émile' = 'emile' COLLATE accents, 'Émile' = 'emile'",
+      vec!["1|0"],
+    ),
+    (
+      "SELECT DISTINCT name COLLATE accents FROM people
+       WHERE name COLLATE accents IN ('EMILE', 'edith')",
+      vec!["émile", "Édith"],
+    ),
+    (
+      "SELECT count(DISTINCT city COLLATE accents) FROM people",
+      vec!["3"],
+    ),
+    ("SELECT min(name), max(name) FROM people", vec!["Édith|Zoé"]),
+    (
+      "SELECT 'ÉRIC' COLLATE accents UNION SELECT 'eric' COLLATE accents",
+      vec!["ÉRIC"],
+    ),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_indexes_with_custom_collations() {
+  let mut conn = open();
+  conn
+    .execute("CREATE TABLE people(id INTEGER PRIMARY KEY, name TEXT)")
+    .unwrap();
+  conn
+    .execute("CREATE INDEX people_name ON people(name COLLATE names)")
+    .unwrap();
+  for (id, name) in ["Zoé", "émile", "Eric", "Édith", "élodie"]
+    .iter()
+    .enumerate()
+  {
+    conn
+      .execute(&format!("INSERT INTO people VALUES ({id}, '{name}')"))
+      .unwrap();
+  }
+  assert_eq!(
+    rows(
+      &mut conn,
+      "SELECT name FROM people INDEXED BY people_name
+       WHERE name COLLATE names > 'e' ORDER BY name COLLATE names"
+    ),
+    ["Édith", "élodie", "émile", "Eric", "Zoé"]
+  );
+  assert_eq!(
+    rows(
+      &mut conn,
+      "EXPLAIN QUERY PLAN SELECT id FROM people
+       WHERE name COLLATE names = 'Eric'"
+    ),
+    ["1|0|0|SEARCH people USING COVERING INDEX people_name (name=?)"]
+  );
+  assert_eq!(
+    rows(
+      &mut conn,
+      "SELECT id FROM people WHERE name COLLATE names = 'Eric'"
+    ),
+    ["2"]
+  );
+  let create = "CREATE UNIQUE INDEX people_loose ON people(name COLLATE loose)";
+  assert_eq!(
+    execute_error(&mut conn, create),
+    "no such collation sequence: loose"
+  );
+  conn.create_collation("loose", loose_names).unwrap();
+  conn.execute(create).unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO people VALUES (9, 'ERIC')"),
+    "UNIQUE constraint failed: people.name"
+  );
+}
+
+#[test]
+fn error_on_unregistered_collations() {
+  let path = std::env::temp_dir().join("sqlite-rs-collations.db");
+  let _ = std::fs::remove_file(&path);
+  let uri = format!("sqlite://{}?mode=rwc", path.display());
+  {
+    let mut conn = SqliteConnection::open(&uri).unwrap();
+    conn.create_collation("names", names).unwrap();
+    conn
+      .execute("CREATE TABLE people(name TEXT COLLATE names)")
+      .unwrap();
+    conn
+      .execute("CREATE INDEX people_name ON people(name)")
+      .unwrap();
+    conn
+      .execute("INSERT INTO people VALUES ('Zoé'), ('Eric'), ('émile')")
+      .unwrap();
+  }
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  for sql in [
+    "SELECT name FROM people",
+    "SELECT name FROM people ORDER BY name",
+  ] {
+    assert_eq!(
+      query_error(&mut conn, sql),
+      "no such collation sequence: names",
+      "{sql}"
+    );
+  }
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO people VALUES ('Anne')"),
+    "no such collation sequence: names"
+  );
+  conn.create_collation("NAMES", names).unwrap();
+  conn.execute("INSERT INTO people VALUES ('Anne')").unwrap();
+  assert_eq!(
+    rows(&mut conn, "SELECT name FROM people ORDER BY name"),
+    ["Anne", "émile", "Eric", "Zoé"]
+  );
+  for name in ["", "nocase", "BINARY"] {
+    assert!(conn.create_collation(name, names).is_err(), "{name:?}");
+  }
+  drop(conn);
+  let _ = std::fs::remove_file(&path);
+}
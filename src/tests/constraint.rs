@@ -0,0 +1,269 @@
+use super::{execute_error, open, rows};
+use crate::result::{ConstraintKind, SqliteError};
+use crate::SqliteConnection;
+
+#[test]
+fn ok_on_not_null_and_check() {
+  let mut conn = open(&["CREATE TABLE t(
+       a INT NOT NULL,
+       b TEXT CHECK(length(b) < 3),
+       c DEFAULT 5 NOT NULL ON CONFLICT REPLACE,
+       CONSTRAINT positive CHECK(a > 0)
+     )"]);
+  for (sql, expected) in [
+    (
+      "INSERT INTO t VALUES(NULL, 'x', 1)",
+      "NOT NULL constraint failed: t.a",
+    ),
+    (
+      "INSERT INTO t VALUES(1, 'xxxx', 1)",
+      "CHECK constraint failed: length(b) < 3",
+    ),
+    (
+      "INSERT INTO t VALUES(0, 'x', 1)",
+      "CHECK constraint failed: positive",
+    ),
+    (
+      "INSERT OR REPLACE INTO t VALUES(NULL, 'x', 1)",
+      "NOT NULL constraint failed: t.a",
+    ),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    conn.execute("INSERT INTO t VALUES(1, NULL, NULL)").unwrap(),
+    1
+  );
+  assert_eq!(
+    conn
+      .execute("INSERT OR IGNORE INTO t VALUES(NULL, 'x', 1), (2, 'y', 2)")
+      .unwrap(),
+    1
+  );
+  assert_eq!(
+    execute_error(&mut conn, "UPDATE t SET a = -1"),
+    "CHECK constraint failed: positive"
+  );
+  assert_eq!(conn.execute("UPDATE OR IGNORE t SET a = NULL").unwrap(), 0);
+  assert_eq!(rows(&mut conn, "SELECT * FROM t"), ["1||5", "2|y|2"]);
+}
+
+#[test]
+fn ok_on_conflict_clauses() {
+  let mut conn = open(&[
+    "CREATE TABLE u(
+       a UNIQUE ON CONFLICT REPLACE,
+       b NOT NULL ON CONFLICT IGNORE,
+       c UNIQUE ON CONFLICT FAIL
+     )",
+    "CREATE TABLE k(id INTEGER PRIMARY KEY ON CONFLICT IGNORE, v)",
+    "CREATE TABLE w(a, b, PRIMARY KEY(a, b)) WITHOUT ROWID",
+  ]);
+  let sql = "INSERT INTO u VALUES(1, 1, 1), (2, NULL, 2), (3, 3, 3)";
+  assert_eq!(conn.execute(sql).unwrap(), 2);
+  assert_eq!(conn.execute("INSERT INTO u VALUES(1, 5, 5)").unwrap(), 1);
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO u VALUES(6, 6, 6), (7, 7, 3)"),
+    "UNIQUE constraint failed: u.c"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "INSERT OR ABORT INTO u VALUES(1, 9, 9)"),
+    "UNIQUE constraint failed: u.a"
+  );
+  assert_eq!(
+    rows(&mut conn, "SELECT * FROM u"),
+    ["3|3|3", "1|5|5", "6|6|6"]
+  );
+  let sql = "INSERT INTO k VALUES(1, 'a'), (1, 'b')";
+  assert_eq!(conn.execute(sql).unwrap(), 1);
+  assert_eq!(
+    execute_error(
+      &mut conn,
+      "INSERT OR ROLLBACK INTO k VALUES(2, 'c'), (1, 'd')"
+    ),
+    "UNIQUE constraint failed: k.id"
+  );
+  assert_eq!(rows(&mut conn, "SELECT * FROM k"), ["1|a"]);
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO w VALUES(1, NULL)"),
+    "NOT NULL constraint failed: w.b"
+  );
+}
+
+#[test]
+fn ok_on_strict_tables() {
+  let mut conn =
+    open(&["CREATE TABLE s(i INTEGER, r REAL, t TEXT, b BLOB, a ANY) STRICT"]);
+  for sql in [
+    "INSERT INTO s VALUES(1, 2, 'x', x'00', '1')",
+    "INSERT INTO s VALUES('1', 2.5, 'x', x'00', 1)",
+    "INSERT INTO s(t) VALUES(3)",
+  ] {
+    assert_eq!(conn.execute(sql).unwrap(), 1, "{sql}");
+  }
+  for (sql, expected) in [
+    (
+      "INSERT INTO s VALUES('a', 2, 'x', x'00', 1)",
+      "cannot store TEXT value in INTEGER column s.i",
+    ),
+    (
+      "INSERT OR IGNORE INTO s VALUES(1, 'r', 'x', x'00', 1)",
+      "cannot store TEXT value in REAL column s.r",
+    ),
+    (
+      "UPDATE s SET b = 1",
+      "cannot store INTEGER value in BLOB column s.b",
+    ),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    rows(
+      &mut conn,
+      "SELECT typeof(i), typeof(r), typeof(t), typeof(a) FROM s"
+    ),
+    [
+      "integer|real|text|text",
+      "integer|real|text|integer",
+      "null|null|text|null"
+    ]
+  );
+}
+
+fn open_family() -> SqliteConnection {
+  open(&[
+    "PRAGMA foreign_keys = ON",
+    "CREATE TABLE p(id INTEGER PRIMARY KEY, code TEXT UNIQUE)",
+    "CREATE TABLE c(
+       x,
+       pid REFERENCES p ON DELETE CASCADE ON UPDATE CASCADE,
+       pcode REFERENCES p(code) ON DELETE SET NULL ON UPDATE RESTRICT
+     )",
+    "CREATE TABLE d(x REFERENCES p(id) ON DELETE SET DEFAULT DEFAULT 1)",
+    "INSERT INTO p VALUES(1, 'a'), (2, 'b'), (3, 'c')",
+    "INSERT INTO c VALUES(1, '2', 'b'), (2, 3, 'c'), (3, NULL, 'a')",
+    "INSERT INTO d VALUES(2), (3)",
+  ])
+}
+
+#[test]
+fn ok_on_foreign_keys() {
+  let mut conn = open_family();
+  assert_eq!(rows(&mut conn, "PRAGMA foreign_keys"), ["1"]);
+  for sql in [
+    "INSERT INTO c VALUES(4, 9, NULL)",
+    "INSERT INTO d VALUES(7)",
+    "UPDATE p SET code = 'z' WHERE code = 'c'",
+    "UPDATE p SET id = 20 WHERE id = 2",
+  ] {
+    assert_eq!(
+      execute_error(&mut conn, sql),
+      "FOREIGN KEY constraint failed",
+      "{sql}"
+    );
+  }
+  assert_eq!(conn.execute("DELETE FROM p WHERE id = 2").unwrap(), 1);
+  assert_eq!(rows(&mut conn, "SELECT * FROM c"), ["2|3|c", "3||a"]);
+  assert_eq!(rows(&mut conn, "SELECT * FROM d"), ["1", "3"]);
+  let mut conn = open(&[
+    "PRAGMA foreign_keys = 1",
+    "CREATE TABLE t(id INTEGER PRIMARY KEY, parent REFERENCES t)",
+    "CREATE TABLE wp(a, b, PRIMARY KEY(b, a)) WITHOUT ROWID",
+    "CREATE TABLE wc(
+       x, y,
+       FOREIGN KEY(x, y) REFERENCES wp(a, b) DEFERRABLE INITIALLY DEFERRED
+     )",
+    "CREATE TABLE m(a REFERENCES missing)",
+    "CREATE TABLE o(v)",
+    "CREATE TABLE n(a REFERENCES o(v))",
+  ]);
+  let sql = "INSERT INTO t VALUES(1, 2), (2, 1)";
+  assert_eq!(conn.execute(sql).unwrap(), 2);
+  for (sql, expected) in [
+    (
+      "DELETE FROM t WHERE id = 1",
+      "FOREIGN KEY constraint failed",
+    ),
+    ("UPDATE t SET parent = 5", "FOREIGN KEY constraint failed"),
+    (
+      "INSERT INTO wc VALUES(1, 2)",
+      "FOREIGN KEY constraint failed",
+    ),
+    ("INSERT INTO m VALUES(1)", "no such table: main.missing"),
+    (
+      "INSERT INTO n VALUES(1)",
+      "foreign key mismatch - \"n\" referencing \"o\"",
+    ),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(conn.execute("DELETE FROM t").unwrap(), 2);
+  conn.execute("INSERT INTO wp VALUES(1, 2)").unwrap();
+  conn
+    .execute("INSERT INTO wc VALUES(1, 2), (NULL, 5)")
+    .unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "DELETE FROM wp"),
+    "FOREIGN KEY constraint failed"
+  );
+  conn.execute("PRAGMA foreign_keys = off").unwrap();
+  assert_eq!(conn.execute("DELETE FROM wp").unwrap(), 1);
+  assert_eq!(conn.execute("INSERT INTO m VALUES(1)").unwrap(), 1);
+}
+
+#[test]
+fn ok_on_foreign_key_actions() {
+  let mut conn = open_family();
+  conn.execute("DELETE FROM d WHERE x = 2").unwrap();
+  assert_eq!(conn.execute("DELETE FROM p WHERE id = 3").unwrap(), 1);
+  assert_eq!(rows(&mut conn, "SELECT * FROM c"), ["1|2|b", "3||a"]);
+  assert_eq!(rows(&mut conn, "SELECT * FROM d"), ["1"]);
+  assert_eq!(
+    execute_error(&mut conn, "DELETE FROM p WHERE id = 1"),
+    "FOREIGN KEY constraint failed"
+  );
+  conn.execute("DELETE FROM d").unwrap();
+  assert_eq!(
+    conn.execute("UPDATE p SET id = 20 WHERE id = 2").unwrap(),
+    1
+  );
+  assert_eq!(rows(&mut conn, "SELECT * FROM c"), ["1|20|b", "3||a"]);
+  assert_eq!(conn.execute("DELETE FROM p WHERE id = 1").unwrap(), 1);
+  assert_eq!(rows(&mut conn, "SELECT * FROM c"), ["1|20|b", "3||"]);
+  let sql = "INSERT OR REPLACE INTO p VALUES(20, 'q')";
+  assert_eq!(conn.execute(sql).unwrap(), 1);
+  assert_eq!(rows(&mut conn, "SELECT * FROM c"), ["3||"]);
+}
+
+#[test]
+fn ok_on_constraint_kinds() {
+  let mut conn = open(&[
+    "PRAGMA foreign_keys = ON",
+    "CREATE TABLE p(id INTEGER PRIMARY KEY, u UNIQUE, n NOT NULL CHECK(n > 0))",
+    "CREATE TABLE c(k TEXT PRIMARY KEY, p REFERENCES p)",
+    "CREATE TABLE s(i INTEGER) STRICT",
+    "INSERT INTO p VALUES(1, 1, 1)",
+    "INSERT INTO c VALUES('a', 1)",
+  ]);
+  for (sql, kind) in [
+    ("INSERT INTO p VALUES(1, 2, 1)", ConstraintKind::PrimaryKey),
+    ("INSERT INTO p VALUES(2, 1, 1)", ConstraintKind::Unique),
+    ("INSERT INTO p VALUES(2, 2, NULL)", ConstraintKind::NotNull),
+    ("INSERT INTO p VALUES(2, 2, 0)", ConstraintKind::Check),
+    (
+      "INSERT INTO c VALUES('a', NULL)",
+      ConstraintKind::PrimaryKey,
+    ),
+    ("INSERT INTO c VALUES('b', 2)", ConstraintKind::ForeignKey),
+    ("INSERT INTO s VALUES('x')", ConstraintKind::Datatype),
+  ] {
+    match conn.execute(sql) {
+      Err(SqliteError::Constraint { kind: found, .. }) => {
+        assert_eq!(found, kind, "{sql}")
+      }
+      other => {
+        panic!("expected a constraint failure for {sql:?}, got {other:?}")
+      }
+    }
+  }
+}
@@ -0,0 +1,179 @@
+use super::{open, query};
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// The values of the single row of `sql`, joined as the `sqlite3` shell
+/// shows them.
+fn row(conn: &mut SqliteConnection, sql: &str) -> String {
+  let rows = query(conn, sql);
+  assert_eq!(rows.len(), 1, "{sql}");
+  let values = rows[0].iter().map(ToString::to_string).collect::<Vec<_>>();
+  values.join("|")
+}
+
+#[test]
+fn ok_on_time_values() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT date('2024-02-29'), time('12:34:56.789'), \
+       datetime('2024-02-29T12:34:56Z')",
+      "2024-02-29|12:34:56|2024-02-29 12:34:56",
+    ),
+    (
+      "SELECT julianday('2000-01-01 12:00'), unixepoch('1970-01-02'), \
+       datetime(2460000.5)",
+      "2451545.0|86400|2023-02-25 00:00:00",
+    ),
+    (
+      "SELECT datetime('2024-05-15 10:00:00+05:30'), \
+       time('10:00 -02:00'), datetime('2023-02-31')",
+      "2024-05-15 04:30:00|12:00:00|2023-03-03 00:00:00",
+    ),
+    (
+      "SELECT datetime('-0044-03-15'), julianday('-4713-11-24 12:00:00'), \
+       date(x'323032342d30312d3031')",
+      "-0044-03-15 00:00:00|0.0|2024-01-01",
+    ),
+    (
+      "SELECT datetime('2024-13-01'), time('25:00'), date('24-01-01'), \
+       datetime(-1), date('9999-12-31', '+1 day'), date('now', NULL)",
+      "|||||",
+    ),
+  ] {
+    assert_eq!(row(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_modifiers() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT datetime(1700000000, 'unixepoch'), \
+       datetime(1700000000, 'auto'), datetime(2460000.5, 'auto'), \
+       datetime(1700000000)",
+      "2023-11-14 22:13:20|2023-11-14 22:13:20|2023-02-25 00:00:00|",
+    ),
+    (
+      "SELECT date('2024-01-31', '+1 month'), \
+       date('2024-01-31', '+1 month', 'floor'), \
+       date('2024-02-29', '+1 year'), date('2023-02-31', 'floor')",
+      "2024-03-02|2024-02-29|2025-03-01|2023-02-28",
+    ),
+    (
+      "SELECT datetime('2024-01-01', '+1.5 days'), \
+       datetime('2024-01-01', '-90 minutes'), \
+       datetime('2024-01-01', '+1.5 months'), \
+       date('2024-05-15', '+1 DAYS'), date('2024-05-15', '+1days')",
+      "2024-01-02 12:00:00|2023-12-31 22:30:00|2024-02-16 00:00:00|\
+       2024-05-16|",
+    ),
+    (
+      "SELECT date('2024-05-15', 'start of month'), \
+       date('2024-05-15', 'start of year'), \
+       datetime('2024-05-15 13:14:15', 'start of day'), \
+       date('2024-05-15', 'weekday 0'), date('2024-05-15', 'weekday 3')",
+      "2024-05-01|2024-01-01|2024-05-15 00:00:00|2024-05-19|2024-05-15",
+    ),
+    (
+      "SELECT datetime('2024-05-15 10:00', '-01:30:15.5'), \
+       datetime('2024-05-15 10:00', '+0001-02-03'), \
+       datetime('2024-05-15 10:00', '-0001-02-03 04:05:06'), \
+       datetime('2024-05-15 10:00', '+0001-12-03')",
+      "2024-05-15 08:29:44|2025-07-18 10:00:00|2023-03-12 05:54:54|",
+    ),
+    (
+      "SELECT datetime('2024-05-15 10:00:00.1234', 'subsec'), \
+       unixepoch('2024-05-15 10:00:00.1234', 'subsec'), \
+       time('10:00:59.9996', 'subsec'), datetime('2024-05-15', 'julianday')",
+      "2024-05-15 10:00:00.123|1715767200.123|10:00:59.999|",
+    ),
+    (
+      "SELECT datetime('2024-05-15 12:00', 'localtime', 'utc'), \
+       datetime(12, 'start of day', 'unixepoch')",
+      "2024-05-15 12:00:00|",
+    ),
+  ] {
+    assert_eq!(row(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_strftime() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT strftime('%Y-%m-%d %H:%M:%S|%f|%s|%J|%j|%w|%u', \
+       '2024-05-15 13:14:15.678')",
+      "2024-05-15 13:14:15|15.678|1715778855|2460446.051570347|136|3|3",
+    ),
+    (
+      "SELECT strftime('%e|%k|%l|%I|%p|%P|%R|%T|%F|%%', \
+       '2024-01-05 00:07:09')",
+      " 5| 0|12|12|AM|am|00:07|00:07:09|2024-01-05|%",
+    ),
+    (
+      "SELECT strftime('%U %V %W %G %g', '2021-01-01'), \
+       strftime('%U %V %W %G %g', '2024-12-30')",
+      "00 53 00 2020 20|52 01 53 2025 25",
+    ),
+    (
+      "SELECT strftime('%s', 1.5, 'unixepoch', 'subsec'), \
+       strftime('%Q', 'now'), strftime('abc%'), strftime(NULL, 'now')",
+      "1.500|||",
+    ),
+  ] {
+    assert_eq!(row(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_timediff() {
+  let mut conn = open(&[]);
+  assert_eq!(
+    row(
+      &mut conn,
+      "SELECT timediff('2024-05-15', '2023-01-01'), \
+       timediff('2023-01-01', '2024-05-15'), \
+       timediff('2024-03-31', '2024-02-29 12:00:00.5'), \
+       timediff('abc', 'now')"
+    ),
+    "+0001-04-14 00:00:00.000|-0001-04-14 00:00:00.000|\
+     +0000-01-01 11:59:59.500|"
+  );
+}
+
+#[test]
+fn ok_on_current_time() {
+  let mut conn = open(&[]);
+  assert_eq!(
+    row(
+      &mut conn,
+      "SELECT datetime() = CURRENT_TIMESTAMP, time() = CURRENT_TIME, \
+       date() = CURRENT_DATE, typeof(unixepoch('now', 'subsec')), \
+       datetime('now', 'subsec') = datetime('subsec')"
+    ),
+    "1|1|1|real|1"
+  );
+  for sql in [
+    "CREATE TABLE ev(id INTEGER PRIMARY KEY, at DEFAULT CURRENT_TIMESTAMP, \
+     ts INTEGER)",
+    "INSERT INTO ev(id, ts) VALUES (1, 1700000000), (2, 1710000000)",
+    "INSERT INTO ev VALUES (3, '2024-01-01 10:00:00', 1704103200)",
+  ] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT id, datetime(ts, 'unixepoch') FROM ev \
+       WHERE unixepoch(at) >= ts AND date(ts, 'unixepoch') > '2023-12-01' \
+       ORDER BY at = datetime('now'), id"
+    ),
+    [
+      vec![Value::Integer(3), Value::Text("2024-01-01 10:00:00".into())],
+      vec![Value::Integer(2), Value::Text("2024-03-09 16:00:00".into())],
+    ]
+  );
+}
@@ -0,0 +1,272 @@
+use super::{open, query, query_error};
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// The values of the single row of `sql`, as the `sqlite3` shell shows them.
+fn row(conn: &mut SqliteConnection, sql: &str) -> Vec<String> {
+  let rows = query(conn, sql);
+  assert_eq!(rows.len(), 1, "{sql}");
+  rows[0].iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn ok_on_text_functions() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT length('héllo'), length(x'0001'), length(12.5)",
+      "5|2|4",
+    ),
+    ("SELECT octet_length('héllo'), octet_length(12)", "6|2"),
+    ("SELECT lower('ÀB'), upper('àb')", "Àb|àB"),
+    (
+      "SELECT trim('  ab  '), ltrim('xxabx', 'x'), rtrim('abxyx', 'xy')",
+      "ab|abx|ab",
+    ),
+    (
+      "SELECT instr('héllo', 'l'), instr(x'0102', x'02'), instr('abc', '')",
+      "3|2|1",
+    ),
+    (
+      "SELECT replace('aaa', 'a', 'bb'), replace('abc', '', 'x')",
+      "bbbbbb|abc",
+    ),
+    (
+      "SELECT substr('hello', 0), substr('hello', -2), substr('hello', 2, -1)",
+      "hello|lo|h",
+    ),
+    (
+      "SELECT substr('héllo', 2, 2), substr('abc', 0, 2), \
+       substring('hello', 3, -2)",
+      "él|a|he",
+    ),
+    (
+      "SELECT hex('é'), hex(12), hex(unhex('01 02', ' ')), \
+       unhex('0 102', ' ')",
+      "C3A9|3132|0102|",
+    ),
+    (
+      "SELECT unicode('é'), char(72, 233), hex(char(-1))",
+      "233|Hé|EFBFBD",
+    ),
+    (
+      "SELECT quote('it''s'), quote(x'abcd'), quote(NULL), quote(12)",
+      "'it''s'|X'ABCD'|NULL|12",
+    ),
+    (
+      "SELECT quote(0.1), quote(1e300), quote(1.0 / 3)",
+      "0.1|1.0e+300|3.333333333333333148e-01",
+    ),
+    (
+      "SELECT like('a%', 'ABC'), glob('a*', 'ABC'), like('a\\%', 'a%', '\\')",
+      "1|0|1",
+    ),
+    (
+      "SELECT typeof(1), typeof(1.5), typeof('a'), typeof(x'00'), typeof(NULL)",
+      "integer|real|text|blob|null",
+    ),
+  ] {
+    assert_eq!(row(&mut conn, sql).join("|"), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_numeric_functions() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT abs(-3), abs(-2.5), abs('-5'), abs('x')",
+      "3|2.5|5.0|0.0",
+    ),
+    (
+      "SELECT round(2.675, 2), round(2.5), round(-2.5), round(0.125, 2)",
+      "2.67|3.0|-3.0|0.13",
+    ),
+    (
+      "SELECT round(2.345, 2), round(3.5, -2), round('2.5'), round(1e20)",
+      "2.35|4.0|3.0|1.0e+20",
+    ),
+    (
+      "SELECT min(1, 'a', 2.0), max('a', 'B' COLLATE NOCASE), max(3, 1, 2)",
+      "1|B|3",
+    ),
+    (
+      "SELECT typeof(min(1, 1.0)), typeof(max(1, 1.0)), max(1, NULL)",
+      "real|integer|",
+    ),
+    (
+      "SELECT nullif(1, 1), nullif(1, 2), nullif('a', 'A' COLLATE NOCASE)",
+      "|1|",
+    ),
+    (
+      "SELECT coalesce(NULL, 2, 3), ifnull(NULL, 'a'), iif(1, 'y', 'n'), \
+       iif(0, 1, 0, 2, 3)",
+      "2|a|y|3",
+    ),
+    (
+      "SELECT hex(zeroblob(2)), length(randomblob(0)), length(randomblob(7))",
+      "0000|1|7",
+    ),
+    (
+      "SELECT typeof(random()), typeof(random(*))",
+      "integer|integer",
+    ),
+  ] {
+    assert_eq!(row(&mut conn, sql).join("|"), expected, "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT abs(-9223372036854775808)"),
+    "integer overflow"
+  );
+}
+
+#[test]
+fn ok_on_printf() {
+  let mut conn = open(&[]);
+  for (format, arguments, expected) in [
+    (
+      "%.2f|%5d|%-5s|%x",
+      "3.14159, 42, 'ab', 255",
+      "3.14|   42|ab   |ff",
+    ),
+    (
+      "%q|%Q|%Q|%w",
+      "'it''s', NULL, 'a', 'a\"b'",
+      "it''s|NULL|'a'|a\"\"b",
+    ),
+    (
+      "%e|%g|%,d|%c",
+      "12345.678, 0.0001, 1234567, 'xyz'",
+      "1.234568e+04|0.0001|1,234,567|x",
+    ),
+    (
+      "%.3s|%05.1f|%+d|%#x|%o",
+      "'abcdef', 3.14159, 5, 255, 8",
+      "abc|003.1|+5|0xff|10",
+    ),
+    ("%s %d", "'x'", "x 0"),
+    (
+      "%g|%g|%.10g",
+      "100000000.0, 1e20, 1.0 / 3",
+      "1e+08|1e+20|0.3333333333",
+    ),
+    ("%.0f|%.0f|%.0f|%.17g", "0.5, 1.5, 2.5, 0.1", "1|2|3|0.1"),
+    ("%*d|%-*d|%.*f", "5, 1, 4, 2, 2, 3.14159", "    1|2   |3.14"),
+    (
+      "%,.2f|%08.2f|%u",
+      "1234567.891, -3.5, -1",
+      "1,234,567.89|-0003.50|18446744073709551615",
+    ),
+    (
+      "%!.3g|%#g|%.20e",
+      "1.0, 1.0, 2.0 / 3",
+      "1.0|1.00000|6.66666666666666600000e-01",
+    ),
+    ("abc%", "", "abc%"),
+    ("%d%y%d", "1, 2", "1"),
+  ] {
+    let sql = match arguments {
+      "" => format!("SELECT printf('{format}')"),
+      arguments => format!("SELECT format('{format}', {arguments})"),
+    };
+    assert_eq!(row(&mut conn, &sql), [expected], "{sql}");
+  }
+  assert_eq!(
+    query(&mut conn, "SELECT printf(), printf(NULL)"),
+    [vec![Value::Null, Value::Null]]
+  );
+}
+
+#[test]
+fn ok_on_connection_functions() {
+  let mut conn = open(&[]);
+  conn
+    .execute("CREATE TABLE t(a INTEGER PRIMARY KEY, b)")
+    .unwrap();
+  conn
+    .execute("INSERT INTO t(b) VALUES (1), (2), (3)")
+    .unwrap();
+  conn.execute("UPDATE t SET b = b + 1 WHERE a > 1").unwrap();
+  assert_eq!(
+    row(
+      &mut conn,
+      "SELECT last_insert_rowid(), changes(), total_changes()"
+    ),
+    ["3", "2", "5"]
+  );
+  let version = env!("CARGO_PKG_VERSION");
+  assert_eq!(row(&mut conn, "SELECT sqlite_version()"), [version]);
+}
+
+#[test]
+fn ok_on_functions_of_columns() {
+  let mut conn = open(&[]);
+  for sql in [
+    "CREATE TABLE t(a INTEGER PRIMARY KEY, b TEXT COLLATE NOCASE, c)",
+    "INSERT INTO t VALUES (1, 'x', NULL), (2, 'Y', 5), (3, NULL, 7)",
+  ] {
+    conn.execute(sql).unwrap();
+  }
+  let sql = "SELECT upper(b), coalesce(c, b, 'none'), max(b, 'X'), \
+             length(b) FROM t ORDER BY a";
+  let rows = query(&mut conn, sql)
+    .into_iter()
+    .map(|row| row.iter().map(ToString::to_string).collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    rows,
+    [
+      ["X", "x", "x", "1"],
+      ["Y", "5", "Y", "1"],
+      ["", "7", "", ""],
+    ]
+  );
+  let program = query(&mut conn, &format!("EXPLAIN {sql}"));
+  assert!(program
+    .iter()
+    .any(|row| row[1] == Value::Text("Function".into())
+      && row[5] == Value::Text("upper(1)".into())));
+}
+
+#[test]
+fn error_on_function_misuse() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT abs(1, 2)",
+      "wrong number of arguments to function abs()",
+    ),
+    (
+      "SELECT coalesce(1)",
+      "wrong number of arguments to function coalesce()",
+    ),
+    (
+      "SELECT iif(1)",
+      "wrong number of arguments to function iif()",
+    ),
+    (
+      "SELECT abs(1) OVER ()",
+      "abs() may not be used as a window function",
+    ),
+    (
+      "SELECT max(1, 2) OVER ()",
+      "max() may not be used as a window function",
+    ),
+    (
+      "SELECT abs(1) FILTER (WHERE 1)",
+      "FILTER may not be used with non-aggregate abs()",
+    ),
+    (
+      "SELECT abs(1 ORDER BY 1)",
+      "ORDER BY may not be used with non-aggregate abs()",
+    ),
+    ("SELECT nosuch(1)", "no such function: nosuch"),
+    ("SELECT zeroblob(2000000000)", "string or blob too big"),
+    (
+      "SELECT like('a', 'a', 'bb')",
+      "ESCAPE expression must be a single character",
+    ),
+  ] {
+    assert_eq!(query_error(&mut conn, sql), expected, "{sql}");
+  }
+}
@@ -0,0 +1,293 @@
+use super::{open, query_error, rows};
+use crate::SqliteConnection;
+
+/// The values of the rows of `sql`, joined as the `sqlite3` shell shows
+/// them, one line for each row.
+fn lines(conn: &mut SqliteConnection, sql: &str) -> String {
+  rows(conn, sql).join("\n")
+}
+
+#[test]
+fn ok_on_json_text() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT json(' { \"a\" : [1, 2.50, \"x\\ty\"] } '), json('null'), \
+       json(NULL)",
+      "{\"a\":[1,2.50,\"x\\ty\"]}|null|",
+    ),
+    (
+      "SELECT json('{a: 0x1F, b: .5, c: +1., d: ''q\"'', /* c */ e: [1,],}'), \
+       json('[Infinity, -inf, NaN]')",
+      "{\"a\":31,\"b\":0.5,\"c\":1.0,\"d\":\"q\\\"\",\"e\":[1]}|\
+       [9e999,-9e999,null]",
+    ),
+    (
+      "SELECT json_valid('{\"a\":1}'), json_valid('{a:1}'), \
+       json_valid('{a:1}', 2), json_valid('[1,'), json_valid(NULL), \
+       json_valid(jsonb('[1]'), 4)",
+      "1|0|1|0||1",
+    ),
+    (
+      "SELECT json_error_position('[1,2'), json_error_position('{\"a\":1}'), \
+       json_error_position('[1,x]')",
+      "5|0|4",
+    ),
+    (
+      "SELECT json_type('{\"a\":[1,2.0,\"s\",true,null,{}]}'), \
+       json_type('{\"a\":[1,2.0,\"s\",true,null,{}]}', '$.a'), \
+       json_type('[1,2.0,\"s\",true,null,{}]', '$[1]'), \
+       json_type('[1,2.0,\"s\",true,null,{}]', '$[#-1]'), \
+       json_type('[1]', '$[5]')",
+      "object|array|real|object|",
+    ),
+    (
+      "SELECT json_array_length('[1,2,3]'), json_array_length('{}'), \
+       json_array_length('{\"a\":[1,2]}', '$.a'), json_quote('a\"b'), \
+       json_quote(3.5), json_quote(NULL)",
+      "3|0|2|\"a\\\"b\"|3.5|null",
+    ),
+    (
+      "SELECT json_pretty('{\"a\":[1,{}],\"b\":{\"c\":null}}')",
+      "{\n    \"a\": [\n        1,\n        {}\n    ],\n    \"b\": {\n        \
+       \"c\": null\n    }\n}",
+    ),
+  ] {
+    assert_eq!(lines(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT json('[1,')"),
+    "malformed JSON"
+  );
+}
+
+#[test]
+fn ok_on_extract() {
+  let mut conn = open(&[]);
+  let doc = "'{\"a\":{\"b\":[10,20,{\"c\":\"x\"}]},\"d e\":1.5,\"f\":true}'";
+  for (sql, expected) in [
+    (
+      format!(
+        "SELECT json_extract({doc}, '$.a.b[1]'), \
+         json_extract({doc}, '$.a.b[#-1].c'), json_extract({doc}, '$.a'), \
+         json_extract({doc}, '$.\"d e\"'), json_extract({doc}, '$.f'), \
+         json_extract({doc}, '$.nope')"
+      ),
+      "20|x|{\"b\":[10,20,{\"c\":\"x\"}]}|1.5|1|",
+    ),
+    (
+      format!("SELECT json_extract({doc}, '$.f', '$.a.b[0]', '$.g')"),
+      "[true,10,null]",
+    ),
+    (
+      format!(
+        "SELECT {doc} -> '$.a.b[2]', {doc} -> 'f', {doc} ->> 'f', \
+         {doc} -> '$.a' -> 'b' ->> 2, {doc} -> 'd e', \
+         '[1,[2,3]]' -> 1 -> -1, '[1,\"s\"]' -> 1, '[1,\"s\"]' ->> 1"
+      ),
+      "{\"c\":\"x\"}|true|1|{\"c\":\"x\"}|1.5|3|\"s\"|s",
+    ),
+  ] {
+    assert_eq!(lines(&mut conn, &sql), expected, "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT json_extract('{}', 'a')"),
+    "bad JSON path: 'a'"
+  );
+}
+
+#[test]
+fn ok_on_building_and_editing() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT json_array(1, 2.5, 'x', NULL, json('[1]'), json_array()), \
+       json_object('a', 1, 'b', json_object('c', 'd'), 'e', '[1]')",
+      "[1,2.5,\"x\",null,[1],[]]|{\"a\":1,\"b\":{\"c\":\"d\"},\"e\":\"[1]\"}",
+    ),
+    (
+      "SELECT json_insert('{\"a\":1}', '$.a', 2, '$.b', 3), \
+       json_replace('{\"a\":1}', '$.a', 2, '$.b', 3), \
+       json_set('{\"a\":1}', '$.a', 2, '$.b', 3)",
+      "{\"a\":1,\"b\":3}|{\"a\":2}|{\"a\":2,\"b\":3}",
+    ),
+    (
+      "SELECT json_set('[1,2]', '$[#]', 3), json_insert('[1]', '$[5]', 3), \
+       json_set('{}', '$.a.b[0]', 1), json_set('{}', '$.a', json('[1]')), \
+       json_set('{}', '$.a', '[1]')",
+      "[1,2,3]|[1]|{\"a\":{\"b\":[1]}}|{\"a\":[1]}|{\"a\":\"[1]\"}",
+    ),
+    (
+      "SELECT json_remove('[1,2,3]', '$[1]'), \
+       json_remove('{\"a\":1,\"b\":2}', '$.a', '$.c'), \
+       json_remove('[1,2,3]', '$[#-1]', '$[0]'), json_remove('[1]', '$')",
+      "[1,3]|{\"b\":2}|[2]|",
+    ),
+    (
+      "SELECT json_patch('{\"a\":1,\"b\":{\"c\":2,\"d\":3}}', \
+       '{\"a\":null,\"b\":{\"c\":4},\"e\":[5]}'), json_patch('[1]', '{}'), \
+       json_patch('{\"a\":1}', '7')",
+      "{\"b\":{\"c\":4,\"d\":3},\"e\":[5]}|{}|7",
+    ),
+  ] {
+    assert_eq!(lines(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT json_object('a')"),
+    "json_object() requires an even number of arguments"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT json_array(x'4142')"),
+    "JSON cannot hold BLOB values"
+  );
+}
+
+#[test]
+fn ok_on_jsonb() {
+  let mut conn = open(&[]);
+  for (sql, expected) in [
+    (
+      "SELECT hex(jsonb('{\"a\":[1,true]}')), hex(jsonb('null')), \
+       hex(jsonb_array('x'))",
+      "6C17613B133101|00|2B1A78",
+    ),
+    (
+      "SELECT json(jsonb('{\"a\":[1,true]}')), \
+       json_extract(jsonb('{\"a\":[1,true]}'), '$.a[1]'), \
+       jsonb('[1,2]') -> 1, json_type(jsonb_object('k', 1.5), '$.k'), \
+       typeof(jsonb_set('[]', '$[0]', 1))",
+      "{\"a\":[1,true]}|1|2|real|blob",
+    ),
+    (
+      "SELECT json_valid(x'2b1a78', 8), json_valid(x'2b1b78', 8), \
+       json_valid(x'2b1b78', 4), json_error_position(x'2b1b78')",
+      "1|0|1|3",
+    ),
+  ] {
+    assert_eq!(lines(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_group_functions() {
+  let mut conn = open(&[]);
+  for sql in [
+    "CREATE TABLE ev(id INTEGER PRIMARY KEY, kind TEXT, payload TEXT)",
+    "INSERT INTO ev(kind, payload) VALUES \
+     ('click', '{\"x\":1,\"tags\":[\"a\"]}'), \
+     ('view', '{\"x\":2,\"tags\":[]}'), \
+     ('click', '{\"x\":3,\"tags\":[\"b\",\"c\"]}')",
+  ] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT kind, json_group_array(payload ->> 'x'), \
+       json_group_array(payload -> 'tags'), \
+       json_group_object(id, payload ->> '$.x') FROM ev \
+       GROUP BY kind ORDER BY kind"
+    ),
+    "click|[1,3]|[[\"a\"],[\"b\",\"c\"]]|{\"1\":1,\"3\":3}\n\
+     view|[2]|[[]]|{\"2\":2}"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT json_group_array(id ORDER BY id DESC), \
+       json_group_object(kind, id), json_group_array(json(payload)) \
+       FROM ev WHERE id < 3"
+    ),
+    "[2,1]|{\"click\":1,\"view\":2}|\
+     [{\"x\":1,\"tags\":[\"a\"]},{\"x\":2,\"tags\":[]}]"
+  );
+  assert_eq!(
+    lines(&mut conn, "SELECT json_group_array(id) FROM ev WHERE 0"),
+    "[]"
+  );
+}
+
+#[test]
+fn ok_on_json_each_and_json_tree() {
+  let mut conn = open(&[]);
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT * FROM json_each('{\"a\":1,\"b\":[2,3],\"c d\":null}')"
+    ),
+    "a|1|integer|1|2||$.a|$\n\
+     b|[2,3]|array||6||$.b|$\n\
+     c d||null||13||$.\"c d\"|$"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT key, value, type, id, parent, fullkey, path \
+       FROM json_tree('{\"a\":[1,{\"b\":2}]}')"
+    ),
+    "|{\"a\":[1,{\"b\":2}]}|object|0||$|$\n\
+     a|[1,{\"b\":2}]|array|1|0|$.a|$\n\
+     0|1|integer|4|1|$.a[0]|$.a\n\
+     1|{\"b\":2}|object|6|1|$.a[1]|$.a\n\
+     b|2|integer|7|6|$.a[1].b|$.a[1]"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT key, value, json, root FROM json_each('{\"a\":[7,8]}', '$.a')"
+    ),
+    "0|7|{\"a\":[7,8]}|$.a\n1|8|{\"a\":[7,8]}|$.a"
+  );
+  assert_eq!(
+    lines(&mut conn, "SELECT key, value, type FROM json_each('5')"),
+    "|5|integer"
+  );
+  for sql in [
+    "CREATE TABLE ev(id INTEGER PRIMARY KEY, payload TEXT)",
+    "INSERT INTO ev(payload) VALUES ('{\"tags\":[\"a\",\"b\"]}'), \
+     ('{\"tags\":[\"b\"]}'), ('{}')",
+  ] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT ev.id, t.value FROM ev, json_each(ev.payload, '$.tags') AS t \
+       ORDER BY t.value, ev.id"
+    ),
+    "1|a\n1|b\n2|b"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT id FROM ev WHERE EXISTS \
+       (SELECT 1 FROM json_each(ev.payload -> 'tags') WHERE value = 'b') \
+       ORDER BY id"
+    ),
+    "1\n2"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT json_group_array(value) FROM json_each('[[1],{\"a\":2},\"s\",null]')"
+    ),
+    "[[1],{\"a\":2},\"s\",null]"
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "EXPLAIN QUERY PLAN SELECT * FROM ev, json_each(ev.payload)"
+    )
+    .lines()
+    .last(),
+    Some("2|0|0|SCAN json_each VIRTUAL TABLE INDEX 1:")
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM json_each('[1]', '$', 3)"),
+    "too many arguments on json_each() - max 2"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM no_such_fn(1)"),
+    "no such table-valued function: no_such_fn"
+  );
+}
@@ -1,4 +1,77 @@
-use crate::{debug, trace, SqliteConnection};
+mod btree;
+mod collation;
+mod constraint;
+mod datetime;
+mod function;
+mod json;
+mod pragma;
+mod query;
+mod query_plan;
+mod schema;
+mod sql;
+mod statement;
+mod table;
+mod transaction;
+mod trigger;
+mod user_function;
+mod vdbe;
+
+use crate::result::SqliteError;
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// An in-memory database with the tables, rows and so on `schema` creates.
+fn open(schema: &[&str]) -> SqliteConnection {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  for sql in schema {
+    conn.execute(sql).unwrap();
+  }
+  conn
+}
+
+fn query(conn: &mut SqliteConnection, sql: &str) -> Vec<Vec<Value>> {
+  conn
+    .query(sql)
+    .unwrap()
+    .map(|row| row.unwrap().into_values())
+    .collect()
+}
+
+/// The rows of `sql`, each with its values separated by `|`.
+fn rows(conn: &mut SqliteConnection, sql: &str) -> Vec<String> {
+  let rows = query(conn, sql).into_iter().map(|row| {
+    let values = row.iter().map(ToString::to_string).collect::<Vec<_>>();
+    values.join("|")
+  });
+  rows.collect()
+}
+
+fn query_error(conn: &mut SqliteConnection, sql: &str) -> String {
+  match conn
+    .query(sql)
+    .map(|rows| rows.collect::<Result<Vec<_>, _>>())
+  {
+    Err(error) | Ok(Err(error)) => message(error),
+    other => panic!("expected an error for {sql:?}, got {other:?}"),
+  }
+}
+
+fn execute_error(conn: &mut SqliteConnection, sql: &str) -> String {
+  match conn.execute(sql) {
+    Err(error) => message(error),
+    other => panic!("expected an error for {sql:?}, got {other:?}"),
+  }
+}
+
+/// The message of an error a user can fix.
+fn message(error: SqliteError) -> String {
+  match error {
+    SqliteError::Custom(message) | SqliteError::Constraint { message, .. } => {
+      message
+    }
+    error => panic!("expected an error with a message, got {error:?}"),
+  }
+}
 
 #[test]
 fn ok_on_new_inmemory_database() {
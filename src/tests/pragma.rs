@@ -0,0 +1,298 @@
+use super::{query_error, rows};
+use crate::SqliteConnection;
+
+fn open() -> SqliteConnection {
+  super::open(&[
+    "CREATE TABLE author(
+       id INTEGER PRIMARY KEY,
+       name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+       born DATE DEFAULT (date('now')),
+       email VARCHAR(80) DEFAULT 'none',
+       code TEXT GENERATED ALWAYS AS (upper(name)) VIRTUAL
+     )",
+    "CREATE TABLE book(
+       author INTEGER REFERENCES author ON DELETE CASCADE,
+       title TEXT,
+       isbn TEXT,
+       year INT DEFAULT -1,
+       PRIMARY KEY(title DESC, author),
+       UNIQUE(isbn),
+       FOREIGN KEY(author, title) REFERENCES shelf(a, b) ON UPDATE SET NULL
+     ) WITHOUT ROWID",
+    "CREATE INDEX book_year ON book(year DESC, lower(title)) WHERE year > 0",
+    "CREATE UNIQUE INDEX author_email ON author(email COLLATE nocase)",
+  ])
+}
+
+#[test]
+fn ok_on_table_info() {
+  let mut conn = open();
+  for (sql, expected) in [
+    (
+      "PRAGMA table_info(author)",
+      vec![
+        "0|id|INTEGER|0||1",
+        "1|name|TEXT|1||0",
+        "2|born|DATE|0|date('now')|0",
+        "3|email|VARCHAR(80)|0|'none'|0",
+      ],
+    ),
+    (
+      "PRAGMA table_xinfo('author')",
+      vec![
+        "0|id|INTEGER|0||1|0",
+        "1|name|TEXT|1||0|0",
+        "2|born|DATE|0|date('now')|0|0",
+        "3|email|VARCHAR(80)|0|'none'|0|0",
+        "4|code|TEXT|0||0|2",
+      ],
+    ),
+    (
+      "PRAGMA main.table_info = book",
+      vec![
+        "0|author|INTEGER|1||2",
+        "1|title|TEXT|1||1",
+        "2|isbn|TEXT|0||0",
+        "3|year|INT|0|-1|0",
+      ],
+    ),
+    ("PRAGMA table_info(nothing)", vec![]),
+    ("PRAGMA table_info", vec![]),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_index_pragmas() {
+  let mut conn = open();
+  for (sql, expected) in [
+    (
+      "PRAGMA index_list(author)",
+      vec!["0|author_email|1|c|0", "1|sqlite_autoindex_author_1|1|u|0"],
+    ),
+    (
+      "PRAGMA index_list(book)",
+      vec![
+        "0|book_year|0|c|1",
+        "1|sqlite_autoindex_book_2|1|u|0",
+        "2|sqlite_autoindex_book_1|1|pk|0",
+      ],
+    ),
+    ("PRAGMA index_info(book_year)", vec!["0|3|year", "1|-2|"]),
+    (
+      "PRAGMA index_xinfo(book_year)",
+      vec![
+        "0|3|year|1|BINARY|1",
+        "1|-2||0|BINARY|1",
+        "2|1|title|1|BINARY|0",
+        "3|0|author|0|BINARY|0",
+      ],
+    ),
+    (
+      "PRAGMA index_xinfo(author_email)",
+      vec!["0|3|email|0|nocase|1", "1|-1||0|BINARY|0"],
+    ),
+    (
+      "PRAGMA index_xinfo(sqlite_autoindex_author_1)",
+      vec!["0|1|name|0|NOCASE|1", "1|-1||0|BINARY|0"],
+    ),
+    (
+      "PRAGMA index_xinfo(sqlite_autoindex_book_1)",
+      vec![
+        "0|1|title|1|BINARY|1",
+        "1|0|author|0|BINARY|1",
+        "2|2|isbn|0|BINARY|0",
+        "3|3|year|0|BINARY|0",
+      ],
+    ),
+    (
+      "PRAGMA index_xinfo(sqlite_autoindex_book_2)",
+      vec![
+        "0|2|isbn|0|BINARY|1",
+        "1|1|title|1|BINARY|0",
+        "2|0|author|0|BINARY|0",
+      ],
+    ),
+    ("PRAGMA index_info(nothing)", vec![]),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_foreign_key_list() {
+  let mut conn = open();
+  assert_eq!(
+    rows(&mut conn, "PRAGMA foreign_key_list(book)"),
+    [
+      "0|0|shelf|author|a|SET NULL|NO ACTION|NONE",
+      "0|1|shelf|title|b|SET NULL|NO ACTION|NONE",
+      "1|0|author|author||NO ACTION|CASCADE|NONE",
+    ]
+  );
+  assert!(rows(&mut conn, "PRAGMA foreign_key_list(author)").is_empty());
+}
+
+#[test]
+fn ok_on_settings() {
+  let path = std::env::temp_dir().join("sqlite-rs-pragmas.db");
+  let _ = std::fs::remove_file(&path);
+  let uri = format!("sqlite://{}?mode=rwc", path.display());
+  {
+    let mut conn = SqliteConnection::open(&uri).unwrap();
+    for (sql, expected) in [
+      ("PRAGMA user_version", vec!["0"]),
+      ("PRAGMA user_version = 7", vec![]),
+      ("PRAGMA application_id = -5", vec![]),
+      ("PRAGMA auto_vacuum = full", vec![]),
+      ("PRAGMA auto_vacuum", vec!["1"]),
+      ("PRAGMA encoding", vec!["UTF-8"]),
+      ("PRAGMA encoding = 'UTF-16le'", vec![]),
+      ("PRAGMA encoding", vec!["UTF-8"]),
+      ("PRAGMA cache_size", vec!["-2000"]),
+      ("PRAGMA cache_size = 100", vec![]),
+      ("PRAGMA cache_size", vec!["100"]),
+      ("PRAGMA journal_mode = wal", vec!["delete"]),
+      ("PRAGMA no_such_pragma", vec![]),
+      ("PRAGMA no_such_pragma = 1", vec![]),
+    ] {
+      assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+    }
+    conn.execute("CREATE TABLE t(x)").unwrap();
+    conn.execute("PRAGMA auto_vacuum = NONE").unwrap();
+    let version = rows(&mut conn, "PRAGMA schema_version");
+    assert_eq!(version, ["1"]);
+    let page_size = rows(&mut conn, "PRAGMA page_size")[0].clone();
+    let page_count = rows(&mut conn, "PRAGMA page_count")[0].clone();
+    let length = std::fs::metadata(&path).unwrap().len();
+    assert_eq!(
+      page_size.parse::<u64>().unwrap() * page_count.parse::<u64>().unwrap(),
+      length
+    );
+    assert_eq!(rows(&mut conn, "PRAGMA freelist_count"), ["0"]);
+    assert_eq!(
+      rows(&mut conn, "PRAGMA database_list"),
+      [format!("0|main|{}", path.canonicalize().unwrap().display())]
+    );
+  }
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  for (sql, expected) in [
+    ("PRAGMA user_version", "7"),
+    ("PRAGMA application_id", "-5"),
+    ("PRAGMA auto_vacuum", "1"),
+    ("PRAGMA cache_size", "-2000"),
+    ("SELECT * FROM pragma_user_version", "7"),
+  ] {
+    assert_eq!(rows(&mut conn, sql), [expected], "{sql}");
+  }
+  drop(conn);
+  let _ = std::fs::remove_file(&path);
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  assert_eq!(rows(&mut conn, "PRAGMA database_list"), ["0|main|"]);
+  assert_eq!(rows(&mut conn, "PRAGMA journal_mode"), ["memory"]);
+  assert_eq!(
+    query_error(&mut conn, "PRAGMA other.user_version"),
+    "unknown database other"
+  );
+}
+
+#[test]
+fn ok_on_incremental_vacuum() {
+  let path = std::env::temp_dir().join("sqlite-rs-incremental-vacuum.db");
+  let _ = std::fs::remove_file(&path);
+  let uri = format!("sqlite://{}?mode=rwc", path.display());
+  {
+    let mut conn = SqliteConnection::open(&uri).unwrap();
+    conn.execute("PRAGMA auto_vacuum = incremental").unwrap();
+    conn.execute("CREATE TABLE t(a)").unwrap();
+    conn
+      .execute("INSERT INTO t VALUES(zeroblob(9000)), (zeroblob(9000))")
+      .unwrap();
+    conn.execute("DELETE FROM t").unwrap();
+    let freelist = rows(&mut conn, "PRAGMA freelist_count")[0].clone();
+    let freelist = freelist.parse::<u32>().unwrap();
+    assert!(freelist > 2);
+    assert!(rows(&mut conn, "PRAGMA incremental_vacuum(2)").is_empty());
+    assert_eq!(
+      rows(&mut conn, "PRAGMA freelist_count"),
+      [(freelist - 2).to_string()]
+    );
+  }
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  conn.execute("PRAGMA incremental_vacuum").unwrap();
+  assert_eq!(rows(&mut conn, "PRAGMA freelist_count"), ["0"]);
+  let page_size = rows(&mut conn, "PRAGMA page_size")[0].clone();
+  let page_count = rows(&mut conn, "PRAGMA page_count")[0].clone();
+  assert_eq!(
+    page_size.parse::<u64>().unwrap() * page_count.parse::<u64>().unwrap(),
+    std::fs::metadata(&path).unwrap().len()
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM pragma_incremental_vacuum"),
+    "no such table: pragma_incremental_vacuum"
+  );
+  drop(conn);
+  let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn ok_on_pragma_functions() {
+  let mut conn = open();
+  conn
+    .create_scalar_function("twice", Some(1), true, |args| Ok(args[0].clone()))
+    .unwrap();
+  for (sql, expected) in [
+    (
+      "SELECT m.name, p.name FROM sqlite_schema m, pragma_index_list(m.name) p
+       WHERE m.type = 'table' ORDER BY 1, 2",
+      vec![
+        "author|author_email",
+        "author|sqlite_autoindex_author_1",
+        "book|book_year",
+        "book|sqlite_autoindex_book_1",
+        "book|sqlite_autoindex_book_2",
+      ],
+    ),
+    (
+      "SELECT name, pk FROM pragma_table_info('book')
+       WHERE pk > 0 ORDER BY pk",
+      vec!["title|1", "author|2"],
+    ),
+    (
+      "SELECT * FROM pragma_table_info('author', 'main') WHERE cid = 0",
+      vec!["0|id|INTEGER|0||1"],
+    ),
+    (
+      "SELECT arg, schema FROM pragma_index_info('book_year')",
+      vec!["book_year|", "book_year|"],
+    ),
+    (
+      "SELECT count(*) FROM pragma_table_info('author', 'temp')",
+      vec!["0"],
+    ),
+    (
+      "SELECT name, builtin, type, narg, flags FROM pragma_function_list
+       WHERE name IN ('count', 'random', 'row_number', 'substr', 'twice')",
+      vec![
+        "count|1|w|0|2097152",
+        "count|1|w|1|2097152",
+        "random|1|s|0|2097152",
+        "row_number|1|w|0|2097152",
+        "substr|1|s|2|2099200",
+        "substr|1|s|3|2099200",
+        "twice|0|s|1|2048",
+      ],
+    ),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM pragma_table_info('a', 'main', 1)"),
+    "too many arguments on pragma_table_info() - max 2"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM pragma_nothing"),
+    "no such table: pragma_nothing"
+  );
+}
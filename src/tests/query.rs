@@ -0,0 +1,1031 @@
+use super::{execute_error, query, query_error};
+use crate::result::SqliteError;
+use crate::runtime::{KeyInfo, Record, SqliteSchema, TableDefinition, Value};
+use crate::sql::{ast::StatementKind, Parser};
+use crate::SqliteConnection;
+
+/// Creates the table of `sql` straight through the b-tree layer, with its
+/// rows keyed by rowids starting at 1. `WITHOUT ROWID` tables are created
+/// empty.
+fn create_table(conn: &mut SqliteConnection, sql: &str, rows: &[Vec<Value>]) {
+  let definition = TableDefinition::parse(sql).unwrap();
+  let name = definition.name().to_owned();
+  let schema_rowid = conn.runtime_mut().schema().unwrap().len() as i64 + 1;
+  let mut btree = conn.runtime_mut().btree();
+  let root = match definition.is_without_rowid() {
+    true => btree.create_index().unwrap(),
+    false => btree.create_table().unwrap(),
+  };
+  let entry = SqliteSchema::new("table", &name, &name, root, Some(sql.into()));
+  btree
+    .insert(1, schema_rowid, &Record::encode(&entry.to_record()))
+    .unwrap();
+  for (idx, row) in rows.iter().enumerate() {
+    btree
+      .insert(root, idx as i64 + 1, &Record::encode(row))
+      .unwrap();
+  }
+}
+
+/// Creates the index of `sql` on the `columns` of `rows`, the rows of a
+/// table created by [`create_table`].
+fn create_index(
+  conn: &mut SqliteConnection,
+  sql: &str,
+  columns: &[usize],
+  rows: &[Vec<Value>],
+) {
+  let statement = Parser::new(sql).unwrap().next_statement().unwrap();
+  let Some(StatementKind::CreateIndex(index)) = statement.map(|s| s.kind)
+  else {
+    panic!("not a CREATE INDEX statement: {sql}");
+  };
+  let schema_rowid = conn.runtime_mut().schema().unwrap().len() as i64 + 1;
+  let mut btree = conn.runtime_mut().btree();
+  let root = btree.create_index().unwrap();
+  let entry = SqliteSchema::new(
+    "index",
+    &index.name.name.value,
+    &index.table.value,
+    root,
+    Some(sql.into()),
+  );
+  btree
+    .insert(1, schema_rowid, &Record::encode(&entry.to_record()))
+    .unwrap();
+  for (idx, row) in rows.iter().enumerate() {
+    let mut key: Vec<Value> =
+      columns.iter().map(|&column| row[column].clone()).collect();
+    key.push(Value::Integer(idx as i64 + 1));
+    btree.insert_index(root, &key, &KeyInfo::default()).unwrap();
+  }
+}
+
+/// Creates the index SQLite makes for the `number`-th PRIMARY KEY or UNIQUE
+/// constraint of the empty `table`.
+fn create_autoindex(conn: &mut SqliteConnection, table: &str, number: usize) {
+  let name = format!("sqlite_autoindex_{table}_{number}");
+  let schema_rowid = conn.runtime_mut().schema().unwrap().len() as i64 + 1;
+  let mut btree = conn.runtime_mut().btree();
+  let root = btree.create_index().unwrap();
+  let entry = SqliteSchema::new("index", &name, table, root, None);
+  btree
+    .insert(1, schema_rowid, &Record::encode(&entry.to_record()))
+    .unwrap();
+}
+
+#[test]
+fn ok_on_select_expressions() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let rows = conn
+    .query("SELECT 7 / 2, 7.0 / 2, 1 / 0, 5.5 % 2, '12abc' + 1, 1 + 2 AS three")
+    .unwrap();
+  assert_eq!(
+    rows.column_names(),
+    [
+      "7 / 2",
+      "7.0 / 2",
+      "1 / 0",
+      "5.5 % 2",
+      "'12abc' + 1",
+      "three"
+    ]
+  );
+  let values = rows
+    .map(|row| row.unwrap().into_values())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    values,
+    vec![vec![
+      Value::Integer(3),
+      Value::Real(3.5),
+      Value::Null,
+      Value::Real(1.0),
+      Value::Integer(13),
+      Value::Integer(3),
+    ]]
+  );
+
+  // Integer overflow moves to floating point, shown with 15 digits.
+  let values = query(
+    &mut conn,
+    "SELECT 9223372036854775807 + 1, CAST(0.1 + 0.2 AS TEXT), \
+     CAST(1e20 AS TEXT), CAST(100.0 / 3 AS TEXT)",
+  );
+  assert_eq!(values[0][0], Value::Real(9.223372036854776e18));
+  assert_eq!(
+    values[0][1..],
+    ["0.3".into(), "1.0e+20".into(), "33.3333333333333".into()]
+  );
+
+  // Three-valued logic.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT 1 AND NULL, 0 AND NULL, 1 OR NULL, NULL = NULL, NULL IS NULL, \
+       2 IN (1, NULL), 2 NOT IN (), NOT NULL"
+    ),
+    vec![vec![
+      Value::Null,
+      Value::Integer(0),
+      Value::Integer(1),
+      Value::Null,
+      Value::Integer(1),
+      Value::Null,
+      Value::Integer(1),
+      Value::Null,
+    ]]
+  );
+
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT 'aBc' LIKE 'AB%', 'a_c' LIKE 'a\\_c' ESCAPE '\\', \
+       'abc' GLOB 'a[a-c]?', 'ABC' GLOB 'a*', \
+       CASE 2 WHEN 1 THEN 'one' WHEN 2 THEN 'two' END, \
+       CAST('12.9abc' AS INTEGER), CAST('3.0' AS NUMERIC), x'41' || 1"
+    ),
+    vec![vec![
+      Value::Integer(1),
+      Value::Integer(1),
+      Value::Integer(1),
+      Value::Integer(0),
+      "two".into(),
+      Value::Integer(12),
+      Value::Integer(3),
+      "A1".into(),
+    ]]
+  );
+
+  assert_eq!(
+    query(&mut conn, "VALUES (1, 'a'), (2, 'b')"),
+    vec![vec![1.into(), "a".into()], vec![2.into(), "b".into()]]
+  );
+}
+
+#[test]
+fn ok_on_table_scan() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE t(id INTEGER PRIMARY KEY, n INT, s TEXT COLLATE NOCASE, \
+     r REAL, v)",
+    &[
+      vec![Value::Null, 5.into(), "Apple".into(), 2.into(), "10".into()],
+      vec![
+        Value::Null,
+        "7".into(),
+        "banana".into(),
+        2.5.into(),
+        3.into(),
+      ],
+      vec![
+        Value::Null,
+        Value::Null,
+        "APPLE".into(),
+        Value::Null,
+        "x".into(),
+      ],
+    ],
+  );
+
+  let rows = conn.query("SELECT *, rowid FROM t").unwrap();
+  assert_eq!(rows.column_names(), ["id", "n", "s", "r", "v", "id"]);
+  let rows = rows.map(|row| row.unwrap()).collect::<Vec<_>>();
+  assert_eq!(rows.len(), 3);
+  assert_eq!(rows[0].get_by_name("ID"), Some(&Value::Integer(1)));
+  // Integers stored in REAL columns read as reals.
+  assert_eq!(rows[0].get(3), Some(&Value::Real(2.0)));
+
+  // The text "7" compares as a number with an INTEGER column, and column
+  // collations apply to comparisons with them.
+  assert_eq!(
+    query(&mut conn, "SELECT id FROM t WHERE n = 7 OR s = 'apple'"),
+    vec![vec![1.into()], vec![2.into()], vec![3.into()]]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT id FROM t WHERE s = 'apple' COLLATE BINARY"
+    ),
+    Vec::<Vec<Value>>::new()
+  );
+  // A column without affinity is compared as stored.
+  assert_eq!(
+    query(&mut conn, "SELECT id FROM t WHERE v = 10 OR v = '3'"),
+    Vec::<Vec<Value>>::new()
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT u.id * 10 FROM t AS u WHERE r > 2"),
+    vec![vec![20.into()]]
+  );
+}
+
+#[test]
+fn ok_on_database_file() {
+  let mut conn =
+    SqliteConnection::open("sqlite://./data/flights-populated.db?mode=ro")
+      .unwrap();
+  let rows = query(
+    &mut conn,
+    "SELECT passengers FROM Observation WHERE year = 1955 AND month_id = 6",
+  );
+  assert_eq!(rows, vec![vec![Value::Integer(364)]]);
+  let rows = query(
+    &mut conn,
+    "SELECT * FROM main.Observation WHERE year = 1955",
+  );
+  assert_eq!(rows.len(), 12);
+
+  let tables = query(
+    &mut conn,
+    "SELECT name FROM sqlite_schema WHERE type = 'table' AND name LIKE 'o%'",
+  );
+  assert_eq!(tables, vec![vec![Value::Text("Observation".into())]]);
+}
+
+#[test]
+fn ok_on_query_errors() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(&mut conn, "CREATE TABLE t(a, b)", &[]);
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM nope"),
+    "no such table: nope"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT t.c FROM t"),
+    "no such column: t.c"
+  );
+  assert_eq!(query_error(&mut conn, "SELECT *"), "no tables specified");
+  assert_eq!(
+    query_error(&mut conn, "SELECT 'a' LIKE 'b' ESCAPE 'xy'"),
+    "ESCAPE expression must be a single character"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT 1 COLLATE klingon"),
+    "no such collation sequence: klingon"
+  );
+  assert!(matches!(
+    conn.query("SELECT FROM t"),
+    Err(SqliteError::Syntax(_))
+  ));
+}
+
+#[test]
+fn ok_on_order_by_and_limit() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE t(n INT, s TEXT COLLATE NOCASE)",
+    &[
+      vec![3.into(), "b".into()],
+      vec![Value::Null, "A".into()],
+      vec![1.into(), "a".into()],
+      vec![3.into(), "B".into()],
+      vec![2.into(), Value::Null],
+    ],
+  );
+
+  assert_eq!(
+    query(&mut conn, "SELECT n FROM t ORDER BY n DESC NULLS FIRST"),
+    vec![
+      vec![Value::Null],
+      vec![3.into()],
+      vec![3.into()],
+      vec![2.into()],
+      vec![1.into()],
+    ]
+  );
+  // The column collation applies unless the term has its own, and rows
+  // with equal keys keep their order.
+  assert_eq!(
+    query(&mut conn, "SELECT s AS x FROM t ORDER BY x DESC LIMIT 3"),
+    vec![vec!["b".into()], vec!["B".into()], vec!["A".into()]]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT s FROM t ORDER BY s COLLATE BINARY LIMIT 2 OFFSET 1"
+    ),
+    vec![vec!["A".into()], vec!["B".into()]]
+  );
+  // Terms may be expressions that are not result columns.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT s FROM t WHERE n > 1 ORDER BY -n, s LIMIT 1"
+    ),
+    vec![vec!["b".into()]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT DISTINCT s FROM t ORDER BY 1"),
+    vec![vec![Value::Null], vec!["A".into()], vec!["b".into()]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT n FROM t LIMIT -1 OFFSET 4"),
+    vec![vec![2.into()]]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "VALUES (2), (1), (3) ORDER BY column1 DESC LIMIT '2'"
+    ),
+    vec![vec![3.into()], vec![2.into()]]
+  );
+
+  assert_eq!(
+    query_error(&mut conn, "SELECT n FROM t ORDER BY 2"),
+    "1st ORDER BY term out of range - should be between 1 and 1"
+  );
+  assert_eq!(
+    query_error(&mut conn, "VALUES (1) ORDER BY 1, n"),
+    "2nd ORDER BY term does not match any column in the result set"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT n FROM t LIMIT 1.5"),
+    "datatype mismatch"
+  );
+}
+
+#[test]
+fn ok_on_sort_spilling_to_disk() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let rows = (0..500)
+    .map(|idx: i64| vec![((idx * 7919) % 500).into(), (idx % 3).into()])
+    .collect::<Vec<_>>();
+  create_table(&mut conn, "CREATE TABLE t(n INT, m INT)", &rows);
+  conn.set_sort_memory_limit(1024);
+
+  let sorted = query(&mut conn, "SELECT n FROM t ORDER BY n DESC");
+  let expected = (0..500).rev().map(|n| vec![n.into()]).collect::<Vec<_>>();
+  assert_eq!(sorted, expected);
+
+  // Equal keys keep their order across the spilled runs.
+  let sorted = query(&mut conn, "SELECT m, n FROM t ORDER BY m");
+  let expected = [0, 1, 2]
+    .into_iter()
+    .flat_map(|m| {
+      rows
+        .iter()
+        .filter(move |row| row[1] == Value::Integer(m))
+        .map(|row| vec![row[1].clone(), row[0].clone()])
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(sorted, expected);
+
+  let top = query(&mut conn, "SELECT n FROM t ORDER BY n LIMIT 3 OFFSET 10");
+  assert_eq!(top, vec![vec![10.into()], vec![11.into()], vec![12.into()]]);
+}
+
+#[test]
+fn ok_on_distinct_rows_spilling_to_disk() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let rows = (0..500)
+    .map(|idx: i64| vec![((idx * 7919) % 500).into(), (idx % 3).into()])
+    .collect::<Vec<_>>();
+  create_table(&mut conn, "CREATE TABLE t(n INT, m INT)", &rows);
+  conn
+    .create_collation("reverse", |left, right| right.cmp(left))
+    .unwrap();
+  conn.set_sort_memory_limit(1024);
+
+  // Distinct rows come in the order they are first seen.
+  let mut expected = vec![];
+  for row in &rows {
+    let Value::Integer(n) = row[0] else {
+      unreachable!()
+    };
+    let row = vec![(n % 60).into(), row[1].clone()];
+    if !expected.contains(&row) {
+      expected.push(row);
+    }
+  }
+  assert_eq!(
+    query(&mut conn, "SELECT DISTINCT n % 60, m FROM t"),
+    expected
+  );
+
+  for (sql, count) in [
+    ("SELECT n FROM t UNION SELECT n + 250 FROM t", 750),
+    ("SELECT n FROM t INTERSECT SELECT n + 250 FROM t", 250),
+    ("SELECT n FROM t EXCEPT SELECT n + 250 FROM t", 250),
+    (
+      "SELECT DISTINCT CAST(n % 70 AS TEXT) COLLATE reverse FROM t",
+      70,
+    ),
+    (
+      "SELECT n FROM t WHERE n IN (SELECT n FROM t WHERE m = 0)",
+      167,
+    ),
+  ] {
+    let counted = format!("SELECT count(*) FROM ({sql})");
+    assert_eq!(query(&mut conn, &counted), [[count.into()]], "{sql}");
+  }
+  assert_eq!(
+    query(&mut conn, "SELECT count(DISTINCT n % 100) FROM t"),
+    [[100.into()]]
+  );
+}
+
+#[test]
+fn ok_on_aggregates() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE t(a INT, b TEXT COLLATE NOCASE, c)",
+    &[
+      vec![1.into(), "x".into(), 10.into()],
+      vec![2.into(), "X".into(), 30.into()],
+      vec![3.into(), "y".into(), 20.into()],
+      vec![4.into(), Value::Null, Value::Null],
+      vec![5.into(), "y".into(), 5.into()],
+    ],
+  );
+
+  // Groups follow the collating sequence of their expression, and come out
+  // in order.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT b, count(*), group_concat(a), sum(c), avg(c), total(c) \
+       FROM t GROUP BY b"
+    ),
+    vec![
+      vec![
+        Value::Null,
+        1.into(),
+        "4".into(),
+        Value::Null,
+        Value::Null,
+        0.0.into()
+      ],
+      vec![
+        "x".into(),
+        2.into(),
+        "1,2".into(),
+        40.into(),
+        20.0.into(),
+        40.0.into()
+      ],
+      vec![
+        "y".into(),
+        2.into(),
+        "3,5".into(),
+        25.into(),
+        12.5.into(),
+        25.0.into()
+      ],
+    ]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT count(DISTINCT b), count(b), count(*) FILTER (WHERE a > 2), \
+       group_concat(a, '-' ORDER BY c DESC), string_agg(b, ';') FROM t"
+    ),
+    vec![vec![
+      2.into(),
+      4.into(),
+      3.into(),
+      "2-3-1-5-4".into(),
+      "x;X;y;y".into(),
+    ]]
+  );
+  // Aliases can be used by the other clauses.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT b AS k, sum(a) AS s FROM t GROUP BY k HAVING s > 3 \
+       ORDER BY s DESC"
+    ),
+    vec![vec!["y".into(), 8.into()], vec![Value::Null, 4.into()]]
+  );
+  // Bare columns come from the row of the minimum or maximum, else from the
+  // first row of their group.
+  assert_eq!(
+    query(&mut conn, "SELECT a, max(c), count(*) FROM t"),
+    vec![vec![2.into(), 30.into(), 5.into()]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT a, count(*) FROM t WHERE a > 2"),
+    vec![vec![3.into(), 3.into()]]
+  );
+  // Without GROUP BY there is a row even without input rows.
+  assert_eq!(
+    query(&mut conn, "SELECT count(*), max(a), sum(a) FROM t WHERE 0"),
+    vec![vec![0.into(), Value::Null, Value::Null]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT a, count(*) FROM t WHERE 0 GROUP BY a"),
+    Vec::<Vec<Value>>::new()
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT sum('12'), sum('abc'), avg('3')"),
+    vec![vec![12.into(), 0.0.into(), 3.0.into()]]
+  );
+
+  assert_eq!(
+    query_error(&mut conn, "SELECT a FROM t WHERE count(*) > 1"),
+    "misuse of aggregate function count()"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT count(*) FROM t GROUP BY 1"),
+    "aggregate functions are not allowed in the GROUP BY clause"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT a FROM t HAVING a > 1"),
+    "HAVING clause on a non-aggregate query"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT count(a, b) FROM t"),
+    "wrong number of arguments to function count()"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT sum(a) FROM t GROUP BY 2"),
+    "1st GROUP BY term out of range - should be between 1 and 1"
+  );
+  create_table(
+    &mut conn,
+    "CREATE TABLE big(n INT)",
+    &[vec![i64::MAX.into()], vec![1.into()]],
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT sum(n) FROM big"),
+    "integer overflow"
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT total(n) FROM big"),
+    vec![vec![Value::Real(9.223372036854776e18)]]
+  );
+}
+
+#[test]
+fn ok_on_joins() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE customer(id INTEGER PRIMARY KEY, name TEXT)",
+    &[
+      vec![Value::Null, "ann".into()],
+      vec![Value::Null, "bob".into()],
+      vec![Value::Null, "cy".into()],
+    ],
+  );
+  // Enough orders of the same customers for their index entries to span
+  // several pages.
+  let orders = (1..=900)
+    .map(|id: i64| {
+      let customer = match id % 10 {
+        0 => Value::Null,
+        n if n < 7 => 1.into(),
+        _ => 2.into(),
+      };
+      vec![Value::Null, customer, format!("item{}", id % 4).into()]
+    })
+    .collect::<Vec<_>>();
+  create_table(
+    &mut conn,
+    "CREATE TABLE orders(id INTEGER PRIMARY KEY, customer INT, item TEXT)",
+    &orders,
+  );
+  create_index(
+    &mut conn,
+    "CREATE INDEX orders_customer ON orders(customer)",
+    &[1],
+    &orders,
+  );
+  create_table(
+    &mut conn,
+    "CREATE TABLE item(item TEXT, price INT)",
+    &[
+      vec!["item0".into(), 5.into()],
+      vec!["item1".into(), 7.into()],
+      vec!["item9".into(), 9.into()],
+    ],
+  );
+
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT c.name, count(*), sum(i.price) FROM customer c \
+       JOIN orders o ON o.customer = c.id JOIN item i USING (item) \
+       GROUP BY c.name ORDER BY 1"
+    ),
+    vec![
+      vec!["ann".into(), 270.into(), 1620.into()],
+      vec!["bob".into(), 135.into(), 855.into()],
+    ]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT count(*) FROM orders a, orders b \
+       WHERE b.customer = a.customer AND a.id < 10"
+    ),
+    vec![vec![(6 * 540 + 3 * 270).into()]]
+  );
+  // Rows without a match are extended with NULLs.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT c.name, o.id FROM customer c \
+       LEFT JOIN orders o ON o.customer = c.id AND o.id < 3 ORDER BY 1, 2"
+    ),
+    vec![
+      vec!["ann".into(), 1.into()],
+      vec!["ann".into(), 2.into()],
+      vec!["bob".into(), Value::Null],
+      vec!["cy".into(), Value::Null],
+    ]
+  );
+  create_table(
+    &mut conn,
+    "CREATE TABLE note(item TEXT, text TEXT)",
+    &[
+      vec!["item1".into(), "a".into()],
+      vec!["item7".into(), "b".into()],
+    ],
+  );
+  // Full joins merge the columns of USING, which take the first value that
+  // is not NULL.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT * FROM item FULL JOIN note USING (item) ORDER BY item"
+    ),
+    vec![
+      vec!["item0".into(), 5.into(), Value::Null],
+      vec!["item1".into(), 7.into(), "a".into()],
+      vec!["item7".into(), Value::Null, "b".into()],
+      vec!["item9".into(), 9.into(), Value::Null],
+    ]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT note.* FROM item NATURAL JOIN note"),
+    vec![vec!["item1".into(), "a".into()]]
+  );
+
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM item JOIN note USING (price)"),
+    "cannot join using column price - column not present in both tables"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT item FROM item, note"),
+    "ambiguous column name: item"
+  );
+  assert_eq!(
+    query_error(
+      &mut conn,
+      "SELECT * FROM item LEFT JOIN note ON note.item = c.name, customer c"
+    ),
+    "ON clause references tables to its right"
+  );
+}
+
+#[test]
+fn ok_on_subqueries() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE emp(id INTEGER PRIMARY KEY, name TEXT, boss INT)",
+    &[
+      vec![Value::Null, "ann".into(), Value::Null],
+      vec![Value::Null, "bob".into(), 1.into()],
+      vec![Value::Null, "cy".into(), 1.into()],
+      vec![Value::Null, "dan".into(), 2.into()],
+    ],
+  );
+
+  // Correlated subqueries run again for each row of their query.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT name, (SELECT count(*) FROM emp c WHERE c.boss = e.id) \
+       FROM emp e WHERE EXISTS (SELECT 1 FROM emp c WHERE c.boss = e.id)"
+    ),
+    vec![vec!["ann".into(), 2.into()], vec!["bob".into(), 1.into()],]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT name FROM emp WHERE id NOT IN (SELECT boss FROM emp)"
+    ),
+    Vec::<Vec<Value>>::new()
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT name FROM emp \
+       WHERE id NOT IN (SELECT boss FROM emp WHERE boss IS NOT NULL)"
+    ),
+    vec![vec!["cy".into()], vec!["dan".into()]]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT e.name, t.n FROM emp e \
+       JOIN (SELECT boss, count(*) AS n FROM emp GROUP BY boss) t \
+       ON t.boss = e.id"
+    ),
+    vec![vec!["ann".into(), 2.into()], vec!["bob".into(), 1.into()],]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "WITH RECURSIVE under(id, depth) AS (\
+       SELECT 1, 0 UNION ALL \
+       SELECT emp.id, depth + 1 FROM under JOIN emp ON emp.boss = under.id) \
+       SELECT name, depth FROM under JOIN emp USING (id)"
+    ),
+    vec![
+      vec!["ann".into(), 0.into()],
+      vec!["bob".into(), 1.into()],
+      vec!["cy".into(), 1.into()],
+      vec!["dan".into(), 2.into()],
+    ]
+  );
+  // Compound selects other than UNION ALL return their rows in order.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT boss FROM emp UNION SELECT 3 EXCEPT SELECT 2"
+    ),
+    vec![vec![Value::Null], vec![1.into()], vec![3.into()]]
+  );
+
+  assert_eq!(
+    query_error(&mut conn, "WITH r AS (SELECT * FROM r) SELECT * FROM r"),
+    "circular reference: r"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT 1 UNION SELECT 1, 2"),
+    "SELECTs to the left and right of UNION do not have the same number \
+     of result columns"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT (SELECT 1, 2)"),
+    "sub-select returns 2 columns - expected 1"
+  );
+}
+
+#[test]
+fn ok_on_window_functions() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let sales = [("a", 1, 10), ("a", 2, 20), ("a", 3, 20), ("b", 1, 5)]
+    .iter()
+    .map(|&(shop, day, amount)| {
+      vec![shop.into(), Value::Integer(day), Value::Integer(amount)]
+    })
+    .collect::<Vec<_>>();
+  create_table(
+    &mut conn,
+    "CREATE TABLE sale(shop TEXT, day INT, amount INT)",
+    &sales,
+  );
+
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT shop, day, sum(amount) OVER w, \
+       rank() OVER (PARTITION BY shop ORDER BY amount), \
+       lag(amount, 1, 0) OVER w \
+       FROM sale WINDOW w AS (PARTITION BY shop ORDER BY day) \
+       ORDER BY shop, day"
+    ),
+    vec![
+      vec!["a".into(), 1.into(), 10.into(), 1.into(), 0.into()],
+      vec!["a".into(), 2.into(), 30.into(), 2.into(), 10.into()],
+      vec!["a".into(), 3.into(), 50.into(), 2.into(), 20.into()],
+      vec!["b".into(), 1.into(), 5.into(), 1.into(), 0.into()],
+    ]
+  );
+  // Moving averages over rows, and over the values of the sort key.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT avg(amount) OVER (ORDER BY rowid ROWS 1 PRECEDING), \
+       count(*) OVER (ORDER BY amount RANGE BETWEEN 5 PRECEDING \
+       AND 10 FOLLOWING EXCLUDE CURRENT ROW) FROM sale ORDER BY rowid"
+    ),
+    vec![
+      vec![Value::Real(10.0), 3.into()],
+      vec![Value::Real(15.0), 1.into()],
+      vec![Value::Real(20.0), 1.into()],
+      vec![Value::Real(12.5), 1.into()],
+    ]
+  );
+  // Window functions are computed over the groups of aggregate queries.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT shop, sum(sum(amount)) OVER (ORDER BY shop DESC) \
+       FROM sale GROUP BY shop ORDER BY shop"
+    ),
+    vec![vec!["a".into(), 55.into()], vec!["b".into(), 5.into()]]
+  );
+
+  assert_eq!(
+    query_error(&mut conn, "SELECT * FROM sale WHERE row_number() OVER ()"),
+    "misuse of window function row_number()"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT count(*) OVER w FROM sale"),
+    "no such window: w"
+  );
+  assert_eq!(
+    query_error(
+      &mut conn,
+      "SELECT count(*) OVER (RANGE 1 PRECEDING) FROM sale"
+    ),
+    "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression"
+  );
+}
+
+#[test]
+fn ok_on_insert_update_delete() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT, price REAL)",
+    &[],
+  );
+  create_index(&mut conn, "CREATE INDEX item_name ON item(name)", &[], &[]);
+
+  assert_eq!(
+    conn
+      .execute("INSERT INTO item(name, price) VALUES ('pen', 2), ('ink', '3')")
+      .unwrap(),
+    2
+  );
+  assert_eq!(conn.last_insert_rowid(), 2);
+  conn
+    .execute("INSERT INTO item VALUES (10, 'cap', 1.5), (NULL, 'nib', 1)")
+    .unwrap();
+  assert_eq!((conn.changes(), conn.last_insert_rowid()), (2, 11));
+  conn
+    .execute("INSERT INTO item(name) SELECT name || '2' FROM item")
+    .unwrap();
+  assert_eq!(conn.total_changes(), 8);
+  assert_eq!(
+    query(&mut conn, "SELECT id, price FROM item WHERE name = 'ink'"),
+    vec![vec![2.into(), Value::Real(3.0)]]
+  );
+
+  // Rows moved by an update are found through the index at their new place.
+  assert_eq!(
+    conn
+      .execute("UPDATE item SET name = 'quill', id = id + 100 WHERE id < 3")
+      .unwrap(),
+    2
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT id FROM item WHERE name = 'quill'"),
+    vec![vec![101.into()], vec![102.into()]]
+  );
+  assert!(query(&mut conn, "SELECT id FROM item WHERE name = 'pen'").is_empty());
+  assert_eq!(
+    conn
+      .execute("UPDATE item SET price = 0 WHERE name LIKE '%2' ORDER BY id DESC LIMIT 3")
+      .unwrap(),
+    3
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT id FROM item WHERE price = 0"),
+    vec![vec![13.into()], vec![14.into()], vec![15.into()]]
+  );
+
+  assert_eq!(conn.execute("DELETE FROM item WHERE price < 2").unwrap(), 5);
+  assert_eq!(
+    query(&mut conn, "SELECT name FROM item ORDER BY name"),
+    vec![
+      vec!["cap2".into()],
+      vec!["quill".into()],
+      vec!["quill".into()]
+    ]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT count(*) FROM item WHERE name > 'a'"),
+    vec![vec![3.into()]]
+  );
+}
+
+#[test]
+fn ok_on_uniqueness_conflicts() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(
+    &mut conn,
+    "CREATE TABLE account(id INTEGER PRIMARY KEY, email TEXT UNIQUE, n INT)",
+    &[],
+  );
+  create_autoindex(&mut conn, "account", 1);
+  create_table(
+    &mut conn,
+    "CREATE TABLE pair(a, b, PRIMARY KEY(a, b)) WITHOUT ROWID",
+    &[],
+  );
+  conn
+    .execute("INSERT INTO account(email, n) VALUES ('a@x', 1), ('b@x', 2)")
+    .unwrap();
+
+  // A failing statement leaves no change behind.
+  assert_eq!(
+    execute_error(
+      &mut conn,
+      "INSERT INTO account(email) VALUES ('c@x'), ('a@x')"
+    ),
+    "UNIQUE constraint failed: account.email"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "UPDATE account SET id = 1"),
+    "UNIQUE constraint failed: account.id"
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT count(*) FROM account"),
+    vec![vec![2.into()]]
+  );
+  // Unless it fails with FAIL, which keeps the rows changed before.
+  assert_eq!(
+    execute_error(
+      &mut conn,
+      "INSERT OR FAIL INTO account(email) VALUES ('c@x'), ('a@x')"
+    ),
+    "UNIQUE constraint failed: account.email"
+  );
+  assert_eq!(
+    conn
+      .execute("INSERT OR IGNORE INTO account(email) VALUES ('b@x'), ('d@x')")
+      .unwrap(),
+    1
+  );
+  assert_eq!(
+    conn
+      .execute("INSERT OR REPLACE INTO account(email, n) VALUES ('a@x', 5)")
+      .unwrap(),
+    1
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT id, email, n FROM account WHERE email IN ('a@x', 'c@x')"
+    ),
+    vec![
+      vec![3.into(), "c@x".into(), Value::Null],
+      vec![5.into(), "a@x".into(), 5.into()],
+    ]
+  );
+
+  conn
+    .execute("INSERT INTO pair VALUES (1, 1), (1, 2)")
+    .unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "UPDATE pair SET b = 2"),
+    "UNIQUE constraint failed: pair.a, pair.b"
+  );
+  conn
+    .execute("UPDATE OR REPLACE pair SET b = 3 WHERE b = 1")
+    .unwrap();
+  assert_eq!(
+    query(&mut conn, "SELECT * FROM pair"),
+    vec![vec![1.into(), 2.into()], vec![1.into(), 3.into()]]
+  );
+}
+
+#[test]
+fn ok_on_change_errors() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  create_table(&mut conn, "CREATE TABLE t(id INTEGER PRIMARY KEY, a)", &[]);
+
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t VALUES (1)"),
+    "table t has 2 columns but 1 values were supplied"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t(a) VALUES (1, 2)"),
+    "2 values for 1 columns"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t(b) VALUES (1)"),
+    "table t has no column named b"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "UPDATE t SET b = 1"),
+    "no such column: b"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "UPDATE t SET (id, a) = (1, 2, 3)"),
+    "2 columns assigned 3 values"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t VALUES ('one', 1)"),
+    "datatype mismatch"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "DELETE FROM sqlite_schema"),
+    "table sqlite_master may not be modified"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "SELECT * FROM t"),
+    "Only INSERT, UPDATE, DELETE, CREATE, DROP and ALTER TABLE statements \
+     can be executed"
+  );
+}
@@ -0,0 +1,331 @@
+use super::query;
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// The lines of the query plan of `sql`, each indented by two spaces per
+/// line it is under.
+fn query_plan(conn: &mut SqliteConnection, sql: &str) -> Vec<String> {
+  let rows = query(conn, &format!("EXPLAIN QUERY PLAN {sql}"));
+  let mut depths = vec![(0, 0)];
+  rows
+    .into_iter()
+    .map(|row| {
+      let field = |i: usize| row[i].to_string().parse::<i64>().unwrap();
+      let parent = depths.iter().find(|(id, _)| *id == field(1)).unwrap().1;
+      depths.push((field(0), parent + 1));
+      format!("{}{}", "  ".repeat(parent), row[3])
+    })
+    .collect()
+}
+
+fn open() -> SqliteConnection {
+  super::open(&[
+    "CREATE TABLE t(a INTEGER PRIMARY KEY, b, c)",
+    "CREATE INDEX tc ON t(c)",
+    "CREATE TABLE u(x, y)",
+    "CREATE TABLE w(k, v, PRIMARY KEY(k)) WITHOUT ROWID",
+    "INSERT INTO t VALUES (1, 10, 'x'), (2, 20, 'y'), (3, 30, NULL)",
+    "INSERT INTO t VALUES (4, 40, 'z'), (5, 50, 'y')",
+    "INSERT INTO u VALUES (10, 'p'), (30, 'q'), (60, 'r')",
+    "INSERT INTO w VALUES (1, 'one'), (2, 'two'), (3, 'three')",
+  ])
+}
+
+#[test]
+fn ok_on_explain_query_plan_columns() {
+  let mut conn = open();
+  assert_eq!(
+    conn
+      .query("EXPLAIN QUERY PLAN SELECT * FROM t")
+      .unwrap()
+      .column_names(),
+    ["id", "parent", "notused", "detail"]
+  );
+  assert_eq!(
+    query(&mut conn, "EXPLAIN QUERY PLAN SELECT * FROM t WHERE a = 1"),
+    [vec![
+      Value::Integer(1),
+      Value::Integer(0),
+      Value::Integer(0),
+      Value::Text("SEARCH t USING INTEGER PRIMARY KEY (rowid=?)".into()),
+    ]]
+  );
+}
+
+#[test]
+fn ok_on_access_paths() {
+  let mut conn = open();
+  for (sql, expected) in [
+    ("SELECT * FROM t", "SCAN t"),
+    (
+      "SELECT * FROM t WHERE 2 = a",
+      "SEARCH t USING INTEGER PRIMARY KEY (rowid=?)",
+    ),
+    (
+      "SELECT * FROM t WHERE a > 1 AND a <= 4",
+      "SEARCH t USING INTEGER PRIMARY KEY (rowid>? AND rowid<?)",
+    ),
+    (
+      "SELECT * FROM t WHERE c = 'y'",
+      "SEARCH t USING INDEX tc (c=?)",
+    ),
+    (
+      "SELECT a FROM t WHERE c = 'y'",
+      "SEARCH t USING COVERING INDEX tc (c=?)",
+    ),
+    (
+      "SELECT c FROM t WHERE c BETWEEN 'a' AND 'x'",
+      "SEARCH t USING COVERING INDEX tc (c>? AND c<?)",
+    ),
+    ("SELECT c FROM t", "SCAN t USING COVERING INDEX tc"),
+    (
+      "SELECT * FROM t WHERE a IN (5, 6, 7)",
+      "SEARCH t USING INTEGER PRIMARY KEY (rowid=?)",
+    ),
+    (
+      "SELECT * FROM t WHERE c IN ('x', 'y')",
+      "SEARCH t USING INDEX tc (c=?)",
+    ),
+    (
+      "SELECT * FROM w WHERE k = 2",
+      "SEARCH w USING PRIMARY KEY (k=?)",
+    ),
+    ("SELECT 1", "SCAN CONSTANT ROW"),
+    ("VALUES (1), (2)", "SCAN 2-ROW VALUES CLAUSE"),
+  ] {
+    assert_eq!(query_plan(&mut conn, sql), [expected], "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_join_plans() {
+  let mut conn = open();
+  assert_eq!(
+    query_plan(&mut conn, "SELECT * FROM t, u WHERE u.x = t.b"),
+    ["SCAN t", "SEARCH u USING AUTOMATIC COVERING INDEX (x=?)"]
+  );
+  assert_eq!(
+    query_plan(&mut conn, "SELECT * FROM u JOIN t ON t.a = u.x / 10"),
+    ["SCAN u", "SEARCH t USING INTEGER PRIMARY KEY (rowid=?)"]
+  );
+  assert_eq!(
+    query_plan(
+      &mut conn,
+      "SELECT * FROM t LEFT JOIN u ON u.x = t.a ORDER BY u.y"
+    ),
+    ["SCAN t", "SCAN u LEFT-JOIN", "USE TEMP B-TREE FOR ORDER BY"]
+  );
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT t.a, u.y FROM t, u WHERE u.x = t.b ORDER BY t.a"
+    ),
+    [
+      vec![Value::Integer(1), Value::Text("p".into())],
+      vec![Value::Integer(3), Value::Text("q".into())],
+    ]
+  );
+}
+
+#[test]
+fn ok_on_nested_plans() {
+  let mut conn = open();
+  assert_eq!(
+    query_plan(
+      &mut conn,
+      "SELECT a FROM t UNION SELECT x FROM u EXCEPT SELECT 1"
+    ),
+    [
+      "COMPOUND QUERY",
+      "  LEFT-MOST SUBQUERY",
+      "    SCAN t USING COVERING INDEX tc",
+      "  UNION USING TEMP B-TREE",
+      "    SCAN u",
+      "  EXCEPT USING TEMP B-TREE",
+      "    SCAN CONSTANT ROW",
+    ]
+  );
+  assert_eq!(
+    query_plan(
+      &mut conn,
+      "SELECT b FROM t WHERE b IN (SELECT x FROM u) \
+       AND c = (SELECT max(y) FROM u WHERE x = t.b)"
+    ),
+    [
+      "SCAN t",
+      "LIST SUBQUERY 1",
+      "  SCAN u",
+      "CORRELATED SCALAR SUBQUERY 2",
+      "  SCAN u",
+    ]
+  );
+  assert_eq!(
+    query_plan(&mut conn, "SELECT b, count(*) FROM t GROUP BY b"),
+    ["SCAN t", "USE TEMP B-TREE FOR GROUP BY"]
+  );
+  assert_eq!(
+    query_plan(
+      &mut conn,
+      "WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r \
+       WHERE n < 3) SELECT * FROM r"
+    ),
+    [
+      "CO-ROUTINE r",
+      "  SETUP",
+      "    SCAN CONSTANT ROW",
+      "  RECURSIVE STEP",
+      "    SCAN r",
+      "SCAN r",
+    ]
+  );
+}
+
+#[test]
+fn ok_on_range_scans() {
+  let mut conn = open();
+  for (sql, expected) in [
+    ("SELECT a FROM t WHERE a > 2", vec![3, 4, 5]),
+    ("SELECT a FROM t WHERE a >= 2 AND a < 4", vec![2, 3]),
+    ("SELECT a FROM t WHERE a > 2.5 AND a <= 4.0", vec![3, 4]),
+    ("SELECT a FROM t WHERE a < 0", vec![]),
+    ("SELECT a FROM t WHERE c > 'x' ORDER BY a", vec![2, 4, 5]),
+    ("SELECT a FROM t WHERE c < 'y' ORDER BY a", vec![1]),
+    (
+      "SELECT a FROM t WHERE c >= 'y' AND c < 'z' ORDER BY a",
+      vec![2, 5],
+    ),
+    ("SELECT a FROM t WHERE c > NULL", vec![]),
+    ("SELECT a FROM t WHERE a BETWEEN 2 AND 3", vec![2, 3]),
+  ] {
+    let rows = query(&mut conn, sql);
+    let expected = expected
+      .into_iter()
+      .map(|a| vec![Value::Integer(a)])
+      .collect::<Vec<_>>();
+    assert_eq!(rows, expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_in_lists() {
+  let mut conn = open();
+  for (sql, expected) in [
+    (
+      "SELECT a FROM t WHERE a IN (4, 2, NULL, 2, '5')",
+      vec![2, 4, 5],
+    ),
+    ("SELECT a FROM t WHERE a IN (9, NULL)", vec![]),
+    (
+      "SELECT a FROM t WHERE c IN ('y', 'x', 'y') ORDER BY a",
+      vec![1, 2, 5],
+    ),
+    ("SELECT a FROM t WHERE c IN ('z') AND a IN (1, 4)", vec![4]),
+    (
+      "SELECT u.x FROM u, t WHERE t.a IN (u.x / 10, u.x / 20) ORDER BY 1",
+      vec![10, 30, 30, 60],
+    ),
+  ] {
+    let rows = query(&mut conn, sql);
+    let expected = expected
+      .into_iter()
+      .map(|a| vec![Value::Integer(a)])
+      .collect::<Vec<_>>();
+    assert_eq!(rows, expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_ordered_access() {
+  let mut conn = open();
+  for (sql, plan) in [
+    ("SELECT * FROM t ORDER BY a DESC LIMIT 1", vec!["SCAN t"]),
+    ("SELECT * FROM t ORDER BY c", vec!["SCAN t USING INDEX tc"]),
+    (
+      "SELECT c, count(*) FROM t GROUP BY c",
+      vec!["SCAN t USING COVERING INDEX tc"],
+    ),
+    (
+      "SELECT c, count(*) FROM t GROUP BY c ORDER BY c DESC",
+      vec!["SCAN t USING COVERING INDEX tc"],
+    ),
+    (
+      "SELECT a FROM t WHERE c > 'x' ORDER BY c DESC",
+      vec!["SEARCH t USING COVERING INDEX tc (c>?)"],
+    ),
+    (
+      "SELECT a FROM t WHERE c IS NOT NULL",
+      vec!["SEARCH t USING COVERING INDEX tc (c>?)"],
+    ),
+    ("SELECT * FROM w ORDER BY k DESC", vec!["SCAN w"]),
+    (
+      "SELECT * FROM t ORDER BY b",
+      vec!["SCAN t", "USE TEMP B-TREE FOR ORDER BY"],
+    ),
+    (
+      "SELECT * FROM t ORDER BY c NULLS LAST",
+      vec!["SCAN t", "USE TEMP B-TREE FOR ORDER BY"],
+    ),
+  ] {
+    assert_eq!(query_plan(&mut conn, sql), plan, "{sql}");
+  }
+  for (sql, expected) in [
+    ("SELECT a FROM t ORDER BY a DESC LIMIT 2", vec![5, 4]),
+    (
+      "SELECT a FROM t ORDER BY c DESC, a DESC",
+      vec![4, 5, 2, 1, 3],
+    ),
+    ("SELECT a FROM t WHERE a < 4 ORDER BY a DESC", vec![3, 2, 1]),
+    (
+      "SELECT a FROM t WHERE c <= 'y' ORDER BY c DESC",
+      vec![5, 2, 1],
+    ),
+    (
+      "SELECT a FROM t WHERE c IS NOT NULL ORDER BY a",
+      vec![1, 2, 4, 5],
+    ),
+    (
+      "SELECT count(*) FROM t GROUP BY c ORDER BY c DESC",
+      vec![1, 2, 1, 1],
+    ),
+    ("SELECT k FROM w ORDER BY k DESC", vec![3, 2, 1]),
+  ] {
+    let rows = query(&mut conn, sql);
+    let expected = expected
+      .into_iter()
+      .map(|a| vec![Value::Integer(a)])
+      .collect::<Vec<_>>();
+    assert_eq!(rows, expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_min_max() {
+  let mut conn = open();
+  for (sql, plan, expected) in [
+    (
+      "SELECT min(c) FROM t",
+      "SEARCH t USING COVERING INDEX tc (c>?)",
+      vec![Value::Text("x".into())],
+    ),
+    (
+      "SELECT max(c), a FROM t",
+      "SCAN t USING COVERING INDEX tc",
+      vec![Value::Text("z".into()), Value::Integer(4)],
+    ),
+    ("SELECT max(a) FROM t", "SCAN t", vec![Value::Integer(5)]),
+    (
+      "SELECT min(a) FROM t WHERE a > 2",
+      "SEARCH t USING INTEGER PRIMARY KEY (rowid>?)",
+      vec![Value::Integer(3)],
+    ),
+    ("SELECT max(k) FROM w", "SCAN w", vec![Value::Integer(3)]),
+    (
+      "SELECT min(c) FROM t WHERE a > 9",
+      "SEARCH t USING COVERING INDEX tc (c>?)",
+      vec![Value::Null],
+    ),
+  ] {
+    assert_eq!(query_plan(&mut conn, sql), [plan], "{sql}");
+    assert_eq!(query(&mut conn, sql), [expected], "{sql}");
+  }
+}
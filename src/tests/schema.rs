@@ -0,0 +1,217 @@
+use super::{execute_error, query};
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// The type, name, table, root page and SQL text of the schema objects.
+fn schema(conn: &mut SqliteConnection) -> Vec<Vec<Value>> {
+  query(
+    conn,
+    "SELECT type, name, tbl_name, rootpage, sql FROM sqlite_schema",
+  )
+}
+
+#[test]
+fn ok_on_create_and_drop() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let cookie = **conn.runtime().header().schema_cookie();
+  conn
+    .execute("CREATE TABLE t(a UNIQUE, b PRIMARY KEY, c, UNIQUE(a));")
+    .unwrap();
+  conn
+    .execute("INSERT INTO t VALUES (1, 2, 3), (4, 5, 3)")
+    .unwrap();
+  conn.execute("CREATE INDEX tc ON t(c DESC)").unwrap();
+  assert_eq!(**conn.runtime().header().schema_cookie(), cookie + 2);
+  assert_eq!(
+    schema(&mut conn),
+    vec![
+      vec![
+        "table".into(),
+        "t".into(),
+        "t".into(),
+        2.into(),
+        "CREATE TABLE t(a UNIQUE, b PRIMARY KEY, c, UNIQUE(a))".into()
+      ],
+      vec![
+        "index".into(),
+        "sqlite_autoindex_t_1".into(),
+        "t".into(),
+        3.into(),
+        Value::Null
+      ],
+      vec![
+        "index".into(),
+        "sqlite_autoindex_t_2".into(),
+        "t".into(),
+        4.into(),
+        Value::Null
+      ],
+      vec![
+        "index".into(),
+        "tc".into(),
+        "t".into(),
+        5.into(),
+        "CREATE INDEX tc ON t(c DESC)".into()
+      ],
+    ]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT a FROM t WHERE c = 3 ORDER BY c, a DESC"),
+    vec![vec![4.into()], vec![1.into()]]
+  );
+  assert_eq!(
+    execute_error(&mut conn, "CREATE UNIQUE INDEX tu ON t(c)"),
+    "UNIQUE constraint failed: t.c"
+  );
+
+  conn
+    .execute(
+      "CREATE TABLE s AS SELECT a, b AS a, c + 1, CAST(c AS REAL) FROM t",
+    )
+    .unwrap();
+  assert_eq!(
+    query(&mut conn, "SELECT sql FROM sqlite_schema WHERE name = 's'"),
+    vec![vec![
+      "CREATE TABLE s(\n  a,\n  \"a:1\",\n  \"c + 1\",\n  \"CAST(c AS REAL)\" \
+       REAL\n)"
+        .into()
+    ]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT * FROM s"),
+    vec![
+      vec![1.into(), 2.into(), 4.into(), 3.0.into()],
+      vec![4.into(), 5.into(), 4.into(), 3.0.into()],
+    ]
+  );
+
+  conn.execute("DROP TABLE t").unwrap();
+  conn.execute("DROP TABLE IF EXISTS t").unwrap();
+  assert_eq!(
+    query(&mut conn, "SELECT name, rootpage FROM sqlite_schema"),
+    vec![vec!["s".into(), 6.into()]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT count(*) FROM s"),
+    vec![vec![2.into()]]
+  );
+}
+
+#[test]
+fn ok_on_alter_table() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  conn
+    .execute(
+      "CREATE TABLE t(id INTEGER PRIMARY KEY AUTOINCREMENT, a UNIQUE, b, \
+       CHECK (b > a))",
+    )
+    .unwrap();
+  conn
+    .execute("CREATE TABLE p(x REFERENCES t(a), y, z)")
+    .unwrap();
+  conn.execute("CREATE INDEX tb ON t(b) WHERE a > 0").unwrap();
+  conn
+    .execute("INSERT INTO t(a, b) VALUES (1, 2), (3, 4)")
+    .unwrap();
+
+  conn.execute("ALTER TABLE t RENAME TO u").unwrap();
+  conn
+    .execute("ALTER TABLE u RENAME COLUMN a TO \"a a\"")
+    .unwrap();
+  conn
+    .execute("ALTER TABLE u ADD COLUMN c TEXT DEFAULT 5")
+    .unwrap();
+  assert_eq!(
+    query(&mut conn, "SELECT name, tbl_name, sql FROM sqlite_schema"),
+    vec![
+      vec![
+        "u".into(),
+        "u".into(),
+        "CREATE TABLE \"u\"(id INTEGER PRIMARY KEY AUTOINCREMENT, \
+         \"a a\" UNIQUE, b, c TEXT DEFAULT 5, CHECK (b > \"a a\"))"
+          .into()
+      ],
+      vec!["sqlite_autoindex_u_1".into(), "u".into(), Value::Null],
+      vec![
+        "sqlite_sequence".into(),
+        "sqlite_sequence".into(),
+        "CREATE TABLE sqlite_sequence(name,seq)".into()
+      ],
+      vec![
+        "p".into(),
+        "p".into(),
+        "CREATE TABLE p(x REFERENCES \"u\"(\"a a\"), y, z)".into()
+      ],
+      vec![
+        "tb".into(),
+        "u".into(),
+        "CREATE INDEX tb ON \"u\"(b) WHERE \"a a\" > 0".into()
+      ],
+    ]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT * FROM sqlite_sequence"),
+    vec![vec!["u".into(), 2.into()]]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT c FROM u"),
+    vec![vec!["5".into()], vec!["5".into()]]
+  );
+
+  conn.execute("INSERT INTO p VALUES (1, 2, 3)").unwrap();
+  conn.execute("ALTER TABLE p DROP COLUMN y").unwrap();
+  conn.execute("ALTER TABLE p DROP COLUMN z").unwrap();
+  assert_eq!(
+    query(&mut conn, "SELECT sql FROM sqlite_schema WHERE name = 'p'"),
+    vec![vec!["CREATE TABLE p(x REFERENCES \"u\"(\"a a\"))".into()]]
+  );
+  assert_eq!(query(&mut conn, "SELECT * FROM p"), vec![vec![1.into()]]);
+}
+
+#[test]
+fn ok_on_schema_errors() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  conn.execute("CREATE TABLE t(a PRIMARY KEY, b, c)").unwrap();
+  conn.execute("CREATE INDEX tc ON t(c)").unwrap();
+  conn.execute("INSERT INTO t VALUES (1, 2, 3)").unwrap();
+
+  let errors = [
+    ("CREATE TABLE t(x)", "table t already exists"),
+    ("CREATE TABLE tc(x)", "there is already an index named tc"),
+    ("CREATE TABLE sqlite_t(x)", "object name reserved for internal use: sqlite_t"),
+    ("CREATE TABLE u(x, X)", "duplicate column name: X"),
+    ("CREATE TABLE u(x) WITHOUT ROWID", "PRIMARY KEY missing on table u"),
+    (
+      "CREATE TABLE u(x INT PRIMARY KEY AUTOINCREMENT)",
+      "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY",
+    ),
+    ("CREATE TABLE u(x CHECK (x IN (SELECT 1)))", "subqueries prohibited in CHECK constraints"),
+    ("CREATE TABLE u(x DEFAULT (x))", "default value of column [x] is not constant"),
+    ("CREATE INDEX tc ON t(b)", "index tc already exists"),
+    ("CREATE INDEX i ON sqlite_schema(name)", "table sqlite_master may not be indexed"),
+    ("DROP INDEX sqlite_autoindex_t_1", "index associated with UNIQUE or PRIMARY KEY constraint cannot be dropped"),
+    ("DROP VIEW t", "use DROP TABLE to delete table t"),
+    ("ALTER TABLE t RENAME TO tc", "there is already another table or index with this name: tc"),
+    ("ALTER TABLE t RENAME COLUMN b TO c", "error in table t after rename: duplicate column name: c"),
+    ("ALTER TABLE t ADD COLUMN d UNIQUE", "Cannot add a UNIQUE column"),
+    ("ALTER TABLE t ADD COLUMN d NOT NULL", "Cannot add a NOT NULL column with default value NULL"),
+    ("ALTER TABLE t ADD COLUMN d DEFAULT (1 + 2)", "Cannot add a column with non-constant default"),
+    ("ALTER TABLE t DROP COLUMN a", "cannot drop PRIMARY KEY column: \"a\""),
+    ("ALTER TABLE t DROP COLUMN c", "error in index tc after drop column: no such column: c"),
+    ("ALTER TABLE sqlite_schema ADD COLUMN x", "table sqlite_master may not be altered"),
+  ];
+  for (sql, error) in errors {
+    assert_eq!(execute_error(&mut conn, sql), error, "{sql}");
+  }
+  // Failed changes leave the schema as it was.
+  assert_eq!(
+    query(
+      &mut conn,
+      "SELECT name, sql FROM sqlite_schema WHERE name = 't'"
+    ),
+    vec![vec![
+      "t".into(),
+      "CREATE TABLE t(a PRIMARY KEY, b, c)".into()
+    ]]
+  );
+}
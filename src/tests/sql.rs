@@ -0,0 +1,462 @@
+use crate::result::SqliteError;
+use crate::sql::ast::{
+  BinaryOperator, ColumnConstraintKind, CompoundOperator, CreateTableBody,
+  Expr, ExprKind, FrameBound, FrameUnit, FunctionArguments, InsertSource,
+  JoinConstraint, JoinKind, Literal, NullsOrder, Over, SelectCore, SortOrder,
+  StatementKind, TableConstraintKind, TableOrSubquery, TriggerEvent,
+  TriggerTime, UnaryOperator, UpsertAction,
+};
+use crate::sql::{parse, tokenize, Parser, TokenKind};
+
+fn syntax_error(sql: &str) -> (String, usize, usize) {
+  match parse(sql) {
+    Err(SqliteError::Syntax(error)) => {
+      (error.message().into(), error.line(), error.column())
+    }
+    other => panic!("expected a syntax error for {sql:?}, got {other:?}"),
+  }
+}
+
+fn single_expr(sql: &str) -> Expr {
+  let statement = parse(&format!("SELECT {sql}")).unwrap().remove(0);
+  let StatementKind::Select(select) = statement.kind else {
+    panic!("not a select");
+  };
+  let SelectCore::Select(core) = select.body.first else {
+    panic!("not a simple select");
+  };
+  match core.columns.into_iter().next() {
+    Some(crate::sql::ast::ResultColumn::Expr { expr, .. }) => expr,
+    other => panic!("unexpected result column {other:?}"),
+  }
+}
+
+#[test]
+fn ok_on_tokenizer() {
+  let sql = "SELECT 'it''s', \"a \"\"b\"\"\", [c d], `e`, x'0aFF', 1.5e3, \
+             .5, 0x1F, ?, ?12, :name, @at, $tcl::var(x) -- comment\n\
+             /* block */ a->>'$.b' <> 2";
+  let kinds: Vec<TokenKind> = tokenize(sql)
+    .unwrap()
+    .into_iter()
+    .map(|token| token.kind)
+    .collect();
+  let expected = vec![
+    TokenKind::Word("SELECT".into()),
+    TokenKind::String("it's".into()),
+    TokenKind::Comma,
+    TokenKind::QuotedIdentifier("a \"b\"".into()),
+    TokenKind::Comma,
+    TokenKind::QuotedIdentifier("c d".into()),
+    TokenKind::Comma,
+    TokenKind::QuotedIdentifier("e".into()),
+    TokenKind::Comma,
+    TokenKind::Blob(vec![0x0a, 0xff]),
+    TokenKind::Comma,
+    TokenKind::Number("1.5e3".into()),
+    TokenKind::Comma,
+    TokenKind::Number(".5".into()),
+    TokenKind::Comma,
+    TokenKind::Number("0x1F".into()),
+    TokenKind::Comma,
+    TokenKind::Variable("?".into()),
+    TokenKind::Comma,
+    TokenKind::Variable("?12".into()),
+    TokenKind::Comma,
+    TokenKind::Variable(":name".into()),
+    TokenKind::Comma,
+    TokenKind::Variable("@at".into()),
+    TokenKind::Comma,
+    TokenKind::Variable("$tcl::var(x)".into()),
+    TokenKind::Word("a".into()),
+    TokenKind::LongArrow,
+    TokenKind::String("$.b".into()),
+    TokenKind::NotEq,
+    TokenKind::Number("2".into()),
+    TokenKind::Eof,
+  ];
+  assert_eq!(kinds, expected);
+
+  let tokens = tokenize("SELECT  [x]").unwrap();
+  assert_eq!(tokens[1].span.text("SELECT  [x]"), "[x]");
+
+  for (sql, token) in [
+    ("SELECT 'open", "'open"),
+    ("SELECT x'abc'", "x'abc'"),
+    ("SELECT 12abc", "12abc"),
+    ("SELECT a ! b", "!"),
+    ("SELECT :", ":"),
+  ] {
+    let (message, _, _) = syntax_error(sql);
+    assert_eq!(message, format!("unrecognized token: \"{token}\""));
+  }
+}
+
+#[test]
+fn ok_on_expression_precedence() {
+  // 1 + (2 * 3) = 7 AND (NOT (x IS NULL))
+  let expr = single_expr("1 + 2 * 3 = 7 AND NOT x IS NULL");
+  let ExprKind::Binary {
+    left,
+    operator: BinaryOperator::And,
+    right,
+  } = expr.kind
+  else {
+    panic!("expected AND");
+  };
+  let ExprKind::Binary {
+    left: sum,
+    operator: BinaryOperator::Eq,
+    ..
+  } = left.kind
+  else {
+    panic!("expected =");
+  };
+  assert!(matches!(
+    sum.kind,
+    ExprKind::Binary {
+      operator: BinaryOperator::Add,
+      ..
+    }
+  ));
+  assert!(matches!(
+    right.kind,
+    ExprKind::Unary {
+      operator: UnaryOperator::Not,
+      ..
+    }
+  ));
+
+  assert_eq!(
+    single_expr("-9223372036854775808").kind,
+    ExprKind::Literal(Literal::Integer(i64::MIN))
+  );
+  assert_eq!(
+    single_expr("9223372036854775808").kind,
+    ExprKind::Literal(Literal::Real(9.223372036854776e18))
+  );
+  assert_eq!(
+    single_expr("0xFFFFFFFFFFFFFFFF").kind,
+    ExprKind::Literal(Literal::Integer(-1))
+  );
+
+  let expr =
+    single_expr("a NOT LIKE 'x%' ESCAPE '\\' OR b NOT BETWEEN 1 AND 2");
+  let ExprKind::Binary { left, right, .. } = expr.kind else {
+    panic!("expected OR");
+  };
+  assert!(matches!(
+    left.kind,
+    ExprKind::Like {
+      not: true,
+      escape: Some(_),
+      ..
+    }
+  ));
+  assert!(matches!(right.kind, ExprKind::Between { not: true, .. }));
+  assert!(matches!(
+    single_expr("a IS NOT DISTINCT FROM b").kind,
+    ExprKind::Is { not: false, .. }
+  ));
+  assert!(matches!(
+    single_expr("(a, b) IN (SELECT 1, 2)").kind,
+    ExprKind::In { not: false, .. }
+  ));
+  let expr = single_expr("CAST(x AS VARCHAR(10)) COLLATE nocase");
+  let ExprKind::Collate { expr, collation } = expr.kind else {
+    panic!("expected COLLATE");
+  };
+  assert!(collation.is("NOCASE"));
+  let ExprKind::Cast { type_name, .. } = expr.kind else {
+    panic!("expected CAST");
+  };
+  assert_eq!(type_name.name, "VARCHAR");
+  assert_eq!(type_name.arguments, vec!["10".to_owned()]);
+
+  // Parameters are numbered like SQLite does.
+  let mut parser = Parser::new("SELECT ?, :a, ?5, ?, :a, @b").unwrap();
+  parser.next_statement().unwrap();
+  assert_eq!(parser.parameter_count(), 7);
+}
+
+#[test]
+fn ok_on_select_statements() {
+  let sql = "WITH RECURSIVE tree(id, parent) AS NOT MATERIALIZED (\n\
+               SELECT id, parent FROM nodes WHERE parent IS NULL\n\
+               UNION ALL\n\
+               SELECT n.id, n.parent FROM nodes AS n JOIN tree ON n.parent = \
+             tree.id\n\
+             )\n\
+             SELECT DISTINCT t.*, count(*) FILTER (WHERE x > 0) AS c,\n\
+               sum(v) OVER (PARTITION BY k ORDER BY ts ROWS BETWEEN 2 \
+             PRECEDING AND CURRENT ROW) s,\n\
+               rank() OVER w\n\
+             FROM tree t LEFT OUTER JOIN main.other o USING (id)\n\
+               NATURAL CROSS JOIN (SELECT 1 AS one) sub, json_each(t.data)\n\
+             WHERE t.id IN (1, 2, 3) GROUP BY 1 HAVING count(*) > 1\n\
+             WINDOW w AS (ORDER BY id)\n\
+             ORDER BY c DESC NULLS LAST, 2 LIMIT 10 OFFSET 5";
+  let statements = parse(sql).unwrap();
+  assert_eq!(statements.len(), 1);
+  assert_eq!(statements[0].span.text(sql), sql);
+  let StatementKind::Select(select) = &statements[0].kind else {
+    panic!("not a select");
+  };
+  let with = select.with.as_ref().unwrap();
+  assert!(with.recursive);
+  assert_eq!(with.tables[0].materialized, Some(false));
+  assert_eq!(
+    with.tables[0].select.body.compounds[0].0,
+    CompoundOperator::UnionAll
+  );
+  assert_eq!(select.order_by[0].order, SortOrder::Desc);
+  assert_eq!(select.order_by[0].nulls, Some(NullsOrder::Last));
+  assert!(select.limit.as_ref().unwrap().offset.is_some());
+
+  let SelectCore::Select(core) = &select.body.first else {
+    panic!("not a simple select");
+  };
+  assert!(core.distinct);
+  assert_eq!(core.columns.len(), 4);
+  assert_eq!(core.windows[0].name.value, "w");
+  let from = core.from.as_ref().unwrap();
+  let kinds: Vec<(JoinKind, bool)> = from
+    .joins
+    .iter()
+    .map(|join| (join.kind, join.natural))
+    .collect();
+  assert_eq!(
+    kinds,
+    vec![
+      (JoinKind::Left, false),
+      (JoinKind::Cross, true),
+      (JoinKind::Comma, false)
+    ]
+  );
+  assert!(matches!(
+    from.joins[0].constraint,
+    Some(JoinConstraint::Using(_))
+  ));
+  assert!(matches!(
+    from.joins[1].table,
+    TableOrSubquery::Subquery { .. }
+  ));
+  assert!(matches!(
+    from.joins[2].table,
+    TableOrSubquery::TableFunction { .. }
+  ));
+
+  let crate::sql::ast::ResultColumn::Expr { expr, alias } = &core.columns[2]
+  else {
+    panic!("not an expression");
+  };
+  assert_eq!(alias.as_ref().unwrap().value, "s");
+  let ExprKind::Function(call) = &expr.kind else {
+    panic!("not a function");
+  };
+  let Some(Over::Window(window)) = &call.over else {
+    panic!("no window");
+  };
+  let frame = window.frame.as_ref().unwrap();
+  assert_eq!(frame.unit, FrameUnit::Rows);
+  assert!(matches!(frame.start, FrameBound::Preceding(_)));
+  assert_eq!(frame.end, FrameBound::CurrentRow);
+  let text = expr.span.text(sql);
+  assert!(text.starts_with("sum(v)") && text.ends_with("CURRENT ROW)"));
+
+  let ExprKind::Function(count) = &(match &core.columns[1] {
+    crate::sql::ast::ResultColumn::Expr { expr, .. } => expr,
+    _ => panic!("not an expression"),
+  })
+  .kind
+  else {
+    panic!("not a function");
+  };
+  assert_eq!(count.arguments, FunctionArguments::Star);
+  assert!(count.filter.is_some());
+
+  let statements = parse("VALUES (1, 'a'), (2, 'b') UNION SELECT 3, 'c'");
+  assert!(statements.is_ok());
+}
+
+#[test]
+fn ok_on_data_statements() {
+  let sql = "INSERT OR IGNORE INTO t (a, b) VALUES (1, 2), (3, 4)\n\
+               ON CONFLICT (a) WHERE a > 0 DO UPDATE SET b = excluded.b\n\
+               ON CONFLICT DO NOTHING RETURNING *;\n\
+             REPLACE INTO t DEFAULT VALUES;\n\
+             WITH x AS (SELECT 1) UPDATE OR FAIL t AS u SET (a, b) = (1, 2), \
+             c = c + 1 FROM x WHERE u.a = x.a RETURNING a AS r;\n\
+             DELETE FROM main.t INDEXED BY t_a WHERE a = ? LIMIT 1";
+  let statements = parse(sql).unwrap();
+  assert_eq!(statements.len(), 4);
+  let StatementKind::Insert(insert) = &statements[0].kind else {
+    panic!("not an insert");
+  };
+  assert_eq!(insert.columns.len(), 2);
+  assert_eq!(insert.upserts.len(), 2);
+  assert!(matches!(
+    insert.upserts[0].action,
+    UpsertAction::Update { .. }
+  ));
+  assert_eq!(insert.upserts[1].action, UpsertAction::Nothing);
+  let StatementKind::Insert(replace) = &statements[1].kind else {
+    panic!("not an insert");
+  };
+  assert_eq!(replace.source, InsertSource::DefaultValues);
+  let StatementKind::Update(update) = &statements[2].kind else {
+    panic!("not an update");
+  };
+  assert!(update.with.is_some());
+  assert_eq!(update.assignments[0].columns.len(), 2);
+  assert!(update.from.is_some());
+  let StatementKind::Delete(delete) = &statements[3].kind else {
+    panic!("not a delete");
+  };
+  assert!(delete.indexed_by.is_some());
+  assert_eq!(
+    statements[3].span.text(sql),
+    &sql[sql.rfind("DELETE").unwrap()..]
+  );
+}
+
+#[test]
+fn ok_on_schema_statements() {
+  let sql = "CREATE TABLE IF NOT EXISTS main.t (\n\
+               id INTEGER PRIMARY KEY DESC AUTOINCREMENT,\n\
+               name VARCHAR(20) NOT NULL DEFAULT 'x' COLLATE NOCASE,\n\
+               total UNSIGNED BIG INT DEFAULT -1 CHECK (total > -2),\n\
+               parent INT REFERENCES t(id) ON DELETE CASCADE \
+             DEFERRABLE INITIALLY DEFERRED,\n\
+               doubled INT GENERATED ALWAYS AS (total * 2) STORED,\n\
+               CONSTRAINT u UNIQUE (name, total) ON CONFLICT REPLACE\n\
+               FOREIGN KEY (parent) REFERENCES t\n\
+             ) STRICT, WITHOUT ROWID;\n\
+             CREATE UNIQUE INDEX i ON t (lower(name) DESC, total) WHERE id > 0;\n\
+             CREATE TEMP VIEW v (a) AS SELECT 1;\n\
+             CREATE TRIGGER tr AFTER UPDATE OF name ON t FOR EACH ROW \
+             WHEN new.name <> old.name BEGIN\n\
+               INSERT INTO log VALUES (new.id);\n\
+               SELECT RAISE(ABORT, 'no');\n\
+             END;\n\
+             CREATE VIRTUAL TABLE f USING fts5(body, tokenize = 'porter');\n\
+             ALTER TABLE t RENAME COLUMN name TO label;\n\
+             DROP INDEX IF EXISTS i;\n\
+             PRAGMA main.journal_mode = DELETE;\n\
+             BEGIN IMMEDIATE; SAVEPOINT s; ROLLBACK TO s; COMMIT;\n\
+             EXPLAIN QUERY PLAN SELECT 1";
+  let statements = parse(sql).unwrap();
+  assert_eq!(statements.len(), 13);
+
+  let StatementKind::CreateTable(table) = &statements[0].kind else {
+    panic!("not a create table");
+  };
+  assert!(table.if_not_exists);
+  let CreateTableBody::Columns {
+    columns,
+    constraints,
+    options,
+  } = &table.body
+  else {
+    panic!("no columns");
+  };
+  assert!(options.strict && options.without_rowid);
+  assert_eq!(columns.len(), 5);
+  assert_eq!(
+    columns[2].type_name.as_ref().unwrap().name,
+    "UNSIGNED BIG INT"
+  );
+  assert_eq!(columns[4].type_name.as_ref().unwrap().name, "INT");
+  assert!(matches!(
+    columns[0].constraints[0].kind,
+    ColumnConstraintKind::PrimaryKey {
+      order: Some(SortOrder::Desc),
+      autoincrement: true,
+      ..
+    }
+  ));
+  let ColumnConstraintKind::ForeignKey(foreign_key) =
+    &columns[3].constraints[0].kind
+  else {
+    panic!("no foreign key");
+  };
+  assert!(foreign_key.deferred);
+  assert!(matches!(
+    columns[4].constraints[0].kind,
+    ColumnConstraintKind::Generated { stored: true, .. }
+  ));
+  assert_eq!(constraints.len(), 2);
+  assert_eq!(constraints[0].name.as_ref().unwrap().value, "u");
+  assert!(matches!(
+    constraints[1].kind,
+    TableConstraintKind::ForeignKey { .. }
+  ));
+
+  let StatementKind::CreateTrigger(trigger) = &statements[3].kind else {
+    panic!("not a trigger");
+  };
+  assert_eq!(trigger.time, TriggerTime::After);
+  assert!(
+    matches!(&trigger.event, TriggerEvent::Update(columns) if columns.len() == 1)
+  );
+  assert_eq!(trigger.body.len(), 2);
+  let StatementKind::CreateVirtualTable(virtual_table) = &statements[4].kind
+  else {
+    panic!("not a virtual table");
+  };
+  assert_eq!(
+    virtual_table.arguments,
+    vec!["body".to_owned(), "tokenize = 'porter'".into()]
+  );
+  let StatementKind::Pragma(pragma) = &statements[7].kind else {
+    panic!("not a pragma");
+  };
+  assert!(matches!(
+    &pragma.value.as_ref().unwrap().kind,
+    ExprKind::Column { column, .. } if column.is("delete")
+  ));
+  assert!(matches!(
+    statements[12].kind,
+    StatementKind::Explain {
+      query_plan: true,
+      ..
+    }
+  ));
+}
+
+#[test]
+fn ok_on_syntax_errors() {
+  assert_eq!(
+    syntax_error("SELECT 1;\nSELECT * FROM\n  WHERE x"),
+    ("near \"WHERE\": syntax error".into(), 3, 3)
+  );
+  assert_eq!(
+    syntax_error("SELECT (1"),
+    ("incomplete input".into(), 1, 10)
+  );
+  assert_eq!(
+    syntax_error("SELECT 1 FROM;"),
+    ("near \";\": syntax error".into(), 1, 14)
+  );
+  assert_eq!(
+    syntax_error("SELECT (1;\n"),
+    ("near \";\": syntax error".into(), 1, 10)
+  );
+  assert_eq!(
+    syntax_error("SELECT 1 FROM"),
+    ("incomplete input".into(), 1, 14)
+  );
+  assert_eq!(
+    syntax_error("SELECT 1 2"),
+    ("near \"2\": syntax error".into(), 1, 10)
+  );
+  assert_eq!(
+    syntax_error("CREATE TABLE t(a) WITHOUT rowids").0,
+    "unknown table option: rowids"
+  );
+  assert_eq!(
+    syntax_error("SELECT * FROM a LEFT b").0,
+    "unknown join type: LEFT"
+  );
+  assert!(parse("  ;; -- nothing\n").unwrap().is_empty());
+}
@@ -0,0 +1,190 @@
+use crate::executor::Statement;
+use crate::result::SqliteError;
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+fn error<T: core::fmt::Debug>(result: Result<T, SqliteError>) -> String {
+  match result {
+    Err(SqliteError::Custom(error)) => error,
+    other => panic!("expected an error, got {other:?}"),
+  }
+}
+
+#[test]
+fn ok_on_parameters() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  let stmt = conn
+    .prepare("SELECT ?, :a, ?5, ?, :a, @b, $c, ?2, ?7")
+    .unwrap();
+  assert_eq!(stmt.parameter_count(), 8);
+  let names = (1..=9)
+    .map(|index| stmt.parameter_name(index))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    names,
+    [
+      None,
+      Some(":a"),
+      None,
+      None,
+      Some("?5"),
+      None,
+      Some("@b"),
+      Some("$c"),
+      None
+    ]
+  );
+  assert_eq!(stmt.parameter_name(0), None);
+  assert_eq!(stmt.parameter_index(":a"), Some(2));
+  assert_eq!(stmt.parameter_index("$c"), Some(8));
+  assert_eq!(stmt.parameter_index("?5"), Some(5));
+  assert_eq!(stmt.parameter_index("a"), None);
+
+  let mut stmt = conn.prepare("SELECT ?1 + ?1, :x || $y, ?3").unwrap();
+  stmt.bind(1, 20i64).unwrap();
+  stmt.bind_by_name(":x", "a").unwrap();
+  stmt.bind_by_name("$y", "b").unwrap();
+  let row = stmt.step(&mut conn).unwrap().unwrap();
+  // `?3` is the third parameter, `$y`.
+  assert_eq!(row.values(), [40.into(), "ab".into(), "b".into()]);
+  assert_eq!(row.column_names(), ["?1 + ?1", ":x || $y", "?3"]);
+  assert!(stmt.step(&mut conn).unwrap().is_none());
+  assert!(stmt.step(&mut conn).unwrap().is_none());
+
+  // Values are bound between runs only.
+  assert_eq!(
+    error(stmt.bind(1, 1i64)),
+    "bad parameter or other API misuse"
+  );
+  stmt.reset();
+  assert_eq!(error(stmt.bind(4, 1i64)), "column index out of range");
+  assert_eq!(error(stmt.bind(0, 1i64)), "column index out of range");
+  assert_eq!(
+    error(stmt.bind_by_name(":z", 1i64)),
+    "column index out of range"
+  );
+  stmt.bind(3, 1.5).unwrap();
+  let rows = stmt
+    .query(&mut conn)
+    .unwrap()
+    .map(|row| row.unwrap().into_values())
+    .collect::<Vec<_>>();
+  assert_eq!(rows, [vec![40.into(), "a1.5".into(), 1.5.into()]]);
+  stmt.clear_bindings();
+  let row = stmt.query(&mut conn).unwrap().next().unwrap().unwrap();
+  assert_eq!(row.values(), [Value::Null, Value::Null, Value::Null]);
+}
+
+#[test]
+fn ok_on_prepared_changes() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  conn
+    .execute("CREATE TABLE t(id INTEGER PRIMARY KEY, name)")
+    .unwrap();
+  let mut insert = conn.prepare("INSERT INTO t(name) VALUES (?)").unwrap();
+  // A value is only ever data, never SQL text.
+  for name in ["a", "b'); DROP TABLE t; --"] {
+    insert.bind(1, name).unwrap();
+    assert_eq!(insert.execute(&mut conn).unwrap(), 1);
+  }
+  assert_eq!(conn.last_insert_rowid(), 2);
+
+  let mut select = conn
+    .prepare("SELECT name FROM t WHERE id >= :min ORDER BY id LIMIT ?")
+    .unwrap();
+  select.bind_by_name(":min", 1i64).unwrap();
+  select.bind(2, 5i64).unwrap();
+  let mut names = vec![];
+  while let Some(row) = select.step(&mut conn).unwrap() {
+    names.extend(row.into_values());
+  }
+  assert_eq!(names, ["a".into(), "b'); DROP TABLE t; --".into()]);
+  select.reset();
+  select.bind(2, 1i64).unwrap();
+  let row = select.step(&mut conn).unwrap().unwrap();
+  assert_eq!(row.values(), ["a".into()]);
+  // Resetting part way through starts over.
+  select.reset();
+  let row = select.step(&mut conn).unwrap().unwrap();
+  assert_eq!(row.values(), ["a".into()]);
+
+  let mut update = conn
+    .prepare("UPDATE t SET name = ?2 WHERE id = ?1")
+    .unwrap();
+  update.bind(1, 1i64).unwrap();
+  update.bind(2, "c").unwrap();
+  assert!(update.step(&mut conn).unwrap().is_none());
+  assert_eq!(conn.changes(), 1);
+
+  // Statements follow the changes made to the schema since they were
+  // prepared.
+  conn
+    .execute("ALTER TABLE t RENAME COLUMN name TO label")
+    .unwrap();
+  assert_eq!(error(update.execute(&mut conn)), "no such column: name");
+  conn.execute("ALTER TABLE t ADD COLUMN name").unwrap();
+  assert_eq!(update.execute(&mut conn).unwrap(), 1);
+
+  let mut create = conn.prepare("CREATE TABLE u AS SELECT ? AS x").unwrap();
+  create.bind(1, "y").unwrap();
+  create.execute(&mut conn).unwrap();
+  let row = conn
+    .query("SELECT x FROM u")
+    .unwrap()
+    .next()
+    .unwrap()
+    .unwrap();
+  assert_eq!(row.values(), ["y".into()]);
+}
+
+#[test]
+fn ok_on_statement_cache() {
+  let mut conn = SqliteConnection::open(":memory:").unwrap();
+  assert_eq!(conn.statement_cache_capacity(), 16);
+  conn.set_statement_cache_capacity(2);
+  // Statements parsed from the same SQL text share it.
+  let cached = |conn: &mut SqliteConnection, stmt: &Statement| {
+    core::ptr::eq(conn.prepare(stmt.sql()).unwrap().sql(), stmt.sql())
+  };
+  let one = conn.prepare("SELECT 1").unwrap();
+  let two = conn.prepare("SELECT 2").unwrap();
+  assert!(cached(&mut conn, &one));
+  // The statement used least recently makes room for the new one.
+  conn.prepare("SELECT 3").unwrap();
+  assert!(cached(&mut conn, &one));
+  assert!(!cached(&mut conn, &two));
+  conn.set_statement_cache_capacity(0);
+  assert!(!cached(&mut conn, &one));
+  assert!(!cached(&mut conn, &one));
+
+  assert_eq!(
+    error(conn.prepare("SELECT 1; SELECT 2")),
+    "Only one statement can be prepared at a time"
+  );
+  assert_eq!(error(conn.prepare("")), "No statement to run");
+}
+
+#[test]
+fn ok_on_writes_after_reset_or_drop() {
+  let path = std::env::temp_dir().join("sqlite-rs-statements.db");
+  let _ = std::fs::remove_file(&path);
+  let uri = format!("sqlite://{}?mode=rwc", path.display());
+  let mut reader = SqliteConnection::open(&uri).unwrap();
+  reader.execute("CREATE TABLE t(x)").unwrap();
+  reader.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+  let mut writer = SqliteConnection::open(&uri).unwrap();
+
+  // A statement left between rows keeps no other connection from writing
+  // once it is reset, or dropped.
+  let mut stmt = reader.prepare("SELECT x FROM t").unwrap();
+  assert!(stmt.step(&mut reader).unwrap().is_some());
+  stmt.reset();
+  writer.execute("INSERT INTO t VALUES (3)").unwrap();
+  assert!(stmt.step(&mut reader).unwrap().is_some());
+  drop(stmt);
+  writer.execute("INSERT INTO t VALUES (4)").unwrap();
+  let count = reader.query("SELECT count(*) FROM t").unwrap().next();
+  assert_eq!(count.unwrap().unwrap().values(), [4.into()]);
+  drop(writer);
+  let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,91 @@
+use crate::runtime::{Collation, TableDefinition, Value};
+use crate::SqliteConnection;
+
+#[test]
+fn ok_on_table_definitions() {
+  let definition = TableDefinition::parse(
+    "CREATE TABLE IF NOT EXISTS main.\"my table\" (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      name VARCHAR(20) NOT NULL COLLATE NOCASE DEFAULT ('x'),
+      -- a comment
+      [weight] DOUBLE PRECISION CHECK (weight > 0),
+      UNIQUE (name)
+    )",
+  )
+  .unwrap();
+  assert_eq!(definition.name(), "my table");
+  let columns = definition.columns();
+  assert_eq!(columns.len(), 3);
+  assert_eq!(columns[1].declared_type(), Some("VARCHAR(20)"));
+  assert_eq!(columns[1].collation(), &Collation::NoCase);
+  assert!(columns[1].is_not_null());
+  assert_eq!(columns[2].name(), "weight");
+  assert_eq!(columns[2].declared_type(), Some("DOUBLE PRECISION"));
+  assert_eq!(definition.rowid_alias(), Some(0));
+  assert!(!definition.is_without_rowid());
+
+  let definition = TableDefinition::parse(
+    "CREATE TABLE t(a, b TEXT, c INT, PRIMARY KEY(c, a DESC)) WITHOUT ROWID",
+  )
+  .unwrap();
+  assert!(definition.is_without_rowid());
+  assert_eq!(definition.rowid_alias(), None);
+  assert_eq!(definition.storage_order(), vec![2, 0, 1]);
+  assert_eq!(
+    definition.row_from_record(vec![3.into(), 1.into(), "b".into()], None),
+    vec![
+      Value::Integer(1),
+      Value::Text("b".into()),
+      Value::Integer(3)
+    ]
+  );
+
+  let definition =
+    TableDefinition::parse("CREATE TABLE t(id INTEGER PRIMARY KEY DESC)")
+      .unwrap();
+  assert_eq!(definition.rowid_alias(), None);
+  assert!(TableDefinition::parse("CREATE TABLE t(a) WITHOUT ROWID").is_err());
+}
+
+#[test]
+fn ok_on_without_rowid_table() {
+  let mut conn =
+    SqliteConnection::open("sqlite://./data/without-rowid.db?mode=ro").unwrap();
+  let runtime = conn.runtime_mut();
+  let tables = runtime.tables().unwrap();
+  assert!(tables[0].is_without_rowid());
+  assert!(!tables[1].is_without_rowid());
+
+  let mut table = runtime.table("lookup").unwrap();
+  let mut btree = runtime.btree();
+  let mut rows = vec![];
+  let mut has_row = table.first(&mut btree).unwrap();
+  while has_row {
+    assert_eq!(table.rowid(&btree).unwrap(), None);
+    rows.push(table.row(&mut btree).unwrap());
+    has_row = table.next(&mut btree).unwrap();
+  }
+  assert_eq!(rows.len(), 1500);
+  // Rows come in primary key order: code descending, then version.
+  assert_eq!(rows[0][1], Value::Text("Code0500".into()));
+  assert_eq!(rows[1499][1], Value::Text("Code0000".into()));
+
+  let key = [Value::Text("CODE0100".into()), Value::Integer(2)];
+  assert!(table.seek_primary_key(&mut btree, &key).unwrap());
+  let row = table.row(&mut btree).unwrap();
+  assert!(
+    matches!(&row[0], Value::Text(label) if label.starts_with("label-302-"))
+  );
+  assert_eq!(row[2], Value::Integer(2));
+  let key = [Value::Text("Code0100".into()), Value::Integer(3)];
+  assert!(!table.seek_primary_key(&mut btree, &key).unwrap());
+
+  let mut items = runtime.table("items").unwrap();
+  let mut btree = runtime.btree();
+  assert!(items.seek_primary_key(&mut btree, &[7.into()]).unwrap());
+  assert_eq!(
+    items.row(&mut btree).unwrap(),
+    vec![Value::Integer(7), Value::Text("seven".into())]
+  );
+  assert!(!items.seek_primary_key(&mut btree, &["7".into()]).unwrap());
+}
@@ -0,0 +1,88 @@
+use super::{execute_error, open, rows};
+
+#[test]
+fn ok_on_transactions() {
+  let mut conn = open(&["CREATE TABLE t(id INTEGER PRIMARY KEY, v UNIQUE)"]);
+  for sql in ["BEGIN", "INSERT INTO t VALUES(1, 'a')", "COMMIT"] {
+    conn.execute(sql).unwrap();
+  }
+  for sql in ["BEGIN TRANSACTION", "INSERT INTO t VALUES(2, 'b')"] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(rows(&mut conn, "SELECT v FROM t"), ["a", "b"]);
+  conn.execute("ROLLBACK").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT v FROM t"), ["a"]);
+  for (sql, expected) in [
+    ("COMMIT", "cannot commit - no transaction is active"),
+    ("ROLLBACK", "cannot rollback - no transaction is active"),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  conn.execute("BEGIN").unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "BEGIN"),
+    "cannot start a transaction within a transaction"
+  );
+  conn.execute("INSERT INTO t VALUES(3, 'c')").unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t VALUES(4, 'd'), (5, 'a')"),
+    "UNIQUE constraint failed: t.v"
+  );
+  conn.execute("CREATE TABLE u(x)").unwrap();
+  conn.execute("COMMIT").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT v FROM t"), ["a", "c"]);
+  assert_eq!(rows(&mut conn, "SELECT count(*) FROM u"), ["0"]);
+  conn.execute("BEGIN").unwrap();
+  conn.execute("INSERT INTO t VALUES(6, 'e')").unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "INSERT OR ROLLBACK INTO t VALUES(7, 'a')"),
+    "UNIQUE constraint failed: t.v"
+  );
+  assert_eq!(
+    execute_error(&mut conn, "COMMIT"),
+    "cannot commit - no transaction is active"
+  );
+  assert_eq!(rows(&mut conn, "SELECT v FROM t"), ["a", "c"]);
+}
+
+#[test]
+fn ok_on_deferred_foreign_keys() {
+  let mut conn = open(&[
+    "PRAGMA foreign_keys = 1",
+    "CREATE TABLE p(id INTEGER PRIMARY KEY)",
+    "CREATE TABLE c(
+       p REFERENCES p DEFERRABLE INITIALLY DEFERRED
+     )",
+  ]);
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO c VALUES(1)"),
+    "FOREIGN KEY constraint failed"
+  );
+  for sql in [
+    "BEGIN",
+    "INSERT INTO c VALUES(1)",
+    "INSERT INTO p VALUES(1)",
+  ] {
+    conn.execute(sql).unwrap();
+  }
+  conn.execute("COMMIT").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT p FROM c"), ["1"]);
+  for sql in ["BEGIN", "INSERT INTO c VALUES(2)"] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(
+    execute_error(&mut conn, "COMMIT"),
+    "FOREIGN KEY constraint failed"
+  );
+  conn.execute("DELETE FROM c WHERE p = 2").unwrap();
+  conn.execute("COMMIT").unwrap();
+  for sql in ["BEGIN", "DELETE FROM p"] {
+    conn.execute(sql).unwrap();
+  }
+  assert_eq!(
+    execute_error(&mut conn, "COMMIT"),
+    "FOREIGN KEY constraint failed"
+  );
+  conn.execute("ROLLBACK").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT id FROM p"), ["1"]);
+}
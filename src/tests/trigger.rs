@@ -0,0 +1,191 @@
+use super::{execute_error, open, rows};
+use crate::result::{ConstraintKind, SqliteError};
+
+#[test]
+fn ok_on_views() {
+  let mut conn = open(&[
+    "CREATE TABLE t(a, b)",
+    "INSERT INTO t VALUES(1, 2), (3, 4)",
+    "CREATE VIEW v AS SELECT a, a + b AS s FROM t WHERE a > 1",
+    "CREATE VIEW w(x, y) AS SELECT * FROM v",
+  ]);
+  assert_eq!(rows(&mut conn, "SELECT * FROM v"), ["3|7"]);
+  assert_eq!(rows(&mut conn, "SELECT y FROM w WHERE x = 3"), ["7"]);
+  assert_eq!(
+    rows(&mut conn, "SELECT t.a, v.s FROM t LEFT JOIN v USING (a)"),
+    ["1|", "3|7"]
+  );
+  for (sql, expected) in [
+    ("CREATE VIEW v AS SELECT 1", "view v already exists"),
+    (
+      "INSERT INTO v VALUES(1, 2)",
+      "cannot modify v because it is a view",
+    ),
+    ("DELETE FROM w", "cannot modify w because it is a view"),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  conn.execute("CREATE VIEW u(x) AS SELECT 1, 2").unwrap();
+  assert!(matches!(
+    conn.query("SELECT * FROM u").map(|_| ()),
+    Err(SqliteError::Custom(error))
+      if error == "expected 1 columns for 'u' but got 2"
+  ));
+  conn.execute("CREATE VIEW c1 AS SELECT * FROM c2").unwrap();
+  conn.execute("CREATE VIEW c2 AS SELECT * FROM c1").unwrap();
+  assert!(matches!(
+    conn.query("SELECT * FROM c1").map(|_| ()),
+    Err(SqliteError::Custom(error)) if error == "view c1 is circularly defined"
+  ));
+}
+
+#[test]
+fn ok_on_before_and_after_triggers() {
+  let mut conn = open(&[
+    "CREATE TABLE t(a INTEGER PRIMARY KEY, b)",
+    "CREATE TABLE log(x)",
+    "CREATE TRIGGER t1 BEFORE INSERT ON t BEGIN
+       INSERT INTO log VALUES('b1 ' || new.a || ' ' || new.rowid || ' ' || new.b);
+     END",
+    "CREATE TRIGGER t2 BEFORE INSERT ON t BEGIN
+       INSERT INTO log VALUES('b2');
+     END",
+    "CREATE TRIGGER t3 AFTER UPDATE OF b ON t WHEN new.b > 10 BEGIN
+       INSERT INTO log VALUES('u ' || old.b || '>' || new.b);
+     END",
+    "CREATE TRIGGER t4 AFTER DELETE ON t BEGIN
+       INSERT INTO log VALUES('d ' || old.a);
+     END",
+  ]);
+  assert_eq!(conn.execute("INSERT INTO t(b) VALUES(5), (6)").unwrap(), 2);
+  assert_eq!(conn.total_changes(), 6);
+  conn.execute("UPDATE t SET b = b * 3 WHERE a = 1").unwrap();
+  conn.execute("UPDATE t SET a = a + 10").unwrap();
+  conn.execute("DELETE FROM t WHERE a = 12").unwrap();
+  assert_eq!(
+    rows(&mut conn, "SELECT x FROM log"),
+    ["b2", "b1 -1 -1 5", "b2", "b1 -1 -1 6", "u 5>15", "d 12"]
+  );
+  assert_eq!(rows(&mut conn, "SELECT * FROM t"), ["11|15"]);
+}
+
+#[test]
+fn ok_on_raise() {
+  let mut conn = open(&[
+    "CREATE TABLE t(a)",
+    "CREATE TRIGGER i BEFORE DELETE ON t WHEN old.a = 2 BEGIN
+       SELECT RAISE(IGNORE);
+     END",
+    "CREATE TRIGGER a BEFORE INSERT ON t WHEN new.a = 9 BEGIN
+       SELECT RAISE(ABORT, 'no nines');
+     END",
+    "CREATE TRIGGER f BEFORE INSERT ON t WHEN new.a = 7 BEGIN
+       SELECT RAISE(FAIL, 'no sevens');
+     END",
+  ]);
+  conn.execute("INSERT INTO t VALUES(1), (2), (3)").unwrap();
+  assert_eq!(conn.execute("DELETE FROM t").unwrap(), 2);
+  assert!(matches!(
+    conn.execute("INSERT INTO t VALUES(8), (9)"),
+    Err(SqliteError::Constraint { kind: ConstraintKind::Trigger, message })
+      if message == "no nines"
+  ));
+  assert_eq!(
+    execute_error(&mut conn, "INSERT INTO t VALUES(6), (7)"),
+    "no sevens"
+  );
+  assert_eq!(rows(&mut conn, "SELECT a FROM t"), ["2", "6"]);
+  assert!(matches!(
+    conn.query("SELECT RAISE(IGNORE)").map(|_| ()),
+    Err(SqliteError::Custom(error))
+      if error == "RAISE() may only be used within a trigger-program"
+  ));
+}
+
+#[test]
+fn ok_on_instead_of_triggers() {
+  let mut conn = open(&[
+    "CREATE TABLE t(a, b)",
+    "CREATE VIEW v AS SELECT a, b, a + b AS s FROM t",
+    "CREATE TRIGGER vi INSTEAD OF INSERT ON v BEGIN
+       INSERT INTO t VALUES(new.a, new.b);
+     END",
+    "CREATE TRIGGER vu INSTEAD OF UPDATE ON v BEGIN
+       UPDATE t SET b = new.b WHERE a = old.a;
+     END",
+    "CREATE TRIGGER vd INSTEAD OF DELETE ON v BEGIN
+       DELETE FROM t WHERE a = old.a;
+     END",
+  ]);
+  assert_eq!(
+    conn
+      .execute("INSERT INTO v(a, b) VALUES(1, 2), (3, 4), (5, 6)")
+      .unwrap(),
+    0
+  );
+  conn.execute("UPDATE v SET b = b * 10 WHERE a > 1").unwrap();
+  conn.execute("DELETE FROM v WHERE a = 5").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT * FROM v"), ["1|2|3", "3|40|43"]);
+}
+
+#[test]
+fn ok_on_recursive_triggers() {
+  let mut conn = open(&[
+    "CREATE TABLE r(n)",
+    "CREATE TRIGGER rec AFTER INSERT ON r WHEN new.n < 5 BEGIN
+       INSERT INTO r VALUES(new.n + 1);
+     END",
+  ]);
+  conn.execute("INSERT INTO r VALUES(1)").unwrap();
+  assert_eq!(rows(&mut conn, "SELECT group_concat(n) FROM r"), ["1,2"]);
+  conn.execute("DELETE FROM r").unwrap();
+  conn.execute("PRAGMA recursive_triggers = 1").unwrap();
+  assert_eq!(rows(&mut conn, "PRAGMA recursive_triggers"), ["1"]);
+  conn.execute("INSERT INTO r VALUES(1)").unwrap();
+  assert_eq!(
+    rows(&mut conn, "SELECT group_concat(n) FROM r"),
+    ["1,2,3,4,5"]
+  );
+}
+
+#[test]
+fn ok_on_create_trigger_errors() {
+  let mut conn = open(&[
+    "CREATE TABLE t(a)",
+    "CREATE VIEW v AS SELECT a FROM t",
+    "CREATE TRIGGER x AFTER INSERT ON t BEGIN SELECT 1; END",
+  ]);
+  for (sql, expected) in [
+    (
+      "CREATE TRIGGER y AFTER INSERT ON nope BEGIN SELECT 1; END",
+      "no such table: main.nope",
+    ),
+    (
+      "CREATE TRIGGER x AFTER DELETE ON t BEGIN SELECT 1; END",
+      "trigger x already exists",
+    ),
+    (
+      "CREATE TRIGGER y BEFORE INSERT ON v BEGIN SELECT 1; END",
+      "cannot create BEFORE trigger on view: v",
+    ),
+    (
+      "CREATE TRIGGER y INSTEAD OF INSERT ON t BEGIN SELECT 1; END",
+      "cannot create INSTEAD OF trigger on table: t",
+    ),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  conn
+    .execute("CREATE TRIGGER y AFTER DELETE ON t BEGIN SELECT new.a; END")
+    .unwrap();
+  conn.execute("INSERT INTO t VALUES(1)").unwrap();
+  assert_eq!(
+    execute_error(&mut conn, "DELETE FROM t"),
+    "no such column: new.a"
+  );
+  conn
+    .execute(
+      "CREATE TRIGGER IF NOT EXISTS x AFTER DELETE ON t BEGIN SELECT 1; END",
+    )
+    .unwrap();
+}
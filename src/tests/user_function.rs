@@ -0,0 +1,273 @@
+use super::{execute_error, query_error, rows};
+use crate::result::{SqliteError, SqliteResult};
+use crate::runtime::{to_integer, to_text, Value};
+use crate::SqliteConnection;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn open() -> SqliteConnection {
+  super::open(&[
+    "CREATE TABLE t(g TEXT, x INTEGER)",
+    "INSERT INTO t VALUES ('a', 1), ('a', 2), ('b', 3), ('b', 4), ('b', 5)",
+  ])
+}
+
+/// Multiplies integers, skipping NULLs.
+fn product(conn: &mut SqliteConnection) {
+  conn
+    .create_aggregate_function(
+      "product",
+      Some(1),
+      |product: &mut Option<i64>, args: &[Value]| {
+        if !args[0].is_null() {
+          let value = to_integer(&args[0]);
+          let product = product.get_or_insert(1);
+          *product = product
+            .checked_mul(value)
+            .ok_or_else(|| SqliteError::Custom("product overflow".into()))?;
+        }
+        Ok(())
+      },
+      |product: Option<i64>| Ok(product.map_or(Value::Null, Value::Integer)),
+    )
+    .unwrap();
+}
+
+#[test]
+fn ok_on_scalar_functions() {
+  let mut conn = open();
+  conn
+    .create_scalar_function("twice", Some(1), true, |args| {
+      Ok(match &args[0] {
+        Value::Integer(int) => Value::Integer(int * 2),
+        Value::Null => Value::Null,
+        value => Value::Text(to_text(value).unwrap_or_default().repeat(2)),
+      })
+    })
+    .unwrap();
+  conn
+    .create_scalar_function("twice", Some(2), true, |args| {
+      Ok(Value::Integer(
+        to_integer(&args[0]) * 2 + to_integer(&args[1]),
+      ))
+    })
+    .unwrap();
+  conn
+    .create_scalar_function("argc", None, true, |args| {
+      Ok(Value::Integer(args.len() as i64))
+    })
+    .unwrap();
+  conn
+    .create_scalar_function("ARGC", Some(0), true, |_| {
+      Ok(Value::Text("none".into()))
+    })
+    .unwrap();
+  // Built-in functions are overridden.
+  conn
+    .create_scalar_function("upper", Some(1), true, |args| {
+      Ok(Value::Text(format!(
+        "<{}>",
+        to_text(&args[0]).unwrap_or_default()
+      )))
+    })
+    .unwrap();
+  for (sql, expected) in [
+    ("SELECT twice(21), twice('ab'), twice(NULL)", "42|abab|"),
+    ("SELECT TWICE(1, 5), twice(twice(1))", "7|4"),
+    ("SELECT argc(), argc(1), argc(1, 2, 3)", "none|1|3"),
+    ("SELECT upper('a'), lower('B')", "<a>|b"),
+  ] {
+    assert_eq!(rows(&mut conn, sql), [expected], "{sql}");
+  }
+  assert_eq!(
+    query_error(&mut conn, "SELECT upper('a', 'b')"),
+    "wrong number of arguments to function upper()"
+  );
+  assert_eq!(
+    rows(
+      &mut conn,
+      "SELECT g, twice(x) FROM t WHERE twice(x) > 6 ORDER BY 2"
+    ),
+    ["b|8", "b|10"]
+  );
+  // The same function, replaced.
+  conn
+    .create_scalar_function("twice", Some(1), false, |_| Ok(Value::Integer(0)))
+    .unwrap();
+  assert_eq!(rows(&mut conn, "SELECT twice(21)"), ["0"]);
+}
+
+#[test]
+fn error_on_scalar_functions() {
+  let mut conn = open();
+  conn
+    .create_scalar_function("fail", Some(1), true, |args| match &args[0] {
+      Value::Integer(3) => Err(SqliteError::Custom("no threes".into())),
+      value => Ok(value.clone()),
+    })
+    .unwrap();
+  for (sql, expected) in [
+    ("SELECT fail(x) FROM t", "no threes"),
+    (
+      "SELECT fail()",
+      "wrong number of arguments to function fail()",
+    ),
+    (
+      "SELECT fail(1) OVER ()",
+      "fail() may not be used as a window function",
+    ),
+    (
+      "SELECT fail(1) FILTER (WHERE 1)",
+      "FILTER may not be used with non-aggregate fail()",
+    ),
+  ] {
+    assert_eq!(query_error(&mut conn, sql), expected, "{sql}");
+  }
+  assert_eq!(
+    rows(&mut conn, "SELECT fail(x) FROM t WHERE x < 3"),
+    ["1", "2"]
+  );
+  for (name, arity) in
+    [("", Some(1)), (&*"f".repeat(256), None), ("f", Some(128))]
+  {
+    let registered =
+      conn.create_scalar_function(name, arity, true, |_| Ok(Value::Null));
+    assert!(registered.is_err(), "{name:?} {arity:?}");
+  }
+}
+
+#[test]
+fn ok_on_aggregate_functions() {
+  let mut conn = open();
+  product(&mut conn);
+  for (sql, expected) in [
+    ("SELECT product(x) FROM t", vec!["120"]),
+    (
+      "SELECT g, product(x) FROM t GROUP BY g",
+      vec!["a|2", "b|60"],
+    ),
+    ("SELECT product(x) FROM t WHERE x > 5", vec![""]),
+    (
+      "SELECT product(DISTINCT x % 2 + 1) FILTER (WHERE x > 1) FROM t",
+      vec!["2"],
+    ),
+    (
+      "SELECT g FROM t GROUP BY g HAVING product(x) > 10",
+      vec!["b"],
+    ),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+  conn
+    .execute("INSERT INTO t VALUES ('c', 9223372036854775807), ('c', 2)")
+    .unwrap();
+  for (sql, expected) in [
+    ("SELECT g, product(x) FROM t GROUP BY g", "product overflow"),
+    (
+      "SELECT product(x) OVER () FROM t",
+      "product() may not be used as a window function",
+    ),
+    (
+      "SELECT product(x, 1) FROM t",
+      "wrong number of arguments to function product()",
+    ),
+    (
+      "SELECT x FROM t WHERE product(x) > 1",
+      "misuse of aggregate function product()",
+    ),
+  ] {
+    assert_eq!(query_error(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_window_functions() {
+  let mut conn = open();
+  let inverses = Arc::new(AtomicUsize::new(0));
+  let counted = inverses.clone();
+  conn
+    .create_window_function(
+      "sumint",
+      Some(1),
+      |sum: &mut i64, args: &[Value]| {
+        *sum += to_integer(&args[0]);
+        Ok(())
+      },
+      move |sum: &mut i64, args: &[Value]| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        *sum -= to_integer(&args[0]);
+        Ok(())
+      },
+      |sum: &i64| Ok(Value::Integer(*sum)),
+    )
+    .unwrap();
+  for (sql, expected) in [
+    (
+      "SELECT x, sumint(x) OVER (ORDER BY x ROWS 1 PRECEDING) FROM t",
+      vec!["1|1", "2|3", "3|5", "4|7", "5|9"],
+    ),
+    (
+      "SELECT x, sumint(x) OVER (PARTITION BY g ORDER BY x) FROM t",
+      vec!["1|1", "2|3", "3|3", "4|7", "5|12"],
+    ),
+    (
+      "SELECT x, sumint(x) FILTER (WHERE x <> 3) OVER (
+         ORDER BY x ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING
+       ) FROM t",
+      vec!["1|3", "2|3", "3|6", "4|9", "5|9"],
+    ),
+    (
+      "SELECT x, sumint(x) OVER (
+         ORDER BY x ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING EXCLUDE CURRENT ROW
+       ) FROM t",
+      vec!["1|2", "2|4", "3|6", "4|8", "5|4"],
+    ),
+    ("SELECT g, sumint(x) FROM t GROUP BY g", vec!["a|3", "b|12"]),
+  ] {
+    assert_eq!(rows(&mut conn, sql), expected, "{sql}");
+  }
+  // The frames of the first and third queries slide over the rows, and
+  // the row the filter leaves out is not taken back out.
+  assert_eq!(inverses.load(Ordering::Relaxed), 3 + 2);
+}
+
+#[test]
+fn error_on_non_deterministic_functions_in_indexes() {
+  let mut conn = open();
+  let counter = Arc::new(AtomicUsize::new(0));
+  conn
+    .create_scalar_function("next", Some(0), false, move |_| {
+      Ok(Value::Integer(
+        counter.fetch_add(1, Ordering::Relaxed) as i64
+      ))
+    })
+    .unwrap();
+  conn
+    .create_scalar_function("neg", Some(1), true, |args| -> SqliteResult<_> {
+      Ok(Value::Integer(-to_integer(&args[0])))
+    })
+    .unwrap();
+  assert_eq!(rows(&mut conn, "SELECT next(), next()"), ["0|1"]);
+  for (sql, expected) in [
+    (
+      "CREATE INDEX i ON t(x + next())",
+      "non-deterministic functions prohibited in index expressions",
+    ),
+    (
+      "CREATE INDEX i ON t(random())",
+      "non-deterministic functions prohibited in index expressions",
+    ),
+    (
+      "CREATE INDEX i ON t(x) WHERE x > next()",
+      "non-deterministic functions prohibited in partial index WHERE clauses",
+    ),
+  ] {
+    assert_eq!(execute_error(&mut conn, sql), expected, "{sql}");
+  }
+  conn.execute("CREATE INDEX i ON t(neg(x))").unwrap();
+  conn.execute("INSERT INTO t VALUES ('c', 6)").unwrap();
+  assert_eq!(
+    rows(&mut conn, "SELECT x FROM t WHERE neg(x) < -4 ORDER BY 1"),
+    ["5", "6"]
+  );
+}
@@ -0,0 +1,352 @@
+use super::{query, query_error};
+use crate::runtime::Value;
+use crate::SqliteConnection;
+
+/// The opcodes and comments of the program of `sql`.
+fn explain(conn: &mut SqliteConnection, sql: &str) -> Vec<(String, String)> {
+  query(conn, &format!("EXPLAIN {sql}"))
+    .into_iter()
+    .map(|row| (row[1].to_string(), row[7].to_string()))
+    .collect()
+}
+
+fn opcodes(program: &[(String, String)]) -> Vec<&str> {
+  program.iter().map(|(opcode, _)| opcode.as_str()).collect()
+}
+
+fn open() -> SqliteConnection {
+  super::open(&[
+    "CREATE TABLE t(a INTEGER PRIMARY KEY, b REAL, c TEXT)",
+    "CREATE INDEX tc ON t(c)",
+    "INSERT INTO t VALUES (1, 2, 'x'), (2, 3.5, 'y'), (3, NULL, 'x')",
+  ])
+}
+
+#[test]
+fn ok_on_explain() {
+  let mut conn = open();
+  let rows = query(&mut conn, "EXPLAIN SELECT 1");
+  assert_eq!(
+    conn.query("EXPLAIN SELECT 1").unwrap().column_names(),
+    ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"]
+  );
+  assert_eq!(
+    rows,
+    [
+      vec![
+        0.into(),
+        "Init".into(),
+        0.into(),
+        4.into(),
+        0.into(),
+        Value::Null,
+        0.into(),
+        "Start at 4".into()
+      ],
+      vec![
+        1.into(),
+        "Integer".into(),
+        1.into(),
+        1.into(),
+        0.into(),
+        Value::Null,
+        0.into(),
+        "r[1]=1".into()
+      ],
+      vec![
+        2.into(),
+        "ResultRow".into(),
+        1.into(),
+        1.into(),
+        0.into(),
+        Value::Null,
+        0.into(),
+        "output=r[1]".into()
+      ],
+      vec![
+        3.into(),
+        "Halt".into(),
+        0.into(),
+        0.into(),
+        0.into(),
+        Value::Null,
+        0.into(),
+        Value::Null
+      ],
+      vec![
+        4.into(),
+        "Goto".into(),
+        0.into(),
+        1.into(),
+        0.into(),
+        Value::Null,
+        0.into(),
+        Value::Null
+      ],
+    ]
+  );
+
+  let program = explain(&mut conn, "SELECT b FROM t");
+  assert_eq!(
+    opcodes(&program),
+    [
+      "Init",
+      "OpenRead",
+      "Rewind",
+      "Column",
+      "RealAffinity",
+      "ResultRow",
+      "Next",
+      "Halt",
+      "Transaction",
+      "Goto"
+    ]
+  );
+  assert_eq!(program[1].1, "root=2 iDb=0; t");
+  assert_eq!(program[8].1, "usesStmtJournal=0");
+
+  let program = explain(&mut conn, "SELECT b FROM t WHERE c = 'x'");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"SeekGE") && ops.contains(&"DeferredSeek"));
+  let program = explain(&mut conn, "SELECT c FROM t WHERE a = ?");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"SeekRowid") && ops.contains(&"Variable"));
+
+  let program = explain(&mut conn, "SELECT a FROM t LIMIT 2 OFFSET 1");
+  let comments = program
+    .iter()
+    .map(|(_, comment)| comment.as_str())
+    .collect::<Vec<_>>();
+  assert!(comments.contains(&"r[1]=2; LIMIT counter"));
+  assert!(comments.contains(&"OFFSET counter"));
+  assert!(comments.iter().any(|comment| comment.ends_with("; OFFSET")));
+
+  let program = explain(&mut conn, "SELECT * FROM t LEFT JOIN t AS u ON 1");
+  let comments = program
+    .iter()
+    .map(|(_, comment)| comment.as_str())
+    .collect::<Vec<_>>();
+  assert!(comments.contains(&"r[1]=0; init LEFT JOIN match flag"));
+  assert!(comments.contains(&"r[1]=1; record LEFT JOIN hit"));
+  assert!(opcodes(&program).contains(&"NullRow"));
+
+  let program = explain(&mut conn, "SELECT count(*) FROM t");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"AggStep") && ops.contains(&"AggFinal"));
+  let program = explain(&mut conn, "SELECT c, max(b) FROM t GROUP BY c");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"Gosub") && ops.contains(&"Return"));
+
+  let program = explain(&mut conn, "INSERT INTO t VALUES (4, 1, 'z')");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"OpenWrite") && ops.contains(&"Insert"));
+  assert_eq!(program[1].1, "root=2 iDb=0; t");
+  let program = explain(&mut conn, "DELETE FROM t WHERE a = 1");
+  let ops = opcodes(&program);
+  assert!(ops.contains(&"SeekRowid") && ops.contains(&"Delete"));
+
+  for (sql, expected) in [
+    (
+      "EXPLAIN SELECT (SELECT 1)",
+      "EXPLAIN of subqueries is not supported",
+    ),
+    (
+      "EXPLAIN SELECT * FROM json_each('[1]')",
+      "EXPLAIN of table-valued functions is not supported",
+    ),
+    (
+      "EXPLAIN SELECT a, row_number() OVER () FROM t",
+      "EXPLAIN of window functions is not supported",
+    ),
+    (
+      "EXPLAIN SELECT * FROM t RIGHT JOIN t AS u ON 1",
+      "EXPLAIN of RIGHT and FULL joins is not supported",
+    ),
+    (
+      "EXPLAIN CREATE TABLE u(x)",
+      "EXPLAIN of schema and transaction statements is not supported",
+    ),
+    (
+      "EXPLAIN QUERY PLAN INSERT INTO t VALUES(1)",
+      "EXPLAIN QUERY PLAN of INSERT statements is not supported",
+    ),
+  ] {
+    assert_eq!(query_error(&mut conn, sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_programs() {
+  let mut conn = open();
+  let cases: [(&str, Vec<Vec<Value>>); 9] = [
+    (
+      "SELECT * FROM t",
+      vec![
+        vec![1.into(), 2.0.into(), "x".into()],
+        vec![2.into(), 3.5.into(), "y".into()],
+        vec![3.into(), Value::Null, "x".into()],
+      ],
+    ),
+    (
+      "SELECT a, b FROM t WHERE c = 'x' ORDER BY b DESC",
+      vec![vec![1.into(), 2.0.into()], vec![3.into(), Value::Null]],
+    ),
+    ("SELECT c FROM t WHERE a = '2'", vec![vec!["y".into()]]),
+    (
+      "SELECT a FROM t ORDER BY c DESC, a LIMIT 2 OFFSET 1",
+      vec![vec![1.into()], vec![3.into()]],
+    ),
+    (
+      "SELECT DISTINCT c FROM t",
+      vec![vec!["x".into()], vec!["y".into()]],
+    ),
+    (
+      "SELECT t.a, u.a FROM t LEFT JOIN t AS u ON u.a = t.a + 1",
+      vec![
+        vec![1.into(), 2.into()],
+        vec![3.into(), Value::Null],
+        vec![2.into(), 3.into()],
+      ],
+    ),
+    (
+      "SELECT t.a, u.c FROM t JOIN t AS u ON u.c = t.c WHERE t.a < u.a",
+      vec![vec![1.into(), "x".into()]],
+    ),
+    (
+      "SELECT CASE WHEN b > 2 THEN 'big' WHEN b IS NULL THEN 'none' \
+       ELSE 'small' END, c LIKE 'X%', a BETWEEN 2 AND 3, a IN (1, 3, NULL), \
+       -a FROM t",
+      vec![
+        vec!["small".into(), 1.into(), 0.into(), 1.into(), (-1).into()],
+        vec!["big".into(), 0.into(), 1.into(), Value::Null, (-2).into()],
+        vec!["none".into(), 1.into(), 1.into(), 1.into(), (-3).into()],
+      ],
+    ),
+    (
+      "VALUES (1, 'a'), (2, 'b') LIMIT 1",
+      vec![vec![1.into(), "a".into()]],
+    ),
+  ];
+  for (sql, rows) in cases {
+    assert_eq!(query(&mut conn, sql), rows, "{sql}");
+  }
+
+  let mut stmt = conn.prepare("SELECT b FROM t WHERE a = ?").unwrap();
+  stmt.bind(1, 2i64).unwrap();
+  let row = stmt.step(&mut conn).unwrap().unwrap();
+  assert_eq!(row.values(), [3.5.into()]);
+  assert!(stmt.step(&mut conn).unwrap().is_none());
+
+  assert_eq!(
+    query_error(&mut conn, "SELECT a FROM t LIMIT 'a'"),
+    "datatype mismatch"
+  );
+  assert_eq!(
+    query_error(&mut conn, "SELECT c LIKE 'x' ESCAPE 'ab' FROM t"),
+    "ESCAPE expression must be a single character"
+  );
+}
+
+#[test]
+fn ok_on_aggregate_programs() {
+  let mut conn = open();
+  let cases: [(&str, Vec<Vec<Value>>); 5] = [
+    (
+      "SELECT count(*), sum(b), avg(a) FROM t",
+      vec![vec![3.into(), 5.5.into(), 2.0.into()]],
+    ),
+    (
+      "SELECT count(*), max(b) FROM t WHERE a > 5",
+      vec![vec![0.into(), Value::Null]],
+    ),
+    (
+      "SELECT c, count(*), max(a) FROM t GROUP BY c",
+      vec![
+        vec!["x".into(), 2.into(), 3.into()],
+        vec!["y".into(), 1.into(), 2.into()],
+      ],
+    ),
+    (
+      "SELECT c, min(b), a FROM t GROUP BY c HAVING count(*) > 1",
+      vec![vec!["x".into(), 2.0.into(), 1.into()]],
+    ),
+    ("SELECT c, count(*) FROM t WHERE a > 5 GROUP BY c", vec![]),
+  ];
+  for (sql, rows) in cases {
+    assert_eq!(query(&mut conn, sql), rows, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_without_rowid_programs() {
+  let mut conn = super::open(&[
+    "CREATE TABLE w(k TEXT, n INT, v, x, PRIMARY KEY(n, k)) WITHOUT ROWID",
+    "CREATE INDEX wv ON w(v)",
+    "INSERT INTO w VALUES ('b', 1, 'p', 2), ('a', 1, 'q', 3), ('c', 0, 'p', 4)",
+  ]);
+  let cases: [(&str, Vec<Vec<Value>>); 4] = [
+    (
+      "SELECT k, n, v FROM w",
+      vec![
+        vec!["c".into(), 0.into(), "p".into()],
+        vec!["b".into(), 1.into(), "p".into()],
+        vec!["a".into(), 1.into(), "q".into()],
+      ],
+    ),
+    (
+      "SELECT v FROM w WHERE n = 1 AND k = 'b'",
+      vec![vec!["p".into()]],
+    ),
+    (
+      "SELECT k FROM w WHERE v = 'p'",
+      vec![vec!["c".into()], vec!["b".into()]],
+    ),
+    (
+      "SELECT k FROM w WHERE n > 0 ORDER BY n DESC, k DESC LIMIT 1",
+      vec![vec!["b".into()]],
+    ),
+  ];
+  for (sql, rows) in cases {
+    assert_eq!(query(&mut conn, sql), rows, "{sql}");
+  }
+  assert_eq!(
+    query(&mut conn, "SELECT x FROM w WHERE v = 'q'"),
+    [[3.into()]]
+  );
+  let program = explain(&mut conn, "SELECT x FROM w WHERE v = 'q'");
+  assert!(opcodes(&program).contains(&"NotFound"));
+}
+
+#[test]
+fn ok_on_change_programs() {
+  let mut conn = open();
+  conn.execute("CREATE TABLE log(x)").unwrap();
+  conn
+    .execute(
+      "CREATE TRIGGER tr AFTER UPDATE ON t BEGIN \
+       INSERT INTO log VALUES (new.b); END",
+    )
+    .unwrap();
+  assert_eq!(
+    conn.execute("INSERT INTO t(b, c) VALUES (7, 'z')").unwrap(),
+    1
+  );
+  assert_eq!(
+    conn
+      .execute("UPDATE t SET b = b + 1 WHERE c = 'x'")
+      .unwrap(),
+    2
+  );
+  assert_eq!(conn.execute("DELETE FROM t WHERE a < 3").unwrap(), 2);
+  assert_eq!(
+    query(&mut conn, "SELECT * FROM t"),
+    [
+      vec![3.into(), Value::Null, "x".into()],
+      vec![4.into(), 7.0.into(), "z".into()],
+    ]
+  );
+  assert_eq!(
+    query(&mut conn, "SELECT * FROM log"),
+    [vec![3.0.into()], vec![Value::Null],]
+  );
+}
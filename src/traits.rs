@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 use std::io::Seek;
+use std::io::Write;
 
 pub trait Name {
   const NAME: &'static str;
@@ -39,7 +40,19 @@ where
   fn validate_parsed(&self) -> SqliteResult<()>;
 }
 
-// pub(crate) trait SqliteRawIo: Read + Send + Sync + Write {}
-pub(crate) trait SqliteRawIo: Read + Send + Sync + Seek {}
-impl SqliteRawIo for Cursor<Vec<u8>> {}
-impl SqliteRawIo for File {}
+pub(crate) trait SqliteRawIo: Read + Send + Sync + Seek + Write {
+  /// Truncates or extends the underlying storage to `len` bytes.
+  fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+}
+impl SqliteRawIo for Cursor<Vec<u8>> {
+  fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+    let len = usize::try_from(len).map_err(std::io::Error::other)?;
+    self.get_mut().resize(len, 0);
+    Ok(())
+  }
+}
+impl SqliteRawIo for File {
+  fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+    File::set_len(self, len)
+  }
+}